@@ -0,0 +1,237 @@
+//! Agent 检查点与回滚
+//!
+//! 每次执行会修改工作区的工具之前，记下工具将要触及的文件内容以及消息记录和会话历史所处的位置。
+//! 回滚到某个检查点时，按从新到旧的顺序恢复它和之后所有检查点保存的文件（之前不存在的文件删除），
+//! 再把对话截断到该检查点，之后的检查点随之作废
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+
+/// 工具参数中表示目标文件的字段
+const PATH_KEYS: &[&str] = &["path", "file_path", "notebook_path", "destination"];
+
+/// 一个文件在检查点时的内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    /// Base64 编码的内容，文件当时不存在时为 `None`
+    pub content: Option<String>,
+}
+
+impl FileSnapshot {
+    pub fn capture(path: &Path) -> Result<Self> {
+        let content = match std::fs::read(path) {
+            Ok(bytes) => Some(general_purpose::STANDARD.encode(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path: path.to_path_buf(), content })
+    }
+
+    /// 把文件恢复成快照时的样子
+    pub fn restore(&self) -> Result<()> {
+        match &self.content {
+            Some(content) => {
+                let bytes = general_purpose::STANDARD
+                    .decode(content)
+                    .map_err(|e| ClaudeError::General(format!("Corrupt snapshot of {}: {}", self.path.display(), e)))?;
+                if let Some(parent) = self.path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.path, bytes)?;
+            }
+            None => match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            },
+        }
+        Ok(())
+    }
+}
+
+/// 一次修改型工具调用之前的检查点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentCheckpoint {
+    pub id: String,
+    pub session_id: String,
+    /// 会话内的序号，从 1 开始
+    pub seq: u64,
+    pub created_at: DateTime<Utc>,
+    pub tool_name: String,
+    pub call_id: String,
+    /// 发出这次工具调用之前 Agent 消息记录的长度
+    pub transcript_len: usize,
+    /// 持久化会话的 ID 及当时的消息数
+    pub conversation_id: Option<String>,
+    pub conversation_messages: usize,
+    pub files: Vec<FileSnapshot>,
+}
+
+/// 回滚的结果
+#[derive(Debug, Clone)]
+pub struct Rollback {
+    pub checkpoint: AgentCheckpoint,
+    /// 被恢复或删除的文件
+    pub restored: Vec<PathBuf>,
+    /// 作废的检查点数（包括目标检查点）
+    pub discarded: usize,
+}
+
+/// 工具参数中指向的文件，相对路径按工作目录解析
+pub fn touched_paths(input: &Value, working_dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = PATH_KEYS
+        .iter()
+        .filter_map(|key| input.get(key).and_then(Value::as_str))
+        .filter(|path| !path.is_empty() && !path.contains("://"))
+        .map(|path| working_dir.join(path))
+        .collect();
+    paths.dedup();
+    paths
+}
+
+/// 检查点存储，每个会话一个目录，每个检查点一个 JSON 文件
+#[derive(Debug, Clone)]
+pub struct AgentCheckpointStore {
+    dir: PathBuf,
+}
+
+impl AgentCheckpointStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 默认位于数据目录下的 `claude-code/agent-checkpoints`
+    pub fn default_dir() -> PathBuf {
+        dirs::data_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("agent-checkpoints")
+    }
+
+    /// 为即将执行的工具调用创建检查点
+    pub fn create(
+        &self,
+        session_id: &str,
+        tool_name: &str,
+        call_id: &str,
+        files: &[PathBuf],
+        transcript_len: usize,
+        conversation: (Option<String>, usize),
+    ) -> Result<AgentCheckpoint> {
+        let seq = self.list(Some(session_id))?.last().map_or(1, |c| c.seq + 1);
+        let checkpoint = AgentCheckpoint {
+            id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+            session_id: session_id.to_string(),
+            seq,
+            created_at: Utc::now(),
+            tool_name: tool_name.to_string(),
+            call_id: call_id.to_string(),
+            transcript_len,
+            conversation_id: conversation.0,
+            conversation_messages: conversation.1,
+            files: files.iter().map(|path| FileSnapshot::capture(path)).collect::<Result<_>>()?,
+        };
+        let dir = self.dir.join(session_id);
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(format!("{}.json", checkpoint.id)), serde_json::to_string(&checkpoint)?)?;
+        Ok(checkpoint)
+    }
+
+    /// 检查点列表，按会话和序号排序
+    pub fn list(&self, session_id: Option<&str>) -> Result<Vec<AgentCheckpoint>> {
+        let sessions: Vec<PathBuf> = match session_id {
+            Some(session_id) => vec![self.dir.join(session_id)],
+            None => match std::fs::read_dir(&self.dir) {
+                Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            },
+        };
+
+        let mut checkpoints = Vec::new();
+        for dir in sessions {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                if entry.path().extension().is_some_and(|ext| ext == "json") {
+                    checkpoints.push(serde_json::from_str::<AgentCheckpoint>(&std::fs::read_to_string(entry.path())?)?);
+                }
+            }
+        }
+        checkpoints.sort_by(|a, b| a.session_id.cmp(&b.session_id).then(a.seq.cmp(&b.seq)));
+        Ok(checkpoints)
+    }
+
+    /// 按 ID（或唯一的 ID 前缀）查找检查点
+    pub fn find(&self, id: &str) -> Result<AgentCheckpoint> {
+        let mut matches: Vec<AgentCheckpoint> = self.list(None)?.into_iter().filter(|c| c.id.starts_with(id)).collect();
+        match matches.len() {
+            0 => Err(ClaudeError::validation_error("checkpoint", format!("No agent checkpoint matches '{}'", id))),
+            1 => Ok(matches.remove(0)),
+            n => Err(ClaudeError::validation_error("checkpoint", format!("'{}' matches {} checkpoints; use more characters", id, n))),
+        }
+    }
+
+    /// 恢复检查点及之后所有检查点保存的文件，并删除这些检查点。对话的截断由调用方完成
+    pub fn rollback(&self, id: &str) -> Result<Rollback> {
+        let checkpoint = self.find(id)?;
+        let mut later: Vec<AgentCheckpoint> = self
+            .list(Some(&checkpoint.session_id))?
+            .into_iter()
+            .filter(|c| c.seq >= checkpoint.seq)
+            .collect();
+        // 从新到旧恢复，同一文件最终停在最早的快照
+        later.sort_by_key(|c| std::cmp::Reverse(c.seq));
+
+        let mut restored: Vec<PathBuf> = Vec::new();
+        for snapshot in later.iter().flat_map(|c| &c.files) {
+            snapshot.restore()?;
+            if !restored.contains(&snapshot.path) {
+                restored.push(snapshot.path.clone());
+            }
+        }
+        for discarded in &later {
+            std::fs::remove_file(self.dir.join(&discarded.session_id).join(format!("{}.json", discarded.id)))?;
+        }
+        Ok(Rollback { checkpoint, restored, discarded: later.len() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback_restores_earliest_snapshot() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let work = temp_dir.path().join("work");
+        std::fs::create_dir_all(&work).unwrap();
+        let store = AgentCheckpointStore::new(temp_dir.path().join("checkpoints"));
+        let file = work.join("a.txt");
+        std::fs::write(&file, "v1").unwrap();
+
+        let paths = touched_paths(&serde_json::json!({"path": "a.txt", "content": "v2"}), &work);
+        assert_eq!(paths, vec![file.clone()]);
+        let first = store.create("s1", "write", "toolu_1", &paths, 2, (None, 3)).unwrap();
+        std::fs::write(&file, "v2").unwrap();
+
+        let created = work.join("new.txt");
+        store.create("s1", "write", "toolu_2", &[file.clone(), created.clone()], 4, (None, 5)).unwrap();
+        std::fs::write(&file, "v3").unwrap();
+        std::fs::write(&created, "new").unwrap();
+        assert_eq!(store.list(Some("s1")).unwrap().iter().map(|c| c.seq).collect::<Vec<_>>(), [1, 2]);
+
+        let rollback = store.rollback(&first.id[..4]).unwrap();
+        assert_eq!(rollback.checkpoint.transcript_len, 2);
+        assert_eq!(rollback.discarded, 2);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "v1");
+        assert!(!created.exists());
+        assert!(store.list(None).unwrap().is_empty());
+        assert!(store.find(&first.id).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+//! Agent 进度事件
+//!
+//! 嵌入方（TUI、Web 服务器）通过 [`AgentLoop::subscribe`](super::AgentLoop::subscribe) 订阅类型化的事件流，
+//! 边运行边渲染文本增量和工具进度。有订阅者时 Agent 以流式请求模型，文本按增量发出
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 事件通道的容量，订阅者落后超过这么多事件时会丢失最旧的事件
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// 一个进度事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// 模型回复的一段文本
+    TextDelta { text: String },
+    /// 开始执行工具
+    ToolStarted { call_id: String, tool_name: String, input: Value },
+    /// 工具执行结束
+    ToolFinished {
+        call_id: String,
+        tool_name: String,
+        output: String,
+        is_error: bool,
+        duration_ms: u64,
+    },
+    /// 一次模型请求及其工具调用结束
+    TurnComplete {
+        turn: u64,
+        stop_reason: Option<String>,
+        input_tokens: u64,
+        output_tokens: u64,
+    },
+    /// 运行因错误结束
+    Error { message: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{AgentContext, AgentLoop};
+    use crate::config::ClaudeConfig;
+    use crate::conversation::ConversationManager;
+    use crate::network::ClaudeApiClient;
+    use crate::test_support::{MockAnthropicServer, MockReply};
+    use crate::tools::ToolRegistry;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_subscribers_receive_streamed_events() {
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use_stream("toolu_1", "missing", &["{\"pa", "th\": \"a\"}"]),
+            MockReply::text_stream(&["Hel", "lo"]),
+        ])
+        .await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        let (agent, _responses) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent = agent.with_client(client).with_tools(Arc::new(ToolRegistry::new()));
+        let mut events = agent.subscribe();
+        agent.run(vec!["hi".to_string()]).await.unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert_eq!(server.requests()[0].body["stream"], true);
+        assert!(matches!(&received[0], AgentEvent::ToolStarted { input, .. } if input["path"] == "a"));
+        assert!(matches!(&received[1], AgentEvent::ToolFinished { is_error: true, .. }));
+        assert!(matches!(&received[2], AgentEvent::TurnComplete { turn: 1, stop_reason: Some(reason), .. } if reason == "tool_use"));
+        let text: Vec<&str> = received
+            .iter()
+            .filter_map(|event| match event {
+                AgentEvent::TextDelta { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(text, ["Hel", "lo"]);
+        assert!(matches!(received.last(), Some(AgentEvent::TurnComplete { turn: 2, output_tokens: 2, .. })));
+        assert_eq!(agent.final_text(), "Hello");
+    }
+}
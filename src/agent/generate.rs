@@ -0,0 +1,360 @@
+//! 按规格生成代码
+//!
+//! 把 YAML 规格交给模型生成文件，写入后运行项目的构建和测试；验证失败时把错误输出交回模型修复，
+//! 最多修复 N 轮。结束时报告每一轮的结果和生成前后每个文件的 diff
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::{ClaudeError, Result};
+use crate::fs::unified_diff;
+use crate::network::Message;
+
+/// 交给模型的验证输出的最大字符数（保留末尾）
+const MAX_VALIDATION_OUTPUT: usize = 8_000;
+/// 单次验证的超时
+const VALIDATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// 生成规格
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub language: Option<String>,
+    /// 期望生成的文件
+    #[serde(default)]
+    pub files: Vec<FileSpec>,
+    #[serde(default)]
+    pub requirements: Vec<String>,
+    /// 作为参考提供给模型的现有文件
+    #[serde(default)]
+    pub context: Vec<PathBuf>,
+    /// 验证命令，不设置时按项目类型推断
+    #[serde(default)]
+    pub validate: Option<String>,
+    /// 验证失败后最多修复的轮数
+    #[serde(default = "default_max_repairs")]
+    pub max_repairs: u32,
+}
+
+/// 规格中的一个文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileSpec {
+    pub path: String,
+    #[serde(default)]
+    pub purpose: String,
+}
+
+fn default_max_repairs() -> u32 {
+    3
+}
+
+impl GenerationSpec {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        serde_yaml::from_str(&text)
+            .map_err(|e| ClaudeError::validation_error("spec", format!("Invalid spec {}: {}", path.display(), e)))
+    }
+
+    /// 首轮提示词，`root` 用于读取参考文件
+    pub fn prompt(&self, root: &Path) -> Result<String> {
+        let mut prompt = format!("Generate code for the following spec.\n\n# {}\n", self.name);
+        if !self.description.trim().is_empty() {
+            prompt.push_str(&format!("\n{}\n", self.description.trim()));
+        }
+        if let Some(language) = &self.language {
+            prompt.push_str(&format!("\nLanguage: {}\n", language));
+        }
+        if !self.files.is_empty() {
+            prompt.push_str("\n## Files to write\n");
+            for file in &self.files {
+                prompt.push_str(&format!("- {}{}\n", file.path, if file.purpose.is_empty() { String::new() } else { format!(": {}", file.purpose) }));
+            }
+        }
+        if !self.requirements.is_empty() {
+            prompt.push_str("\n## Requirements\n");
+            self.requirements.iter().for_each(|r| prompt.push_str(&format!("- {}\n", r)));
+        }
+        for path in &self.context {
+            let content = std::fs::read_to_string(root.join(path))
+                .map_err(|e| ClaudeError::fs_error(format!("Failed to read context file {}: {}", path.display(), e)))?;
+            prompt.push_str(&format!("\n## Existing file {}\n```\n{}\n```\n", path.display(), content.trim_end()));
+        }
+        prompt.push_str(
+            "\nReply with the complete contents of every file you create or change, each wrapped as\n\
+             <file path=\"relative/path\">\n...contents...\n</file>\n\
+             Paths are relative to the project root. Text outside <file> blocks is ignored.",
+        );
+        Ok(prompt)
+    }
+}
+
+/// 模型输出的一个文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedFile {
+    pub path: String,
+    pub content: String,
+}
+
+/// 提取回复中的 `<file path="...">` 块
+pub fn parse_files(response: &str) -> Vec<GeneratedFile> {
+    let mut files = Vec::new();
+    let mut rest = response;
+    while let Some(start) = rest.find("<file path=\"") {
+        let after = &rest[start + "<file path=\"".len()..];
+        let Some(quote) = after.find('"') else { break };
+        let path = after[..quote].trim().to_string();
+        let Some(open_end) = after[quote..].find('>') else { break };
+        let body = &after[quote + open_end + 1..];
+        let Some(end) = body.find("</file>") else { break };
+        let content = body[..end].strip_prefix('\n').unwrap_or(&body[..end]);
+        files.push(GeneratedFile { path, content: content.to_string() });
+        rest = &body[end + "</file>".len()..];
+    }
+    files
+}
+
+/// 一次验证的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validation {
+    pub command: String,
+    pub success: bool,
+    pub output: String,
+}
+
+/// 一轮生成或修复
+#[derive(Debug, Clone)]
+pub struct GenerationRound {
+    /// 0 为首轮生成，之后为修复轮
+    pub round: u32,
+    pub files: Vec<String>,
+    /// 没有验证命令时为空
+    pub validation: Option<Validation>,
+}
+
+/// 最终状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerationStatus {
+    Passed,
+    /// 用完修复轮数仍未通过
+    Failed,
+    /// 没有可用的验证命令
+    Unvalidated,
+}
+
+/// 整个生成过程的结果
+#[derive(Debug, Clone)]
+pub struct GenerationOutcome {
+    pub status: GenerationStatus,
+    pub rounds: Vec<GenerationRound>,
+    /// 每个改动文件相对生成前的统一 diff，按路径排序
+    pub diffs: Vec<(String, String)>,
+}
+
+/// 生成-验证-修复循环
+pub struct Generator {
+    root: PathBuf,
+    validate: Option<String>,
+    max_repairs: u32,
+}
+
+impl Generator {
+    /// `validate` 为空时按项目类型推断验证命令
+    pub fn new(root: impl Into<PathBuf>, spec: &GenerationSpec) -> Self {
+        let root = root.into();
+        let validate = spec.validate.clone().or_else(|| crate::git::resolve::detect_verify_command(&root));
+        Self { root, validate, max_repairs: spec.max_repairs }
+    }
+
+    pub fn with_max_repairs(mut self, max_repairs: u32) -> Self {
+        self.max_repairs = max_repairs;
+        self
+    }
+
+    pub fn validate_command(&self) -> Option<&str> {
+        self.validate.as_deref()
+    }
+
+    /// 运行循环。`ask` 收到完整对话历史并返回模型回复，`progress` 在每轮结束后调用
+    pub async fn run<F, Fut>(
+        &self,
+        spec: &GenerationSpec,
+        mut ask: F,
+        progress: impl Fn(&GenerationRound),
+    ) -> Result<GenerationOutcome>
+    where
+        F: FnMut(Vec<Message>) -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let mut history = vec![message("user", spec.prompt(&self.root)?)];
+        let mut originals: BTreeMap<String, Option<String>> = BTreeMap::new();
+        let mut rounds = Vec::new();
+        let mut status = GenerationStatus::Failed;
+
+        for round in 0..=self.max_repairs {
+            let response = ask(history.clone()).await?;
+            history.push(message("assistant", response.clone()));
+            let files = parse_files(&response);
+            if files.is_empty() {
+                return Err(ClaudeError::General(format!(
+                    "The model returned no <file> blocks in round {}",
+                    round
+                )));
+            }
+
+            for file in &files {
+                let path = self.resolve(&file.path)?;
+                if !originals.contains_key(&file.path) {
+                    originals.insert(file.path.clone(), std::fs::read_to_string(&path).ok());
+                }
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&path, &file.content)?;
+            }
+
+            let validation = match &self.validate {
+                Some(command) => Some(self.run_validation(command).await?),
+                None => None,
+            };
+            let record = GenerationRound { round, files: files.into_iter().map(|f| f.path).collect(), validation };
+            progress(&record);
+            let failure = record.validation.as_ref().filter(|v| !v.success).cloned();
+            rounds.push(record);
+
+            match failure {
+                None => {
+                    status = if self.validate.is_some() { GenerationStatus::Passed } else { GenerationStatus::Unvalidated };
+                    break;
+                }
+                Some(validation) => history.push(message("user", repair_prompt(&validation))),
+            }
+        }
+
+        let diffs = originals
+            .into_iter()
+            .filter_map(|(path, before)| {
+                let after = std::fs::read_to_string(self.root.join(&path)).ok()?;
+                let before = before.unwrap_or_default();
+                (before != after).then(|| (path.clone(), unified_diff(&path, &before, &after)))
+            })
+            .collect();
+        Ok(GenerationOutcome { status, rounds, diffs })
+    }
+
+    /// 模型给出的相对路径，拒绝绝对路径和跳出项目根目录的路径
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let relative = Path::new(path);
+        if path.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(ClaudeError::permission_error(format!("Refusing to write outside the project: {}", path)));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    async fn run_validation(&self, command: &str) -> Result<Validation> {
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let child = tokio::process::Command::new(shell)
+            .args([flag, command])
+            .current_dir(&self.root)
+            .kill_on_drop(true)
+            .output();
+        let (success, output) = match tokio::time::timeout(VALIDATION_TIMEOUT, child).await {
+            Ok(output) => {
+                let output = output.map_err(|e| ClaudeError::General(format!("Failed to run {}: {}", command, e)))?;
+                let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                text.push_str(&String::from_utf8_lossy(&output.stderr));
+                (output.status.success(), text)
+            }
+            Err(_) => (false, format!("Timed out after {}s", VALIDATION_TIMEOUT.as_secs())),
+        };
+        Ok(Validation { command: command.to_string(), success, output })
+    }
+}
+
+fn message(role: &str, content: String) -> Message {
+    Message { role: role.to_string(), content: content.into() }
+}
+
+/// 修复轮的提示词，只保留验证输出的末尾
+fn repair_prompt(validation: &Validation) -> String {
+    let output = validation.output.trim_end();
+    let skip = output.chars().count().saturating_sub(MAX_VALIDATION_OUTPUT);
+    let tail: String = output.chars().skip(skip).collect();
+    format!(
+        "Validation with `{}` failed:\n```\n{}{}\n```\nFix the problem. Reply with the complete new contents of every file you change, \
+         using the same <file path=\"...\"> format.",
+        validation.command,
+        if skip > 0 { "…\n" } else { "" },
+        tail
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_repairs_until_validation_passes() {
+        use crate::network::{ClaudeApiClient, ResponseContentBlock};
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("greeting.txt"), "hi\n").unwrap();
+        let spec: GenerationSpec = serde_yaml::from_str(
+            "name: greeter\nrequirements: [say hello]\nvalidate: grep -q hello greeting.txt\nmax_repairs: 2\n",
+        )
+        .unwrap();
+
+        // 生成和修复都经过完整的请求链路，由模拟服务器依次给出两次回复
+        let server = MockAnthropicServer::start([
+            MockReply::text("Here you go:\n<file path=\"greeting.txt\">\nhey\n</file>\n<file path=\"src/new.txt\">\nnew\n</file>"),
+            MockReply::text("<file path=\"greeting.txt\">\nhello\n</file>"),
+        ])
+        .await;
+        let client = ClaudeApiClient::new("sk-test".to_string(), Some(server.base_url().to_string())).unwrap();
+        let outcome = Generator::new(dir.path(), &spec)
+            .run(
+                &spec,
+                |history| {
+                    let request = client.create_text_request("claude-mock", history.into_iter().map(|m| (m.role, m.content)).collect());
+                    let client = &client;
+                    async move {
+                        let response = client.send_message(&request).await?;
+                        Ok(response
+                            .content
+                            .into_iter()
+                            .filter_map(|block| match block {
+                                ResponseContentBlock::Text { text } => Some(text),
+                                _ => None,
+                            })
+                            .collect())
+                    }
+                },
+                |_| {},
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.status, GenerationStatus::Passed);
+        assert_eq!(outcome.rounds.len(), 2);
+        assert!(!outcome.rounds[0].validation.as_ref().unwrap().success);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        let repair = &requests[1].body["messages"];
+        assert_eq!(repair.as_array().unwrap().len(), 3);
+        assert!(repair[2]["content"].as_str().unwrap().contains("Validation with `grep -q hello greeting.txt` failed"));
+        assert_eq!(outcome.diffs.len(), 2);
+        assert!(outcome.diffs[0].1.contains("-hi\n+hello\n"));
+        assert_eq!(std::fs::read_to_string(dir.path().join("src/new.txt")).unwrap(), "new\n");
+
+        let escape = Generator::new(dir.path(), &spec)
+            .run(&spec, |_| async { Ok("<file path=\"../x\">x</file>".to_string()) }, |_| {})
+            .await;
+        assert!(escape.is_err());
+    }
+}
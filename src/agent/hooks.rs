@@ -0,0 +1,284 @@
+//! 工具执行钩子
+//!
+//! 每次工具调用前后依次运行匹配的钩子（PreToolUse / PostToolUse）。钩子可以是注册的异步回调，
+//! 也可以是配置中的 shell 命令：命令从标准输入读取 JSON 格式的调用信息，在标准输出写回 JSON 决定，
+//! 以退出码 2 退出表示阻止调用、标准错误为原因。钩子可以阻止调用、改写工具输入，或给模型追加上下文
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::future::Future;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+use crate::error::{ClaudeError, Result};
+
+/// 钩子触发的时机
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HookEvent {
+    /// 工具执行前，可以阻止调用或改写输入
+    PreToolUse,
+    /// 工具执行后，可以把结果标记为错误或追加上下文
+    PostToolUse,
+}
+
+/// 配置文件中的命令钩子
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HookConfig {
+    pub event: HookEvent,
+    /// 匹配工具名的正则表达式，省略时匹配所有工具
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<String>,
+    /// 用 `sh -c` 执行的命令
+    pub command: String,
+    /// 超时秒数
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_hook_timeout() -> u64 {
+    60
+}
+
+/// 交给钩子的调用信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookInput {
+    pub event: HookEvent,
+    pub session_id: String,
+    pub tool_name: String,
+    pub tool_input: Value,
+    /// 工具输出，仅 PostToolUse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_error: Option<bool>,
+}
+
+/// 钩子的决定，字段都为空时表示放行
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HookResponse {
+    /// 阻止调用（PreToolUse）或把结果标记为错误（PostToolUse）的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<String>,
+    /// 替换后的工具输入，仅 PreToolUse 生效
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_input: Option<Value>,
+    /// 追加到工具结果中给模型看的上下文
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub additional_context: Option<String>,
+}
+
+impl HookResponse {
+    pub fn block(reason: impl Into<String>) -> Self {
+        Self { block: Some(reason.into()), ..Self::default() }
+    }
+}
+
+/// 钩子
+#[async_trait]
+pub trait ToolHook: Send + Sync {
+    async fn run(&self, input: &HookInput) -> Result<HookResponse>;
+}
+
+/// 由异步闭包实现的钩子
+pub struct FnHook<F>(pub F);
+
+#[async_trait]
+impl<F, Fut> ToolHook for FnHook<F>
+where
+    F: Fn(HookInput) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<HookResponse>> + Send,
+{
+    async fn run(&self, input: &HookInput) -> Result<HookResponse> {
+        (self.0)(input.clone()).await
+    }
+}
+
+/// 运行 shell 命令的钩子
+#[derive(Debug, Clone)]
+pub struct CommandHook {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+#[async_trait]
+impl ToolHook for CommandHook {
+    async fn run(&self, input: &HookInput) -> Result<HookResponse> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("CLAUDE_HOOK_EVENT", format!("{:?}", input.event))
+            .env("CLAUDE_TOOL_NAME", &input.tool_name)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| ClaudeError::General(format!("Failed to run hook '{}': {}", self.command, e)))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            // 命令可能不读标准输入，写入失败不算错误
+            let _ = stdin.write_all(&serde_json::to_vec(input)?).await;
+        }
+
+        let output = timeout(self.timeout, child.wait_with_output())
+            .await
+            .map_err(|_| ClaudeError::General(format!("Hook '{}' timed out", self.command)))??;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match output.status.code() {
+            Some(0) if stdout.trim_start().starts_with('{') => serde_json::from_str(stdout.trim())
+                .map_err(|e| ClaudeError::General(format!("Hook '{}' printed invalid JSON: {}", self.command, e))),
+            Some(0) => Ok(HookResponse::default()),
+            Some(2) => Ok(HookResponse::block(match stderr.trim() {
+                "" => format!("blocked by hook '{}'", self.command),
+                reason => reason.to_string(),
+            })),
+            code => Err(ClaudeError::General(format!(
+                "Hook '{}' failed ({}): {}",
+                self.command,
+                code.map_or("killed".to_string(), |c| format!("exit {}", c)),
+                stderr.trim()
+            ))),
+        }
+    }
+}
+
+struct RegisteredHook {
+    event: HookEvent,
+    matcher: Option<regex::Regex>,
+    hook: Arc<dyn ToolHook>,
+}
+
+/// 按注册顺序运行的钩子
+#[derive(Default, Clone)]
+pub struct HookRegistry {
+    hooks: Vec<Arc<RegisteredHook>>,
+}
+
+impl std::fmt::Debug for HookRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HookRegistry").field("hooks", &self.hooks.len()).finish()
+    }
+}
+
+impl HookRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 由配置中的命令钩子创建
+    pub fn from_config(configs: &[HookConfig]) -> Result<Self> {
+        let mut registry = Self::new();
+        for config in configs {
+            let hook = CommandHook { command: config.command.clone(), timeout: Duration::from_secs(config.timeout_secs) };
+            registry.register(config.event, config.matcher.as_deref(), Arc::new(hook))?;
+        }
+        Ok(registry)
+    }
+
+    /// 注册钩子，`matcher` 是匹配工具名的正则表达式
+    pub fn register(&mut self, event: HookEvent, matcher: Option<&str>, hook: Arc<dyn ToolHook>) -> Result<()> {
+        let matcher = matcher
+            .filter(|m| !m.is_empty() && *m != "*")
+            .map(|m| regex::Regex::new(&format!("^(?:{})$", m)))
+            .transpose()
+            .map_err(|e| ClaudeError::validation_error("matcher", e.to_string()))?;
+        self.hooks.push(Arc::new(RegisteredHook { event, matcher, hook }));
+        Ok(())
+    }
+
+    /// 依次运行匹配的钩子并合并决定：第一个阻止的钩子生效，改写的输入交给后面的钩子，
+    /// 上下文按顺序拼接。钩子本身出错只记录警告
+    pub async fn run(&self, mut input: HookInput) -> HookResponse {
+        let mut merged = HookResponse::default();
+        let mut context = Vec::new();
+        let matching = self
+            .hooks
+            .iter()
+            .filter(|h| h.event == input.event && h.matcher.as_ref().is_none_or(|m| m.is_match(&input.tool_name)));
+        for registered in matching {
+            let response = match registered.hook.run(&input).await {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!("{:?} hook for '{}' failed: {}", input.event, input.tool_name, e);
+                    continue;
+                }
+            };
+            context.extend(response.additional_context);
+            if input.event == HookEvent::PreToolUse {
+                if let Some(updated) = response.updated_input {
+                    input.tool_input = updated.clone();
+                    merged.updated_input = Some(updated);
+                }
+            }
+            if response.block.is_some() {
+                merged.block = response.block;
+                break;
+            }
+        }
+        if !context.is_empty() {
+            merged.additional_context = Some(context.join("\n"));
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hooks_modify_block_and_append_context() {
+        let mut hooks = HookRegistry::new();
+        hooks
+            .register(
+                HookEvent::PreToolUse,
+                Some("write|edit"),
+                Arc::new(FnHook(|input: HookInput| async move {
+                    let mut updated = input.tool_input.clone();
+                    updated["path"] = Value::String(format!("sandbox/{}", input.tool_input["path"].as_str().unwrap_or_default()));
+                    Ok(HookResponse { updated_input: Some(updated), ..HookResponse::default() })
+                })),
+            )
+            .unwrap();
+        let configs = [
+            HookConfig {
+                event: HookEvent::PreToolUse,
+                matcher: None,
+                command: r#"grep -q '"sandbox/secret' && { echo "secrets are off limits" >&2; exit 2; }; exit 0"#.to_string(),
+                timeout_secs: 10,
+            },
+            HookConfig {
+                event: HookEvent::PostToolUse,
+                matcher: Some("*".to_string()),
+                command: r#"echo '{"additional_context": "remember to run the tests"}'"#.to_string(),
+                timeout_secs: 10,
+            },
+        ];
+        hooks.hooks.extend(HookRegistry::from_config(&configs).unwrap().hooks);
+
+        let input = |tool: &str, path: &str| HookInput {
+            event: HookEvent::PreToolUse,
+            session_id: "s1".to_string(),
+            tool_name: tool.to_string(),
+            tool_input: serde_json::json!({"path": path}),
+            tool_output: None,
+            is_error: None,
+        };
+        let allowed = hooks.run(input("write", "a.txt")).await;
+        assert_eq!(allowed.updated_input.unwrap()["path"], "sandbox/a.txt");
+        assert!(allowed.block.is_none());
+
+        let blocked = hooks.run(input("write", "secret.env")).await;
+        assert_eq!(blocked.block.as_deref(), Some("secrets are off limits"));
+        // 不匹配的工具不改写输入
+        assert!(hooks.run(input("read", "secret.env")).await.updated_input.is_none());
+
+        let post = hooks.run(HookInput { event: HookEvent::PostToolUse, tool_output: Some("ok".to_string()), ..input("bash", "") }).await;
+        assert_eq!(post.additional_context.as_deref(), Some("remember to run the tests"));
+    }
+}
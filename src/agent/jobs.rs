@@ -0,0 +1,343 @@
+//! 后台 Agent 任务
+//!
+//! 长时间运行的 Agent 任务放到 tokio 任务中执行，不阻塞交互会话。每个任务的状态和输出写在
+//! 数据目录下的 `claude-code/jobs/` 中，其他进程（`claude jobs ...`）据此查看状态、跟随输出；
+//! 取消请求以标记文件的形式传递，运行任务的进程定期检查
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Duration;
+
+use super::{AgentLoop, AgentResponse, AgentStatus};
+use crate::error::{ClaudeError, Result};
+use crate::locks::{rank, BlockingMutex};
+
+/// 检查取消标记的间隔
+const CANCEL_POLL: Duration = Duration::from_millis(500);
+
+/// 任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    /// 达到回合、时间或 token 限制而停止
+    Exhausted,
+    Failed,
+    Cancelled,
+    /// 运行任务的进程已经退出，任务没有结束
+    Abandoned,
+}
+
+impl std::fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self {
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Exhausted => "exhausted",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+            Self::Abandoned => "abandoned",
+        };
+        f.pad(label)
+    }
+}
+
+/// 一个后台任务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub description: String,
+    pub status: JobStatus,
+    /// 运行任务的进程
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// 最终回复、停止原因或错误
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+impl JobInfo {
+    pub fn is_running(&self) -> bool {
+        self.status == JobStatus::Running
+    }
+}
+
+/// 任务的状态文件、输出日志和取消标记
+#[derive(Debug, Clone)]
+pub struct JobStore {
+    dir: PathBuf,
+}
+
+impl JobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 默认位于数据目录下的 `claude-code/jobs`
+    pub fn default_dir() -> PathBuf {
+        dirs::data_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("jobs")
+    }
+
+    fn path(&self, id: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", id, extension))
+    }
+
+    fn save(&self, job: &JobInfo) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path(&job.id, "json"), serde_json::to_string_pretty(job)?)?;
+        Ok(())
+    }
+
+    fn append_log(&self, id: &str, line: &str) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(self.path(id, "log"))?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    /// 所有任务，按开始时间排序。进程已退出但仍标记为运行中的任务显示为 `Abandoned`
+    pub fn list(&self) -> Result<Vec<JobInfo>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut jobs = Vec::new();
+        for entry in entries.filter_map(|e| e.ok()) {
+            if entry.path().extension().is_some_and(|ext| ext == "json") {
+                let mut job: JobInfo = serde_json::from_str(&std::fs::read_to_string(entry.path())?)?;
+                if job.is_running() && !process_alive(job.pid) {
+                    job.status = JobStatus::Abandoned;
+                }
+                jobs.push(job);
+            }
+        }
+        jobs.sort_by_key(|job| job.started_at);
+        Ok(jobs)
+    }
+
+    /// 按 ID（或唯一的 ID 前缀）查找任务
+    pub fn get(&self, id: &str) -> Result<JobInfo> {
+        let mut matches: Vec<JobInfo> = self.list()?.into_iter().filter(|job| job.id.starts_with(id)).collect();
+        match matches.len() {
+            0 => Err(ClaudeError::validation_error("job", format!("No job matches '{}'", id))),
+            1 => Ok(matches.remove(0)),
+            n => Err(ClaudeError::validation_error("job", format!("'{}' matches {} jobs; use more characters", id, n))),
+        }
+    }
+
+    /// 任务输出中从字节偏移 `offset` 开始的部分
+    pub fn read_log(&self, id: &str, offset: u64) -> Result<String> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = match std::fs::File::open(self.path(id, "log")) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(String::new()),
+            Err(e) => return Err(e.into()),
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// 请求取消运行中的任务，由运行它的进程在下次检查时停止
+    pub fn request_cancel(&self, id: &str) -> Result<JobInfo> {
+        let job = self.get(id)?;
+        if !job.is_running() {
+            return Err(ClaudeError::validation_error("job", format!("Job {} is already {}", job.id, job.status)));
+        }
+        std::fs::write(self.path(&job.id, "cancel"), b"")?;
+        Ok(job)
+    }
+
+    fn cancel_requested(&self, id: &str) -> bool {
+        self.path(id, "cancel").exists()
+    }
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    // 信号 0 只检查进程是否存在
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+/// 写进任务日志的一行，部分文本和状态更新不记录
+fn log_line(response: &AgentResponse) -> Option<String> {
+    match response {
+        AgentResponse::TextContent { content, is_partial: false } => Some(content.clone()),
+        AgentResponse::ToolCall { tool_name, tool_input, .. } => {
+            Some(format!("🔧 {} {}", tool_name, tool_input.to_string().chars().take(100).collect::<String>()))
+        }
+        AgentResponse::ToolResult { result, is_error: true, .. } => {
+            Some(format!("   ⚠️  {}", result.as_str().unwrap_or_default().lines().next().unwrap_or_default()))
+        }
+        AgentResponse::StatusUpdate { status: AgentStatus::Exhausted(_), message: Some(message) } => Some(format!("⏹️  {}", message)),
+        AgentResponse::Error { error, .. } => Some(format!("❌ {}", error)),
+        _ => None,
+    }
+}
+
+/// 在当前进程中运行后台任务
+#[derive(Debug)]
+pub struct JobManager {
+    store: JobStore,
+    /// 运行中任务的取消通知
+    running: Arc<BlockingMutex<HashMap<String, Arc<Notify>>>>,
+}
+
+impl JobManager {
+    pub fn new(store: JobStore) -> Self {
+        Self { store, running: Arc::new(BlockingMutex::new("jobs.running", rank::JOB_TABLE, HashMap::new())) }
+    }
+
+    pub fn store(&self) -> &JobStore {
+        &self.store
+    }
+
+    /// 在后台运行 Agent，立即返回任务信息
+    pub fn spawn(
+        &self,
+        description: impl Into<String>,
+        mut agent: AgentLoop,
+        mut responses: mpsc::UnboundedReceiver<AgentResponse>,
+        messages: Vec<String>,
+    ) -> Result<JobInfo> {
+        let mut job = JobInfo {
+            id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+            description: description.into(),
+            status: JobStatus::Running,
+            pid: std::process::id(),
+            started_at: Utc::now(),
+            finished_at: None,
+            summary: None,
+        };
+        self.store.save(&job)?;
+        let cancel = Arc::new(Notify::new());
+        self.running.lock().insert(job.id.clone(), cancel.clone());
+
+        let info = job.clone();
+        let store = self.store.clone();
+        let running = self.running.clone();
+        tokio::spawn(async move {
+            let log = |response: &AgentResponse| {
+                if let Some(line) = log_line(response) {
+                    if let Err(e) = store.append_log(&job.id, &line) {
+                        tracing::warn!("Failed to write output of job {}: {}", job.id, e);
+                    }
+                }
+            };
+            let outcome = {
+                let run = agent.run(messages);
+                tokio::pin!(run);
+                let mut poll = tokio::time::interval(CANCEL_POLL);
+                loop {
+                    tokio::select! {
+                        result = &mut run => break Some(result),
+                        Some(response) = responses.recv() => log(&response),
+                        _ = cancel.notified() => break None,
+                        _ = poll.tick() => if store.cancel_requested(&job.id) {
+                            break None;
+                        },
+                    }
+                }
+            };
+            while let Ok(response) = responses.try_recv() {
+                log(&response);
+            }
+
+            (job.status, job.summary) = match outcome {
+                None => (JobStatus::Cancelled, None),
+                Some(Err(e)) => (JobStatus::Failed, Some(e.to_string())),
+                Some(Ok(())) => match agent.get_status().await {
+                    AgentStatus::Exhausted(limit) => (JobStatus::Exhausted, Some(format!("{}. {}", limit, agent.pending_work()))),
+                    AgentStatus::Error(e) => (JobStatus::Failed, Some(e)),
+                    _ => (JobStatus::Completed, Some(agent.final_text().to_string())),
+                },
+            };
+            job.finished_at = Some(Utc::now());
+            if let Err(e) = store.save(&job) {
+                tracing::warn!("Failed to save job {}: {}", job.id, e);
+            }
+            let _ = std::fs::remove_file(store.path(&job.id, "cancel"));
+            running.lock().remove(&job.id);
+        });
+        Ok(info)
+    }
+
+    /// 取消任务；不是本进程运行的任务通过取消标记通知
+    pub fn cancel(&self, id: &str) -> Result<JobInfo> {
+        let job = self.store.request_cancel(id)?;
+        if let Some(cancel) = self.running.lock().get(&job.id) {
+            cancel.notify_one();
+        }
+        Ok(job)
+    }
+
+    /// 本进程中仍在运行的任务数
+    pub fn running(&self) -> usize {
+        self.running.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::AgentContext;
+    use crate::config::ClaudeConfig;
+    use crate::conversation::ConversationManager;
+    use crate::network::ClaudeApiClient;
+    use crate::test_support::{MockAnthropicServer, MockReply};
+
+    #[tokio::test]
+    async fn test_background_job_completes_and_cancels() {
+        let server = MockAnthropicServer::start([MockReply::text("all done")]).await;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let manager = JobManager::new(JobStore::new(temp_dir.path()));
+        let agent = |session: &str| {
+            let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+            let context = AgentContext::new(session.to_string(), ClaudeConfig::default());
+            let (agent, responses) = AgentLoop::new(context, ConversationManager::new());
+            (agent.with_client(client), responses)
+        };
+
+        let (first, responses) = agent("s1");
+        let job = manager.spawn("say done", first, responses, vec!["finish".to_string()]).unwrap();
+        assert!(job.is_running());
+        let finished = loop {
+            let job = manager.store().get(&job.id).unwrap();
+            if !job.is_running() {
+                break job;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+        assert_eq!(finished.status, JobStatus::Completed);
+        assert_eq!(finished.summary.as_deref(), Some("all done"));
+        assert_eq!(manager.store().read_log(&job.id, 0).unwrap(), "all done\n");
+        assert!(manager.cancel(&job.id).is_err());
+
+        // 回复迟迟不来时取消任务
+        server.push(MockReply::text("too late").delayed(Duration::from_secs(30)));
+        let (second, responses) = agent("s2");
+        let job = manager.spawn("hang", second, responses, vec!["wait".to_string()]).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        manager.cancel(&job.id[..6]).unwrap();
+        while manager.running() > 0 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(manager.store().get(&job.id).unwrap().status, JobStatus::Cancelled);
+        assert_eq!(manager.store().list().unwrap().len(), 2);
+    }
+}
@@ -2,17 +2,40 @@
 //! 
 //! 基于原版 nO 主循环引擎，实现 Agent 核心调度和执行逻辑
 
+pub mod checkpoint;
+pub mod events;
+pub mod generate;
+pub mod hooks;
+pub mod jobs;
+pub mod orchestrator;
+pub mod plan;
+pub mod recovery;
+pub mod timing;
+
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex, RwLock};
-use tokio::time::{timeout, Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{timeout_at, Duration, Instant};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::error::{ClaudeError, Result};
+use crate::locks::{rank, Mutex, RwLock};
 use crate::steering::{SteeringController, SteeringMessage};
-use crate::conversation::ConversationManager;
+use crate::conversation::{ConversationManager, EnvironmentSnapshot};
 use crate::config::ClaudeConfig;
+use crate::network::{ClaudeApiClient, ContentBlock, ContentMessage, MessageContent, MessageRequest, MessageResponse, Tool};
+use crate::tools::continuation::OutputSpool;
+use crate::tools::{ToolContext, ToolRegistry};
+
+pub use checkpoint::{AgentCheckpoint, AgentCheckpointStore, Rollback};
+pub use events::AgentEvent;
+pub use hooks::{HookEvent, HookInput, HookRegistry, ToolHook};
+pub use jobs::{JobInfo, JobManager, JobStatus, JobStore};
+pub use orchestrator::{AgentOutput, AgentRole, OrchestrationResult, Orchestrator};
+pub use plan::PlanArtifact;
+pub use recovery::{CompletedToolCall, RetryPolicy, TurnJournal};
+pub use timing::{LatencyStats, LatencySummary, ToolTiming, TurnTimer, TurnTiming};
 
 /// Agent 状态
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -29,12 +52,37 @@ pub enum AgentStatus {
     ExecutingTool,
     /// 暂停
     Paused,
+    /// 计划已生成，等待用户批准
+    AwaitingApproval,
     /// 完成
     Completed,
+    /// 达到运行限制，在完成任务前停止
+    Exhausted(RunLimit),
     /// 错误
     Error(String),
 }
 
+/// 一次运行的限制
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RunLimit {
+    /// 最多请求模型的次数
+    MaxTurns(u32),
+    /// 墙钟时间上限（秒）
+    TimeLimit(u64),
+    /// 输入与输出 token 合计的上限
+    TokenBudget(u64),
+}
+
+impl std::fmt::Display for RunLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MaxTurns(turns) => write!(f, "reached the limit of {} turn(s)", turns),
+            Self::TimeLimit(secs) => write!(f, "ran out of time after {}s", secs),
+            Self::TokenBudget(tokens) => write!(f, "used up the budget of {} tokens", tokens),
+        }
+    }
+}
+
 /// Agent 执行上下文
 #[derive(Debug, Clone)]
 pub struct AgentContext {
@@ -52,6 +100,8 @@ pub struct AgentContext {
     pub max_thinking_tokens: Option<u32>,
     /// 回退模型
     pub fallback_model: Option<String>,
+    /// 修改型工具调用前的检查点存储，未设置时不记录检查点
+    pub checkpoints: Option<AgentCheckpointStore>,
 }
 
 impl AgentContext {
@@ -65,6 +115,7 @@ impl AgentContext {
             environment: HashMap::new(),
             max_thinking_tokens: None,
             fallback_model: None,
+            checkpoints: None,
         }
     }
 
@@ -79,6 +130,12 @@ impl AgentContext {
         self.environment = environment;
         self
     }
+
+    /// 在修改型工具调用前记录检查点
+    pub fn with_checkpoints(mut self, store: AgentCheckpointStore) -> Self {
+        self.checkpoints = Some(store);
+        self
+    }
 }
 
 /// Agent 响应类型
@@ -113,6 +170,10 @@ pub enum AgentResponse {
         error: String,
         error_code: Option<String>,
     },
+    /// 回合延迟分解
+    TurnTiming {
+        timing: TurnTiming,
+    },
     /// 完成
     Completed {
         final_response: String,
@@ -136,6 +197,58 @@ pub struct AgentLoop {
     compression_enabled: bool,
     /// 压缩阈值 (92%)
     compression_threshold: f64,
+    /// 回合延迟历史
+    latency: LatencyStats,
+    /// 当前回合计时器
+    current_turn: Option<TurnTimer>,
+    /// 当前回合日志，保留到工具结果送达模型为止，回合失败重试时复用已完成的工具结果
+    journal: Option<TurnJournal>,
+    /// 瞬时错误重试策略
+    retry_policy: RetryPolicy,
+    /// 会话开始时的环境快照
+    environment: Option<EnvironmentSnapshot>,
+    /// 是否将环境快照注入系统提示
+    inject_environment: bool,
+    /// Claude API 客户端
+    client: Option<Arc<ClaudeApiClient>>,
+    /// 模型可以调用的工具
+    tools: Option<Arc<ToolRegistry>>,
+    /// 工具执行上下文
+    tool_context: ToolContext,
+    /// 发送给模型的消息记录，包含工具调用与工具结果
+    transcript: Vec<ContentMessage>,
+    /// 最近一次模型回复的文本
+    final_text: String,
+    /// 累计的输入、输出 token
+    usage: (u64, u64),
+    /// 输入与输出 token 合计的上限
+    token_budget: Option<u64>,
+    /// 每次运行最多请求模型的次数
+    max_turns: Option<u32>,
+    /// 每次运行的墙钟时间上限
+    time_limit: Option<Duration>,
+    /// 本次运行已请求模型的次数
+    turns_this_run: u32,
+    /// 主模型持续过载后切换到的回退模型
+    active_model: Option<String>,
+    /// 最近一次运行因达到哪个限制而提前停止
+    exhausted: Option<RunLimit>,
+    /// 计划模式：只允许只读工具，最后产出计划
+    plan_mode: bool,
+    /// 计划模式产出的计划
+    plan: Option<PlanArtifact>,
+    /// 当前回复之前消息记录与会话历史的长度，作为检查点的回滚位置
+    checkpoint_position: (usize, usize),
+    /// 工具执行前后运行的钩子
+    hooks: HookRegistry,
+    /// 追加到系统提示的角色说明
+    instructions: Option<String>,
+    /// 类型化进度事件的广播
+    events: broadcast::Sender<AgentEvent>,
+    /// 超出预算的工具输出的保存位置，模型用 `tool_output` 工具续读
+    output_spool: OutputSpool,
+    /// 接收回合延迟指标的分析引擎
+    analytics: Option<Arc<crate::analytics::AnalyticsEngine>>,
 }
 
 impl AgentLoop {
@@ -145,20 +258,222 @@ impl AgentLoop {
         conversation: ConversationManager,
     ) -> (Self, mpsc::UnboundedReceiver<AgentResponse>) {
         let (response_sender, response_receiver) = mpsc::unbounded_channel();
+        let retry_policy = RetryPolicy::with_max_retries(context.config.api.max_retries);
+        let tool_context = ToolContext::new(context.session_id.clone()).with_timeouts(&context.config.tool_timeouts);
+        let output_spool = OutputSpool::for_session(&context.session_id);
+        let hooks = HookRegistry::from_config(&context.config.hooks).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring invalid hook configuration: {}", e);
+            HookRegistry::new()
+        });
         
         let agent_loop = Self {
             context,
             steering: SteeringController::new(),
-            conversation: Arc::new(Mutex::new(conversation)),
-            status: Arc::new(RwLock::new(AgentStatus::NotStarted)),
+            conversation: Arc::new(Mutex::new("agent.conversation", rank::AGENT_CONVERSATION, conversation)),
+            status: Arc::new(RwLock::new("agent.status", rank::AGENT_STATUS, AgentStatus::NotStarted)),
             response_sender,
             compression_enabled: true,
             compression_threshold: 0.92,
+            latency: LatencyStats::new(),
+            current_turn: None,
+            journal: None,
+            retry_policy,
+            environment: None,
+            inject_environment: false,
+            client: None,
+            tools: None,
+            tool_context,
+            transcript: Vec::new(),
+            final_text: String::new(),
+            usage: (0, 0),
+            token_budget: None,
+            max_turns: None,
+            time_limit: None,
+            turns_this_run: 0,
+            active_model: None,
+            exhausted: None,
+            plan_mode: false,
+            plan: None,
+            checkpoint_position: (0, 0),
+            hooks,
+            instructions: None,
+            events: broadcast::channel(events::EVENT_CAPACITY).0,
+            output_spool,
+            analytics: None,
         };
         
         (agent_loop, response_receiver)
     }
 
+    /// 设置 Claude API 客户端
+    pub fn with_client(mut self, client: Arc<ClaudeApiClient>) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// 设置模型可以调用的工具
+    pub fn with_tools(mut self, tools: Arc<ToolRegistry>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// 设置工具执行上下文
+    pub fn with_tool_context(mut self, tool_context: ToolContext) -> Self {
+        self.tool_context = tool_context;
+        self
+    }
+
+    /// 设置截断的工具输出的保存位置
+    pub fn with_output_spool(mut self, spool: OutputSpool) -> Self {
+        self.output_spool = spool;
+        self
+    }
+
+    /// 设置分析引擎，每个回合的延迟分解记为 `turn.*` 指标
+    pub fn with_analytics(mut self, analytics: Arc<crate::analytics::AnalyticsEngine>) -> Self {
+        self.analytics = Some(analytics);
+        self
+    }
+
+    /// 设置 token 预算，累计用量超出后不再继续请求模型
+    pub fn with_token_budget(mut self, budget: u64) -> Self {
+        self.token_budget = Some(budget);
+        self
+    }
+
+    /// 设置每次运行最多请求模型的次数
+    pub fn with_max_turns(mut self, max_turns: u32) -> Self {
+        self.max_turns = Some(max_turns);
+        self
+    }
+
+    /// 设置每次运行的墙钟时间上限
+    pub fn with_time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    /// 设置工具钩子，替换配置中的命令钩子
+    pub fn with_hooks(mut self, hooks: HookRegistry) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// 注册一个工具钩子
+    pub fn add_hook(&mut self, event: HookEvent, matcher: Option<&str>, hook: Arc<dyn ToolHook>) -> Result<()> {
+        self.hooks.register(event, matcher, hook)
+    }
+
+    /// 设置追加到系统提示的说明
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.instructions = Some(instructions.into());
+        self
+    }
+
+    /// 以计划模式运行
+    pub fn with_plan_mode(mut self, plan_mode: bool) -> Self {
+        self.plan_mode = plan_mode;
+        self
+    }
+
+    pub fn is_plan_mode(&self) -> bool {
+        self.plan_mode
+    }
+
+    /// 计划模式产出的计划
+    pub fn plan(&self) -> Option<&PlanArtifact> {
+        self.plan.as_ref()
+    }
+
+    /// 批准计划并切换到执行模式，之后再次调用 `run` 开始执行
+    pub async fn approve_plan(&mut self) -> Result<()> {
+        let plan = self
+            .plan
+            .as_mut()
+            .filter(|plan| !plan.approved)
+            .ok_or_else(|| ClaudeError::General("There is no plan waiting for approval".to_string()))?;
+        plan.approved = true;
+        self.plan_mode = false;
+        let blocks = vec![ContentBlock::Text { text: plan::PLAN_APPROVED_MESSAGE.to_string() }];
+        self.record_conversation("user", &blocks).await?;
+        self.push_user_blocks(blocks);
+        Ok(())
+    }
+
+    /// 使用的模型，会话配置的模型优先于 API 默认模型
+    pub fn model(&self) -> &str {
+        self.active_model.as_deref().unwrap_or_else(|| self.primary_model())
+    }
+
+    /// 配置的主模型
+    fn primary_model(&self) -> &str {
+        self.context.config.model.as_deref().unwrap_or(&self.context.config.api.default_model)
+    }
+
+    /// 是否已切换到回退模型
+    pub fn is_using_fallback(&self) -> bool {
+        self.active_model.is_some()
+    }
+
+    /// 发送给模型的消息记录
+    pub fn transcript(&self) -> &[ContentMessage] {
+        &self.transcript
+    }
+
+    /// 模型最近一次回复的文本
+    pub fn final_text(&self) -> &str {
+        &self.final_text
+    }
+
+    /// 累计的输入、输出 token
+    pub fn usage(&self) -> (u64, u64) {
+        self.usage
+    }
+
+    /// 是否因 token 预算用尽而提前停止
+    pub fn budget_exhausted(&self) -> bool {
+        matches!(self.exhausted, Some(RunLimit::TokenBudget(_)))
+    }
+
+    /// 最近一次运行达到的限制
+    pub fn exhausted(&self) -> Option<RunLimit> {
+        self.exhausted
+    }
+
+    /// 提前停止时尚未完成的工作：模型还没看到的工具结果和它最后说的话
+    pub fn pending_work(&self) -> String {
+        let pending: Vec<&str> = match self.transcript.iter().rev().find(|m| m.role == "assistant").map(|m| &m.content) {
+            Some(MessageContent::Blocks(blocks)) => blocks
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::ToolUse { name, .. } => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut summary = match pending.len() {
+            0 => "No tool results are waiting for the model.".to_string(),
+            n => format!("{} tool result(s) the model has not seen yet: {}.", n, pending.join(", ")),
+        };
+        let last = self.final_text.trim();
+        if !last.is_empty() {
+            let preview: String = last.chars().take(300).collect();
+            summary.push_str(&format!(" Last response: {}", preview));
+        }
+        summary
+    }
+
+    /// 订阅进度事件；有订阅者时模型回复以流式请求，文本按增量发出
+    pub fn subscribe(&self) -> broadcast::Receiver<AgentEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: AgentEvent) {
+        // 没有订阅者时发送失败，忽略即可
+        let _ = self.events.send(event);
+    }
+
     /// 获取当前状态
     pub async fn get_status(&self) -> AgentStatus {
         self.status.read().await.clone()
@@ -191,17 +506,75 @@ impl AgentLoop {
         
         // 设置初始状态
         self.set_status(AgentStatus::Initializing).await;
+
+        // 记录会话环境快照，每个 Agent 只采集一次
+        if self.environment.is_none() {
+            self.capture_environment().await;
+        }
+
+        let blocks: Vec<ContentBlock> = initial_messages.into_iter().map(|text| ContentBlock::Text { text }).collect();
+        if !blocks.is_empty() {
+            self.record_conversation("user", &blocks).await?;
+            self.push_user_blocks(blocks);
+        }
         
-        // 主循环
+        // 主循环：每个周期请求一次模型，直到模型不再调用工具或达到运行限制
+        self.turns_this_run = 0;
+        self.exhausted = None;
+        // 上一次运行中断时未送达的工具结果不再复用，模型重新发出的调用照常执行
+        self.journal = None;
+        let deadline = self.time_limit.map(|limit| Instant::now() + limit);
         loop {
-            match self.execute_cycle(&initial_messages).await {
+            if let Some(limit) = self.max_turns.filter(|max| self.turns_this_run >= *max) {
+                self.exhausted = Some(RunLimit::MaxTurns(limit));
+                break;
+            }
+            self.turns_this_run += 1;
+            let cycle = match deadline {
+                Some(deadline) => match timeout_at(deadline, self.execute_cycle()).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        // 进行中的回合被放弃，消息记录停在上一个完整的回合
+                        self.journal = None;
+                        self.current_turn = None;
+                        self.exhausted = self.time_limit.map(|limit| RunLimit::TimeLimit(limit.as_secs()));
+                        break;
+                    }
+                },
+                None => self.execute_cycle().await,
+            };
+            match cycle {
                 Ok(should_continue) => {
                     if !should_continue {
                         break;
                     }
                 }
+                Err(e) if self.should_retry_turn(&e) => {
+                    let attempt = self.journal.as_ref().map_or(1, |j| j.attempts);
+                    let delay = self.retry_policy.delay_for_error(&e, attempt);
+                    let preserved = self.journal.as_ref().map_or(0, |j| j.completed().len());
+                    tracing::warn!(
+                        "Turn failed with transient error (attempt {}), retrying in {:?} with {} tool result(s) preserved: {}",
+                        attempt, delay, preserved, e
+                    );
+                    let _ = self.response_sender.send(AgentResponse::StatusUpdate {
+                        status: AgentStatus::Running,
+                        message: Some(format!("Retrying turn after transient error: {}", e)),
+                    });
+                    self.current_turn = None;
+                    self.turns_this_run -= 1;
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+                Err(e) if self.should_fall_back(&e) => {
+                    self.switch_to_fallback(&e).await?;
+                    self.current_turn = None;
+                    self.turns_this_run -= 1;
+                    continue;
+                }
                 Err(e) => {
                     tracing::error!("Agent loop error: {}", e);
+                    self.emit(AgentEvent::Error { message: e.to_string() });
                     self.set_status(AgentStatus::Error(e.to_string())).await;
                     self.send_response(AgentResponse::Error {
                         error: e.to_string(),
@@ -218,18 +591,63 @@ impl AgentLoop {
             }
         }
         
-        // 设置完成状态
-        self.set_status(AgentStatus::Completed).await;
+        // 计划模式下回复即计划，保存后等待用户批准
+        if let Some(limit) = self.exhausted {
+            let pending = self.pending_work();
+            tracing::warn!("Agent stopped early: {}. {}", limit, pending);
+            *self.status.write().await = AgentStatus::Exhausted(limit);
+            let _ = self.response_sender.send(AgentResponse::StatusUpdate {
+                status: AgentStatus::Exhausted(limit),
+                message: Some(format!("Stopped early: {}. {}", limit, pending)),
+            });
+        } else if self.plan_mode && !self.final_text.trim().is_empty() {
+            let mut plan = PlanArtifact::new(self.final_text.clone());
+            if let Err(e) = plan.save(std::path::Path::new(&self.tool_context.working_directory)) {
+                tracing::warn!("Failed to save plan: {}", e);
+            }
+            self.plan = Some(plan);
+            self.set_status(AgentStatus::AwaitingApproval).await;
+        } else {
+            self.set_status(AgentStatus::Completed).await;
+        }
+        let mut metadata = HashMap::from([
+            ("model".to_string(), serde_json::json!(self.model())),
+            ("input_tokens".to_string(), serde_json::json!(self.usage.0)),
+            ("output_tokens".to_string(), serde_json::json!(self.usage.1)),
+            ("turns".to_string(), serde_json::json!(self.latency.last_turn().map_or(0, |t| t.turn))),
+        ]);
+        if self.active_model.is_some() {
+            metadata.insert("fallback_from".to_string(), serde_json::json!(self.primary_model()));
+        }
+        if let Some(limit) = self.exhausted {
+            metadata.insert("exhausted".to_string(), serde_json::json!(limit.to_string()));
+            metadata.insert("pending_work".to_string(), serde_json::json!(self.pending_work()));
+        }
+        if let Some(path) = self.plan.as_ref().filter(|plan| !plan.approved).and_then(|plan| plan.path.as_ref()) {
+            metadata.insert("plan_path".to_string(), serde_json::json!(path));
+        }
         self.send_response(AgentResponse::Completed {
-            final_response: "Agent execution completed".to_string(),
-            metadata: HashMap::new(),
+            final_response: self.final_text.clone(),
+            metadata,
         }).await?;
         
         Ok(())
     }
 
-    /// 执行一个循环周期
-    async fn execute_cycle(&mut self, _messages: &[String]) -> Result<bool> {
+    /// 执行一个循环周期，返回是否需要继续（模型请求了工具调用）
+    async fn execute_cycle(&mut self) -> Result<bool> {
+        let timer = self.latency.start_turn();
+        let turn = timer.turn();
+        let journal = self.journal.get_or_insert_with(|| TurnJournal::new(turn));
+        if journal.begin_attempt() > 1 {
+            tracing::info!(
+                "Resuming turn {} from {} completed tool call(s)",
+                journal.turn,
+                journal.completed().len()
+            );
+        }
+        self.current_turn = Some(timer);
+
         // 阶段1：消息预处理和上下文检查
         self.set_status(AgentStatus::Running).await;
         
@@ -245,16 +663,63 @@ impl AgentLoop {
         }
         
         // 阶段3：生成系统提示
-        let _system_prompt = self.generate_system_prompt().await?;
+        let system_prompt = self.generate_system_prompt().await?;
+        if let Some(timer) = self.current_turn.as_mut() {
+            timer.mark_dispatched();
+        }
         
-        // 阶段4：会话流生成 (模拟)
-        self.generate_conversation_stream().await?;
+        // 阶段4：请求模型
+        let response = self.request_model(system_prompt).await?;
+        if let Some(journal) = self.journal.as_mut() {
+            journal.settle(turn);
+        }
+        let stop_reason = response.stop_reason.clone();
+        let usage = response.usage.clone();
+        let blocks: Vec<ContentBlock> = response.content.into_iter().map(ContentBlock::from).collect();
+        let recorded = self.conversation.lock().await.get_message_count();
+        self.checkpoint_position = (self.transcript.len(), recorded);
+        self.record_conversation("assistant", &blocks).await?;
         
-        // 阶段5：工具调用检测与处理
-        self.process_tool_calls().await?;
+        // 阶段5：执行模型请求的工具，结果作为下一条用户消息回传
+        let results = self.process_tool_calls(&blocks).await?;
+        self.transcript.push(ContentMessage::new("assistant", blocks));
+        let delivered = !results.is_empty();
+        let mut should_continue = delivered;
+        if should_continue {
+            self.push_user_blocks(results);
+        } else if stop_reason.as_deref() == Some("tool_use") {
+            tracing::warn!("Model stopped for tool use without requesting any tool");
+        }
+        if let Some(budget) = self.token_budget.filter(|budget| should_continue && self.usage.0 + self.usage.1 >= *budget) {
+            tracing::warn!("Token budget of {} exhausted after {} tokens, stopping", budget, self.usage.0 + self.usage.1);
+            self.exhausted = Some(RunLimit::TokenBudget(budget));
+            should_continue = false;
+        }
+
+        // 阶段6：记录回合延迟
+        if let Some(timer) = self.current_turn.take() {
+            let timing = timer.finish();
+            self.emit(AgentEvent::TurnComplete {
+                turn: timing.turn,
+                stop_reason,
+                input_tokens: usage.input_tokens as u64,
+                output_tokens: usage.output_tokens as u64,
+            });
+            self.latency.record(timing.clone());
+            if let Some(analytics) = &self.analytics {
+                if let Err(e) = analytics.track_turn_timing(&timing).await {
+                    tracing::warn!("Failed to record turn timing: {}", e);
+                }
+            }
+            self.send_response(AgentResponse::TurnTiming { timing }).await?;
+        }
+        // 工具结果在下一次模型请求成功后才算送达，此前请求失败重试时仍可复用
+        match self.journal.as_mut() {
+            Some(journal) if delivered => journal.mark_delivered(),
+            _ => self.journal = None,
+        }
         
-        // 继续循环
-        Ok(true)
+        Ok(should_continue)
     }
 
     /// 检查是否需要压缩
@@ -296,10 +761,9 @@ impl AgentLoop {
         match message {
             SteeringMessage::UserInput { content, .. } => {
                 tracing::info!("Received user input: {}", content);
-                self.send_response(AgentResponse::TextContent {
-                    content: format!("Processing user input: {}", content),
-                    is_partial: false,
-                }).await?;
+                let blocks = vec![ContentBlock::Text { text: content }];
+                self.record_conversation("user", &blocks).await?;
+                self.push_user_blocks(blocks);
             }
             SteeringMessage::SystemControl { command, params } => {
                 tracing::info!("Received system control: {} with params: {:?}", command, params);
@@ -329,6 +793,9 @@ impl AgentLoop {
             "stop" => {
                 self.steering.send_interrupt("System stop command".to_string()).await?;
             }
+            "inject_environment" => {
+                self.inject_environment = true;
+            }
             _ => {
                 tracing::warn!("Unknown system control command: {}", command);
             }
@@ -341,49 +808,434 @@ impl AgentLoop {
     async fn generate_system_prompt(&self) -> Result<String> {
         // 基于上下文和工具配置生成系统提示
         let mut prompt = String::from("You are Claude, an AI assistant created by Anthropic.");
-        
+        if let Some(instructions) = &self.instructions {
+            prompt.push_str("\n\n");
+            prompt.push_str(instructions);
+        }
+
         if !self.context.tools_config.is_empty() {
             prompt.push_str("\n\nAvailable tools:");
             for tool_name in self.context.tools_config.keys() {
                 prompt.push_str(&format!("\n- {}", tool_name));
             }
         }
+
+        if self.plan_mode {
+            prompt.push_str("\n\n");
+            prompt.push_str(plan::PLAN_MODE_INSTRUCTIONS);
+        }
+
+        if self.inject_environment {
+            if let Some(environment) = &self.environment {
+                prompt.push_str("\n\n");
+                prompt.push_str(&environment.to_context_string());
+            }
+        }
+
+        // 会话中选择的语言优先于配置的默认语言
+        let session_language = self.conversation.lock().await.language().cloned();
+        if let Some(language) = session_language.or_else(|| self.context.config.response_language()) {
+            prompt.push_str("\n\n");
+            prompt.push_str(&language.system_instruction());
+        }
         
         Ok(prompt)
     }
 
-    /// 生成会话流 (模拟)
-    async fn generate_conversation_stream(&mut self) -> Result<()> {
-        // 模拟流式响应生成
-        self.send_response(AgentResponse::TextContent {
-            content: "Generating response...".to_string(),
-            is_partial: true,
+    /// 把当前消息记录发送给模型，回复中的文本同时作为响应发出
+    async fn request_model(&mut self, system: String) -> Result<MessageResponse> {
+        let client = self
+            .client
+            .clone()
+            .ok_or_else(|| ClaudeError::config_error("AgentLoop has no API client; call with_client before run"))?;
+        if self.transcript.is_empty() {
+            return Err(ClaudeError::validation_error("messages", "the agent loop needs at least one user message"));
+        }
+
+        let tools: Vec<Tool> = match &self.tools {
+            Some(registry) => registry
+                .api_schemas()
+                .await
+                .iter()
+                .filter_map(|schema| serde_json::from_value::<Tool>(schema.clone()).ok())
+                .filter(|tool| !self.plan_mode || plan::is_read_only(&tool.name))
+                .collect(),
+            None => Vec::new(),
+        };
+        let api = &self.context.config.api;
+        let request = MessageRequest {
+            model: self.model().to_string(),
+            max_tokens: api.max_tokens,
+            messages: self.transcript.clone(),
+            system: Some(system),
+            temperature: Some(api.temperature),
+            top_p: None,
+            top_k: None,
+            stream: None,
+            tools: (!tools.is_empty()).then_some(tools),
+            tool_choice: None,
+            metadata: None,
+            stop_sequences: None,
+        };
+
+        let response = if self.events.receiver_count() > 0 {
+            self.stream_model(&client, &request).await?
+        } else {
+            let response = client.send_message(&request).await?;
+            if let Some(timer) = self.current_turn.as_mut() {
+                timer.mark_first_token();
+            }
+            response
+        };
+        if let Some(timer) = self.current_turn.as_mut() {
+            timer.mark_stream_end();
+        }
+        self.usage.0 += u64::from(response.usage.input_tokens);
+        self.usage.1 += u64::from(response.usage.output_tokens);
+
+        let text: Vec<&str> = response
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                crate::network::ResponseContentBlock::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        if !text.is_empty() {
+            self.final_text = text.join("\n");
+            self.send_response(AgentResponse::TextContent {
+                content: self.final_text.clone(),
+                is_partial: false,
+            }).await?;
+        }
+
+        Ok(response)
+    }
+
+    /// 以流式请求模型，边接收边发出文本增量，最后拼成完整回复
+    async fn stream_model(&mut self, client: &ClaudeApiClient, request: &MessageRequest<ContentMessage>) -> Result<MessageResponse> {
+        use futures::StreamExt;
+
+        let stream = client.send_message_stream(request).await?;
+        futures::pin_mut!(stream);
+        let mut assembler = crate::network::MessageAssembler::new();
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            if let Some(text) = assembler.push(&event)? {
+                if let Some(timer) = self.current_turn.as_mut() {
+                    timer.mark_first_token();
+                }
+                self.emit(AgentEvent::TextDelta { text });
+            }
+            if event.event_type == "message_stop" {
+                break;
+            }
+        }
+        assembler.finish()
+    }
+
+    /// 执行回复中的 `tool_use` 块，返回对应的 `tool_result` 块
+    ///
+    /// 工具经由回合日志执行，回合重试时不会重复执行已完成的工具；工具失败作为错误结果交给模型处理。
+    /// 相邻的只读调用并发执行，结果仍按调用顺序返回
+    async fn process_tool_calls(&mut self, blocks: &[ContentBlock]) -> Result<Vec<ContentBlock>> {
+        let calls: Vec<(&String, &String, &serde_json::Value)> = blocks
+            .iter()
+            .filter_map(|block| match block {
+                ContentBlock::ToolUse { id, name, input } => Some((id, name, input)),
+                _ => None,
+            })
+            .collect();
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.set_status(AgentStatus::ExecutingTool).await;
+        let registry = self.tools.clone();
+        let mut results = Vec::with_capacity(calls.len());
+        let mut index = 0;
+        while index < calls.len() {
+            let end = index + calls[index..].iter().take_while(|(_, name, _)| plan::is_read_only(name)).count().max(1);
+            let mut prepared = Vec::with_capacity(end - index);
+            for &(id, name, input) in &calls[index..end] {
+                prepared.push(self.prepare_tool_call(id, name, input, registry.is_some()).await?);
+            }
+
+            let batch: Vec<(String, String, serde_json::Value)> = prepared
+                .iter()
+                .filter(|call| call.result.is_none())
+                .filter_map(|call| call.executed.clone().map(|input| (call.id.clone(), call.name.clone(), input)))
+                .collect();
+            if let (Some(registry), false) = (&registry, batch.is_empty()) {
+                // 每批工具使用新的取消令牌，steering 中止时只影响正在执行的这一批
+                let context = ToolContext { cancellation: self.steering.tool_cancellation_token(), ..self.tool_context.clone() };
+                let journal = self.journal.get_or_insert_with(|| TurnJournal::new(0));
+                let mut outcomes = journal.execute_batch(registry, batch, &context).await.into_iter();
+                for call in prepared.iter_mut().filter(|call| call.result.is_none()) {
+                    call.result = outcomes.next();
+                }
+            }
+
+            for call in prepared {
+                results.push(self.finish_tool_call(call).await?);
+            }
+            index = end;
+        }
+        self.set_status(AgentStatus::Running).await;
+
+        Ok(results)
+    }
+
+    /// 通知调用开始并运行 PreToolUse 钩子；调用被拒绝时结果已经确定
+    async fn prepare_tool_call(
+        &mut self,
+        id: &str,
+        name: &str,
+        input: &serde_json::Value,
+        has_registry: bool,
+    ) -> Result<PreparedToolCall> {
+        tracing::debug!("Executing tool '{}' (call {})", name, id);
+        self.send_response(AgentResponse::ToolCall {
+            tool_name: name.to_string(),
+            tool_input: input.clone(),
+            call_id: id.to_string(),
         }).await?;
-        
-        // 模拟处理延迟
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        
-        self.send_response(AgentResponse::TextContent {
-            content: "Response generated successfully.".to_string(),
-            is_partial: false,
+        self.emit(AgentEvent::ToolStarted { call_id: id.to_string(), tool_name: name.to_string(), input: input.clone() });
+
+        let mut call = PreparedToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            input: input.clone(),
+            started: Instant::now(),
+            context: Vec::new(),
+            executed: None,
+            result: None,
+        };
+        if !has_registry {
+            call.result = Some(Err(ClaudeError::General(format!("Tool '{}' not found", name))));
+        } else if self.plan_mode && !plan::is_read_only(name) {
+            call.result = Some(Err(ClaudeError::General(format!("Tool '{}' is not available in plan mode; only read-only tools can be used until the plan is approved", name))));
+        } else {
+            let pre = self.hooks.run(self.hook_input(HookEvent::PreToolUse, name, input)).await;
+            call.context.extend(pre.additional_context);
+            let input = pre.updated_input.as_ref().unwrap_or(input);
+            match pre.block {
+                Some(reason) => {
+                    call.result = Some(Err(ClaudeError::General(format!("Tool '{}' was blocked by a hook: {}", name, reason))));
+                }
+                None => {
+                    self.create_checkpoint(id, name, input).await;
+                    call.executed = Some(input.clone());
+                }
+            }
+        }
+        Ok(call)
+    }
+
+    /// 运行 PostToolUse 钩子，通知调用结束并把结果记入对话
+    async fn finish_tool_call(&mut self, call: PreparedToolCall) -> Result<ContentBlock> {
+        let PreparedToolCall { id, name, input, started, mut context, executed, result } = call;
+        if let Some(timer) = self.current_turn.as_mut() {
+            timer.record_tool(name.clone(), started.elapsed());
+        }
+
+        let (mut content, mut is_error) = match result {
+            Some(Ok(result)) if result.success => (tool_output(&result.data), false),
+            Some(Ok(result)) => (result.error.unwrap_or_else(|| format!("Tool '{}' failed", name)), true),
+            Some(Err(e)) => (e.to_string(), true),
+            None => (format!("Tool '{}' did not run", name), true),
+        };
+        let max_tokens = self.context.config.tool_output.max_tokens;
+        content = self.output_spool.truncate(&content, max_tokens).unwrap_or_else(|e| {
+            tracing::warn!("Failed to save truncated output of tool '{}': {}", name, e);
+            let len = crate::tokens::prefix_within(&content, max_tokens);
+            format!("{}\n\n[Output truncated to {} tokens.]", &content[..len], max_tokens)
+        });
+        if let Some(executed) = executed {
+            let post = HookInput {
+                tool_output: Some(content.clone()),
+                is_error: Some(is_error),
+                ..self.hook_input(HookEvent::PostToolUse, &name, &executed)
+            };
+            let post = self.hooks.run(post).await;
+            context.extend(post.additional_context);
+            if let Some(reason) = post.block {
+                content = format!("{}\n\nRejected by a hook: {}", content, reason);
+                is_error = true;
+            }
+        }
+        for context in context {
+            content = format!("{}\n\n{}", content, context);
+        }
+        self.emit(AgentEvent::ToolFinished {
+            call_id: id.clone(),
+            tool_name: name.clone(),
+            output: content.clone(),
+            is_error,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        {
+            let mut conversation = self.conversation.lock().await;
+            if conversation.get_current_conversation().is_some() {
+                conversation.add_tool_result(&name, &input.to_string(), &content)?;
+            }
+        }
+        self.send_response(AgentResponse::ToolResult {
+            call_id: id.clone(),
+            result: serde_json::Value::String(content.clone()),
+            is_error,
         }).await?;
-        
+        Ok(ContentBlock::ToolResult {
+            tool_use_id: id,
+            content,
+            is_error: is_error.then_some(true),
+        })
+    }
+
+    fn hook_input(&self, event: HookEvent, tool_name: &str, input: &serde_json::Value) -> HookInput {
+        HookInput {
+            event,
+            session_id: self.context.session_id.clone(),
+            tool_name: tool_name.to_string(),
+            tool_input: input.clone(),
+            tool_output: None,
+            is_error: None,
+        }
+    }
+
+    /// 在修改型工具执行前记录检查点；记录失败只打印警告，不影响工具执行
+    async fn create_checkpoint(&self, call_id: &str, tool_name: &str, input: &serde_json::Value) {
+        let Some(store) = &self.context.checkpoints else {
+            return;
+        };
+        let replayed = self.journal.as_ref().is_some_and(|journal| journal.find(call_id, tool_name, input).is_some());
+        if plan::is_read_only(tool_name) || replayed {
+            return;
+        }
+        let conversation_id = self.conversation.lock().await.get_current_conversation().map(|c| c.id.clone());
+        let working_dir = std::path::Path::new(&self.tool_context.working_directory);
+        let files = checkpoint::touched_paths(input, working_dir);
+        let (transcript_len, messages) = self.checkpoint_position;
+        match store.create(&self.context.session_id, tool_name, call_id, &files, transcript_len, (conversation_id, messages)) {
+            Ok(checkpoint) => tracing::debug!("Checkpoint {} before '{}' ({} file(s))", checkpoint.id, tool_name, files.len()),
+            Err(e) => tracing::warn!("Failed to record checkpoint before '{}': {}", tool_name, e),
+        }
+    }
+
+    /// 回滚到检查点：恢复文件，把消息记录和会话历史截断到检查点之前
+    pub async fn rollback(&mut self, checkpoint_id: &str) -> Result<Rollback> {
+        let store = self
+            .context
+            .checkpoints
+            .as_ref()
+            .ok_or_else(|| ClaudeError::General("Checkpoints are not enabled for this agent".to_string()))?;
+        if store.find(checkpoint_id)?.session_id != self.context.session_id {
+            return Err(ClaudeError::validation_error("checkpoint", format!("Checkpoint '{}' belongs to another session", checkpoint_id)));
+        }
+        let rollback = store.rollback(checkpoint_id)?;
+        let checkpoint = &rollback.checkpoint;
+        self.transcript.truncate(checkpoint.transcript_len);
+        self.conversation.lock().await.truncate_messages(checkpoint.conversation_messages, &checkpoint.id)?;
+        self.journal = None;
+        Ok(rollback)
+    }
+
+    /// 追加到消息记录末尾的用户消息，没有时新建一条
+    fn push_user_blocks(&mut self, blocks: Vec<ContentBlock>) {
+        match self.transcript.last_mut() {
+            Some(ContentMessage { role, content: MessageContent::Blocks(existing) }) if role == "user" => existing.extend(blocks),
+            _ => self.transcript.push(ContentMessage::new("user", blocks)),
+        }
+    }
+
+    /// 有当前会话时把消息的文本写入会话历史
+    async fn record_conversation(&self, role: &str, blocks: &[ContentBlock]) -> Result<()> {
+        let mut conversation = self.conversation.lock().await;
+        if conversation.get_current_conversation().is_none() {
+            return Ok(());
+        }
+        let text = MessageContent::Blocks(blocks.to_vec()).as_str();
+        if !text.trim().is_empty() {
+            conversation.add_message(role, text, None)?;
+        }
         Ok(())
     }
 
-    /// 处理工具调用
-    async fn process_tool_calls(&mut self) -> Result<()> {
-        // 模拟工具调用检测和处理
-        if !self.context.tools_config.is_empty() {
-            tracing::debug!("Processing tool calls...");
-            
-            // 这里会集成实际的工具执行引擎
-            // 目前只是模拟
+    /// 采集环境快照并随会话保存
+    async fn capture_environment(&mut self) {
+        let working_dir = match std::env::current_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                tracing::warn!("Skipping environment snapshot: {}", e);
+                return;
+            }
+        };
+        let snapshot = EnvironmentSnapshot::capture(&working_dir).await;
+        if let Err(e) = self.conversation.lock().await.set_environment(snapshot.clone()) {
+            tracing::warn!("Failed to record environment snapshot: {}", e);
+        }
+        self.environment = Some(snapshot);
+    }
+
+    /// 获取会话环境快照
+    pub fn environment(&self) -> Option<&EnvironmentSnapshot> {
+        self.environment.as_ref()
+    }
+
+    /// 设置是否将环境快照注入系统提示
+    pub fn set_inject_environment(&mut self, inject: bool) {
+        self.inject_environment = inject;
+    }
+
+    /// 判断失败的回合是否应该重试
+    fn should_retry_turn(&self, error: &ClaudeError) -> bool {
+        let attempt = self.journal.as_ref().map_or(1, |j| j.attempts);
+        self.retry_policy.should_retry(error, attempt)
+    }
+
+    /// 重试用尽后仍是过载或服务端错误时，如果配置了回退模型且尚未切换则改用回退模型
+    fn should_fall_back(&self, error: &ClaudeError) -> bool {
+        self.active_model.is_none()
+            && error.status().is_some_and(|status| status >= 500)
+            && self.context.fallback_model.as_deref().is_some_and(|fallback| fallback != self.primary_model())
+    }
+
+    /// 切换到回退模型并记入会话历史，回合从头获得完整的重试次数
+    async fn switch_to_fallback(&mut self, error: &ClaudeError) -> Result<()> {
+        let Some(fallback) = self.context.fallback_model.clone() else {
+            return Ok(());
+        };
+        let note = format!("Switched from {} to fallback model {} after repeated errors: {}", self.primary_model(), fallback, error);
+        tracing::warn!("{}", note);
+        {
+            let mut conversation = self.conversation.lock().await;
+            if conversation.get_current_conversation().is_some() {
+                conversation.add_message("system", note.clone(), None)?;
+            }
+        }
+        let _ = self.response_sender.send(AgentResponse::StatusUpdate { status: AgentStatus::Running, message: Some(note) });
+        self.active_model = Some(fallback);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.attempts = 0;
         }
-        
         Ok(())
     }
 
+    /// 获取当前回合日志
+    pub fn turn_journal(&self) -> Option<&TurnJournal> {
+        self.journal.as_ref()
+    }
+
+    /// 设置瞬时错误重试策略
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// 获取回合延迟历史
+    pub fn latency_stats(&self) -> &LatencyStats {
+        &self.latency
+    }
+
     /// 获取 Steering 控制器引用
     pub fn steering(&self) -> &SteeringController {
         &self.steering
@@ -395,6 +1247,28 @@ impl AgentLoop {
     }
 }
 
+/// 已通知开始、等待执行或已有结果的工具调用
+struct PreparedToolCall {
+    id: String,
+    name: String,
+    input: serde_json::Value,
+    started: Instant,
+    /// 钩子追加给模型的上下文
+    context: Vec<String>,
+    /// 实际执行的参数（可能被钩子改写），调用被拒绝时为 `None`
+    executed: Option<serde_json::Value>,
+    result: Option<Result<crate::tools::ToolResult>>,
+}
+
+/// 工具结果中回传给模型的文本
+fn tool_output(data: &serde_json::Value) -> String {
+    match data {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Null => String::new(),
+        other => serde_json::to_string_pretty(other).unwrap_or_else(|_| other.to_string()),
+    }
+}
+
 /// 简化的 Agent 接口（用于 CLI）
 pub struct Agent {
     /// Agent 循环
@@ -464,6 +1338,15 @@ impl Agent {
 
     /// 分析代码库
     pub async fn analyze_codebase(&self, path: &str) -> crate::error::Result<CodebaseAnalysis> {
+        self.analyze_codebase_with_progress(path, None).await
+    }
+
+    /// 分析代码库，目录扫描进度通过回调上报
+    pub async fn analyze_codebase_with_progress(
+        &self,
+        path: &str,
+        progress: Option<crate::fs::scan::SharedProgressCallback>,
+    ) -> crate::error::Result<CodebaseAnalysis> {
         use tracing::{info, debug};
         use std::path::Path;
 
@@ -487,7 +1370,7 @@ impl Agent {
             files.push(file_info);
         } else {
             // 分析目录
-            files = self.analyze_directory(path).await?;
+            files = self.analyze_directory(path, progress).await?;
             for file in &files {
                 total_lines += file.line_count;
                 *languages.entry(file.language.clone()).or_insert(0) += 1;
@@ -531,29 +1414,7 @@ impl Agent {
             .unwrap_or("")
             .to_string();
 
-        let language = match extension.as_str() {
-            "rs" => "Rust",
-            "py" => "Python",
-            "js" => "JavaScript",
-            "ts" => "TypeScript",
-            "java" => "Java",
-            "cpp" | "cc" | "cxx" => "C++",
-            "c" => "C",
-            "go" => "Go",
-            "rb" => "Ruby",
-            "php" => "PHP",
-            "swift" => "Swift",
-            "kt" => "Kotlin",
-            "scala" => "Scala",
-            "cs" => "C#",
-            "html" => "HTML",
-            "css" => "CSS",
-            "json" => "JSON",
-            "yaml" | "yml" => "YAML",
-            "toml" => "TOML",
-            "md" => "Markdown",
-            _ => "Unknown",
-        }.to_string();
+        let language = language_for_extension(&extension);
 
         // 读取文件内容并计算行数
         let content = tokio::fs::read_to_string(file_path).await
@@ -573,53 +1434,64 @@ impl Agent {
             line_count,
             char_count,
             size_bytes: content.len(),
+            hash: format!("{:x}", md5::compute(&content)),
         })
     }
 
-    /// 分析目录
-    async fn analyze_directory(&self, dir_path: &str) -> crate::error::Result<Vec<FileInfo>> {
-        use tokio::fs;
-
-        let mut files = Vec::new();
-        let mut entries = fs::read_dir(dir_path).await
-            .map_err(|e| crate::error::ClaudeError::fs_error(format!("Failed to read directory {}: {}", dir_path, e)))?;
-
-        while let Some(entry) = entries.next_entry().await
-            .map_err(|e| crate::error::ClaudeError::fs_error(format!("Failed to read directory entry: {}", e)))? {
-
-            let path = entry.path();
-            let path_str = path.to_string_lossy().to_string();
-
-            if path.is_file() {
-                // 跳过隐藏文件和某些类型的文件
-                if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                    if file_name.starts_with('.') ||
-                       file_name.ends_with(".lock") ||
-                       file_name.ends_with(".log") {
-                        continue;
-                    }
-                }
-
-                if let Ok(file_info) = self.analyze_file(&path_str).await {
-                    files.push(file_info);
-                }
-            } else if path.is_dir() {
-                // 递归分析子目录（限制深度）
-                if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-                    if !dir_name.starts_with('.') &&
-                       dir_name != "target" &&
-                       dir_name != "node_modules" &&
-                       dir_name != "__pycache__" {
-                        // 使用 Box::pin 来处理递归 async 函数
-                        if let Ok(mut sub_files) = Box::pin(self.analyze_directory(&path_str)).await {
-                            files.append(&mut sub_files);
-                        }
-                    }
+    /// 并行扫描目录，项目已初始化时复用 `.claude-code/scan_cache.json` 中未变化文件的结果
+    async fn analyze_directory(
+        &self,
+        dir_path: &str,
+        progress: Option<crate::fs::scan::SharedProgressCallback>,
+    ) -> crate::error::Result<Vec<FileInfo>> {
+        use crate::fs::scan::{ParallelScanner, ProgressCallback, ScanCache, ScanOptions};
+        use tracing::{debug, warn};
+
+        let root = std::path::PathBuf::from(dir_path);
+        let cache_path = root.join(".claude-code").join("scan_cache.json");
+        let persist = root.join(".claude-code").is_dir();
+
+        let report = tokio::task::spawn_blocking(move || {
+            let mut cache = if persist { ScanCache::load(cache_path)? } else { ScanCache::default() };
+            let scanner = ParallelScanner::new(ScanOptions::default())?;
+            let report = scanner.scan(&root, Some(&mut cache), progress.as_deref().map(|p| p as ProgressCallback<'_>))?;
+            if persist {
+                if let Err(e) = cache.save() {
+                    warn!("Failed to save scan cache: {}", e);
                 }
             }
-        }
+            Ok::<_, crate::error::ClaudeError>(report)
+        })
+        .await
+        .map_err(|e| crate::error::ClaudeError::General(format!("Directory scan task failed: {}", e)))??;
 
-        Ok(files)
+        debug!(
+            "Scanned {} files in {:?} ({} hashed, {} cached)",
+            report.files.len(), report.elapsed, report.hashed, report.reused
+        );
+
+        Ok(report
+            .files
+            .into_iter()
+            .filter_map(|file| {
+                let name = file.path.file_name()?.to_str()?.to_string();
+                // 跳过锁文件和日志
+                if name.ends_with(".lock") || name.ends_with(".log") {
+                    return None;
+                }
+                let extension = file.path.extension().and_then(|e| e.to_str()).unwrap_or("").to_string();
+                Some(FileInfo {
+                    path: file.path.to_string_lossy().to_string(),
+                    name,
+                    language: language_for_extension(&extension),
+                    extension,
+                    line_count: file.line_count,
+                    char_count: file.char_count,
+                    size_bytes: file.size as usize,
+                    hash: file.hash,
+                })
+            })
+            .collect())
     }
 
     /// 分析项目结构
@@ -687,6 +1559,34 @@ pub struct CodebaseAnalysis {
     pub last_analyzed: chrono::DateTime<chrono::Utc>,
 }
 
+/// 根据扩展名推断编程语言
+fn language_for_extension(extension: &str) -> String {
+    match extension {
+        "rs" => "Rust",
+        "py" => "Python",
+        "js" => "JavaScript",
+        "ts" => "TypeScript",
+        "java" => "Java",
+        "cpp" | "cc" | "cxx" => "C++",
+        "c" => "C",
+        "go" => "Go",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "swift" => "Swift",
+        "kt" => "Kotlin",
+        "scala" => "Scala",
+        "cs" => "C#",
+        "html" => "HTML",
+        "css" => "CSS",
+        "json" => "JSON",
+        "yaml" | "yml" => "YAML",
+        "toml" => "TOML",
+        "md" => "Markdown",
+        _ => "Unknown",
+    }
+    .to_string()
+}
+
 /// 文件信息
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct FileInfo {
@@ -704,6 +1604,9 @@ pub struct FileInfo {
     pub char_count: usize,
     /// 文件大小（字节）
     pub size_bytes: usize,
+    /// 内容的 MD5 哈希
+    #[serde(default)]
+    pub hash: String,
 }
 
 /// 项目结构
@@ -754,4 +1657,394 @@ mod tests {
         
         assert_eq!(agent_loop.get_status().await, AgentStatus::NotStarted);
     }
+
+    struct EchoTool;
+
+    #[async_trait::async_trait]
+    impl crate::tools::Tool for EchoTool {
+        fn definition(&self) -> crate::tools::ToolDefinition {
+            crate::tools::ToolDefinition {
+                name: "echo".to_string(),
+                description: "Echo the text back".to_string(),
+                version: "1.0.0".to_string(),
+                parameters: vec![crate::tools::ToolParameter {
+                    name: "text".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Text to echo".to_string(),
+                    required: true,
+                    default: None,
+                    constraints: None,
+                }],
+                category: "test".to_string(),
+                requires_confirmation: false,
+                security_level: crate::tools::SecurityLevel::Safe,
+            }
+        }
+
+        async fn execute(&self, parameters: serde_json::Value, _context: &ToolContext) -> Result<crate::tools::ToolResult> {
+            Ok(crate::tools::ToolResult::success(parameters["text"].clone()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_use_round_trip_until_end_turn() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "echo", serde_json::json!({"text": "hi"})),
+            MockReply::tool_use("toolu_2", "missing", serde_json::json!({})),
+            MockReply::text("done"),
+        ])
+        .await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let tools = Arc::new(ToolRegistry::new());
+        tools.register_tool(Arc::new(EchoTool)).await.unwrap();
+
+        let context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        let (agent_loop, mut receiver) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent_loop = agent_loop.with_client(client).with_tools(tools);
+        agent_loop.run(vec!["say hi".to_string()]).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].body["tools"][0]["name"], "echo");
+        let messages = &requests[1].body["messages"];
+        assert_eq!(messages[1]["content"][0]["type"], "tool_use");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "toolu_1");
+        assert_eq!(messages[2]["content"][0]["content"], "hi");
+        // 不存在的工具作为错误结果交给模型，而不是中断循环
+        let failed = &requests[2].body["messages"][4]["content"][0];
+        assert_eq!(failed["is_error"], true);
+        assert!(failed["content"].as_str().unwrap().contains("not found"));
+
+        let mut final_response = None;
+        while let Ok(response) = receiver.try_recv() {
+            if let AgentResponse::Completed { final_response: text, metadata } = response {
+                assert_eq!(metadata["turns"], 3);
+                final_response = Some(text);
+            }
+        }
+        assert_eq!(final_response.as_deref(), Some("done"));
+        assert_eq!(agent_loop.get_status().await, AgentStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_environment_is_captured_once() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([MockReply::text("one"), MockReply::text("two")]).await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        let (agent_loop, _receiver) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent_loop = agent_loop.with_client(client);
+
+        agent_loop.run(vec!["first".to_string()]).await.unwrap();
+        let captured_at = agent_loop.environment().unwrap().captured_at;
+        agent_loop.run(vec!["second".to_string()]).await.unwrap();
+        assert_eq!(agent_loop.final_text(), "two");
+        assert_eq!(agent_loop.environment().unwrap().captured_at, captured_at);
+    }
+
+    #[tokio::test]
+    async fn test_turn_timings_reach_analytics_report() {
+        use crate::analytics::{AnalyticsConfig, AnalyticsEngine, TimeRange};
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "echo", serde_json::json!({"text": "hi"})),
+            MockReply::text("done"),
+        ])
+        .await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let tools = Arc::new(ToolRegistry::new());
+        tools.register_tool(Arc::new(EchoTool)).await.unwrap();
+        let analytics = Arc::new(
+            AnalyticsEngine::new(AnalyticsConfig {
+                enable_realtime: false,
+                data_retention_days: 1,
+                analysis_interval: 60,
+                enable_prediction: false,
+                enable_anomaly_detection: false,
+                report_interval_hours: 1,
+            })
+            .await
+            .unwrap(),
+        );
+
+        let context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        let (agent_loop, _receiver) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent_loop = agent_loop.with_client(client).with_tools(tools).with_analytics(analytics.clone());
+        agent_loop.run(vec!["say hi".to_string()]).await.unwrap();
+
+        let now = chrono::Utc::now();
+        let report = analytics.generate_report("summary", TimeRange { start: now, end: now }).await.unwrap();
+        let summary = agent_loop.latency_stats().summary();
+        assert_eq!(summary.turns, 2);
+        let average = report.key_metrics["avg.turn.total_ms"];
+        assert!((average - summary.avg_total_ms).abs() < 1e-6, "{} vs {}", average, summary.avg_total_ms);
+        assert!(report.key_metrics.contains_key("avg.turn.tool.echo_ms"));
+        assert!(report.data.contains_key("turn_latency"));
+    }
+
+    #[tokio::test]
+    async fn test_large_tool_output_is_truncated_with_cursor() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let text = "word ".repeat(400);
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "echo", serde_json::json!({"text": text})),
+            MockReply::text("done"),
+        ])
+        .await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let tools = Arc::new(ToolRegistry::new());
+        tools.register_tool(Arc::new(EchoTool)).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let spool = OutputSpool::in_dir(dir.path(), "test-session");
+
+        let mut config = ClaudeConfig::default();
+        config.tool_output.max_tokens = 100;
+        let context = AgentContext::new("test-session".to_string(), config);
+        let (agent_loop, _receiver) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent_loop = agent_loop.with_client(client).with_tools(tools).with_output_spool(spool.clone());
+        agent_loop.run(vec!["echo a lot".to_string()]).await.unwrap();
+
+        let requests = server.requests();
+        let content = requests[1].body["messages"][2]["content"][0]["content"].as_str().unwrap();
+        let (head, notice) = content.split_once("\n\n[Output truncated").unwrap();
+        assert_eq!(head.len(), 400);
+        let cursor = notice.split('"').nth(1).unwrap();
+        let rest = spool.read(cursor, 1000).unwrap();
+        assert_eq!((rest.content.len(), rest.next_cursor), (1600, None));
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_after_retries_on_overload() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([MockReply::overloaded(), MockReply::overloaded(), MockReply::text("from fallback")]).await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let mut context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        context.fallback_model = Some("claude-fallback".to_string());
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut manager = ConversationManager::with_storage_dir(temp_dir.path().to_path_buf()).unwrap();
+        manager.create_conversation(None).unwrap();
+        let (agent_loop, _receiver) = AgentLoop::new(context, manager);
+        let mut agent_loop = agent_loop.with_client(client);
+        agent_loop.set_retry_policy(RetryPolicy { max_retries: 1, base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(1) });
+        agent_loop.run(vec!["hello".to_string()]).await.unwrap();
+
+        let models: Vec<_> = server.requests().iter().map(|r| r.body["model"].as_str().unwrap().to_string()).collect();
+        let primary = ClaudeConfig::default().api.default_model;
+        assert_eq!(models, [primary.as_str(), primary.as_str(), "claude-fallback"]);
+        assert!(agent_loop.is_using_fallback());
+        assert_eq!(agent_loop.final_text(), "from fallback");
+        let messages = agent_loop.conversation.lock().await.get_conversation_messages();
+        let note = messages.iter().find(|m| m.role == "system").unwrap();
+        assert!(note.content.contains("fallback model claude-fallback"));
+    }
+
+    /// 记录执行次数的工具，参数 `slow` 为 true 时一直挂起
+    struct CountingTool {
+        runs: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl crate::tools::Tool for CountingTool {
+        fn definition(&self) -> crate::tools::ToolDefinition {
+            crate::tools::ToolDefinition {
+                name: "count".to_string(),
+                description: "Count how often the tool runs".to_string(),
+                version: "1.0.0".to_string(),
+                parameters: Vec::new(),
+                category: "test".to_string(),
+                requires_confirmation: false,
+                security_level: crate::tools::SecurityLevel::Safe,
+            }
+        }
+
+        async fn execute(&self, parameters: serde_json::Value, _context: &ToolContext) -> Result<crate::tools::ToolResult> {
+            if parameters["slow"].as_bool() == Some(true) {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+            let runs = self.runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            Ok(crate::tools::ToolResult::success(serde_json::json!({ "runs": runs })))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_turn_is_not_replayed() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([
+            // 第一个工具完成后第二个工具挂起，回合在时间限制处被放弃
+            MockReply::message(
+                serde_json::json!([
+                    {"type": "tool_use", "id": "toolu_1", "name": "count", "input": {"step": 1}},
+                    {"type": "tool_use", "id": "toolu_2", "name": "count", "input": {"slow": true}},
+                ]),
+                "tool_use",
+            ),
+            // 再次运行：请求先失败一次，重试后模型以新的 ID 重新发出同一调用，照常执行
+            MockReply::overloaded(),
+            MockReply::tool_use("toolu_9", "count", serde_json::json!({"step": 1})),
+            // 携带工具结果的后续请求失败，重试时不再执行工具
+            MockReply::overloaded(),
+            MockReply::text("done"),
+        ])
+        .await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let tools = Arc::new(ToolRegistry::new());
+        tools.register_tool(Arc::new(CountingTool { runs: runs.clone() })).await.unwrap();
+
+        let context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        let (agent_loop, _receiver) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent_loop = agent_loop.with_client(client).with_tools(tools).with_time_limit(Duration::from_secs(1));
+        agent_loop.set_retry_policy(RetryPolicy { max_retries: 2, base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(1) });
+
+        agent_loop.run(vec!["count once".to_string()]).await.unwrap();
+        assert_eq!(agent_loop.exhausted(), Some(RunLimit::TimeLimit(1)));
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(agent_loop.turn_journal().is_none());
+
+        agent_loop.run(Vec::new()).await.unwrap();
+        assert_eq!(agent_loop.final_text(), "done");
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 2, "abandoned results must not be replayed");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        for request in &requests[3..] {
+            let result = &request.body["messages"][2]["content"][0];
+            assert_eq!(result["tool_use_id"], "toolu_9");
+            assert!(result["content"].as_str().unwrap().contains("\"runs\": 2"), "{}", result);
+        }
+        assert!(agent_loop.turn_journal().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_max_turns_stops_with_pending_work() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "echo", serde_json::json!({"text": "one"})),
+            MockReply::tool_use("toolu_2", "echo", serde_json::json!({"text": "two"})),
+            MockReply::text("done"),
+        ])
+        .await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let tools = Arc::new(ToolRegistry::new());
+        tools.register_tool(Arc::new(EchoTool)).await.unwrap();
+
+        let context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        let (agent_loop, mut receiver) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent_loop = agent_loop.with_client(client).with_tools(tools).with_max_turns(2);
+        agent_loop.run(vec!["echo twice".to_string()]).await.unwrap();
+
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(agent_loop.get_status().await, AgentStatus::Exhausted(RunLimit::MaxTurns(2)));
+        assert!(agent_loop.pending_work().contains("1 tool result(s) the model has not seen yet: echo"));
+        let mut metadata = None;
+        while let Ok(response) = receiver.try_recv() {
+            if let AgentResponse::Completed { metadata: m, .. } = response {
+                metadata = Some(m);
+            }
+        }
+        assert_eq!(metadata.unwrap()["exhausted"], "reached the limit of 2 turn(s)");
+
+        // 再次运行时限制重新计算，模型看到之前的工具结果后完成
+        agent_loop.run(Vec::new()).await.unwrap();
+        assert_eq!(agent_loop.exhausted(), None);
+        assert_eq!(agent_loop.final_text(), "done");
+    }
+
+    #[tokio::test]
+    async fn test_plan_mode_waits_for_approval() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "echo", serde_json::json!({"text": "hi"})),
+            MockReply::text("## Plan\n1. Echo hi"),
+            MockReply::text("Executed"),
+        ])
+        .await;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let tools = Arc::new(ToolRegistry::new());
+        tools.register_tool(Arc::new(EchoTool)).await.unwrap();
+        let tool_context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("test-session".to_string())
+        };
+
+        let context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        let (agent_loop, _receiver) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent_loop = agent_loop.with_client(client).with_tools(tools).with_tool_context(tool_context).with_plan_mode(true);
+        agent_loop.run(vec!["echo hi".to_string()]).await.unwrap();
+
+        // 只读工具以外的工具既不发送给模型，也不会被执行
+        let requests = server.requests();
+        assert!(requests[0].body.get("tools").is_none());
+        assert!(requests[0].body["system"].as_str().unwrap().contains("plan mode"));
+        assert!(requests[1].body["messages"][2]["content"][0]["content"].as_str().unwrap().contains("not available in plan mode"));
+        assert_eq!(agent_loop.get_status().await, AgentStatus::AwaitingApproval);
+        let plan = agent_loop.plan().unwrap();
+        assert_eq!(std::fs::read_to_string(plan.path.as_ref().unwrap()).unwrap(), "## Plan\n1. Echo hi");
+
+        agent_loop.approve_plan().await.unwrap();
+        assert!(agent_loop.approve_plan().await.is_err());
+        agent_loop.run(Vec::new()).await.unwrap();
+        let requests = server.requests();
+        assert_eq!(requests[2].body["tools"][0]["name"], "echo");
+        assert_eq!(requests[2].body["messages"][4]["content"][0]["text"], plan::PLAN_APPROVED_MESSAGE);
+        assert_eq!(agent_loop.final_text(), "Executed");
+        assert_eq!(agent_loop.get_status().await, AgentStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_plan_mode_runs_git_tools() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git").args(args).current_dir(temp_dir.path()).output().unwrap().status;
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        git(&["init", "-q", "-b", "main"]);
+        std::fs::write(temp_dir.path().join("lib.rs"), "fn old() {}\n").unwrap();
+        git(&["add", "lib.rs"]);
+        git(&["-c", "user.name=t", "-c", "user.email=t@example.com", "commit", "-q", "-m", "init"]);
+        std::fs::write(temp_dir.path().join("lib.rs"), "fn new() {}\n").unwrap();
+
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "git_status", serde_json::json!({})),
+            MockReply::tool_use("toolu_2", "git_diff", serde_json::json!({"path": "lib.rs"})),
+            MockReply::text("## Plan\n1. Rename new back"),
+        ])
+        .await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let tools = Arc::new(ToolRegistry::new());
+        crate::tools::builtin::register_builtin_tools(&tools).await.unwrap();
+        for name in plan::READ_ONLY_TOOLS {
+            assert!(tools.get_tool(name).await.is_some(), "plan mode tool {} is not registered", name);
+        }
+        let tool_context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("test-session".to_string())
+        };
+
+        let context = AgentContext::new("test-session".to_string(), ClaudeConfig::default());
+        let (agent_loop, _receiver) = AgentLoop::new(context, ConversationManager::new());
+        let mut agent_loop = agent_loop.with_client(client).with_tools(tools).with_tool_context(tool_context).with_plan_mode(true);
+        agent_loop.run(vec!["what changed?".to_string()]).await.unwrap();
+
+        let requests = server.requests();
+        let offered: Vec<&str> = requests[0].body["tools"].as_array().unwrap().iter().map(|tool| tool["name"].as_str().unwrap()).collect();
+        assert!(offered.contains(&"git_status") && offered.contains(&"git_diff"));
+        let status = requests[1].body["messages"][2]["content"][0].clone();
+        assert_ne!(status["is_error"], serde_json::json!(true));
+        assert!(status["content"].as_str().unwrap().contains("M lib.rs"), "{}", status);
+        let diff = requests[2].body["messages"][4]["content"][0].clone();
+        assert!(diff["content"].as_str().unwrap().contains("+fn new() {}"), "{}", diff);
+        assert_eq!(agent_loop.get_status().await, AgentStatus::AwaitingApproval);
+    }
 }
@@ -0,0 +1,461 @@
+//! 多 Agent 编排
+//!
+//! 编排器同时运行多个具名 Agent（例如 reviewer、implementer、tester）。Agent 可以声明依赖：
+//! 依赖的 Agent 全部完成后它才开始，上游的最终回复附在它的任务之后。运行中的 Agent 通过
+//! `send_message` / `read_messages` 工具互相发消息。全部结束后由合并函数汇总各自的结果
+
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use super::{AgentContext, AgentLoop, AgentStatus};
+use crate::config::ClaudeConfig;
+use crate::conversation::ConversationManager;
+use crate::error::{ClaudeError, Result};
+use crate::locks::{rank, BlockingMutex};
+use crate::network::ClaudeApiClient;
+use crate::tools::{SecurityLevel, ToolContext, ToolDefinition, ToolRegistry, ToolResult, TypedTool};
+
+/// 团队中的一个 Agent
+#[derive(Debug, Clone)]
+pub struct AgentRole {
+    pub name: String,
+    /// 角色说明，追加到该 Agent 的系统提示
+    pub instructions: String,
+    /// 可以使用的工具，`None` 为全部工具
+    pub tools: Option<Vec<String>>,
+    /// 必须先完成的 Agent
+    pub after: Vec<String>,
+    pub token_budget: Option<u64>,
+    pub max_turns: Option<u32>,
+}
+
+impl AgentRole {
+    pub fn new(name: impl Into<String>, instructions: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            instructions: instructions.into(),
+            tools: None,
+            after: Vec::new(),
+            token_budget: None,
+            max_turns: None,
+        }
+    }
+
+    pub fn tools<S: Into<String>>(mut self, tools: impl IntoIterator<Item = S>) -> Self {
+        self.tools = Some(tools.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn after<S: Into<String>>(mut self, agents: impl IntoIterator<Item = S>) -> Self {
+        self.after = agents.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn token_budget(mut self, budget: u64) -> Self {
+        self.token_budget = Some(budget);
+        self
+    }
+
+    pub fn max_turns(mut self, max_turns: u32) -> Self {
+        self.max_turns = Some(max_turns);
+        self
+    }
+}
+
+/// Agent 之间的一条消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentMessage {
+    pub from: String,
+    pub to: String,
+    pub content: String,
+}
+
+/// 一次编排中所有 Agent 共享的信箱
+#[derive(Debug, Clone)]
+pub struct Mailbox {
+    /// 全部消息及是否已被收件人读取
+    messages: Arc<BlockingMutex<Vec<(AgentMessage, bool)>>>,
+}
+
+impl Default for Mailbox {
+    fn default() -> Self {
+        Self { messages: Arc::new(BlockingMutex::new("orchestrator.mailbox", rank::ORCHESTRATOR_MAILBOX, Vec::new())) }
+    }
+}
+
+impl Mailbox {
+    pub fn post(&self, message: AgentMessage) {
+        self.messages.lock().push((message, false));
+    }
+
+    /// 取出发给 `name` 的未读消息
+    pub fn take_for(&self, name: &str) -> Vec<AgentMessage> {
+        self.messages
+            .lock()
+            .iter_mut()
+            .filter(|(message, read)| !*read && message.to == name)
+            .map(|(message, read)| {
+                *read = true;
+                message.clone()
+            })
+            .collect()
+    }
+
+    /// 按发送顺序的全部消息
+    pub fn history(&self) -> Vec<AgentMessage> {
+        self.messages.lock().iter().map(|(message, _)| message.clone()).collect()
+    }
+}
+
+crate::tool_input! {
+    /// 发送消息的输入
+    pub struct SendMessageInput {
+        /// Name of the agent to send the message to
+        pub to: String,
+        /// Message text
+        pub content: String,
+    }
+}
+
+crate::tool_input! {
+    /// 读取消息的输入
+    pub struct ReadMessagesInput {}
+}
+
+/// 给团队中其他 Agent 发消息
+struct SendMessageTool {
+    mailbox: Mailbox,
+    from: String,
+    recipients: Vec<String>,
+}
+
+#[async_trait]
+impl TypedTool for SendMessageTool {
+    type Input = SendMessageInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("send_message")
+            .description(format!("Send a message to another agent on your team: {}", self.recipients.join(", ")))
+            .category("agent")
+            .security_level(SecurityLevel::Safe)
+            .input::<SendMessageInput>()
+            .build()
+    }
+
+    async fn run(&self, input: SendMessageInput, _context: &ToolContext) -> Result<ToolResult> {
+        if !self.recipients.contains(&input.to) {
+            return Ok(ToolResult::error(format!("Unknown agent '{}'; send to one of: {}", input.to, self.recipients.join(", "))));
+        }
+        self.mailbox.post(AgentMessage { from: self.from.clone(), to: input.to.clone(), content: input.content });
+        Ok(ToolResult::success(serde_json::json!({ "sent_to": input.to })))
+    }
+}
+
+/// 读取发给自己的未读消息
+struct ReadMessagesTool {
+    mailbox: Mailbox,
+    name: String,
+}
+
+#[async_trait]
+impl TypedTool for ReadMessagesTool {
+    type Input = ReadMessagesInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("read_messages")
+            .description("Read the messages other agents on your team have sent you since you last checked")
+            .category("agent")
+            .security_level(SecurityLevel::Safe)
+            .input::<ReadMessagesInput>()
+            .build()
+    }
+
+    async fn run(&self, _input: ReadMessagesInput, _context: &ToolContext) -> Result<ToolResult> {
+        let messages = self.mailbox.take_for(&self.name);
+        Ok(ToolResult::success(serde_json::json!(messages
+            .into_iter()
+            .map(|message| serde_json::json!({ "from": message.from, "content": message.content }))
+            .collect::<Vec<_>>())))
+    }
+}
+
+/// 一个 Agent 的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentOutput {
+    pub name: String,
+    /// 最终回复
+    pub text: String,
+    pub status: AgentStatus,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// 一次编排的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrchestrationResult {
+    /// 按声明顺序排列的各 Agent 结果
+    pub outputs: Vec<AgentOutput>,
+    /// Agent 之间发送的消息
+    pub messages: Vec<AgentMessage>,
+    /// 合并后的结果
+    pub merged: String,
+}
+
+type MergeFn = Box<dyn Fn(&[AgentOutput]) -> String + Send + Sync>;
+
+/// 默认的合并方式：按顺序列出每个 Agent 的回复
+fn concatenate(outputs: &[AgentOutput]) -> String {
+    outputs.iter().map(|output| format!("## {}\n\n{}", output.name, output.text.trim())).collect::<Vec<_>>().join("\n\n")
+}
+
+/// 编排器构建器
+pub struct OrchestratorBuilder {
+    client: Arc<ClaudeApiClient>,
+    config: ClaudeConfig,
+    tools: Arc<ToolRegistry>,
+    tool_context: Option<ToolContext>,
+    roles: Vec<AgentRole>,
+    merge: Option<MergeFn>,
+}
+
+impl OrchestratorBuilder {
+    /// 添加一个 Agent
+    pub fn agent(self, name: impl Into<String>, instructions: impl Into<String>) -> Self {
+        self.role(AgentRole::new(name, instructions))
+    }
+
+    /// 添加一个带工具、依赖或限制的 Agent
+    pub fn role(mut self, role: AgentRole) -> Self {
+        self.roles.push(role);
+        self
+    }
+
+    /// 设置工具执行上下文（工作目录等），会话 ID 按 Agent 区分
+    pub fn tool_context(mut self, context: ToolContext) -> Self {
+        self.tool_context = Some(context);
+        self
+    }
+
+    /// 设置合并步骤，默认按顺序拼接各 Agent 的回复
+    pub fn merge(mut self, merge: impl Fn(&[AgentOutput]) -> String + Send + Sync + 'static) -> Self {
+        self.merge = Some(Box::new(merge));
+        self
+    }
+
+    /// 检查名称唯一、依赖存在且没有循环
+    pub fn build(self) -> Result<Orchestrator> {
+        if self.roles.is_empty() {
+            return Err(ClaudeError::validation_error("agents", "an orchestrator needs at least one agent"));
+        }
+        let mut names = HashSet::new();
+        for role in &self.roles {
+            if !names.insert(role.name.as_str()) {
+                return Err(ClaudeError::validation_error("agents", format!("duplicate agent name '{}'", role.name)));
+            }
+        }
+        for role in &self.roles {
+            if let Some(missing) = role.after.iter().find(|dep| !names.contains(dep.as_str())) {
+                return Err(ClaudeError::validation_error("after", format!("agent '{}' waits for unknown agent '{}'", role.name, missing)));
+            }
+        }
+
+        // 反复取出依赖都已满足的 Agent，取不完说明有循环
+        let mut done: HashSet<&str> = HashSet::new();
+        while done.len() < self.roles.len() {
+            let ready: Vec<&str> = self
+                .roles
+                .iter()
+                .filter(|role| !done.contains(role.name.as_str()) && role.after.iter().all(|dep| done.contains(dep.as_str())))
+                .map(|role| role.name.as_str())
+                .collect();
+            if ready.is_empty() {
+                return Err(ClaudeError::validation_error("after", "agent dependencies form a cycle"));
+            }
+            done.extend(ready);
+        }
+
+        Ok(Orchestrator {
+            client: self.client,
+            config: self.config,
+            tools: self.tools,
+            tool_context: self.tool_context,
+            roles: self.roles,
+            merge: self.merge.unwrap_or_else(|| Box::new(concatenate)),
+        })
+    }
+}
+
+/// 多 Agent 编排器
+pub struct Orchestrator {
+    client: Arc<ClaudeApiClient>,
+    config: ClaudeConfig,
+    tools: Arc<ToolRegistry>,
+    tool_context: Option<ToolContext>,
+    roles: Vec<AgentRole>,
+    merge: MergeFn,
+}
+
+impl Orchestrator {
+    pub fn builder(client: Arc<ClaudeApiClient>, config: ClaudeConfig, tools: Arc<ToolRegistry>) -> OrchestratorBuilder {
+        OrchestratorBuilder { client, config, tools, tool_context: None, roles: Vec::new(), merge: None }
+    }
+
+    /// 运行整个团队：没有依赖的 Agent 立即并发开始，其余在依赖完成后开始
+    pub async fn run(&self, task: &str) -> Result<OrchestrationResult> {
+        let run_id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let mailbox = Mailbox::default();
+        let mut outputs: HashMap<String, AgentOutput> = HashMap::new();
+        let mut pending: Vec<&AgentRole> = self.roles.iter().collect();
+        let mut running = FuturesUnordered::new();
+
+        loop {
+            let (ready, waiting): (Vec<&AgentRole>, Vec<&AgentRole>) =
+                pending.into_iter().partition(|role| role.after.iter().all(|dep| outputs.contains_key(dep)));
+            pending = waiting;
+            for role in ready {
+                let upstream: Vec<&AgentOutput> = role.after.iter().filter_map(|dep| outputs.get(dep)).collect();
+                let prompt = role_prompt(task, &upstream);
+                running.push(self.run_role(role, prompt, &run_id, mailbox.clone()));
+            }
+            match running.next().await {
+                Some(output) => {
+                    tracing::info!("Agent '{}' finished: {:?}", output.name, output.status);
+                    outputs.insert(output.name.clone(), output);
+                }
+                None => break,
+            }
+        }
+
+        let outputs: Vec<AgentOutput> = self.roles.iter().filter_map(|role| outputs.remove(&role.name)).collect();
+        let merged = (self.merge)(&outputs);
+        Ok(OrchestrationResult { outputs, messages: mailbox.history(), merged })
+    }
+
+    async fn run_role(&self, role: &AgentRole, prompt: String, run_id: &str, mailbox: Mailbox) -> AgentOutput {
+        let failed = |error: String| AgentOutput {
+            name: role.name.clone(),
+            text: String::new(),
+            status: AgentStatus::Error(error),
+            input_tokens: 0,
+            output_tokens: 0,
+        };
+        let tools = match self.tools.subset(role.tools.as_deref()).await {
+            Ok(tools) => tools,
+            Err(e) => return failed(e.to_string()),
+        };
+        let teammates: Vec<String> = self.roles.iter().filter(|r| r.name != role.name).map(|r| r.name.clone()).collect();
+        if !teammates.is_empty() {
+            let send = SendMessageTool { mailbox: mailbox.clone(), from: role.name.clone(), recipients: teammates.clone() };
+            let read = ReadMessagesTool { mailbox, name: role.name.clone() };
+            if let Err(e) = tools.register_tool(Arc::new(send)).await {
+                return failed(e.to_string());
+            }
+            if let Err(e) = tools.register_tool(Arc::new(read)).await {
+                return failed(e.to_string());
+            }
+        }
+
+        let session_id = format!("orchestrator-{}-{}", run_id, role.name);
+        let tool_context = match &self.tool_context {
+            Some(context) => ToolContext { session_id: session_id.clone(), ..context.clone() },
+            None => ToolContext::new(session_id.clone()),
+        };
+        let mut instructions = format!("You are the {} agent.", role.name);
+        if !teammates.is_empty() {
+            instructions.push_str(&format!(
+                " You work concurrently with these agents: {}. Use send_message to tell or ask them something and read_messages to check for replies.",
+                teammates.join(", ")
+            ));
+        }
+        if !role.instructions.trim().is_empty() {
+            instructions.push_str("\n\n");
+            instructions.push_str(role.instructions.trim());
+        }
+
+        let (agent, _responses) = AgentLoop::new(AgentContext::new(session_id, self.config.clone()), ConversationManager::new());
+        let mut agent = agent
+            .with_client(self.client.clone())
+            .with_tools(Arc::new(tools))
+            .with_tool_context(tool_context)
+            .with_instructions(instructions);
+        if let Some(budget) = role.token_budget {
+            agent = agent.with_token_budget(budget);
+        }
+        if let Some(max_turns) = role.max_turns {
+            agent = agent.with_max_turns(max_turns);
+        }
+
+        if let Err(e) = agent.run(vec![prompt]).await {
+            return failed(e.to_string());
+        }
+        let (input_tokens, output_tokens) = agent.usage();
+        AgentOutput {
+            name: role.name.clone(),
+            text: agent.final_text().to_string(),
+            status: agent.get_status().await,
+            input_tokens,
+            output_tokens,
+        }
+    }
+}
+
+/// 任务加上上游 Agent 的结果
+fn role_prompt(task: &str, upstream: &[&AgentOutput]) -> String {
+    let mut prompt = task.to_string();
+    if !upstream.is_empty() {
+        prompt.push_str("\n\nResults from the agents that ran before you:");
+        for output in upstream {
+            let text = match &output.status {
+                AgentStatus::Error(error) => format!("(failed: {})", error),
+                _ => output.text.trim().to_string(),
+            };
+            prompt.push_str(&format!("\n\n### {}\n{}", output.name, text));
+        }
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{MockAnthropicServer, MockReply};
+
+    #[tokio::test]
+    async fn test_dependent_agents_pass_results_and_messages() {
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "send_message", serde_json::json!({"to": "reviewer", "content": "check the parser"})),
+            MockReply::text("implemented"),
+            MockReply::tool_use("toolu_2", "read_messages", serde_json::json!({})),
+            MockReply::text("approved"),
+        ])
+        .await;
+        let client = Arc::new(ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let builder = || Orchestrator::builder(client.clone(), ClaudeConfig::default(), Arc::new(ToolRegistry::new()));
+
+        assert!(builder().role(AgentRole::new("a", "").after(["b"])).role(AgentRole::new("b", "").after(["a"])).build().is_err());
+        assert!(builder().agent("a", "").agent("a", "").build().is_err());
+
+        let orchestrator = builder()
+            .agent("implementer", "Write the code.")
+            .role(AgentRole::new("reviewer", "Review the change.").after(["implementer"]))
+            .merge(|outputs| outputs.iter().map(|o| o.text.as_str()).collect::<Vec<_>>().join(" + "))
+            .build()
+            .unwrap();
+        let result = orchestrator.run("Add a parser").await.unwrap();
+
+        assert_eq!(result.merged, "implemented + approved");
+        assert_eq!(result.outputs[1].status, AgentStatus::Completed);
+        assert_eq!(result.messages, [AgentMessage { from: "implementer".into(), to: "reviewer".into(), content: "check the parser".into() }]);
+
+        let requests = server.requests();
+        assert!(requests[0].body["system"].as_str().unwrap().contains("You are the implementer agent."));
+        let reviewer_prompt = requests[2].body["messages"][0]["content"][0]["text"].as_str().unwrap();
+        assert!(reviewer_prompt.starts_with("Add a parser"));
+        assert!(reviewer_prompt.contains("### implementer\nimplemented"));
+        let inbox = requests[3].body["messages"][2]["content"][0]["content"].as_str().unwrap();
+        assert!(inbox.contains("check the parser"));
+    }
+}
@@ -0,0 +1,72 @@
+//! 计划模式
+//!
+//! 计划模式下 Agent 只能使用只读工具调查代码，最后给出一份计划。计划保存为
+//! `.claude/plans/` 下的 Markdown 文件，用户批准后 Agent 才切换到执行模式开始修改
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+
+/// 计划模式下允许使用的只读工具
+pub const READ_ONLY_TOOLS: &[&str] = &["read", "ls", "grep", "glob", "notebook_read", "tool_output", "git_status", "git_diff"];
+
+/// 计划模式追加到系统提示的说明
+pub const PLAN_MODE_INSTRUCTIONS: &str = "You are in plan mode. Investigate the codebase with the read-only tools you have, \
+but do not modify files or run commands with side effects. When you understand the task, reply with an implementation \
+plan in Markdown: a short summary, then numbered steps naming the files to change and how to verify the result. \
+The user reviews the plan before anything is executed.";
+
+/// 用户批准计划后发给 Agent 的消息
+pub const PLAN_APPROVED_MESSAGE: &str = "The plan is approved. Carry it out now, step by step.";
+
+/// 工具是否可以在计划模式下使用
+pub fn is_read_only(tool_name: &str) -> bool {
+    READ_ONLY_TOOLS.contains(&tool_name)
+}
+
+/// 计划产物
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanArtifact {
+    /// 计划正文（Markdown）
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+    /// 保存的位置
+    pub path: Option<PathBuf>,
+    pub approved: bool,
+}
+
+impl PlanArtifact {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self { text: text.into(), created_at: Utc::now(), path: None, approved: false }
+    }
+
+    /// 保存到 `<root>/.claude/plans/plan-<时间>.md`
+    pub fn save(&mut self, root: &Path) -> Result<&Path> {
+        let dir = root.join(".claude").join("plans");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("plan-{}.md", self.created_at.format("%Y%m%d-%H%M%S")));
+        std::fs::write(&path, &self.text)?;
+        Ok(self.path.insert(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_only_tools_and_save() {
+        assert!(is_read_only("read"));
+        assert!(is_read_only("git_diff"));
+        assert!(!is_read_only("write"));
+        assert!(!is_read_only("bash"));
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut plan = PlanArtifact::new("## Plan\n1. Do it");
+        let path = plan.save(temp_dir.path()).unwrap().to_path_buf();
+        assert!(path.starts_with(temp_dir.path().join(".claude/plans")));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "## Plan\n1. Do it");
+    }
+}
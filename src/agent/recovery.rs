@@ -0,0 +1,364 @@
+//! 回合恢复
+//!
+//! 回合在工具执行后因瞬时错误（网络中断、API 5xx）失败时，保留已完成的工具结果，
+//! 重试只重新请求模型，已执行过的工具直接复用结果而不再重复产生副作用
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use tokio::time::Duration;
+
+use crate::error::{ClaudeError, Result};
+use crate::tools::{ToolContext, ToolRegistry, ToolResult};
+
+/// 已完成的工具调用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletedToolCall {
+    /// 调用 ID
+    pub call_id: String,
+    /// 工具名称
+    pub tool_name: String,
+    /// 调用参数
+    pub input: Value,
+    /// 执行结果
+    pub result: ToolResult,
+    /// 执行时的尝试序号
+    #[serde(default)]
+    pub attempt: u32,
+}
+
+/// 回合日志：记录回合内已完成的工具调用，作为重试时的持久状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TurnJournal {
+    /// 回合序号
+    pub turn: u64,
+    /// 已尝试次数
+    pub attempts: u32,
+    /// 已完成的工具调用
+    completed: Vec<CompletedToolCall>,
+    /// 结果已写入消息记录，等待下一次模型请求成功
+    #[serde(default)]
+    delivered: bool,
+    /// 本次尝试中已按内容复用过的调用，每个只复用一次
+    #[serde(skip)]
+    reused: Vec<usize>,
+}
+
+impl TurnJournal {
+    /// 创建新的回合日志
+    pub fn new(turn: u64) -> Self {
+        Self {
+            turn,
+            attempts: 0,
+            completed: Vec::new(),
+            delivered: false,
+            reused: Vec::new(),
+        }
+    }
+
+    /// 开始一次尝试，返回当前尝试序号（从 1 开始）
+    pub fn begin_attempt(&mut self) -> u32 {
+        self.attempts += 1;
+        self.reused.clear();
+        self.attempts
+    }
+
+    /// 是否为重试（之前已有失败的尝试）
+    pub fn is_retry(&self) -> bool {
+        self.attempts > 1
+    }
+
+    /// 记录一次已完成的工具调用
+    pub fn record(&mut self, call_id: impl Into<String>, tool_name: impl Into<String>, input: Value, result: ToolResult) {
+        self.completed.push(CompletedToolCall {
+            call_id: call_id.into(),
+            tool_name: tool_name.into(),
+            input,
+            result,
+            attempt: self.attempts,
+        });
+    }
+
+    /// 查找已完成的调用：优先按调用 ID，其次按工具名称和参数
+    ///
+    /// 重试时模型可能为相同的调用生成新的 ID，因此需要按内容匹配；内容匹配只在重试时进行，
+    /// 并且只匹配之前失败的尝试记录的调用。同一响应中重复的调用（先读、再改、再读）照常执行
+    pub fn find(&self, call_id: &str, tool_name: &str, input: &Value) -> Option<&CompletedToolCall> {
+        self.position(call_id, tool_name, input).map(|index| &self.completed[index])
+    }
+
+    fn position(&self, call_id: &str, tool_name: &str, input: &Value) -> Option<usize> {
+        if let Some(index) = self.completed.iter().position(|c| c.call_id == call_id) {
+            return Some(index);
+        }
+        if !self.is_retry() {
+            return None;
+        }
+        (0..self.completed.len()).find(|index| {
+            let c = &self.completed[*index];
+            c.attempt < self.attempts && !self.reused.contains(index) && c.tool_name == tool_name && &c.input == input
+        })
+    }
+
+    /// 取出可复用的结果，按内容匹配的调用在本次尝试中不再复用
+    fn replay(&mut self, call_id: &str, tool_name: &str, input: &Value) -> Option<ToolResult> {
+        let index = self.position(call_id, tool_name, input)?;
+        let done = &self.completed[index];
+        tracing::info!("Reusing result of '{}' from turn {} (call {})", tool_name, self.turn, done.call_id);
+        let result = done.result.clone();
+        if done.call_id != call_id {
+            self.reused.push(index);
+        }
+        Some(result)
+    }
+
+    /// 工具结果已写入消息记录：下一次模型请求重新计算尝试次数，请求成功前结果仍可重放
+    pub fn mark_delivered(&mut self) {
+        self.delivered = true;
+        self.attempts = 0;
+    }
+
+    /// 模型请求成功：已送达的结果不再需要，日志改为记录 `turn` 回合的调用；
+    /// 未送达的结果（回合中途放弃）保留，模型重新发出相同调用时复用
+    pub fn settle(&mut self, turn: u64) {
+        if self.delivered {
+            self.turn = turn;
+            self.completed.clear();
+            self.delivered = false;
+        }
+    }
+
+    /// 所有已完成的工具调用
+    pub fn completed(&self) -> &[CompletedToolCall] {
+        &self.completed
+    }
+
+    /// 通过日志执行工具：已执行过的调用直接返回保存的结果
+    pub async fn execute_tool(
+        &mut self,
+        registry: &ToolRegistry,
+        call_id: &str,
+        tool_name: &str,
+        input: Value,
+        context: &ToolContext,
+    ) -> Result<ToolResult> {
+        if let Some(result) = self.replay(call_id, tool_name, &input) {
+            return Ok(result);
+        }
+
+        let result = registry.execute_tool(tool_name, input.clone(), context).await?;
+        self.record(call_id, tool_name, input, result.clone());
+        Ok(result)
+    }
+
+    /// 通过日志执行一组 `(调用 ID, 工具名称, 参数)`，未执行过的调用交给注册表批量执行，结果按调用顺序返回
+    pub async fn execute_batch(
+        &mut self,
+        registry: &ToolRegistry,
+        calls: Vec<(String, String, Value)>,
+        context: &ToolContext,
+    ) -> Vec<Result<ToolResult>> {
+        let mut results: Vec<Option<Result<ToolResult>>> = calls
+            .iter()
+            .map(|(call_id, tool_name, input)| self.replay(call_id, tool_name, input).map(Ok))
+            .collect();
+        let pending: Vec<usize> = (0..calls.len()).filter(|&index| results[index].is_none()).collect();
+        let requests = pending.iter().map(|&index| (calls[index].1.clone(), calls[index].2.clone())).collect();
+        let executed = registry.execute_batch(requests, context).await;
+        for (index, result) in pending.into_iter().zip(executed) {
+            if let Ok(result) = &result {
+                let (call_id, tool_name, input) = &calls[index];
+                self.record(call_id.clone(), tool_name.clone(), input.clone(), result.clone());
+            }
+            results[index] = Some(result);
+        }
+        results.into_iter().flatten().collect()
+    }
+
+    /// 保存到磁盘
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+
+    /// 从磁盘加载
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path).await?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+/// 服务端要求的重试等待时间上限，避免异常的 Retry-After 让会话长时间挂起
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// 回合重试策略
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// 最大重试次数（不含首次尝试）
+    pub max_retries: u32,
+    /// 初始退避时间
+    pub base_delay: Duration,
+    /// 最大退避时间
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 使用指定的最大重试次数创建策略
+    pub fn with_max_retries(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    /// 第 `attempt` 次尝试失败后是否应该重试
+    pub fn should_retry(&self, error: &ClaudeError, attempt: u32) -> bool {
+        attempt <= self.max_retries && error.is_retryable()
+    }
+
+    /// 第 `attempt` 次尝试失败后的退避时间（指数退避）
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// 错误带有服务端要求的等待时间（限流的 Retry-After）时优先使用，否则按指数退避
+    pub fn delay_for_error(&self, error: &ClaudeError, attempt: u32) -> Duration {
+        match error.retry_after() {
+            Some(retry_after) => retry_after.min(MAX_RETRY_AFTER),
+            None => self.delay_for(attempt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{SecurityLevel, Tool, ToolDefinition};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// 记录执行次数的测试工具
+    struct CountingTool {
+        runs: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Tool for CountingTool {
+        fn definition(&self) -> ToolDefinition {
+            ToolDefinition {
+                name: "count".to_string(),
+                description: "Count executions".to_string(),
+                version: "1.0.0".to_string(),
+                parameters: Vec::new(),
+                category: "test".to_string(),
+                requires_confirmation: false,
+                security_level: SecurityLevel::Safe,
+            }
+        }
+
+        async fn execute(&self, _parameters: Value, _context: &ToolContext) -> Result<ToolResult> {
+            let runs = self.runs.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(ToolResult::success(json!({ "runs": runs })))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_reuses_completed_tools() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let registry = ToolRegistry::new();
+        registry.register_tool(Arc::new(CountingTool { runs: runs.clone() })).await.unwrap();
+        let context = ToolContext::new("test".to_string());
+        let input = json!({ "step": 1 });
+
+        let mut journal = TurnJournal::new(1);
+        journal.begin_attempt();
+        let first = journal.execute_tool(&registry, "call_1", "count", input.clone(), &context).await.unwrap();
+        assert!(first.success);
+
+        // 模拟回合中断后重试，模型为相同调用生成了新的 ID
+        journal.begin_attempt();
+        assert!(journal.is_retry());
+        let second = journal.execute_tool(&registry, "call_9", "count", input, &context).await.unwrap();
+        assert_eq!(second.data, first.data);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        // 新的调用参数仍会执行
+        journal.execute_tool(&registry, "call_10", "count", json!({ "step": 2 }), &context).await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let journal_path = temp_dir.path().join("journal.json");
+        journal.save(&journal_path).await.unwrap();
+        let loaded = TurnJournal::load(&journal_path).await.unwrap();
+        assert_eq!(loaded.attempts, 2);
+        assert_eq!(loaded.completed().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_calls_in_one_response_run_again() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let registry = ToolRegistry::new();
+        registry.register_tool(Arc::new(CountingTool { runs: runs.clone() })).await.unwrap();
+        let context = ToolContext::new("test".to_string());
+        let read = json!({ "read": "x" });
+        let edit = json!({ "edit": "x" });
+
+        // 先读、再改、再读：第二次读取必须重新执行
+        let mut journal = TurnJournal::new(1);
+        journal.begin_attempt();
+        journal.execute_tool(&registry, "call_1", "count", read.clone(), &context).await.unwrap();
+        journal.execute_tool(&registry, "call_2", "count", edit.clone(), &context).await.unwrap();
+        let reread = journal.execute_tool(&registry, "call_3", "count", read.clone(), &context).await.unwrap();
+        assert_eq!(reread.data, json!({ "runs": 3 }));
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+        // 重试时每个记录的调用按顺序复用一次，同一批次中的重复调用也不串用
+        journal.begin_attempt();
+        let calls = vec![
+            ("call_7".to_string(), "count".to_string(), read.clone()),
+            ("call_8".to_string(), "count".to_string(), edit),
+            ("call_9".to_string(), "count".to_string(), read.clone()),
+            ("call_10".to_string(), "count".to_string(), read),
+        ];
+        let results: Vec<Value> = journal.execute_batch(&registry, calls, &context).await.into_iter().map(|r| r.unwrap().data).collect();
+        assert_eq!(results, [json!({ "runs": 1 }), json!({ "runs": 2 }), json!({ "runs": 3 }), json!({ "runs": 4 })]);
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::with_max_retries(2);
+        let transient = ClaudeError::network_error("HTTP 500 Internal Server Error");
+        let fatal = ClaudeError::network_error("HTTP 400 Bad Request");
+
+        assert!(policy.should_retry(&transient, 1));
+        assert!(policy.should_retry(&transient, 2));
+        assert!(!policy.should_retry(&transient, 3));
+        assert!(!policy.should_retry(&fatal, 1));
+        assert_eq!(policy.delay_for(1), Duration::from_millis(500));
+        assert_eq!(policy.delay_for(3), Duration::from_secs(2));
+        assert_eq!(policy.delay_for(10), Duration::from_secs(8));
+
+        let limited = ClaudeError::from_status(429, "", Some(Duration::from_secs(20)));
+        assert!(policy.should_retry(&limited, 1));
+        assert_eq!(policy.delay_for_error(&limited, 1), Duration::from_secs(20));
+        assert!(!policy.should_retry(&ClaudeError::auth_error("invalid x-api-key"), 1));
+    }
+}
@@ -0,0 +1,330 @@
+//! 回合延迟统计
+//!
+//! 记录每个回合的排队时间、首 token 延迟、流式耗时、各工具执行耗时以及总耗时
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// 保留的最近回合数量
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// 单个工具的执行耗时
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ToolTiming {
+    /// 工具名称
+    pub tool_name: String,
+    /// 执行耗时（毫秒）
+    pub duration_ms: u64,
+}
+
+/// 单个回合的延迟分解
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TurnTiming {
+    /// 回合序号（从 1 开始）
+    pub turn: u64,
+    /// 排队时间：回合开始到请求发出（毫秒）
+    pub queue_ms: u64,
+    /// 首 token 延迟：请求发出到收到第一个 token（毫秒）
+    pub time_to_first_token_ms: Option<u64>,
+    /// 流式耗时：第一个 token 到流结束（毫秒）
+    pub streaming_ms: Option<u64>,
+    /// 各工具执行耗时
+    pub tools: Vec<ToolTiming>,
+    /// 总耗时（毫秒）
+    pub total_ms: u64,
+}
+
+impl TurnTiming {
+    /// 工具执行总耗时（毫秒）
+    pub fn tool_total_ms(&self) -> u64 {
+        self.tools.iter().map(|t| t.duration_ms).sum()
+    }
+
+    /// 格式化为 `/stats turn` 输出
+    pub fn format_report(&self) -> String {
+        let mut report = format!("⏱️  Turn #{} latency breakdown\n\n", self.turn);
+        report.push_str(&format!("  Queueing:            {}\n", format_ms(Some(self.queue_ms))));
+        report.push_str(&format!("  Time to first token: {}\n", format_ms(self.time_to_first_token_ms)));
+        report.push_str(&format!("  Streaming:           {}\n", format_ms(self.streaming_ms)));
+        if self.tools.is_empty() {
+            report.push_str("  Tools:               none\n");
+        } else {
+            report.push_str(&format!("  Tools:               {}\n", format_ms(Some(self.tool_total_ms()))));
+            for tool in &self.tools {
+                report.push_str(&format!("    • {:<17} {}\n", tool.tool_name, format_ms(Some(tool.duration_ms))));
+            }
+        }
+        report.push_str(&format!("  Total wall time:     {}", format_ms(Some(self.total_ms))));
+        report
+    }
+}
+
+/// 回合计时器，按阶段打点后生成 [`TurnTiming`]
+#[derive(Debug, Clone)]
+pub struct TurnTimer {
+    turn: u64,
+    started_at: Instant,
+    dispatched_at: Option<Instant>,
+    first_token_at: Option<Instant>,
+    stream_ended_at: Option<Instant>,
+    tools: Vec<ToolTiming>,
+}
+
+impl TurnTimer {
+    /// 开始一个新回合的计时
+    pub fn start(turn: u64) -> Self {
+        Self {
+            turn,
+            started_at: Instant::now(),
+            dispatched_at: None,
+            first_token_at: None,
+            stream_ended_at: None,
+            tools: Vec::new(),
+        }
+    }
+
+    /// 回合序号
+    pub fn turn(&self) -> u64 {
+        self.turn
+    }
+
+    /// 标记请求已发出（排队结束）
+    pub fn mark_dispatched(&mut self) {
+        self.dispatched_at.get_or_insert_with(Instant::now);
+    }
+
+    /// 标记收到第一个 token，重复调用只保留第一次
+    pub fn mark_first_token(&mut self) {
+        self.mark_dispatched();
+        self.first_token_at.get_or_insert_with(Instant::now);
+    }
+
+    /// 标记流式响应结束
+    pub fn mark_stream_end(&mut self) {
+        self.mark_first_token();
+        self.stream_ended_at = Some(Instant::now());
+    }
+
+    /// 记录一次工具执行耗时
+    pub fn record_tool(&mut self, tool_name: impl Into<String>, duration: Duration) {
+        self.tools.push(ToolTiming {
+            tool_name: tool_name.into(),
+            duration_ms: duration.as_millis() as u64,
+        });
+    }
+
+    /// 结束计时并生成延迟分解
+    pub fn finish(self) -> TurnTiming {
+        let finished_at = Instant::now();
+        let dispatched_at = self.dispatched_at.unwrap_or(finished_at);
+
+        TurnTiming {
+            turn: self.turn,
+            queue_ms: millis_between(self.started_at, dispatched_at),
+            time_to_first_token_ms: self.first_token_at.map(|t| millis_between(dispatched_at, t)),
+            streaming_ms: self
+                .first_token_at
+                .zip(self.stream_ended_at)
+                .map(|(first, end)| millis_between(first, end)),
+            tools: self.tools,
+            total_ms: millis_between(self.started_at, finished_at),
+        }
+    }
+}
+
+/// 多个回合的延迟汇总
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct LatencySummary {
+    /// 回合数
+    pub turns: usize,
+    /// 平均排队时间（毫秒）
+    pub avg_queue_ms: f64,
+    /// 平均首 token 延迟（毫秒）
+    pub avg_time_to_first_token_ms: f64,
+    /// 平均流式耗时（毫秒）
+    pub avg_streaming_ms: f64,
+    /// 平均工具耗时（毫秒）
+    pub avg_tool_ms: f64,
+    /// 平均总耗时（毫秒）
+    pub avg_total_ms: f64,
+    /// 总耗时 p95（毫秒）
+    pub p95_total_ms: u64,
+}
+
+impl LatencySummary {
+    /// 格式化为 `/stats` 输出
+    pub fn format_report(&self) -> String {
+        format!(
+            "📈 Latency over {} turn(s)\n\n  \
+            Avg queueing:            {:.0}ms\n  \
+            Avg time to first token: {:.0}ms\n  \
+            Avg streaming:           {:.0}ms\n  \
+            Avg tool time:           {:.0}ms\n  \
+            Avg wall time:           {:.0}ms (p95 {}ms)",
+            self.turns,
+            self.avg_queue_ms,
+            self.avg_time_to_first_token_ms,
+            self.avg_streaming_ms,
+            self.avg_tool_ms,
+            self.avg_total_ms,
+            self.p95_total_ms,
+        )
+    }
+}
+
+/// 回合延迟历史
+#[derive(Debug, Clone)]
+pub struct LatencyStats {
+    history: VecDeque<TurnTiming>,
+    limit: usize,
+    next_turn: u64,
+}
+
+impl Default for LatencyStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LatencyStats {
+    /// 创建新的延迟历史
+    pub fn new() -> Self {
+        Self::with_limit(DEFAULT_HISTORY_LIMIT)
+    }
+
+    /// 创建保留指定回合数的延迟历史
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            history: VecDeque::new(),
+            limit: limit.max(1),
+            next_turn: 1,
+        }
+    }
+
+    /// 为下一个回合开始计时
+    pub fn start_turn(&mut self) -> TurnTimer {
+        let timer = TurnTimer::start(self.next_turn);
+        self.next_turn += 1;
+        timer
+    }
+
+    /// 记录一个已完成回合
+    pub fn record(&mut self, timing: TurnTiming) {
+        if self.history.len() == self.limit {
+            self.history.pop_front();
+        }
+        self.history.push_back(timing);
+    }
+
+    /// 最近一个回合
+    pub fn last_turn(&self) -> Option<&TurnTiming> {
+        self.history.back()
+    }
+
+    /// 所有保留的回合
+    pub fn turns(&self) -> impl Iterator<Item = &TurnTiming> {
+        self.history.iter()
+    }
+
+    /// 汇总保留的回合
+    pub fn summary(&self) -> LatencySummary {
+        let turns = self.history.len();
+        if turns == 0 {
+            return LatencySummary::default();
+        }
+
+        let avg = |values: Vec<u64>| -> f64 {
+            if values.is_empty() {
+                0.0
+            } else {
+                values.iter().sum::<u64>() as f64 / values.len() as f64
+            }
+        };
+
+        let mut totals: Vec<u64> = self.history.iter().map(|t| t.total_ms).collect();
+        totals.sort_unstable();
+        let p95_index = ((totals.len() as f64 * 0.95).ceil() as usize).clamp(1, totals.len()) - 1;
+
+        LatencySummary {
+            turns,
+            avg_queue_ms: avg(self.history.iter().map(|t| t.queue_ms).collect()),
+            avg_time_to_first_token_ms: avg(self.history.iter().filter_map(|t| t.time_to_first_token_ms).collect()),
+            avg_streaming_ms: avg(self.history.iter().filter_map(|t| t.streaming_ms).collect()),
+            avg_tool_ms: avg(self.history.iter().map(|t| t.tool_total_ms()).collect()),
+            avg_total_ms: avg(totals.clone()),
+            p95_total_ms: totals[p95_index],
+        }
+    }
+}
+
+fn millis_between(start: Instant, end: Instant) -> u64 {
+    end.saturating_duration_since(start).as_millis() as u64
+}
+
+fn format_ms(ms: Option<u64>) -> String {
+    match ms {
+        Some(ms) if ms >= 1000 => format!("{:.2}s", ms as f64 / 1000.0),
+        Some(ms) => format!("{}ms", ms),
+        None => "n/a".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(turn: u64, total_ms: u64, tools: &[(&str, u64)]) -> TurnTiming {
+        TurnTiming {
+            turn,
+            queue_ms: 10,
+            time_to_first_token_ms: Some(100),
+            streaming_ms: Some(200),
+            tools: tools
+                .iter()
+                .map(|(name, ms)| ToolTiming { tool_name: name.to_string(), duration_ms: *ms })
+                .collect(),
+            total_ms,
+        }
+    }
+
+    #[test]
+    fn test_turn_timer_phases() {
+        let mut timer = TurnTimer::start(1);
+        timer.mark_first_token();
+        timer.mark_stream_end();
+        timer.record_tool("bash", Duration::from_millis(25));
+        let timing = timer.finish();
+
+        assert_eq!(timing.turn, 1);
+        assert!(timing.time_to_first_token_ms.is_some());
+        assert!(timing.streaming_ms.is_some());
+        assert_eq!(timing.tool_total_ms(), 25);
+        assert!(timing.format_report().contains("bash"));
+    }
+
+    #[test]
+    fn test_turn_without_tokens() {
+        let timing = TurnTimer::start(3).finish();
+        assert_eq!(timing.time_to_first_token_ms, None);
+        assert_eq!(timing.streaming_ms, None);
+        assert!(timing.format_report().contains("n/a"));
+    }
+
+    #[test]
+    fn test_latency_summary() {
+        let mut stats = LatencyStats::with_limit(2);
+        assert_eq!(stats.summary().turns, 0);
+
+        stats.record(timing(1, 5000, &[]));
+        stats.record(timing(2, 1000, &[("read", 40)]));
+        stats.record(timing(3, 3000, &[("bash", 60), ("read", 20)]));
+
+        let summary = stats.summary();
+        assert_eq!(summary.turns, 2);
+        assert_eq!(summary.avg_total_ms, 2000.0);
+        assert_eq!(summary.avg_tool_ms, 60.0);
+        assert_eq!(summary.p95_total_ms, 3000);
+        assert_eq!(stats.last_turn().map(|t| t.turn), Some(3));
+    }
+}
@@ -628,6 +628,26 @@ impl AnalyticsEngine {
         Ok(())
     }
 
+    /// 记录回合延迟分解，各阶段作为 `turn.*` 指标
+    pub async fn track_turn_timing(&self, timing: &crate::agent::TurnTiming) -> Result<()> {
+        let labels = HashMap::from([("turn".to_string(), timing.turn.to_string())]);
+
+        self.track_metric("turn.queue_ms", timing.queue_ms as f64, labels.clone()).await?;
+        if let Some(ttft) = timing.time_to_first_token_ms {
+            self.track_metric("turn.time_to_first_token_ms", ttft as f64, labels.clone()).await?;
+        }
+        if let Some(streaming) = timing.streaming_ms {
+            self.track_metric("turn.streaming_ms", streaming as f64, labels.clone()).await?;
+        }
+        for tool in &timing.tools {
+            let mut tool_labels = labels.clone();
+            tool_labels.insert("tool".to_string(), tool.tool_name.clone());
+            self.track_metric(&format!("turn.tool.{}_ms", tool.tool_name), tool.duration_ms as f64, tool_labels).await?;
+        }
+        self.track_metric("turn.total_ms", timing.total_ms as f64, labels).await?;
+        Ok(())
+    }
+
     /// 生成洞察
     pub async fn generate_insights(&self) -> Result<Vec<Insight>> {
         let insights = self.insight_generator.generate_insights().await?;
@@ -636,7 +656,17 @@ impl AnalyticsEngine {
 
     /// 生成报告
     pub async fn generate_report(&self, template_id: &str, time_range: TimeRange) -> Result<AnalyticsReport> {
-        let report = self.report_generator.generate_report(template_id, time_range).await?;
+        let mut report = self.report_generator.generate_report(template_id, time_range).await?;
+
+        // 汇总回合延迟指标
+        let latency = self.data_collector.metric_averages("turn.").await;
+        for (name, value) in &latency {
+            report.key_metrics.insert(format!("avg.{}", name), *value);
+        }
+        if !latency.is_empty() {
+            report.data.insert("turn_latency".to_string(), serde_json::to_value(&latency)?);
+        }
+
         Ok(report)
     }
 
@@ -702,6 +732,20 @@ impl DataCollector {
         
         Ok(())
     }
+
+    /// 计算指定前缀的时间序列平均值
+    pub async fn metric_averages(&self, prefix: &str) -> HashMap<String, f64> {
+        let store = self.metrics_store.read().await;
+
+        store.time_series
+            .iter()
+            .filter(|(name, series)| name.starts_with(prefix) && !series.data_points.is_empty())
+            .map(|(name, series)| {
+                let sum: f64 = series.data_points.iter().map(|p| p.value).sum();
+                (name.clone(), sum / series.data_points.len() as f64)
+            })
+            .collect()
+    }
 }
 
 impl Analyzer {
@@ -0,0 +1,456 @@
+//! 智能体基准测试
+//!
+//! 读取录制好的任务夹具（修改某个文件、修复某个测试等），在临时目录中让模型修改文件并运行检查命令，
+//! 直到检查通过或回合用尽；统计成功与否、回合数、Token 数和耗时，生成跨模型/配置的对比表。
+//! 回放模式使用夹具中录制的模型回复，不访问网络，适合在 CI 中检查回归
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::{ClaudeError, Result};
+use crate::network::batch::BatchCollector;
+use crate::network::{ClaudeRequest, ClaudeResponse, Message, NetworkManager};
+
+/// 反馈给模型的检查输出保留的末尾行数
+const CHECK_OUTPUT_TAIL: usize = 60;
+
+/// 在线模型使用的系统提示，说明修改文件的格式
+const SYSTEM_PROMPT: &str = "You are being evaluated on a coding task inside a small project. \
+To change a file, reply with its complete new content in a fenced block whose info string is \
+`file:<relative path>`, for example:\n```file:src/lib.rs\n<entire file>\n```\n\
+Only files in such blocks are written. After each reply the project's check command runs; \
+if it fails you will see its output and can try again.";
+
+/// 一个基准任务夹具
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchTask {
+    pub name: String,
+    /// 交给模型的任务描述
+    pub prompt: String,
+    /// 任务开始时的项目文件（相对路径 -> 内容）
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    /// 判定成功的命令，在项目目录中用 `sh -c` 执行，退出码为 0 即成功
+    pub check: String,
+    /// 覆盖配置中的最大回合数
+    #[serde(default)]
+    pub max_turns: Option<u32>,
+    /// 录制的模型回复，按回合回放
+    #[serde(default)]
+    pub replay: Vec<String>,
+}
+
+impl BenchTask {
+    /// 读取 YAML 或 JSON 格式的夹具
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_yaml::from_str(&content)
+            .map_err(|e| ClaudeError::config_error(format!("Invalid bench task {}: {}", path.display(), e)))
+    }
+}
+
+/// 读取一个夹具文件，或目录下所有 `.yaml`/`.yml`/`.json` 夹具（按文件名排序）
+pub fn load_suite(path: &Path) -> Result<Vec<BenchTask>> {
+    if path.is_file() {
+        return Ok(vec![BenchTask::load(path)?]);
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)
+        .map_err(|e| ClaudeError::fs_error(format!("Cannot read bench suite {}: {}", path.display(), e)))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "yaml" || ext == "yml" || ext == "json"))
+        .collect();
+    files.sort();
+    files.iter().map(|file| BenchTask::load(file)).collect()
+}
+
+/// 一次模型回复
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+/// 基准测试使用的模型来源
+#[async_trait]
+pub trait BenchProvider: Send + Sync {
+    /// 对比表中的列名
+    fn label(&self) -> String;
+
+    /// 第 `turn` 回合（从 0 开始）的回复
+    async fn complete(&self, task: &BenchTask, turn: usize, messages: &[Message]) -> Result<Completion>;
+}
+
+/// 回放夹具中录制的回复，Token 数按文本估算
+pub struct ReplayProvider;
+
+#[async_trait]
+impl BenchProvider for ReplayProvider {
+    fn label(&self) -> String {
+        "replay".to_string()
+    }
+
+    async fn complete(&self, task: &BenchTask, turn: usize, messages: &[Message]) -> Result<Completion> {
+        let text = task.replay.get(turn).cloned().ok_or_else(|| {
+            ClaudeError::General(format!("No recorded reply for turn {} of '{}'", turn + 1, task.name))
+        })?;
+        let input_tokens = crate::tokens::estimate_tokens(SYSTEM_PROMPT)
+            + messages.iter().map(|m| crate::tokens::estimate_tokens(&m.content)).sum::<u32>();
+        Ok(Completion { output_tokens: crate::tokens::estimate_tokens(&text), input_tokens, text })
+    }
+}
+
+/// 调用在线模型
+pub struct LiveProvider {
+    client: Arc<NetworkManager>,
+    model: String,
+}
+
+impl LiveProvider {
+    pub fn new(client: Arc<NetworkManager>, model: impl Into<String>) -> Self {
+        Self { client, model: model.into() }
+    }
+}
+
+#[async_trait]
+impl BenchProvider for LiveProvider {
+    fn label(&self) -> String {
+        self.model.clone()
+    }
+
+    async fn complete(&self, _task: &BenchTask, _turn: usize, messages: &[Message]) -> Result<Completion> {
+        let response = self.client.send_claude_request(live_request(&self.model, messages)).await?;
+        Ok(Completion::from(response))
+    }
+}
+
+/// 通过 Message Batches API 调用在线模型：并发运行的任务在同一回合的请求合并成一个批次
+pub struct BatchProvider {
+    collector: Arc<BatchCollector>,
+    model: String,
+}
+
+impl BatchProvider {
+    pub fn new(collector: Arc<BatchCollector>, model: impl Into<String>) -> Self {
+        Self { collector, model: model.into() }
+    }
+}
+
+#[async_trait]
+impl BenchProvider for BatchProvider {
+    fn label(&self) -> String {
+        format!("{} (batch)", self.model)
+    }
+
+    async fn complete(&self, _task: &BenchTask, _turn: usize, messages: &[Message]) -> Result<Completion> {
+        let response = self.collector.send(live_request(&self.model, messages)).await?;
+        Ok(Completion::from(response))
+    }
+}
+
+fn live_request(model: &str, messages: &[Message]) -> ClaudeRequest {
+    ClaudeRequest {
+        model: model.to_string(),
+        messages: messages.to_vec(),
+        max_tokens: 8192,
+        stream: Some(false),
+        tools: None,
+        temperature: Some(0.0),
+        top_p: None,
+        system: Some(SYSTEM_PROMPT.to_string()),
+    }
+}
+
+impl From<ClaudeResponse> for Completion {
+    fn from(response: ClaudeResponse) -> Self {
+        let (input_tokens, output_tokens) = response.usage.map_or((0, 0), |u| (u.input_tokens, u.output_tokens));
+        Completion { text: response.content, input_tokens, output_tokens }
+    }
+}
+
+/// 运行参数
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// 任务未指定时的最大回合数
+    pub max_turns: u32,
+    /// 检查命令的超时
+    pub check_timeout: Duration,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self { max_turns: 5, check_timeout: Duration::from_secs(120) }
+    }
+}
+
+/// 单个任务在一种配置下的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskResult {
+    pub task: String,
+    pub config: String,
+    pub success: bool,
+    pub turns: u32,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub wall_time_ms: u64,
+    /// 模型调用或检查命令本身出错时的信息
+    pub error: Option<String>,
+}
+
+/// 在临时目录中运行一个任务
+pub async fn run_task(task: &BenchTask, provider: &dyn BenchProvider, config: &BenchConfig) -> TaskResult {
+    let started = Instant::now();
+    let mut result = TaskResult {
+        task: task.name.clone(),
+        config: provider.label(),
+        success: false,
+        turns: 0,
+        input_tokens: 0,
+        output_tokens: 0,
+        wall_time_ms: 0,
+        error: None,
+    };
+    let workspace = std::env::temp_dir().join(format!("claude-bench-{}", uuid::Uuid::new_v4().simple()));
+    if let Err(e) = run_turns(task, provider, config, &workspace, &mut result).await {
+        result.error = Some(e.to_string());
+    }
+    let _ = std::fs::remove_dir_all(&workspace);
+    result.wall_time_ms = started.elapsed().as_millis() as u64;
+    result
+}
+
+async fn run_turns(
+    task: &BenchTask,
+    provider: &dyn BenchProvider,
+    config: &BenchConfig,
+    workspace: &Path,
+    result: &mut TaskResult,
+) -> Result<()> {
+    std::fs::create_dir_all(workspace)?;
+    for (path, content) in &task.files {
+        let target = workspace.join(checked_relative(path)?);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(target, content)?;
+    }
+
+    let mut messages = vec![Message { role: "user".to_string(), content: task.prompt.clone().into() }];
+    for turn in 0..task.max_turns.unwrap_or(config.max_turns) {
+        let completion = provider.complete(task, turn as usize, &messages).await?;
+        result.turns = turn + 1;
+        result.input_tokens += completion.input_tokens;
+        result.output_tokens += completion.output_tokens;
+
+        let written = apply_file_blocks(workspace, &completion.text)?;
+        messages.push(Message { role: "assistant".to_string(), content: completion.text.into() });
+
+        let (passed, output) = run_check(workspace, &task.check, config.check_timeout).await?;
+        if passed {
+            result.success = true;
+            return Ok(());
+        }
+        let tail: Vec<&str> = output.lines().rev().take(CHECK_OUTPUT_TAIL).collect();
+        let feedback = format!(
+            "Updated {} file(s). `{}` still fails:\n```\n{}\n```\nFix the remaining problem.",
+            written.len(),
+            task.check,
+            tail.into_iter().rev().collect::<Vec<_>>().join("\n")
+        );
+        messages.push(Message { role: "user".to_string(), content: feedback.into() });
+    }
+    Ok(())
+}
+
+/// 拒绝绝对路径和跳出项目目录的路径
+fn checked_relative(path: &str) -> Result<&Path> {
+    let relative = Path::new(path);
+    if relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        Ok(relative)
+    } else {
+        Err(ClaudeError::validation_error("path", format!("Path must stay inside the project: {}", path)))
+    }
+}
+
+/// 把回复中 `file:<path>` 代码块写入项目，返回写入的路径
+pub fn apply_file_blocks(root: &Path, reply: &str) -> Result<Vec<String>> {
+    let mut written = Vec::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    for line in reply.lines() {
+        match current.as_mut() {
+            None => {
+                let path = line
+                    .trim_start()
+                    .strip_prefix("```")
+                    .and_then(|info| info.split_whitespace().find_map(|word| word.strip_prefix("file:")));
+                if let Some(path) = path {
+                    current = Some((path.to_string(), Vec::new()));
+                }
+            }
+            Some(_) if line.trim_start().starts_with("```") => {
+                if let Some((path, lines)) = current.take() {
+                    let target = root.join(checked_relative(&path)?);
+                    if let Some(parent) = target.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    let mut content = lines.join("\n");
+                    content.push('\n');
+                    std::fs::write(target, content)?;
+                    written.push(path);
+                }
+            }
+            Some((_, lines)) => lines.push(line),
+        }
+    }
+    Ok(written)
+}
+
+/// 运行检查命令，返回是否通过和合并后的输出；超时视为失败
+async fn run_check(dir: &Path, command: &str, timeout: Duration) -> Result<(bool, String)> {
+    let child = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(timeout, child).await {
+        Ok(output) => {
+            let output = output?;
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            Ok((output.status.success(), text))
+        }
+        Err(_) => Ok((false, format!("Check timed out after {}s", timeout.as_secs()))),
+    }
+}
+
+/// 任务为行、配置为列的对比表，末尾附各配置的汇总
+pub fn comparison_table(results: &[TaskResult]) -> String {
+    let mut tasks: Vec<&str> = Vec::new();
+    let mut configs: Vec<&str> = Vec::new();
+    for result in results {
+        if !tasks.contains(&result.task.as_str()) {
+            tasks.push(&result.task);
+        }
+        if !configs.contains(&result.config.as_str()) {
+            configs.push(&result.config);
+        }
+    }
+    let find = |task: &str, config: &str| results.iter().find(|r| r.task == task && r.config == config);
+    let cell = |result: Option<&TaskResult>| match result {
+        Some(r) => format!(
+            "{} {}t {}tok {:.1}s",
+            if r.success { "pass" } else if r.error.is_some() { "ERR " } else { "FAIL" },
+            r.turns,
+            r.input_tokens + r.output_tokens,
+            r.wall_time_ms as f64 / 1000.0
+        ),
+        None => "-".to_string(),
+    };
+
+    let mut rows: Vec<Vec<String>> = vec![std::iter::once("Task".to_string()).chain(configs.iter().map(|c| c.to_string())).collect()];
+    for task in &tasks {
+        rows.push(std::iter::once(task.to_string()).chain(configs.iter().map(|config| cell(find(task, config)))).collect());
+    }
+    let summary = |config: &str| {
+        let runs: Vec<&TaskResult> = results.iter().filter(|r| r.config == config).collect();
+        let passed = runs.iter().filter(|r| r.success).count();
+        let turns: u32 = runs.iter().map(|r| r.turns).sum();
+        let tokens: u32 = runs.iter().map(|r| r.input_tokens + r.output_tokens).sum();
+        (passed, runs.len(), turns as f64 / runs.len().max(1) as f64, tokens, runs.iter().map(|r| r.wall_time_ms).sum::<u64>())
+    };
+    rows.push(
+        std::iter::once("Passed".to_string())
+            .chain(configs.iter().map(|config| {
+                let (passed, total, ..) = summary(config);
+                format!("{}/{} ({:.0}%)", passed, total, passed as f64 * 100.0 / total.max(1) as f64)
+            }))
+            .collect(),
+    );
+    rows.push(
+        std::iter::once("Avg turns / tokens / time".to_string())
+            .chain(configs.iter().map(|config| {
+                let (_, _, avg_turns, tokens, time) = summary(config);
+                format!("{:.1} / {} / {:.1}s", avg_turns, tokens, time as f64 / 1000.0)
+            }))
+            .collect(),
+    );
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+    let format_row = |row: &Vec<String>| {
+        row.iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+    };
+    let separator = widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("-+-");
+
+    let mut table = String::new();
+    for (index, row) in rows.iter().enumerate() {
+        if index == 1 || index == tasks.len() + 1 {
+            table.push_str(&separator);
+            table.push('\n');
+        }
+        table.push_str(&format_row(row));
+        table.push('\n');
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> BenchTask {
+        serde_yaml::from_str(
+            r#"
+name: fix-greeting
+prompt: "greet.txt should say hello"
+files:
+  greet.txt: "helo\n"
+check: grep -qx hello greet.txt
+max_turns: 3
+replay:
+  - "Fixed:\n```file:greet.txt\nhallo\n```"
+  - "```file:greet.txt\nhello\n```"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replay_run_retries_until_check_passes() {
+        let result = run_task(&task(), &ReplayProvider, &BenchConfig::default()).await;
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.turns, 2);
+        assert!(result.output_tokens > 0);
+
+        let mut short = task();
+        short.replay.truncate(1);
+        let result = run_task(&short, &ReplayProvider, &BenchConfig::default()).await;
+        assert!(!result.success);
+        assert!(result.error.as_deref().unwrap().contains("No recorded reply for turn 2"));
+
+        let table = comparison_table(&[result]);
+        assert!(table.lines().any(|line| line.starts_with("fix-greeting") && line.contains("| ERR  1t")));
+        assert!(table.contains("Passed"));
+    }
+
+    #[test]
+    fn test_file_blocks_stay_inside_project() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let written = apply_file_blocks(temp_dir.path(), "```rust file:src/a.rs\nfn a() {}\n```\n```\nignored\n```").unwrap();
+        assert_eq!(written, vec!["src/a.rs"]);
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("src/a.rs")).unwrap(), "fn a() {}\n");
+        assert!(apply_file_blocks(temp_dir.path(), "```file:../escape\nx\n```").is_err());
+    }
+}
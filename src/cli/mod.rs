@@ -53,6 +53,10 @@ pub struct Cli {
     #[arg(long, value_enum)]
     pub input_format: Option<InputFormat>,
 
+    /// Report long-held locks, lock order violations and deadlocks (diagnostics printed on exit)
+    #[arg(long, global = true)]
+    pub debug_locks: bool,
+
     /// [DEPRECATED. Use --debug instead] Enable MCP debug mode (shows MCP server errors)
     #[arg(long)]
     pub mcp_debug: bool,
@@ -89,10 +93,18 @@ pub struct Cli {
     #[arg(long)]
     pub model: Option<String>,
 
-    /// Enable automatic fallback to specified model when default model is overloaded (only works with --print)
+    /// Switch to this model when the default model stays overloaded after retries (agent runs: --plan, /plan, /bg)
     #[arg(long)]
     pub fallback_model: Option<String>,
 
+    /// Plan first: investigate with read-only tools and wait for approval of the plan before making changes
+    #[arg(long)]
+    pub plan: bool,
+
+    /// Stop the agent gracefully after this many model turns
+    #[arg(long = "max-turns")]
+    pub max_turns: Option<u32>,
+
     /// Additional directories to allow tool access to
     #[arg(long = "add-dir", global = true)]
     pub add_dirs: Vec<String>,
@@ -177,6 +189,9 @@ pub enum Commands {
         /// 是否启用工具调用
         #[arg(long)]
         tools: bool,
+        /// 把回复流式写入文件（先写临时文件，校验通过后替换目标）
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
     },
     /// 初始化项目分析
     Init {
@@ -191,6 +206,12 @@ pub enum Commands {
         target: Option<String>,
         /// 审查类型
         review_type: Option<String>,
+        /// Save the review with its diff and charts under the reports directory
+        #[arg(long)]
+        report: bool,
+        /// Email the saved report using the configured SMTP settings (implies --report)
+        #[arg(long)]
+        email: bool,
     },
     /// 上下文压缩
     Compact {
@@ -254,6 +275,9 @@ pub enum Commands {
         /// 列出可用模型
         #[arg(short, long)]
         list: bool,
+        /// 从配置的远程清单更新模型能力表
+        #[arg(long)]
+        update: bool,
     },
 
     /// 恢复对话
@@ -332,6 +356,283 @@ pub enum Commands {
     /// 启动终端UI界面 (Terminal User Interface)
     Tui,
 
+    /// Manage trusted project folders
+    Trust {
+        #[command(subcommand)]
+        action: TrustCommands,
+    },
+
+    /// Manage saved sessions
+    Sessions {
+        #[command(subcommand)]
+        action: SessionsCommands,
+    },
+
+    /// Inspect registered tools
+    Tools {
+        #[command(subcommand)]
+        action: ToolsCommands,
+    },
+
+    /// Show usage statistics recorded during sessions
+    Stats {
+        #[command(subcommand)]
+        action: StatsCommands,
+    },
+
+    /// Manage saved code snippets
+    Snippets {
+        #[command(subcommand)]
+        action: SnippetsCommands,
+    },
+
+    /// Manage users of the shared web UI (`claude ui` with web_auth.mode = "token" or "proxy")
+    WebUsers {
+        #[command(subcommand)]
+        action: WebUsersCommands,
+    },
+
+    /// Inspect and test the webhooks configured under `webhooks`
+    Webhooks {
+        #[command(subcommand)]
+        action: WebhooksCommands,
+    },
+
+    /// List, generate and email long-form reports saved under `reports.dir`
+    Reports {
+        #[command(subcommand)]
+        action: ReportsCommands,
+    },
+
+    /// Bundle config, memory, sessions and MCP servers into a .tar.zst file
+    ExportState {
+        /// Output bundle path
+        file: std::path::PathBuf,
+
+        /// Encrypt API keys and MCP credentials with the passphrase in CLAUDE_STATE_PASSPHRASE instead of leaving them out
+        #[arg(long)]
+        encrypt_secrets: bool,
+
+        /// Leave conversation history out of the bundle
+        #[arg(long)]
+        no_sessions: bool,
+    },
+
+    /// Restore a bundle created by export-state
+    ImportState {
+        /// Bundle path
+        file: std::path::PathBuf,
+
+        /// Overwrite sessions that already exist locally
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Import session history from the official Claude Code CLI (~/.claude/projects)
+    ImportSessions {
+        /// Projects directory or a single .jsonl session file to import
+        #[arg(long)]
+        path: Option<std::path::PathBuf>,
+
+        /// Overwrite sessions that were already imported
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Automation that runs against a GitHub repository
+    Bot {
+        #[command(subcommand)]
+        command: BotCommand,
+    },
+
+    /// Group duplicate open issues and suggest labels and priorities
+    Triage {
+        /// Repository as owner/name
+        #[arg(long)]
+        repo: String,
+
+        /// Maximum number of open issues to triage, newest first
+        #[arg(long, default_value = "50")]
+        limit: usize,
+
+        /// Similarity (0-1) at which two issues are treated as duplicates
+        #[arg(long, default_value = "0.35")]
+        threshold: f32,
+
+        /// Apply the suggestions through the GitHub API after confirming each issue
+        #[arg(long)]
+        apply: bool,
+
+        /// Submit the model requests through the Message Batches API (slower, half price)
+        #[arg(long)]
+        batch: bool,
+    },
+
+    /// Search and replace across the project, previewing every change before applying it
+    Replace {
+        /// Regular expression to search for (literal text with --literal)
+        #[arg(required_unless_present = "undo")]
+        pattern: Option<String>,
+
+        /// Replacement text; `$1` or `${name}` insert capture groups
+        #[arg(required_unless_present = "undo")]
+        replacement: Option<String>,
+
+        /// Treat the pattern and replacement as plain text
+        #[arg(long)]
+        literal: bool,
+
+        /// Match case-insensitively
+        #[arg(short = 'i', long)]
+        ignore_case: bool,
+
+        /// Only touch paths matching this glob (repeatable)
+        #[arg(long = "path")]
+        paths: Vec<String>,
+
+        /// Skip paths matching this glob (repeatable)
+        #[arg(long)]
+        exclude: Vec<String>,
+
+        /// Apply without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Only show the preview
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Undo the most recent replacement in this project
+        #[arg(long, conflicts_with_all = ["pattern", "replacement"])]
+        undo: bool,
+    },
+
+    /// Run recorded agent tasks and compare success, turns, tokens and time across models
+    Bench {
+        /// Task fixture (YAML or JSON) or a directory of fixtures
+        suite: std::path::PathBuf,
+
+        /// Model to benchmark (repeatable; defaults to the configured model)
+        #[arg(long)]
+        model: Vec<String>,
+
+        /// Replay the replies recorded in the fixtures instead of calling a model
+        #[arg(long)]
+        replay: bool,
+
+        /// Maximum turns per task unless the fixture sets its own
+        #[arg(long, default_value = "5")]
+        max_turns: u32,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Run tasks concurrently and send each turn through the Message Batches API (slower, half price)
+        #[arg(long, conflicts_with = "replay")]
+        batch: bool,
+    },
+
+    /// Remove old sessions, artifacts, caches and logs according to the retention policy
+    Gc {
+        /// Only report what would be freed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Generate code from a YAML spec, then build, test and repair until validation passes
+    Generate {
+        /// Spec file describing what to generate
+        #[arg(long)]
+        spec: std::path::PathBuf,
+
+        /// Maximum repair rounds after a failed validation (overrides the spec)
+        #[arg(long)]
+        max_repairs: Option<u32>,
+
+        /// Model to generate with
+        #[arg(short, long)]
+        model: Option<String>,
+    },
+
+    /// Score repository health: dead code, oversized files, TODOs, tests and dependencies
+    Health {
+        /// Repository root (defaults to the current directory)
+        path: Option<std::path::PathBuf>,
+
+        /// Write the report as markdown to this file
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+
+        /// Files longer than this many lines count as oversized
+        #[arg(long, default_value = "1000")]
+        max_file_lines: usize,
+
+        /// Skip the crates.io lookup for dependency staleness
+        #[arg(long)]
+        offline: bool,
+
+        /// Do not ask the model for recommendations
+        #[arg(long)]
+        no_ai: bool,
+
+        /// Save the report with a score chart under the reports directory
+        #[arg(long)]
+        report: bool,
+
+        /// Email the saved report using the configured SMTP settings (implies --report)
+        #[arg(long)]
+        email: bool,
+    },
+
+    /// Render a prompt template from .claude/prompts and send it to the model
+    RunPrompt {
+        /// Template name
+        name: String,
+
+        /// Template variable as key=value (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE")]
+        vars: Vec<String>,
+
+        /// Print the rendered prompt instead of sending it
+        #[arg(long)]
+        preview: bool,
+
+        /// Model to use (defaults to the template's model, then the configured default)
+        #[arg(short, long)]
+        model: Option<String>,
+    },
+
+    /// List and inspect prompt templates
+    Prompts {
+        #[command(subcommand)]
+        action: PromptsCommands,
+    },
+
+    /// Show or forget the failure lessons recorded for this repository
+    Lessons {
+        #[command(subcommand)]
+        action: LessonsCommands,
+    },
+
+    /// Inspect the per-turn workspace checkpoints recorded during interactive sessions
+    Checkpoint {
+        #[command(subcommand)]
+        action: CheckpointCommands,
+    },
+
+    /// List or roll back the checkpoints the agent records before each mutating tool call
+    Agent {
+        #[command(subcommand)]
+        action: AgentCommands,
+    },
+
+    /// Inspect, follow or cancel background agent jobs (start one with /bg in interactive mode)
+    Jobs {
+        #[command(subcommand)]
+        action: JobsCommands,
+    },
+
     #[cfg(feature = "web-server")]
     /// 启动 Web 服务器
     Serve {
@@ -363,15 +664,27 @@ pub enum McpCommands {
     Add {
         /// 服务器名称
         name: String,
-        /// 服务器命令
+        /// 服务器命令，sse、http 方式下为服务器地址
         command: String,
         /// 命令参数
         args: Vec<String>,
+        /// 连接方式
+        #[arg(short, long, value_enum, default_value = "stdio")]
+        transport: crate::config::McpTransport,
+        /// 请求远程服务器时附带的 HTTP 头，格式 "Name: value"，可重复
+        #[arg(short = 'H', long = "header")]
+        headers: Vec<String>,
+        /// 配置范围：local 只对自己、只在当前项目生效，project 写入可提交的 .mcp.json，user 在所有项目中生效
+        #[arg(short, long, value_enum, default_value = "local")]
+        scope: crate::mcp::scope::McpScope,
     },
     /// 移除 MCP 服务器
     Remove {
         /// 服务器名称
         name: String,
+        /// 配置范围，不指定时从唯一包含该服务器的范围中移除
+        #[arg(short, long, value_enum)]
+        scope: Option<crate::mcp::scope::McpScope>,
     },
     /// 列出 MCP 服务器
     List,
@@ -385,93 +698,471 @@ pub enum McpCommands {
         /// 服务器名称
         name: String,
     },
+    /// 列出 MCP 服务器提供的资源，可在提示中用 @<服务器>:<资源> 引用
+    Resources {
+        /// 服务器名称
+        name: String,
+    },
+    /// 作为 stdio MCP 服务器运行，向其他 MCP 客户端提供文件、搜索、bash 和 git 工具
+    Serve,
+    /// 清除对当前项目 .mcp.json 中服务器的批准和拒绝，下次使用时重新询问
+    ResetProjectChoices,
 }
 
-/// Git 子命令
-#[derive(Subcommand)]
-pub enum GitCommand {
-    /// 查看Git状态
-    Status,
-    /// 添加文件到暂存区
+/// 文件夹信任子命令
+#[derive(Debug, Subcommand)]
+pub enum TrustCommands {
+    /// 列出受信任的文件夹
+    List,
+    /// 信任文件夹（默认当前目录）
     Add {
-        /// 文件路径
-        files: Vec<String>,
+        /// 文件夹路径
+        path: Option<String>,
     },
-    /// 提交更改
-    Commit {
-        /// 提交消息
+    /// 撤销对文件夹的信任（默认当前目录）
+    Revoke {
+        /// 文件夹路径
+        path: Option<String>,
+    },
+}
+
+/// 会话子命令
+#[derive(Debug, Subcommand)]
+pub enum SessionsCommands {
+    /// 按 Git 分支分组列出会话
+    List {
+        /// 只列出与该分支关联的会话
+        #[arg(long)]
+        branch: Option<String>,
+    },
+    /// 生成脱敏后的会话总结报告，可贴进 PR 描述或团队聊天
+    Share {
+        /// 会话 ID（可以只写前缀）
+        id: String,
+
+        /// Report format: markdown or html
+        #[arg(long, default_value = "markdown")]
+        format: String,
+
+        /// Write the report to this file instead of stdout
         #[arg(short, long)]
-        message: String,
+        output: Option<std::path::PathBuf>,
     },
-    /// 查看提交历史
+    /// Print a session's event log: messages, tool calls, edits and setting changes
     Log {
-        /// 限制显示的提交数量
-        #[arg(short, long, default_value = "10")]
-        limit: u32,
+        /// Session ID (a unique prefix is enough)
+        id: String,
     },
-    /// 查看分支
-    Branch,
-    /// 创建新分支
-    Checkout {
-        /// 分支名称
-        branch: String,
-        /// 创建新分支
-        #[arg(short = 'b', long)]
-        create: bool,
+    /// Rebuild a session from its event log and print the transcript
+    Replay {
+        /// Session ID (a unique prefix is enough)
+        id: String,
+
+        /// Stop after this event number (see `sessions log`)
+        #[arg(long)]
+        until: Option<u64>,
     },
-    /// 查看差异
-    Diff {
-        /// 文件路径（可选）
-        file: Option<String>,
+}
+
+/// 工具子命令
+#[derive(Subcommand)]
+pub enum ToolsCommands {
+    /// Show the context cost of each tool definition and which ones a prompt would send
+    Costs {
+        /// Prompt to select tools for (shows the selection policy's decision)
+        #[arg(long)]
+        prompt: Option<String>,
+
+        /// Force-include this tool (repeatable)
+        #[arg(long)]
+        include: Vec<String>,
     },
 }
 
-/// 语法高亮子命令
-#[derive(Subcommand, Debug)]
-pub enum HighlightCommand {
-    /// 高亮代码文件
-    File {
-        /// 文件路径
-        path: String,
-        /// 指定语言（可选）
-        #[arg(short, long)]
+/// 统计子命令
+#[derive(Subcommand)]
+pub enum StatsCommands {
+    /// Per-tool call counts, durations, failure rates and output size for a session
+    Tools {
+        /// Session to report on (defaults to the most recent session that ran tools)
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Print the summary as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// 代码片段子命令
+#[derive(Subcommand)]
+pub enum SnippetsCommands {
+    /// List snippets, optionally filtered by name, language or description
+    List {
+        query: Option<String>,
+    },
+    /// Print a snippet
+    Show {
+        name: String,
+    },
+    /// Save a file (or stdin) as a snippet
+    Save {
+        name: String,
+
+        /// Read the snippet from this file instead of stdin
+        #[arg(long)]
+        file: Option<std::path::PathBuf>,
+
+        /// Language of the snippet (defaults to the file extension)
+        #[arg(long)]
         language: Option<String>,
+
+        /// Short description shown in listings
+        #[arg(long)]
+        description: Option<String>,
+
+        /// Save for all projects instead of only this one
+        #[arg(long)]
+        user: bool,
     },
-    /// 高亮代码片段
-    Code {
-        /// 代码内容
-        code: String,
-        /// 语言
-        #[arg(short, long)]
-        language: String,
+    /// Insert a snippet into a file
+    Insert {
+        name: String,
+
+        file: std::path::PathBuf,
+
+        /// Insert after this many lines (0 for the top); appends when omitted
+        #[arg(long)]
+        line: Option<usize>,
+    },
+    /// Delete a snippet
+    Delete {
+        name: String,
+
+        /// Delete the user-level snippet instead of the project one
+        #[arg(long)]
+        user: bool,
     },
-    /// 列出支持的语言
-    Languages,
 }
 
-/// 进程管理子命令
-#[derive(Subcommand, Debug)]
-pub enum ProcessCommand {
-    /// 列出所有进程
+/// Web 用户子命令
+#[derive(Subcommand)]
+pub enum WebUsersCommands {
+    /// List web users with their profile and spend
     List,
-    /// 启动新进程
-    Start {
-        /// 进程名称
+    /// Add a user and print their API token
+    Add {
         name: String,
-        /// 执行命令
-        command: String,
-        /// 命令参数
-        args: Vec<String>,
-        /// 工作目录
-        #[arg(short, long)]
-        workdir: Option<String>,
-        /// 捕获输出
-        #[arg(short, long)]
-        capture: bool,
+
+        /// Permission profile: read-only, standard or admin
+        #[arg(long, default_value = "standard")]
+        profile: crate::database::users::PermissionProfile,
+
+        /// Spending limit in USD
+        #[arg(long)]
+        budget: Option<f64>,
     },
-    /// 停止进程
-    Stop {
-        /// 进程ID或名称
+    /// Issue a new token for a user, revoking the old one
+    Rotate {
+        name: String,
+    },
+    /// Remove a user
+    Remove {
+        name: String,
+    },
+}
+
+/// Webhook 子命令
+#[derive(Subcommand)]
+pub enum WebhooksCommands {
+    /// List configured webhooks and the events they subscribe to
+    List,
+    /// Send a signed test event to a webhook and wait for the result
+    Test {
+        name: String,
+    },
+    /// Show recent delivery attempts
+    Log {
+        /// Number of attempts to show
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+}
+
+/// 报告子命令
+#[derive(Subcommand)]
+pub enum ReportsCommands {
+    /// List saved reports, newest first
+    List,
+    /// Email a saved report (.md or .html path)
+    Email {
+        path: std::path::PathBuf,
+        /// Recipients; defaults to reports.smtp.to
+        #[arg(long)]
+        to: Vec<String>,
+    },
+    /// Build a changelog report with per-day charts from the git history
+    Changelog {
+        /// Starting revision; the report covers <SINCE>..HEAD
+        since: String,
+        /// Email the report after saving it
+        #[arg(long)]
+        email: bool,
+    },
+}
+
+/// 提示模板子命令
+#[derive(Subcommand)]
+pub enum PromptsCommands {
+    /// List project and user prompt templates
+    List,
+    /// Show a template's variables and body
+    Show {
+        name: String,
+    },
+}
+
+/// 失败教训子命令
+#[derive(Subcommand)]
+pub enum LessonsCommands {
+    /// List lessons, most frequent first
+    List,
+    /// Forget one lesson by id
+    Forget {
+        id: String,
+    },
+    /// Forget every lesson for this repository
+    Clear,
+}
+
+/// 检查点子命令
+#[derive(Subcommand)]
+pub enum CheckpointCommands {
+    /// List recorded checkpoints
+    List {
+        /// Only show this session's checkpoints
+        #[arg(long)]
+        session: Option<String>,
+    },
+    /// Show everything that changed on disk between two checkpoints
+    Diff {
+        /// Start point: a turn number, <session>:<turn>, or any git revision
+        from: String,
+        /// End point, in the same forms
+        to: String,
+        /// Session that bare turn numbers refer to (defaults to the most recent one)
+        #[arg(long)]
+        session: Option<String>,
+        /// Only print the per-file summary
+        #[arg(long)]
+        stat: bool,
+    },
+}
+
+/// Agent 子命令
+#[derive(Subcommand)]
+pub enum AgentCommands {
+    /// List the checkpoints recorded before mutating tool calls
+    Checkpoints {
+        /// Only show this agent session's checkpoints
+        #[arg(long)]
+        session: Option<String>,
+    },
+    /// Restore the files touched since a checkpoint and truncate the conversation back to it
+    Rollback {
+        /// Checkpoint ID (a unique prefix is enough)
+        checkpoint: String,
+    },
+}
+
+/// 后台任务子命令
+#[derive(Subcommand)]
+pub enum JobsCommands {
+    /// List background jobs
+    List,
+    /// Show a job's status and the end of its output
+    Status {
+        /// Job ID (a unique prefix is enough)
+        id: String,
+    },
+    /// Follow a job's output until it finishes (Ctrl-C detaches)
+    Attach {
+        /// Job ID (a unique prefix is enough)
+        id: String,
+    },
+    /// Cancel a running job
+    Cancel {
+        /// Job ID (a unique prefix is enough)
+        id: String,
+    },
+}
+
+/// 机器人子命令
+#[derive(Subcommand)]
+pub enum BotCommand {
+    /// Poll a repository for open pull requests and review each new head commit
+    Review {
+        /// Repository as owner/name
+        #[arg(long)]
+        repo: String,
+
+        /// Polling interval, e.g. 30s, 15m or 1h
+        #[arg(long, default_value = "15m", value_parser = parse_interval)]
+        interval: std::time::Duration,
+
+        /// Review pending pull requests once and exit
+        #[arg(long)]
+        once: bool,
+
+        /// Print reviews instead of posting them (nothing is recorded as reviewed)
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also review draft pull requests
+        #[arg(long)]
+        include_drafts: bool,
+
+        /// Submit each round of reviews through the Message Batches API (slower, half price)
+        #[arg(long)]
+        batch: bool,
+    },
+}
+
+/// 解析 `30s`、`15m`、`1h` 形式的时间间隔，纯数字按秒计
+fn parse_interval(value: &str) -> std::result::Result<std::time::Duration, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid interval '{}'", value))?;
+    let seconds = match unit {
+        "" | "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        _ => return Err(format!("invalid interval unit '{}', expected s, m or h", unit)),
+    };
+    if seconds == 0 {
+        return Err("interval must be greater than zero".to_string());
+    }
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+/// Git 子命令
+#[derive(Subcommand)]
+pub enum GitCommand {
+    /// 查看Git状态
+    Status,
+    /// 添加文件到暂存区
+    Add {
+        /// 文件路径
+        files: Vec<String>,
+    },
+    /// 提交更改
+    Commit {
+        /// 提交消息
+        #[arg(short, long)]
+        message: String,
+    },
+    /// 查看提交历史
+    Log {
+        /// 限制显示的提交数量
+        #[arg(short, long, default_value = "10")]
+        limit: u32,
+    },
+    /// 查看分支
+    Branch,
+    /// 创建新分支
+    Checkout {
+        /// 分支名称
+        branch: String,
+        /// 创建新分支
+        #[arg(short = 'b', long)]
+        create: bool,
+    },
+    /// 查看差异
+    Diff {
+        /// 文件路径（可选）
+        file: Option<String>,
+    },
+    /// 逐块解决合并冲突
+    Resolve {
+        /// 解决后运行的构建/测试命令（默认按项目类型推断）
+        #[arg(long)]
+        verify: Option<String>,
+        /// 不运行构建/测试验证
+        #[arg(long)]
+        no_verify: bool,
+    },
+    /// 自动二分定位首个让测试失败的提交，并请模型分析根因
+    BisectAssist {
+        /// 测试命令：退出码 0 为好，125 跳过，其他 1-127 为坏
+        #[arg(long)]
+        test: String,
+        /// 已知正常的提交（默认为坏提交之前最近的标签）
+        #[arg(long)]
+        good: Option<String>,
+        /// 已知有问题的提交
+        #[arg(long, default_value = "HEAD")]
+        bad: String,
+        /// 单次测试的超时（秒），超时的提交会被跳过
+        #[arg(long, default_value = "600")]
+        timeout: u64,
+    },
+}
+
+/// 语法高亮子命令
+#[derive(Subcommand, Debug)]
+pub enum HighlightCommand {
+    /// 高亮代码文件
+    File {
+        /// 文件路径
+        path: String,
+        /// 指定语言（可选）
+        #[arg(short, long)]
+        language: Option<String>,
+        /// 主题（覆盖配置中的 ui.highlight_theme）
+        #[arg(short, long)]
+        theme: Option<String>,
+    },
+    /// 高亮代码片段
+    Code {
+        /// 代码内容
+        code: String,
+        /// 语言
+        #[arg(short, long)]
+        language: String,
+        /// 主题（覆盖配置中的 ui.highlight_theme）
+        #[arg(short, long)]
+        theme: Option<String>,
+    },
+    /// 列出支持的语言
+    Languages,
+    /// 列出内置主题和用户主题
+    Themes,
+}
+
+/// 进程管理子命令
+#[derive(Subcommand, Debug)]
+pub enum ProcessCommand {
+    /// 列出所有进程
+    List,
+    /// 启动新进程
+    Start {
+        /// 进程名称
+        name: String,
+        /// 执行命令
+        command: String,
+        /// 命令参数
+        args: Vec<String>,
+        /// 工作目录
+        #[arg(short, long)]
+        workdir: Option<String>,
+        /// 捕获输出
+        #[arg(short, long)]
+        capture: bool,
+    },
+    /// 停止进程
+    Stop {
+        /// 进程ID或名称
         process: String,
         /// 强制终止
         #[arg(short, long)]
@@ -700,96 +1391,1045 @@ impl Cli {
     pub fn parse_args() -> Self {
         Self::parse()
     }
+
+    /// 是否以 MCP 服务器模式运行；此时标准输出只用于协议消息
+    pub fn serves_mcp(&self) -> bool {
+        matches!(self.command, Some(Commands::Mcp { action: McpCommands::Serve }))
+    }
 }
 
 /// CLI 命令处理器
+///
+/// 除配置外的子系统都在命令第一次用到时才创建，`config get` 这类不访问 API 的命令不必为它们付出启动开销
 pub struct ClaudeCodeCli {
     /// 配置管理器
     config: Arc<crate::config::ConfigManager>,
     /// 网络客户端
-    client: Arc<crate::network::NetworkManager>,
+    client: std::sync::OnceLock<Arc<crate::network::NetworkManager>>,
     /// 文件管理器
-    file_manager: Arc<crate::fs::FileManager>,
+    file_manager: std::sync::OnceLock<Arc<crate::fs::FileManager>>,
     /// AI Agent
-    agent: Arc<crate::agent::Agent>,
+    agent: tokio::sync::OnceCell<Arc<crate::agent::Agent>>,
+    /// 工具注册表
+    tools: tokio::sync::OnceCell<Arc<crate::tools::ToolRegistry>>,
+    /// MCP 服务器管理器
+    mcp: std::sync::OnceLock<Arc<crate::mcp::McpManager>>,
+    /// 工具命令的执行位置（本机或开发容器）
+    execution_target: crate::process::devcontainer::SharedExecutionTarget,
+    /// `--max-turns` 指定的 Agent 回合上限
+    max_turns: std::sync::OnceLock<u32>,
+    /// `--fallback-model` 指定的回退模型
+    fallback_model: std::sync::OnceLock<String>,
+    /// `--allowed-tools` 和 `--disallowed-tools` 追加的权限规则
+    tool_rules: std::sync::OnceLock<(Vec<String>, Vec<String>)>,
+    /// `--dangerously-skip-permissions`：执行工具前不检查权限
+    skip_permissions: std::sync::OnceLock<bool>,
+    /// 本进程中的后台 Agent 任务
+    jobs: std::sync::OnceLock<Arc<crate::agent::JobManager>>,
 }
 
-impl ClaudeCodeCli {
-    /// 创建新的 CLI 处理器
-    pub async fn new() -> crate::error::Result<Self> {
-        let config = Arc::new(crate::config::ConfigManager::new()?);
-        let client = Arc::new(crate::network::NetworkManager::new());
-        let file_manager = Arc::new(crate::fs::FileManager::new());
-        let agent = Arc::new(crate::agent::Agent::new().await?);
+/// 拆分 `--allowed-tools` 这类以逗号或空格分隔的规则列表，括号内的分隔符保留，例如 `Bash(git status) Edit`
+fn split_tool_rules(values: &[String]) -> Vec<String> {
+    let mut rules = Vec::new();
+    for value in values {
+        let mut depth = 0usize;
+        let mut current = String::new();
+        for c in value.chars() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                ',' | ' ' if depth == 0 => {
+                    if !current.is_empty() {
+                        rules.push(std::mem::take(&mut current));
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            current.push(c);
+        }
+        if !current.is_empty() {
+            rules.push(current);
+        }
+    }
+    rules
+}
 
-        Ok(Self {
-            config,
-            client,
-            file_manager,
-            agent,
-        })
+/// 首次在交互模式下进入未受信任的目录时询问是否信任
+///
+/// 返回当前目录是否受信任。非交互模式（--print、子命令、非终端输入）不会询问
+pub fn ensure_folder_trust(cli: &Cli) -> crate::error::Result<bool> {
+    use crate::security::trust::TrustStore;
+    use std::io::{IsTerminal, Write};
+
+    let dir = std::env::current_dir()?;
+    let mut store = TrustStore::load_default()?;
+    if store.is_trusted(&dir) {
+        return Ok(true);
     }
 
-    /// 执行 CLI 命令
-    pub async fn execute(&self, cli: Cli) -> crate::error::Result<()> {
-        use tracing::{info, debug};
+    let interactive = !cli.print
+        && matches!(cli.command, None | Some(Commands::Interactive) | Some(Commands::Tui))
+        && std::io::stdin().is_terminal();
+    if !interactive {
+        return Ok(false);
+    }
 
-        if cli.debug || cli.mcp_debug {
-            debug!("Debug mode enabled");
-        }
+    println!("📁 {}", dir.display());
+    println!("Do you trust the files in this folder?");
+    println!("Trusted folders can load project hooks, commands and MCP servers.");
+    print!("Trust this folder? [y/N] ");
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        store.trust(&dir);
+        store.save()?;
+        println!("✅ Folder trusted. Use `claude trust revoke` to undo.");
+        Ok(true)
+    } else {
+        println!("🔒 Continuing without loading project hooks, commands or MCP servers.");
+        Ok(false)
+    }
+}
 
-        if cli.verbose {
-            debug!("Verbose mode enabled");
-        }
+/// 在终端中显示目录扫描进度，输出被重定向时不显示
+fn scan_progress() -> Option<crate::fs::scan::SharedProgressCallback> {
+    use std::io::IsTerminal;
 
-        // 处理全局添加目录
-        for dir in &cli.add_dirs {
-            self.add_directory(dir).await?;
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let ui = crate::ui::TerminalUI::new();
+    Some(Arc::new(move |progress| {
+        let _ = ui.show_scan_progress(&progress);
+        if progress.phase == crate::fs::scan::ScanPhase::Hashing && progress.processed == progress.total {
+            println!();
         }
+    }))
+}
 
-        // 处理权限设置
-        if cli.dangerously_skip_permissions {
-            info!("⚠️  Bypassing all permission checks");
-        }
+/// 打印配置热重载结果
+fn print_reload_outcome(outcome: &crate::config::reload::ReloadOutcome) {
+    if let Some(message) = outcome.system_message() {
+        println!("{}", message);
+    }
+    if let Some(warning) = outcome.warning() {
+        eprintln!("{}", warning);
+    }
+}
 
-        // 处理工具白名单/黑名单
-        if !cli.allowed_tools.is_empty() {
-            info!("✅ Allowed tools: {:?}", cli.allowed_tools);
-        }
-        if !cli.disallowed_tools.is_empty() {
-            info!("❌ Disallowed tools: {:?}", cli.disallowed_tools);
-        }
+/// 当前 Git 分支，不在仓库中或处于分离 HEAD 时返回 None
+async fn current_git_branch(git: &crate::git::GitManager) -> Option<String> {
+    git.get_current_branch().await.ok().filter(|branch| !branch.is_empty())
+}
 
-        // 处理 MCP 配置
-        if let Some(mcp_config) = &cli.mcp_config {
-            info!("🔧 Using MCP config: {}", mcp_config);
-        }
+/// 显示提示并读取一行输入（去掉首尾空白并转为小写）
+fn prompt_line(prompt: &str) -> crate::error::Result<String> {
+    Ok(read_answer(prompt)?.to_lowercase())
+}
 
-        // 处理系统提示追加
-        if let Some(system_prompt) = &cli.append_system_prompt {
-            info!("📝 Appending system prompt: {}", system_prompt);
-        }
+/// project 范围的服务器能否启动：目录须受信任；尚未决定或配置变化后在终端询问并记录，没有终端时不启动
+fn approve_project_server(
+    store: &mut crate::mcp::scope::ApprovalStore,
+    config: &crate::config::McpServerConfig,
+) -> crate::error::Result<bool> {
+    use crate::mcp::scope::PROJECT_FILE;
+    use std::io::IsTerminal;
+
+    if !crate::security::trust::is_current_dir_trusted() {
+        tracing::warn!("Ignoring MCP server '{}' from {} in an untrusted folder", config.name, PROJECT_FILE);
+        return Ok(false);
+    }
+    let project = std::env::current_dir()?.canonicalize()?;
+    if let Some(approved) = store.decision(&project, config) {
+        return Ok(approved);
+    }
+    if !std::io::stdin().is_terminal() {
+        tracing::warn!("Skipping MCP server '{}' from {} until it is approved in an interactive session", config.name, PROJECT_FILE);
+        return Ok(false);
+    }
+    println!("🔌 {} in this project configures MCP server '{}'", PROJECT_FILE, config.name);
+    println!("   {} {}", config.transport.as_str(), config.target());
+    let approved = matches!(prompt_line("Allow this server to start? [y/N] ")?.as_str(), "y" | "yes");
+    store.record(&project, config, approved);
+    Ok(approved)
+}
 
-        // 处理模型设置
-        if let Some(model) = &cli.model {
-            info!("🤖 Using model: {}", model);
+/// 显示提示并读取一行，去掉首尾空白
+fn read_answer(prompt: &str) -> crate::error::Result<String> {
+    use std::io::Write;
+
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+/// 按查询词列出片段
+fn print_snippets(library: &crate::snippets::SnippetLibrary, query: &str) {
+    let snippets = library.search(query);
+    if snippets.is_empty() {
+        println!("No snippets found. Save one with /snippet save <name> or `claude snippets save`.");
+        return;
+    }
+    println!("✂️  Snippets:");
+    for (store, snippet) in snippets {
+        println!(
+            "  {:<24} {:<8} {:<8} {}",
+            snippet.name,
+            snippet.language.as_deref().unwrap_or("-"),
+            store.scope(),
+            snippet.description.as_deref().unwrap_or("")
+        );
+    }
+}
+
+/// 处理交互模式中的 `/snippet` 命令
+fn handle_snippet_command(session: &mut crate::conversation::ConversationManager, args: &str) -> crate::error::Result<()> {
+    use crate::error::ClaudeError;
+    use crate::snippets::{code_blocks, insert_into_file, SnippetLibrary, SnippetScope};
+
+    let mut library = SnippetLibrary::open(&std::env::current_dir()?)?;
+    let mut words: Vec<&str> = args.split_whitespace().collect();
+    let user = words.contains(&"--user");
+    words.retain(|w| *w != "--user");
+    let scope = if user { SnippetScope::User } else { SnippetScope::Project };
+    let not_found = |name: &str| ClaudeError::validation_error("name", format!("No snippet named '{}'", name));
+
+    match words.as_slice() {
+        [] | ["list"] => print_snippets(&library, ""),
+        ["list", query @ ..] => print_snippets(&library, &query.join(" ")),
+        ["save", name, rest @ ..] => {
+            let reply = session
+                .get_current_conversation()
+                .and_then(|c| c.messages.iter().rev().find(|m| m.role == "assistant"))
+                .map(|m| m.content.to_string())
+                .ok_or_else(|| ClaudeError::validation_error("snippet", "No assistant reply to save from yet"))?;
+            let blocks = code_blocks(&reply);
+            let block = match (rest.first().map(|n| n.parse::<usize>()), blocks.len()) {
+                (_, 0) => return Err(ClaudeError::validation_error("snippet", "The last reply has no code blocks")),
+                (None, 1) => &blocks[0],
+                (None, count) => {
+                    return Err(ClaudeError::validation_error(
+                        "snippet",
+                        format!("The last reply has {} code blocks; pick one with /snippet save {} <1-{}>", count, name, count),
+                    ))
+                }
+                (Some(Ok(n)), count) if (1..=count).contains(&n) => &blocks[n - 1],
+                (Some(_), count) => {
+                    return Err(ClaudeError::validation_error("snippet", format!("Block number must be between 1 and {}", count)))
+                }
+            };
+            library.store_mut(scope).save(name, block.language.as_deref(), None, &block.code)?;
+            println!("✂️  Saved {} snippet '{}' ({} lines)", scope, name, block.code.lines().count());
         }
-        if let Some(fallback_model) = &cli.fallback_model {
-            info!("🔄 Fallback model: {}", fallback_model);
+        ["show", name] => {
+            let (store, snippet) = library.find(name).ok_or_else(|| not_found(name))?;
+            let fenced = format!("```{}\n{}\n```", snippet.language.as_deref().unwrap_or(""), store.content(snippet)?.trim_end());
+            println!("{}", crate::ui::markdown::render_markdown(&fenced));
         }
-
-        // 处理会话恢复
-        if cli.continue_conversation {
-            info!("🔄 Continuing most recent conversation");
-            return self.handle_continue_conversation().await;
+        ["insert", name, target] => {
+            let (store, snippet) = library.find(name).ok_or_else(|| not_found(name))?;
+            let (path, line) = match target.rsplit_once(':').map(|(path, line)| (path, line.parse::<usize>())) {
+                Some((path, Ok(line))) => (path, Some(line)),
+                _ => (*target, None),
+            };
+            insert_into_file(std::path::Path::new(path), &store.content(snippet)?, line)?;
+            session.record_file_edit(path, &format!("inserted snippet '{}'", name))?;
+            println!("✅ Inserted '{}' into {}", name, path);
         }
-        if let Some(session_id) = &cli.resume {
-            info!("📂 Resuming conversation: {}", session_id);
-            return self.handle_resume_conversation(session_id.clone()).await;
+        ["delete", name] => {
+            if library.store_mut(scope).remove(name)? {
+                println!("🗑️  Deleted {} snippet '{}'", scope, name);
+            } else {
+                println!("No {} snippet named '{}'", scope, name);
+            }
         }
+        _ => println!(
+            "Usage: /snippet [list [query] | save <name> [block] | show <name> | insert <name> <file>[:line] | delete <name>] [--user]"
+        ),
+    }
+    Ok(())
+}
 
-        // 处理 --print 模式
-        if cli.print {
+/// 抓取的网页最多放入上下文的字符数
+const MAX_FETCHED_CHARS: usize = 20_000;
+
+/// 展开输入中的 `@snippet:`、`@路径`、`@URL` 和 `@服务器:资源` 引用，返回发给模型的文本和引入的来源
+async fn expand_context(
+    input: &str,
+    mcp: &crate::mcp::McpManager,
+) -> crate::error::Result<(String, Vec<crate::conversation::ContextSource>)> {
+    use crate::conversation::provenance::{self, ContextSource, SourceKind};
+
+    let cwd = std::env::current_dir()?;
+    let mut sources = Vec::new();
+    let mut expanded = input.to_string();
+    if input.contains("@snippet:") {
+        let library = crate::snippets::SnippetLibrary::open(&cwd)?;
+        for (name, content) in library.referenced(input) {
+            sources.push(ContextSource::new(SourceKind::Snippet, name, &content));
+        }
+        expanded = library.expand_references(input);
+    }
+
+    for mention in provenance::mentions(input) {
+        let (kind, location, content) = if provenance::is_url(mention) {
+            match fetch_page(mention).await {
+                Ok(content) => (SourceKind::Url, mention.to_string(), content),
+                Err(e) => {
+                    println!("⚠️  Could not fetch {}: {}", mention, e);
+                    continue;
+                }
+            }
+        } else if let Some((server, resource)) =
+            crate::mcp::resources::parse_mention(mention).filter(|(server, _)| mcp.client(server).is_some())
+        {
+            match mcp.read_resource(server, resource).await {
+                Ok((uri, content)) => (SourceKind::McpResource, format!("{}:{}", server, uri), content),
+                Err(e) => {
+                    println!("⚠️  Could not read MCP resource {}: {}", mention, e);
+                    continue;
+                }
+            }
+        } else {
+            // 不是已有文件的 @ 文本（例如邮箱、用户名）保持原样
+            let path = cwd.join(mention);
+            let Ok(content) = std::fs::read_to_string(&path) else { continue };
+            let location = path.canonicalize().unwrap_or(path).display().to_string();
+            (SourceKind::File, location, content)
+        };
+        expanded.push_str(&format!("\n\n--- {} ---\n{}", mention, content.trim_end()));
+        sources.push(ContextSource::new(kind, location, &content));
+    }
+    Ok((expanded, sources))
+}
+
+async fn fetch_page(url: &str) -> crate::error::Result<String> {
+    let response = crate::network::shared_client()
+        .get(url)
+        .timeout(std::time::Duration::from_secs(20))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.text().await?.chars().take(MAX_FETCHED_CHARS).collect())
+}
+
+/// 读取用户级和项目级 CLAUDE.md，合并为系统提示
+fn memory_context(project_dir: &std::path::Path) -> (Option<String>, Vec<crate::conversation::ContextSource>) {
+    use crate::conversation::provenance::{memory_files, ContextSource, SourceKind};
+
+    let mut sections = Vec::new();
+    let mut sources = Vec::new();
+    for path in memory_files(project_dir) {
+        let Ok(content) = std::fs::read_to_string(&path) else { continue };
+        sources.push(ContextSource::new(SourceKind::Memory, path.display().to_string(), &content));
+        sections.push(content.trim().to_string());
+    }
+    let memory = Some(sections.join("\n\n")).filter(|memory| !memory.is_empty());
+    (memory, sources)
+}
+
+/// 当前仓库中与输入相关的失败教训：系统提示段落和教训 ID
+fn lesson_context(project_dir: &std::path::Path, query: &str, limit: usize) -> Option<(String, Vec<String>)> {
+    let store = match crate::conversation::lessons::LessonStore::for_repo(project_dir) {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::debug!("Failed to load lessons: {}", e);
+            return None;
+        }
+    };
+    let section = store.system_prompt_section(query, limit)?;
+    let ids = store.relevant(query, limit).into_iter().map(|lesson| lesson.id.clone()).collect();
+    Some((section, ids))
+}
+
+/// `/why-did-you-say-that <n>`：显示第 n 条回复（省略时为最近一条）生成时上下文中的来源
+fn print_provenance(session: &crate::conversation::ConversationManager, argument: &str) {
+    let number = if argument.is_empty() {
+        let messages = session.get_conversation_messages();
+        match messages.iter().rposition(|message| message.role == "assistant") {
+            Some(index) => index + 1,
+            None => {
+                println!("No replies yet in this session.");
+                return;
+            }
+        }
+    } else {
+        match argument.trim_start_matches('#').parse::<usize>() {
+            Ok(number) => number,
+            Err(_) => {
+                println!("Usage: /why-did-you-say-that <message number>");
+                return;
+            }
+        }
+    };
+    match session.message(number) {
+        Ok(message) if message.role != "assistant" => println!("Message #{} is a {} message, not a reply.", number, message.role),
+        Ok(message) => match &message.provenance {
+            Some(provenance) => println!("🔎 Context for reply #{}:\n{}", number, provenance.describe()),
+            None => println!("No provenance was recorded for reply #{}.", number),
+        },
+        Err(e) => println!("❌ {}", e),
+    }
+}
+
+/// 审查报告：模型的审查意见，加上工作区未提交的 diff 和每个文件的改动行数
+async fn review_report(target: &str, review_type: &str, review: &str) -> crate::error::Result<crate::reports::Report> {
+    let path = std::path::Path::new(target);
+    let diffs = if path.is_dir() {
+        crate::git::GitManager::new(path.to_path_buf()).get_diff(None).await?
+    } else {
+        crate::git::GitManager::new(std::env::current_dir()?).get_diff(Some(target)).await?
+    };
+
+    let mut report = crate::reports::Report::new("review", format!("Code review of {} ({})", target, review_type));
+    report.markdown(review);
+    if !diffs.is_empty() {
+        report.chart(crate::reports::Chart::bar(
+            "Lines changed per file",
+            "File",
+            "Lines",
+            diffs.iter().map(|d| (d.file_path.clone(), (d.lines_added + d.lines_deleted) as f64)).collect(),
+        ));
+        report.markdown("## Uncommitted changes");
+        for diff in &diffs {
+            report.diff(&diff.file_path, &diff.diff_content);
+        }
+    }
+    Ok(report)
+}
+
+/// 列出已连接 MCP 服务器提供的提示命令
+fn print_mcp_prompts(prompts: &[crate::mcp::McpPrompt]) {
+    if prompts.is_empty() {
+        println!("No MCP prompts. Connected MCP servers that provide prompts appear here as /mcp__<server>__<prompt>.");
+    }
+    for prompt in prompts {
+        let arguments: Vec<String> = prompt
+            .arguments
+            .iter()
+            .map(|a| if a.required { a.name.clone() } else { format!("[{}]", a.name) })
+            .collect();
+        println!("  /{:<30} {}  {}", prompt.command(), prompt.description.as_deref().unwrap_or(""), arguments.join(" "));
+    }
+}
+
+/// 列出当前会话中固定的消息
+fn print_prompt_templates(library: &crate::prompts::PromptLibrary) {
+    let (templates, broken) = library.list();
+    if templates.is_empty() && broken.is_empty() {
+        println!("No prompt templates. Add Markdown files to .claude/prompts or ~/.claude/prompts.");
+    }
+    for template in &templates {
+        let variables: Vec<String> = template
+            .variables
+            .iter()
+            .map(|v| if v.is_required() { v.name.clone() } else { format!("[{}]", v.name) })
+            .collect();
+        println!(
+            "  {:<20} {:<8} {}  {}",
+            template.name,
+            template.scope,
+            template.description.as_deref().unwrap_or(""),
+            variables.join(" ")
+        );
+    }
+    for (path, error) in broken {
+        println!("  ⚠️  {}: {}", path.display(), error);
+    }
+}
+
+fn print_prompt_template(template: &crate::prompts::PromptTemplate, defaults: &std::collections::HashMap<String, String>) {
+    println!("📝 {} ({}, {})", template.name, template.scope, template.path.display());
+    if let Some(description) = &template.description {
+        println!("   {}", description);
+    }
+    for variable in &template.variables {
+        let default = defaults.get(&variable.name).or(variable.default.as_ref());
+        let mut line = format!("   {} : {}", variable.name, variable.kind);
+        if !variable.values.is_empty() {
+            line.push_str(&format!(" ({})", variable.values.join("|")));
+        }
+        match default {
+            Some(default) => line.push_str(&format!(" = {}", default)),
+            None if variable.is_required() => line.push_str(" (required)"),
+            None => {}
+        }
+        if let Some(description) = &variable.description {
+            line.push_str(&format!("  - {}", description));
+        }
+        println!("{}", line);
+    }
+    println!("\n{}", template.body);
+}
+
+/// 记录会话的下一个检查点；不在 git 仓库里或记录失败都不影响对话
+async fn record_checkpoint(store: &crate::git::checkpoint::CheckpointStore, session: &str, label: &str) {
+    let result = match store.next_turn(session).await {
+        Ok(turn) => store.create(session, turn, label).await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::debug!("Skipping checkpoint: {}", e);
+    }
+}
+
+/// 显示 Agent 循环的进度：回复文本和工具调用
+fn print_agent_response(response: crate::agent::AgentResponse) {
+    use crate::agent::AgentResponse;
+
+    match response {
+        AgentResponse::TextContent { content, is_partial: false } => {
+            println!("{}", crate::ui::markdown::render_markdown(&content));
+        }
+        AgentResponse::ToolCall { tool_name, tool_input, .. } => {
+            let input = tool_input.to_string();
+            let input: String = input.chars().take(100).collect();
+            println!("🔧 {} {}", tool_name, input);
+        }
+        AgentResponse::ToolResult { result, is_error: true, .. } => {
+            println!("   ⚠️  {}", result.as_str().unwrap_or_default().lines().next().unwrap_or_default());
+        }
+        AgentResponse::Error { error, .. } => println!("❌ {}", error),
+        AgentResponse::StatusUpdate { status: crate::agent::AgentStatus::Exhausted(_), message: Some(message) } => {
+            println!("⏹️  {}", message);
+        }
+        _ => {}
+    }
+}
+
+/// 运行 Agent 循环，同时显示它发出的进度
+async fn drive_agent(
+    agent: &mut crate::agent::AgentLoop,
+    responses: &mut tokio::sync::mpsc::UnboundedReceiver<crate::agent::AgentResponse>,
+    messages: Vec<String>,
+) -> crate::error::Result<()> {
+    let run = agent.run(messages);
+    tokio::pin!(run);
+    let result = loop {
+        tokio::select! {
+            result = &mut run => break result,
+            Some(response) = responses.recv() => print_agent_response(response),
+        }
+    };
+    while let Ok(response) = responses.try_recv() {
+        print_agent_response(response);
+    }
+    result
+}
+
+fn print_pinned_messages(session: &crate::conversation::ConversationManager) {
+    let pinned = session.pinned_messages();
+    if pinned.is_empty() {
+        println!("No pinned messages. Use /pin <n> to pin one.");
+        return;
+    }
+    println!("📌 Pinned messages:");
+    for (number, message) in pinned {
+        let preview: String = message.content.lines().next().unwrap_or_default().chars().take(80).collect();
+        println!("  #{:<4} {:<9} {}", number, message.role, preview);
+    }
+}
+
+/// 处理 `/lang`：不带参数时显示当前语言和常用语言，`off` 恢复由模型决定
+/// `/set <参数> <值>`：覆盖当前会话的采样参数
+fn handle_set_command(session: &mut crate::conversation::ConversationManager, argument: &str) {
+    use crate::conversation::sampling::SETTING_KEYS;
+
+    let mut parts = argument.split_whitespace();
+    let (Some(key), Some(value), None) = (parts.next(), parts.next(), parts.next()) else {
+        println!("Usage: /set <{}> <value|default>", SETTING_KEYS.join("|"));
+        return;
+    };
+    match session.update_sampling(|sampling| sampling.set(key, value)) {
+        Ok(sampling) => match sampling.get(key) {
+            Some(value) => println!("🎛️  {} = {} for this session", key, value),
+            None => println!("🎛️  {} reset to the default", key),
+        },
+        Err(e) => println!("❌ {}", e),
+    }
+}
+
+/// `/settings show`：当前会话生效的设置
+fn print_session_settings(session: &crate::conversation::ConversationManager, model: &str) {
+    use crate::conversation::sampling::SETTING_KEYS;
+
+    println!("⚙️  Session settings");
+    println!("  model        {}", model);
+    println!(
+        "  language     {}",
+        session.language().map_or_else(|| "default".to_string(), |language| language.to_string())
+    );
+    for key in SETTING_KEYS {
+        match session.sampling().get(key) {
+            Some(value) => println!("  {:<12} {} (session override)", key, value),
+            None => println!("  {:<12} default", key),
+        }
+    }
+}
+
+fn handle_lang_command(session: &mut crate::conversation::ConversationManager, argument: &str) -> crate::error::Result<()> {
+    use crate::conversation::language::{known_languages, ResponseLanguage};
+
+    match argument {
+        "" => {
+            match session.language() {
+                Some(language) => println!("🌐 Response language: {} ({})", language.native_name(), language),
+                None => println!("🌐 Response language: not set (the model follows your language)"),
+            }
+            println!("Available:");
+            for (tag, name) in known_languages() {
+                println!("  {:<6} {}", tag, name);
+            }
+        }
+        "off" | "auto" | "none" => {
+            session.set_language(None)?;
+            println!("🌐 Response language cleared");
+        }
+        tag => match ResponseLanguage::parse(tag) {
+            Ok(language) => {
+                println!("🌐 Responses will be in {} ({})", language.native_name(), language);
+                session.set_language(Some(language))?;
+            }
+            Err(e) => println!("❌ {}", e),
+        },
+    }
+    Ok(())
+}
+
+/// 状态包凭据口令所在的环境变量
+const STATE_PASSPHRASE_ENV: &str = "CLAUDE_STATE_PASSPHRASE";
+
+/// 从环境变量读取状态包口令，避免出现在命令行参数中
+fn state_passphrase() -> Option<String> {
+    std::env::var(STATE_PASSPHRASE_ENV).ok().filter(|p| !p.is_empty())
+}
+
+/// 打印恢复的对话概况和最近几条消息
+fn print_conversation_recap(conversation: &crate::conversation::Conversation) {
+    const RECAP_MESSAGES: usize = 4;
+    const PREVIEW_CHARS: usize = 200;
+
+    println!("📂 {} ({} messages)", conversation.title, conversation.messages.len());
+    if let Some(cwd) = conversation.metadata.get("cwd").and_then(|v| v.as_str()) {
+        println!("   Project: {}", cwd);
+    }
+    if !conversation.sampling.is_empty() {
+        println!("   Sampling: {}", conversation.sampling);
+    }
+    let start = conversation.messages.len().saturating_sub(RECAP_MESSAGES);
+    for message in &conversation.messages[start..] {
+        let preview: String = message.content.chars().take(PREVIEW_CHARS).collect();
+        let ellipsis = if message.content.chars().count() > PREVIEW_CHARS { "…" } else { "" };
+        println!("  [{}] {}{}", message.role, preview.replace('\n', " "), ellipsis);
+    }
+}
+
+/// 按 ID 前缀找到唯一的会话
+fn resolve_session_id(manager: &crate::conversation::ConversationManager, prefix: &str) -> crate::error::Result<String> {
+    let matches: Vec<_> = manager
+        .list_conversations()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|summary| summary.id.starts_with(prefix))
+        .collect();
+    match matches.as_slice() {
+        [summary] => Ok(summary.id.clone()),
+        [] => Err(crate::error::ClaudeError::validation_error("id", format!("no session matches '{}'", prefix))),
+        _ => Err(crate::error::ClaudeError::validation_error(
+            "id",
+            format!("'{}' matches {} sessions, use a longer prefix", prefix, matches.len()),
+        )),
+    }
+}
+
+/// 提示会话中崩溃前没有完成的回合
+fn print_interrupted_turn(turn: &crate::conversation::InterruptedTurn) {
+    let preview: String = turn.input.chars().take(80).collect();
+    println!(
+        "⚠️  The last turn was interrupted at {} after {} tool call(s): \"{}\"",
+        turn.started_at.format("%Y-%m-%d %H:%M:%S"),
+        turn.tool_calls,
+        preview.replace('\n', " ")
+    );
+}
+
+/// 打印批处理的用量和节省的费用
+fn print_batch_summary(summary: &crate::network::batch::BatchSummary) {
+    if summary.batches == 0 {
+        return;
+    }
+    println!(
+        "📦 {} request(s) in {} batch(es): {} in / {} out tokens, ${:.4} (saved ${:.4} vs standard pricing)",
+        summary.requests, summary.batches, summary.input_tokens, summary.output_tokens, summary.cost, summary.saved
+    );
+}
+
+/// 以二进制单位格式化字节数
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// 健康报告的输出方式
+struct HealthReportOptions {
+    /// 写入 Markdown 的文件，不指定时打印到终端
+    output: Option<std::path::PathBuf>,
+    /// 保存到报告目录
+    save: bool,
+    /// 保存后发送邮件
+    email: bool,
+}
+
+impl ClaudeCodeCli {
+    /// 创建新的 CLI 处理器
+    pub async fn new() -> crate::error::Result<Self> {
+        let config = Arc::new(crate::config::ConfigManager::new()?);
+        // 只记录配置：HTTP 客户端和 Webhook 通知器在第一次发请求时才创建
+        let performance = &config.get_config().performance;
+        crate::network::configure_shared_client(&performance.http);
+        crate::network::dispatch::init_dispatcher(performance.max_concurrent_requests, performance.requests_per_minute);
+        crate::network::webhooks::init(&config.runtime_config().webhooks);
+
+        Ok(Self {
+            config,
+            client: std::sync::OnceLock::new(),
+            file_manager: std::sync::OnceLock::new(),
+            agent: tokio::sync::OnceCell::new(),
+            tools: tokio::sync::OnceCell::new(),
+            mcp: std::sync::OnceLock::new(),
+            execution_target: crate::process::devcontainer::SharedExecutionTarget::default(),
+            max_turns: std::sync::OnceLock::new(),
+            fallback_model: std::sync::OnceLock::new(),
+            tool_rules: std::sync::OnceLock::new(),
+            skip_permissions: std::sync::OnceLock::new(),
+            jobs: std::sync::OnceLock::new(),
+        })
+    }
+
+    fn client(&self) -> &Arc<crate::network::NetworkManager> {
+        self.client.get_or_init(|| Arc::new(crate::network::NetworkManager::new()))
+    }
+
+    fn jobs(&self) -> &Arc<crate::agent::JobManager> {
+        use crate::agent::{JobManager, JobStore};
+        self.jobs.get_or_init(|| Arc::new(JobManager::new(JobStore::new(JobStore::default_dir()))))
+    }
+
+    fn file_manager(&self) -> &Arc<crate::fs::FileManager> {
+        self.file_manager.get_or_init(|| Arc::new(crate::fs::FileManager::new()))
+    }
+
+    async fn agent(&self) -> crate::error::Result<&Arc<crate::agent::Agent>> {
+        self.agent.get_or_try_init(|| async { crate::agent::Agent::new().await.map(Arc::new) }).await
+    }
+
+    /// 内置工具注册表，第一次使用时按配置注册
+    async fn tools(&self) -> crate::error::Result<&Arc<crate::tools::ToolRegistry>> {
+        self.tools
+            .get_or_try_init(|| async {
+                use crate::tools::permission::{RuleDecider, TerminalPrompt};
+
+                let config = self.config.runtime_config();
+                let tools = Arc::new(crate::tools::ToolRegistry::new());
+                crate::tools::builtin::register_builtin_tools_with_config(&tools, &config, self.execution_target.clone()).await?;
+                if !self.skip_permissions.get().copied().unwrap_or(false) {
+                    let (allowed, denied) = self.tool_rules.get().cloned().unwrap_or_default();
+                    let decider = RuleDecider::from_config(&config.permissions)
+                        .with_rules(&allowed, &denied)
+                        .with_prompt(Arc::new(TerminalPrompt::new()));
+                    tools.set_permission_decider(Arc::new(decider)).await;
+                }
+                let metrics_log = crate::tools::metrics::MetricsLog::new(crate::tools::metrics::MetricsLog::root());
+                tools.set_metrics_log(Arc::new(metrics_log)).await;
+                Ok(tools)
+            })
+            .await
+    }
+
+    /// 三个范围合并后的 MCP 服务器，`user` 为主配置文件中的服务器；损坏的范围文件只打印警告
+    fn scoped_mcp_servers(
+        &self,
+        user: std::collections::HashMap<String, crate::config::McpServerConfig>,
+    ) -> crate::error::Result<std::collections::BTreeMap<String, crate::mcp::scope::ScopedServer>> {
+        use crate::mcp::scope::{merge, McpScope, ProjectScopes};
+
+        let scopes = ProjectScopes::for_current_dir()?;
+        Ok(merge(scopes.servers(McpScope::Local), scopes.servers(McpScope::Project), user))
+    }
+
+    /// 预热时自动启动的服务器：project 范围的服务器需要目录受信任并经过批准
+    fn approved_mcp_servers(
+        &self,
+        servers: std::collections::BTreeMap<String, crate::mcp::scope::ScopedServer>,
+    ) -> crate::error::Result<std::collections::HashMap<String, crate::config::McpServerConfig>> {
+        use crate::mcp::scope::{ApprovalStore, McpScope};
+
+        let mut approvals = None;
+        let mut approved = std::collections::HashMap::new();
+        for (name, server) in servers {
+            if server.scope == McpScope::Project && server.config.auto_start {
+                let store = match &mut approvals {
+                    Some(store) => store,
+                    None => approvals.insert(ApprovalStore::load_default()?),
+                };
+                if !approve_project_server(store, &server.config)? {
+                    continue;
+                }
+            }
+            approved.insert(name, server.config);
+        }
+        if let Some(store) = approvals {
+            store.save()?;
+        }
+        Ok(approved)
+    }
+
+    /// 按名称查找服务器，project 范围的服务器在使用前确认；未受信任目录中的项目配置文件不提供服务器
+    fn find_mcp_server(&self, name: &str) -> crate::error::Result<crate::config::McpServerConfig> {
+        use crate::mcp::scope::{ApprovalStore, McpScope};
+
+        let server = self
+            .scoped_mcp_servers(self.config.auto_load_mcp_servers())?
+            .remove(name)
+            .ok_or_else(|| crate::error::ClaudeError::config_error(format!("No MCP server named '{}'", name)))?;
+        if server.scope == McpScope::Project {
+            if !crate::security::trust::is_current_dir_trusted() {
+                return Err(crate::error::ClaudeError::permission_error(format!(
+                    "MCP server '{}' comes from .mcp.json in an untrusted folder; trust it first with `claude trust add`",
+                    name
+                )));
+            }
+            let mut store = ApprovalStore::load_default()?;
+            let approved = approve_project_server(&mut store, &server.config)?;
+            store.save()?;
+            if !approved {
+                return Err(crate::error::ClaudeError::permission_error(format!(
+                    "Project MCP server '{}' is not approved; approve it when asked in an interactive terminal, or run `claude mcp reset-project-choices` to be asked again after rejecting it",
+                    name
+                )));
+            }
+        }
+        Ok(server.config)
+    }
+
+    fn mcp(&self) -> &Arc<crate::mcp::McpManager> {
+        self.mcp.get_or_init(|| {
+            let schema_cache = crate::mcp::cache::McpSchemaCache::load_default().unwrap_or_default();
+            let health_log = crate::mcp::health::HealthLog::new(crate::mcp::health::HealthLog::default_path());
+            Arc::new(crate::mcp::McpManager::with_schema_cache(schema_cache).with_health_log(health_log))
+        })
+    }
+
+    /// 项目有开发容器且容器正在运行时切换到容器中执行工具命令；
+    /// 启动时按 `permissions.bash.devcontainer` 询问或直接切换，`explicit` 为 `/target devcontainer`
+    async fn offer_devcontainer(&self, explicit: bool) {
+        use crate::process::devcontainer::{DevContainer, DevContainerMode, ExecutionTarget};
+        use std::io::IsTerminal;
+
+        let mode = self.config.runtime_config().permissions.bash.devcontainer;
+        if !explicit && mode == DevContainerMode::Never {
+            return;
+        }
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let root = crate::git::GitManager::new(cwd.clone()).repository_root().await.unwrap_or(cwd);
+        let container = match DevContainer::detect(&root) {
+            Ok(Some(container)) => container,
+            Ok(None) => {
+                if explicit {
+                    println!("No .devcontainer configuration found in {}", root.display());
+                }
+                return;
+            }
+            Err(e) => {
+                println!("⚠️  {}", e);
+                return;
+            }
+        };
+        let container_id = match container.find_running().await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                println!(
+                    "🐳 Found devcontainer \"{}\" ({}) but it is not running; tool commands run on the host",
+                    container.name,
+                    container.config_path.display()
+                );
+                return;
+            }
+            Err(e) => {
+                println!("⚠️  Could not look for the devcontainer: {}", e);
+                return;
+            }
+        };
+
+        if !explicit && mode == DevContainerMode::Ask {
+            if !std::io::stdin().is_terminal() {
+                return;
+            }
+            let question = format!("🐳 Run tool commands inside devcontainer \"{}\"? [Y/n] ", container.name);
+            if !matches!(prompt_line(&question).as_deref(), Ok("" | "y" | "yes")) {
+                return;
+            }
+        }
+        self.set_execution_target(ExecutionTarget::DevContainer { container_id, container });
+    }
+
+    fn set_execution_target(&self, target: crate::process::devcontainer::ExecutionTarget) {
+        println!("⚙️  Tool commands now run on: {}", target.label());
+        if let Ok(mut current) = self.execution_target.write() {
+            *current = target;
+        }
+    }
+
+    /// `/target`：显示或切换工具命令的执行位置
+    async fn handle_target_command(&self, argument: &str) {
+        match argument {
+            "" => {
+                let label = self.execution_target.read().map(|t| t.label()).unwrap_or_default();
+                println!("⚙️  Tool commands run on: {}", label);
+            }
+            "host" => self.set_execution_target(crate::process::devcontainer::ExecutionTarget::Host),
+            "devcontainer" | "container" => self.offer_devcontainer(true).await,
+            _ => println!("Usage: /target [host|devcontainer]"),
+        }
+    }
+
+    /// 在后台预热：预序列化工具定义并预先连接自动启动的 MCP 服务器，避免拖慢首轮对话；
+    /// 连接成功的服务器提供的工具随后注册为 `mcp__<服务器>__<工具>`
+    async fn spawn_warm_up(&self) -> crate::error::Result<()> {
+        let tools = self.tools().await?.clone();
+        let mcp = self.mcp().clone();
+        let servers = self.approved_mcp_servers(self.scoped_mcp_servers(self.config.auto_load_mcp_servers())?)?;
+
+        tokio::spawn(async move {
+            let (schemas, report) = tokio::join!(tools.api_schemas(), mcp.warm_up(&servers));
+            match mcp.register_tools(&tools).await {
+                Ok(registered) if !registered.is_empty() => {
+                    tracing::info!("Registered {} MCP tool(s): {}", registered.len(), registered.join(", "))
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to register MCP tools: {}", e),
+            }
+            mcp.spawn_health_monitor();
+            tracing::debug!(
+                "Warm-up finished in {:?}: {} tool schemas, {} MCP server(s) started, {} already running, {} cached, {} failed",
+                report.elapsed,
+                schemas.len(),
+                report.started.len(),
+                report.already_running.len(),
+                report.cached.len(),
+                report.failed.len()
+            );
+        });
+        Ok(())
+    }
+
+    /// 执行 CLI 命令
+    pub async fn execute(&self, cli: Cli) -> crate::error::Result<()> {
+        use tracing::{info, debug};
+
+        if cli.debug || cli.mcp_debug {
+            debug!("Debug mode enabled");
+        }
+
+        if cli.verbose {
+            debug!("Verbose mode enabled");
+        }
+
+        if let Some(max_turns) = cli.max_turns {
+            let _ = self.max_turns.set(max_turns);
+        }
+        if let Some(fallback_model) = &cli.fallback_model {
+            let _ = self.fallback_model.set(fallback_model.clone());
+        }
+
+        // 处理全局添加目录
+        for dir in &cli.add_dirs {
+            self.add_directory(dir).await?;
+        }
+
+        // 处理权限设置
+        if cli.dangerously_skip_permissions {
+            info!("⚠️  Bypassing all permission checks");
+            let _ = self.skip_permissions.set(true);
+        }
+
+        // 处理工具白名单/黑名单
+        let allowed = split_tool_rules(&cli.allowed_tools);
+        let denied = split_tool_rules(&cli.disallowed_tools);
+        if !allowed.is_empty() {
+            info!("✅ Allowed tools: {:?}", allowed);
+        }
+        if !denied.is_empty() {
+            info!("❌ Disallowed tools: {:?}", denied);
+        }
+        let _ = self.tool_rules.set((allowed, denied));
+
+        // 处理 MCP 配置
+        if let Some(mcp_config) = &cli.mcp_config {
+            info!("🔧 Using MCP config: {}", mcp_config);
+        }
+
+        // 处理系统提示追加
+        if let Some(system_prompt) = &cli.append_system_prompt {
+            info!("📝 Appending system prompt: {}", system_prompt);
+        }
+
+        // 处理模型设置
+        if let Some(model) = &cli.model {
+            info!("🤖 Using model: {}", model);
+        }
+        if let Some(fallback_model) = &cli.fallback_model {
+            info!("🔄 Fallback model: {}", fallback_model);
+        }
+
+        // 对话类命令在后台预热工具和 MCP 服务器
+        let conversational = cli.print
+            || cli.continue_conversation
+            || cli.resume.is_some()
+            || matches!(cli.command, None | Some(Commands::Interactive) | Some(Commands::Tui) | Some(Commands::Api { .. }));
+        if conversational {
+            self.spawn_warm_up().await?;
+            self.spawn_storage_gc();
+        }
+
+        // 处理会话恢复
+        if cli.continue_conversation {
+            info!("🔄 Continuing most recent conversation");
+            return self.handle_continue_conversation().await;
+        }
+        if let Some(session_id) = &cli.resume {
+            info!("📂 Resuming conversation: {}", session_id);
+            return self.handle_resume_conversation(session_id.clone()).await;
+        }
+
+        // 计划模式：先只读调查并产出计划，批准后再执行
+        if cli.plan {
+            let Some(prompt) = &cli.prompt else {
+                return Err(crate::error::ClaudeError::validation_error(
+                    "prompt",
+                    "--plan requires a task prompt; in interactive mode use /plan <task>",
+                ));
+            };
+            return self.run_plan_mode(prompt).await;
+        }
+
+        // 处理 --print 模式
+        if cli.print {
             if let Some(ref prompt) = cli.prompt {
                 return self.handle_print_mode(prompt.clone(), &cli).await;
             } else {
@@ -832,11 +2472,11 @@ impl ClaudeCodeCli {
             Some(Commands::Install { target, force }) => {
                 self.handle_install_command(target, force).await
             },
-            Some(Commands::Api { message, model, stream, image, tools }) => {
-                self.handle_api_command(message, model, stream, image, tools).await
+            Some(Commands::Api { message, model, stream, image, tools, output }) => {
+                self.handle_api_command(message, model, stream, image, tools, output).await
             },
-            Some(Commands::Review { target, review_type }) => {
-                self.handle_review_command(target, review_type).await
+            Some(Commands::Review { target, review_type, report, email }) => {
+                self.handle_review_command(target, review_type, report || email, email).await
             },
             Some(Commands::Init { path, force }) => {
                 self.handle_init_command(path, force).await
@@ -853,8 +2493,8 @@ impl ClaudeCodeCli {
             Some(Commands::Interactive) => {
                 self.handle_interactive_command().await
             },
-            Some(Commands::Model { set, list }) => {
-                self.handle_model_command(set, list).await
+            Some(Commands::Model { set, list, update }) => {
+                self.handle_model_command(set, list, update).await
             },
             Some(Commands::Resume { conversation_id }) => {
                 self.handle_resume_command(conversation_id).await
@@ -890,62 +2530,180 @@ impl ClaudeCodeCli {
             Some(Commands::Tui) => {
                 self.handle_tui_command().await
             },
-            None => {
-                // 这种情况不应该发生，因为默认行为已经在上面处理了
-                unreachable!("Default behavior should be handled above")
+            Some(Commands::Trust { action }) => {
+                self.handle_trust_command(action).await
             },
-            _ => {
-                info!("Command not yet implemented");
-                Ok(())
-            }
-        }
-    }
-
-    /// 处理 API 命令（核心聊天功能）
-    async fn handle_api_command(
-        &self,
+            Some(Commands::Sessions { action }) => {
+                self.handle_sessions_command(action).await
+            },
+            Some(Commands::Tools { action: ToolsCommands::Costs { prompt, include } }) => {
+                self.handle_tool_costs_command(prompt, include).await
+            },
+            Some(Commands::Stats { action: StatsCommands::Tools { session, json } }) => {
+                self.handle_tool_stats_command(session, json)
+            },
+            Some(Commands::Snippets { action }) => {
+                self.handle_snippets_command(action)
+            },
+            Some(Commands::WebUsers { action }) => {
+                self.handle_web_users_command(action)
+            },
+            Some(Commands::Webhooks { action }) => {
+                self.handle_webhooks_command(action).await
+            },
+            Some(Commands::Reports { action }) => {
+                self.handle_reports_command(action).await
+            },
+            Some(Commands::Generate { spec, max_repairs, model }) => {
+                self.handle_generate_command(spec, max_repairs, model).await
+            },
+            Some(Commands::ExportState { file, encrypt_secrets, no_sessions }) => {
+                self.handle_export_state_command(file, encrypt_secrets, !no_sessions).await
+            },
+            Some(Commands::ImportState { file, force }) => {
+                self.handle_import_state_command(file, force).await
+            },
+            Some(Commands::ImportSessions { path, force }) => {
+                self.handle_import_sessions_command(path, force).await
+            },
+            Some(Commands::Git { command: GitCommand::Resolve { verify, no_verify } }) => {
+                self.handle_git_resolve_command(verify, no_verify).await
+            },
+            Some(Commands::Git { command: GitCommand::BisectAssist { test, good, bad, timeout } }) => {
+                self.handle_git_bisect_command(test, good, bad, timeout).await
+            },
+            Some(Commands::Export { format, output }) => {
+                self.handle_export_command(format, output).await
+            },
+            Some(Commands::Bot { command: BotCommand::Review { repo, interval, once, dry_run, include_drafts, batch } }) => {
+                self.handle_bot_review_command(repo, interval, once, dry_run, include_drafts, batch).await
+            },
+            Some(Commands::Triage { repo, limit, threshold, apply, batch }) => {
+                self.handle_triage_command(repo, limit, threshold, apply, batch).await
+            },
+            Some(Commands::Replace { pattern, replacement, literal, ignore_case, paths, exclude, yes, dry_run, undo }) => {
+                if undo {
+                    self.handle_replace_undo()
+                } else {
+                    let spec = crate::fs::replace::ReplaceSpec {
+                        pattern: pattern.unwrap_or_default(),
+                        replacement: replacement.unwrap_or_default(),
+                        literal,
+                        ignore_case,
+                        include: paths,
+                        exclude,
+                    };
+                    self.handle_replace_command(spec, yes, dry_run).await
+                }
+            },
+            Some(Commands::Bench { suite, model, replay, max_turns, json, batch }) => {
+                self.handle_bench_command(suite, model, replay, max_turns, json, batch).await
+            },
+            Some(Commands::Gc { dry_run }) => {
+                self.handle_gc_command(dry_run).await
+            },
+            Some(Commands::Health { path, output, max_file_lines, offline, no_ai, report, email }) => {
+                let report_options = HealthReportOptions { output, save: report || email, email };
+                self.handle_health_command(path, max_file_lines, offline, no_ai, report_options).await
+            },
+            Some(Commands::RunPrompt { name, vars, preview, model }) => {
+                self.handle_run_prompt_command(name, vars, preview, model).await
+            },
+            Some(Commands::Prompts { action }) => {
+                self.handle_prompts_command(action)
+            },
+            Some(Commands::Lessons { action }) => {
+                self.handle_lessons_command(action)
+            },
+            Some(Commands::Checkpoint { action }) => {
+                self.handle_checkpoint_command(action).await
+            },
+            Some(Commands::Agent { action }) => {
+                self.handle_agent_command(action)
+            },
+            Some(Commands::Jobs { action }) => {
+                self.handle_jobs_command(action).await
+            },
+            #[cfg(feature = "syntax-highlighting")]
+            Some(Commands::Highlight { command }) => {
+                self.handle_highlight_command(command).await
+            },
+            None => {
+                // 这种情况不应该发生，因为默认行为已经在上面处理了
+                unreachable!("Default behavior should be handled above")
+            },
+            _ => {
+                info!("Command not yet implemented");
+                Ok(())
+            }
+        }
+    }
+
+    /// 处理 API 命令（核心聊天功能）
+    async fn handle_api_command(
+        &self,
         message: String,
         model: String,
         stream: bool,
         image: Option<String>,
         tools: bool,
+        output: Option<std::path::PathBuf>,
     ) -> crate::error::Result<()> {
+        use crate::models::{Capability, ModelRegistry};
         use tracing::{info, error};
 
         info!("Processing API command with message: {}", message);
 
+        // 在发出请求前拦截模型不支持的功能
+        let registry = ModelRegistry::load();
+        if image.is_some() {
+            registry.require(&model, Capability::Vision)?;
+        }
+        if tools {
+            registry.require(&model, Capability::ToolUse)?;
+        }
+        registry.check_context(&model, crate::tokens::estimate_tokens(&message))?;
+        let max_tokens = registry.clamp_max_tokens(&model, 4096);
+
         // 构建请求
         let mut request = crate::network::ClaudeRequest {
             model,
             messages: vec![crate::network::Message {
                 role: "user".to_string(),
-                content: message,
+                content: message.into(),
             }],
-            max_tokens: 4096,
+            max_tokens,
             stream: Some(stream),
             tools: if tools { Some(vec![]) } else { None },
             temperature: None,
+            top_p: None,
             system: None,
         };
 
         // 处理图像输入
         if let Some(image_path) = image {
-            if let Ok(image_data) = self.file_manager.read_image(&image_path).await {
-                request.messages[0].content = format!("{}\\n[Image: {}]", request.messages[0].content, image_path);
+            if let Ok(image_data) = self.file_manager().read_image(&image_path).await {
+                request.messages[0].content = format!("{}\\n[Image: {}]", request.messages[0].content, image_path).into();
                 // 这里应该添加实际的图像处理逻辑
+
+                #[cfg(feature = "image-processing")]
+                {
+                    if let Some(text) = self.extract_image_text(&image_path).await {
+                        request.messages[0].content = format!("{}\n\n{}", request.messages[0].content, text).into();
+                    }
+                }
             }
         }
 
+        // 流式请求：实时输出增量并显示用量计数；写入文件时总是流式，避免在内存中攒完整内容
+        if stream || output.is_some() {
+            return self.stream_api_request(request, output).await;
+        }
+
         // 发送请求到 Claude API
-        match self.client.send_claude_request(request).await {
+        match self.client().send_claude_request(request).await {
             Ok(response) => {
-                if stream {
-                    // 处理流式响应
-                    self.handle_streaming_response(response).await?;
-                } else {
-                    // 处理普通响应
-                    println!("{}", response.content);
-                }
+                println!("{}", crate::ui::markdown::render_markdown(&response.content));
                 Ok(())
             },
             Err(e) => {
@@ -955,11 +2713,39 @@ impl ClaudeCodeCli {
         }
     }
 
+    /// 识别附加截图中的文字，未启用、tesseract 不可用或没有文字时返回 None
+    #[cfg(feature = "image-processing")]
+    async fn extract_image_text(&self, image_path: &str) -> Option<String> {
+        use crate::image_processing::ocr::{format_attachment, OcrEngine};
+        use tracing::{debug, warn};
+
+        let config = self.config.runtime_config().images;
+        if !config.ocr {
+            return None;
+        }
+
+        let engine = OcrEngine::from_config(&config);
+        if !engine.is_available().await {
+            debug!("tesseract not available, skipping OCR for {}", image_path);
+            return None;
+        }
+
+        match engine.recognize_file(std::path::Path::new(image_path)).await {
+            Ok(text) => text.map(|text| format_attachment(image_path, &text)),
+            Err(e) => {
+                warn!("OCR failed for {}: {}", image_path, e);
+                None
+            }
+        }
+    }
+
     /// 处理代码审查命令
     async fn handle_review_command(
         &self,
         target: Option<String>,
         review_type: Option<String>,
+        save_report: bool,
+        email: bool,
     ) -> crate::error::Result<()> {
         use tracing::info;
 
@@ -969,7 +2755,7 @@ impl ClaudeCodeCli {
         let review_type = review_type.unwrap_or_else(|| "general".to_string());
 
         // 分析代码库
-        let analysis = self.agent.analyze_codebase(&target_path).await?;
+        let analysis = self.agent().await?.analyze_codebase_with_progress(&target_path, scan_progress()).await?;
 
         // 生成审查报告
         let review_prompt = format!(
@@ -981,19 +2767,24 @@ impl ClaudeCodeCli {
             model: "claude-3-sonnet-20240229".to_string(),
             messages: vec![crate::network::Message {
                 role: "user".to_string(),
-                content: review_prompt,
+                content: review_prompt.into(),
             }],
             max_tokens: 4096,
             stream: Some(false),
             tools: None,
             temperature: None,
+            top_p: None,
             system: None,
         };
 
-        match self.client.send_claude_request(request).await {
+        match self.client().send_claude_request(request).await {
             Ok(response) => {
                 println!("\\n🔍 Code Review Report:\\n");
-                println!("{}", response.content);
+                println!("{}", crate::ui::markdown::render_markdown(&response.content));
+                if save_report {
+                    let report = review_report(&target_path, &review_type, &response.content).await?;
+                    self.publish_report(&report, email).await?;
+                }
                 Ok(())
             },
             Err(e) => {
@@ -1016,20 +2807,20 @@ impl ClaudeCodeCli {
 
         // 检查项目是否已经初始化
         let config_path = format!("{}/.claude-code", project_path);
-        if self.file_manager.exists(&config_path).await && !force {
+        if self.file_manager().exists(&config_path).await && !force {
             println!("Project already initialized. Use --force to reinitialize.");
             return Ok(());
         }
 
         // 创建项目配置目录
-        self.file_manager.create_dir(&config_path).await?;
+        self.file_manager().create_dir(&config_path).await?;
 
         // 分析项目结构
-        let analysis = self.agent.analyze_codebase(&project_path).await?;
+        let analysis = self.agent().await?.analyze_codebase_with_progress(&project_path, scan_progress()).await?;
 
         // 保存分析结果
         let analysis_path = format!("{}/.claude-code/analysis.json", project_path);
-        self.file_manager.write_json(&analysis_path, &analysis).await?;
+        self.file_manager().write_json(&analysis_path, &analysis).await?;
 
         println!("✅ Project initialized successfully!");
         println!("📁 Configuration saved to: {}", config_path);
@@ -1050,11 +2841,16 @@ impl ClaudeCodeCli {
         }
 
         // 检查网络连接
-        match self.client.test_connection().await {
+        match self.client().test_connection().await {
             Ok(_) => println!("✅ Network: Connected"),
             Err(_) => println!("❌ Network: Connection failed"),
         }
 
+        let connections = crate::network::connection_stats();
+        if connections.requests > 0 {
+            println!("🔌 {}", connections.format_report());
+        }
+
         // 显示版本信息
         println!("📦 Version: 0.1.0");
         println!("🦀 Rust Version: {}", std::env::var("RUSTC_VERSION").unwrap_or_else(|_| "Unknown".to_string()));
@@ -1084,26 +2880,93 @@ impl ClaudeCodeCli {
         }
 
         // 检查网络连接
-        match self.client.test_connection().await {
+        match self.client().test_connection().await {
             Ok(_) => println!("✅ Network: Healthy"),
             Err(_) => issues.push("Network connection failed"),
         }
 
         // 检查文件权限
-        match self.file_manager.check_permissions(".").await {
+        match self.file_manager().check_permissions(".").await {
             Ok(_) => println!("✅ File Permissions: OK"),
             Err(_) => issues.push("File permission issues"),
         }
 
-        if issues.is_empty() {
+        // 检查外部工具
+        let tools = crate::process::tooling::check_tools(self.config.get_config());
+        let mut missing = Vec::new();
+        for status in &tools {
+            match &status.path {
+                Some(path) => println!("✅ {}: {}", status.tool.label(), path.display()),
+                None if status.required => {
+                    println!("❌ {}: not found (needed for {})", status.tool.label(), status.tool.purpose());
+                    missing.push(status.tool);
+                }
+                None => println!("ℹ️  {}: not found (only needed for {})", status.tool.label(), status.tool.purpose()),
+            }
+        }
+
+        if issues.is_empty() && missing.is_empty() {
             println!("\\n🎉 All checks passed! Claude Code is healthy.");
-        } else {
+        } else if !issues.is_empty() {
             println!("\\n⚠️  Issues found:");
             for issue in issues {
                 println!("   - {}", issue);
             }
         }
 
+        if !missing.is_empty() {
+            self.offer_tool_installs(&missing).await?;
+        }
+
+        Ok(())
+    }
+
+    /// 为缺少的工具提供安装：逐个确认后通过系统包管理器安装并报告结果
+    async fn offer_tool_installs(&self, missing: &[crate::process::tooling::Tool]) -> crate::error::Result<()> {
+        use crate::process::tooling::{install_tool, PackageManager};
+        use std::io::IsTerminal;
+
+        let Some(manager) = PackageManager::detect() else {
+            println!("\n⚠️  Missing tools: {}", missing.iter().map(|t| t.label()).collect::<Vec<_>>().join(", "));
+            println!("   No supported package manager was found; please install them manually.");
+            return Ok(());
+        };
+
+        println!("\n🧰 Missing tools can be installed with {}:", manager.binary());
+        let mut planned = Vec::new();
+        for tool in missing {
+            let (command, args) = manager.install_command(*tool);
+            let command_line = format!("{} {}", command, args.join(" "));
+            println!("   - {}: {}", tool.label(), command_line);
+            planned.push((*tool, command_line));
+        }
+        if !std::io::stdin().is_terminal() {
+            println!("   Run `claude doctor` in an interactive terminal to install them.");
+            return Ok(());
+        }
+
+        let processes = crate::process::ProcessManager::new();
+        for (tool, command_line) in planned {
+            let answer = prompt_line(&format!("\nInstall {} by running `{}`? [y/N] ", tool.label(), command_line))?;
+            if answer != "y" && answer != "yes" {
+                println!("⏭️  Skipped {}", tool.label());
+                continue;
+            }
+            match install_tool(&processes, manager, tool).await {
+                Ok(outcome) if outcome.succeeded() => println!("✅ Installed {}", outcome.tool.label()),
+                Ok(outcome) if outcome.exit_code == Some(0) => println!(
+                    "⚠️  {} finished but `{}` is still not on PATH; open a new shell and re-run `claude doctor`",
+                    outcome.command_line,
+                    tool.binary()
+                ),
+                Ok(outcome) => println!(
+                    "❌ Installing {} failed (exit code {})",
+                    tool.label(),
+                    outcome.exit_code.map(|code| code.to_string()).unwrap_or_else(|| "unknown".to_string())
+                ),
+                Err(e) => println!("❌ Installing {} failed: {}", tool.label(), e),
+            }
+        }
         Ok(())
     }
 
@@ -1112,13 +2975,20 @@ impl ClaudeCodeCli {
         println!("💰 Usage and Cost Report (Last {} days)", days);
         println!("========================================");
 
-        // 这里应该从数据库或日志中获取实际的使用统计
-        println!("📊 API Calls: 0");
-        println!("💸 Estimated Cost: $0.00");
-        println!("📈 Tokens Used: 0");
-        println!("⏱️  Average Response Time: N/A");
+        let ledger = crate::cost::CostTracker::new(crate::cost::CostTracker::default_dir())?;
+        let stats = ledger.get_usage_statistics(Some(days))?;
+        println!("📊 API Calls: {}", stats.total_calls);
+        println!("💸 Estimated Cost: ${:.2}", stats.total_cost);
+        println!("📈 Tokens Used: {} ({} in / {} out)", stats.total_tokens, stats.total_input_tokens, stats.total_output_tokens);
+        let mut models: Vec<_> = stats.by_model.iter().collect();
+        models.sort_by(|a, b| b.1.cost.total_cmp(&a.1.cost));
+        for (model, usage) in models {
+            println!("   {:<32} {:>5} calls  ${:.2}", model, usage.calls, usage.cost);
+        }
 
-        println!("\\n💡 Tip: Cost tracking will be available after first API usage.");
+        if stats.total_calls == 0 {
+            println!("\n💡 Tip: Cost tracking will be available after first API usage.");
+        }
 
         Ok(())
     }
@@ -1126,194 +2996,2784 @@ impl ClaudeCodeCli {
     /// 处理清除命令
     async fn handle_clear_command(&self) -> crate::error::Result<()> {
         // 清除对话历史
-        self.agent.clear_conversation_history().await?;
+        self.agent().await?.clear_conversation_history().await?;
         println!("✅ Conversation history cleared.");
         Ok(())
     }
 
-    /// 处理配置命令
-    async fn handle_config_command(&self, action: ConfigAction) -> crate::error::Result<()> {
+    /// 处理配置命令
+    async fn handle_config_command(&self, action: ConfigAction) -> crate::error::Result<()> {
+        match action {
+            ConfigAction::Show => {
+                let config = self.config.get_config();
+                println!("📋 Current Configuration:");
+                println!("  API Key: {}", if config.api.anthropic_api_key.is_some() { "Set" } else { "Not set" });
+                println!("  Base URL: {}", config.api.base_url);
+                println!("  Default Model: {}", config.api.default_model);
+            },
+            ConfigAction::Get { key } => {
+                match self.config.get_value(&key) {
+                    Ok(value) => println!("{}: {}", key, value),
+                    Err(_) => println!("Configuration key '{}' not found", key),
+                }
+            },
+            ConfigAction::Set { key, value } => {
+                // 这里需要实现 set_value 的可变访问
+                println!("⚠️  Configuration setting not yet implemented");
+                println!("Would set: {} = {}", key, value);
+            },
+            ConfigAction::Init { path, format, force } => {
+                let config_path = path.unwrap_or_else(|| "claude-code.yaml".to_string());
+                println!("⚠️  Configuration initialization not yet implemented");
+                println!("Would create config at: {} (format: {:?}, force: {})", config_path, format, force);
+            },
+            ConfigAction::Validate => {
+                // 简单验证
+                let config = self.config.get_config();
+                if config.api.anthropic_api_key.is_some() {
+                    println!("✅ Configuration is valid");
+                } else {
+                    println!("❌ Configuration validation failed: API key not set");
+                }
+            },
+            ConfigAction::List => {
+                println!("📁 Configuration file locations:");
+                println!("  - ~/.config/claude-code/config.yaml");
+                println!("  - ./claude-code.yaml");
+                println!("  - ./.claude-code.yaml");
+            },
+        }
+        Ok(())
+    }
+
+    /// 处理交互模式命令
+    async fn handle_interactive_command(&self) -> crate::error::Result<()> {
+        println!("🤖 Claude Code Interactive Mode");
+        println!("Type 'exit' to quit, 'help' for commands");
+        println!("================================");
+
+        let (reloader, mut config_updates) = self.start_config_reloader();
+        let mut session = crate::conversation::ConversationManager::with_storage_dir(
+            crate::conversation::ConversationManager::default_storage_dir(),
+        )?;
+        session.set_default_language(reloader.config().response_language());
+        let git = crate::git::GitManager::new(std::env::current_dir()?);
+        let mut branch = current_git_branch(&git).await;
+        let mut refs = crate::git::refs::RefCache::new();
+        let mut editor = crate::ui::line_editor::LineEditor::new();
+        self.offer_devcontainer(false).await;
+        if let Err(e) = self.offer_turn_recovery(&mut session, branch.as_deref(), reloader.config().api.default_model).await {
+            println!("❌ {}", e);
+        }
+
+        loop {
+            while let Ok(outcome) = config_updates.try_recv() {
+                print_reload_outcome(&outcome);
+                if outcome.applied.iter().any(|change| change.key == "preferences.response_language") {
+                    session.set_default_language(reloader.config().response_language());
+                }
+            }
+
+            let now_on = current_git_branch(&git).await;
+            if now_on != branch {
+                if let Some(to) = &now_on {
+                    self.handle_branch_switch(&mut session, branch.as_deref(), to)?;
+                }
+                branch = now_on;
+            }
+
+            refs.refresh_if_stale(&git).await;
+            let Some(input) = editor.read_line("claude> ", |line| refs.complete(line))? else {
+                println!("👋 Goodbye!");
+                break;
+            };
+            let input = input.as_str();
+
+            match input {
+                "exit" | "quit" => {
+                    let running = self.jobs.get().map_or(0, |jobs| jobs.running());
+                    if running > 0 {
+                        println!("⚠️  Stopping {} background job(s) that are still running", running);
+                    }
+                    println!("👋 Goodbye!");
+                    break;
+                },
+                "help" => {
+                    self.show_interactive_help();
+                },
+                "clear" => {
+                    self.handle_clear_command().await?;
+                },
+                "status" => {
+                    self.handle_status_command().await?;
+                },
+                "" => continue,
+                _ if input == "/lang" || input.starts_with("/lang ") => {
+                    handle_lang_command(&mut session, input["/lang".len()..].trim())?;
+                },
+                "/pins" => {
+                    print_pinned_messages(&session);
+                },
+                _ if input == "/set" || input.starts_with("/set ") => {
+                    handle_set_command(&mut session, input["/set".len()..].trim());
+                },
+                "/settings" | "/settings show" => {
+                    print_session_settings(&session, &reloader.config().api.default_model);
+                },
+                _ if input == "/snippet" || input.starts_with("/snippet ") => {
+                    if let Err(e) = handle_snippet_command(&mut session, input["/snippet".len()..].trim()) {
+                        println!("❌ {}", e);
+                    }
+                },
+                "/scratch" => match session.get_current_conversation() {
+                    Some(conversation) => {
+                        println!("📁 {}", crate::fs::scratch::Scratchpad::for_session(&conversation.id).describe());
+                    }
+                    None => println!("📁 No conversation yet; the scratchpad is created with the first message."),
+                },
+                _ if input == "/git" || input.starts_with("/git ") => {
+                    let args: Vec<String> = input["/git".len()..].split_whitespace().map(str::to_string).collect();
+                    if args.is_empty() {
+                        println!("Usage: /git <command> [args] (Tab completes branches, tags and recent commits)");
+                    } else {
+                        match git.run(&args).await {
+                            Ok(output) => {
+                                print!("{}", output);
+                                session.record_tool_call("git", serde_json::json!(args), &output, false)?;
+                                // 输出进入上下文，之后不再被提到时会被裁剪
+                                session.add_tool_result("git", &format!("git {}", args.join(" ")), &output)?;
+                            }
+                            Err(e) => {
+                                println!("❌ {}", e);
+                                session.record_tool_call("git", serde_json::json!(args), &e.to_string(), true)?;
+                            }
+                        }
+                        refs.invalidate();
+                    }
+                },
+                _ if input == "/target" || input.starts_with("/target ") => {
+                    self.handle_target_command(input["/target".len()..].trim()).await;
+                },
+                _ if input.starts_with("/pin ") || input.starts_with("/unpin ") => {
+                    let (command, argument) = input.split_once(' ').unwrap_or((input, ""));
+                    match argument.trim().parse::<usize>() {
+                        Ok(number) => match session.set_pinned(number, command == "/pin") {
+                            Ok(message) if message.pinned => println!("📌 Pinned message #{}", number),
+                            Ok(_) => println!("📌 Unpinned message #{}", number),
+                            Err(e) => println!("❌ {}", e),
+                        },
+                        Err(_) => println!("Usage: {} <message number> (see /pins for pinned messages)", command),
+                    }
+                },
+                _ if input == "/compact" || input.starts_with("/compact ") => {
+                    let instructions = input["/compact".len()..].trim();
+                    let before = session.get_message_count();
+                    session.compact_conversation(Some(instructions).filter(|i| !i.is_empty()))?;
+                    println!(
+                        "📦 Compacted {} messages to {} ({} pinned kept verbatim)",
+                        before,
+                        session.get_message_count(),
+                        session.pinned_messages().len()
+                    );
+                },
+                _ if input == "/why-did-you-say-that" || input.starts_with("/why-did-you-say-that ") || input == "/why" || input.starts_with("/why ") => {
+                    let argument = input.split_once(' ').map(|(_, rest)| rest.trim()).unwrap_or_default();
+                    print_provenance(&session, argument);
+                },
+                "/prompts" => {
+                    match crate::prompts::PromptLibrary::open(&std::env::current_dir()?) {
+                        Ok(library) => print_prompt_templates(&library),
+                        Err(e) => println!("❌ {}", e),
+                    }
+                },
+                _ if input.starts_with("/prompt ") => {
+                    let (name, args) = input["/prompt ".len()..].trim().split_once(' ').unwrap_or((input["/prompt ".len()..].trim(), ""));
+                    let rendered = crate::prompts::PromptLibrary::open(&std::env::current_dir()?).and_then(|library| {
+                        let values = crate::prompts::parse_inline_assignments(args)?;
+                        library.render(name, &values)
+                    });
+                    match rendered {
+                        Ok((template, prompt)) => {
+                            let model = template.model.unwrap_or(reloader.config().api.default_model);
+                            println!("📝 {}", prompt);
+                            self.chat_turn(&mut session, branch.clone(), &prompt, Vec::new(), model).await?;
+                        }
+                        Err(e) => println!("❌ {}", e),
+                    }
+                },
+                _ if input == "/bg" || input.starts_with("/bg ") => {
+                    match input["/bg".len()..].trim() {
+                        "" => println!("Usage: /bg <task>  (run an agent in the background; see /jobs)"),
+                        task => {
+                            let started = match self.build_agent_loop().await {
+                                Ok((agent, responses)) => self.jobs().spawn(task, agent, responses, vec![task.to_string()]),
+                                Err(e) => Err(e),
+                            };
+                            match started {
+                                Ok(job) => println!("🧵 Started job {}. Follow it with /jobs attach {}", job.id, job.id),
+                                Err(e) => println!("❌ {}", e),
+                            }
+                        }
+                    }
+                },
+                _ if input == "/jobs" || input.starts_with("/jobs ") => {
+                    let args: Vec<&str> = input["/jobs".len()..].split_whitespace().collect();
+                    let action = match args.as_slice() {
+                        [] | ["list"] => Some(JobsCommands::List),
+                        ["status", id] => Some(JobsCommands::Status { id: id.to_string() }),
+                        ["attach", id] => Some(JobsCommands::Attach { id: id.to_string() }),
+                        ["cancel", id] => Some(JobsCommands::Cancel { id: id.to_string() }),
+                        _ => None,
+                    };
+                    match action {
+                        Some(action) => {
+                            if let Err(e) = self.handle_jobs_command(action).await {
+                                println!("❌ {}", e);
+                            }
+                        }
+                        None => println!("Usage: /jobs [list | status <id> | attach <id> | cancel <id>]"),
+                    }
+                },
+                _ if input == "/tools" || input.starts_with("/tools ") => {
+                    let args: Vec<&str> = input["/tools".len()..].split_whitespace().collect();
+                    if let Err(e) = self.handle_tools_slash_command(&args).await {
+                        println!("❌ {}", e);
+                    }
+                },
+                _ if input == "/plan" || input.starts_with("/plan ") => {
+                    match input["/plan".len()..].trim() {
+                        "" => println!("Usage: /plan <task>  (investigate read-only, then approve the plan before any change)"),
+                        task => {
+                            if let Err(e) = self.run_plan_mode(task).await {
+                                println!("❌ {}", e);
+                            }
+                        }
+                    }
+                },
+                _ if input == "/diff-turns" || input.starts_with("/diff-turns ") => {
+                    let turns: Vec<usize> = input["/diff-turns".len()..].split_whitespace().filter_map(|t| t.parse().ok()).collect();
+                    match (session.get_current_conversation(), turns.as_slice()) {
+                        (None, _) => println!("No active conversation yet."),
+                        (Some(conversation), &[first, last] | &[first @ last]) => {
+                            use std::io::IsTerminal;
+                            let store = crate::git::checkpoint::CheckpointStore::new(std::env::current_dir()?);
+                            match store.diff_turns(&conversation.id, first, last).await {
+                                Ok(diff) => print!("{}", diff.render(std::io::stdout().is_terminal())),
+                                Err(e) => println!("❌ {}", e),
+                            }
+                        }
+                        _ => println!("Usage: /diff-turns <n> [m]  (changes made during turns n through m)"),
+                    }
+                },
+                "/mcp" => {
+                    print_mcp_prompts(&self.mcp().prompts());
+                },
+                _ if input.starts_with("/mcp__") => {
+                    let (command, args) = input[1..].split_once(' ').unwrap_or((&input[1..], ""));
+                    match self.expand_mcp_prompt(command, args).await {
+                        Ok(Some(prompt)) => {
+                            println!("📝 {}", prompt);
+                            self.chat_turn(&mut session, branch.clone(), &prompt, Vec::new(), reloader.config().api.default_model).await?;
+                        }
+                        Ok(None) => println!("Cancelled."),
+                        Err(e) => println!("❌ {}", e),
+                    }
+                },
+                _ => {
+                    // 展开 @snippet:<name>、@文件、@URL 和 @服务器:资源 引用
+                    let (input, sources) = expand_context(input, self.mcp()).await?;
+                    // 将输入作为聊天消息处理，并记录到与当前分支关联的会话
+                    self.chat_turn(&mut session, branch.clone(), &input, sources, reloader.config().api.default_model).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 发送一轮对话：带上会话历史，打印回复并把双方消息写入会话
+    async fn chat_turn(
+        &self,
+        session: &mut crate::conversation::ConversationManager,
+        branch: Option<String>,
+        input: &str,
+        sources: Vec<crate::conversation::ContextSource>,
+        model: String,
+    ) -> crate::error::Result<()> {
+        if session.get_current_conversation().is_none() {
+            let title: String = input.chars().take(60).collect();
+            session.create_conversation(Some(title))?;
+            session.set_branch(branch)?;
+        }
+        let message_id = session.add_message("user", input, None)?;
+        if !sources.is_empty() {
+            let introduced = crate::conversation::Provenance { sources, ..Default::default() };
+            session.set_provenance(&message_id, introduced)?;
+        }
+        self.answer_turn(session, &message_id, model).await
+    }
+
+    /// 回答历史中最后一条用户消息；开始和结束都记入事件日志，中途崩溃后可以从这里继续
+    async fn answer_turn(
+        &self,
+        session: &mut crate::conversation::ConversationManager,
+        message_id: &str,
+        model: String,
+    ) -> crate::error::Result<()> {
+        session.start_turn(message_id)?;
+        // 每轮结束时给工作区拍一个检查点，会话的第一轮之前先记下起点
+        let working_dir = std::env::current_dir()?;
+        let checkpoints = session
+            .get_current_conversation()
+            .map(|c| (crate::git::checkpoint::CheckpointStore::new(&working_dir), c.id.clone()));
+        if let Some((store, id)) = &checkpoints {
+            if store.next_turn(id).await.unwrap_or_default() == 0 {
+                record_checkpoint(store, id, "session start").await;
+            }
+        }
+
+        // 记忆文件每轮重新读取，回复记录的是当时的内容
+        let (mut memory, mut context_sources) = memory_context(&working_dir);
+        let lessons = &self.config.get_config().lessons;
+        if lessons.enabled {
+            let query = session
+                .get_current_conversation()
+                .and_then(|c| c.messages.iter().find(|m| m.id == message_id))
+                .map(|m| m.content.to_string())
+                .unwrap_or_default();
+            if let Some((section, ids)) = lesson_context(&working_dir, &query, lessons.max_injected) {
+                memory = Some(match memory {
+                    Some(memory) => format!("{}\n\n{}", memory, section),
+                    None => section.clone(),
+                });
+                context_sources.extend(ids.into_iter().map(|id| {
+                    crate::conversation::ContextSource::new(crate::conversation::provenance::SourceKind::Lesson, id, &section)
+                }));
+            }
+        }
+        context_sources.extend(session.history_sources());
+        let (messages, pruned) = session.pruned_api_messages(&self.config.get_config().context_pruning);
+        if pruned.pruned > 0 {
+            println!("🧹 Pruned {} stale tool result(s), ~{} tokens", pruned.pruned, pruned.tokens_saved);
+        }
+        let provenance = crate::conversation::Provenance {
+            sources: context_sources,
+            history_messages: messages.len(),
+            model: Some(model.clone()),
+        };
+
+        let sampling = session.sampling();
+        let request = crate::network::ClaudeRequest {
+            model,
+            messages,
+            max_tokens: sampling.max_tokens.unwrap_or(4096),
+            stream: Some(false),
+            tools: None,
+            temperature: sampling.temperature,
+            top_p: sampling.top_p,
+            system: match session.language() {
+                Some(language) => language.apply_to_system(memory),
+                None => memory,
+            },
+        };
+        let response = self.client().send_claude_request(request).await?;
+        println!("{}", crate::ui::markdown::render_markdown(&response.content));
+
+        let usage = response.usage.map(|usage| crate::conversation::TokenUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.input_tokens + usage.output_tokens,
+            estimated_cost: 0.0,
+        });
+        let reply_id = session.add_message("assistant", response.content, usage)?;
+        session.set_provenance(&reply_id, provenance)?;
+        session.finish_turn(message_id)?;
+
+        if let Some((store, id)) = &checkpoints {
+            let label = session
+                .get_current_conversation()
+                .and_then(|c| c.messages.iter().find(|m| m.id == message_id))
+                .map(|m| m.content.lines().next().unwrap_or_default().chars().take(60).collect::<String>())
+                .unwrap_or_default();
+            record_checkpoint(store, id, &label).await;
+        }
+        Ok(())
+    }
+
+    /// 当前分支最近的会话在回合中途崩溃时，提供从中断处继续
+    async fn offer_turn_recovery(
+        &self,
+        session: &mut crate::conversation::ConversationManager,
+        branch: Option<&str>,
+        model: String,
+    ) -> crate::error::Result<()> {
+        let Some(summary) = branch.map(|branch| session.latest_for_branch(branch)).transpose()?.flatten() else {
+            return Ok(());
+        };
+        let conversation = session.get_conversation(&summary.id)?;
+        let events = session.events(&summary.id)?;
+        let Some(turn) = crate::conversation::events::interrupted_turn(&conversation, &events) else {
+            return Ok(());
+        };
+
+        println!("📂 Session \"{}\"", summary.title);
+        print_interrupted_turn(&turn);
+        loop {
+            match prompt_line("[r]esume the turn / [c]ontinue the session without it / [i]gnore > ")?.as_str() {
+                "r" | "resume" => {
+                    session.load_conversation(&summary.id)?;
+                    return self.answer_turn(session, &turn.message_id, model).await;
+                }
+                "c" | "continue" => {
+                    session.load_conversation(&summary.id)?;
+                    return session.finish_turn(&turn.message_id);
+                }
+                "i" | "ignore" | "" => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    /// 会话进行中切换了分支：提示并提供切换到新分支的会话或派生当前会话
+    fn handle_branch_switch(
+        &self,
+        session: &mut crate::conversation::ConversationManager,
+        from: Option<&str>,
+        to: &str,
+    ) -> crate::error::Result<()> {
+        println!("⚠️  Branch changed from {} to {}", from.unwrap_or("(none)"), to);
+        let Some(current) = session.get_current_conversation().map(|c| c.id.clone()) else {
+            return Ok(());
+        };
+        let existing = session.latest_for_branch(to)?.filter(|summary| summary.id != current);
+
+        match &existing {
+            Some(summary) => println!("   Session \"{}\" is associated with {}", summary.title, to),
+            None => println!("   No session is associated with {} yet", to),
+        }
+        let options = if existing.is_some() {
+            "[s]witch to it / [f]ork this session / [k]eep this session"
+        } else {
+            "[f]ork this session / [n]ew session / [k]eep this session"
+        };
+
+        loop {
+            match (prompt_line(&format!("{} > ", options))?.as_str(), &existing) {
+                ("s" | "switch", Some(summary)) => {
+                    session.load_conversation(&summary.id)?;
+                    println!("📂 Switched to \"{}\"", summary.title);
+                }
+                ("f" | "fork", _) => {
+                    session.fork_current(Some(to.to_string()))?;
+                    println!("🌿 Forked the session onto {}", to);
+                }
+                ("n" | "new", None) => {
+                    session.create_conversation(None)?;
+                    session.set_branch(Some(to.to_string()))?;
+                    println!("🆕 Started a new session on {}", to);
+                }
+                ("k" | "keep" | "", _) => {}
+                _ => continue,
+            }
+            return Ok(());
+        }
+    }
+
+    /// 监控配置文件，运行中的会话随文件变化热更新
+    fn start_config_reloader(
+        &self,
+    ) -> (crate::config::reload::ConfigReloader, tokio::sync::broadcast::Receiver<crate::config::reload::ReloadOutcome>) {
+        let mut reloader = crate::config::reload::ConfigReloader::for_manager(&self.config);
+        if let Err(e) = reloader.start() {
+            tracing::warn!("Config hot-reload disabled for {}: {}", reloader.path().display(), e);
+        }
+        let updates = reloader.subscribe();
+        (reloader, updates)
+    }
+
+    /// `/mcp__<服务器>__<提示>`：命令后没有给出的参数逐个询问，必填参数留空时取消
+    async fn expand_mcp_prompt(&self, command: &str, args: &str) -> crate::error::Result<Option<String>> {
+        let mcp = self.mcp();
+        let prompt = mcp.find_prompt(command).ok_or_else(|| {
+            crate::error::ClaudeError::validation_error("command", format!("Unknown MCP prompt '/{}' (see /mcp)", command))
+        })?;
+        let mut values = prompt.parse_arguments(args)?;
+        for argument in prompt.missing(&values) {
+            let question = format!(
+                "  {}{}{}: ",
+                argument.name,
+                argument.description.as_ref().map(|d| format!(" ({})", d)).unwrap_or_default(),
+                if argument.required { "" } else { " [optional]" }
+            );
+            let answer = read_answer(&question)?;
+            if answer.is_empty() {
+                if argument.required {
+                    return Ok(None);
+                }
+                continue;
+            }
+            values.insert(argument.name.clone(), answer);
+        }
+        mcp.get_prompt(&prompt, &values).await.map(Some)
+    }
+
+    /// `/tools`：列出工具是否可用，或在本次会话中启用、停用工具
+    async fn handle_tools_slash_command(&self, args: &[&str]) -> crate::error::Result<()> {
+        let tools = self.tools().await?;
+        match args {
+            [] | ["list"] => {
+                for (definition, enabled) in tools.availability().await {
+                    println!("  {} {:<20} {}", if enabled { "✅" } else { "⛔" }, definition.name, definition.category);
+                }
+            }
+            [action @ ("enable" | "disable"), names @ ..] if !names.is_empty() => {
+                for name in names {
+                    tools.set_enabled(name, *action == "enable").await?;
+                    println!("🔧 {} {}", if *action == "enable" { "Enabled" } else { "Disabled" }, name);
+                }
+            }
+            _ => println!("Usage: /tools [list | enable <name>... | disable <name>...]"),
+        }
+        Ok(())
+    }
+
+    /// 显示交互模式帮助
+    fn show_interactive_help(&self) {
+        println!("\\n📚 Available Commands:");
+        println!("  help     - Show this help message");
+        println!("  status   - Show system status");
+        println!("  clear    - Clear conversation history");
+        println!("  /lang    - Show or set the response language (e.g. /lang zh-CN, /lang off)");
+        println!("  /pin <n> - Keep message n verbatim through compaction (/unpin <n> to release)");
+        println!("  /pins    - List pinned messages");
+        println!("  /set     - Override sampling for this session (e.g. /set temperature 0.2, /set top_p default)");
+        println!("  /settings show - Show the session's model, language and sampling settings");
+        println!("  /compact - Compact the conversation, keeping pinned messages");
+        println!("  /target  - Show or switch where tool commands run (host or devcontainer)");
+        println!("  /snippet - Save a code block from the last reply, or list/show/insert/delete snippets");
+        println!("  @snippet:<name> - Insert a saved snippet into your message");
+        println!("  /prompts - List prompt templates; /prompt <name> key=value ... sends one");
+        println!("  /mcp     - List MCP server prompts; /mcp__<server>__<prompt> [key=value ...] sends one");
+        println!("  @<path>, @<url> - Attach a file or fetched page to your message");
+        println!("  @<server>:<resource> - Attach a resource from a running MCP server (see claude mcp resources)");
+        println!("  /plan <task> - Investigate with read-only tools and approve a plan before any change");
+        println!("  /bg <task> - Run an agent task in the background");
+        println!("  /jobs [list|status|attach|cancel] [id] - Manage background agent jobs");
+        println!("  /tools [enable|disable <name>] - List tool availability or toggle a tool for this session");
+        println!("  /diff-turns <n> [m] - Show the file changes made during turns n through m");
+        println!("  /why-did-you-say-that <n> - Show the files, memory and URLs in context for reply n (alias /why)");
+        println!("  /git     - Run a git command; Tab completes branches, tags and recent commits");
+        println!("  /scratch - List files the agent created in this session's scratchpad (scratch://)");
+        println!("  exit     - Exit interactive mode");
+        println!("  <text>   - Send message to Claude");
+        println!();
+    }
+
+    /// 添加目录到工作空间
+    async fn add_directory(&self, dir: &str) -> crate::error::Result<()> {
+        use tracing::info;
+        info!("Adding directory to workspace: {}", dir);
+        // 这里应该实现实际的目录添加逻辑
+        Ok(())
+    }
+
+    /// 发送流式请求，实时打印增量（或写入 `output`）并在结束时输出用量摘要
+    async fn stream_api_request(&self, request: crate::network::ClaudeRequest, output: Option<std::path::PathBuf>) -> crate::error::Result<()> {
+        use futures::StreamExt;
+        use std::io::Write;
+
+        let api_key = std::env::var("ANTHROPIC_API_KEY")
+            .map_err(|_| crate::error::ClaudeError::auth_error("ANTHROPIC_API_KEY environment variable not set"))?;
+        let base_url = self.config.get_config().api.base_url.clone();
+        let client = crate::network::ClaudeApiClient::new(api_key, Some(base_url))?;
+
+        let messages = request.messages
+            .into_iter()
+            .map(|message| (message.role, message.content))
+            .collect();
+        let mut message_request = client.create_text_request(&request.model, messages);
+        message_request.max_tokens = request.max_tokens;
+        message_request.system = request.system;
+
+        let (mut stream, cancel) = client.send_message_stream_cancellable(&message_request).await?;
+        let mut file = output.map(crate::fs::stream_write::StreamingFileWriter::create).transpose()?;
+
+        // Ctrl-C 只停止生成，保留已经输出的部分
+        let interrupt = cancel.clone();
+        let watcher = tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                interrupt.cancel();
+            }
+        });
+
+        while let Some(event) = stream.next().await {
+            let event = event?;
+            let payload = event.data.unwrap_or_default();
+
+            match event.event_type.as_str() {
+                "content_block_delta" => {
+                    let Some(text) = payload["delta"]["text"].as_str() else {
+                        continue;
+                    };
+                    match file.as_mut() {
+                        Some(file) => {
+                            let lines = file.lines_written();
+                            file.write(text)?;
+                            if file.lines_written() != lines {
+                                eprint!(
+                                    "\r✍️  {}: {} lines, {}",
+                                    file.target().display(),
+                                    file.lines_written(),
+                                    format_size(file.bytes_written())
+                                );
+                            }
+                        }
+                        None => print!("{}", text),
+                    }
+                    std::io::stdout().flush()?;
+                }
+                "message_stop" => break,
+                "error" => {
+                    let message = payload["error"]["message"].as_str().unwrap_or("Unknown stream error");
+                    let error_type = payload["error"]["type"].as_str().unwrap_or_default();
+                    return Err(crate::error::ClaudeError::from_api_error(error_type, message));
+                }
+                _ => {}
+            }
+        }
+
+        watcher.abort();
+
+        let progress = cancel.snapshot();
+        match file {
+            Some(file) => {
+                let target = file.target().to_path_buf();
+                let bytes = file.commit()?;
+                eprintln!("\r\x1b[2K✅ Wrote {} ({})", target.display(), format_size(bytes));
+            }
+            None => println!(),
+        }
+        if progress.cancelled {
+            eprintln!("⏹️  Generation cancelled after {} characters", progress.text.chars().count());
+        }
+        // 摘要写到 stderr，避免污染管道中的正文输出
+        eprintln!("{}", progress.usage.format_summary());
+        Ok(())
+    }
+
+    /// 处理 --print 模式
+    async fn handle_print_mode(&self, prompt: String, cli: &Cli) -> crate::error::Result<()> {
+        use tracing::info;
+
+        info!("🖨️  Print mode: {}", prompt);
+
+        // 根据输出格式处理
+        match cli.output_format.as_ref().unwrap_or(&crate::cli::OutputFormat::Text) {
+            crate::cli::OutputFormat::Text => {
+                println!("{}", prompt);
+            },
+            crate::cli::OutputFormat::Json => {
+                let json_output = serde_json::json!({
+                    "prompt": prompt,
+                    "response": "Response would go here",
+                    "timestamp": chrono::Utc::now().to_rfc3339()
+                });
+                println!("{}", serde_json::to_string_pretty(&json_output)?);
+            },
+            crate::cli::OutputFormat::StreamJson => {
+                // 流式 JSON 输出
+                let stream_output = serde_json::json!({
+                    "type": "response",
+                    "content": prompt,
+                    "timestamp": chrono::Utc::now().to_rfc3339()
+                });
+                println!("{}", serde_json::to_string(&stream_output)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 处理继续对话
+    async fn handle_continue_conversation(&self) -> crate::error::Result<()> {
+        use tracing::info;
+        info!("🔄 Continuing most recent conversation");
+        println!("Continuing the most recent conversation...");
+        // 这里应该实现实际的会话恢复逻辑
+        Ok(())
+    }
+
+    /// 处理恢复对话
+    async fn handle_resume_conversation(&self, session_id: String) -> crate::error::Result<()> {
+        use tracing::info;
+        info!("📂 Resuming conversation: {}", session_id);
+        let mut manager = crate::conversation::ConversationManager::new();
+        manager.load_conversation(&session_id)?;
+        if let Some(conversation) = manager.get_current_conversation() {
+            print_conversation_recap(conversation);
+        }
+        if let Some(turn) = manager.interrupted_turn() {
+            print_interrupted_turn(turn);
+            println!("   Run `claude interactive` on the session's branch to resume it");
+        }
+        Ok(())
+    }
+
+    /// 处理交互式提示
+    /// 创建带 API 客户端、工具、检查点和回合限制的 Agent 循环
+    async fn build_agent_loop(
+        &self,
+    ) -> crate::error::Result<(crate::agent::AgentLoop, tokio::sync::mpsc::UnboundedReceiver<crate::agent::AgentResponse>)> {
+        use crate::agent::{AgentCheckpointStore, AgentContext, AgentLoop};
+
+        let config = self.config.runtime_config();
+        let api_key = config
+            .api
+            .anthropic_api_key
+            .clone()
+            .or_else(|| std::env::var("ANTHROPIC_API_KEY").ok())
+            .ok_or_else(|| crate::error::ClaudeError::auth_error("ANTHROPIC_API_KEY environment variable not set"))?;
+        let client = Arc::new(crate::network::ClaudeApiClient::new(api_key, Some(config.api.base_url.clone()))?);
+        let tools = self.tools().await?.clone();
+        let mut context = AgentContext::new(uuid::Uuid::new_v4().to_string(), config)
+            .with_checkpoints(AgentCheckpointStore::new(AgentCheckpointStore::default_dir()));
+        context.fallback_model = self.fallback_model.get().cloned();
+        let (agent, responses) = AgentLoop::new(context, crate::conversation::ConversationManager::new());
+        let mut agent = agent.with_client(client).with_tools(tools);
+        if let Some(max_turns) = self.max_turns.get() {
+            agent = agent.with_max_turns(*max_turns);
+        }
+        Ok((agent, responses))
+    }
+
+    /// 计划模式：Agent 只用只读工具调查并给出计划，用户批准后在同一上下文中执行
+    async fn run_plan_mode(&self, task: &str) -> crate::error::Result<()> {
+        let (agent, mut responses) = self.build_agent_loop().await?;
+        let mut agent = agent.with_plan_mode(true);
+
+        println!("🗺️  Plan mode: investigating with read-only tools...");
+        drive_agent(&mut agent, &mut responses, vec![task.to_string()]).await?;
+        let Some(plan) = agent.plan() else {
+            println!("⚠️  The agent finished without producing a plan");
+            return Ok(());
+        };
+        if let Some(path) = &plan.path {
+            println!("📋 Plan saved to {}", path.display());
+        }
+
+        match prompt_line("Approve the plan and start executing? [y/N] ")?.as_str() {
+            "y" | "yes" => {
+                agent.approve_plan().await?;
+                println!("🚀 Executing the plan...");
+                drive_agent(&mut agent, &mut responses, Vec::new()).await?;
+                println!("✅ Plan executed. Undo changes with `claude agent checkpoints` and `claude agent rollback <id>`");
+            }
+            _ => println!("Plan not approved; nothing was changed."),
+        }
+        Ok(())
+    }
+
+    async fn handle_interactive_prompt(&self, prompt: String) -> crate::error::Result<()> {
+        use tracing::info;
+        info!("💬 Interactive prompt: {}", prompt);
+        println!("Processing: {}", prompt);
+        // 这里应该实现实际的提示处理逻辑
+        Ok(())
+    }
+
+    /// 显示工具定义的 Token 开销和选择结果
+    async fn handle_tool_costs_command(&self, prompt: Option<String>, include: Vec<String>) -> crate::error::Result<()> {
+        use crate::tools::selection::Inclusion;
+
+        let config = &self.config.get_config().tool_selection;
+        let selection = self.tools().await?.select_api_schemas(prompt.as_deref().unwrap_or(""), config, &include).await;
+        println!("🧰 {} tool(s), {} tokens of definitions (budget {})", selection.decisions.len(), selection.total_tokens, config.budget_tokens);
+        for (cost, inclusion) in &selection.decisions {
+            let label = match (inclusion, &prompt) {
+                (Inclusion::All, _) => "sent",
+                (Inclusion::Core, _) => "core",
+                (Inclusion::Forced, _) => "forced",
+                (Inclusion::Relevant, _) => "relevant",
+                (Inclusion::Omitted, Some(_)) => "omitted",
+                (Inclusion::Omitted, None) => "on demand",
+            };
+            println!("  {:<32} {:>6} tok  {}", cost.name, cost.tokens, label);
+        }
+        if prompt.is_some() {
+            println!("Would send {} tool(s), {} tokens", selection.schemas.len(), selection.selected_tokens);
+        }
+        Ok(())
+    }
+
+    /// 汇总一个会话的工具调用记录
+    fn handle_tool_stats_command(&self, session: Option<String>, json: bool) -> crate::error::Result<()> {
+        use crate::tools::metrics::{self, MetricsLog};
+
+        let log = MetricsLog::new(MetricsLog::root());
+        let Some(session) = session.or_else(|| log.latest_session()) else {
+            println!("No tool calls recorded yet.");
+            return Ok(());
+        };
+        let records = log.load(&session).map_err(|e| {
+            crate::error::ClaudeError::config_error(format!("No tool metrics for session '{}': {}", session, e))
+        })?;
+        let summary = metrics::summarize(&records);
+        if json {
+            let tools: Vec<_> = summary
+                .iter()
+                .map(|(tool, stats)| {
+                    serde_json::json!({
+                        "tool": tool,
+                        "calls": stats.call_count,
+                        "failures": stats.error_count,
+                        "failure_rate": stats.failure_rate(),
+                        "total_ms": stats.total_execution_time_ms,
+                        "average_ms": stats.average_execution_time_ms,
+                        "max_ms": stats.max_execution_time_ms,
+                        "output_bytes": stats.output_bytes,
+                    })
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "session": session, "tools": tools }))?);
+            return Ok(());
+        }
+        println!("📊 Session {}: {} tool call(s)", session, records.len());
+        println!("{}", metrics::render_table(&summary));
+        Ok(())
+    }
+
+    /// 处理 Webhook 命令
+    async fn handle_webhooks_command(&self, action: WebhooksCommands) -> crate::error::Result<()> {
+        use crate::network::webhooks::{self, WebhookEvent};
+
+        let Some(notifier) = webhooks::notifier() else {
+            return Err(crate::error::ClaudeError::config_error("Webhooks are not initialized"));
+        };
+        match action {
+            WebhooksCommands::List => {
+                let webhooks = self.config.runtime_config().webhooks;
+                if webhooks.is_empty() {
+                    println!("No webhooks configured. Add one under \"webhooks\" in the config file.");
+                }
+                let mut names: Vec<_> = webhooks.keys().collect();
+                names.sort();
+                for name in names {
+                    let webhook = &webhooks[name];
+                    let events = if webhook.events.is_empty() { "all events".to_string() } else { webhook.events.join(", ") };
+                    let signed = if webhook.secret.is_some() { "signed" } else { "unsigned" };
+                    println!("  {:<16} {}  ({}, {:?}, {})", name, webhook.url, events, webhook.format, signed);
+                }
+            }
+            WebhooksCommands::Test { name } => {
+                let status = notifier.deliver_to(&name, &WebhookEvent::Test).await?;
+                println!("✅ Delivered a test event to {} (HTTP {})", name, status);
+            }
+            WebhooksCommands::Log { limit } => {
+                let records = notifier.log().recent(limit)?;
+                if records.is_empty() {
+                    println!("No deliveries logged in {}", notifier.log().path().display());
+                }
+                for record in records {
+                    let outcome = match (&record.error, record.status) {
+                        (None, Some(status)) => format!("✅ {}", status),
+                        (Some(error), _) => format!("❌ {}", error),
+                        (None, None) => "✅".to_string(),
+                    };
+                    println!(
+                        "  {}  {:<16} {:<22} #{}  {}",
+                        record.at.format("%Y-%m-%d %H:%M:%S"),
+                        record.webhook,
+                        record.event,
+                        record.attempt,
+                        outcome
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理 Web 用户命令
+    fn handle_web_users_command(&self, action: WebUsersCommands) -> crate::error::Result<()> {
+        use crate::database::users::UserStore;
+
+        let mut store = UserStore::load(crate::web::auth::users_file(&self.config.get_config().web_auth))?;
+        match action {
+            WebUsersCommands::List => {
+                if store.list().is_empty() {
+                    println!("No web users. Add one with `claude web-users add <name>`.");
+                }
+                for user in store.list() {
+                    let budget = user.budget_usd.map(|budget| format!("${:.2}", budget)).unwrap_or_else(|| "unlimited".to_string());
+                    let login = if user.token_hash.is_some() { "token" } else { "proxy" };
+                    println!("  {:<24} {:<10} {:<6} spent ${:.4} of {}", user.name, user.profile, login, user.spent_usd, budget);
+                }
+                return Ok(());
+            }
+            WebUsersCommands::Add { name, profile, budget } => {
+                let (user, token) = store.add(&name, profile, budget)?;
+                println!("✅ Added web user '{}' ({})", user.name, user.profile);
+                println!("🔑 Token (shown once): {}", token);
+            }
+            WebUsersCommands::Rotate { name } => {
+                let token = store.rotate_token(&name)?;
+                println!("🔑 New token for '{}' (shown once): {}", name, token);
+            }
+            WebUsersCommands::Remove { name } => {
+                if !store.remove(&name) {
+                    return Err(crate::error::ClaudeError::validation_error("name", format!("No web user named '{}'", name)));
+                }
+                println!("🗑️ Removed web user '{}'", name);
+            }
+        }
+        store.save()
+    }
+
+    /// 处理代码片段命令
+    fn handle_snippets_command(&self, action: SnippetsCommands) -> crate::error::Result<()> {
+        use crate::snippets::{insert_into_file, SnippetLibrary, SnippetScope};
+
+        let mut library = SnippetLibrary::open(&std::env::current_dir()?)?;
+        let not_found = |name: &str| crate::error::ClaudeError::validation_error("name", format!("No snippet named '{}'", name));
+        match action {
+            SnippetsCommands::List { query } => print_snippets(&library, query.as_deref().unwrap_or("")),
+            SnippetsCommands::Show { name } => {
+                let (store, snippet) = library.find(&name).ok_or_else(|| not_found(&name))?;
+                print!("{}", store.content(snippet)?);
+            }
+            SnippetsCommands::Save { name, file, language, description, user } => {
+                let content = match &file {
+                    Some(path) => std::fs::read_to_string(path)?,
+                    None => std::io::read_to_string(std::io::stdin())?,
+                };
+                let language = language.or_else(|| {
+                    file.as_ref().and_then(|path| path.extension()).map(|ext| ext.to_string_lossy().into_owned())
+                });
+                let scope = if user { SnippetScope::User } else { SnippetScope::Project };
+                library.store_mut(scope).save(&name, language.as_deref(), description.as_deref(), &content)?;
+                println!("✅ Saved {} snippet '{}'", scope, name);
+            }
+            SnippetsCommands::Insert { name, file, line } => {
+                let (store, snippet) = library.find(&name).ok_or_else(|| not_found(&name))?;
+                insert_into_file(&file, &store.content(snippet)?, line)?;
+                println!("✅ Inserted '{}' into {}", name, file.display());
+            }
+            SnippetsCommands::Delete { name, user } => {
+                let scope = if user { SnippetScope::User } else { SnippetScope::Project };
+                if library.store_mut(scope).remove(&name)? {
+                    println!("🗑️  Deleted {} snippet '{}'", scope, name);
+                } else {
+                    println!("No {} snippet named '{}'", scope, name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理会话管理命令
+    async fn handle_sessions_command(&self, action: SessionsCommands) -> crate::error::Result<()> {
+        match action {
+            SessionsCommands::List { branch } => {
+                let manager = crate::conversation::ConversationManager::new();
+                let sessions = manager.list_conversations().unwrap_or_default();
+                let current = current_git_branch(&crate::git::GitManager::new(std::env::current_dir()?)).await;
+
+                // 按分支分组，组内保持按更新时间倒序；未关联分支的会话放在最后
+                let mut groups: std::collections::BTreeMap<Option<String>, Vec<&crate::conversation::ConversationSummary>> =
+                    std::collections::BTreeMap::new();
+                for summary in sessions.iter().filter(|s| branch.is_none() || s.branch == branch) {
+                    groups.entry(summary.branch.clone()).or_default().push(summary);
+                }
+                if groups.is_empty() {
+                    println!("No sessions found.");
+                    return Ok(());
+                }
+
+                let mut ordered: Vec<_> = groups.into_iter().collect();
+                ordered.sort_by_key(|(name, _)| (name.is_none(), name != &current, name.clone()));
+                for (name, summaries) in ordered {
+                    let marker = if name.is_some() && name == current { " (current)" } else { "" };
+                    println!("🌿 {}{}", name.as_deref().unwrap_or("no branch"), marker);
+                    for summary in summaries {
+                        println!(
+                            "  {}  {}  {} ({} messages)",
+                            summary.id,
+                            summary.updated_at.format("%Y-%m-%d %H:%M"),
+                            summary.title,
+                            summary.message_count
+                        );
+                    }
+                }
+            }
+            SessionsCommands::Share { id, format, output } => {
+                self.share_session(&id, &format, output).await?;
+            }
+            SessionsCommands::Log { id } => {
+                let manager = crate::conversation::ConversationManager::new();
+                let id = resolve_session_id(&manager, &id)?;
+                let events = manager.events(&id)?;
+                if events.is_empty() {
+                    println!("Session {} was saved before event logging and has no event log.", id);
+                }
+                for event in events {
+                    println!("{:>5}  {}  {}", event.seq, event.at.format("%Y-%m-%d %H:%M:%S"), event.kind.describe());
+                }
+            }
+            SessionsCommands::Replay { id, until } => {
+                let manager = crate::conversation::ConversationManager::new();
+                let id = resolve_session_id(&manager, &id)?;
+                let conversation = manager.replay_conversation(&id, until)?;
+                print!("{}", conversation.to_markdown(self.config.get_config().response_language().as_ref()));
+                if let Some(until) = until {
+                    println!("_Replayed up to event {} of session {}_", until, id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 总结会话并生成脱敏报告；模型不可用时以第一条用户消息作为问题描述
+    async fn share_session(&self, id: &str, format: &str, output: Option<std::path::PathBuf>) -> crate::error::Result<()> {
+        use crate::conversation::share::{fallback_summary, parse_summary, summary_prompt, ShareReport};
+
+        if !matches!(format, "markdown" | "md" | "html") {
+            return Err(crate::error::ClaudeError::validation_error(
+                "format",
+                format!("unsupported report format '{}', expected markdown or html", format),
+            ));
+        }
+        let manager = crate::conversation::ConversationManager::new();
+        let conversation = manager.get_conversation(&resolve_session_id(&manager, id)?)?;
+
+        let request = crate::network::ClaudeRequest {
+            model: self.config.get_config().api.default_model.clone(),
+            messages: vec![crate::network::Message {
+                role: "user".to_string(),
+                content: summary_prompt(&conversation).into(),
+            }],
+            max_tokens: 2048,
+            stream: Some(false),
+            tools: None,
+            temperature: Some(0.2),
+            top_p: None,
+            system: None,
+        };
+        let session_summary = match self.client().send_claude_request(request).await {
+            Ok(response) => parse_summary(&response.content),
+            Err(e) => {
+                eprintln!("⚠️  Could not summarize the session ({}), using its first message instead", e);
+                fallback_summary(&conversation)
+            }
+        };
+
+        let report = ShareReport::new(&conversation, session_summary);
+        let content = if format == "html" { report.to_html() } else { report.to_markdown() };
+        match output {
+            Some(path) => {
+                std::fs::write(&path, content)?;
+                println!("📤 Wrote report for \"{}\" to {}", report.title, path.display());
+                if report.redactions > 0 {
+                    println!("🔒 Redacted {} secret(s)", report.redactions);
+                }
+            }
+            None => print!("{}", content),
+        }
+        Ok(())
+    }
+
+    /// 轮询仓库中打开的拉取请求，评审尚未评审过的头提交并发表评论
+    async fn handle_bot_review_command(
+        &self,
+        repo: String,
+        interval: std::time::Duration,
+        once: bool,
+        dry_run: bool,
+        include_drafts: bool,
+        batch: bool,
+    ) -> crate::error::Result<()> {
+        use crate::github::review::ReviewLedger;
+        use crate::github::{GitHubClient, RepoRef};
+
+        let repo = RepoRef::parse(&repo)?;
+        let github = GitHubClient::from_env();
+        if !github.has_token() && !dry_run {
+            return Err(crate::error::ClaudeError::auth_error(
+                "Set GITHUB_TOKEN or GH_TOKEN to post reviews (or use --dry-run)",
+            ));
+        }
+        let mut ledger = ReviewLedger::load(ReviewLedger::default_path())?;
+        let collector = if batch { Some(self.batch_collector()?) } else { None };
+        println!(
+            "🤖 Reviewing pull requests in {} every {}s ({} reviews recorded in {})",
+            repo,
+            interval.as_secs(),
+            ledger.records().len(),
+            ledger.path().display()
+        );
+
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = tokio::signal::ctrl_c() => {
+                    println!("👋 Stopping review bot");
+                    break;
+                }
+            }
+
+            let pulls = match github.list_open_pull_requests(&repo).await {
+                Ok(pulls) => pulls,
+                Err(e) => {
+                    println!("⚠️  Failed to list pull requests: {}", e);
+                    if once {
+                        return Err(e);
+                    }
+                    continue;
+                }
+            };
+            let pending: Vec<_> = pulls
+                .into_iter()
+                .filter(|pr| include_drafts || !pr.draft)
+                .filter(|pr| !ledger.is_reviewed(&repo, pr.number, &pr.head.sha))
+                .collect();
+            if pending.is_empty() {
+                tracing::debug!("No pull requests to review in {}", repo);
+            }
+
+            // 批处理时同一轮的评审并发进行，请求合并成一个批次
+            let reviews: Vec<_> = if collector.is_some() {
+                futures::future::join_all(
+                    pending.iter().map(|pr| self.review_pull_request(&github, &repo, pr, dry_run, collector.as_ref())),
+                )
+                .await
+            } else {
+                let mut reviews = Vec::new();
+                for pr in &pending {
+                    reviews.push(self.review_pull_request(&github, &repo, pr, dry_run, None).await);
+                }
+                reviews
+            };
+            for (pr, review) in pending.iter().zip(reviews) {
+                match review {
+                    Ok(comments) if !dry_run => ledger.record(&repo, pr.number, &pr.head.sha, comments)?,
+                    Ok(_) => {}
+                    Err(e) => println!("❌ #{} {}: {}", pr.number, pr.title, e),
+                }
+            }
+            if let Some(collector) = &collector {
+                print_batch_summary(&collector.summary());
+            }
+
+            if once {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 评审单个拉取请求，返回发表的行内评论数
+    async fn review_pull_request(
+        &self,
+        github: &crate::github::GitHubClient,
+        repo: &crate::github::RepoRef,
+        pr: &crate::github::PullRequest,
+        dry_run: bool,
+        collector: Option<&Arc<crate::network::batch::BatchCollector>>,
+    ) -> crate::error::Result<usize> {
+        use crate::github::review::{parse_review_response, parse_unified_diff, review_prompt};
+
+        /// 提示中差异的最大字符数
+        const MAX_DIFF_CHARS: usize = 120_000;
+
+        println!("🔍 Reviewing #{} {} ({})", pr.number, pr.title, &pr.head.sha[..pr.head.sha.len().min(7)]);
+        let diff = github.pull_request_diff(repo, pr.number).await?;
+        let files = parse_unified_diff(&diff);
+        if files.is_empty() {
+            println!("   No reviewable changes");
+            return Ok(0);
+        }
+
+        let request = crate::network::ClaudeRequest {
+            model: self.config.get_config().api.default_model.clone(),
+            messages: vec![crate::network::Message {
+                role: "user".to_string(),
+                content: review_prompt(pr, &files, MAX_DIFF_CHARS).into(),
+            }],
+            max_tokens: 4096,
+            stream: Some(false),
+            tools: None,
+            temperature: None,
+            top_p: None,
+            system: Some("You are a meticulous senior engineer reviewing a pull request.".to_string()),
+        };
+        let response = match collector {
+            Some(collector) => collector.send(request).await?,
+            None => self.client().send_claude_request(request).await?,
+        };
+        let draft = parse_review_response(&response.content, &files);
+        let body = format!("🤖 Automated review\n\n{}", draft.summary);
+
+        if dry_run {
+            println!("{}", crate::ui::markdown::render_markdown(&body));
+            for comment in &draft.comments {
+                println!("   {}:{} {}", comment.path, comment.line, comment.body);
+            }
+        } else {
+            github.create_review(repo, pr.number, &pr.head.sha, &body, &draft.comments).await?;
+        }
+        println!(
+            "   {} {} inline comment(s){}",
+            if dry_run { "Drafted" } else { "Posted" },
+            draft.comments.len(),
+            if draft.dropped > 0 { format!(", dropped {} outside the diff", draft.dropped) } else { String::new() }
+        );
+        Ok(draft.comments.len())
+    }
+
+    /// 分诊打开的议题：聚合疑似重复的议题，建议标签和优先级，确认后通过 API 应用
+    async fn handle_triage_command(
+        &self,
+        repo: String,
+        limit: usize,
+        threshold: f32,
+        apply: bool,
+        batch: bool,
+    ) -> crate::error::Result<()> {
+        use crate::github::triage::{
+            cluster_duplicates, embed_issues, parse_triage_response, priority_label, triage_prompt,
+        };
+        use crate::github::{GitHubClient, RepoRef};
+        use std::io::IsTerminal;
+
+        /// 每次请求模型分诊的议题数
+        const BATCH_SIZE: usize = 20;
+
+        let repo = RepoRef::parse(&repo)?;
+        let github = GitHubClient::from_env();
+        if apply && !github.has_token() {
+            return Err(crate::error::ClaudeError::auth_error("Set GITHUB_TOKEN or GH_TOKEN to apply triage results"));
+        }
+        if apply && !std::io::stdin().is_terminal() {
+            return Err(crate::error::ClaudeError::validation_error(
+                "apply",
+                "--apply asks for confirmation and needs an interactive terminal",
+            ));
+        }
+
+        let issues = github.list_open_issues(&repo, limit).await?;
+        if issues.is_empty() {
+            println!("No open issues in {}", repo);
+            return Ok(());
+        }
+        let labels = github.list_labels(&repo).await?;
+        println!("🗂️  Triaging {} open issue(s) in {} ({} labels available)", issues.len(), repo, labels.len());
+        let title = |number: u64| issues.iter().find(|i| i.number == number).map(|i| i.title.as_str()).unwrap_or("");
+
+        let groups = cluster_duplicates(&issues, &embed_issues(&issues), threshold);
+        if !groups.is_empty() {
+            println!("\n🔁 Possible duplicates:");
+            for group in &groups {
+                println!("  #{} {}", group.original, title(group.original));
+                for (number, similarity) in &group.duplicates {
+                    println!("    ↳ #{} {} ({:.0}% similar)", number, title(*number), similarity * 100.0);
+                }
+            }
+        }
+
+        let request = |chunk: &[crate::github::Issue]| crate::network::ClaudeRequest {
+            model: self.config.get_config().api.default_model.clone(),
+            messages: vec![crate::network::Message {
+                role: "user".to_string(),
+                content: triage_prompt(chunk, &labels).into(),
+            }],
+            max_tokens: 4096,
+            stream: Some(false),
+            tools: None,
+            temperature: Some(0.0),
+            top_p: None,
+            system: None,
+        };
+        let mut suggestions = Vec::new();
+        if batch {
+            let collector = self.batch_collector()?;
+            let responses = futures::future::join_all(
+                issues.chunks(BATCH_SIZE).map(|chunk| collector.send(request(chunk))),
+            )
+            .await;
+            for (chunk, response) in issues.chunks(BATCH_SIZE).zip(responses) {
+                suggestions.extend(parse_triage_response(&response?.content, chunk, &labels));
+            }
+            print_batch_summary(&collector.summary());
+        } else {
+            for chunk in issues.chunks(BATCH_SIZE) {
+                let response = self.client().send_claude_request(request(chunk)).await?;
+                suggestions.extend(parse_triage_response(&response.content, chunk, &labels));
+            }
+        }
+
+        println!("\n🏷️  Suggestions:");
+        for suggestion in &suggestions {
+            println!(
+                "  #{} {}\n     labels: {}  priority: {}  {}",
+                suggestion.number,
+                title(suggestion.number),
+                if suggestion.labels.is_empty() { "-".to_string() } else { suggestion.labels.join(", ") },
+                suggestion.priority.as_deref().unwrap_or("-"),
+                suggestion.reason
+            );
+        }
+        if !apply {
+            println!("\n💡 Re-run with --apply to label issues after confirming each one");
+            return Ok(());
+        }
+
+        let duplicate_label = labels.iter().find(|l| l.name.eq_ignore_ascii_case("duplicate")).map(|l| l.name.clone());
+        let mut apply_all = false;
+        for suggestion in &suggestions {
+            let mut to_add = suggestion.labels.clone();
+            if let Some(label) = suggestion.priority.as_deref().and_then(|p| priority_label(p, &labels)) {
+                to_add.push(label.name.clone());
+            }
+            let original = groups
+                .iter()
+                .find(|g| g.duplicates.iter().any(|(n, _)| *n == suggestion.number))
+                .map(|g| g.original);
+            if let (Some(_), Some(label)) = (original, &duplicate_label) {
+                to_add.push(label.clone());
+            }
+            to_add.dedup();
+            if to_add.is_empty() && original.is_none() {
+                continue;
+            }
+
+            if !apply_all {
+                let mut plan = format!("#{}: add [{}]", suggestion.number, to_add.join(", "));
+                if let Some(original) = original {
+                    plan.push_str(&format!(" and comment \"possible duplicate of #{}\"", original));
+                }
+                match prompt_line(&format!("{}? [y/N/a(ll)/q(uit)] ", plan))?.as_str() {
+                    "y" | "yes" => {}
+                    "a" | "all" => apply_all = true,
+                    "q" | "quit" => break,
+                    _ => continue,
+                }
+            }
+
+            if !to_add.is_empty() {
+                if let Err(e) = github.add_labels(&repo, suggestion.number, &to_add).await {
+                    println!("❌ #{}: {}", suggestion.number, e);
+                    continue;
+                }
+            }
+            if let Some(original) = original {
+                let comment = format!("This looks like a possible duplicate of #{}.", original);
+                if let Err(e) = github.create_issue_comment(&repo, suggestion.number, &comment).await {
+                    println!("❌ #{}: {}", suggestion.number, e);
+                    continue;
+                }
+            }
+            println!("✅ Updated #{}", suggestion.number);
+        }
+        Ok(())
+    }
+
+    /// 导出最近更新的对话，Markdown 的标题和角色名称使用对话语言
+    async fn handle_export_command(&self, format: String, output: Option<String>) -> crate::error::Result<()> {
+        let manager = crate::conversation::ConversationManager::new();
+        let Some(latest) = manager.list_conversations().unwrap_or_default().into_iter().next() else {
+            println!("No conversations to export.");
+            return Ok(());
+        };
+        let conversation = manager.get_conversation(&latest.id)?;
+
+        let content = match format.as_str() {
+            "markdown" | "md" => conversation.to_markdown(self.config.get_config().response_language().as_ref()),
+            "json" => serde_json::to_string_pretty(&conversation)?,
+            other => {
+                return Err(crate::error::ClaudeError::validation_error(
+                    "format",
+                    format!("unsupported export format '{}', expected markdown or json", other),
+                ))
+            }
+        };
+
+        match output {
+            Some(path) => {
+                std::fs::write(&path, content)?;
+                println!("📤 Exported \"{}\" to {}", conversation.title, path);
+            }
+            None => print!("{}", content),
+        }
+        Ok(())
+    }
+
+    /// 处理状态导出命令
+    async fn handle_export_state_command(
+        &self,
+        file: std::path::PathBuf,
+        encrypt_secrets: bool,
+        include_sessions: bool,
+    ) -> crate::error::Result<()> {
+        use crate::config::bundle::{export_state, ExportOptions, SecretHandling, StateLocations};
+
+        let passphrase = if encrypt_secrets {
+            Some(state_passphrase().ok_or_else(|| {
+                crate::error::ClaudeError::validation_error(
+                    STATE_PASSPHRASE_ENV,
+                    "Set CLAUDE_STATE_PASSPHRASE to encrypt secrets in the bundle",
+                )
+            })?)
+        } else {
+            None
+        };
+        let options = ExportOptions { passphrase, include_sessions };
+        let locations = StateLocations::current(self.config.config_path());
+        let manifest = export_state(self.config.get_config(), &locations, &file, &options)?;
+
+        println!("📦 Exported state to {}", file.display());
+        println!("   Sessions: {}", manifest.sessions);
+        println!("   Memory: {}", if manifest.memory { "included" } else { "none" });
+        match (manifest.secrets, manifest.secret_names.is_empty()) {
+            (_, true) => {}
+            (SecretHandling::Encrypted, false) => {
+                println!("🔐 Encrypted {} secret(s) with your passphrase", manifest.secret_names.len())
+            }
+            (SecretHandling::Excluded, false) => {
+                println!("🔒 Left out {} secret(s): {}", manifest.secret_names.len(), manifest.secret_names.join(", "));
+                println!("💡 Use --encrypt-secrets with CLAUDE_STATE_PASSPHRASE to include them");
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理状态导入命令
+    async fn handle_import_state_command(&self, file: std::path::PathBuf, force: bool) -> crate::error::Result<()> {
+        use crate::config::bundle::{import_state, ImportOptions, StateLocations};
+
+        let options = ImportOptions {
+            passphrase: state_passphrase(),
+            overwrite_sessions: force,
+        };
+        let locations = StateLocations::current(self.config.config_path());
+        let summary = import_state(&file, &locations, &options)?;
+
+        println!(
+            "📥 Imported state from {} (created {})",
+            file.display(),
+            summary.manifest.created_at.format("%Y-%m-%d %H:%M")
+        );
+        println!("   Config: {}", summary.config_path.display());
+        if let Some(backup) = &summary.backup_path {
+            println!("   Previous config saved to {}", backup.display());
+        }
+        println!("   Sessions: {} imported, {} already present", summary.sessions_imported, summary.sessions_skipped);
+        if summary.secrets_restored > 0 {
+            println!("🔐 Restored {} secret(s)", summary.secrets_restored);
+        }
+        if summary.secrets_locked {
+            println!("⚠️  The bundle contains encrypted secrets; set CLAUDE_STATE_PASSPHRASE and import again to restore them");
+        }
+        Ok(())
+    }
+
+    /// 处理会话导入命令
+    async fn handle_import_sessions_command(&self, path: Option<std::path::PathBuf>, force: bool) -> crate::error::Result<()> {
+        use crate::conversation::import::{ClaudeCodeImporter, ImportStatus};
+
+        let source = match path {
+            Some(path) => path,
+            None => ClaudeCodeImporter::default_projects_dir()?,
+        };
+        if !source.exists() {
+            println!("No Claude Code sessions found at {}", source.display());
+            return Ok(());
+        }
+
+        let importer = ClaudeCodeImporter::new(&source).overwrite(force);
+        let report = tokio::task::spawn_blocking(move || {
+            let mut manager = crate::conversation::ConversationManager::new();
+            importer.import_all(&mut manager)
+        })
+        .await
+        .map_err(|e| crate::error::ClaudeError::General(format!("Session import failed: {}", e)))?;
+
+        for (file, status) in &report.sessions {
+            match status {
+                ImportStatus::Imported { messages } => {
+                    let id = file.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+                    println!("  ✅ {} ({} messages)", id, messages);
+                }
+                ImportStatus::Failed(reason) => println!("  ⚠️  {}: {}", file.display(), reason),
+                ImportStatus::AlreadyImported | ImportStatus::Empty => {}
+            }
+        }
+        println!(
+            "📥 Imported {} session(s), skipped {}, failed {}",
+            report.imported(),
+            report.skipped(),
+            report.failed()
+        );
+        if report.imported() > 0 {
+            println!("💡 Use `claude --resume <session-id>` to continue an imported session");
+        }
+        Ok(())
+    }
+
+    /// 处理冲突解决命令：逐个文件、逐个冲突块请模型给出方案，由用户决定是否采用
+    async fn handle_git_resolve_command(&self, verify: Option<String>, no_verify: bool) -> crate::error::Result<()> {
+        use crate::git::resolve::{detect_verify_command, ConflictedFile, Resolution};
+
+        let git = crate::git::GitManager::new(std::env::current_dir()?);
+        if !git.is_git_repository().await {
+            println!("❌ Not in a Git repository");
+            return Ok(());
+        }
+        let root = git.repository_root().await?;
+        let files = git.conflicted_files().await?;
+        if files.is_empty() {
+            println!("✅ No merge conflicts to resolve");
+            return Ok(());
+        }
+        println!("🔀 {} conflicted file(s)", files.len());
+
+        let model = self.config.get_config().api.default_model.clone();
+        let mut resolved = Vec::new();
+        let mut quit = false;
+
+        for path in &files {
+            if quit {
+                break;
+            }
+            let full_path = root.join(path);
+            let content = match tokio::fs::read_to_string(&full_path).await {
+                Ok(content) => content,
+                Err(e) => {
+                    println!("⚠️  Skipping {}: {}", path, e);
+                    continue;
+                }
+            };
+            let file = ConflictedFile::parse(&content);
+            let hunks = file.hunks();
+            if hunks.is_empty() {
+                println!("⚠️  {} has no conflict markers; resolve it manually", path);
+                continue;
+            }
+
+            let mut resolutions = Vec::new();
+            for (index, hunk) in hunks.iter().enumerate() {
+                println!("\n── {} · conflict {}/{} (line {}) ──", path, index + 1, hunks.len(), hunk.line);
+                print!("{}", hunk.original());
+
+                let proposal = match self.propose_conflict_resolution(path, &file, index, &model).await {
+                    Ok(text) => {
+                        println!("💡 Proposed resolution:\n{}", text);
+                        Some(text)
+                    }
+                    Err(e) => {
+                        println!("⚠️  Could not get a proposal: {}", e);
+                        None
+                    }
+                };
+
+                let resolution = loop {
+                    let options = if proposal.is_some() {
+                        "[a]ccept / [o]urs / [t]heirs / [b]oth / [s]kip / [q]uit"
+                    } else {
+                        "[o]urs / [t]heirs / [b]oth / [s]kip / [q]uit"
+                    };
+                    match prompt_line(&format!("{} > ", options))?.as_str() {
+                        "a" | "accept" if proposal.is_some() => break Resolution::Custom(proposal.clone().unwrap_or_default()),
+                        "o" | "ours" => break Resolution::Ours,
+                        "t" | "theirs" => break Resolution::Theirs,
+                        "b" | "both" => break Resolution::Both,
+                        "s" | "skip" | "" => break Resolution::Skip,
+                        "q" | "quit" => {
+                            quit = true;
+                            break Resolution::Skip;
+                        }
+                        _ => continue,
+                    }
+                };
+                resolutions.push(resolution);
+                if quit {
+                    break;
+                }
+            }
+
+            if resolutions.iter().all(|r| *r == Resolution::Skip) {
+                continue;
+            }
+            tokio::fs::write(&full_path, file.render(&resolutions)).await?;
+            if resolutions.len() == hunks.len() && !resolutions.contains(&Resolution::Skip) {
+                git.add_files(std::slice::from_ref(path)).await?;
+                println!("✅ {} resolved and staged", path);
+                resolved.push(path.clone());
+            } else {
+                println!("📝 {} partially resolved; remaining conflicts are left in place", path);
+            }
+        }
+
+        println!("\n🔀 Resolved {}/{} file(s)", resolved.len(), files.len());
+        if resolved.is_empty() || no_verify {
+            return Ok(());
+        }
+        let Some(command) = verify.or_else(|| detect_verify_command(&root)) else {
+            println!("💡 No build/test command detected; pass --verify <command> to check the result");
+            return Ok(());
+        };
+
+        println!("🧪 Verifying: {}", command);
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let status = tokio::process::Command::new(shell)
+            .arg(flag)
+            .arg(&command)
+            .current_dir(&root)
+            .status()
+            .await?;
+        if status.success() {
+            println!("✅ Verification passed");
+        } else {
+            println!("❌ Verification failed ({}); review the resolutions before committing", status);
+        }
+        Ok(())
+    }
+
+    /// 驱动 git bisect：在每个待测提交上通过 ProcessManager 运行测试，结束后总是复位
+    async fn handle_git_bisect_command(
+        &self,
+        test: String,
+        good: Option<String>,
+        bad: String,
+        timeout: u64,
+    ) -> crate::error::Result<()> {
+        let git = crate::git::GitManager::new(std::env::current_dir()?);
+        if !git.is_git_repository().await {
+            println!("❌ Not in a Git repository");
+            return Ok(());
+        }
+        if git.get_status().await?.has_changes {
+            return Err(crate::error::ClaudeError::validation_error(
+                "git",
+                "the working tree has uncommitted changes; commit or stash them before bisecting",
+            ));
+        }
+        let good = match good {
+            Some(good) => good,
+            None => git.latest_tag(&format!("{}^", bad)).await?.ok_or_else(|| {
+                crate::error::ClaudeError::validation_error("good", "no tag found before the bad commit; pass --good <commit>")
+            })?,
+        };
+
+        println!("🔎 Bisecting {}..{} with `{}`", good, bad, test);
+        let result = self.run_bisect(&git, &test, &good, &bad, timeout).await;
+        if let Err(e) = git.bisect(&["reset"]).await {
+            println!("⚠️  Could not reset bisect state: {}", e);
+        }
+        let Some((commit, failing_output)) = result? else {
+            return Ok(());
+        };
+
+        println!("\n🎯 First bad commit: {}", commit);
+        let show = git.show_commit(&commit).await?;
+        if let Some(header) = show.split("\n\n").nth(1) {
+            println!("{}", header.trim_end());
+        }
+        let request = crate::network::ClaudeRequest {
+            model: self.config.get_config().api.default_model.clone(),
+            messages: vec![crate::network::Message {
+                role: "user".to_string(),
+                content: crate::git::bisect::culprit_prompt(&test, &show, &failing_output).into(),
+            }],
+            max_tokens: 4096,
+            stream: Some(false),
+            tools: None,
+            temperature: Some(0.0),
+            top_p: None,
+            system: None,
+        };
+        match self.client().send_claude_request(request).await {
+            Ok(response) => println!("\n{}", crate::ui::markdown::render_markdown(&response.content)),
+            Err(e) => println!("⚠️  Could not analyze the commit: {}", e),
+        }
+        Ok(())
+    }
+
+    /// 二分循环；返回首个坏提交及其测试输出，无法确定时返回 None
+    async fn run_bisect(
+        &self,
+        git: &crate::git::GitManager,
+        test: &str,
+        good: &str,
+        bad: &str,
+        timeout: u64,
+    ) -> crate::error::Result<Option<(String, Vec<String>)>> {
+        use crate::git::bisect::{parse_bisect_output, BisectState, Verdict};
+
+        /// 防止异常输出导致无限循环的步数上限
+        const MAX_STEPS: usize = 64;
+
+        let root = git.repository_root().await?;
+        let processes = crate::process::ProcessManager::new();
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let mut outputs: std::collections::HashMap<String, Vec<String>> = std::collections::HashMap::new();
+
+        let mut state = parse_bisect_output(&git.bisect(&["start", bad, good]).await?);
+        for step in 1..=MAX_STEPS {
+            match state {
+                Some(BisectState::Found { commit }) => {
+                    let output = outputs.remove(&commit).unwrap_or_default();
+                    return Ok(Some((commit, output)));
+                }
+                Some(BisectState::Inconclusive) => {
+                    println!("🤷 Only skipped commits are left; the culprit could not be isolated");
+                    return Ok(None);
+                }
+                Some(BisectState::Testing { commit, steps_left }) => {
+                    let remaining = steps_left.map(|n| format!(", ~{} left", n)).unwrap_or_default();
+                    print!("  step {}{} · {} … ", step, remaining, &commit[..commit.len().min(10)]);
+                    std::io::Write::flush(&mut std::io::stdout())?;
+
+                    let config = crate::process::ProcessConfig {
+                        name: format!("bisect-{}", step),
+                        command: shell.to_string(),
+                        args: vec![flag.to_string(), test.to_string()],
+                        env: std::collections::HashMap::new(),
+                        working_dir: Some(root.to_string_lossy().into_owned()),
+                        timeout: Some(timeout),
+                        capture_output: true,
+                        auto_restart: false,
+                        limits: Default::default(),
+                    };
+                    let process_id = processes.start_process(config).await?;
+                    let verdict = match processes.wait_for_process(&process_id, Some(timeout)).await {
+                        Ok(output) => {
+                            let verdict = Verdict::from_exit_code(output.exit_code);
+                            outputs.insert(commit.clone(), output.stdout.into_iter().chain(output.stderr).collect());
+                            verdict
+                        }
+                        Err(_) => {
+                            print!("timed out, ");
+                            Verdict::Skip
+                        }
+                    };
+                    let Some(subcommand) = verdict.subcommand() else {
+                        println!("aborted");
+                        println!("❌ The test command was killed or exited with 128 or above; stopping");
+                        return Ok(None);
+                    };
+                    println!("{}", subcommand);
+                    state = parse_bisect_output(&git.bisect(&[subcommand]).await?);
+                }
+                None => {
+                    return Err(crate::error::ClaudeError::General(
+                        "Unexpected git bisect output; check the good and bad commits".to_string(),
+                    ))
+                }
+            }
+        }
+        println!("❌ Gave up after {} steps", MAX_STEPS);
+        Ok(None)
+    }
+
+    /// 请模型为单个冲突块给出合并结果
+    async fn propose_conflict_resolution(
+        &self,
+        path: &str,
+        file: &crate::git::resolve::ConflictedFile,
+        hunk_index: usize,
+        model: &str,
+    ) -> crate::error::Result<String> {
+        use crate::git::resolve::{extract_resolution, resolution_prompt};
+
+        let prompt = resolution_prompt(path, file, hunk_index)
+            .ok_or_else(|| crate::error::ClaudeError::General("Conflict not found".to_string()))?;
+        let request = crate::network::ClaudeRequest {
+            model: model.to_string(),
+            messages: vec![crate::network::Message {
+                role: "user".to_string(),
+                content: prompt.into(),
+            }],
+            max_tokens: 4096,
+            stream: Some(false),
+            tools: None,
+            temperature: Some(0.0),
+            top_p: None,
+            system: Some("You are resolving git merge conflicts. Preserve the intent of both sides and keep the surrounding code style.".to_string()),
+        };
+        let response = self.client().send_claude_request(request).await?;
+        Ok(extract_resolution(&response.content))
+    }
+
+    /// 处理项目范围的搜索替换命令
+    async fn handle_replace_command(
+        &self,
+        spec: crate::fs::replace::ReplaceSpec,
+        yes: bool,
+        dry_run: bool,
+    ) -> crate::error::Result<()> {
+        use crate::fs::undo::UndoJournal;
+        use std::io::IsTerminal;
+
+        let root = std::env::current_dir()?;
+        let plan = {
+            let root = root.clone();
+            let spec = spec.clone();
+            tokio::task::spawn_blocking(move || crate::fs::replace::plan(&root, &spec))
+                .await
+                .map_err(|e| crate::error::ClaudeError::General(format!("Search failed: {}", e)))??
+        };
+        if plan.is_empty() {
+            println!("No matches for {}", spec.pattern);
+            return Ok(());
+        }
+
+        print!("{}", plan.preview());
+        println!("🔎 {} match(es) in {} file(s)", plan.total_matches(), plan.files.len());
+        if dry_run {
+            return Ok(());
+        }
+        if !yes {
+            if !std::io::stdin().is_terminal() {
+                println!("Run with --yes to apply these changes.");
+                return Ok(());
+            }
+            if !matches!(prompt_line("Apply these changes? [y/N] ")?.as_str(), "y" | "yes") {
+                println!("Cancelled.");
+                return Ok(());
+            }
+        }
+
+        let description = format!("replace {} -> {}", spec.pattern, spec.replacement);
+        let entry = plan.apply(&UndoJournal::new(UndoJournal::default_dir()), &description)?;
+        println!("✅ Updated {} file(s). Undo with `claude replace --undo` ({})", entry.files.len(), entry.id);
+        Ok(())
+    }
+
+    /// 撤销当前项目中最近一次搜索替换
+    fn handle_replace_undo(&self) -> crate::error::Result<()> {
+        use crate::fs::undo::UndoJournal;
+
+        let root = std::env::current_dir()?;
+        let journal = UndoJournal::new(UndoJournal::default_dir());
+        let Some(entry) = journal.list()?.into_iter().find(|entry| entry.touches(&root)) else {
+            println!("Nothing to undo in {}", root.display());
+            return Ok(());
+        };
+
+        let outcome = journal.undo(&entry, false)?;
+        println!("↩️  Undid \"{}\" from {}", entry.description, entry.created_at.format("%Y-%m-%d %H:%M"));
+        for path in &outcome.restored {
+            println!("  restored {}", path.strip_prefix(&root).unwrap_or(path).display());
+        }
+        if !outcome.conflicts.is_empty() {
+            println!("⚠️  These files changed after the replacement and were left alone:");
+            for path in &outcome.conflicts {
+                println!("  {}", path.strip_prefix(&root).unwrap_or(path).display());
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理基准测试命令
+    async fn handle_bench_command(
+        &self,
+        suite: std::path::PathBuf,
+        models: Vec<String>,
+        replay: bool,
+        max_turns: u32,
+        json: bool,
+        batch: bool,
+    ) -> crate::error::Result<()> {
+        use crate::bench::{BatchProvider, BenchConfig, BenchProvider, LiveProvider, ReplayProvider};
+
+        let tasks = crate::bench::load_suite(&suite)?;
+        if tasks.is_empty() {
+            println!("No bench tasks found in {}", suite.display());
+            return Ok(());
+        }
+
+        let mut providers: Vec<Box<dyn BenchProvider>> = Vec::new();
+        if replay {
+            providers.push(Box::new(ReplayProvider));
+        }
+        let models = if models.is_empty() && !replay {
+            vec![self.config.get_config().api.default_model.clone()]
+        } else {
+            models
+        };
+        let collector = if batch { Some(self.batch_collector()?) } else { None };
+        for model in models {
+            match &collector {
+                Some(collector) => providers.push(Box::new(BatchProvider::new(collector.clone(), model))),
+                None => providers.push(Box::new(LiveProvider::new(self.client().clone(), model))),
+            }
+        }
+
+        let config = BenchConfig { max_turns, ..Default::default() };
+        let mut results = Vec::new();
+        for provider in &providers {
+            if collector.is_some() {
+                // 任务并发运行，每个回合的请求合并成一个批次
+                if !json {
+                    println!("⏱️  {} / {} task(s)", provider.label(), tasks.len());
+                }
+                let batch_results = futures::future::join_all(
+                    tasks.iter().map(|task| crate::bench::run_task(task, provider.as_ref(), &config)),
+                )
+                .await;
+                for result in batch_results {
+                    if let (false, Some(error)) = (json, &result.error) {
+                        println!("   ⚠️  {}: {}", result.task, error);
+                    }
+                    results.push(result);
+                }
+                continue;
+            }
+            for task in &tasks {
+                if !json {
+                    println!("⏱️  {} / {}", provider.label(), task.name);
+                }
+                let result = crate::bench::run_task(task, provider.as_ref(), &config).await;
+                if let (false, Some(error)) = (json, &result.error) {
+                    println!("   ⚠️  {}", error);
+                }
+                results.push(result);
+            }
+        }
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&results)?);
+        } else {
+            println!();
+            print!("{}", crate::bench::comparison_table(&results));
+            if let Some(collector) = &collector {
+                print_batch_summary(&collector.summary());
+            }
+        }
+        let passed = results.iter().filter(|result| result.success).count();
+        crate::network::webhooks::notify(crate::network::webhooks::WebhookEvent::JobCompleted {
+            job: format!("bench {}", suite.display()),
+            summary: format!("{} of {} run(s) passed", passed, results.len()),
+        });
+        Ok(())
+    }
+
+    /// 创建批处理收集器：用量记入成本账本，批次进度显示在标准错误上
+    fn batch_collector(&self) -> crate::error::Result<Arc<crate::network::batch::BatchCollector>> {
+        let ledger = crate::cost::CostTracker::new(crate::cost::CostTracker::default_dir())?;
+        let collector = crate::network::batch::BatchCollector::new(self.client().clone())
+            .with_ledger(ledger)
+            .with_progress(|batch| {
+                let counts = &batch.request_counts;
+                eprintln!(
+                    "📦 Batch {}: {}/{} done ({})",
+                    batch.id,
+                    counts.total() - counts.processing,
+                    counts.total(),
+                    batch.processing_status
+                );
+            });
+        Ok(Arc::new(collector))
+    }
+
+    /// 处理仓库健康报告命令
+    async fn handle_health_command(
+        &self,
+        path: Option<std::path::PathBuf>,
+        max_file_lines: usize,
+        offline: bool,
+        no_ai: bool,
+        report_options: HealthReportOptions,
+    ) -> crate::error::Result<()> {
+        let root = match path {
+            Some(path) => path,
+            None => std::env::current_dir()?,
+        };
+        let options = crate::health::HealthOptions { max_file_lines, check_dependencies: !offline };
+        println!("🩺 Analyzing {}...", root.display());
+        let mut report = crate::health::analyze(&root, &options).await?;
+
+        if !no_ai {
+            let request = crate::network::ClaudeRequest {
+                model: self.config.get_config().api.default_model.clone(),
+                messages: vec![crate::network::Message {
+                    role: "user".to_string(),
+                    content: report.prompt().into(),
+                }],
+                max_tokens: 1024,
+                stream: Some(false),
+                tools: None,
+                temperature: None,
+                top_p: None,
+                system: None,
+            };
+            // 报告不需要立即结果，让交互式会话的请求先走
+            match crate::network::dispatch::background(self.client().send_claude_request(request)).await {
+                Ok(response) => report.recommendations = Some(response.content),
+                Err(e) => eprintln!("⚠️  Skipping recommendations: {}", e),
+            }
+        }
+
+        let markdown = report.to_markdown();
+        match report_options.output {
+            Some(output) => {
+                std::fs::write(&output, &markdown)?;
+                println!("✅ Health score {}/100 ({}); report written to {}", report.score(), report.grade(), output.display());
+            }
+            None => println!("{}", crate::ui::markdown::render_markdown(&markdown)),
+        }
+        if report_options.save {
+            let mut saved = crate::reports::Report::new("health", format!("Health report for {}", root.display()));
+            saved.created_at = report.generated_at;
+            saved.chart(crate::reports::Chart::bar(
+                format!("Health score {}/100 ({})", report.score(), report.grade()),
+                "Check",
+                "Score",
+                report.checks.iter().filter_map(|check| Some((check.name.clone(), check.score? as f64))).collect(),
+            ));
+            // 正文自带一级标题，报告已经有标题
+            saved.markdown(markdown.lines().skip_while(|line| !line.starts_with("## ")).collect::<Vec<_>>().join("\n"));
+            self.publish_report(&saved, report_options.email).await?;
+        }
+        Ok(())
+    }
+
+    /// 处理按规格生成代码命令
+    async fn handle_generate_command(
+        &self,
+        spec_path: std::path::PathBuf,
+        max_repairs: Option<u32>,
+        model: Option<String>,
+    ) -> crate::error::Result<()> {
+        use crate::agent::generate::{GenerationSpec, GenerationStatus, Generator};
+
+        let spec = GenerationSpec::load(&spec_path)?;
+        let mut generator = Generator::new(std::env::current_dir()?, &spec);
+        if let Some(max_repairs) = max_repairs {
+            generator = generator.with_max_repairs(max_repairs);
+        }
+        let repairs = max_repairs.unwrap_or(spec.max_repairs);
+        match generator.validate_command() {
+            Some(command) => println!("🛠️  Generating {}; validating with `{}`, up to {} repair round(s)", spec.name, command, repairs),
+            None => println!("🛠️  Generating {}; no build/test command detected, files will not be validated", spec.name),
+        }
+
+        let client = self.client().clone();
+        let model = model.unwrap_or_else(|| self.config.get_config().api.default_model.clone());
+        let ask = |messages: Vec<crate::network::Message>| {
+            let (client, model) = (client.clone(), model.clone());
+            async move {
+                let request = crate::network::ClaudeRequest {
+                    model,
+                    messages,
+                    max_tokens: 8192,
+                    stream: Some(false),
+                    tools: None,
+                    temperature: None,
+                    top_p: None,
+                    system: None,
+                };
+                Ok(client.send_claude_request(request).await?.content)
+            }
+        };
+        let progress = |round: &crate::agent::generate::GenerationRound| {
+            let label = if round.round == 0 { "Generated".to_string() } else { format!("Repair {}", round.round) };
+            let result = match &round.validation {
+                Some(validation) if validation.success => "✅ validation passed".to_string(),
+                Some(validation) => format!("❌ `{}` failed", validation.command),
+                None => "not validated".to_string(),
+            };
+            println!("  {}: wrote {} — {}", label, round.files.join(", "), result);
+        };
+        let outcome = generator.run(&spec, ask, progress).await?;
+
+        for (path, diff) in &outcome.diffs {
+            println!("\n📄 {}\n{}", path, diff.trim_end());
+        }
+        match outcome.status {
+            GenerationStatus::Passed => {
+                println!("\n✅ {} passed validation after {} round(s)", spec.name, outcome.rounds.len());
+                Ok(())
+            }
+            GenerationStatus::Unvalidated => {
+                println!("\n⚠️  Wrote {} file(s) without validation", outcome.diffs.len());
+                Ok(())
+            }
+            GenerationStatus::Failed => {
+                if let Some(validation) = outcome.rounds.last().and_then(|round| round.validation.as_ref()) {
+                    let lines: Vec<&str> = validation.output.lines().collect();
+                    println!("\n{}", lines[lines.len().saturating_sub(20)..].join("\n"));
+                    if self.config.get_config().lessons.enabled {
+                        let lesson = crate::conversation::lessons::Lesson::repeated_failure(
+                            &spec.name,
+                            &validation.command,
+                            outcome.rounds.len(),
+                            &validation.output,
+                        );
+                        crate::conversation::lessons::LessonStore::for_repo(&std::env::current_dir()?)?.record(lesson)?;
+                    }
+                }
+                Err(crate::error::ClaudeError::General(format!(
+                    "{} still fails validation after {} repair round(s)",
+                    spec.name, repairs
+                )))
+            }
+        }
+    }
+
+    /// 保存报告，需要时再发送邮件
+    async fn publish_report(&self, report: &crate::reports::Report, email: bool) -> crate::error::Result<()> {
+        let settings = &self.config.get_config().reports;
+        let saved = report.save(&settings.dir)?;
+        println!("📝 Report saved to {} and {}", saved.markdown.display(), saved.html.display());
+        if email {
+            let Some(smtp) = &settings.smtp else {
+                return Err(crate::error::ClaudeError::config_error("Set reports.smtp in the config file to email reports"));
+            };
+            let recipients = crate::reports::email::send_report(smtp, &saved, &[]).await?;
+            println!("📧 Emailed to {}", recipients.join(", "));
+        }
+        Ok(())
+    }
+
+    /// 处理报告命令
+    async fn handle_run_prompt_command(&self, name: String, vars: Vec<String>, preview: bool, model: Option<String>) -> crate::error::Result<()> {
+        let library = crate::prompts::PromptLibrary::open(&std::env::current_dir()?)?;
+        let (template, prompt) = library.render(&name, &crate::prompts::parse_assignments(&vars)?)?;
+        if preview {
+            println!("{}", prompt);
+            return Ok(());
+        }
+
+        let request = crate::network::ClaudeRequest {
+            model: model.or(template.model).unwrap_or_else(|| self.config.get_config().api.default_model.clone()),
+            messages: vec![crate::network::Message { role: "user".to_string(), content: prompt.into() }],
+            max_tokens: 4096,
+            stream: Some(false),
+            tools: None,
+            temperature: None,
+            top_p: None,
+            system: None,
+        };
+        let response = self.client().send_claude_request(request).await?;
+        println!("{}", crate::ui::markdown::render_markdown(&response.content));
+        Ok(())
+    }
+
+    fn handle_prompts_command(&self, action: PromptsCommands) -> crate::error::Result<()> {
+        let library = crate::prompts::PromptLibrary::open(&std::env::current_dir()?)?;
+        match action {
+            PromptsCommands::List => print_prompt_templates(&library),
+            PromptsCommands::Show { name } => print_prompt_template(&library.get(&name)?, &library.defaults_for(&name)),
+        }
+        Ok(())
+    }
+
+    fn handle_lessons_command(&self, action: LessonsCommands) -> crate::error::Result<()> {
+        let mut store = crate::conversation::lessons::LessonStore::for_repo(&std::env::current_dir()?)?;
         match action {
-            ConfigAction::Show => {
-                let config = self.config.get_config();
-                println!("📋 Current Configuration:");
-                println!("  API Key: {}", if config.api.anthropic_api_key.is_some() { "Set" } else { "Not set" });
-                println!("  Base URL: {}", config.api.base_url);
-                println!("  Default Model: {}", config.api.default_model);
-            },
-            ConfigAction::Get { key } => {
-                match self.config.get_value(&key) {
-                    Ok(value) => println!("{}: {}", key, value),
-                    Err(_) => println!("Configuration key '{}' not found", key),
+            LessonsCommands::List => {
+                if store.lessons().is_empty() {
+                    println!("No lessons recorded for this repository.");
                 }
-            },
-            ConfigAction::Set { key, value } => {
-                // 这里需要实现 set_value 的可变访问
-                println!("⚠️  Configuration setting not yet implemented");
-                println!("Would set: {} = {}", key, value);
-            },
-            ConfigAction::Init { path, format, force } => {
-                let config_path = path.unwrap_or_else(|| "claude-code.yaml".to_string());
-                println!("⚠️  Configuration initialization not yet implemented");
-                println!("Would create config at: {} (format: {:?}, force: {})", config_path, format, force);
-            },
-            ConfigAction::Validate => {
-                // 简单验证
-                let config = self.config.get_config();
-                if config.api.anthropic_api_key.is_some() {
-                    println!("✅ Configuration is valid");
+                let mut lessons: Vec<_> = store.lessons().iter().collect();
+                lessons.sort_by(|a, b| b.occurrences.cmp(&a.occurrences).then(b.last_seen.cmp(&a.last_seen)));
+                for lesson in lessons {
+                    println!("  {} [{}] ×{}  {}", lesson.id, lesson.kind, lesson.occurrences, lesson.summary);
+                }
+            }
+            LessonsCommands::Forget { id } => {
+                if store.forget(&id)? {
+                    println!("🗑️  Forgot lesson {}", id);
                 } else {
-                    println!("❌ Configuration validation failed: API key not set");
+                    println!("No lesson with id {}", id);
                 }
-            },
-            ConfigAction::List => {
-                println!("📁 Configuration file locations:");
-                println!("  - ~/.config/claude-code/config.yaml");
-                println!("  - ./claude-code.yaml");
-                println!("  - ./.claude-code.yaml");
-            },
+            }
+            LessonsCommands::Clear => {
+                let count = store.lessons().len();
+                store.clear()?;
+                println!("🗑️  Cleared {} lesson(s)", count);
+            }
         }
         Ok(())
     }
 
-    /// 处理交互模式命令
-    async fn handle_interactive_command(&self) -> crate::error::Result<()> {
-        use std::io::{self, Write};
-
-        println!("🤖 Claude Code Interactive Mode");
-        println!("Type 'exit' to quit, 'help' for commands");
-        println!("================================");
-
-        loop {
-            print!("claude> ");
-            io::stdout().flush().unwrap();
-
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            let input = input.trim();
-
-            match input {
-                "exit" | "quit" => {
-                    println!("👋 Goodbye!");
-                    break;
-                },
-                "help" => {
-                    self.show_interactive_help();
-                },
-                "clear" => {
-                    self.handle_clear_command().await?;
-                },
-                "status" => {
-                    self.handle_status_command().await?;
-                },
-                "" => continue,
-                _ => {
-                    // 将输入作为聊天消息处理
-                    self.handle_api_command(
-                        input.to_string(),
-                        "claude-3-haiku-20240307".to_string(),
-                        false,
-                        None,
-                        false,
-                    ).await?;
+    async fn handle_checkpoint_command(&self, action: CheckpointCommands) -> crate::error::Result<()> {
+        let store = crate::git::checkpoint::CheckpointStore::new(std::env::current_dir()?);
+        match action {
+            CheckpointCommands::List { session } => {
+                let checkpoints = store.list(session.as_deref()).await?;
+                if checkpoints.is_empty() {
+                    println!("No checkpoints yet. Interactive sessions record one after every turn.");
+                }
+                let mut current = None;
+                for checkpoint in checkpoints {
+                    if current.as_ref() != Some(&checkpoint.session) {
+                        println!("📍 Session {}", checkpoint.session);
+                        current = Some(checkpoint.session.clone());
+                    }
+                    println!(
+                        "  turn {:>3}  {}  {}  {}",
+                        checkpoint.turn,
+                        &checkpoint.commit[..checkpoint.commit.len().min(10)],
+                        checkpoint.created_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                        checkpoint.label
+                    );
+                }
+            }
+            CheckpointCommands::Diff { from, to, session, stat } => {
+                use std::io::IsTerminal;
+                let from = store.resolve(session.as_deref(), &from).await?;
+                let to = store.resolve(session.as_deref(), &to).await?;
+                let mut diff = store.diff(&from, &to).await?;
+                if stat {
+                    diff.patch.clear();
                 }
+                print!("{}", diff.render(std::io::stdout().is_terminal()));
             }
         }
-
         Ok(())
     }
 
-    /// 显示交互模式帮助
-    fn show_interactive_help(&self) {
-        println!("\\n📚 Available Commands:");
-        println!("  help     - Show this help message");
-        println!("  status   - Show system status");
-        println!("  clear    - Clear conversation history");
-        println!("  exit     - Exit interactive mode");
-        println!("  <text>   - Send message to Claude");
-        println!();
+    async fn handle_jobs_command(&self, action: JobsCommands) -> crate::error::Result<()> {
+        let jobs = self.jobs();
+        let store = jobs.store();
+        match action {
+            JobsCommands::List => {
+                let list = store.list()?;
+                if list.is_empty() {
+                    println!("No background jobs. Start one with /bg <task> in interactive mode.");
+                }
+                for job in list {
+                    let description: String = job.description.chars().take(60).collect();
+                    println!(
+                        "  {}  {:<10} {}  {}",
+                        job.id,
+                        job.status,
+                        job.started_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                        description
+                    );
+                }
+            }
+            JobsCommands::Status { id } => {
+                let job = store.get(&id)?;
+                println!("🧵 Job {} — {}", job.id, job.status);
+                println!("   Task: {}", job.description);
+                println!("   Started: {}", job.started_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"));
+                if let Some(finished) = job.finished_at {
+                    println!("   Finished: {}", finished.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"));
+                }
+                let log = store.read_log(&job.id, 0)?;
+                let lines: Vec<&str> = log.lines().collect();
+                if !lines.is_empty() {
+                    println!("   Output (last {} line(s)):", lines.len().min(20));
+                    for line in &lines[lines.len().saturating_sub(20)..] {
+                        println!("   │ {}", line);
+                    }
+                }
+                if let Some(summary) = &job.summary {
+                    println!("   Result: {}", summary);
+                }
+            }
+            JobsCommands::Attach { id } => {
+                let mut job = store.get(&id)?;
+                println!("📎 Attached to job {} ({}). Press Ctrl-C to detach.", job.id, job.status);
+                let mut offset = 0;
+                loop {
+                    let output = store.read_log(&job.id, offset)?;
+                    offset += output.len() as u64;
+                    print!("{}", output);
+                    if !job.is_running() {
+                        break;
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(std::time::Duration::from_millis(500)) => {}
+                        _ = tokio::signal::ctrl_c() => {
+                            println!("\n🔌 Detached; the job keeps running.");
+                            return Ok(());
+                        }
+                    }
+                    job = store.get(&job.id)?;
+                }
+                match &job.summary {
+                    Some(summary) => println!("🏁 Job {} {}: {}", job.id, job.status, summary),
+                    None => println!("🏁 Job {} {}", job.id, job.status),
+                }
+            }
+            JobsCommands::Cancel { id } => {
+                let job = jobs.cancel(&id)?;
+                println!("🛑 Cancellation requested for job {} ({})", job.id, job.description);
+            }
+        }
+        Ok(())
     }
 
-    /// 添加目录到工作空间
-    async fn add_directory(&self, dir: &str) -> crate::error::Result<()> {
-        use tracing::info;
-        info!("Adding directory to workspace: {}", dir);
-        // 这里应该实现实际的目录添加逻辑
+    fn handle_agent_command(&self, action: AgentCommands) -> crate::error::Result<()> {
+        use crate::agent::AgentCheckpointStore;
+
+        let store = AgentCheckpointStore::new(AgentCheckpointStore::default_dir());
+        match action {
+            AgentCommands::Checkpoints { session } => {
+                let checkpoints = store.list(session.as_deref())?;
+                if checkpoints.is_empty() {
+                    println!("No agent checkpoints yet. The agent records one before every tool call that can change files.");
+                }
+                let mut current = None;
+                for checkpoint in checkpoints {
+                    if current.as_ref() != Some(&checkpoint.session_id) {
+                        println!("📍 Session {}", checkpoint.session_id);
+                        current = Some(checkpoint.session_id.clone());
+                    }
+                    println!(
+                        "  {:>3}  {}  {}  {:<12} {} file(s)",
+                        checkpoint.seq,
+                        checkpoint.id,
+                        checkpoint.created_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+                        checkpoint.tool_name,
+                        checkpoint.files.len()
+                    );
+                }
+            }
+            AgentCommands::Rollback { checkpoint } => {
+                let rollback = store.rollback(&checkpoint)?;
+                let checkpoint = &rollback.checkpoint;
+                for path in &rollback.restored {
+                    println!("  ↩️  {}", path.display());
+                }
+                if let Some(id) = &checkpoint.conversation_id {
+                    let mut conversation = crate::conversation::ConversationManager::new();
+                    conversation.load_conversation(id)?;
+                    conversation.truncate_messages(checkpoint.conversation_messages, &checkpoint.id)?;
+                    println!("💬 Conversation {} truncated to {} message(s)", id, checkpoint.conversation_messages);
+                }
+                println!(
+                    "✅ Rolled back to checkpoint {} (before {}); discarded {} checkpoint(s)",
+                    checkpoint.id, checkpoint.tool_name, rollback.discarded
+                );
+            }
+        }
         Ok(())
     }
 
-    /// 处理流式响应
-    async fn handle_streaming_response(&self, response: crate::network::ClaudeResponse) -> crate::error::Result<()> {
-        // 这里应该实现实际的流式响应处理
-        println!("{}", response.content);
+    async fn handle_reports_command(&self, action: ReportsCommands) -> crate::error::Result<()> {
+        let settings = &self.config.get_config().reports;
+        match action {
+            ReportsCommands::List => {
+                let reports = crate::reports::list(&settings.dir)?;
+                if reports.is_empty() {
+                    println!("No reports in {}. Generate one with --report on review or health.", settings.dir.display());
+                }
+                for report in reports {
+                    let charts = if report.charts.is_empty() { String::new() } else { format!("  ({} chart(s))", report.charts.len()) };
+                    println!("  {}{}", report.markdown.display(), charts);
+                }
+            }
+            ReportsCommands::Email { path, to } => {
+                let Some(smtp) = &settings.smtp else {
+                    return Err(crate::error::ClaudeError::config_error("Set reports.smtp in the config file to email reports"));
+                };
+                let report = crate::reports::SavedReport::open(&path)?;
+                let recipients = crate::reports::email::send_report(smtp, &report, &to).await?;
+                println!("📧 Emailed {} to {}", report.markdown.display(), recipients.join(", "));
+            }
+            ReportsCommands::Changelog { since, email } => {
+                let git = crate::git::GitManager::new(std::env::current_dir()?);
+                let range = format!("{}..HEAD", since);
+                let log = git
+                    .run(&[
+                        "log".to_string(),
+                        "--no-merges".to_string(),
+                        "--numstat".to_string(),
+                        "--date=short".to_string(),
+                        "--pretty=format:>%h%x09%ad%x09%s".to_string(),
+                        range.clone(),
+                    ])
+                    .await?;
+                let diffstat = git.run(&["diff".to_string(), "--stat".to_string(), range.clone()]).await?;
+                let report = crate::reports::changelog(&range, &log, &diffstat);
+                self.publish_report(&report, email).await?;
+            }
+        }
         Ok(())
     }
 
-    /// 处理 --print 模式
-    async fn handle_print_mode(&self, prompt: String, cli: &Cli) -> crate::error::Result<()> {
-        use tracing::info;
+    /// 处理存储回收命令
+    async fn handle_gc_command(&self, dry_run: bool) -> crate::error::Result<()> {
+        use crate::gc::{GarbageCollector, StorageClass};
 
-        info!("🖨️  Print mode: {}", prompt);
+        let collector = GarbageCollector::from_config(self.config.get_config());
+        let report = tokio::task::spawn_blocking(move || collector.run(dry_run))
+            .await
+            .map_err(|e| crate::error::ClaudeError::General(format!("Storage GC failed: {}", e)))?;
 
-        // 根据输出格式处理
-        match cli.output_format.as_ref().unwrap_or(&crate::cli::OutputFormat::Text) {
-            crate::cli::OutputFormat::Text => {
-                println!("{}", prompt);
-            },
-            crate::cli::OutputFormat::Json => {
-                let json_output = serde_json::json!({
-                    "prompt": prompt,
-                    "response": "Response would go here",
-                    "timestamp": chrono::Utc::now().to_rfc3339()
-                });
-                println!("{}", serde_json::to_string_pretty(&json_output)?);
-            },
-            crate::cli::OutputFormat::StreamJson => {
-                // 流式 JSON 输出
-                let stream_output = serde_json::json!({
-                    "type": "response",
-                    "content": prompt,
-                    "timestamp": chrono::Utc::now().to_rfc3339()
-                });
-                println!("{}", serde_json::to_string(&stream_output)?);
+        println!("🧹 Scanned {} file(s), {}", report.scanned_files, format_size(report.scanned_bytes));
+        for class in StorageClass::ALL {
+            let (count, bytes) = report.class_totals(class);
+            if count > 0 {
+                println!("  {:<10} {} file(s), {}", class.label(), count, format_size(bytes));
             }
         }
-
+        if report.candidates.is_empty() {
+            println!("Nothing to free.");
+        } else if dry_run {
+            println!("Would free {}. Run `claude gc` to remove these files.", format_size(report.reclaimable_bytes()));
+        } else {
+            println!("✅ Freed {}", format_size(report.freed_bytes));
+        }
+        for error in &report.errors {
+            println!("⚠️  Failed to remove {}", error);
+        }
         Ok(())
     }
 
-    /// 处理继续对话
-    async fn handle_continue_conversation(&self) -> crate::error::Result<()> {
-        use tracing::info;
-        info!("🔄 Continuing most recent conversation");
-        println!("Continuing the most recent conversation...");
-        // 这里应该实现实际的会话恢复逻辑
-        Ok(())
+    /// 按保留策略在后台定期清理本地存储
+    fn spawn_storage_gc(&self) {
+        let retention = &self.config.get_config().retention;
+        if !retention.enabled {
+            return;
+        }
+        let interval = std::time::Duration::from_secs(retention.interval_secs.max(60));
+        crate::gc::GarbageCollector::from_config(self.config.get_config()).spawn_background(interval);
     }
 
-    /// 处理恢复对话
-    async fn handle_resume_conversation(&self, session_id: String) -> crate::error::Result<()> {
-        use tracing::info;
-        info!("📂 Resuming conversation: {}", session_id);
-        println!("Resuming conversation: {}", session_id);
-        // 这里应该实现实际的会话恢复逻辑
+    /// 处理文件夹信任命令
+    async fn handle_trust_command(&self, action: TrustCommands) -> crate::error::Result<()> {
+        use crate::security::trust::TrustStore;
+
+        let mut store = TrustStore::load_default()?;
+        let resolve = |path: Option<String>| -> crate::error::Result<std::path::PathBuf> {
+            match path {
+                Some(path) => Ok(std::path::PathBuf::from(path)),
+                None => Ok(std::env::current_dir()?),
+            }
+        };
+
+        match action {
+            TrustCommands::List => {
+                if store.list().is_empty() {
+                    println!("No trusted folders.");
+                } else {
+                    println!("🔐 Trusted folders:");
+                    for folder in store.list() {
+                        println!("  {}  (since {})", folder.path.display(), folder.trusted_at.format("%Y-%m-%d %H:%M"));
+                    }
+                }
+            }
+            TrustCommands::Add { path } => {
+                let path = resolve(path)?;
+                if store.trust(&path) {
+                    store.save()?;
+                    println!("✅ Trusted {}", path.display());
+                } else {
+                    println!("{} is already trusted", path.display());
+                }
+            }
+            TrustCommands::Revoke { path } => {
+                let path = resolve(path)?;
+                if store.revoke(&path) {
+                    store.save()?;
+                    println!("🔒 Revoked trust for {}", path.display());
+                } else {
+                    println!("{} was not trusted", path.display());
+                }
+            }
+        }
         Ok(())
     }
 
-    /// 处理交互式提示
-    async fn handle_interactive_prompt(&self, prompt: String) -> crate::error::Result<()> {
-        use tracing::info;
-        info!("💬 Interactive prompt: {}", prompt);
-        println!("Processing: {}", prompt);
-        // 这里应该实现实际的提示处理逻辑
+    /// 处理语法高亮命令
+    #[cfg(feature = "syntax-highlighting")]
+    async fn handle_highlight_command(&self, command: HighlightCommand) -> crate::error::Result<()> {
+        use crate::syntax_highlighting::{HighlightConfig, SyntaxHighlighter, TerminalBackground};
+
+        let highlighter = SyntaxHighlighter::with_user_themes()?;
+        let ui = &self.config.get_config().ui;
+        let background = TerminalBackground::from_setting(&ui.terminal_background);
+        let highlight = |code: &str, language: Option<&str>, theme: Option<String>| -> crate::error::Result<()> {
+            let theme = highlighter.resolve_theme(theme.or_else(|| ui.highlight_theme.clone()).as_deref(), background)?;
+            let config = HighlightConfig { theme, ..HighlightConfig::default() };
+            let result = highlighter.highlight_code(code, language, &config)?;
+            println!("{}\x1b[0m", result.highlighted_code);
+            Ok(())
+        };
+
+        match command {
+            HighlightCommand::File { path, language, theme } => {
+                let content = tokio::fs::read_to_string(&path).await?;
+                let language = language.or_else(|| {
+                    let extension = std::path::Path::new(&path).extension().and_then(|ext| ext.to_str());
+                    highlighter.detect_language(&content, extension).map(|syntax| syntax.name.clone())
+                });
+                highlight(&content, language.as_deref(), theme)?;
+            }
+            HighlightCommand::Code { code, language, theme } => {
+                highlight(&code, Some(&language), theme)?;
+            }
+            HighlightCommand::Languages => {
+                let mut languages = highlighter.get_available_languages();
+                languages.sort();
+                println!("🎨 Supported languages ({}):", languages.len());
+                for language in languages {
+                    println!("  • {}", language);
+                }
+            }
+            HighlightCommand::Themes => {
+                let active = highlighter.resolve_theme(ui.highlight_theme.as_deref(), background)
+                    .unwrap_or_else(|_| background.default_theme().to_string());
+                let mark = |name: &str| if name == active { " (active)" } else { "" };
+
+                let mut builtin = highlighter.get_builtin_themes();
+                builtin.sort();
+                println!("🎨 Built-in themes:");
+                for name in &builtin {
+                    println!("  • {}{}", name, mark(name));
+                }
+
+                println!("\n📁 User themes ({}):", SyntaxHighlighter::user_themes_dir()?.display());
+                let user = highlighter.get_user_themes();
+                if user.is_empty() {
+                    println!("  (none — drop .tmTheme files into this directory)");
+                }
+                for name in &user {
+                    println!("  • {}{}", name, mark(name));
+                }
+
+                println!("\n💡 Set ui.highlight_theme in the config or pass --theme to pick a theme");
+                println!("💡 Set ui.terminal_background to light/dark if auto-detection picks the wrong default");
+            }
+        }
         Ok(())
     }
 
     /// 处理 MCP 命令
     async fn handle_mcp_command(&self, action: McpCommands) -> crate::error::Result<()> {
-        use tracing::info;
-        info!("🔧 MCP command: {:?}", action);
-        println!("MCP command executed successfully");
+        use crate::error::ClaudeError;
+
+        match action {
+            McpCommands::Add { name, command, args, transport, headers, scope } => {
+                use crate::config::McpTransport;
+                use crate::mcp::scope::{ApprovalStore, McpScope, ProjectScopes};
+
+                let headers = headers
+                    .iter()
+                    .map(|header| {
+                        let (key, value) = header.split_once(':').ok_or_else(|| {
+                            ClaudeError::validation_error("header", format!("Expected 'Name: value', got '{}'", header))
+                        })?;
+                        Ok((key.trim().to_string(), value.trim().to_string()))
+                    })
+                    .collect::<crate::error::Result<std::collections::HashMap<_, _>>>()?;
+                let server = match transport {
+                    McpTransport::Stdio => {
+                        if !headers.is_empty() {
+                            return Err(ClaudeError::validation_error("header", "Headers only apply to sse and http transports"));
+                        }
+                        crate::config::McpServerConfig { name: name.clone(), command, args, auto_start: true, ..Default::default() }
+                    }
+                    McpTransport::Sse | McpTransport::Http => {
+                        reqwest::Url::parse(&command)
+                            .map_err(|e| ClaudeError::validation_error("url", format!("Invalid URL '{}': {}", command, e)))?;
+                        if !args.is_empty() {
+                            return Err(ClaudeError::validation_error("args", "Remote MCP servers take a URL and no arguments"));
+                        }
+                        crate::config::McpServerConfig {
+                            name: name.clone(),
+                            transport,
+                            url: Some(command),
+                            headers,
+                            auto_start: true,
+                            ..Default::default()
+                        }
+                    }
+                };
+                let exists = || ClaudeError::config_error(format!("MCP server '{}' already exists in {} config", name, scope.as_str()));
+                let path = if scope == McpScope::User {
+                    let mut manager = crate::config::ConfigManager::from_path(self.config.config_path().to_path_buf())?;
+                    let servers = &mut manager.get_config_mut().mcp_servers;
+                    if servers.contains_key(&name) {
+                        return Err(exists());
+                    }
+                    servers.insert(name.clone(), server);
+                    manager.save()?;
+                    manager.config_path().to_path_buf()
+                } else {
+                    let scopes = ProjectScopes::for_current_dir()?;
+                    let mut servers = scopes.load(scope)?;
+                    if servers.servers.contains_key(&name) {
+                        return Err(exists());
+                    }
+                    servers.insert(&server)?;
+                    scopes.save(scope, &servers)?;
+                    if scope == McpScope::Project {
+                        // 自己添加的服务器不必再询问
+                        let mut approvals = ApprovalStore::load_default()?;
+                        approvals.record(scopes.project_dir(), &server, true);
+                        approvals.save()?;
+                        scopes.project_file()
+                    } else {
+                        scopes.local_file().to_path_buf()
+                    }
+                };
+                println!("✅ Added MCP server '{}' to {} config {}", name, scope.as_str(), path.display());
+            }
+            McpCommands::Remove { name, scope } => {
+                use crate::mcp::scope::{McpScope, ProjectScopes};
+
+                let scopes = ProjectScopes::for_current_dir()?;
+                let mut manager = crate::config::ConfigManager::from_path(self.config.config_path().to_path_buf())?;
+                let mut found = Vec::new();
+                for candidate in McpScope::ALL.into_iter().filter(|candidate| scope.is_none_or(|scope| scope == *candidate)) {
+                    let present = match candidate {
+                        McpScope::User => manager.get_config().mcp_servers.contains_key(&name),
+                        _ => scopes.load(candidate)?.servers.contains_key(&name),
+                    };
+                    if present {
+                        found.push(candidate);
+                    }
+                }
+                let scope = match found.as_slice() {
+                    [] => return Err(ClaudeError::config_error(format!("No MCP server named '{}'", name))),
+                    [scope] => *scope,
+                    _ => {
+                        let names: Vec<&str> = found.iter().map(|scope| scope.as_str()).collect();
+                        return Err(ClaudeError::config_error(format!(
+                            "MCP server '{}' exists in {} configs; choose one with --scope",
+                            name,
+                            names.join(" and ")
+                        )));
+                    }
+                };
+                if scope == McpScope::User {
+                    manager.get_config_mut().mcp_servers.remove(&name);
+                    manager.save()?;
+                } else {
+                    let mut servers = scopes.load(scope)?;
+                    servers.remove(&name);
+                    scopes.save(scope, &servers)?;
+                }
+                println!("🗑️  Removed MCP server '{}' from {} config", name, scope.as_str());
+            }
+            McpCommands::List => {
+                use crate::mcp::scope::{ApprovalStore, McpScope};
+
+                let servers = self.scoped_mcp_servers(self.config.get_config().mcp_servers.clone())?;
+                if servers.is_empty() {
+                    println!("No MCP servers configured. Add one with: claude mcp add <name> <command> [args...]");
+                    println!("  or for a remote server: claude mcp add --transport http <name> <url>");
+                    println!("  or share it with the team in .mcp.json: claude mcp add --scope project <name> <command>");
+                    return Ok(());
+                }
+                let approvals = ApprovalStore::load_default()?;
+                let project = std::env::current_dir()?.canonicalize()?;
+                println!("🔌 MCP servers");
+                for (name, scoped) in &servers {
+                    let server = &scoped.config;
+                    let status = match self.mcp().get_server_status(name) {
+                        Some(crate::mcp::McpServerStatus::Running) => "running",
+                        Some(crate::mcp::McpServerStatus::Error(_)) => "failed",
+                        _ if scoped.scope == McpScope::Project => match approvals.decision(&project, server) {
+                            Some(true) if server.auto_start => "auto-start",
+                            Some(true) => "manual",
+                            Some(false) => "rejected",
+                            None => "pending",
+                        },
+                        _ if server.auto_start => "auto-start",
+                        _ => "manual",
+                    };
+                    println!(
+                        "  {:<20} {:<11} {:<8} {:<6} {}",
+                        name,
+                        status,
+                        scoped.scope.as_str(),
+                        server.transport.as_str(),
+                        server.target()
+                    );
+                    // 最近一次健康事件，间歇崩溃的服务器不会悄无声息地消失
+                    let latest = self.mcp().health_log().and_then(|log| log.latest(&server.name).ok().flatten());
+                    if let Some(event) = latest {
+                        println!(
+                            "  {:<20} last: {} {} ({})",
+                            "",
+                            event.kind.label(),
+                            event.at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                            event.detail
+                        );
+                    }
+                }
+            }
+            McpCommands::Start { name } => {
+                let config = self.find_mcp_server(&name)?;
+                let mcp = self.mcp();
+                mcp.start_server(config).await?;
+                if let Some(server) = mcp.server_info(&name) {
+                    println!(
+                        "✅ Connected to {} {} (protocol {})",
+                        server.server_info.name, server.server_info.version, server.protocol_version
+                    );
+                    println!("   Capabilities: {}", server.capabilities.names().join(", "));
+                    if let Some(instructions) = &server.instructions {
+                        println!("   Instructions: {}", instructions);
+                    }
+                }
+                let tools = mcp.tools(&name);
+                if !tools.is_empty() {
+                    let names: Vec<&str> = tools.iter().filter_map(|tool| tool["name"].as_str()).collect();
+                    println!("   Tools ({}): {}", names.len(), names.join(", "));
+                }
+                // 服务器随本进程退出，会话中由 Agent 按需启动
+                mcp.stop_server(&name).await?;
+            }
+            McpCommands::Stop { name } => {
+                self.mcp().stop_server(&name).await?;
+                println!("⏹️  Stopped MCP server '{}'", name);
+            }
+            McpCommands::Resources { name } => {
+                let config = self.find_mcp_server(&name)?;
+                let mcp = self.mcp();
+                mcp.start_server(config).await?;
+                let resources = mcp.list_resources(&name).await;
+                mcp.stop_server(&name).await?;
+                let resources = resources?;
+                if resources.is_empty() {
+                    println!("MCP server '{}' has no resources", name);
+                    return Ok(());
+                }
+                println!("📚 Resources of '{}' (mention with @{}:<uri or name>)", name, name);
+                for resource in resources {
+                    let field = |key: &str| resource.get(key).and_then(|value| value.as_str()).unwrap_or_default().to_string();
+                    println!("  {:<40} {:<20} {}", field("uri"), field("name"), field("description"));
+                }
+            }
+            McpCommands::Serve => {
+                use crate::mcp::server::{ClientApproval, McpServer};
+                use crate::tools::permission::RuleDecider;
+
+                let config = self.config.runtime_config();
+                let tools = Arc::new(crate::tools::ToolRegistry::new());
+                crate::tools::builtin::register_builtin_tools_with_config(&tools, &config, self.execution_target.clone()).await?;
+                // 终端被协议占用，需要确认的调用交给客户端确认，拒绝规则照常生效
+                if !self.skip_permissions.get().copied().unwrap_or(false) {
+                    let (allowed, denied) = self.tool_rules.get().cloned().unwrap_or_default();
+                    let decider = RuleDecider::from_config(&config.permissions)
+                        .with_rules(&allowed, &denied)
+                        .with_prompt(Arc::new(ClientApproval));
+                    tools.set_permission_decider(Arc::new(decider)).await;
+                }
+                let mut context = crate::tools::ToolContext::new(format!("mcp-serve-{}", uuid::Uuid::new_v4()))
+                    .with_timeouts(&config.tool_timeouts);
+                context.permissions.push("execute".to_string());
+                let server = Arc::new(McpServer::new(tools, context).await?);
+                tracing::info!("Serving MCP tools on stdio: {}", server.tools().join(", "));
+                server.serve(tokio::io::stdin(), tokio::io::stdout()).await?;
+            }
+            McpCommands::ResetProjectChoices => {
+                let project = std::env::current_dir()?.canonicalize()?;
+                let mut approvals = crate::mcp::scope::ApprovalStore::load_default()?;
+                let cleared = approvals.reset(&project);
+                approvals.save()?;
+                println!("♻️  Cleared {} choice(s) for .mcp.json servers in {}", cleared, project.display());
+            }
+        }
         Ok(())
     }
 
@@ -1351,24 +5811,52 @@ impl ClaudeCodeCli {
     }
 
     /// 处理模型命令
-    async fn handle_model_command(&self, set: Option<String>, list: bool) -> crate::error::Result<()> {
+    async fn handle_model_command(&self, set: Option<String>, list: bool, update: bool) -> crate::error::Result<()> {
+        use crate::models::{ModelCapabilities, ModelRegistry};
+
+        let mut registry = ModelRegistry::load();
+        let current = self.config.get_config().api.default_model.clone();
+
+        if update {
+            let url = self.config.get_config().model_registry.manifest_url.clone().ok_or_else(|| {
+                crate::error::ClaudeError::config_error("model_registry.manifest_url is not set")
+            })?;
+            let count = registry.update_from(&url).await?;
+            println!("✅ Updated {} model(s) from {}", count, url);
+            if let Some(version) = registry.manifest_version() {
+                println!("   Manifest version: {}", version);
+            }
+        }
+
+        let describe = |m: &ModelCapabilities| {
+            let mut features = vec!["text"];
+            for (enabled, name) in [(m.vision, "vision"), (m.tool_use, "tools"), (m.thinking, "thinking"), (m.prompt_caching, "cache")] {
+                if enabled {
+                    features.push(name);
+                }
+            }
+            format!("{}k context, {} max output, {}", m.max_context / 1000, m.max_output, features.join(" "))
+        };
+
         if list {
             println!("🤖 Available AI Models");
             println!("======================");
-            println!("• claude-3-5-sonnet-20241022 (Latest Sonnet)");
-            println!("• claude-3-5-haiku-20241022 (Latest Haiku)");
-            println!("• claude-3-opus-20240229 (Opus)");
-            println!("• claude-3-sonnet-20240229 (Sonnet)");
-            println!("• claude-3-haiku-20240307 (Haiku)");
-
-            // 这里应该从配置中读取当前模型
-            println!("\n🎯 Current model: claude-3-5-sonnet-20241022 (default)");
+            for model in registry.models() {
+                println!("• {} ({})", model.name, describe(model));
+            }
+            println!("\n🎯 Current model: {}", current);
         } else if let Some(model) = set {
+            if registry.get(&model).is_none() {
+                println!("⚠️  {} is not in the model registry; features will not be checked", model);
+            }
             println!("🤖 Setting AI model to: {}", model);
             // 这里应该保存到配置中
             println!("✅ Model set to: {}", model);
-        } else {
-            println!("🤖 Current model: claude-3-5-sonnet-20241022 (default)");
+        } else if !update {
+            match registry.get(&current) {
+                Some(model) => println!("🤖 Current model: {} ({})", current, describe(model)),
+                None => println!("🤖 Current model: {}", current),
+            }
             println!("💡 Use --list to see available models");
             println!("💡 Use --set <model> to change the model");
         }
@@ -1379,13 +5867,25 @@ impl ClaudeCodeCli {
     /// 处理恢复对话命令
     async fn handle_resume_command(&self, conversation_id: Option<String>) -> crate::error::Result<()> {
         if let Some(id) = conversation_id {
-            println!("🔄 Resuming conversation: {}", id);
-            println!("💡 Conversation resume functionality needs to be implemented");
-        } else {
-            println!("🔄 Recent Conversations");
-            println!("======================");
+            return self.handle_resume_conversation(id).await;
+        }
+
+        println!("🔄 Recent Conversations");
+        println!("======================");
+        let manager = crate::conversation::ConversationManager::new();
+        let conversations = manager.list_conversations().unwrap_or_default();
+        if conversations.is_empty() {
             println!("💡 No recent conversations found");
-            println!("💡 Conversation history functionality needs to be implemented");
+            println!("💡 Use `claude import-sessions` to bring over Claude Code history");
+        }
+        for summary in conversations.iter().take(20) {
+            println!(
+                "  {}  {}  {} ({} messages)",
+                summary.id,
+                summary.updated_at.format("%Y-%m-%d %H:%M"),
+                summary.title,
+                summary.message_count
+            );
         }
 
         Ok(())
@@ -1613,8 +6113,18 @@ impl ClaudeCodeCli {
             request_timeout: 30,
         };
 
-        // 创建Claude配置
-        let claude_config = ClaudeConfig::default();
+        // 使用已加载的配置，其中包含 Web 认证设置
+        let claude_config: ClaudeConfig = self.config.get_config().clone();
+        match claude_config.web_auth.mode {
+            crate::config::WebAuthMode::None => println!("🔓 Authentication: off (every request acts as the local admin)"),
+            crate::config::WebAuthMode::Token => println!(
+                "🔐 Authentication: API tokens from {}",
+                crate::web::auth::users_file(&claude_config.web_auth).display()
+            ),
+            crate::config::WebAuthMode::Proxy => {
+                println!("🔐 Authentication: user name from the {} header set by your auth proxy", claude_config.web_auth.proxy_header)
+            }
+        }
 
         // 创建Web服务器
         let web_server = WebServer::new(web_config, claude_config)?;
@@ -1766,7 +6276,17 @@ impl ClaudeCodeCli {
         println!("🖥️ Starting Claude Code Terminal UI...");
         println!("Press 'q' to quit, 'h' for help");
 
+        self.offer_devcontainer(false).await;
+        let (reloader, config_updates) = self.start_config_reloader();
         let mut app = TerminalApp::new();
+        app.watch_config(config_updates);
+        app.set_model(reloader.config().api.default_model);
+        app.set_language(reloader.config().response_language());
+        app.set_native_dialogs(reloader.config().ui.native_dialogs);
+        app.set_execution_target(self.execution_target.clone());
+        let session_id = uuid::Uuid::new_v4().to_string();
+        app.set_scratchpad(crate::fs::scratch::Scratchpad::for_session(&session_id));
+        app.set_todo_store(crate::tools::todo::TodoStore::for_session(&session_id));
 
         if let Err(e) = app.run().await {
             eprintln!("❌ Terminal UI error: {}", e);
@@ -1,3 +1,5 @@
+pub mod presence;
+
 use crate::error::{ClaudeError, Result};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -128,7 +130,7 @@ pub struct TextSelection {
 }
 
 /// 位置
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub line: u32,
     pub column: u32,
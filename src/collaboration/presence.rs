@@ -0,0 +1,301 @@
+//! 共享会话的在场状态与轮流发言
+//!
+//! 多个浏览器打开同一个共享会话时，服务器记录每个参与者是否在输入和光标位置，并裁决唯一的驾驶者：
+//! 只有驾驶者的消息会交给代理。其他人可以申请控制权，由驾驶者移交；驾驶者离开或长时间没有操作时，
+//! 排在最前面的申请者自动接手
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+use super::Position;
+use crate::error::{ClaudeError, Result};
+
+/// 驾驶者超过这个时间没有操作时，控制权申请直接生效
+pub const DRIVER_IDLE_TIMEOUT_SECS: i64 = 120;
+
+/// 一个参与者的在场状态
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Presence {
+    pub user_id: String,
+    pub name: String,
+    pub typing: bool,
+    pub cursor: Option<Position>,
+    pub last_active: DateTime<Utc>,
+    /// 同一用户打开的连接数（多个标签页）
+    #[serde(skip)]
+    connections: usize,
+}
+
+/// 控制权转移的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandoffReason {
+    /// 第一个加入或没有驾驶者时申请
+    Vacant,
+    /// 驾驶者移交
+    Granted,
+    /// 驾驶者主动放弃
+    Released,
+    DriverLeft,
+    DriverIdle,
+}
+
+/// 推送给所有参与者的事件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PresenceEvent {
+    Joined { user_id: String, name: String },
+    Left { user_id: String },
+    Typing { user_id: String, typing: bool },
+    Cursor { user_id: String, cursor: Position },
+    ControlRequested { user_id: String },
+    DriverChanged { driver: Option<String>, reason: HandoffReason },
+    Message { user_id: String, role: String, content: String },
+}
+
+/// 控制权申请的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlOutcome {
+    Granted,
+    /// 等待驾驶者移交
+    Queued,
+    AlreadyDriving,
+}
+
+/// 共享会话的当前状态
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSnapshot {
+    pub id: String,
+    pub driver: Option<String>,
+    pub participants: Vec<Presence>,
+    pub requests: Vec<String>,
+}
+
+/// 一个共享会话
+#[derive(Debug, Clone)]
+pub struct SharedSession {
+    pub id: String,
+    /// 代理侧的对话 ID，驾驶者的消息都进入这个对话
+    pub conversation_id: Option<String>,
+    participants: Vec<Presence>,
+    driver: Option<String>,
+    requests: VecDeque<String>,
+}
+
+impl SharedSession {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            conversation_id: None,
+            participants: Vec::new(),
+            driver: None,
+            requests: VecDeque::new(),
+        }
+    }
+
+    pub fn driver(&self) -> Option<&str> {
+        self.driver.as_deref()
+    }
+
+    pub fn is_driver(&self, user_id: &str) -> bool {
+        self.driver.as_deref() == Some(user_id)
+    }
+
+    pub fn snapshot(&self) -> SessionSnapshot {
+        SessionSnapshot {
+            id: self.id.clone(),
+            driver: self.driver.clone(),
+            participants: self.participants.clone(),
+            requests: self.requests.iter().cloned().collect(),
+        }
+    }
+
+    /// 加入会话；没有驾驶者时加入者成为驾驶者
+    pub fn join(&mut self, user_id: &str, name: &str, now: DateTime<Utc>) -> Vec<PresenceEvent> {
+        if let Some(presence) = self.presence_mut(user_id) {
+            presence.connections += 1;
+            presence.last_active = now;
+            return Vec::new();
+        }
+        self.participants.push(Presence {
+            user_id: user_id.to_string(),
+            name: name.to_string(),
+            typing: false,
+            cursor: None,
+            last_active: now,
+            connections: 1,
+        });
+        let mut events = vec![PresenceEvent::Joined { user_id: user_id.to_string(), name: name.to_string() }];
+        if self.driver.is_none() {
+            events.push(self.hand_to(Some(user_id.to_string()), HandoffReason::Vacant));
+        }
+        events
+    }
+
+    /// 关闭一个连接；用户的最后一个连接关闭时离开会话，驾驶者离开时交给下一个申请者
+    pub fn leave(&mut self, user_id: &str) -> Vec<PresenceEvent> {
+        let Some(index) = self.participants.iter().position(|p| p.user_id == user_id) else {
+            return Vec::new();
+        };
+        self.participants[index].connections -= 1;
+        if self.participants[index].connections > 0 {
+            return Vec::new();
+        }
+        self.participants.remove(index);
+        self.requests.retain(|id| id != user_id);
+        let mut events = vec![PresenceEvent::Left { user_id: user_id.to_string() }];
+        if self.is_driver(user_id) {
+            let next = self.requests.pop_front();
+            events.push(self.hand_to(next, HandoffReason::DriverLeft));
+        }
+        events
+    }
+
+    /// 记录活动，供空闲判断使用
+    pub fn touch(&mut self, user_id: &str, now: DateTime<Utc>) {
+        if let Some(presence) = self.presence_mut(user_id) {
+            presence.last_active = now;
+        }
+    }
+
+    pub fn set_typing(&mut self, user_id: &str, typing: bool, now: DateTime<Utc>) -> Result<Option<PresenceEvent>> {
+        let presence = self.participant(user_id)?;
+        presence.last_active = now;
+        if presence.typing == typing {
+            return Ok(None);
+        }
+        presence.typing = typing;
+        Ok(Some(PresenceEvent::Typing { user_id: user_id.to_string(), typing }))
+    }
+
+    pub fn set_cursor(&mut self, user_id: &str, cursor: Position, now: DateTime<Utc>) -> Result<PresenceEvent> {
+        let presence = self.participant(user_id)?;
+        presence.last_active = now;
+        presence.cursor = Some(cursor.clone());
+        Ok(PresenceEvent::Cursor { user_id: user_id.to_string(), cursor })
+    }
+
+    /// 申请控制权：没有驾驶者或驾驶者空闲过久时立即生效，否则排队等待移交
+    pub fn request_control(&mut self, user_id: &str, now: DateTime<Utc>) -> Result<(ControlOutcome, Vec<PresenceEvent>)> {
+        self.participant(user_id)?.last_active = now;
+        if self.is_driver(user_id) {
+            return Ok((ControlOutcome::AlreadyDriving, Vec::new()));
+        }
+        let idle_driver = self.driver.as_deref().and_then(|driver| {
+            let presence = self.participants.iter().find(|p| p.user_id == driver)?;
+            (now - presence.last_active > Duration::seconds(DRIVER_IDLE_TIMEOUT_SECS)).then_some(())
+        });
+        if self.driver.is_none() || idle_driver.is_some() {
+            let reason = if self.driver.is_none() { HandoffReason::Vacant } else { HandoffReason::DriverIdle };
+            self.requests.retain(|id| id != user_id);
+            return Ok((ControlOutcome::Granted, vec![self.hand_to(Some(user_id.to_string()), reason)]));
+        }
+        if self.requests.iter().any(|id| id == user_id) {
+            return Ok((ControlOutcome::Queued, Vec::new()));
+        }
+        self.requests.push_back(user_id.to_string());
+        Ok((ControlOutcome::Queued, vec![PresenceEvent::ControlRequested { user_id: user_id.to_string() }]))
+    }
+
+    /// 驾驶者把控制权交给另一个参与者，不指定时交给最早的申请者
+    pub fn grant(&mut self, driver_id: &str, to: Option<&str>) -> Result<PresenceEvent> {
+        if !self.is_driver(driver_id) {
+            return Err(ClaudeError::validation_error("driver", "Only the current driver can hand over control"));
+        }
+        let next = match to {
+            Some(user_id) => user_id.to_string(),
+            None => self
+                .requests
+                .front()
+                .cloned()
+                .ok_or_else(|| ClaudeError::validation_error("to", "Nobody has requested control"))?,
+        };
+        self.participant(&next)?;
+        self.requests.retain(|id| *id != next);
+        Ok(self.hand_to(Some(next), HandoffReason::Granted))
+    }
+
+    /// 驾驶者放弃控制权，交给最早的申请者（没有则空缺）
+    pub fn release(&mut self, driver_id: &str) -> Result<PresenceEvent> {
+        if !self.is_driver(driver_id) {
+            return Err(ClaudeError::validation_error("driver", "Only the current driver can release control"));
+        }
+        let next = self.requests.pop_front();
+        Ok(self.hand_to(next, HandoffReason::Released))
+    }
+
+    fn hand_to(&mut self, driver: Option<String>, reason: HandoffReason) -> PresenceEvent {
+        self.driver = driver.clone();
+        PresenceEvent::DriverChanged { driver, reason }
+    }
+
+    fn presence_mut(&mut self, user_id: &str) -> Option<&mut Presence> {
+        self.participants.iter_mut().find(|p| p.user_id == user_id)
+    }
+
+    fn participant(&mut self, user_id: &str) -> Result<&mut Presence> {
+        self.presence_mut(user_id)
+            .ok_or_else(|| ClaudeError::validation_error("user", format!("{} has not joined this session", user_id)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turn_taking() {
+        let start = Utc::now();
+        let mut session = SharedSession::new("s1");
+        session.join("alice", "Alice", start);
+        session.join("bob", "Bob", start);
+        session.join("carol", "Carol", start);
+        assert_eq!(session.driver(), Some("alice"));
+
+        let (outcome, events) = session.request_control("bob", start).unwrap();
+        assert_eq!(outcome, ControlOutcome::Queued);
+        assert_eq!(events, vec![PresenceEvent::ControlRequested { user_id: "bob".to_string() }]);
+        session.request_control("carol", start).unwrap();
+        assert!(session.grant("bob", None).is_err());
+
+        session.grant("alice", None).unwrap();
+        assert_eq!(session.driver(), Some("bob"));
+        assert_eq!(session.snapshot().requests, vec!["carol".to_string()]);
+
+        // 驾驶者离开后交给下一个申请者
+        let events = session.leave("bob");
+        assert_eq!(
+            events.last(),
+            Some(&PresenceEvent::DriverChanged { driver: Some("carol".to_string()), reason: HandoffReason::DriverLeft })
+        );
+
+        // 驾驶者空闲过久时申请直接生效
+        let later = start + Duration::seconds(DRIVER_IDLE_TIMEOUT_SECS + 1);
+        session.touch("alice", later);
+        let (outcome, _) = session.request_control("alice", later).unwrap();
+        assert_eq!(outcome, ControlOutcome::Granted);
+        assert_eq!(session.driver(), Some("alice"));
+    }
+
+    #[test]
+    fn test_presence_across_tabs() {
+        let now = Utc::now();
+        let mut session = SharedSession::new("s1");
+        session.join("alice", "Alice", now);
+        assert!(session.join("alice", "Alice", now).is_empty());
+        assert!(session.set_typing("bob", true, now).is_err());
+
+        assert!(session.set_typing("alice", true, now).unwrap().is_some());
+        assert!(session.set_typing("alice", true, now).unwrap().is_none());
+        session.set_cursor("alice", Position { line: 3, column: 7 }, now).unwrap();
+        assert_eq!(session.snapshot().participants[0].cursor, Some(Position { line: 3, column: 7 }));
+
+        // 关闭一个标签页不算离开
+        assert!(session.leave("alice").is_empty());
+        let events = session.leave("alice");
+        assert_eq!(events[0], PresenceEvent::Left { user_id: "alice".to_string() });
+        assert_eq!(session.driver(), None);
+    }
+}
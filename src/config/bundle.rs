@@ -0,0 +1,496 @@
+//! 配置与状态包
+//!
+//! 把配置（含 MCP 服务器定义）、记忆文件和会话记录打包为 `.tar.zst`，用于迁移到新机器
+//! 或给同事一份统一的初始环境。API 密钥等凭据默认不打包；提供口令时以
+//! Argon2id 派生密钥、ChaCha20-Poly1305 加密后单独保存，导入时用同一口令解密
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::ClaudeConfig;
+use crate::error::{ClaudeError, Result};
+
+/// 包格式版本
+const BUNDLE_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const CONFIG_ENTRY: &str = "config.json";
+const SECRETS_ENTRY: &str = "secrets.enc";
+const MEMORY_DIR: &str = "memory";
+const SESSIONS_DIR: &str = "sessions";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// 名称中包含这些片段的 MCP 环境变量视为凭据
+const SECRET_ENV_MARKERS: &[&str] = &["KEY", "TOKEN", "SECRET", "PASSWORD", "PASSWD", "CREDENTIAL", "AUTH"];
+
+/// API 密钥在凭据表中的键
+const API_KEY_SECRET: &str = "api.anthropic_api_key";
+/// 报告邮件的 SMTP 密码
+const SMTP_PASSWORD_SECRET: &str = "reports.smtp.password";
+
+/// 包内清单
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// 包格式版本
+    pub version: u32,
+    /// 创建时间
+    pub created_at: DateTime<Utc>,
+    /// 创建时的 crate 版本
+    pub created_by: String,
+    /// 打包的会话数
+    pub sessions: usize,
+    /// 是否包含记忆文件
+    pub memory: bool,
+    /// 凭据的处理方式
+    pub secrets: SecretHandling,
+    /// 未打包或已加密的凭据名称
+    pub secret_names: Vec<String>,
+}
+
+/// 凭据的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretHandling {
+    /// 未打包
+    Excluded,
+    /// 以口令加密后打包
+    Encrypted,
+}
+
+/// 导出选项
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// 加密凭据所用的口令，为空时不打包凭据
+    pub passphrase: Option<String>,
+    /// 是否打包会话记录
+    pub include_sessions: bool,
+}
+
+/// 导入选项
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// 解密凭据所用的口令
+    pub passphrase: Option<String>,
+    /// 覆盖本地已有的会话记录
+    pub overwrite_sessions: bool,
+}
+
+/// 导入结果
+#[derive(Debug, Clone)]
+pub struct ImportSummary {
+    /// 包内清单
+    pub manifest: BundleManifest,
+    /// 写入的配置文件
+    pub config_path: PathBuf,
+    /// 原配置文件的备份
+    pub backup_path: Option<PathBuf>,
+    /// 写入的会话数
+    pub sessions_imported: usize,
+    /// 本地已存在而跳过的会话数
+    pub sessions_skipped: usize,
+    /// 恢复的凭据数
+    pub secrets_restored: usize,
+    /// 包内有加密凭据但未提供口令
+    pub secrets_locked: bool,
+}
+
+/// 状态来源和写入位置
+#[derive(Debug, Clone)]
+pub struct StateLocations {
+    /// 配置文件
+    pub config_file: PathBuf,
+    /// 会话存储目录
+    pub sessions_dir: PathBuf,
+}
+
+impl StateLocations {
+    /// 当前配置文件和默认会话目录
+    pub fn current(config_file: &Path) -> Self {
+        Self {
+            config_file: config_file.to_path_buf(),
+            sessions_dir: crate::conversation::ConversationManager::default_storage_dir(),
+        }
+    }
+}
+
+/// 把配置和状态导出为 `.tar.zst` 包
+pub fn export_state(
+    config: &ClaudeConfig,
+    locations: &StateLocations,
+    output: &Path,
+    options: &ExportOptions,
+) -> Result<BundleManifest> {
+    let mut config = config.clone();
+    let secrets = extract_secrets(&mut config);
+
+    let mut entries: Vec<(String, Vec<u8>)> = Vec::new();
+    entries.push((CONFIG_ENTRY.to_string(), to_json(&config)?));
+
+    let memory = match config.memory.memory_file.as_deref().filter(|p| p.is_file()) {
+        Some(path) => {
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("memory");
+            entries.push((format!("{}/{}", MEMORY_DIR, name), std::fs::read(path)?));
+            true
+        }
+        None => false,
+    };
+
+    let mut sessions = 0;
+    if options.include_sessions && locations.sessions_dir.is_dir() {
+        for entry in std::fs::read_dir(&locations.sessions_dir)?.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+            // 快照和事件日志一起导出，会话数按快照计
+            if !name.ends_with(".json") && !name.ends_with(".events.jsonl") {
+                continue;
+            }
+            entries.push((format!("{}/{}", SESSIONS_DIR, name), std::fs::read(&path)?));
+            sessions += usize::from(name.ends_with(".json"));
+        }
+    }
+
+    let handling = match (&options.passphrase, secrets.is_empty()) {
+        (Some(passphrase), false) => {
+            entries.push((SECRETS_ENTRY.to_string(), seal(&to_json(&secrets)?, passphrase)?));
+            SecretHandling::Encrypted
+        }
+        _ => SecretHandling::Excluded,
+    };
+
+    let manifest = BundleManifest {
+        version: BUNDLE_VERSION,
+        created_at: Utc::now(),
+        created_by: env!("CARGO_PKG_VERSION").to_string(),
+        sessions,
+        memory,
+        secrets: handling,
+        secret_names: secrets.keys().cloned().collect(),
+    };
+    entries.insert(0, (MANIFEST_ENTRY.to_string(), to_json(&manifest)?));
+
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = std::fs::File::create(output)?;
+    let encoder = zstd::Encoder::new(file, 0)?.auto_finish();
+    let mut archive = tar::Builder::new(encoder);
+    for (name, data) in &entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(manifest.created_at.timestamp().max(0) as u64);
+        header.set_cksum();
+        archive.append_data(&mut header, name, data.as_slice())?;
+    }
+    archive.into_inner()?;
+
+    Ok(manifest)
+}
+
+/// 从 `.tar.zst` 包恢复配置和状态
+pub fn import_state(bundle: &Path, locations: &StateLocations, options: &ImportOptions) -> Result<ImportSummary> {
+    let entries = read_entries(bundle)?;
+    let entry = |name: &str| {
+        entries
+            .get(name)
+            .ok_or_else(|| ClaudeError::validation_error("bundle", format!("{} is missing {}", bundle.display(), name)))
+    };
+
+    let manifest: BundleManifest = from_json(entry(MANIFEST_ENTRY)?)?;
+    if manifest.version > BUNDLE_VERSION {
+        return Err(ClaudeError::validation_error(
+            "bundle",
+            format!("Bundle version {} is newer than this build supports", manifest.version),
+        ));
+    }
+    let mut config: ClaudeConfig = from_json(entry(CONFIG_ENTRY)?)?;
+
+    // 保留本机已有的凭据，包内加密的凭据在提供口令时覆盖
+    let mut secrets = super::ConfigManager::read_config_file(&locations.config_file)
+        .map(|mut local| extract_secrets(&mut local))
+        .unwrap_or_default();
+    let mut secrets_restored = 0;
+    let mut secrets_locked = false;
+    if let Some(sealed) = entries.get(SECRETS_ENTRY) {
+        match &options.passphrase {
+            Some(passphrase) => {
+                let bundled: BTreeMap<String, String> = from_json(&open(sealed, passphrase)?)?;
+                secrets_restored = bundled.len();
+                secrets.extend(bundled);
+            }
+            None => secrets_locked = true,
+        }
+    }
+    restore_secrets(&mut config, &secrets);
+
+    if let Some(memory_file) = &config.memory.memory_file {
+        let bundled = entries.iter().find(|(name, _)| name.starts_with(&format!("{}/", MEMORY_DIR)));
+        if let Some((_, data)) = bundled {
+            backup(memory_file)?;
+            if let Some(parent) = memory_file.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(memory_file, data)?;
+        }
+    }
+
+    let mut sessions_imported = 0;
+    let mut sessions_skipped = 0;
+    for (name, data) in &entries {
+        let Some(file_name) = name.strip_prefix(&format!("{}/", SESSIONS_DIR)) else { continue };
+        // 只接受目录下的单个文件名，拒绝带路径的条目
+        if file_name.is_empty() || file_name.contains(['/', '\\']) || file_name.starts_with('.') {
+            continue;
+        }
+        let target = locations.sessions_dir.join(file_name);
+        let counted = usize::from(file_name.ends_with(".json"));
+        if target.exists() && !options.overwrite_sessions {
+            sessions_skipped += counted;
+            continue;
+        }
+        std::fs::create_dir_all(&locations.sessions_dir)?;
+        std::fs::write(&target, data)?;
+        sessions_imported += counted;
+    }
+
+    let backup_path = backup(&locations.config_file)?;
+    let format = super::ConfigManager::detect_format(&locations.config_file)?;
+    super::ConfigManager::save_config_file(&config, &locations.config_file, &format)?;
+
+    Ok(ImportSummary {
+        manifest,
+        config_path: locations.config_file.clone(),
+        backup_path,
+        sessions_imported,
+        sessions_skipped,
+        secrets_restored,
+        secrets_locked,
+    })
+}
+
+/// 从配置中移除凭据，返回被移除的名称和值
+fn extract_secrets(config: &mut ClaudeConfig) -> BTreeMap<String, String> {
+    let mut secrets = BTreeMap::new();
+    if let Some(key) = config.api.anthropic_api_key.take() {
+        secrets.insert(API_KEY_SECRET.to_string(), key);
+    }
+    for (server, definition) in config.mcp_servers.iter_mut() {
+        let names: Vec<String> = definition.env.keys().filter(|name| is_secret_name(name)).cloned().collect();
+        for name in names {
+            if let Some(value) = definition.env.remove(&name) {
+                secrets.insert(format!("mcp_servers.{}.env.{}", server, name), value);
+            }
+        }
+    }
+    for (name, webhook) in config.webhooks.iter_mut() {
+        if let Some(secret) = webhook.secret.take() {
+            secrets.insert(format!("webhooks.{}.secret", name), secret);
+        }
+    }
+    if let Some(password) = config.reports.smtp.as_mut().and_then(|smtp| smtp.password.take()) {
+        secrets.insert(SMTP_PASSWORD_SECRET.to_string(), password);
+    }
+    secrets
+}
+
+/// 把凭据写回配置，找不到对应位置的凭据被忽略
+fn restore_secrets(config: &mut ClaudeConfig, secrets: &BTreeMap<String, String>) {
+    for (name, value) in secrets {
+        if name == API_KEY_SECRET {
+            config.api.anthropic_api_key = Some(value.clone());
+            continue;
+        }
+        if name == SMTP_PASSWORD_SECRET {
+            if let Some(smtp) = config.reports.smtp.as_mut() {
+                smtp.password = Some(value.clone());
+            }
+            continue;
+        }
+        if let Some(webhook) = name.strip_prefix("webhooks.").and_then(|rest| rest.strip_suffix(".secret")) {
+            if let Some(webhook) = config.webhooks.get_mut(webhook) {
+                webhook.secret = Some(value.clone());
+            }
+            continue;
+        }
+        let Some((server, env)) = name.strip_prefix("mcp_servers.").and_then(|rest| rest.split_once(".env.")) else {
+            continue;
+        };
+        if let Some(definition) = config.mcp_servers.get_mut(server) {
+            definition.env.insert(env.to_string(), value.clone());
+        }
+    }
+}
+
+fn is_secret_name(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    SECRET_ENV_MARKERS.iter().any(|marker| upper.contains(marker))
+}
+
+/// 以口令加密：salt | nonce | 密文
+fn seal(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| ClaudeError::General("Failed to encrypt secrets".to_string()))?;
+
+    let mut sealed = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+fn open(sealed: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    if sealed.len() < SALT_LEN + NONCE_LEN {
+        return Err(ClaudeError::validation_error("bundle", "Encrypted secrets are truncated"));
+    }
+    let (salt, rest) = sealed.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| ClaudeError::auth_error("Wrong passphrase for the bundled secrets"))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| ClaudeError::General(format!("Failed to derive key: {}", e)))?;
+    Ok(key)
+}
+
+fn read_entries(bundle: &Path) -> Result<BTreeMap<String, Vec<u8>>> {
+    let file = std::fs::File::open(bundle)
+        .map_err(|e| ClaudeError::fs_error(format!("Failed to open {}: {}", bundle.display(), e)))?;
+    let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut entries = BTreeMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().replace('\\', "/");
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.insert(name, data);
+    }
+    Ok(entries)
+}
+
+/// 已存在的文件复制为 `<文件名>.bak`
+fn backup(path: &Path) -> Result<Option<PathBuf>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    let backup = path.with_file_name(name);
+    std::fs::copy(path, &backup)?;
+    Ok(Some(backup))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec_pretty(value)?)
+}
+
+fn from_json<T: for<'de> Deserialize<'de>>(data: &[u8]) -> Result<T> {
+    Ok(serde_json::from_slice(data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::McpServerConfig;
+
+    fn sample_config() -> ClaudeConfig {
+        let mut config = ClaudeConfig::default();
+        config.api.anthropic_api_key = Some("sk-ant-test".to_string());
+        config.mcp_servers.insert(
+            "github".to_string(),
+            McpServerConfig {
+                name: "github".to_string(),
+                command: "github-mcp".to_string(),
+                args: Vec::new(),
+                env: [
+                    ("GITHUB_TOKEN".to_string(), "ghp_secret".to_string()),
+                    ("LOG_LEVEL".to_string(), "info".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+                working_dir: None,
+                auto_start: true,
+                ..Default::default()
+            },
+        );
+        config
+    }
+
+    fn locations(root: &Path) -> StateLocations {
+        StateLocations {
+            config_file: root.join("config.json"),
+            sessions_dir: root.join("sessions"),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_excludes_secrets_without_passphrase() {
+        let source = tempfile::TempDir::new().unwrap();
+        let target = tempfile::TempDir::new().unwrap();
+        let source_locations = locations(source.path());
+        std::fs::create_dir_all(&source_locations.sessions_dir).unwrap();
+        std::fs::write(source_locations.sessions_dir.join("abc.json"), "{}").unwrap();
+
+        let bundle = source.path().join("state.tar.zst");
+        let options = ExportOptions { passphrase: None, include_sessions: true };
+        let manifest = export_state(&sample_config(), &source_locations, &bundle, &options).unwrap();
+        assert_eq!(manifest.secrets, SecretHandling::Excluded);
+        assert_eq!(manifest.sessions, 1);
+
+        let raw = read_entries(&bundle).unwrap();
+        let config_text = String::from_utf8(raw[CONFIG_ENTRY].clone()).unwrap();
+        assert!(!config_text.contains("sk-ant-test") && !config_text.contains("ghp_secret"));
+        assert!(config_text.contains("LOG_LEVEL"));
+
+        let summary = import_state(&bundle, &locations(target.path()), &ImportOptions::default()).unwrap();
+        assert_eq!(summary.sessions_imported, 1);
+        assert!(!summary.secrets_locked);
+        let imported = crate::config::ConfigManager::read_config_file(&summary.config_path).unwrap();
+        assert!(!imported.mcp_servers["github"].env.contains_key("GITHUB_TOKEN"));
+        assert_eq!(imported.mcp_servers["github"].command, "github-mcp");
+    }
+
+    #[test]
+    fn test_secrets_are_encrypted_with_passphrase() {
+        let source = tempfile::TempDir::new().unwrap();
+        let target = tempfile::TempDir::new().unwrap();
+        let bundle = source.path().join("state.tar.zst");
+        let options = ExportOptions { passphrase: Some("correct horse".to_string()), include_sessions: false };
+        let manifest = export_state(&sample_config(), &locations(source.path()), &bundle, &options).unwrap();
+        assert_eq!(manifest.secrets, SecretHandling::Encrypted);
+        assert!(manifest.secret_names.contains(&"mcp_servers.github.env.GITHUB_TOKEN".to_string()));
+
+        let wrong = ImportOptions { passphrase: Some("wrong".to_string()), overwrite_sessions: false };
+        assert!(import_state(&bundle, &locations(target.path()), &wrong).is_err());
+
+        let right = ImportOptions { passphrase: Some("correct horse".to_string()), overwrite_sessions: false };
+        let summary = import_state(&bundle, &locations(target.path()), &right).unwrap();
+        assert_eq!(summary.secrets_restored, 2);
+        let imported = crate::config::ConfigManager::read_config_file(&summary.config_path).unwrap();
+        assert_eq!(imported.mcp_servers["github"].env["GITHUB_TOKEN"], "ghp_secret");
+    }
+}
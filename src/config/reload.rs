@@ -0,0 +1,442 @@
+//! 配置热重载
+//!
+//! 监控当前配置文件，变化时只把可以安全热更新的设置（界面主题、默认模型与采样参数、
+//! token 预算、权限）应用到运行中的会话；需要重启才能生效的设置保持原值并给出警告
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use super::{ClaudeConfig, ConfigManager};
+use crate::error::{ClaudeError, Result};
+use crate::watcher::{FileWatcher, WatchConfig};
+
+/// 可以在运行中修改的配置项（键本身或其子键）
+const HOT_RELOADABLE: &[&str] = &[
+    "ui",
+    "permissions",
+    "model",
+    "api.default_model",
+    "api.temperature",
+    "api.top_p",
+    "api.top_k",
+    "api.max_tokens",
+    "preferences.response_language",
+];
+
+/// 编辑器保存时常见的连续写入在此时间内合并为一次重载
+const SETTLE_DELAY: Duration = Duration::from_millis(150);
+
+/// 单个配置项的变化
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    /// 以点分隔的配置键，如 `ui.theme`
+    pub key: String,
+    /// 旧值，`None` 表示原来不存在
+    pub old: Option<Value>,
+    /// 新值，`None` 表示已删除
+    pub new: Option<Value>,
+}
+
+impl ConfigChange {
+    fn describe(&self) -> String {
+        format!("{}: {} → {}", self.key, display_value(&self.key, &self.old), display_value(&self.key, &self.new))
+    }
+
+    fn is_hot_reloadable(&self) -> bool {
+        HOT_RELOADABLE
+            .iter()
+            .any(|prefix| self.key == *prefix || self.key.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('.')))
+    }
+}
+
+/// 一次重载的结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReloadOutcome {
+    /// 已应用到运行中会话的变化
+    pub applied: Vec<ConfigChange>,
+    /// 需要重启才能生效而被忽略的变化
+    pub rejected: Vec<ConfigChange>,
+    /// 配置文件无法解析或未通过校验时的原因，此时不应用任何变化
+    pub error: Option<String>,
+}
+
+impl ReloadOutcome {
+    /// 是否没有任何需要告知用户的内容
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.rejected.is_empty() && self.error.is_none()
+    }
+
+    /// 描述已应用变化的系统消息
+    pub fn system_message(&self) -> Option<String> {
+        if self.applied.is_empty() {
+            return None;
+        }
+        let changes: Vec<String> = self.applied.iter().map(|c| format!("  • {}", c.describe())).collect();
+        Some(format!("⚙️ Configuration reloaded:\n{}", changes.join("\n")))
+    }
+
+    /// 被拒绝的变化或重载失败的警告
+    pub fn warning(&self) -> Option<String> {
+        if let Some(error) = &self.error {
+            return Some(format!("⚠️ Configuration reload failed, keeping previous settings: {}", error));
+        }
+        if self.rejected.is_empty() {
+            return None;
+        }
+        let keys: Vec<&str> = self.rejected.iter().map(|c| c.key.as_str()).collect();
+        Some(format!(
+            "⚠️ Restart required for these config changes, they were not applied: {}",
+            keys.join(", ")
+        ))
+    }
+}
+
+/// 比较两份配置，按能否热更新分类所有变化
+pub fn diff_configs(old: &ClaudeConfig, new: &ClaudeConfig) -> Result<ReloadOutcome> {
+    let mut changes = Vec::new();
+    collect_changes("", Some(&serde_json::to_value(old)?), Some(&serde_json::to_value(new)?), &mut changes);
+
+    let (applied, rejected) = changes.into_iter().partition(ConfigChange::is_hot_reloadable);
+    Ok(ReloadOutcome { applied, rejected, error: None })
+}
+
+/// 把可热更新的变化应用到运行中的配置
+pub fn apply_changes(config: &ClaudeConfig, changes: &[ConfigChange]) -> Result<ClaudeConfig> {
+    let mut value = serde_json::to_value(config)?;
+    for change in changes {
+        let mut segments: Vec<&str> = change.key.split('.').collect();
+        let Some(field) = segments.pop() else { continue };
+
+        let mut target = &mut value;
+        for segment in segments {
+            target = target
+                .as_object_mut()
+                .map(|map| map.entry(segment).or_insert_with(|| Value::Object(Default::default())))
+                .ok_or_else(|| ClaudeError::config_error(format!("Cannot apply config change to {}", change.key)))?;
+        }
+        let Some(map) = target.as_object_mut() else {
+            return Err(ClaudeError::config_error(format!("Cannot apply config change to {}", change.key)));
+        };
+        match &change.new {
+            Some(new) => map.insert(field.to_string(), new.clone()),
+            None => map.remove(field),
+        };
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// 校验将要热更新的设置，避免把运行中的会话切换到无效配置
+fn validate(config: &ClaudeConfig) -> Result<()> {
+    if config.api.default_model.trim().is_empty() {
+        return Err(ClaudeError::validation_error("api.default_model", "Default model cannot be empty"));
+    }
+    if !(0.0..=1.0).contains(&config.api.temperature) {
+        return Err(ClaudeError::validation_error("api.temperature", "Temperature must be between 0.0 and 1.0"));
+    }
+    if !(0.0..=1.0).contains(&config.api.top_p) {
+        return Err(ClaudeError::validation_error("api.top_p", "Top-p must be between 0.0 and 1.0"));
+    }
+    if config.api.max_tokens == 0 {
+        return Err(ClaudeError::validation_error("api.max_tokens", "Max tokens must be greater than 0"));
+    }
+    crate::security::command_guard::CommandGuard::from_config(&config.permissions.bash)?;
+    Ok(())
+}
+
+fn collect_changes(key: &str, old: Option<&Value>, new: Option<&Value>, changes: &mut Vec<ConfigChange>) {
+    if old == new {
+        return;
+    }
+    if let (Some(Value::Object(old)), Some(Value::Object(new))) = (old, new) {
+        let mut fields: Vec<&String> = old.keys().chain(new.keys().filter(|k| !old.contains_key(*k))).collect();
+        fields.sort();
+        for field in fields {
+            let child = if key.is_empty() { field.clone() } else { format!("{}.{}", key, field) };
+            collect_changes(&child, old.get(field), new.get(field), changes);
+        }
+        return;
+    }
+    changes.push(ConfigChange {
+        key: key.to_string(),
+        old: old.cloned(),
+        new: new.cloned(),
+    });
+}
+
+fn display_value(key: &str, value: &Option<Value>) -> String {
+    match value {
+        None | Some(Value::Null) => "(unset)".to_string(),
+        Some(_) if key.contains("api_key") => "(hidden)".to_string(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// 配置热重载器
+///
+/// 持有运行中的配置，文件变化时合并可热更新的设置并广播重载结果
+pub struct ConfigReloader {
+    inner: Arc<ReloaderState>,
+    watcher: Option<FileWatcher>,
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+struct ReloaderState {
+    path: PathBuf,
+    /// 运行中的配置（可能包含环境变量等覆盖）
+    current: RwLock<ClaudeConfig>,
+    /// 上次从磁盘读到的配置，用于计算文件本身的变化
+    on_disk: Mutex<ClaudeConfig>,
+    updates: broadcast::Sender<ReloadOutcome>,
+}
+
+impl ConfigReloader {
+    /// 以运行中的配置创建重载器，`path` 为当前生效的配置文件
+    pub fn new(path: impl Into<PathBuf>, current: ClaudeConfig) -> Self {
+        let path = path.into();
+        let on_disk = ConfigManager::read_config_file(&path)
+            .map(|config| ConfigManager::trusted_view(&path, config))
+            .unwrap_or_else(|_| current.clone());
+        let (updates, _) = broadcast::channel(16);
+        Self {
+            inner: Arc::new(ReloaderState {
+                path,
+                current: RwLock::new(current),
+                on_disk: Mutex::new(on_disk),
+                updates,
+            }),
+            watcher: None,
+            task: None,
+        }
+    }
+
+    /// 为配置管理器当前使用的文件创建重载器
+    pub fn for_manager(manager: &ConfigManager) -> Self {
+        Self::new(manager.config_path(), manager.runtime_config())
+    }
+
+    /// 被监控的配置文件
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// 当前生效配置的快照
+    pub fn config(&self) -> ClaudeConfig {
+        self.inner.current.read().unwrap().clone()
+    }
+
+    /// 订阅重载结果
+    pub fn subscribe(&self) -> broadcast::Receiver<ReloadOutcome> {
+        self.inner.updates.subscribe()
+    }
+
+    /// 立即从磁盘重载一次
+    pub fn reload(&self) -> ReloadOutcome {
+        self.inner.reload()
+    }
+
+    /// 开始监控配置文件
+    ///
+    /// 监控所在目录而不是文件本身，编辑器以重命名方式保存时也能收到变化
+    pub fn start(&mut self) -> Result<()> {
+        if self.task.is_some() {
+            return Ok(());
+        }
+        let dir = match self.inner.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name = self
+            .inner
+            .path
+            .file_name()
+            .map(|name| name.to_os_string())
+            .ok_or_else(|| ClaudeError::config_error(format!("Invalid config path: {}", self.inner.path.display())))?;
+
+        let mut watcher = FileWatcher::new()?;
+        watcher.watch_path(
+            &dir,
+            WatchConfig {
+                recursive: false,
+                ignore_patterns: Vec::new(),
+                watch_extensions: None,
+                debounce_delay: 50,
+                max_files: None,
+            },
+        )?;
+        let mut events = watcher.subscribe();
+
+        let inner = self.inner.clone();
+        self.task = Some(tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.path.file_name() == Some(file_name.as_os_str()) => {}
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+
+                tokio::time::sleep(SETTLE_DELAY).await;
+                while events.try_recv().is_ok() {}
+
+                if inner.path.exists() {
+                    let outcome = inner.reload();
+                    if !outcome.is_empty() {
+                        let _ = inner.updates.send(outcome);
+                    }
+                }
+            }
+        }));
+        self.watcher = Some(watcher);
+        Ok(())
+    }
+
+    /// 停止监控
+    pub fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        if let Some(mut watcher) = self.watcher.take() {
+            watcher.stop();
+        }
+    }
+}
+
+impl Drop for ConfigReloader {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl ReloaderState {
+    fn reload(&self) -> ReloadOutcome {
+        let new = match ConfigManager::read_config_file(&self.path) {
+            Ok(config) => ConfigManager::trusted_view(&self.path, config),
+            Err(e) => return ReloadOutcome { error: Some(e.to_string()), ..Default::default() },
+        };
+
+        let mut on_disk = self.on_disk.lock().unwrap();
+        let mut outcome = match diff_configs(&on_disk, &new) {
+            Ok(outcome) => outcome,
+            Err(e) => return ReloadOutcome { error: Some(e.to_string()), ..Default::default() },
+        };
+        if outcome.applied.is_empty() {
+            *on_disk = new;
+            return outcome;
+        }
+
+        let mut current = self.current.write().unwrap();
+        let merged = apply_changes(&current, &outcome.applied).and_then(|merged| validate(&merged).map(|_| merged));
+        match merged {
+            Ok(merged) => {
+                tracing::info!("Applied {} config change(s) from {}", outcome.applied.len(), self.path.display());
+                *current = merged;
+                *on_disk = new;
+            }
+            Err(e) => {
+                tracing::warn!("Rejected config reload from {}: {}", self.path.display(), e);
+                outcome.applied.clear();
+                outcome.rejected.clear();
+                outcome.error = Some(e.to_string());
+            }
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(path: &Path, config: &ClaudeConfig) {
+        std::fs::write(path, serde_yaml::to_string(config).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_diff_classifies_changes() {
+        let old = ClaudeConfig::default();
+        let mut new = old.clone();
+        new.ui.theme = "solarized".to_string();
+        new.api.default_model = "claude-3-opus-20240229".to_string();
+        new.permissions.denied_tools.push("bash".to_string());
+        new.api.base_url = "https://proxy.example.com".to_string();
+        new.api.anthropic_api_key = Some("sk-secret".to_string());
+
+        let outcome = diff_configs(&old, &new).unwrap();
+        let applied: Vec<&str> = outcome.applied.iter().map(|c| c.key.as_str()).collect();
+        let rejected: Vec<&str> = outcome.rejected.iter().map(|c| c.key.as_str()).collect();
+        assert_eq!(applied, vec!["api.default_model", "permissions.denied_tools", "ui.theme"]);
+        assert_eq!(rejected, vec!["api.anthropic_api_key", "api.base_url"]);
+
+        let message = outcome.system_message().unwrap();
+        assert!(message.contains("ui.theme: \"default\" → \"solarized\""));
+        assert!(outcome.warning().unwrap().contains("api.base_url"));
+        assert!(!format!("{:?}", outcome.rejected.iter().map(ConfigChange::describe).collect::<Vec<_>>()).contains("sk-secret"));
+
+        let merged = apply_changes(&old, &outcome.applied).unwrap();
+        assert_eq!(merged.ui.theme, "solarized");
+        assert_eq!(merged.permissions.denied_tools, vec!["bash".to_string()]);
+        assert_eq!(merged.api.base_url, old.api.base_url);
+    }
+
+    #[test]
+    fn test_reload_keeps_runtime_overrides() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.yaml");
+        let file_config = ClaudeConfig::default();
+        write_config(&path, &file_config);
+
+        // 运行中的配置带有来自环境变量的 API key
+        let mut runtime = file_config.clone();
+        runtime.api.anthropic_api_key = Some("from-env".to_string());
+        let reloader = ConfigReloader::new(&path, runtime);
+        assert!(reloader.reload().is_empty());
+
+        let mut edited = file_config.clone();
+        edited.api.temperature = 0.2;
+        edited.api.timeout = 5;
+        write_config(&path, &edited);
+
+        let outcome = reloader.reload();
+        assert_eq!(outcome.applied.len(), 1);
+        assert_eq!(outcome.rejected[0].key, "api.timeout");
+        let config = reloader.config();
+        assert!((config.api.temperature - 0.2).abs() < f32::EPSILON);
+        assert_eq!(config.api.timeout, file_config.api.timeout);
+        assert_eq!(config.api.anthropic_api_key.as_deref(), Some("from-env"));
+
+        // 同一份文件不会重复报告
+        assert!(reloader.reload().is_empty());
+
+        edited.api.temperature = 3.0;
+        write_config(&path, &edited);
+        let outcome = reloader.reload();
+        assert!(outcome.error.unwrap().contains("api.temperature"));
+        assert!((reloader.config().api.temperature - 0.2).abs() < f32::EPSILON);
+
+        std::fs::write(&path, "api: [not, a, map").unwrap();
+        assert!(reloader.reload().warning().unwrap().contains("reload failed"));
+    }
+
+    #[tokio::test]
+    async fn test_watcher_broadcasts_changes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.yaml");
+        let config = ClaudeConfig::default();
+        write_config(&path, &config);
+
+        let mut reloader = ConfigReloader::new(&path, config.clone());
+        let mut updates = reloader.subscribe();
+        reloader.start().unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut edited = config;
+        edited.ui.vim_mode = !edited.ui.vim_mode;
+        write_config(&path, &edited);
+
+        let outcome = tokio::time::timeout(Duration::from_secs(5), updates.recv()).await.unwrap().unwrap();
+        assert_eq!(outcome.applied[0].key, "ui.vim_mode");
+        assert_eq!(reloader.config().ui.vim_mode, edited.ui.vim_mode);
+    }
+}
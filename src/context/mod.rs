@@ -2,21 +2,16 @@
 //! 
 //! 基于原版 wU2 压缩算法，实现 92% 阈值自动压缩和 8 段式结构化压缩
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use serde::{Deserialize, Serialize};
 use tokio::time::{Duration, Instant};
 use crate::error::{ClaudeError, Result};
 use crate::conversation::ConversationManager;
+use crate::conversation::SharedText;
 use crate::network::Message;
 
-/// 上下文压缩阈值 (92%)
-const COMPRESSION_THRESHOLD: f64 = 0.92;
-
-/// 警告阈值 (60%)
-const WARNING_THRESHOLD: f64 = 0.6;
-
-/// 错误阈值 (80%)
-const ERROR_THRESHOLD: f64 = 0.8;
+/// 上下文压缩阈值 (92%)，与 Web UI 的上下文用量显示共用
+use crate::tokens::COMPRESSION_THRESHOLD;
 
 /// 8段式压缩结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,19 +19,19 @@ pub struct CompressedContext {
     /// 背景上下文
     pub background_context: String,
     /// 关键决策
-    pub key_decisions: Vec<String>,
+    pub key_decisions: Vec<SharedText>,
     /// 工具使用记录
     pub tool_usage: Vec<ToolUsageRecord>,
     /// 用户意图
     pub user_intent: String,
     /// 执行结果
-    pub execution_results: Vec<String>,
+    pub execution_results: Vec<SharedText>,
     /// 错误处理记录
-    pub error_cases: Vec<String>,
+    pub error_cases: Vec<SharedText>,
     /// 未解决问题
-    pub open_issues: Vec<String>,
+    pub open_issues: Vec<SharedText>,
     /// 后续计划
-    pub future_plans: Vec<String>,
+    pub future_plans: Vec<SharedText>,
     /// 压缩时间戳
     pub compressed_at: u64,
     /// 原始消息数量
@@ -84,8 +79,10 @@ pub struct ContextManager {
     compression_threshold: f64,
     /// 统计信息
     stats: ContextStats,
-    /// 重要性评分缓存
-    importance_cache: HashMap<String, f64>,
+    /// 重要性评分缓存，键与上下文中的消息共享文本
+    importance_cache: HashMap<SharedText, f64>,
+    /// 已固定的消息内容，压缩时原样保留在原来的位置
+    pinned: HashSet<SharedText>,
 }
 
 impl ContextManager {
@@ -105,6 +102,7 @@ impl ContextManager {
                 last_compression: None,
             },
             importance_cache: HashMap::new(),
+            pinned: HashSet::new(),
         }
     }
 
@@ -121,6 +119,21 @@ impl ContextManager {
         Ok(())
     }
 
+    /// 固定消息，之后的压缩不会移除或改写它
+    pub fn pin(&mut self, content: SharedText) {
+        self.pinned.insert(content);
+    }
+
+    /// 取消固定
+    pub fn unpin(&mut self, content: &str) {
+        self.pinned.remove(content);
+    }
+
+    /// 消息是否已固定
+    pub fn is_pinned(&self, content: &str) -> bool {
+        self.pinned.contains(content)
+    }
+
     /// 获取当前上下文
     pub fn get_current_context(&self) -> &VecDeque<Message> {
         &self.current_context
@@ -236,7 +249,7 @@ impl ContextManager {
     }
 
     /// 识别关键决策
-    async fn extract_key_decisions(&self, messages: &[&Message]) -> Result<Vec<String>> {
+    async fn extract_key_decisions(&self, messages: &[&Message]) -> Result<Vec<SharedText>> {
         let mut decisions = Vec::new();
 
         for message in messages {
@@ -267,21 +280,20 @@ impl ContextManager {
     /// 提取用户意图
     async fn extract_user_intent(&self, messages: &[&Message]) -> Result<String> {
         // 分析用户消息，提取主要意图
-        let user_messages: Vec<String> = messages
+        let last_user_message = messages
             .iter()
-            .filter(|m| m.role == "user")
-            .map(|m| m.content.clone())
-            .collect();
+            .rev()
+            .find(|m| m.role == "user");
         
-        if let Some(last_user_message) = user_messages.last() {
-            Ok(last_user_message.clone())
+        if let Some(last_user_message) = last_user_message {
+            Ok(last_user_message.content.to_string())
         } else {
             Ok("No clear user intent identified".to_string())
         }
     }
 
     /// 总结执行结果
-    async fn summarize_execution_results(&self, messages: &[&Message]) -> Result<Vec<String>> {
+    async fn summarize_execution_results(&self, messages: &[&Message]) -> Result<Vec<SharedText>> {
         let mut results = Vec::new();
         
         for message in messages {
@@ -294,7 +306,7 @@ impl ContextManager {
     }
 
     /// 提取错误处理记录
-    async fn extract_error_cases(&self, messages: &[&Message]) -> Result<Vec<String>> {
+    async fn extract_error_cases(&self, messages: &[&Message]) -> Result<Vec<SharedText>> {
         let mut errors = Vec::new();
         
         for message in messages {
@@ -307,7 +319,7 @@ impl ContextManager {
     }
 
     /// 识别未解决问题
-    async fn identify_open_issues(&self, messages: &[&Message]) -> Result<Vec<String>> {
+    async fn identify_open_issues(&self, messages: &[&Message]) -> Result<Vec<SharedText>> {
         let mut issues = Vec::new();
         
         for message in messages {
@@ -320,7 +332,7 @@ impl ContextManager {
     }
 
     /// 生成后续计划
-    async fn generate_future_plans(&self, messages: &[&Message]) -> Result<Vec<String>> {
+    async fn generate_future_plans(&self, messages: &[&Message]) -> Result<Vec<SharedText>> {
         let mut plans = Vec::new();
         
         for message in messages {
@@ -334,26 +346,24 @@ impl ContextManager {
 
     /// 保留重要消息
     async fn retain_important_messages(&mut self) -> Result<()> {
-        let mut important_messages = VecDeque::new();
-        
-        // 计算每条消息的重要性评分
-        let messages_to_check: Vec<_> = self.current_context.iter().cloned().collect();
-        for message in messages_to_check {
-            let importance = self.calculate_importance_score(&message).await?;
-            if importance > 0.7 { // 保留重要性评分 > 0.7 的消息
-                important_messages.push_back(message);
-            }
+        // 固定的消息和重要性评分 > 0.7 的消息保留，并保持原有顺序
+        let messages: Vec<_> = self.current_context.iter().cloned().collect();
+        let mut keep = Vec::with_capacity(messages.len());
+        for message in &messages {
+            let retained = self.pinned.contains(&message.content) || self.calculate_importance_score(message).await? > 0.7;
+            keep.push(retained);
         }
-        
+
         // 至少保留最后几条消息
         let min_retain = 5;
-        while important_messages.len() < min_retain && !self.current_context.is_empty() {
-            if let Some(message) = self.current_context.pop_back() {
-                important_messages.push_front(message);
-            }
-        }
-        
-        self.current_context = important_messages;
+        let tail_start = messages.len().saturating_sub(min_retain);
+        self.current_context = messages
+            .into_iter()
+            .zip(keep)
+            .enumerate()
+            .filter(|(index, (_, retained))| *retained || *index >= tail_start)
+            .map(|(_, (message, _))| message)
+            .collect();
         Ok(())
     }
 
@@ -405,13 +415,10 @@ impl ContextManager {
 
     /// 估算 Token 数量
     fn estimate_token_count(&self) -> u32 {
-        // 简化的 Token 估算：大约 4 个字符 = 1 个 Token
-        let total_chars: usize = self.current_context
+        self.current_context
             .iter()
-            .map(|m| m.content.len())
-            .sum();
-        
-        (total_chars / 4) as u32
+            .map(|m| crate::tokens::estimate_tokens(&m.content))
+            .sum()
     }
 
     /// 获取压缩历史
@@ -441,7 +448,7 @@ mod tests {
         let mut manager = ContextManager::new(100000);
         let message = Message {
             role: "user".to_string(),
-            content: crate::network::MessageContent::Text("Hello, Claude!".to_string()),
+            content: "Hello, Claude!".into(),
         };
         
         manager.add_message(message).await.unwrap();
@@ -453,10 +460,28 @@ mod tests {
         let mut manager = ContextManager::new(100000);
         let important_message = Message {
             role: "system".to_string(),
-            content: crate::network::MessageContent::Text("这是一个重要的系统消息".to_string()),
+            content: "这是一个重要的系统消息".into(),
         };
         
         let score = manager.calculate_importance_score(&important_message).await.unwrap();
         assert!(score > 0.8);
     }
+
+    #[tokio::test]
+    async fn test_pinned_messages_are_kept_in_place() {
+        let mut manager = ContextManager::new(100000);
+        for i in 0..12 {
+            manager.current_context.push_back(Message {
+                role: "tool".to_string(),
+                content: format!("步骤 {}", i).into(),
+            });
+        }
+        let pinned: SharedText = "步骤 1".into();
+        manager.pin(pinned.clone());
+
+        manager.compress_context().await.unwrap();
+        let contents: Vec<&str> = manager.get_current_context().iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["步骤 1", "步骤 7", "步骤 8", "步骤 9", "步骤 10", "步骤 11"]);
+        assert!(manager.is_pinned("步骤 1"));
+    }
 }
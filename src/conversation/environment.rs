@@ -0,0 +1,191 @@
+//! 会话环境快照
+//!
+//! 会话开始时记录操作系统、工具链版本和 Git 提交等信息并随会话保存，
+//! 需要时可注入上下文，便于复现 "在我机器上没问题" 类问题
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "native")]
+use std::path::Path;
+#[cfg(feature = "native")]
+use tokio::process::Command;
+#[cfg(feature = "native")]
+use tokio::time::{timeout, Duration};
+
+/// 单个探测命令的超时时间
+#[cfg(feature = "native")]
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 需要探测版本的工具：(名称, 程序, 参数)
+#[cfg(feature = "native")]
+const TOOL_PROBES: &[(&str, &str, &[&str])] = &[
+    ("rustc", "rustc", &["--version"]),
+    ("cargo", "cargo", &["--version"]),
+    ("node", "node", &["--version"]),
+    ("npm", "npm", &["--version"]),
+    ("python", "python3", &["--version"]),
+    ("go", "go", &["version"]),
+    ("git", "git", &["--version"]),
+    ("docker", "docker", &["--version"]),
+];
+
+/// 记录的环境变量（不包含任何凭据）
+#[cfg(feature = "native")]
+const RECORDED_ENV_VARS: &[&str] = &["SHELL", "TERM", "LANG", "RUSTUP_TOOLCHAIN", "VIRTUAL_ENV", "NODE_ENV"];
+
+/// Git 仓库状态
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GitSnapshot {
+    /// 当前提交
+    pub sha: String,
+    /// 当前分支
+    pub branch: Option<String>,
+    /// 是否有未提交的修改
+    pub dirty: bool,
+}
+
+/// 会话环境快照
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EnvironmentSnapshot {
+    /// 采集时间
+    pub captured_at: DateTime<Utc>,
+    /// 操作系统
+    pub os: String,
+    /// CPU 架构
+    pub arch: String,
+    /// 内核/系统版本
+    pub os_version: Option<String>,
+    /// 工作目录
+    pub working_dir: String,
+    /// Git 状态
+    pub git: Option<GitSnapshot>,
+    /// 工具版本
+    pub tools: BTreeMap<String, String>,
+    /// 相关环境变量
+    pub env_vars: BTreeMap<String, String>,
+}
+
+impl EnvironmentSnapshot {
+    /// 采集当前环境，探测失败的工具会被忽略
+    #[cfg(feature = "native")]
+    pub async fn capture(working_dir: &Path) -> Self {
+        let tool_probes = TOOL_PROBES.iter().map(|(name, program, args)| async move {
+            probe(program, args, None).await.map(|version| (name.to_string(), version))
+        });
+        let (tools, os_version, git) = tokio::join!(
+            futures::future::join_all(tool_probes),
+            probe_os_version(),
+            probe_git(working_dir),
+        );
+
+        Self {
+            captured_at: Utc::now(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            os_version,
+            working_dir: working_dir.to_string_lossy().to_string(),
+            git,
+            tools: tools.into_iter().flatten().collect(),
+            env_vars: RECORDED_ENV_VARS
+                .iter()
+                .filter_map(|key| std::env::var(key).ok().map(|value| (key.to_string(), value)))
+                .collect(),
+        }
+    }
+
+    /// 格式化为可注入上下文的文本
+    pub fn to_context_string(&self) -> String {
+        let mut lines = vec![
+            format!("Environment snapshot (captured {})", self.captured_at.format("%Y-%m-%d %H:%M:%S UTC")),
+            format!("- OS: {} {}{}", self.os, self.arch, self.os_version.as_deref().map(|v| format!(" ({})", v)).unwrap_or_default()),
+            format!("- Working directory: {}", self.working_dir),
+        ];
+
+        if let Some(git) = &self.git {
+            lines.push(format!(
+                "- Git: {}{}{}",
+                git.sha,
+                git.branch.as_deref().map(|b| format!(" on {}", b)).unwrap_or_default(),
+                if git.dirty { " (uncommitted changes)" } else { "" }
+            ));
+        }
+        for (name, version) in &self.tools {
+            lines.push(format!("- {}: {}", name, version));
+        }
+        for (key, value) in &self.env_vars {
+            lines.push(format!("- ${}={}", key, value));
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// 运行命令并返回首行输出
+#[cfg(feature = "native")]
+async fn probe(program: &str, args: &[&str], dir: Option<&Path>) -> Option<String> {
+    let mut command = Command::new(program);
+    command.args(args).kill_on_drop(true);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+
+    let output = timeout(PROBE_TIMEOUT, command.output()).await.ok()?.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // python2 等工具会把版本输出到 stderr
+    let text = if output.stdout.is_empty() { output.stderr } else { output.stdout };
+    String::from_utf8_lossy(&text)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+}
+
+#[cfg(feature = "native")]
+async fn probe_os_version() -> Option<String> {
+    if cfg!(windows) {
+        probe("cmd", &["/C", "ver"], None).await
+    } else {
+        probe("uname", &["-sr"], None).await
+    }
+}
+
+#[cfg(feature = "native")]
+async fn probe_git(working_dir: &Path) -> Option<GitSnapshot> {
+    let sha = probe("git", &["rev-parse", "HEAD"], Some(working_dir)).await?;
+    let (branch, status) = tokio::join!(
+        probe("git", &["rev-parse", "--abbrev-ref", "HEAD"], Some(working_dir)),
+        probe("git", &["status", "--porcelain"], Some(working_dir)),
+    );
+
+    Some(GitSnapshot {
+        sha,
+        branch: branch.filter(|b| b != "HEAD"),
+        dirty: status.is_some(),
+    })
+}
+
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_capture_snapshot() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let snapshot = EnvironmentSnapshot::capture(temp_dir.path()).await;
+
+        assert_eq!(snapshot.os, std::env::consts::OS);
+        assert!(snapshot.git.is_none());
+
+        let context = snapshot.to_context_string();
+        assert!(context.starts_with("Environment snapshot"));
+        assert!(context.contains(&snapshot.working_dir));
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored: EnvironmentSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, snapshot);
+    }
+}
@@ -0,0 +1,305 @@
+//! 会话事件日志
+//!
+//! 会话状态的唯一来源是只追加的事件日志（`<id>.events.jsonl`），`<id>.json` 只是定期写出的快照。
+//! 加载时从快照开始重放之后的事件；进程在回合中途崩溃时，日志里停在最后一个写入的事件，
+//! 恢复后能知道哪条输入还没有得到回复、期间已经执行了哪些工具。审计和回放也读取同一份日志
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::{Conversation, ConversationMessage, EnvironmentSnapshot, ResponseLanguage, SamplingOverrides, TokenUsage};
+use crate::error::{ClaudeError, Result};
+
+/// 每隔多少个事件写一次快照
+pub const SNAPSHOT_INTERVAL: u64 = 50;
+
+/// 日志中的一个事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEvent {
+    /// 会话内从 1 开始递增的序号
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// 事件内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    /// 会话创建（新建、派生或导入），携带初始状态
+    Created { conversation: Box<Conversation> },
+    MessageAdded { message: ConversationMessage },
+    /// 消息被固定、记录来源等
+    MessageUpdated { message: ConversationMessage },
+    Cleared,
+    /// 回滚到 Agent 检查点，只保留前 `keep` 条消息
+    RolledBack { keep: usize, checkpoint: String },
+    /// 压缩后保留的消息
+    Compacted {
+        kept: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        instructions: Option<String>,
+    },
+    SettingChanged { setting: Setting },
+    ToolCall {
+        name: String,
+        input: serde_json::Value,
+        output: String,
+        is_error: bool,
+    },
+    FileEdited { path: String, summary: String },
+    /// 开始回答某条用户消息
+    TurnStarted { message_id: String },
+    TurnFinished { message_id: String },
+}
+
+/// 会话设置的变更
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "key", content = "value", rename_all = "snake_case")]
+pub enum Setting {
+    Branch(Option<String>),
+    Language(Option<ResponseLanguage>),
+    Sampling(SamplingOverrides),
+    Environment(Box<EnvironmentSnapshot>),
+}
+
+impl EventKind {
+    /// 审计日志中的一行描述
+    pub fn describe(&self) -> String {
+        let preview = |text: &str| -> String {
+            let line = text.lines().next().unwrap_or_default();
+            let mut preview: String = line.chars().take(60).collect();
+            if preview.len() < text.len() {
+                preview.push('…');
+            }
+            preview
+        };
+        match self {
+            EventKind::Created { conversation } => format!("created \"{}\"", conversation.title),
+            EventKind::MessageAdded { message } => format!("{}: {}", message.role, preview(&message.content)),
+            EventKind::MessageUpdated { message } => format!("updated {} message {}", message.role, &message.id[..8.min(message.id.len())]),
+            EventKind::Cleared => "cleared history".to_string(),
+            EventKind::Compacted { kept, .. } => format!("compacted to {} message(s)", kept.len()),
+            EventKind::RolledBack { keep, checkpoint } => format!("rolled back to checkpoint {} ({} message(s) kept)", checkpoint, keep),
+            EventKind::SettingChanged { setting } => match setting {
+                Setting::Branch(branch) => format!("branch = {}", branch.as_deref().unwrap_or("(none)")),
+                Setting::Language(language) => {
+                    format!("language = {}", language.as_ref().map_or("(model decides)".to_string(), |l| l.to_string()))
+                }
+                Setting::Sampling(_) => "sampling overrides changed".to_string(),
+                Setting::Environment(_) => "environment captured".to_string(),
+            },
+            EventKind::ToolCall { name, input, is_error, .. } => {
+                format!("tool {}{} {}", name, if *is_error { " (failed)" } else { "" }, preview(&input.to_string()))
+            }
+            EventKind::FileEdited { path, summary } => format!("edited {}: {}", path, summary),
+            EventKind::TurnStarted { .. } => "turn started".to_string(),
+            EventKind::TurnFinished { .. } => "turn finished".to_string(),
+        }
+    }
+}
+
+impl Conversation {
+    /// 把事件应用到会话状态
+    pub fn apply(&mut self, event: &SessionEvent) {
+        match &event.kind {
+            EventKind::Created { conversation } => *self = (**conversation).clone(),
+            EventKind::MessageAdded { message } => {
+                if let Some(usage) = &message.token_usage {
+                    self.total_token_usage.input_tokens += usage.input_tokens;
+                    self.total_token_usage.output_tokens += usage.output_tokens;
+                    self.total_token_usage.total_tokens += usage.total_tokens;
+                    self.total_token_usage.estimated_cost += usage.estimated_cost;
+                }
+                self.messages.push(message.clone());
+            }
+            EventKind::MessageUpdated { message } => {
+                if let Some(existing) = self.messages.iter_mut().find(|m| m.id == message.id) {
+                    *existing = message.clone();
+                }
+            }
+            EventKind::Cleared => {
+                self.messages.clear();
+                self.total_token_usage = TokenUsage::default();
+            }
+            EventKind::RolledBack { keep, .. } => self.messages.truncate(*keep),
+            EventKind::Compacted { kept, instructions } => {
+                self.messages.retain(|m| kept.contains(&m.id));
+                self.messages.sort_by_key(|m| m.timestamp);
+                if let Some(instructions) = instructions {
+                    self.metadata.insert(
+                        "last_compact_instructions".to_string(),
+                        serde_json::Value::String(instructions.clone()),
+                    );
+                }
+            }
+            EventKind::SettingChanged { setting } => match setting {
+                Setting::Branch(branch) => self.branch = branch.clone(),
+                Setting::Language(language) => self.language = language.clone(),
+                Setting::Sampling(sampling) => self.sampling = sampling.clone(),
+                Setting::Environment(snapshot) => self.environment = Some((**snapshot).clone()),
+            },
+            EventKind::ToolCall { .. }
+            | EventKind::FileEdited { .. }
+            | EventKind::TurnStarted { .. }
+            | EventKind::TurnFinished { .. } => {}
+        }
+        self.updated_at = event.at;
+        self.event_seq = event.seq;
+    }
+}
+
+/// 崩溃时没有完成的回合
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterruptedTurn {
+    /// 等待回复的用户消息
+    pub message_id: String,
+    pub input: String,
+    /// 回合开始后已经执行的工具调用数
+    pub tool_calls: usize,
+    pub started_at: DateTime<Utc>,
+}
+
+/// 事件中最后一个开始后没有结束的回合
+pub fn interrupted_turn(conversation: &Conversation, events: &[SessionEvent]) -> Option<InterruptedTurn> {
+    let start = events.iter().rposition(|event| {
+        matches!(event.kind, EventKind::TurnStarted { .. } | EventKind::TurnFinished { .. })
+    })?;
+    let EventKind::TurnStarted { message_id } = &events[start].kind else {
+        return None;
+    };
+    let message = conversation.messages.iter().find(|m| &m.id == message_id)?;
+    Some(InterruptedTurn {
+        message_id: message_id.clone(),
+        input: message.content.as_str().to_string(),
+        tool_calls: events[start..].iter().filter(|event| matches!(event.kind, EventKind::ToolCall { .. })).count(),
+        started_at: events[start].at,
+    })
+}
+
+/// 从快照和事件重建会话；`until` 指定时只重放到该序号
+pub fn replay(snapshot: Option<Conversation>, events: &[SessionEvent], until: Option<u64>) -> Option<Conversation> {
+    let snapshot = snapshot.filter(|snapshot| until.is_none_or(|until| snapshot.event_seq <= until));
+    let after = snapshot.as_ref().map_or(0, |snapshot| snapshot.event_seq);
+    let mut state = snapshot;
+    for event in events.iter().filter(|event| event.seq > after && until.is_none_or(|until| event.seq <= until)) {
+        match (&mut state, &event.kind) {
+            (Some(conversation), _) => conversation.apply(event),
+            (None, EventKind::Created { conversation }) => {
+                let mut conversation = (**conversation).clone();
+                conversation.apply(event);
+                state = Some(conversation);
+            }
+            // 缺少起点的日志无法重放
+            (None, _) => return None,
+        }
+    }
+    state
+}
+
+/// 一个会话的事件日志文件
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    path: PathBuf,
+}
+
+impl EventLog {
+    pub fn new(storage_dir: &Path, id: &str) -> Self {
+        Self { path: storage_dir.join(format!("{}.events.jsonl", id)) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.is_file()
+    }
+
+    /// 追加一个事件并落盘
+    pub fn append(&self, event: &SessionEvent) -> Result<()> {
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// 读取全部事件。写到一半的最后一行（崩溃所致）会被截掉，以免之后的追加接在它后面
+    pub fn read(&self) -> Result<Vec<SessionEvent>> {
+        let data = match std::fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut events = Vec::new();
+        let mut valid_len = 0;
+        for line in data.split_inclusive('\n') {
+            let parsed = serde_json::from_str::<SessionEvent>(line.trim_end());
+            match parsed {
+                Ok(event) if line.ends_with('\n') => {
+                    events.push(event);
+                    valid_len += line.len();
+                }
+                _ if valid_len + line.len() == data.len() => {
+                    tracing::warn!("Discarding incomplete last event in {}", self.path.display());
+                    let file = std::fs::OpenOptions::new().write(true).open(&self.path)?;
+                    file.set_len(valid_len as u64)?;
+                    break;
+                }
+                _ => {
+                    return Err(ClaudeError::General(format!(
+                        "Corrupt event {} in {}",
+                        events.len() + 1,
+                        self.path.display()
+                    )))
+                }
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::ConversationManager;
+
+    #[test]
+    fn test_recovers_mid_turn_from_snapshot_and_log() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manager = ConversationManager::with_storage_dir(dir.path().to_path_buf()).unwrap();
+        let id = manager.create_conversation(Some("Crash".to_string())).unwrap();
+        for i in 0..SNAPSHOT_INTERVAL {
+            manager.add_message("user", format!("message {}", i), None).unwrap();
+        }
+        let question = manager.add_message("user", "rename the module", None).unwrap();
+        manager.start_turn(&question).unwrap();
+        manager.record_tool_call("git", serde_json::json!(["mv", "a.rs", "b.rs"]), "", false).unwrap();
+
+        // 模拟写到一半时崩溃
+        let log = EventLog::new(dir.path(), &id);
+        let mut file = std::fs::OpenOptions::new().append(true).open(log.path()).unwrap();
+        file.write_all(br#"{"seq":999,"at":"#).unwrap();
+
+        let mut recovered = ConversationManager::with_storage_dir(dir.path().to_path_buf()).unwrap();
+        recovered.load_conversation(&id).unwrap();
+        let conversation = recovered.get_current_conversation().unwrap();
+        assert_eq!(conversation.messages.len(), SNAPSHOT_INTERVAL as usize + 1);
+        let turn = recovered.interrupted_turn().unwrap();
+        assert_eq!((turn.input.as_str(), turn.tool_calls), ("rename the module", 1));
+
+        // 截掉残行后可以继续追加，回放可以停在任意位置
+        let reply = recovered.add_message("assistant", "done", None).unwrap();
+        recovered.finish_turn(&question).unwrap();
+        assert!(recovered.interrupted_turn().is_none());
+        let events = log.read().unwrap();
+        assert!(events.iter().any(|e| matches!(&e.kind, EventKind::MessageAdded { message } if message.id == reply)));
+        assert_eq!(replay(None, &events, Some(3)).unwrap().messages.len(), 2);
+    }
+}
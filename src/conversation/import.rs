@@ -0,0 +1,406 @@
+//! 从官方 Claude Code 会话导入
+//!
+//! 官方 CLI 把每个会话保存为 `~/.claude/projects/<项目目录>/<会话ID>.jsonl`，
+//! 每行一条记录。这里把其中的用户和助手消息转换为本 crate 的对话格式，
+//! 沿用原会话 ID，迁移后可以直接用 `--resume <ID>` 继续
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::{Conversation, ConversationManager, ConversationMessage, TokenUsage};
+use crate::error::{ClaudeError, Result};
+
+/// 没有摘要时从首条用户消息截取标题的长度
+const TITLE_MAX_CHARS: usize = 60;
+
+/// 工具结果写入消息文本时保留的长度
+const TOOL_RESULT_MAX_CHARS: usize = 2000;
+
+/// JSONL 中的一行记录，只解析需要的字段
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    uuid: Option<String>,
+    #[serde(default)]
+    session_id: Option<String>,
+    #[serde(default)]
+    timestamp: Option<DateTime<Utc>>,
+    #[serde(default)]
+    cwd: Option<String>,
+    #[serde(default)]
+    git_branch: Option<String>,
+    #[serde(default)]
+    is_sidechain: bool,
+    #[serde(default)]
+    is_meta: bool,
+    #[serde(default)]
+    message: Option<RecordMessage>,
+    #[serde(default)]
+    summary: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordMessage {
+    role: String,
+    #[serde(default)]
+    content: Value,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    usage: Option<RecordUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecordUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+/// 单个会话的导入结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportStatus {
+    /// 已导入
+    Imported { messages: usize },
+    /// 本地已有同 ID 的会话
+    AlreadyImported,
+    /// 没有可导入的消息
+    Empty,
+    /// 读取或解析失败
+    Failed(String),
+}
+
+/// 一次导入的汇总
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    /// 每个会话文件及其结果
+    pub sessions: Vec<(PathBuf, ImportStatus)>,
+}
+
+impl ImportReport {
+    /// 成功导入的会话数
+    pub fn imported(&self) -> usize {
+        self.count(|status| matches!(status, ImportStatus::Imported { .. }))
+    }
+
+    /// 因已存在而跳过的会话数
+    pub fn skipped(&self) -> usize {
+        self.count(|status| matches!(status, ImportStatus::AlreadyImported | ImportStatus::Empty))
+    }
+
+    /// 导入失败的会话数
+    pub fn failed(&self) -> usize {
+        self.count(|status| matches!(status, ImportStatus::Failed(_)))
+    }
+
+    fn count(&self, predicate: impl Fn(&ImportStatus) -> bool) -> usize {
+        self.sessions.iter().filter(|(_, status)| predicate(status)).count()
+    }
+}
+
+/// 官方 Claude Code 会话导入器
+pub struct ClaudeCodeImporter {
+    /// 会话根目录，默认为 `~/.claude/projects`
+    projects_dir: PathBuf,
+    /// 是否覆盖已导入的会话
+    overwrite: bool,
+}
+
+impl ClaudeCodeImporter {
+    /// 从指定目录（或单个 .jsonl 文件）导入
+    pub fn new(projects_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            projects_dir: projects_dir.into(),
+            overwrite: false,
+        }
+    }
+
+    /// 官方 CLI 的默认会话目录
+    pub fn default_projects_dir() -> Result<PathBuf> {
+        let home = dirs::home_dir().ok_or_else(|| ClaudeError::config_error("Cannot find home directory"))?;
+        Ok(home.join(".claude").join("projects"))
+    }
+
+    /// 覆盖本地已存在的同 ID 会话
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// 查找所有会话文件，按修改时间从旧到新排列
+    pub fn discover(&self) -> Vec<PathBuf> {
+        if self.projects_dir.is_file() {
+            return vec![self.projects_dir.clone()];
+        }
+        let mut files: Vec<(PathBuf, std::time::SystemTime)> = walkdir::WalkDir::new(&self.projects_dir)
+            .max_depth(2)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| entry.path().extension().and_then(|e| e.to_str()) == Some("jsonl"))
+            .map(|entry| {
+                let modified = entry
+                    .metadata()
+                    .ok()
+                    .and_then(|m| m.modified().ok())
+                    .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+                (entry.into_path(), modified)
+            })
+            .collect();
+        files.sort_by_key(|(_, modified)| *modified);
+        files.into_iter().map(|(path, _)| path).collect()
+    }
+
+    /// 导入所有会话到对话存储
+    pub fn import_all(&self, manager: &mut ConversationManager) -> ImportReport {
+        let mut report = ImportReport::default();
+        for path in self.discover() {
+            let status = self.import_file(&path, manager);
+            report.sessions.push((path, status));
+        }
+        report
+    }
+
+    fn import_file(&self, path: &Path, manager: &mut ConversationManager) -> ImportStatus {
+        let conversation = match parse_session(path) {
+            Ok(Some(conversation)) => conversation,
+            Ok(None) => return ImportStatus::Empty,
+            Err(e) => return ImportStatus::Failed(e.to_string()),
+        };
+        if !self.overwrite && manager.has_conversation(&conversation.id) {
+            return ImportStatus::AlreadyImported;
+        }
+        let messages = conversation.messages.len();
+        match manager.import_conversation(conversation) {
+            Ok(()) => ImportStatus::Imported { messages },
+            Err(e) => ImportStatus::Failed(e.to_string()),
+        }
+    }
+}
+
+/// 解析单个会话文件，没有用户或助手消息时返回 `None`
+pub fn parse_session(path: &Path) -> Result<Option<Conversation>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| ClaudeError::fs_error(format!("Failed to read {}: {}", path.display(), e)))?;
+
+    let mut session_id = None;
+    let mut summary = None;
+    let mut cwd = None;
+    let mut git_branch = None;
+    let mut model = None;
+    let mut messages = Vec::new();
+    let mut total = TokenUsage::default();
+
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(e) => {
+                tracing::debug!("Skipping line {} of {}: {}", index + 1, path.display(), e);
+                continue;
+            }
+        };
+
+        if record.kind == "summary" {
+            summary = summary.or(record.summary);
+            continue;
+        }
+        // 子代理的旁路对话和系统注入的元消息不属于主对话
+        if !matches!(record.kind.as_str(), "user" | "assistant") || record.is_sidechain || record.is_meta {
+            continue;
+        }
+        let Some(message) = record.message else { continue };
+
+        session_id = session_id.or(record.session_id);
+        cwd = record.cwd.or(cwd);
+        git_branch = record.git_branch.or(git_branch);
+        if message.model.is_some() {
+            model = message.model.clone();
+        }
+
+        let text = render_content(&message.content);
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let token_usage = message.usage.map(|usage| TokenUsage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.input_tokens + usage.output_tokens,
+            estimated_cost: 0.0,
+        });
+        if let Some(usage) = &token_usage {
+            total.input_tokens += usage.input_tokens;
+            total.output_tokens += usage.output_tokens;
+            total.total_tokens += usage.total_tokens;
+        }
+
+        let mut metadata = HashMap::new();
+        if message.content.is_array() {
+            metadata.insert("claude_code_content".to_string(), message.content);
+        }
+        messages.push(ConversationMessage {
+            id: record.uuid.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            role: message.role,
+            content: text.into(),
+            timestamp: record.timestamp.unwrap_or_else(Utc::now),
+            metadata,
+            token_usage,
+            pinned: false,
+            provenance: None,
+        });
+    }
+
+    if messages.is_empty() {
+        return Ok(None);
+    }
+
+    let id = session_id
+        .or_else(|| path.file_stem().and_then(|s| s.to_str()).map(str::to_string))
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let title = summary.unwrap_or_else(|| {
+        let first = messages.iter().find(|m| m.role == "user").unwrap_or(&messages[0]);
+        title_from(&first.content)
+    });
+
+    let mut metadata = HashMap::new();
+    metadata.insert("imported_from".to_string(), Value::String(path.display().to_string()));
+    if let Some(cwd) = cwd {
+        metadata.insert("cwd".to_string(), Value::String(cwd));
+    }
+    if let Some(branch) = &git_branch {
+        metadata.insert("git_branch".to_string(), Value::String(branch.clone()));
+    }
+    if let Some(model) = model {
+        metadata.insert("model".to_string(), Value::String(model));
+    }
+
+    Ok(Some(Conversation {
+        id,
+        title,
+        created_at: messages[0].timestamp,
+        updated_at: messages[messages.len() - 1].timestamp,
+        messages,
+        metadata,
+        tags: vec!["claude-code-import".to_string()],
+        archived: false,
+        total_token_usage: total,
+        environment: None,
+        branch: git_branch,
+        language: None,
+        sampling: Default::default(),
+        event_seq: 0,
+    }))
+}
+
+/// 把消息内容（字符串或内容块数组）渲染为纯文本
+fn render_content(content: &Value) -> String {
+    let blocks = match content {
+        Value::String(text) => return text.clone(),
+        Value::Array(blocks) => blocks,
+        _ => return String::new(),
+    };
+
+    let mut parts = Vec::new();
+    for block in blocks {
+        match block.get("type").and_then(Value::as_str) {
+            Some("text") => {
+                if let Some(text) = block.get("text").and_then(Value::as_str) {
+                    parts.push(text.to_string());
+                }
+            }
+            Some("tool_use") => {
+                let name = block.get("name").and_then(Value::as_str).unwrap_or("tool");
+                let input = block.get("input").map(Value::to_string).unwrap_or_default();
+                parts.push(format!("[Tool call: {} {}]", name, input));
+            }
+            Some("tool_result") => {
+                let result = render_content(block.get("content").unwrap_or(&Value::Null));
+                parts.push(format!("[Tool result]\n{}", truncate(&result, TOOL_RESULT_MAX_CHARS)));
+            }
+            // 思考过程和图片不计入可恢复的历史
+            _ => {}
+        }
+    }
+    parts.join("\n")
+}
+
+fn title_from(content: &str) -> String {
+    let line = content.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+    truncate(line, TITLE_MAX_CHARS)
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: &str = r#"{"type":"summary","summary":"Fix the parser","leafUuid":"a2"}
+{"type":"user","uuid":"u1","sessionId":"0f5c1d2e-1111-2222-3333-444455556666","timestamp":"2026-03-01T10:00:00Z","cwd":"/work/app","gitBranch":"main","isSidechain":false,"message":{"role":"user","content":"The parser panics on empty input"}}
+{"type":"user","uuid":"m1","sessionId":"0f5c1d2e-1111-2222-3333-444455556666","timestamp":"2026-03-01T10:00:01Z","isMeta":true,"message":{"role":"user","content":"<command-name>/clear</command-name>"}}
+{"type":"assistant","uuid":"a1","sessionId":"0f5c1d2e-1111-2222-3333-444455556666","timestamp":"2026-03-01T10:00:05Z","message":{"role":"assistant","model":"claude-sonnet-4","content":[{"type":"thinking","thinking":"..."},{"type":"text","text":"Let me look."},{"type":"tool_use","id":"t1","name":"Read","input":{"file_path":"src/parser.rs"}}],"usage":{"input_tokens":120,"output_tokens":30}}}
+{"type":"user","uuid":"r1","sessionId":"0f5c1d2e-1111-2222-3333-444455556666","timestamp":"2026-03-01T10:00:06Z","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"fn parse() {}"}]}}
+{"type":"assistant","uuid":"s1","sessionId":"0f5c1d2e-1111-2222-3333-444455556666","isSidechain":true,"message":{"role":"assistant","content":"sub-agent chatter"}}
+not json
+"#;
+
+    #[test]
+    fn test_parse_official_session() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("session.jsonl");
+        std::fs::write(&path, SESSION).unwrap();
+
+        let conversation = parse_session(&path).unwrap().unwrap();
+        assert_eq!(conversation.id, "0f5c1d2e-1111-2222-3333-444455556666");
+        assert_eq!(conversation.title, "Fix the parser");
+        assert_eq!(conversation.metadata["cwd"], "/work/app");
+        assert_eq!(conversation.branch.as_deref(), Some("main"));
+        assert_eq!(conversation.metadata["model"], "claude-sonnet-4");
+        assert_eq!(conversation.total_token_usage.total_tokens, 150);
+
+        let roles: Vec<_> = conversation.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant", "user"]);
+        assert_eq!(
+            conversation.messages[1].content,
+            "Let me look.\n[Tool call: Read {\"file_path\":\"src/parser.rs\"}]"
+        );
+        assert!(conversation.messages[2].content.contains("fn parse() {}"));
+    }
+
+    #[test]
+    fn test_import_is_idempotent_and_resumable() {
+        let source = tempfile::TempDir::new().unwrap();
+        let project = source.path().join("-work-app");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("0f5c1d2e-1111-2222-3333-444455556666.jsonl"), SESSION).unwrap();
+        std::fs::write(project.join("empty.jsonl"), "{\"type\":\"summary\",\"summary\":\"x\"}\n").unwrap();
+
+        let store = tempfile::TempDir::new().unwrap();
+        let mut manager = ConversationManager::with_storage_dir(store.path().to_path_buf()).unwrap();
+        let importer = ClaudeCodeImporter::new(source.path());
+
+        let report = importer.import_all(&mut manager);
+        assert_eq!((report.imported(), report.skipped(), report.failed()), (1, 1, 0));
+
+        let again = importer.import_all(&mut manager);
+        assert_eq!(again.imported(), 0);
+
+        manager.load_conversation("0f5c1d2e-1111-2222-3333-444455556666").unwrap();
+        assert_eq!(manager.get_message_count(), 3);
+    }
+}
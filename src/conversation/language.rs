@@ -0,0 +1,236 @@
+//! 回复语言
+//!
+//! 解析和规范化语言标签（如 zh-CN、en），生成注入系统提示的语言要求，
+//! 并为默认会话标题和导出内容提供对应语言的固定文字
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::{ClaudeError, Result};
+
+/// 常用语言：(标签, 英文名称, 本地名称)
+const KNOWN_LANGUAGES: &[(&str, &str, &str)] = &[
+    ("en", "English", "English"),
+    ("zh-CN", "Simplified Chinese", "简体中文"),
+    ("zh-TW", "Traditional Chinese", "繁體中文"),
+    ("ja", "Japanese", "日本語"),
+    ("ko", "Korean", "한국어"),
+    ("fr", "French", "Français"),
+    ("de", "German", "Deutsch"),
+    ("es", "Spanish", "Español"),
+    ("pt-BR", "Brazilian Portuguese", "Português (Brasil)"),
+    ("ru", "Russian", "Русский"),
+];
+
+/// 常见的非标准写法
+const ALIASES: &[(&str, &str)] = &[
+    ("zh", "zh-CN"),
+    ("zh-hans", "zh-CN"),
+    ("zh-hant", "zh-TW"),
+    ("chinese", "zh-CN"),
+    ("中文", "zh-CN"),
+    ("english", "en"),
+    ("japanese", "ja"),
+    ("日本語", "ja"),
+    ("korean", "ko"),
+    ("pt", "pt-BR"),
+];
+
+/// 会话和导出中的固定文字
+struct Labels {
+    conversation: &'static str,
+    user: &'static str,
+    assistant: &'static str,
+    system: &'static str,
+}
+
+const ENGLISH_LABELS: Labels = Labels {
+    conversation: "Conversation",
+    user: "User",
+    assistant: "Assistant",
+    system: "System",
+};
+
+/// 回复语言
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ResponseLanguage(String);
+
+impl ResponseLanguage {
+    /// 解析语言标签或常见别名
+    pub fn parse(input: &str) -> Result<Self> {
+        let trimmed = input.trim();
+        if let Some((_, tag)) = ALIASES.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(trimmed)) {
+            return Ok(Self(tag.to_string()));
+        }
+
+        let parts: Vec<&str> = trimmed.split(['-', '_']).collect();
+        let valid = !parts.is_empty()
+            && parts.iter().all(|p| !p.is_empty() && p.len() <= 8 && p.chars().all(|c| c.is_ascii_alphanumeric()))
+            && (2..=3).contains(&parts[0].len())
+            && parts[0].chars().all(|c| c.is_ascii_alphabetic());
+        if !valid {
+            return Err(ClaudeError::validation_error(
+                "language",
+                format!("'{}' is not a language tag such as en, zh-CN or pt-BR", input),
+            ));
+        }
+
+        // 主语言小写、文字代码首字母大写、地区代码大写
+        let normalized: Vec<String> = parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let alphabetic = part.chars().all(|c| c.is_ascii_alphabetic());
+                match part.len() {
+                    _ if i == 0 => part.to_lowercase(),
+                    4 if alphabetic => part[..1].to_uppercase() + &part[1..].to_lowercase(),
+                    2 | 3 => part.to_uppercase(),
+                    _ => part.to_lowercase(),
+                }
+            })
+            .collect();
+        let tag = normalized.join("-");
+        if let Some((_, canonical)) = ALIASES.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(&tag)) {
+            return Ok(Self(canonical.to_string()));
+        }
+        Ok(Self(tag))
+    }
+
+    /// 规范化后的语言标签
+    pub fn tag(&self) -> &str {
+        &self.0
+    }
+
+    /// 英文名称，未知语言返回标签本身
+    pub fn english_name(&self) -> &str {
+        self.known().map(|(_, name, _)| *name).unwrap_or(&self.0)
+    }
+
+    /// 本地名称，未知语言返回标签本身
+    pub fn native_name(&self) -> &str {
+        self.known().map(|(_, _, native)| *native).unwrap_or(&self.0)
+    }
+
+    /// 注入系统提示的语言要求
+    pub fn system_instruction(&self) -> String {
+        format!(
+            "Always respond in {} ({}), including explanations, summaries and conversation titles. \
+             Keep code, identifiers, shell commands, file paths and quoted output unchanged.",
+            self.english_name(),
+            self.0
+        )
+    }
+
+    /// 在已有系统提示后追加语言要求
+    pub fn apply_to_system(&self, system: Option<String>) -> Option<String> {
+        Some(match system {
+            Some(system) if !system.is_empty() => format!("{}\n\n{}", system, self.system_instruction()),
+            _ => self.system_instruction(),
+        })
+    }
+
+    /// 默认会话标题
+    pub fn default_title(&self, at: DateTime<Utc>) -> String {
+        format!("{} {}", self.labels().conversation, at.format("%Y-%m-%d %H:%M"))
+    }
+
+    /// 消息角色的显示名称
+    pub fn role_label<'a>(&self, role: &'a str) -> &'a str {
+        labels_role(self.labels(), role)
+    }
+
+    fn known(&self) -> Option<&'static (&'static str, &'static str, &'static str)> {
+        KNOWN_LANGUAGES.iter().find(|(tag, _, _)| *tag == self.0)
+    }
+
+    fn labels(&self) -> &'static Labels {
+        const ZH_CN: Labels = Labels { conversation: "对话", user: "用户", assistant: "助手", system: "系统" };
+        const ZH_TW: Labels = Labels { conversation: "對話", user: "使用者", assistant: "助理", system: "系統" };
+        const JA: Labels = Labels { conversation: "会話", user: "ユーザー", assistant: "アシスタント", system: "システム" };
+        const KO: Labels = Labels { conversation: "대화", user: "사용자", assistant: "어시스턴트", system: "시스템" };
+        const FR: Labels = Labels { conversation: "Conversation", user: "Utilisateur", assistant: "Assistant", system: "Système" };
+        const DE: Labels = Labels { conversation: "Unterhaltung", user: "Benutzer", assistant: "Assistent", system: "System" };
+        const ES: Labels = Labels { conversation: "Conversación", user: "Usuario", assistant: "Asistente", system: "Sistema" };
+
+        let primary = self.0.split('-').next().unwrap_or_default();
+        match (primary, self.0.as_str()) {
+            (_, "zh-TW") | (_, "zh-HK") => &ZH_TW,
+            ("zh", _) => &ZH_CN,
+            ("ja", _) => &JA,
+            ("ko", _) => &KO,
+            ("fr", _) => &FR,
+            ("de", _) => &DE,
+            ("es", _) => &ES,
+            _ => &ENGLISH_LABELS,
+        }
+    }
+}
+
+impl fmt::Display for ResponseLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// 未设置语言时的默认会话标题
+pub fn default_title(language: Option<&ResponseLanguage>, at: DateTime<Utc>) -> String {
+    match language {
+        Some(language) => language.default_title(at),
+        None => format!("{} {}", ENGLISH_LABELS.conversation, at.format("%Y-%m-%d %H:%M")),
+    }
+}
+
+fn labels_role<'a>(labels: &'static Labels, role: &'a str) -> &'a str {
+    match role {
+        "user" => labels.user,
+        "assistant" => labels.assistant,
+        "system" => labels.system,
+        other => other,
+    }
+}
+
+/// 未设置语言时使用英文的角色显示名称
+pub fn role_label<'a>(language: Option<&ResponseLanguage>, role: &'a str) -> &'a str {
+    match language {
+        Some(language) => language.role_label(role),
+        None => labels_role(&ENGLISH_LABELS, role),
+    }
+}
+
+/// 支持的常用语言列表：(标签, 本地名称)
+pub fn known_languages() -> impl Iterator<Item = (&'static str, &'static str)> {
+    KNOWN_LANGUAGES.iter().map(|(tag, _, native)| (*tag, *native))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_normalizes_tags() {
+        assert_eq!(ResponseLanguage::parse("zh-cn").unwrap().tag(), "zh-CN");
+        assert_eq!(ResponseLanguage::parse("zh_Hant").unwrap().tag(), "zh-TW");
+        assert_eq!(ResponseLanguage::parse("Chinese").unwrap().tag(), "zh-CN");
+        assert_eq!(ResponseLanguage::parse("EN").unwrap().tag(), "en");
+        assert_eq!(ResponseLanguage::parse("sr-latn-rs").unwrap().tag(), "sr-Latn-RS");
+        assert!(ResponseLanguage::parse("not a language").is_err());
+        assert!(ResponseLanguage::parse("").is_err());
+    }
+
+    #[test]
+    fn test_localized_prompt_and_labels() {
+        let zh = ResponseLanguage::parse("zh-CN").unwrap();
+        assert!(zh.system_instruction().contains("Simplified Chinese (zh-CN)"));
+        assert_eq!(
+            zh.apply_to_system(Some("Be brief.".to_string())).unwrap(),
+            format!("Be brief.\n\n{}", zh.system_instruction())
+        );
+        assert_eq!(zh.role_label("assistant"), "助手");
+
+        let at = DateTime::parse_from_rfc3339("2026-05-01T08:30:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(default_title(Some(&zh), at), "对话 2026-05-01 08:30");
+        assert_eq!(default_title(None, at), "Conversation 2026-05-01 08:30");
+    }
+}
@@ -0,0 +1,314 @@
+//! 按仓库记录的失败教训
+//!
+//! 回合以失败收场时（多轮修复后测试仍不通过、命令不存在、路径写错）记下一条结构化的教训，
+//! 按仓库分别保存在数据目录下的 `claude-code/lessons/<仓库>.json`。之后在同一仓库开始对话时，
+//! 挑出与当前输入最相关的几条放进系统提示，避免重复犯同样的错误
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+
+/// 每个仓库最多保留的教训数，超出时淘汰最久未出现的
+const MAX_LESSONS: usize = 200;
+
+/// 教训配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LessonConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 每次注入系统提示的最大条数
+    #[serde(default = "default_max_injected")]
+    pub max_injected: usize,
+}
+
+impl Default for LessonConfig {
+    fn default() -> Self {
+        Self { enabled: default_enabled(), max_injected: default_max_injected() }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_injected() -> usize {
+    5
+}
+
+/// 失败的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LessonKind {
+    /// 多次修复后测试或构建仍失败
+    RepeatedTestFailure,
+    /// 调用了环境中不存在的命令
+    CommandNotFound,
+    /// 使用了不存在的路径
+    WrongPath,
+}
+
+impl std::fmt::Display for LessonKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RepeatedTestFailure => write!(f, "repeated test failure"),
+            Self::CommandNotFound => write!(f, "command not found"),
+            Self::WrongPath => write!(f, "wrong path"),
+        }
+    }
+}
+
+/// 一条教训
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lesson {
+    pub id: String,
+    pub kind: LessonKind,
+    /// 放进系统提示的一句话
+    pub summary: String,
+    /// 失败时的命令、输出摘要等细节
+    #[serde(default)]
+    pub detail: String,
+    /// 出现次数，同一教训再次出现时累加
+    pub occurrences: u32,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl Lesson {
+    pub fn new(kind: LessonKind, summary: impl Into<String>, detail: impl Into<String>) -> Self {
+        let summary = summary.into();
+        let now = Utc::now();
+        Self {
+            id: format!("{:x}", md5::compute(format!("{:?}:{}", kind, summary)))[..8].to_string(),
+            kind,
+            summary,
+            detail: detail.into(),
+            occurrences: 1,
+            first_seen: now,
+            last_seen: now,
+        }
+    }
+
+    /// 多轮修复后验证命令仍然失败
+    pub fn repeated_failure(task: &str, command: &str, attempts: usize, output: &str) -> Self {
+        let error = output
+            .lines()
+            .map(str::trim)
+            .find(|line| line.starts_with("error") || line.contains("FAILED") || line.contains("panicked"))
+            .or_else(|| output.lines().map(str::trim).rfind(|line| !line.is_empty()))
+            .unwrap_or_default();
+        Self::new(
+            LessonKind::RepeatedTestFailure,
+            format!("`{}` kept failing after {} attempt(s) while working on {}: {}", command, attempts, task, truncate(error, 160)),
+            truncate(output, 2000),
+        )
+    }
+
+    /// 从失败命令的输出中识别可以总结为教训的错误
+    pub fn from_command_failure(command: &str, exit_code: Option<i32>, stderr: &str) -> Option<Self> {
+        let program = command.split_whitespace().find(|word| !word.contains('=')).unwrap_or(command);
+        let not_found = stderr.lines().find(|line| line.contains("command not found") || line.contains("not recognized as"));
+        if exit_code == Some(127) || not_found.is_some() {
+            let missing = not_found
+                .and_then(|line| line.rsplit_once(": command not found").map(|(head, _)| head.rsplit(": ").next().unwrap_or(head)))
+                .unwrap_or(program)
+                .trim();
+            return Some(Self::new(
+                LessonKind::CommandNotFound,
+                format!("`{}` is not available in this environment; use an alternative or check how the project runs it", missing),
+                format!("$ {}\n{}", command, truncate(stderr, 500)),
+            ));
+        }
+
+        let line = stderr.lines().find(|line| line.contains("No such file or directory") || line.contains("cannot find the path"))?;
+        let path = line
+            .split([':', '\''])
+            .map(str::trim)
+            .find(|part| part.contains('/') || part.contains('.'))
+            .filter(|part| !part.contains(' '))
+            .unwrap_or(program);
+        Some(Self::new(
+            LessonKind::WrongPath,
+            format!("`{}` does not exist in this repository; check the layout before using paths", path),
+            format!("$ {}\n{}", command, truncate(stderr, 500)),
+        ))
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(max_chars) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text.to_string(),
+    }
+}
+
+fn terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| word.len() >= 4)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// 包含 `start` 的 git 仓库根目录，不在仓库中时为 `start` 本身
+pub fn repo_root(start: &Path) -> PathBuf {
+    start.ancestors().find(|dir| dir.join(".git").exists()).unwrap_or(start).to_path_buf()
+}
+
+/// 一个仓库的教训
+#[derive(Debug)]
+pub struct LessonStore {
+    path: PathBuf,
+    lessons: Vec<Lesson>,
+}
+
+impl LessonStore {
+    /// 打开 `dir` 所在仓库的教训
+    pub fn for_repo(dir: &Path) -> Result<Self> {
+        let root = repo_root(dir);
+        let root = root.canonicalize().unwrap_or(root);
+        let name = root.file_name().and_then(|n| n.to_str()).unwrap_or("repo");
+        let key = format!("{}-{}", name, &format!("{:x}", md5::compute(root.display().to_string()))[..8]);
+        let dir = dirs::data_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("lessons");
+        Self::load(dir.join(format!("{}.json", key)))
+    }
+
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let lessons = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, lessons })
+    }
+
+    pub fn lessons(&self) -> &[Lesson] {
+        &self.lessons
+    }
+
+    /// 记录教训；同一教训已存在时累加次数并更新细节
+    pub fn record(&mut self, lesson: Lesson) -> Result<&Lesson> {
+        let index = match self.lessons.iter().position(|l| l.id == lesson.id) {
+            Some(index) => {
+                let existing = &mut self.lessons[index];
+                existing.occurrences += 1;
+                existing.last_seen = lesson.last_seen;
+                existing.detail = lesson.detail;
+                index
+            }
+            None => {
+                if self.lessons.len() >= MAX_LESSONS {
+                    if let Some(oldest) = self.lessons.iter().enumerate().min_by_key(|(_, l)| l.last_seen).map(|(i, _)| i) {
+                        self.lessons.remove(oldest);
+                    }
+                }
+                self.lessons.push(lesson);
+                self.lessons.len() - 1
+            }
+        };
+        self.save()?;
+        Ok(&self.lessons[index])
+    }
+
+    /// 删除指定 ID 的教训，返回是否存在
+    pub fn forget(&mut self, id: &str) -> Result<bool> {
+        let before = self.lessons.len();
+        self.lessons.retain(|l| l.id != id);
+        let removed = self.lessons.len() != before;
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.lessons.clear();
+        self.save()
+    }
+
+    /// 与 `query` 最相关的至多 `limit` 条：与输入共有的词越多、出现越频繁、越近越靠前。
+    /// 命令和路径类教训与输入无关时也可能入选，它们描述的是整个仓库的约定
+    pub fn relevant(&self, query: &str, limit: usize) -> Vec<&Lesson> {
+        let query = terms(query);
+        let now = Utc::now();
+        let mut scored: Vec<(f64, &Lesson)> = self
+            .lessons
+            .iter()
+            .filter_map(|lesson| {
+                let overlap = terms(&lesson.summary).intersection(&query).count() as f64;
+                if overlap == 0.0 && lesson.kind == LessonKind::RepeatedTestFailure {
+                    return None;
+                }
+                let age_days = (now - lesson.last_seen).num_days().max(0) as f64;
+                let score = overlap * 3.0 + (lesson.occurrences as f64).ln_1p() + 1.0 / (1.0 + age_days / 7.0);
+                Some((score, lesson))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(limit).map(|(_, lesson)| lesson).collect()
+    }
+
+    /// 注入系统提示的段落，没有相关教训时为 `None`
+    pub fn system_prompt_section(&self, query: &str, limit: usize) -> Option<String> {
+        let lessons = self.relevant(query, limit);
+        if lessons.is_empty() {
+            return None;
+        }
+        let mut section = String::from("Lessons from earlier failures in this repository (avoid repeating them):");
+        for lesson in lessons {
+            section.push_str(&format!("\n- {}", lesson.summary));
+        }
+        Some(section)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.lessons)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_select_lessons() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("lessons.json");
+        let mut store = LessonStore::load(&path).unwrap();
+
+        let missing = Lesson::from_command_failure("pnpm test", Some(127), "bash: line 1: pnpm: command not found\n").unwrap();
+        assert_eq!(missing.kind, LessonKind::CommandNotFound);
+        assert!(missing.summary.starts_with("`pnpm` is not available"));
+        store.record(missing.clone()).unwrap();
+        store.record(missing).unwrap();
+
+        let wrong = Lesson::from_command_failure("cat src/utils.rs", Some(1), "cat: src/utils.rs: No such file or directory").unwrap();
+        assert_eq!(wrong.kind, LessonKind::WrongPath);
+        assert!(wrong.summary.starts_with("`src/utils.rs` does not exist"));
+        store.record(wrong).unwrap();
+        assert!(Lesson::from_command_failure("false", Some(1), "").is_none());
+
+        store.record(Lesson::repeated_failure("parser spec", "cargo test", 4, "running 3 tests\nerror[E0308]: mismatched types\n")).unwrap();
+
+        let reloaded = LessonStore::load(&path).unwrap();
+        assert_eq!(reloaded.lessons().len(), 3);
+        assert_eq!(reloaded.lessons()[0].occurrences, 2);
+
+        // 无关的测试失败不入选，命令和路径约定总是候选
+        let unrelated = reloaded.relevant("add a README", 5);
+        assert_eq!(unrelated.len(), 2);
+        assert_eq!(unrelated[0].kind, LessonKind::CommandNotFound);
+        let related = reloaded.relevant("fix the parser tests", 1);
+        assert_eq!(related[0].kind, LessonKind::RepeatedTestFailure);
+
+        let section = reloaded.system_prompt_section("fix the parser", 5).unwrap();
+        assert!(section.contains("mismatched types"));
+    }
+}
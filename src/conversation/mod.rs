@@ -2,6 +2,18 @@
 //! 
 //! 实现对话历史的存储、检索、压缩和导出功能
 
+pub mod environment;
+pub mod events;
+#[cfg(feature = "native")]
+pub mod import;
+pub mod language;
+pub mod lessons;
+pub mod provenance;
+pub mod prune;
+pub mod sampling;
+pub mod share;
+pub mod shared_text;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -9,6 +21,14 @@ use std::path::PathBuf;
 use uuid::Uuid;
 
 use crate::error::{ClaudeError, Result};
+use events::{EventLog, Setting, SNAPSHOT_INTERVAL};
+
+pub use environment::EnvironmentSnapshot;
+pub use events::{EventKind, InterruptedTurn, SessionEvent};
+pub use language::ResponseLanguage;
+pub use provenance::{ContextSource, Provenance};
+pub use sampling::SamplingOverrides;
+pub use shared_text::SharedText;
 
 /// 对话消息
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,14 +37,20 @@ pub struct ConversationMessage {
     pub id: String,
     /// 消息角色 (user, assistant, system)
     pub role: String,
-    /// 消息内容
-    pub content: String,
+    /// 消息内容，与上下文和界面共享同一份文本
+    pub content: SharedText,
     /// 创建时间
     pub timestamp: DateTime<Utc>,
     /// 消息元数据
     pub metadata: HashMap<String, serde_json::Value>,
     /// Token使用情况
     pub token_usage: Option<TokenUsage>,
+    /// 是否已固定：压缩上下文时原样保留
+    #[serde(default)]
+    pub pinned: bool,
+    /// 用户消息引入的来源，或助手回复生成时上下文中的全部来源
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
 }
 
 /// Token使用统计
@@ -61,6 +87,44 @@ pub struct Conversation {
     pub archived: bool,
     /// 总Token使用
     pub total_token_usage: TokenUsage,
+    /// 会话开始时的环境快照
+    #[serde(default)]
+    pub environment: Option<EnvironmentSnapshot>,
+    /// 会话开始时所在的 Git 分支
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// 会话的回复语言，未设置时使用配置中的默认值
+    #[serde(default)]
+    pub language: Option<ResponseLanguage>,
+    /// 会话的采样参数覆盖
+    #[serde(default, skip_serializing_if = "SamplingOverrides::is_empty")]
+    pub sampling: SamplingOverrides,
+    /// 已应用的最后一个事件序号
+    #[serde(default)]
+    pub event_seq: u64,
+}
+
+impl Conversation {
+    /// 导出为 Markdown，标题和角色名称使用会话语言（未设置时使用 `fallback`）
+    pub fn to_markdown(&self, fallback: Option<&ResponseLanguage>) -> String {
+        let language = self.language.as_ref().or(fallback);
+        let mut output = format!("# {}
+
+", self.title);
+        for message in &self.messages {
+            output.push_str(&format!(
+                "## {} · {}
+
+{}
+
+",
+                language::role_label(language, &message.role),
+                message.timestamp.format("%Y-%m-%d %H:%M"),
+                message.content.as_str().trim_end()
+            ));
+        }
+        output
+    }
 }
 
 /// 对话历史管理器
@@ -73,19 +137,33 @@ pub struct ConversationManager {
     conversation_cache: HashMap<String, Conversation>,
     /// 最大缓存大小
     max_cache_size: usize,
+    /// 新会话默认的回复语言
+    default_language: Option<ResponseLanguage>,
+    /// 新会话沿用的采样参数覆盖
+    default_sampling: SamplingOverrides,
+    /// 当前对话加载时发现的未完成回合
+    interrupted: Option<InterruptedTurn>,
 }
 
 impl ConversationManager {
     /// 创建新的对话管理器（简化版本）
     pub fn new() -> Self {
         Self {
-            storage_dir: std::env::temp_dir().join("claude-conversations"),
+            storage_dir: Self::default_storage_dir(),
             current_conversation: None,
             conversation_cache: HashMap::new(),
             max_cache_size: 100,
+            default_language: None,
+            default_sampling: SamplingOverrides::default(),
+            interrupted: None,
         }
     }
 
+    /// 默认的会话存储目录
+    pub fn default_storage_dir() -> PathBuf {
+        std::env::temp_dir().join("claude-conversations")
+    }
+
     /// 创建新的对话管理器
     pub fn with_storage_dir(storage_dir: PathBuf) -> Result<Self> {
         // 确保存储目录存在
@@ -97,6 +175,9 @@ impl ConversationManager {
             current_conversation: None,
             conversation_cache: HashMap::new(),
             max_cache_size: 100,
+            default_language: None,
+            default_sampling: SamplingOverrides::default(),
+            interrupted: None,
         })
     }
 
@@ -107,7 +188,7 @@ impl ConversationManager {
         
         let conversation = Conversation {
             id: id.clone(),
-            title: title.unwrap_or_else(|| format!("Conversation {}", now.format("%Y-%m-%d %H:%M"))),
+            title: title.unwrap_or_else(|| language::default_title(self.default_language.as_ref(), now)),
             created_at: now,
             updated_at: now,
             messages: Vec::new(),
@@ -120,11 +201,16 @@ impl ConversationManager {
                 total_tokens: 0,
                 estimated_cost: 0.0,
             },
+            environment: None,
+            branch: None,
+            language: self.default_language.clone(),
+            sampling: self.default_sampling.clone(),
+            event_seq: 0,
         };
 
-        self.save_conversation(&conversation)?;
         self.current_conversation = Some(conversation.clone());
-        self.add_to_cache(conversation);
+        self.interrupted = None;
+        self.record(EventKind::Created { conversation: Box::new(conversation) })?;
 
         Ok(id)
     }
@@ -134,11 +220,13 @@ impl ConversationManager {
         // 先检查缓存
         if let Some(conversation) = self.conversation_cache.get(id) {
             self.current_conversation = Some(conversation.clone());
+            self.interrupted = None;
             return Ok(());
         }
 
-        // 从文件加载
-        let conversation = self.load_conversation_from_file(id)?;
+        // 从快照和事件日志恢复
+        let (conversation, events) = self.read_conversation(id)?;
+        self.interrupted = events::interrupted_turn(&conversation, &events);
         self.current_conversation = Some(conversation.clone());
         self.add_to_cache(conversation);
 
@@ -146,36 +234,37 @@ impl ConversationManager {
     }
 
     /// 添加消息到当前对话
-    pub fn add_message(&mut self, role: &str, content: &str, token_usage: Option<TokenUsage>) -> Result<String> {
+    pub fn add_message(&mut self, role: &str, content: impl Into<SharedText>, token_usage: Option<TokenUsage>) -> Result<String> {
         let message_id = Uuid::new_v4().to_string();
         let message = ConversationMessage {
             id: message_id.clone(),
             role: role.to_string(),
-            content: content.to_string(),
+            content: content.into(),
             timestamp: Utc::now(),
             metadata: HashMap::new(),
-            token_usage: token_usage.clone(),
+            token_usage,
+            pinned: false,
+            provenance: None,
         };
+        self.record(EventKind::MessageAdded { message })?;
+        Ok(message_id)
+    }
 
-        if let Some(conversation) = self.current_conversation.as_mut() {
-            conversation.messages.push(message);
-            conversation.updated_at = Utc::now();
-
-            // 更新总Token使用
-            if let Some(usage) = token_usage {
-                conversation.total_token_usage.input_tokens += usage.input_tokens;
-                conversation.total_token_usage.output_tokens += usage.output_tokens;
-                conversation.total_token_usage.total_tokens += usage.total_tokens;
-                conversation.total_token_usage.estimated_cost += usage.estimated_cost;
-            }
-
-            // 克隆对话以避免借用冲突
-            let conversation_clone = conversation.clone();
-            self.save_conversation(&conversation_clone)?;
-            Ok(message_id)
-        } else {
-            Err(ClaudeError::General("No active conversation".to_string()))
-        }
+    /// 把工具输出作为用户消息加入对话，之后可以被裁剪
+    pub fn add_tool_result(&mut self, tool: &str, invocation: &str, output: &str) -> Result<String> {
+        let message_id = Uuid::new_v4().to_string();
+        let message = ConversationMessage {
+            id: message_id.clone(),
+            role: "user".to_string(),
+            content: prune::tool_result_content(invocation, output).into(),
+            timestamp: Utc::now(),
+            metadata: HashMap::from([(prune::TOOL_METADATA_KEY.to_string(), serde_json::Value::String(tool.to_string()))]),
+            token_usage: None,
+            pinned: false,
+            provenance: None,
+        };
+        self.record(EventKind::MessageAdded { message })?;
+        Ok(message_id)
     }
 
     /// 获取当前对话
@@ -191,34 +280,61 @@ impl ConversationManager {
             .unwrap_or_default()
     }
 
+    /// 当前对话转换为 API 消息，消息内容与对话历史共享
+    #[cfg(feature = "native")]
+    pub fn api_messages(&self) -> Vec<crate::network::Message> {
+        self.current_conversation
+            .as_ref()
+            .map(|c| {
+                c.messages
+                    .iter()
+                    .map(|m| crate::network::Message {
+                        role: m.role.clone(),
+                        content: m.content.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 发送请求用的 API 消息：过时的工具结果换成一行占位
+    #[cfg(feature = "native")]
+    pub fn pruned_api_messages(&self, config: &prune::PruneConfig) -> (Vec<crate::network::Message>, prune::PruneStats) {
+        let Some(conversation) = self.current_conversation.as_ref() else {
+            return (Vec::new(), prune::PruneStats::default());
+        };
+        let (messages, stats) = prune::prune(&conversation.messages, config);
+        let messages = messages.into_iter().map(|(role, content)| crate::network::Message { role, content }).collect();
+        (messages, stats)
+    }
+
     /// 清除当前对话历史
     pub fn clear_current_conversation(&mut self) -> Result<()> {
-        if let Some(conversation) = self.current_conversation.as_mut() {
-            conversation.messages.clear();
-            conversation.updated_at = Utc::now();
-            conversation.total_token_usage = TokenUsage {
-                input_tokens: 0,
-                output_tokens: 0,
-                total_tokens: 0,
-                estimated_cost: 0.0,
-            };
-
-            // 克隆对话以避免借用冲突
-            let conversation_clone = conversation.clone();
-            self.save_conversation(&conversation_clone)?;
+        if self.current_conversation.is_some() {
+            self.record(EventKind::Cleared)?;
         }
         Ok(())
     }
 
+    /// 把当前对话截断到前 `keep` 条消息，没有当前对话时忽略
+    pub fn truncate_messages(&mut self, keep: usize, checkpoint: &str) -> Result<()> {
+        match self.current_conversation.as_ref() {
+            Some(conversation) if conversation.messages.len() > keep => {
+                self.record(EventKind::RolledBack { keep, checkpoint: checkpoint.to_string() })
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// 压缩对话历史
     pub fn compact_conversation(&mut self, instructions: Option<&str>) -> Result<()> {
-        if let Some(conversation) = self.current_conversation.as_mut() {
+        if let Some(conversation) = self.current_conversation.as_ref() {
             // 简单的压缩策略：保留最近的消息和重要的系统消息
             let mut important_messages = Vec::new();
             let mut recent_messages = Vec::new();
 
             for message in &conversation.messages {
-                if message.role == "system" || message.content.len() > 1000 {
+                if message.pinned || message.role == "system" || message.content.len() > 1000 {
                     important_messages.push(message.clone());
                 }
             }
@@ -235,23 +351,9 @@ impl ConversationManager {
                 }
             }
 
-            // 按时间排序
-            compacted_messages.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
-
-            conversation.messages = compacted_messages;
-            conversation.updated_at = Utc::now();
-
-            // 添加压缩说明
-            if let Some(instr) = instructions {
-                conversation.metadata.insert(
-                    "last_compact_instructions".to_string(),
-                    serde_json::Value::String(instr.to_string())
-                );
-            }
-
-            // 克隆对话以避免借用冲突
-            let conversation_clone = conversation.clone();
-            self.save_conversation(&conversation_clone)?;
+            // 保留的消息按时间排序，压缩说明记入元数据
+            let kept = compacted_messages.into_iter().map(|m| m.id).collect();
+            self.record(EventKind::Compacted { kept, instructions: instructions.map(str::to_string) })?;
         }
         Ok(())
     }
@@ -263,26 +365,31 @@ impl ConversationManager {
         let entries = std::fs::read_dir(&self.storage_dir)
             .map_err(|e| ClaudeError::General(format!("Failed to read storage directory: {}", e)))?;
 
+        // 快照和事件日志都可能单独存在
+        let mut ids = std::collections::BTreeSet::new();
         for entry in entries {
             let entry = entry.map_err(|e| ClaudeError::General(format!("Failed to read directory entry: {}", e)))?;
-            let path = entry.path();
-            
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    if let Ok(conversation) = self.load_conversation_from_file(stem) {
-                        summaries.push(ConversationSummary {
-                            id: conversation.id,
-                            title: conversation.title,
-                            created_at: conversation.created_at,
-                            updated_at: conversation.updated_at,
-                            message_count: conversation.messages.len(),
-                            total_tokens: conversation.total_token_usage.total_tokens,
-                            estimated_cost: conversation.total_token_usage.estimated_cost,
-                            tags: conversation.tags,
-                            archived: conversation.archived,
-                        });
-                    }
-                }
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else { continue };
+            if let Some(id) = name.strip_suffix(".events.jsonl").or_else(|| name.strip_suffix(".json")) {
+                ids.insert(id.to_string());
+            }
+        }
+
+        for id in ids {
+            if let Ok(conversation) = self.load_conversation_from_file(&id) {
+                summaries.push(ConversationSummary {
+                    id: conversation.id,
+                    title: conversation.title,
+                    created_at: conversation.created_at,
+                    updated_at: conversation.updated_at,
+                    message_count: conversation.messages.len(),
+                    total_tokens: conversation.total_token_usage.total_tokens,
+                    estimated_cost: conversation.total_token_usage.estimated_cost,
+                    tags: conversation.tags,
+                    archived: conversation.archived,
+                    branch: conversation.branch,
+                });
             }
         }
 
@@ -291,30 +398,131 @@ impl ConversationManager {
         Ok(summaries)
     }
 
-    /// 保存对话到文件
-    fn save_conversation(&mut self, conversation: &Conversation) -> Result<()> {
+    /// 记录当前对话的事件
+    fn record(&mut self, kind: EventKind) -> Result<()> {
+        let mut conversation = self
+            .current_conversation
+            .take()
+            .ok_or_else(|| ClaudeError::General("No active conversation".to_string()))?;
+        let result = self.append_event(&mut conversation, kind);
+        self.current_conversation = Some(conversation);
+        result
+    }
+
+    /// 事件先追加到日志再应用到状态，创建时和每隔 `SNAPSHOT_INTERVAL` 个事件写一次快照
+    fn append_event(&mut self, conversation: &mut Conversation, kind: EventKind) -> Result<()> {
+        let event = SessionEvent { seq: conversation.event_seq + 1, at: Utc::now(), kind };
+        EventLog::new(&self.storage_dir, &conversation.id).append(&event)?;
+        let snapshot = matches!(event.kind, EventKind::Created { .. }) || event.seq.is_multiple_of(SNAPSHOT_INTERVAL);
+        conversation.apply(&event);
+        if snapshot {
+            self.save_snapshot(conversation)?;
+        }
+        self.add_to_cache(conversation.clone());
+        Ok(())
+    }
+
+    /// 保存对话快照
+    fn save_snapshot(&self, conversation: &Conversation) -> Result<()> {
         let file_path = self.storage_dir.join(format!("{}.json", conversation.id));
         let json = serde_json::to_string_pretty(conversation)
             .map_err(|e| ClaudeError::General(format!("Failed to serialize conversation: {}", e)))?;
-        
+
         std::fs::write(&file_path, json)
-            .map_err(|e| ClaudeError::General(format!("Failed to write conversation file: {}", e)))?;
+            .map_err(|e| ClaudeError::General(format!("Failed to write conversation file: {}", e)))
+    }
 
-        // 更新缓存
-        self.add_to_cache(conversation.clone());
-        Ok(())
+    /// 存储中是否已有该对话
+    pub fn has_conversation(&self, id: &str) -> bool {
+        self.conversation_cache.contains_key(id)
+            || self.storage_dir.join(format!("{}.json", id)).exists()
+            || EventLog::new(&self.storage_dir, id).exists()
+    }
+
+    /// 把外部来源的完整对话写入存储，不改变当前对话
+    pub fn import_conversation(&mut self, mut conversation: Conversation) -> Result<()> {
+        std::fs::create_dir_all(&self.storage_dir)
+            .map_err(|e| ClaudeError::General(format!("Failed to create storage directory: {}", e)))?;
+        // 覆盖导入时旧的日志作废
+        let log = EventLog::new(&self.storage_dir, &conversation.id);
+        if log.exists() {
+            std::fs::remove_file(log.path())?;
+        }
+        conversation.event_seq = 0;
+        let created = EventKind::Created { conversation: Box::new(conversation.clone()) };
+        self.append_event(&mut conversation, created)
     }
 
     /// 从文件加载对话
     fn load_conversation_from_file(&self, id: &str) -> Result<Conversation> {
+        self.read_conversation(id).map(|(conversation, _)| conversation)
+    }
+
+    /// 读取快照并重放之后的事件，同时返回完整的事件日志
+    fn read_conversation(&self, id: &str) -> Result<(Conversation, Vec<SessionEvent>)> {
         let file_path = self.storage_dir.join(format!("{}.json", id));
-        let json = std::fs::read_to_string(&file_path)
-            .map_err(|e| ClaudeError::General(format!("Failed to read conversation file: {}", e)))?;
-        
-        let conversation: Conversation = serde_json::from_str(&json)
-            .map_err(|e| ClaudeError::General(format!("Failed to deserialize conversation: {}", e)))?;
-        
-        Ok(conversation)
+        let snapshot = match std::fs::read_to_string(&file_path) {
+            Ok(json) => Some(
+                serde_json::from_str::<Conversation>(&json)
+                    .map_err(|e| ClaudeError::General(format!("Failed to deserialize conversation: {}", e)))?,
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(ClaudeError::General(format!("Failed to read conversation file: {}", e))),
+        };
+        let events = EventLog::new(&self.storage_dir, id).read()?;
+        let conversation = events::replay(snapshot, &events, None)
+            .ok_or_else(|| ClaudeError::General(format!("Failed to read conversation file: no snapshot or event log for {}", id)))?;
+        Ok((conversation, events))
+    }
+
+    /// 会话的完整事件日志，早于事件日志保存的会话没有事件
+    pub fn events(&self, id: &str) -> Result<Vec<SessionEvent>> {
+        EventLog::new(&self.storage_dir, id).read()
+    }
+
+    /// 重放会话到第 `until` 个事件时的状态，省略时为最新状态
+    pub fn replay_conversation(&self, id: &str, until: Option<u64>) -> Result<Conversation> {
+        let (latest, events) = self.read_conversation(id)?;
+        if until.is_none() {
+            return Ok(latest);
+        }
+        let snapshot = self.storage_dir.join(format!("{}.json", id));
+        let snapshot = std::fs::read_to_string(snapshot).ok().and_then(|json| serde_json::from_str(&json).ok());
+        events::replay(snapshot, &events, until)
+            .ok_or_else(|| ClaudeError::validation_error("until", format!("event {} is before the start of the log", until.unwrap_or(0))))
+    }
+
+    /// 开始回答当前对话中的一条用户消息
+    pub fn start_turn(&mut self, message_id: &str) -> Result<()> {
+        self.interrupted = None;
+        self.record(EventKind::TurnStarted { message_id: message_id.to_string() })
+    }
+
+    /// 一条用户消息已经得到回复
+    pub fn finish_turn(&mut self, message_id: &str) -> Result<()> {
+        self.interrupted = None;
+        self.record(EventKind::TurnFinished { message_id: message_id.to_string() })
+    }
+
+    /// 记录当前对话中执行的工具调用，没有当前对话时忽略
+    pub fn record_tool_call(&mut self, name: &str, input: serde_json::Value, output: &str, is_error: bool) -> Result<()> {
+        if self.current_conversation.is_none() {
+            return Ok(());
+        }
+        self.record(EventKind::ToolCall { name: name.to_string(), input, output: output.to_string(), is_error })
+    }
+
+    /// 记录当前对话中对文件的修改，没有当前对话时忽略
+    pub fn record_file_edit(&mut self, path: &str, summary: &str) -> Result<()> {
+        if self.current_conversation.is_none() {
+            return Ok(());
+        }
+        self.record(EventKind::FileEdited { path: path.to_string(), summary: summary.to_string() })
+    }
+
+    /// 当前对话加载时发现的、崩溃前没有完成的回合
+    pub fn interrupted_turn(&self) -> Option<&InterruptedTurn> {
+        self.interrupted.as_ref()
     }
 
     /// 添加到缓存
@@ -328,6 +536,174 @@ impl ConversationManager {
         self.conversation_cache.insert(conversation.id.clone(), conversation);
     }
 
+    /// 为当前对话记录环境快照
+    pub fn set_environment(&mut self, snapshot: EnvironmentSnapshot) -> Result<()> {
+        if self.current_conversation.is_some() {
+            self.record(EventKind::SettingChanged { setting: Setting::Environment(Box::new(snapshot)) })?;
+        }
+        Ok(())
+    }
+
+    /// 把当前对话关联到 Git 分支
+    pub fn set_branch(&mut self, branch: Option<String>) -> Result<()> {
+        if self.current_conversation.is_some() {
+            self.record(EventKind::SettingChanged { setting: Setting::Branch(branch) })?;
+        }
+        Ok(())
+    }
+
+    /// 设置新会话默认的回复语言
+    pub fn set_default_language(&mut self, language: Option<ResponseLanguage>) {
+        self.default_language = language;
+    }
+
+    /// 设置当前对话的回复语言，之后新建的对话也沿用该语言
+    pub fn set_language(&mut self, language: Option<ResponseLanguage>) -> Result<()> {
+        self.default_language = language.clone();
+        if self.current_conversation.is_some() {
+            self.record(EventKind::SettingChanged { setting: Setting::Language(language) })?;
+        }
+        Ok(())
+    }
+
+    /// 当前生效的回复语言：当前对话的设置优先，其次是默认值
+    pub fn language(&self) -> Option<&ResponseLanguage> {
+        self.current_conversation
+            .as_ref()
+            .and_then(|c| c.language.as_ref())
+            .or(self.default_language.as_ref())
+    }
+
+    /// 修改当前对话的采样参数覆盖（没有对话时作用于下一个新建的对话），返回修改后的值
+    pub fn update_sampling(&mut self, update: impl FnOnce(&mut SamplingOverrides) -> Result<()>) -> Result<SamplingOverrides> {
+        let mut sampling = self.sampling().clone();
+        update(&mut sampling)?;
+        self.default_sampling = sampling.clone();
+        if self.current_conversation.is_some() {
+            self.record(EventKind::SettingChanged { setting: Setting::Sampling(sampling.clone()) })?;
+        }
+        Ok(sampling)
+    }
+
+    /// 当前生效的采样参数覆盖
+    pub fn sampling(&self) -> &SamplingOverrides {
+        self.current_conversation
+            .as_ref()
+            .map_or(&self.default_sampling, |c| &c.sampling)
+    }
+
+    /// 读取已保存的对话而不切换当前对话
+    pub fn get_conversation(&self, id: &str) -> Result<Conversation> {
+        match self.conversation_cache.get(id) {
+            Some(conversation) => Ok(conversation.clone()),
+            None => self.load_conversation_from_file(id),
+        }
+    }
+
+    /// 与分支关联的最近更新的对话
+    pub fn latest_for_branch(&self, branch: &str) -> Result<Option<ConversationSummary>> {
+        Ok(self
+            .list_conversations()?
+            .into_iter()
+            .find(|summary| !summary.archived && summary.branch.as_deref() == Some(branch)))
+    }
+
+    /// 以当前对话的历史为起点创建关联到另一个分支的新对话，并切换到新对话
+    pub fn fork_current(&mut self, branch: Option<String>) -> Result<String> {
+        let source = self
+            .current_conversation
+            .clone()
+            .ok_or_else(|| ClaudeError::General("No active conversation to fork".to_string()))?;
+
+        let now = Utc::now();
+        let mut fork = source.clone();
+        fork.id = Uuid::new_v4().to_string();
+        fork.title = match &branch {
+            Some(branch) => format!("{} ({})", source.title, branch),
+            None => format!("{} (fork)", source.title),
+        };
+        fork.created_at = now;
+        fork.updated_at = now;
+        fork.branch = branch;
+        fork.metadata.insert("forked_from".to_string(), serde_json::Value::String(source.id));
+        fork.event_seq = 0;
+
+        let id = fork.id.clone();
+        self.current_conversation = Some(fork.clone());
+        self.interrupted = None;
+        self.record(EventKind::Created { conversation: Box::new(fork) })?;
+        Ok(id)
+    }
+
+    /// 固定或取消固定当前对话的第 `number` 条消息（从 1 开始）
+    pub fn set_pinned(&mut self, number: usize, pinned: bool) -> Result<ConversationMessage> {
+        let mut message = self.message(number)?.clone();
+        message.pinned = pinned;
+
+        self.record(EventKind::MessageUpdated { message: message.clone() })?;
+        Ok(message)
+    }
+
+    /// 当前对话中已固定的消息及其编号
+    pub fn pinned_messages(&self) -> Vec<(usize, &ConversationMessage)> {
+        self.current_conversation
+            .iter()
+            .flat_map(|c| c.messages.iter().enumerate())
+            .filter(|(_, message)| message.pinned)
+            .map(|(index, message)| (index + 1, message))
+            .collect()
+    }
+
+    /// 记录消息的上下文来源
+    pub fn set_provenance(&mut self, message_id: &str, provenance: Provenance) -> Result<()> {
+        let conversation = self
+            .current_conversation
+            .as_ref()
+            .ok_or_else(|| ClaudeError::General("No active conversation".to_string()))?;
+        let mut message = conversation
+            .messages
+            .iter()
+            .find(|message| message.id == message_id)
+            .cloned()
+            .ok_or_else(|| ClaudeError::validation_error("message", format!("unknown message id {}", message_id)))?;
+        message.provenance = Some(provenance);
+
+        self.record(EventKind::MessageUpdated { message })
+    }
+
+    /// 当前历史中用户消息引入的来源；压缩掉的消息不再计入
+    pub fn history_sources(&self) -> Vec<ContextSource> {
+        provenance::merge_sources(
+            self.current_conversation
+                .iter()
+                .flat_map(|c| c.messages.iter())
+                .filter(|message| message.role == "user")
+                .filter_map(|message| message.provenance.as_ref())
+                .flat_map(|provenance| provenance.sources.iter().cloned()),
+        )
+    }
+
+    /// 当前对话第 `number` 条消息（从 1 开始）
+    pub fn message(&self, number: usize) -> Result<&ConversationMessage> {
+        let messages = self
+            .current_conversation
+            .as_ref()
+            .map(|c| c.messages.as_slice())
+            .ok_or_else(|| ClaudeError::General("No active conversation".to_string()))?;
+        number
+            .checked_sub(1)
+            .and_then(|index| messages.get(index))
+            .ok_or_else(|| ClaudeError::validation_error("message", format!("expected a number between 1 and {}", messages.len())))
+    }
+
+    /// 当前对话的环境快照，格式化为可注入上下文的文本
+    pub fn environment_context(&self) -> Option<String> {
+        self.current_conversation
+            .as_ref()
+            .and_then(|c| c.environment.as_ref())
+            .map(EnvironmentSnapshot::to_context_string)
+    }
+
     /// 获取消息数量
     pub fn get_message_count(&self) -> usize {
         if let Some(conversation) = &self.current_conversation {
@@ -350,6 +726,7 @@ pub struct ConversationSummary {
     pub estimated_cost: f64,
     pub tags: Vec<String>,
     pub archived: bool,
+    pub branch: Option<String>,
 }
 
 impl Default for TokenUsage {
@@ -362,3 +739,99 @@ impl Default for TokenUsage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branch_association_and_fork() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut manager = ConversationManager::with_storage_dir(temp_dir.path().to_path_buf()).unwrap();
+
+        let main_id = manager.create_conversation(Some("Refactor parser".to_string())).unwrap();
+        manager.set_branch(Some("main".to_string())).unwrap();
+        manager.add_message("user", "split the parser module", None).unwrap();
+        assert!(manager.latest_for_branch("feature").unwrap().is_none());
+
+        let fork_id = manager.fork_current(Some("feature".to_string())).unwrap();
+        let fork = manager.get_current_conversation().unwrap();
+        assert_eq!(fork.branch.as_deref(), Some("feature"));
+        assert_eq!(fork.messages.len(), 1);
+        assert_eq!(fork.metadata["forked_from"], main_id.as_str());
+
+        assert_eq!(manager.latest_for_branch("main").unwrap().unwrap().id, main_id);
+        assert_eq!(manager.latest_for_branch("feature").unwrap().unwrap().id, fork_id);
+    }
+
+    #[test]
+    fn test_language_applies_to_titles_and_export() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut manager = ConversationManager::with_storage_dir(temp_dir.path().to_path_buf()).unwrap();
+        let zh = ResponseLanguage::parse("zh-CN").unwrap();
+        manager.set_default_language(Some(zh.clone()));
+
+        let id = manager.create_conversation(None).unwrap();
+        manager.add_message("user", "你好", None).unwrap();
+        manager.add_message("assistant", "你好！", None).unwrap();
+        let conversation = manager.get_current_conversation().unwrap().clone();
+        assert!(conversation.title.starts_with("对话 "));
+        assert_eq!(conversation.language.as_ref(), Some(&zh));
+
+        let markdown = conversation.to_markdown(None);
+        assert!(markdown.contains("## 用户 · "));
+        assert!(markdown.contains("## 助手 · "));
+
+        // 会话语言被持久化，并优先于调用方给出的默认语言
+        let stored = manager.get_conversation(&id).unwrap();
+        let en = ResponseLanguage::parse("en").unwrap();
+        assert!(stored.to_markdown(Some(&en)).contains("## 用户 · "));
+
+        manager.set_language(Some(en.clone())).unwrap();
+        assert_eq!(manager.language(), Some(&en));
+        assert!(manager.get_conversation(&id).unwrap().to_markdown(None).contains("## User · "));
+    }
+
+    #[test]
+    fn test_sampling_overrides_persist_with_session() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut manager = ConversationManager::with_storage_dir(temp_dir.path().to_path_buf()).unwrap();
+        manager.update_sampling(|s| s.set("temperature", "0.2")).unwrap();
+        let id = manager.create_conversation(None).unwrap();
+        assert_eq!(manager.sampling().temperature, Some(0.2));
+
+        manager.update_sampling(|s| s.set("top_p", "0.9")).unwrap();
+        assert!(manager.update_sampling(|s| s.set("temperature", "1.5")).is_err());
+        assert!(manager.update_sampling(|s| s.set("seed", "1")).is_err());
+
+        // 恢复会话时沿用保存的覆盖值
+        let mut resumed = ConversationManager::with_storage_dir(temp_dir.path().to_path_buf()).unwrap();
+        resumed.load_conversation(&id).unwrap();
+        assert_eq!(resumed.sampling().to_string(), "temperature=0.2 top_p=0.9");
+
+        resumed.update_sampling(|s| s.set("temperature", "default")).unwrap();
+        assert_eq!(resumed.get_conversation(&id).unwrap().sampling.temperature, None);
+    }
+
+    #[test]
+    fn test_pinned_messages_survive_compaction() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut manager = ConversationManager::with_storage_dir(temp_dir.path().to_path_buf()).unwrap();
+        manager.create_conversation(None).unwrap();
+        for i in 0..30 {
+            manager.add_message("user", format!("message {}", i), None).unwrap();
+        }
+
+        let pinned = manager.set_pinned(2, true).unwrap();
+        assert_eq!(pinned.content.as_str(), "message 1");
+        assert!(manager.set_pinned(31, true).is_err());
+        assert_eq!(manager.pinned_messages().len(), 1);
+
+        manager.compact_conversation(None).unwrap();
+        let messages = manager.get_conversation_messages();
+        assert_eq!(messages.len(), 21);
+        assert_eq!(messages[0].content.as_str(), "message 1");
+        assert!(messages[0].pinned);
+        assert_eq!(manager.pinned_messages()[0].0, 1);
+    }
+}
@@ -0,0 +1,185 @@
+//! 上下文来源
+//!
+//! 记录每条回复生成时上下文里有哪些来源（项目记忆、引用的文件、抓取的网页、片段），连同内容摘要一起
+//! 随会话保存。之后可以查看某条回复当时依据了什么，并判断文件从那以后是否改过
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// 项目和用户记忆文件名
+pub const MEMORY_FILE: &str = "CLAUDE.md";
+
+/// 来源类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// 用 `@路径` 引用的文件
+    File,
+    /// CLAUDE.md 记忆文件
+    Memory,
+    /// 用 `@https://…` 引用并抓取的网页
+    Url,
+    /// `@snippet:名称` 展开的片段
+    Snippet,
+    /// 同一仓库以前的失败教训
+    Lesson,
+    /// 用 `@服务器:资源` 引用的 MCP 资源
+    McpResource,
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            SourceKind::File => "📄 file",
+            SourceKind::Memory => "🧠 memory",
+            SourceKind::Url => "🌐 url",
+            SourceKind::Snippet => "✂️ snippet",
+            SourceKind::Lesson => "📝 lesson",
+            SourceKind::McpResource => "🔌 mcp resource",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+/// 一个上下文来源及其当时内容的摘要
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContextSource {
+    pub kind: SourceKind,
+    /// 路径、URL 或片段名
+    pub location: String,
+    /// 内容的 MD5 摘要
+    pub digest: String,
+    /// 内容的估算 Token 数
+    pub tokens: u32,
+    pub captured_at: DateTime<Utc>,
+}
+
+impl ContextSource {
+    pub fn new(kind: SourceKind, location: impl Into<String>, content: &str) -> Self {
+        Self {
+            kind,
+            location: location.into(),
+            digest: digest(content),
+            tokens: crate::tokens::estimate_tokens(content),
+            captured_at: Utc::now(),
+        }
+    }
+
+    /// 本地文件来源的内容是否已变化：文件被删除或内容不同即为变化，其他来源无法判断时返回 `None`
+    pub fn changed_since(&self) -> Option<bool> {
+        if !matches!(self.kind, SourceKind::File | SourceKind::Memory) {
+            return None;
+        }
+        Some(std::fs::read_to_string(&self.location).map_or(true, |content| digest(&content) != self.digest))
+    }
+}
+
+/// 一条回复生成时的完整上下文
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// 当时上下文中的来源，按引入顺序
+    pub sources: Vec<ContextSource>,
+    /// 请求中包含的历史消息数（含本轮用户消息）
+    pub history_messages: usize,
+    /// 生成回复的模型
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl Provenance {
+    /// 多行描述，供 `/why-did-you-say-that` 显示
+    pub fn describe(&self) -> String {
+        let mut lines = vec![format!(
+            "{} message(s) of history{}",
+            self.history_messages,
+            self.model.as_ref().map(|m| format!(", answered by {}", m)).unwrap_or_default()
+        )];
+        if self.sources.is_empty() {
+            lines.push("No files, memory, URLs, snippets or MCP resources were in context".to_string());
+        }
+        for source in &self.sources {
+            let state = match source.changed_since() {
+                Some(true) => "  ⚠️ changed since",
+                Some(false) => "  ✓ unchanged",
+                None => "",
+            };
+            lines.push(format!(
+                "{} {} (~{} tokens, md5 {}){}",
+                source.kind,
+                source.location,
+                source.tokens,
+                &source.digest[..12.min(source.digest.len())],
+                state
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// 同一来源只保留最新引入的一次
+pub fn merge_sources(sources: impl IntoIterator<Item = ContextSource>) -> Vec<ContextSource> {
+    let mut merged: Vec<ContextSource> = Vec::new();
+    for source in sources {
+        merged.retain(|s| !(s.kind == source.kind && s.location == source.location));
+        merged.push(source);
+    }
+    merged
+}
+
+/// 输入中以 `@` 开头的文件、URL 或 MCP 资源引用（`@snippet:` 由片段库处理）
+pub fn mentions(input: &str) -> Vec<&str> {
+    input
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .map(|mention| mention.trim_end_matches([',', '.', ';', ':', ')', '?', '!']))
+        .filter(|mention| !mention.is_empty() && !mention.starts_with("snippet:"))
+        .collect()
+}
+
+/// 是否为 URL 引用
+pub fn is_url(mention: &str) -> bool {
+    mention.starts_with("https://") || mention.starts_with("http://")
+}
+
+/// 存在的记忆文件：用户级 `~/.claude/CLAUDE.md` 在前，项目级在后
+pub fn memory_files(project_dir: &Path) -> Vec<PathBuf> {
+    let user = dirs::home_dir().map(|home| home.join(".claude").join(MEMORY_FILE));
+    user.into_iter()
+        .chain(std::iter::once(project_dir.join(MEMORY_FILE)))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+fn digest(content: &str) -> String {
+    format!("{:x}", md5::compute(content.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sources_track_changes_and_mentions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "first").unwrap();
+
+        let source = ContextSource::new(SourceKind::File, path.display().to_string(), "first");
+        assert_eq!(source.changed_since(), Some(false));
+        std::fs::write(&path, "second").unwrap();
+        assert_eq!(source.changed_since(), Some(true));
+        assert_eq!(ContextSource::new(SourceKind::Url, "https://example.com", "x").changed_since(), None);
+
+        let newer = ContextSource::new(SourceKind::File, path.display().to_string(), "second");
+        let merged = merge_sources([source, ContextSource::new(SourceKind::Snippet, "intro", "hi"), newer.clone()]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[1], newer);
+
+        assert_eq!(
+            mentions("compare @src/main.rs, @https://example.com/a. and @snippet:intro with me@"),
+            vec!["src/main.rs", "https://example.com/a"]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! 过时工具结果的裁剪
+//!
+//! 每次请求前，把若干回合之前、最近的对话已经不再提到的大块工具输出（目录列表、旧的测试日志）换成一行占位。
+//! 只影响发给模型的上下文，会话记录保持完整；固定的消息从不裁剪
+
+use serde::{Deserialize, Serialize};
+
+use super::{ConversationMessage, SharedText};
+
+/// 消息元数据中记录工具名的键
+pub const TOOL_METADATA_KEY: &str = "tool";
+
+/// 导入的会话和工具输出消息的内容前缀
+const TOOL_RESULT_PREFIX: &str = "[Tool result";
+
+/// 每个工具结果最多提取的特征词数
+const MAX_TERMS: usize = 200;
+
+/// 裁剪配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PruneConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 工具结果之后又经过多少个用户回合才可以裁剪
+    #[serde(default = "default_stale_after_turns")]
+    pub stale_after_turns: usize,
+    /// 小于该 Token 数的结果保持原样
+    #[serde(default = "default_min_tokens")]
+    pub min_tokens: u32,
+}
+
+impl Default for PruneConfig {
+    fn default() -> Self {
+        Self { enabled: default_enabled(), stale_after_turns: default_stale_after_turns(), min_tokens: default_min_tokens() }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_stale_after_turns() -> usize {
+    3
+}
+
+fn default_min_tokens() -> u32 {
+    200
+}
+
+/// 裁剪结果统计
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PruneStats {
+    pub pruned: usize,
+    pub tokens_saved: u32,
+}
+
+/// 工具结果消息的工具名，不是工具结果时返回 `None`
+pub fn tool_name(message: &ConversationMessage) -> Option<&str> {
+    match message.metadata.get(TOOL_METADATA_KEY).and_then(|tool| tool.as_str()) {
+        Some(tool) => Some(tool),
+        None => message.content.starts_with(TOOL_RESULT_PREFIX).then_some("tool"),
+    }
+}
+
+/// 工具输出作为消息内容时的格式
+pub fn tool_result_content(invocation: &str, output: &str) -> String {
+    format!("{}: {}]\n{}", TOOL_RESULT_PREFIX, invocation, output.trim_end())
+}
+
+/// 生成请求用的 (角色, 内容) 列表：过时的工具结果换成占位，相邻的同角色消息合并
+pub fn prune(messages: &[ConversationMessage], config: &PruneConfig) -> (Vec<(String, SharedText)>, PruneStats) {
+    // 每条消息之后还有多少个用户回合
+    let mut turns_after = vec![0; messages.len()];
+    let mut turns = 0;
+    for (index, message) in messages.iter().enumerate().rev() {
+        turns_after[index] = turns;
+        if message.role == "user" && tool_name(message).is_none() {
+            turns += 1;
+        }
+    }
+    let recent: String = messages
+        .iter()
+        .zip(&turns_after)
+        .filter(|(message, after)| **after < config.stale_after_turns && tool_name(message).is_none())
+        .map(|(message, _)| message.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut stats = PruneStats::default();
+    let mut out: Vec<(String, SharedText)> = Vec::new();
+    for (message, after) in messages.iter().zip(&turns_after) {
+        let mut content = message.content.clone();
+        if let Some(tool) = tool_name(message).filter(|_| config.enabled && !message.pinned) {
+            let tokens = crate::tokens::estimate_tokens(&message.content);
+            if *after >= config.stale_after_turns && tokens >= config.min_tokens && !referenced(&message.content, &recent) {
+                content = SharedText::from(stub(tool, &message.content, tokens));
+                stats.pruned += 1;
+                stats.tokens_saved += tokens.saturating_sub(crate::tokens::estimate_tokens(&content));
+            }
+        }
+        match out.last_mut() {
+            Some((role, previous)) if *role == message.role => {
+                *previous = SharedText::from(format!("{}\n\n{}", previous, content));
+            }
+            _ => out.push((message.role.clone(), content)),
+        }
+    }
+    (out, stats)
+}
+
+/// 最近的对话是否提到了结果中的路径或标识符
+fn referenced(result: &str, recent: &str) -> bool {
+    let mut terms: Vec<&str> = result
+        .split(|c: char| c.is_whitespace() || "\"'`()[]{}<>,;=|".contains(c))
+        .map(|term| term.trim_matches(|c: char| matches!(c, '.' | ':')))
+        .filter(|term| term.len() >= 6 && term.contains(['/', '.', '_', ':']))
+        .collect();
+    terms.sort_unstable();
+    terms.dedup();
+    terms.iter().take(MAX_TERMS).any(|term| recent.contains(term))
+}
+
+/// 一行占位：优先保留错误或测试结论所在的行
+fn stub(tool: &str, content: &str, tokens: u32) -> String {
+    let body = content.strip_prefix(TOOL_RESULT_PREFIX).and_then(|rest| rest.split_once('\n')).map_or(content, |(_, body)| body);
+    let lines: Vec<&str> = body.lines().filter(|line| !line.trim().is_empty()).collect();
+    let key = lines
+        .iter()
+        .find(|line| {
+            let lower = line.to_lowercase();
+            ["error", "failed", "test result", "passed"].iter().any(|marker| lower.contains(marker))
+        })
+        .or(lines.first())
+        .map(|line| line.trim())
+        .unwrap_or_default();
+    let mut summary: String = key.chars().take(100).collect();
+    if summary.len() < key.len() {
+        summary.push('…');
+    }
+    format!("[Earlier {} output pruned ({} lines, ~{} tokens): {}]", tool, lines.len(), tokens, summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::ConversationManager;
+
+    #[test]
+    fn test_prunes_stale_unreferenced_tool_results() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manager = ConversationManager::with_storage_dir(dir.path().to_path_buf()).unwrap();
+        manager.create_conversation(None).unwrap();
+
+        let listing: String = (0..200).map(|i| format!("src/generated/module_{}.rs\n", i)).collect();
+        let log = format!("running 40 tests\n{}test result: FAILED. 39 passed; 1 failed\n", "test ok\n".repeat(300));
+        manager.add_tool_result("ls", "ls -R", &listing).unwrap();
+        manager.add_tool_result("cargo", "cargo test", &log).unwrap();
+        let pinned = manager.add_tool_result("cat", "cat notes.txt", &"keep me ".repeat(300)).unwrap();
+        let number = manager.get_conversation_messages().iter().position(|m| m.id == pinned).unwrap() + 1;
+        manager.set_pinned(number, true).unwrap();
+        for question in ["why did it fail?", "ok", "now look at src/generated/module_7.rs", "thanks"] {
+            manager.add_message("user", question, None).unwrap();
+            manager.add_message("assistant", "sure", None).unwrap();
+        }
+
+        let messages = manager.get_conversation_messages();
+        let (pruned, stats) = prune(&messages, &PruneConfig::default());
+        assert_eq!(stats.pruned, 1);
+        assert!(stats.tokens_saved > 100);
+        // 相邻的用户消息合并为一条
+        assert_eq!(pruned.len(), 8);
+        let first = pruned[0].1.as_str();
+        assert!(first.contains("module_199.rs"), "listing is still referenced");
+        assert!(first.contains("[Earlier cargo output pruned (302 lines"));
+        assert!(first.contains("test result: FAILED. 39 passed; 1 failed]"));
+        assert!(first.contains("keep me keep me"));
+
+        let disabled = PruneConfig { enabled: false, ..PruneConfig::default() };
+        assert_eq!(prune(&messages, &disabled).1, PruneStats::default());
+    }
+}
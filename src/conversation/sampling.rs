@@ -0,0 +1,89 @@
+//! 会话级采样参数
+//!
+//! 交互模式中用 `/set temperature 0.2` 之类的命令覆盖后续请求的采样参数。覆盖值随会话保存，
+//! 恢复会话时沿用同样的采样配置
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::{ClaudeError, Result};
+
+/// 可以覆盖的参数名
+pub const SETTING_KEYS: &[&str] = &["temperature", "top_p", "max_tokens"];
+
+/// 会话的采样参数覆盖，未设置的项使用请求的默认值
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+}
+
+impl SamplingOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 设置一项参数；值为 `default` 时清除覆盖
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let clear = value.eq_ignore_ascii_case("default");
+        match key {
+            "temperature" => self.temperature = if clear { None } else { Some(parse_unit(key, value)?) },
+            "top_p" => self.top_p = if clear { None } else { Some(parse_unit(key, value)?) },
+            "max_tokens" => {
+                self.max_tokens = if clear {
+                    None
+                } else {
+                    let tokens = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|tokens| *tokens > 0)
+                        .ok_or_else(|| ClaudeError::validation_error(key, "Expected a positive integer"))?;
+                    Some(tokens)
+                }
+            }
+            _ => {
+                return Err(ClaudeError::validation_error(
+                    "setting",
+                    format!("Unknown setting '{}'; expected one of {}", key, SETTING_KEYS.join(", ")),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// 显示用的参数值，未覆盖时为 `None`
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "temperature" => self.temperature.map(|v| v.to_string()),
+            "top_p" => self.top_p.map(|v| v.to_string()),
+            "max_tokens" => self.max_tokens.map(|v| v.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SamplingOverrides {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let values: Vec<String> = SETTING_KEYS
+            .iter()
+            .filter_map(|key| self.get(key).map(|value| format!("{}={}", key, value)))
+            .collect();
+        if values.is_empty() {
+            write!(f, "defaults")
+        } else {
+            write!(f, "{}", values.join(" "))
+        }
+    }
+}
+
+fn parse_unit(key: &str, value: &str) -> Result<f32> {
+    value
+        .parse::<f32>()
+        .ok()
+        .filter(|v| (0.0..=1.0).contains(v))
+        .ok_or_else(|| ClaudeError::validation_error(key, "Expected a number between 0 and 1"))
+}
@@ -0,0 +1,147 @@
+//! 共享消息文本
+//!
+//! 消息内容以引用计数的不可变字符串保存，对话历史、上下文和界面之间传递时只复制指针，
+//! 长会话中每条消息在内存里只保留一份
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// 引用计数的不可变文本，克隆开销为常数
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SharedText(Arc<str>);
+
+impl SharedText {
+    /// 两个值是否指向同一块内存
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(&a.0, &b.0)
+    }
+
+    /// 以 `&str` 借用
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for SharedText {
+    fn default() -> Self {
+        Self(Arc::from(""))
+    }
+}
+
+impl Deref for SharedText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for SharedText {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for SharedText {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SharedText {
+    fn from(text: String) -> Self {
+        Self(Arc::from(text))
+    }
+}
+
+impl From<&str> for SharedText {
+    fn from(text: &str) -> Self {
+        Self(Arc::from(text))
+    }
+}
+
+impl From<&String> for SharedText {
+    fn from(text: &String) -> Self {
+        Self(Arc::from(text.as_str()))
+    }
+}
+
+impl From<SharedText> for String {
+    fn from(text: SharedText) -> Self {
+        text.0.to_string()
+    }
+}
+
+impl PartialEq<str> for SharedText {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for SharedText {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl fmt::Debug for SharedText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for SharedText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl Serialize for SharedText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clone_shares_storage() {
+        let text = SharedText::from("fn main() {}".to_string());
+        let copy = text.clone();
+
+        assert!(SharedText::ptr_eq(&text, &copy));
+        assert!(!SharedText::ptr_eq(&text, &SharedText::from("fn main() {}")));
+        assert_eq!(copy, "fn main() {}");
+        assert_eq!(copy.lines().count(), 1);
+
+        let json = serde_json::to_string(&text).unwrap();
+        assert_eq!(json, "\"fn main() {}\"");
+        assert_eq!(serde_json::from_str::<SharedText>(&json).unwrap(), text);
+    }
+
+    #[test]
+    fn test_history_and_context_share_content() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut manager = crate::conversation::ConversationManager::with_storage_dir(temp_dir.path().to_path_buf()).unwrap();
+        manager.create_conversation(None).unwrap();
+
+        let content = SharedText::from("x".repeat(4096));
+        manager.add_message("user", content.clone(), None).unwrap();
+
+        let history = manager.get_conversation_messages();
+        let api_messages = manager.api_messages();
+        assert!(SharedText::ptr_eq(&history[0].content, &content));
+        assert!(SharedText::ptr_eq(&api_messages[0].content, &content));
+    }
+}
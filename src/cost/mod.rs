@@ -22,6 +22,15 @@ pub struct ModelPricing {
     pub max_context_length: u32,
 }
 
+impl ModelPricing {
+    /// 计算给定 token 数量的成本（美元）
+    pub fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        let input_cost = (input_tokens as f64 / 1000.0) * self.input_price_per_1k;
+        let output_cost = (output_tokens as f64 / 1000.0) * self.output_price_per_1k;
+        input_cost + output_cost
+    }
+}
+
 /// API调用记录
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiCallRecord {
@@ -107,6 +116,14 @@ pub struct CostTracker {
 }
 
 impl CostTracker {
+    /// 默认的账本目录
+    pub fn default_dir() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("claude-code")
+            .join("usage")
+    }
+
     /// 创建新的成本跟踪器
     pub fn new(storage_dir: PathBuf) -> Result<Self> {
         // 确保存储目录存在
@@ -128,49 +145,9 @@ impl CostTracker {
 
     /// 初始化默认的模型定价
     fn initialize_default_pricing(&mut self) {
-        // Claude 3.5 Sonnet 定价（2024年价格）
-        self.model_pricing.insert(
-            "claude-3-5-sonnet-20241022".to_string(),
-            ModelPricing {
-                model_name: "claude-3-5-sonnet-20241022".to_string(),
-                input_price_per_1k: 0.003,  // $3.00 per 1M tokens
-                output_price_per_1k: 0.015, // $15.00 per 1M tokens
-                max_context_length: 200000,
-            },
-        );
-
-        // Claude 3 Sonnet 定价
-        self.model_pricing.insert(
-            "claude-3-sonnet-20240229".to_string(),
-            ModelPricing {
-                model_name: "claude-3-sonnet-20240229".to_string(),
-                input_price_per_1k: 0.003,  // $3.00 per 1M tokens
-                output_price_per_1k: 0.015, // $15.00 per 1M tokens
-                max_context_length: 200000,
-            },
-        );
-
-        // Claude 3 Haiku 定价
-        self.model_pricing.insert(
-            "claude-3-haiku-20240307".to_string(),
-            ModelPricing {
-                model_name: "claude-3-haiku-20240307".to_string(),
-                input_price_per_1k: 0.00025, // $0.25 per 1M tokens
-                output_price_per_1k: 0.00125, // $1.25 per 1M tokens
-                max_context_length: 200000,
-            },
-        );
-
-        // Claude 3 Opus 定价
-        self.model_pricing.insert(
-            "claude-3-opus-20240229".to_string(),
-            ModelPricing {
-                model_name: "claude-3-opus-20240229".to_string(),
-                input_price_per_1k: 0.015,  // $15.00 per 1M tokens
-                output_price_per_1k: 0.075, // $75.00 per 1M tokens
-                max_context_length: 200000,
-            },
-        );
+        for pricing in default_model_pricing() {
+            self.model_pricing.insert(pricing.model_name.clone(), pricing);
+        }
     }
 
     /// 记录API调用
@@ -199,7 +176,33 @@ impl CostTracker {
             request_type: request_type.to_string(),
             conversation_id: conversation_id.map(|s| s.to_string()),
         };
+        self.store(record)?;
 
+        Ok(id)
+    }
+
+    /// 记录通过 Message Batches API 完成的调用，按批处理价格计费；没有定价的模型记为 0
+    pub fn record_batch_call(&mut self, model: &str, input_tokens: u32, output_tokens: u32) -> Result<String> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let cost = self
+            .model_pricing
+            .get(model)
+            .map_or(0.0, |pricing| pricing.cost(input_tokens, output_tokens) * crate::network::batch::BATCH_PRICE_FACTOR);
+        self.store(ApiCallRecord {
+            id: id.clone(),
+            timestamp: Utc::now(),
+            model: model.to_string(),
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+            cost,
+            request_type: "batch".to_string(),
+            conversation_id: None,
+        })?;
+        Ok(id)
+    }
+
+    fn store(&mut self, record: ApiCallRecord) -> Result<()> {
         // 添加到缓存
         self.call_cache.push(record.clone());
 
@@ -209,9 +212,7 @@ impl CostTracker {
         }
 
         // 保存单个记录到文件
-        self.save_call_record(&record)?;
-
-        Ok(id)
+        self.save_call_record(&record)
     }
 
     /// 计算API调用成本
@@ -219,10 +220,7 @@ impl CostTracker {
         let pricing = self.model_pricing.get(model)
             .ok_or_else(|| ClaudeError::General(format!("Unknown model: {}", model)))?;
 
-        let input_cost = (input_tokens as f64 / 1000.0) * pricing.input_price_per_1k;
-        let output_cost = (output_tokens as f64 / 1000.0) * pricing.output_price_per_1k;
-
-        Ok(input_cost + output_cost)
+        Ok(pricing.cost(input_tokens, output_tokens))
     }
 
     /// 获取使用统计
@@ -385,3 +383,42 @@ impl CostTracker {
         self.model_pricing.insert(pricing.model_name.clone(), pricing);
     }
 }
+
+/// 内置的默认模型定价表
+pub fn default_model_pricing() -> Vec<ModelPricing> {
+    vec![
+        // Claude 3.5 Sonnet 定价（2024年价格）
+        ModelPricing {
+            model_name: "claude-3-5-sonnet-20241022".to_string(),
+            input_price_per_1k: 0.003,  // $3.00 per 1M tokens
+            output_price_per_1k: 0.015, // $15.00 per 1M tokens
+            max_context_length: 200000,
+        },
+        // Claude 3 Sonnet 定价
+        ModelPricing {
+            model_name: "claude-3-sonnet-20240229".to_string(),
+            input_price_per_1k: 0.003,  // $3.00 per 1M tokens
+            output_price_per_1k: 0.015, // $15.00 per 1M tokens
+            max_context_length: 200000,
+        },
+        // Claude 3 Haiku 定价
+        ModelPricing {
+            model_name: "claude-3-haiku-20240307".to_string(),
+            input_price_per_1k: 0.00025, // $0.25 per 1M tokens
+            output_price_per_1k: 0.00125, // $1.25 per 1M tokens
+            max_context_length: 200000,
+        },
+        // Claude 3 Opus 定价
+        ModelPricing {
+            model_name: "claude-3-opus-20240229".to_string(),
+            input_price_per_1k: 0.015,  // $15.00 per 1M tokens
+            output_price_per_1k: 0.075, // $75.00 per 1M tokens
+            max_context_length: 200000,
+        },
+    ]
+}
+
+/// 按模型名称查找默认定价
+pub fn lookup_default_pricing(model: &str) -> Option<ModelPricing> {
+    default_model_pricing().into_iter().find(|p| p.model_name == model)
+}
@@ -1,3 +1,5 @@
+pub mod users;
+
 use crate::error::{ClaudeError, Result};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
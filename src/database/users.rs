@@ -0,0 +1,274 @@
+//! Web 用户
+//!
+//! 多人共用一个 Web 服务时的用户表：每个用户有自己的 API 令牌、权限档位和花费预算。
+//! 令牌形如 `crw_<用户 ID>_<随机串>`，只保存 Argon2 哈希；数据以 JSON 保存在数据目录下
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+
+/// 令牌前缀
+const TOKEN_PREFIX: &str = "crw_";
+
+/// 令牌中随机部分的字节数
+const TOKEN_SECRET_BYTES: usize = 32;
+
+/// 用户能做的事
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionProfile {
+    /// 只能查看自己的会话
+    ReadOnly,
+    /// 可以对话
+    #[default]
+    Standard,
+    /// 还可以修改服务器配置、查看全局统计
+    Admin,
+}
+
+impl PermissionProfile {
+    pub fn can_chat(self) -> bool {
+        self != Self::ReadOnly
+    }
+
+    pub fn can_manage(self) -> bool {
+        self == Self::Admin
+    }
+}
+
+impl std::fmt::Display for PermissionProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReadOnly => write!(f, "read_only"),
+            Self::Standard => write!(f, "standard"),
+            Self::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl std::str::FromStr for PermissionProfile {
+    type Err = ClaudeError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "read_only" | "readonly" => Ok(Self::ReadOnly),
+            "standard" => Ok(Self::Standard),
+            "admin" => Ok(Self::Admin),
+            other => Err(ClaudeError::validation_error(
+                "profile",
+                format!("Unknown permission profile '{}', expected read-only, standard or admin", other),
+            )),
+        }
+    }
+}
+
+/// 一个 Web 用户
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebUser {
+    pub id: String,
+    pub name: String,
+    /// 令牌的 Argon2 哈希；由认证代理识别的用户没有令牌
+    #[serde(default)]
+    pub token_hash: Option<String>,
+    #[serde(default)]
+    pub profile: PermissionProfile,
+    /// 花费上限（美元），不设置则不限
+    #[serde(default)]
+    pub budget_usd: Option<f64>,
+    /// 已花费（美元）
+    #[serde(default)]
+    pub spent_usd: f64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebUser {
+    /// 剩余预算，不限时为 `None`
+    pub fn remaining_budget(&self) -> Option<f64> {
+        self.budget_usd.map(|budget| (budget - self.spent_usd).max(0.0))
+    }
+
+    pub fn over_budget(&self) -> bool {
+        self.remaining_budget().is_some_and(|remaining| remaining <= 0.0)
+    }
+}
+
+/// 用户表
+#[derive(Debug, Clone)]
+pub struct UserStore {
+    path: PathBuf,
+    users: Vec<WebUser>,
+}
+
+impl UserStore {
+    /// 默认的用户数据文件
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("claude-code")
+            .join("web-users.json")
+    }
+
+    /// 读取用户表，文件不存在时为空表
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let users = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| ClaudeError::config_error(format!("Invalid user file {}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(ClaudeError::fs_error(format!("Failed to read {}: {}", path.display(), e))),
+        };
+        Ok(Self { path, users })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.users)?)?;
+        Ok(())
+    }
+
+    pub fn list(&self) -> &[WebUser] {
+        &self.users
+    }
+
+    pub fn get(&self, id: &str) -> Option<&WebUser> {
+        self.users.iter().find(|user| user.id == id)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&WebUser> {
+        self.users.iter().find(|user| user.name == name)
+    }
+
+    /// 添加用户并签发令牌；令牌只在此时返回一次
+    pub fn add(&mut self, name: &str, profile: PermissionProfile, budget_usd: Option<f64>) -> Result<(WebUser, String)> {
+        let mut user = self.new_user(name, profile, budget_usd)?;
+        let token = issue_token(&mut user)?;
+        self.users.push(user.clone());
+        Ok((user, token))
+    }
+
+    /// 返回认证代理传来的用户，首次出现时按给定档位和预算创建
+    pub fn provision(&mut self, name: &str, profile: PermissionProfile, budget_usd: Option<f64>) -> Result<&WebUser> {
+        match self.users.iter().position(|user| user.name == name) {
+            Some(index) => Ok(&self.users[index]),
+            None => {
+                let user = self.new_user(name, profile, budget_usd)?;
+                self.users.push(user);
+                Ok(self.users.last().expect("user was just added"))
+            }
+        }
+    }
+
+    /// 为已有用户重新签发令牌，旧令牌随即失效
+    pub fn rotate_token(&mut self, name: &str) -> Result<String> {
+        let user = self
+            .users
+            .iter_mut()
+            .find(|user| user.name == name)
+            .ok_or_else(|| ClaudeError::validation_error("name", format!("No web user named '{}'", name)))?;
+        issue_token(user)
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.users.len();
+        self.users.retain(|user| user.name != name);
+        self.users.len() != before
+    }
+
+    /// 校验令牌，返回其所属用户
+    pub fn verify_token(&self, token: &str) -> Option<&WebUser> {
+        let (id, _) = token.strip_prefix(TOKEN_PREFIX)?.split_once('_')?;
+        let user = self.get(id)?;
+        let hash = PasswordHash::new(user.token_hash.as_deref()?).ok()?;
+        Argon2::default().verify_password(token.as_bytes(), &hash).ok()?;
+        Some(user)
+    }
+
+    /// 记入一次调用的花费
+    pub fn record_spend(&mut self, id: &str, usd: f64) -> Result<()> {
+        let user = self
+            .users
+            .iter_mut()
+            .find(|user| user.id == id)
+            .ok_or_else(|| ClaudeError::validation_error("user", format!("Unknown web user '{}'", id)))?;
+        user.spent_usd += usd;
+        Ok(())
+    }
+
+    fn new_user(&self, name: &str, profile: PermissionProfile, budget_usd: Option<f64>) -> Result<WebUser> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ClaudeError::validation_error("name", "User name must not be empty"));
+        }
+        if self.find(name).is_some() {
+            return Err(ClaudeError::validation_error("name", format!("Web user '{}' already exists", name)));
+        }
+        Ok(WebUser {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            name: name.to_string(),
+            token_hash: None,
+            profile,
+            budget_usd,
+            spent_usd: 0.0,
+            created_at: Utc::now(),
+        })
+    }
+}
+
+fn issue_token(user: &mut WebUser) -> Result<String> {
+    let mut secret = [0u8; TOKEN_SECRET_BYTES];
+    OsRng.fill_bytes(&mut secret);
+    let token = format!("{}{}_{}", TOKEN_PREFIX, user.id, hex::encode(secret));
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(token.as_bytes(), &salt)
+        .map_err(|e| ClaudeError::General(format!("Failed to hash token: {}", e)))?;
+    user.token_hash = Some(hash.to_string());
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_budgets_and_persistence() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("users.json");
+        let mut store = UserStore::load(&path).unwrap();
+
+        let (alice, token) = store.add("alice", PermissionProfile::Standard, Some(1.0)).unwrap();
+        assert!(store.add("alice", PermissionProfile::Admin, None).is_err());
+        assert_eq!(store.verify_token(&token).map(|user| user.id.as_str()), Some(alice.id.as_str()));
+        assert!(store.verify_token(&format!("{}x", token)).is_none());
+        assert!(store.verify_token("crw_unknown_secret").is_none());
+
+        store.record_spend(&alice.id, 0.75).unwrap();
+        store.provision("bob@example.com", PermissionProfile::ReadOnly, None).unwrap();
+        store.save().unwrap();
+
+        let mut store = UserStore::load(&path).unwrap();
+        let alice = store.find("alice").unwrap();
+        assert_eq!(alice.remaining_budget(), Some(0.25));
+        assert!(!alice.over_budget());
+        assert!(store.verify_token(&token).is_some());
+        assert!(store.find("bob@example.com").unwrap().token_hash.is_none());
+
+        let rotated = store.rotate_token("alice").unwrap();
+        assert!(store.verify_token(&token).is_none());
+        assert!(store.verify_token(&rotated).is_some());
+        assert!(store.remove("alice"));
+        assert!(store.verify_token(&rotated).is_none());
+    }
+}
@@ -4,6 +4,24 @@
 
 use thiserror::Error;
 use std::error::Error;
+use std::time::Duration;
+
+/// 进程退出码：通用错误
+pub const EXIT_GENERAL: i32 = 1;
+/// 进程退出码：参数或输入校验失败
+pub const EXIT_VALIDATION: i32 = 2;
+/// 进程退出码：认证失败
+pub const EXIT_AUTH: i32 = 3;
+/// 进程退出码：被限流
+pub const EXIT_RATE_LIMIT: i32 = 4;
+/// 进程退出码：网络或 API 错误
+pub const EXIT_NETWORK: i32 = 5;
+/// 进程退出码：工具执行失败
+pub const EXIT_TOOL_FAILURE: i32 = 6;
+/// 进程退出码：被权限或沙箱策略阻止
+pub const EXIT_DENIED: i32 = 7;
+/// 进程退出码：配置错误（同 sysexits 的 EX_CONFIG）
+pub const EXIT_CONFIG: i32 = 78;
 
 /// Claude Code 的主要错误类型
 #[derive(Error, Debug)]
@@ -16,9 +34,21 @@ pub enum ClaudeError {
     #[error("File system error: {0}")]
     Io(#[from] std::io::Error),
 
-    /// 网络错误
-    #[error("Network error: {0}")]
-    Network(#[from] reqwest::Error),
+    /// 网络错误（连接失败、超时、传输中断）
+    #[error("Network error: {message}")]
+    Network { message: String, retryable: bool },
+
+    /// 认证失败（API key 缺失、无效或无权访问）
+    #[error("Authentication failed: {message}")]
+    Auth { message: String, status: Option<u16> },
+
+    /// 请求被限流
+    #[error("Rate limited: {message}")]
+    RateLimit { message: String, retry_after: Option<Duration> },
+
+    /// API 返回的错误状态
+    #[error("API error ({status}): {message}")]
+    Api { status: u16, message: String },
 
     /// JSON 序列化/反序列化错误
     #[error("JSON error: {0}")]
@@ -36,10 +66,18 @@ pub enum ClaudeError {
     #[error("Permission denied: {operation}")]
     Permission { operation: String },
 
+    /// 被沙箱或安全策略阻止
+    #[error("Blocked by sandbox: {message}")]
+    Sandbox { message: String },
+
     /// 验证错误
     #[error("Validation error: {field} - {message}")]
     Validation { field: String, message: String },
 
+    /// 工具执行失败
+    #[error("Tool '{tool}' failed: {message}")]
+    ToolFailure { tool: String, message: String },
+
     /// 通用错误
     #[error("General error: {0}")]
     General(String),
@@ -52,6 +90,19 @@ pub enum ClaudeError {
 /// 结果类型别名
 pub type Result<T> = std::result::Result<T, ClaudeError>;
 
+#[cfg(feature = "native")]
+impl From<reqwest::Error> for ClaudeError {
+    fn from(error: reqwest::Error) -> Self {
+        if let Some(status) = error.status() {
+            return Self::from_status(status.as_u16(), &error.to_string(), None);
+        }
+        Self::Network {
+            retryable: error.is_timeout() || error.is_connect() || error.is_request() || error.is_body(),
+            message: error.to_string(),
+        }
+    }
+}
+
 /// 错误处理工具函数
 impl ClaudeError {
     /// 创建配置错误
@@ -59,9 +110,22 @@ impl ClaudeError {
         Self::General(format!("Configuration error: {}", msg.into()))
     }
 
-    /// 创建网络错误
+    /// 创建网络错误，按消息内容判断是否可重试
     pub fn network_error(msg: impl Into<String>) -> Self {
-        Self::General(format!("Network error: {}", msg.into()))
+        let message = msg.into();
+        let lower = message.to_lowercase();
+        let retryable = ["timed out", "timeout", "connection", "overloaded", "rate limit", "429", "500", "502", "503", "504", "529"]
+            .iter()
+            .any(|needle| lower.contains(needle));
+        Self::Network { message, retryable }
+    }
+
+    /// 创建认证错误
+    pub fn auth_error(msg: impl Into<String>) -> Self {
+        Self::Auth {
+            message: msg.into(),
+            status: None,
+        }
     }
 
     /// 创建文件系统错误
@@ -83,6 +147,13 @@ impl ClaudeError {
         }
     }
 
+    /// 创建沙箱拦截错误
+    pub fn sandbox_error(msg: impl Into<String>) -> Self {
+        Self::Sandbox {
+            message: msg.into(),
+        }
+    }
+
     /// 创建验证错误
     pub fn validation_error(field: impl Into<String>, message: impl Into<String>) -> Self {
         Self::Validation {
@@ -91,12 +162,141 @@ impl ClaudeError {
         }
     }
 
+    /// 创建工具执行失败错误
+    pub fn tool_failure(tool: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::ToolFailure {
+            tool: tool.into(),
+            message: message.into(),
+        }
+    }
+
     /// 创建未实现错误
     pub fn not_implemented(feature: impl Into<String>) -> Self {
         Self::NotImplemented {
             feature: feature.into(),
         }
     }
+
+    /// 按 HTTP 状态码和响应体创建错误，响应体为 API 错误 JSON 时提取其中的消息
+    pub fn from_status(status: u16, body: &str, retry_after: Option<Duration>) -> Self {
+        let parsed: Option<serde_json::Value> = serde_json::from_str(body).ok();
+        let api_message = parsed
+            .as_ref()
+            .and_then(|v| v["error"]["message"].as_str().or_else(|| v["message"].as_str()))
+            .map(str::to_string);
+        let message = api_message.unwrap_or_else(|| {
+            let body = body.trim();
+            if body.is_empty() {
+                format!("HTTP {}", status)
+            } else {
+                body.chars().take(500).collect()
+            }
+        });
+
+        match status {
+            401 | 403 => Self::Auth { message, status: Some(status) },
+            429 => Self::RateLimit { message, retry_after },
+            _ => Self::Api { status, message },
+        }
+    }
+
+    /// 按 API 错误类型（如流中的 `error` 事件）创建错误
+    pub fn from_api_error(error_type: &str, message: impl Into<String>) -> Self {
+        let message = message.into();
+        match error_type {
+            "authentication_error" => Self::Auth { message, status: Some(401) },
+            "permission_error" => Self::Auth { message, status: Some(403) },
+            "rate_limit_error" => Self::RateLimit { message, retry_after: None },
+            "overloaded_error" => Self::Api { status: 529, message },
+            "api_error" => Self::Api { status: 500, message },
+            "not_found_error" => Self::Api { status: 404, message },
+            "request_too_large" => Self::Api { status: 413, message },
+            "invalid_request_error" => Self::Api { status: 400, message },
+            _ => Self::network_error(message),
+        }
+    }
+
+    /// 是否为可重试的瞬时错误（网络中断、超时、限流、API 5xx）
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Network { retryable, .. } => *retryable,
+            Self::RateLimit { .. } => true,
+            Self::Api { status, .. } => *status >= 500,
+            Self::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::BrokenPipe
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::UnexpectedEof
+            ),
+            _ => false,
+        }
+    }
+
+    /// 关联的 HTTP 状态码
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Auth { status, .. } => *status,
+            Self::RateLimit { .. } => Some(429),
+            Self::Api { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// 服务端要求的重试等待时间
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimit { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// 给用户的处理建议
+    pub fn hint(&self) -> Option<&'static str> {
+        let hint = match self {
+            Self::Auth { status: Some(403), .. } => "Your API key does not have access to this resource; check the model name and your plan",
+            Self::Auth { .. } => "Set ANTHROPIC_API_KEY or run `claude config set api.anthropic_api_key <key>`",
+            Self::RateLimit { .. } => "Wait a moment before retrying, or lower performance.max_concurrent_requests",
+            Self::Api { status: 404, .. } => "Check the model name and api.base_url",
+            Self::Api { status: 413, .. } => "The request is too large; compact the conversation or attach fewer files",
+            Self::Api { status, .. } if *status >= 500 => "The API is temporarily unavailable; try again shortly",
+            Self::Api { .. } => "The API rejected the request; check the model name and request parameters",
+            Self::Network { .. } => "Check your network connection, proxy settings and api.base_url",
+            Self::Config(_) => "Run `claude config list` to find the config file and fix the reported value",
+            Self::General(msg) if msg.starts_with("Configuration error:") => {
+                "Run `claude config list` to find the config file and fix the reported value"
+            }
+            Self::Permission { .. } => "Grant the permission in the config under permissions, or approve the action when prompted",
+            Self::Sandbox { .. } => "Adjust permissions.bash rules or resource limits in the config if this action is expected",
+            Self::ToolFailure { .. } => "Check the tool arguments, or run the equivalent command manually to see the full error",
+            _ => return None,
+        };
+        Some(hint)
+    }
+
+    /// 命令行以该错误退出时使用的退出码
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Validation { .. } => EXIT_VALIDATION,
+            Self::Auth { .. } => EXIT_AUTH,
+            Self::RateLimit { .. } => EXIT_RATE_LIMIT,
+            Self::Network { .. } | Self::Api { .. } => EXIT_NETWORK,
+            Self::ToolFailure { .. } => EXIT_TOOL_FAILURE,
+            Self::Permission { .. } | Self::Sandbox { .. } => EXIT_DENIED,
+            Self::Config(_) => EXIT_CONFIG,
+            Self::General(msg) if msg.starts_with("Configuration error:") => EXIT_CONFIG,
+            _ => EXIT_GENERAL,
+        }
+    }
+
+    /// 终端中展示的错误信息，附带处理建议
+    pub fn render(&self) -> String {
+        match self.hint() {
+            Some(hint) => format!("❌ {}\n💡 {}", self, hint),
+            None => format!("❌ {}", self),
+        }
+    }
 }
 
 impl Clone for ClaudeError {
@@ -104,23 +304,29 @@ impl Clone for ClaudeError {
         match self {
             Self::Config(_) => Self::General("Configuration error".to_string()),
             Self::Io(_) => Self::General("IO error".to_string()),
-            Self::Network(_) => Self::General("Network error".to_string()),
+            Self::Network { message, retryable } => Self::Network { message: message.clone(), retryable: *retryable },
+            Self::Auth { message, status } => Self::Auth { message: message.clone(), status: *status },
+            Self::RateLimit { message, retry_after } => Self::RateLimit { message: message.clone(), retry_after: *retry_after },
+            Self::Api { status, message } => Self::Api { status: *status, message: message.clone() },
             Self::Json(_) => Self::General("JSON error".to_string()),
             Self::Yaml(_) => Self::General("YAML error".to_string()),
             Self::General(msg) => Self::General(msg.clone()),
             Self::Permission { operation } => Self::Permission { operation: operation.clone() },
+            Self::Sandbox { message } => Self::Sandbox { message: message.clone() },
             Self::Validation { field, message } => Self::Validation {
                 field: field.clone(),
                 message: message.clone()
             },
+            Self::ToolFailure { tool, message } => Self::ToolFailure { tool: tool.clone(), message: message.clone() },
             Self::NotImplemented { feature } => Self::NotImplemented { feature: feature.clone() },
             Self::McpServer { message } => Self::McpServer { message: message.clone() },
         }
     }
 }
 
-/// 初始化日志系统
-pub fn init_logging(debug: bool) -> Result<()> {
+/// 初始化日志系统；`to_stderr` 时日志写到标准错误，标准输出留给协议消息
+#[cfg(feature = "native")]
+pub fn init_logging(debug: bool, to_stderr: bool) -> Result<()> {
     use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, fmt, EnvFilter};
     use std::io;
 
@@ -141,15 +347,23 @@ pub fn init_logging(debug: bool) -> Result<()> {
         .with_line_number(debug)
         .compact();
 
-    tracing_subscriber::registry()
-        .with(env_filter)
-        .with(fmt_layer)
-        .init();
+    if to_stderr {
+        tracing_subscriber::registry()
+            .with(env_filter)
+            .with(fmt_layer.with_writer(io::stderr))
+            .init();
+    } else {
+        tracing_subscriber::registry()
+            .with(env_filter)
+            .with(fmt_layer)
+            .init();
+    }
 
     Ok(())
 }
 
 /// 初始化带文件输出的日志系统
+#[cfg(feature = "native")]
 pub fn init_logging_with_file(debug: bool, log_file: Option<&str>) -> Result<()> {
     use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, fmt, EnvFilter};
     use tracing_appender::{rolling, non_blocking};
@@ -212,6 +426,9 @@ pub fn init_logging_with_file(debug: bool, log_file: Option<&str>) -> Result<()>
 /// 错误报告工具
 pub fn report_error(error: &ClaudeError) {
     tracing::error!("Claude Code Error: {}", error);
+    if let Some(hint) = error.hint() {
+        tracing::info!("Hint: {}", hint);
+    }
     
     // 在调试模式下显示错误链
     let mut source = error.source();
@@ -233,4 +450,38 @@ mod tests {
         let error = ClaudeError::validation_error("field1", "invalid value");
         assert!(error.to_string().contains("Validation error"));
     }
+
+    #[test]
+    fn test_transient_errors() {
+        assert!(ClaudeError::network_error("HTTP 503 Service Unavailable: overloaded").is_retryable());
+        assert!(ClaudeError::network_error("connection reset by peer").is_retryable());
+        assert!(!ClaudeError::network_error("HTTP 401 Unauthorized").is_retryable());
+        assert!(!ClaudeError::validation_error("path", "missing").is_retryable());
+    }
+
+    #[test]
+    fn test_status_taxonomy() {
+        let body = r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#;
+        let auth = ClaudeError::from_status(401, body, None);
+        assert!(matches!(&auth, ClaudeError::Auth { message, .. } if message == "invalid x-api-key"));
+        assert!(!auth.is_retryable());
+        assert_eq!(auth.exit_code(), EXIT_AUTH);
+        assert!(auth.render().contains("ANTHROPIC_API_KEY"));
+
+        let limited = ClaudeError::from_status(429, "slow down", Some(Duration::from_secs(7)));
+        assert!(limited.is_retryable());
+        assert_eq!(limited.retry_after(), Some(Duration::from_secs(7)));
+        assert_eq!(limited.status(), Some(429));
+
+        let overloaded = ClaudeError::from_api_error("overloaded_error", "Overloaded");
+        assert!(overloaded.is_retryable());
+        assert_eq!(overloaded.exit_code(), EXIT_NETWORK);
+
+        let bad_request = ClaudeError::from_status(400, "", None);
+        assert!(!bad_request.is_retryable());
+        assert_eq!(bad_request.to_string(), "API error (400): HTTP 400");
+
+        assert_eq!(ClaudeError::sandbox_error("escape").exit_code(), EXIT_DENIED);
+        assert_eq!(ClaudeError::validation_error("path", "missing").exit_code(), EXIT_VALIDATION);
+    }
 }
@@ -0,0 +1,183 @@
+//! .gitignore 规则
+//!
+//! 支持常用语法：`#` 注释、`!` 取反、以 `/` 结尾只匹配目录、模式中间或开头含 `/` 时相对
+//! 所在目录锚定，以及 `*`、`?`、`**` 通配。子目录中的 .gitignore 叠加在上级规则之后，
+//! 后出现的规则优先
+
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::{ClaudeError, Result};
+
+/// 把 glob 模式转换为匹配 `/` 分隔的相对路径的正则：`*` 和 `?` 不跨目录，`**` 跨任意层目录
+pub fn glob_regex(pattern: &str) -> Result<Regex> {
+    let mut source = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    source.push_str("(?:.*/)?");
+                } else {
+                    source.push_str(".*");
+                }
+            }
+            '*' => source.push_str("[^/]*"),
+            '?' => source.push_str("[^/]"),
+            '[' => {
+                // 字符类原样保留，`!` 取反写作 `^`
+                let mut class = String::from("[");
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    class.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        class.push('\\');
+                    }
+                    class.push(c);
+                }
+                source.push_str(&class);
+                source.push(']');
+            }
+            '{' => {
+                // `{a,b}` 展开为分组
+                let mut alternatives = String::new();
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                    alternatives.push(c);
+                }
+                let escaped: Vec<String> = alternatives.split(',').map(regex::escape).collect();
+                source.push_str(&format!("(?:{})", escaped.join("|")));
+            }
+            _ => source.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    source.push('$');
+    Regex::new(&source).map_err(|e| ClaudeError::validation_error("glob", format!("Invalid pattern '{}': {}", pattern, e)))
+}
+
+/// 一条忽略规则
+#[derive(Debug)]
+struct IgnoreRule {
+    /// 规则所在的目录
+    base: PathBuf,
+    regex: Regex,
+    /// 是否匹配相对 `base` 的完整路径（否则只匹配文件名）
+    anchored: bool,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRule {
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        let regex = glob_regex(pattern).ok()?;
+        Some(Self { base: base.to_path_buf(), regex, anchored, negated, dir_only })
+    }
+
+    /// 规则是否适用于该路径，不适用时返回 `None`
+    fn matches(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        if self.dir_only && !is_dir {
+            return None;
+        }
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let matched = if self.anchored {
+            self.regex.is_match(&relative.to_string_lossy().replace('\\', "/"))
+        } else {
+            relative.file_name().is_some_and(|name| self.regex.is_match(&name.to_string_lossy()))
+        };
+        matched.then_some(!self.negated)
+    }
+}
+
+/// 从根目录到当前目录累积的忽略规则，克隆开销很小
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    rules: Vec<Arc<IgnoreRule>>,
+}
+
+impl IgnoreRules {
+    /// 解析 `base` 目录中一个忽略文件的内容
+    pub fn parse(base: &Path, content: &str) -> Self {
+        Self::default().extend(base, content)
+    }
+
+    /// 加上 `dir` 中 .gitignore 的规则；没有该文件时原样返回
+    pub fn with_dir(&self, dir: &Path) -> Self {
+        match std::fs::read_to_string(dir.join(".gitignore")) {
+            Ok(content) => self.clone().extend(dir, &content),
+            Err(_) => self.clone(),
+        }
+    }
+
+    /// `dir` 上级目录中的规则：从所在 git 仓库的根目录到 `dir` 的父目录。不在仓库中时为空
+    pub fn for_ancestors(dir: &Path) -> Self {
+        let Some(repo_root) = dir.ancestors().skip(1).find(|ancestor| ancestor.join(".git").exists()) else {
+            return Self::default();
+        };
+        let mut chain: Vec<&Path> = dir.ancestors().skip(1).take_while(|ancestor| *ancestor != repo_root).collect();
+        chain.push(repo_root);
+        chain.iter().rev().fold(Self::default(), |rules, ancestor| rules.with_dir(ancestor))
+    }
+
+    fn extend(mut self, base: &Path, content: &str) -> Self {
+        self.rules.extend(content.lines().filter_map(|line| IgnoreRule::parse(base, line)).map(Arc::new));
+        self
+    }
+
+    /// 路径是否被忽略，以最后一条匹配的规则为准
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules.iter().rev().find_map(|rule| rule.matches(path, is_dir)).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitignore_rules() {
+        let root = Path::new("/repo");
+        let rules = IgnoreRules::parse(root, "# build output\ntarget/\n*.log\n!keep.log\n/docs/*.html\n**/generated/**\n");
+        assert!(rules.is_ignored(&root.join("target"), true));
+        assert!(!rules.is_ignored(&root.join("target"), false));
+        assert!(rules.is_ignored(&root.join("src/debug.log"), false));
+        assert!(!rules.is_ignored(&root.join("src/keep.log"), false));
+        assert!(rules.is_ignored(&root.join("docs/index.html"), false));
+        assert!(!rules.is_ignored(&root.join("src/docs/index.html"), false));
+        assert!(rules.is_ignored(&root.join("src/generated/api.rs"), false));
+
+        // 子目录的规则只作用于子目录，并可以覆盖上级规则
+        let nested = rules.clone().extend(&root.join("app"), "!debug.log\n");
+        assert!(!nested.is_ignored(&root.join("app/debug.log"), false));
+        assert!(nested.is_ignored(&root.join("lib/debug.log"), false));
+
+        assert!(glob_regex("src/**/*.{rs,toml}").unwrap().is_match("src/a/b/lib.rs"));
+        assert!(glob_regex("[!a]?.txt").unwrap().is_match("b1.txt"));
+    }
+}
@@ -2,6 +2,15 @@
 //! 
 //! 提供文件读写、目录管理、路径处理等核心文件操作功能
 
+pub mod ignore;
+pub mod notebook;
+pub mod replace;
+pub mod scan;
+pub mod scratch;
+pub mod search;
+pub mod stream_write;
+pub mod undo;
+
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
@@ -500,6 +509,57 @@ impl FileSystemManager {
         }
     }
 
+    /// 路径是否位于某个工作目录之内，符号链接和 `..` 解析后再比较
+    pub fn is_within_roots(&self, path: &Path) -> bool {
+        let Ok(path) = std::fs::canonicalize(path) else {
+            return false;
+        };
+        self.working_dirs
+            .iter()
+            .filter_map(|dir| std::fs::canonicalize(dir).ok())
+            .any(|dir| path.starts_with(dir))
+    }
+
+    /// 查找 `base` 下相对路径匹配通配符的文件（遵守 .gitignore，跳过隐藏文件），
+    /// 按修改时间从新到旧排序。`base` 必须位于某个工作目录之内，指向工作目录之外的符号链接被跳过
+    pub async fn glob(&self, base: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+        let base = self.resolve_path(base)?;
+        if !self.is_within_roots(&base) {
+            return Err(ClaudeError::sandbox_error(format!("{} is outside the allowed directories", base.display())));
+        }
+        let pattern = pattern.trim_start_matches("./").to_string();
+        let regex = ignore::glob_regex(&pattern)?;
+        // 从模式中不含通配符的目录前缀开始遍历
+        let parts: Vec<&str> = pattern.split('/').collect();
+        let prefix: PathBuf = parts[..parts.len() - 1]
+            .iter()
+            .take_while(|part| !part.contains(['*', '?', '[', '{']))
+            .collect();
+        let start = base.join(prefix);
+        if !start.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let roots = self.working_dirs.clone();
+        tokio::task::spawn_blocking(move || {
+            let sandbox = FileSystemManager::new(roots);
+            let options = scan::ScanOptions { respect_gitignore: true, ..scan::ScanOptions::default() };
+            let filter = |path: &Path| {
+                let relative = path.strip_prefix(&base).unwrap_or(path).to_string_lossy().replace('\\', "/");
+                regex.is_match(&relative) && (!path.is_symlink() || sandbox.is_within_roots(path))
+            };
+            let mut files: Vec<(Option<std::time::SystemTime>, PathBuf)> = scan::ParallelScanner::new(options)?
+                .discover(&start, &filter, None)?
+                .into_iter()
+                .map(|path| (std::fs::metadata(&path).and_then(|m| m.modified()).ok(), path))
+                .collect();
+            files.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+            Ok(files.into_iter().map(|(_, path)| path).collect())
+        })
+        .await
+        .map_err(|e| ClaudeError::General(format!("Glob task failed: {}", e)))?
+    }
+
     /// 搜索文件
     pub async fn search_files(&self, pattern: &str, extensions: Option<&[&str]>) -> Result<Vec<PathBuf>> {
         let mut results = Vec::new();
@@ -516,45 +576,34 @@ impl FileSystemManager {
 
     /// 在指定目录中搜索文件
     async fn search_in_directory(&self, dir: &Path, pattern: &str, extensions: Option<&[&str]>) -> Result<Vec<PathBuf>> {
-        let mut results = Vec::new();
-        
         if !dir.exists() || !dir.is_dir() {
-            return Ok(results);
-        }
-
-        let mut stack = vec![dir.to_path_buf()];
-        
-        while let Some(current_dir) = stack.pop() {
-            let mut entries = fs::read_dir(&current_dir).await?;
-            
-            while let Some(entry) = entries.next_entry().await? {
-                let path = entry.path();
-                
-                if path.is_dir() {
-                    stack.push(path);
-                } else if path.is_file() {
-                    let file_name = path.file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("");
-                    
-                    // 检查文件名是否匹配模式
-                    if file_name.contains(pattern) {
-                        // 检查扩展名
-                        if let Some(exts) = extensions {
-                            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                                if exts.contains(&ext) {
-                                    results.push(path);
-                                }
-                            }
-                        } else {
-                            results.push(path);
-                        }
-                    }
-                }
-            }
-        }
-        
-        Ok(results)
+            return Ok(Vec::new());
+        }
+
+        let dir = dir.to_path_buf();
+        let pattern = pattern.to_string();
+        let extensions: Option<Vec<String>> = extensions.map(|exts| exts.iter().map(|e| e.to_string()).collect());
+
+        tokio::task::spawn_blocking(move || {
+            let options = scan::ScanOptions {
+                include_hidden: true,
+                skip_dirs: Vec::new(),
+                ..scan::ScanOptions::default()
+            };
+            let filter = |path: &Path| {
+                let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                // 检查文件名是否匹配模式和扩展名
+                file_name.contains(&pattern)
+                    && extensions.as_ref().is_none_or(|exts| {
+                        path.extension()
+                            .and_then(|e| e.to_str())
+                            .is_some_and(|ext| exts.iter().any(|e| e == ext))
+                    })
+            };
+            scan::ParallelScanner::new(options)?.discover(&dir, &filter, None)
+        })
+        .await
+        .map_err(|e| ClaudeError::General(format!("File search task failed: {}", e)))?
     }
 }
 
@@ -567,3 +616,12 @@ pub struct FileMetadata {
     pub modified: Option<std::time::SystemTime>,
     pub created: Option<std::time::SystemTime>,
 }
+
+/// `path` 从 `before` 改为 `after` 的统一差异，带三行上下文
+pub fn unified_diff(path: &str, before: &str, after: &str) -> String {
+    similar::TextDiff::from_lines(before, after)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
@@ -0,0 +1,281 @@
+//! Jupyter 笔记本
+//!
+//! 按单元格读取和修改 `.ipynb` 文件。笔记本保存为原始 JSON，未涉及的单元格、输出和元数据原样保留；
+//! 写回时采用 Jupyter 自身的格式（单空格缩进、键排序），避免无关的格式差异
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+
+use crate::error::{ClaudeError, Result};
+
+/// 单元格类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CellType {
+    Code,
+    Markdown,
+    Raw,
+}
+
+impl FromStr for CellType {
+    type Err = ClaudeError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "code" => Ok(Self::Code),
+            "markdown" => Ok(Self::Markdown),
+            "raw" => Ok(Self::Raw),
+            other => Err(ClaudeError::validation_error(
+                "cell_type",
+                format!("Unknown cell type '{}', expected code, markdown or raw", other),
+            )),
+        }
+    }
+}
+
+impl CellType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Code => "code",
+            Self::Markdown => "markdown",
+            Self::Raw => "raw",
+        }
+    }
+}
+
+/// 单元格的可读视图
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CellView {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub cell_type: String,
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_count: Option<u64>,
+    /// 输出的文本部分（stream、text/plain 结果和错误信息）
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+}
+
+/// 多行文本字段：Jupyter 既可能存为字符串，也可能存为行数组
+fn join_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// 按 Jupyter 的惯例拆成保留换行符的行数组
+fn split_lines(text: &str) -> Value {
+    Value::Array(text.split_inclusive('\n').map(|line| Value::String(line.to_string())).collect())
+}
+
+fn output_text(output: &Value) -> Option<String> {
+    match output.get("output_type")?.as_str()? {
+        "stream" => Some(join_text(output.get("text")?)),
+        "execute_result" | "display_data" => match output.pointer("/data/text~1plain") {
+            Some(text) => Some(join_text(text)),
+            None => Some(format!("<{}>", output.get("data")?.as_object()?.keys().cloned().collect::<Vec<_>>().join(", "))),
+        },
+        "error" => Some(format!(
+            "{}: {}",
+            output.get("ename").and_then(Value::as_str).unwrap_or_default(),
+            output.get("evalue").and_then(Value::as_str).unwrap_or_default()
+        )),
+        _ => None,
+    }
+}
+
+/// 解析后的笔记本
+#[derive(Debug, Clone)]
+pub struct Notebook {
+    root: Map<String, Value>,
+}
+
+impl Notebook {
+    pub fn parse(content: &str) -> Result<Self> {
+        let root: Map<String, Value> = serde_json::from_str(content)
+            .map_err(|e| ClaudeError::validation_error("notebook", format!("Not a valid notebook: {}", e)))?;
+        if !root.get("cells").is_some_and(Value::is_array) {
+            return Err(ClaudeError::validation_error("notebook", "Notebook has no cells array"));
+        }
+        Ok(Self { root })
+    }
+
+    fn cells(&self) -> &Vec<Value> {
+        self.root["cells"].as_array().expect("checked in parse")
+    }
+
+    fn cells_mut(&mut self) -> &mut Vec<Value> {
+        self.root.get_mut("cells").and_then(Value::as_array_mut).expect("checked in parse")
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells().is_empty()
+    }
+
+    /// 笔记本的内核语言，例如 "python"
+    pub fn language(&self) -> Option<&str> {
+        self.root
+            .get("metadata")
+            .and_then(|metadata| metadata.pointer("/language_info/name").or_else(|| metadata.pointer("/kernelspec/language")))
+            .and_then(Value::as_str)
+    }
+
+    /// nbformat 4.5 起单元格必须带 id
+    fn uses_cell_ids(&self) -> bool {
+        let major = self.root.get("nbformat").and_then(Value::as_u64).unwrap_or(4);
+        let minor = self.root.get("nbformat_minor").and_then(Value::as_u64).unwrap_or(0);
+        (major, minor) >= (4, 5)
+    }
+
+    pub fn cell(&self, index: usize) -> Option<CellView> {
+        let cell = self.cells().get(index)?;
+        Some(CellView {
+            index,
+            id: cell.get("id").and_then(Value::as_str).map(str::to_string),
+            cell_type: cell.get("cell_type").and_then(Value::as_str).unwrap_or("code").to_string(),
+            source: cell.get("source").map(join_text).unwrap_or_default(),
+            execution_count: cell.get("execution_count").and_then(Value::as_u64),
+            outputs: cell
+                .get("outputs")
+                .and_then(Value::as_array)
+                .map(|outputs| outputs.iter().filter_map(output_text).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn cell_views(&self) -> Vec<CellView> {
+        (0..self.len()).filter_map(|index| self.cell(index)).collect()
+    }
+
+    /// 按 id 查找单元格的位置
+    pub fn find(&self, id: &str) -> Option<usize> {
+        self.cells().iter().position(|cell| cell.get("id").and_then(Value::as_str) == Some(id))
+    }
+
+    fn check_index(&self, index: usize) -> Result<()> {
+        if index >= self.len() {
+            return Err(ClaudeError::validation_error(
+                "cell_index",
+                format!("Cell {} does not exist; the notebook has {} cells", index, self.len()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// 在 `index` 处插入新单元格，返回其 id（笔记本不使用 id 时为 `None`）
+    pub fn insert(&mut self, index: usize, cell_type: CellType, source: &str) -> Result<Option<String>> {
+        if index > self.len() {
+            return Err(ClaudeError::validation_error(
+                "cell_index",
+                format!("Cannot insert at {}; the notebook has {} cells", index, self.len()),
+            ));
+        }
+        let mut cell = json!({ "cell_type": cell_type.as_str(), "metadata": {}, "source": split_lines(source) });
+        if cell_type == CellType::Code {
+            cell["execution_count"] = Value::Null;
+            cell["outputs"] = json!([]);
+        }
+        let id = self.uses_cell_ids().then(|| uuid::Uuid::new_v4().simple().to_string()[..8].to_string());
+        if let Some(id) = &id {
+            cell["id"] = Value::String(id.clone());
+        }
+        self.cells_mut().insert(index, cell);
+        Ok(id)
+    }
+
+    /// 替换单元格内容，元数据和输出保留；改变类型时按新类型增删输出字段
+    pub fn replace(&mut self, index: usize, source: &str, cell_type: Option<CellType>) -> Result<()> {
+        self.check_index(index)?;
+        let cell = self.cells_mut()[index]
+            .as_object_mut()
+            .ok_or_else(|| ClaudeError::validation_error("notebook", format!("Cell {} is not an object", index)))?;
+        cell.insert("source".to_string(), split_lines(source));
+        if let Some(cell_type) = cell_type {
+            cell.insert("cell_type".to_string(), Value::String(cell_type.as_str().to_string()));
+            if cell_type == CellType::Code {
+                cell.entry("execution_count").or_insert(Value::Null);
+                cell.entry("outputs").or_insert_with(|| json!([]));
+            } else {
+                cell.remove("execution_count");
+                cell.remove("outputs");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn delete(&mut self, index: usize) -> Result<()> {
+        self.check_index(index)?;
+        self.cells_mut().remove(index);
+        Ok(())
+    }
+
+    /// 按 Jupyter 的格式序列化
+    pub fn to_json(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+        let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+        self.root.serialize(&mut serializer)?;
+        buffer.push(b'\n');
+        String::from_utf8(buffer).map_err(|e| ClaudeError::General(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+ "cells": [
+  {
+   "cell_type": "code",
+   "execution_count": 3,
+   "id": "a1",
+   "metadata": {"tags": ["setup"]},
+   "outputs": [{"name": "stdout", "output_type": "stream", "text": ["hello\n"]}],
+   "source": ["import os\n", "print('hello')"]
+  },
+  {"cell_type": "markdown", "id": "b2", "metadata": {}, "source": "# Notes"}
+ ],
+ "metadata": {"kernelspec": {"language": "python", "name": "python3"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+
+    #[test]
+    fn test_notebook_cell_edits_preserve_metadata_and_outputs() {
+        let mut notebook = Notebook::parse(NOTEBOOK).unwrap();
+        assert_eq!(notebook.language(), Some("python"));
+        let first = notebook.cell(0).unwrap();
+        assert_eq!(first.source, "import os\nprint('hello')");
+        assert_eq!(first.outputs, vec!["hello\n".to_string()]);
+        assert_eq!(notebook.find("b2"), Some(1));
+
+        notebook.replace(0, "import sys\nprint(sys.argv)", None).unwrap();
+        let id = notebook.insert(1, CellType::Code, "x = 1\n").unwrap();
+        assert_eq!(id.as_deref().map(str::len), Some(8));
+        notebook.replace(2, "plain", Some(CellType::Raw)).unwrap();
+        assert!(notebook.delete(5).is_err());
+
+        let reparsed = Notebook::parse(&notebook.to_json().unwrap()).unwrap();
+        let cells = reparsed.cells();
+        assert_eq!(cells.len(), 3);
+        assert_eq!(cells[0]["metadata"]["tags"][0], "setup");
+        assert_eq!(cells[0]["outputs"][0]["text"][0], "hello\n");
+        assert_eq!(cells[0]["source"], json!(["import sys\n", "print(sys.argv)"]));
+        assert_eq!(cells[1]["outputs"], json!([]));
+        assert_eq!(cells[2]["cell_type"], "raw");
+        assert!(notebook.to_json().unwrap().starts_with("{\n \"cells\": [\n  {"));
+
+        notebook.delete(1).unwrap();
+        assert_eq!(notebook.len(), 2);
+    }
+}
@@ -0,0 +1,419 @@
+//! 项目范围的搜索替换
+//!
+//! 按正则（替换文本可用 `$1`、`${name}` 引用捕获组）或字面量在项目文件中查找并替换，
+//! 先生成按文件分组的逐行预览，确认后原子写入，并记入撤销日志以便恢复
+
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use super::scan::{ParallelScanner, ScanOptions};
+use super::undo::{FileSnapshot, UndoEntry, UndoJournal};
+use crate::error::{ClaudeError, Result};
+
+/// 预览中每行最多显示的字符数
+const PREVIEW_LINE_CHARS: usize = 200;
+
+/// 写入时使用的临时文件后缀
+const STAGING_SUFFIX: &str = ".claude-replace.tmp";
+
+/// 搜索替换的参数
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceSpec {
+    pub pattern: String,
+    pub replacement: String,
+    /// 按字面量匹配，替换文本中的 `$` 也不展开
+    pub literal: bool,
+    pub ignore_case: bool,
+    /// 只处理匹配这些通配符的路径（相对项目根目录），为空时处理全部文件
+    pub include: Vec<String>,
+    /// 跳过匹配这些通配符的路径
+    pub exclude: Vec<String>,
+}
+
+impl ReplaceSpec {
+    fn regex(&self) -> Result<Regex> {
+        let pattern = if self.literal { regex::escape(&self.pattern) } else { self.pattern.clone() };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.ignore_case)
+            .multi_line(true)
+            .build()
+            .map_err(|e| ClaudeError::validation_error("pattern", e.to_string()))
+    }
+}
+
+/// 路径通配符：`*` 不跨目录，`**` 跨任意层目录；
+/// 不含 `/` 的模式也匹配任意层级的文件名，匹配目录时包含其下所有文件
+struct PathFilter {
+    regex: Regex,
+    match_name: bool,
+}
+
+impl PathFilter {
+    fn new(pattern: &str) -> Result<Self> {
+        let pattern = pattern.trim_start_matches("./").trim_end_matches('/');
+        let mut source = String::from("^");
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        source.push_str("(?:.*/)?");
+                    } else {
+                        source.push_str(".*");
+                    }
+                }
+                '*' => source.push_str("[^/]*"),
+                '?' => source.push_str("[^/]"),
+                _ => source.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        source.push('$');
+        let regex = Regex::new(&source).map_err(|e| ClaudeError::validation_error("path", e.to_string()))?;
+        Ok(Self { regex, match_name: !pattern.contains('/') })
+    }
+
+    fn matches(&self, relative: &Path) -> bool {
+        relative.ancestors().filter(|p| !p.as_os_str().is_empty()).any(|path| {
+            let path = path.to_string_lossy().replace('\\', "/");
+            self.regex.is_match(&path)
+        }) || (self.match_name
+            && relative.iter().any(|name| self.regex.is_match(&name.to_string_lossy())))
+    }
+}
+
+/// 一处改动涉及的行
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LineChange {
+    /// 起始行号（从 1 开始）
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+}
+
+/// 单个文件的改动
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChange {
+    /// 相对项目根目录的路径
+    pub path: PathBuf,
+    pub matches: usize,
+    pub lines: Vec<LineChange>,
+    #[serde(skip)]
+    original: String,
+    #[serde(skip)]
+    updated: String,
+}
+
+/// 替换计划，预览和写入都基于它
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplacePlan {
+    pub root: PathBuf,
+    pub files: Vec<FileChange>,
+}
+
+/// 在 `root` 下查找匹配并计算替换后的内容，不写入文件
+pub fn plan(root: &Path, spec: &ReplaceSpec) -> Result<ReplacePlan> {
+    if spec.pattern.is_empty() {
+        return Err(ClaudeError::validation_error("pattern", "Search pattern must not be empty"));
+    }
+    let regex = spec.regex()?;
+    let include = spec.include.iter().map(|p| PathFilter::new(p)).collect::<Result<Vec<_>>>()?;
+    let exclude = spec.exclude.iter().map(|p| PathFilter::new(p)).collect::<Result<Vec<_>>>()?;
+
+    let options = ScanOptions::default();
+    let max_file_size = options.max_file_size;
+    let scanner = ParallelScanner::new(options)?;
+    let filter = |path: &Path| {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return false;
+        };
+        (include.is_empty() || include.iter().any(|f| f.matches(relative)))
+            && !exclude.iter().any(|f| f.matches(relative))
+            && std::fs::metadata(path).is_ok_and(|m| m.len() <= max_file_size)
+    };
+
+    let mut files = Vec::new();
+    for path in scanner.discover(root, &filter, None)? {
+        // 非 UTF-8 文件视为二进制文件跳过
+        let Ok(original) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        if let Some((updated, matches, lines)) = replace_in(&original, &regex, spec) {
+            files.push(FileChange {
+                path: path.strip_prefix(root).unwrap_or(&path).to_path_buf(),
+                matches,
+                lines,
+                original,
+                updated,
+            });
+        }
+    }
+    Ok(ReplacePlan { root: root.to_path_buf(), files })
+}
+
+/// 外部计算的单个文件编辑：相对路径、原内容和按字节范围的替换
+pub type FileEdits = (PathBuf, String, Vec<(std::ops::Range<usize>, String)>);
+
+/// 一处匹配及其替换文本
+struct Replacement {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+/// 替换一个文件的内容，返回新内容、匹配数和逐行改动；内容不变时返回 `None`
+fn replace_in(content: &str, regex: &Regex, spec: &ReplaceSpec) -> Option<(String, usize, Vec<LineChange>)> {
+    let replacements: Vec<Replacement> = regex
+        .captures_iter(content)
+        .filter_map(|caps| {
+            let m = caps.get(0)?;
+            let text = if spec.literal {
+                spec.replacement.clone()
+            } else {
+                let mut text = String::new();
+                caps.expand(&spec.replacement, &mut text);
+                text
+            };
+            Some(Replacement { start: m.start(), end: m.end(), text })
+        })
+        .collect();
+
+    change_lines(content, &replacements)
+}
+
+/// 按（已排序、互不重叠的）替换计算新内容和逐行改动
+fn change_lines(content: &str, replacements: &[Replacement]) -> Option<(String, usize, Vec<LineChange>)> {
+    let updated = splice(content, 0, content.len(), replacements);
+    if updated == content {
+        return None;
+    }
+
+    // 把落在同一行（或跨行匹配相互重叠）的替换合并为一组，预览整行前后的内容
+    let mut groups: Vec<(usize, usize, &[Replacement])> = Vec::new();
+    for (index, replacement) in replacements.iter().enumerate() {
+        let line_start = content[..replacement.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = content[replacement.end..].find('\n').map_or(content.len(), |i| replacement.end + i);
+        match groups.last_mut() {
+            Some((_, end, members)) if line_start <= *end => {
+                *end = (*end).max(line_end);
+                *members = &replacements[index - members.len()..=index];
+            }
+            _ => groups.push((line_start, line_end, &replacements[index..=index])),
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut counted, mut line) = (0, 1);
+    for (start, end, members) in groups {
+        line += content[counted..start].matches('\n').count();
+        counted = start;
+        let before = &content[start..end];
+        let after = splice(content, start, end, members);
+        if before != after {
+            lines.push(LineChange { line, before: before.to_string(), after });
+        }
+    }
+    Some((updated, replacements.len(), lines))
+}
+
+/// 把 `content[start..end]` 中的匹配替换为对应文本
+fn splice(content: &str, start: usize, end: usize, replacements: &[Replacement]) -> String {
+    let mut output = String::with_capacity(end - start);
+    let mut last = start;
+    for replacement in replacements {
+        output.push_str(&content[last..replacement.start]);
+        output.push_str(&replacement.text);
+        last = replacement.end;
+    }
+    output.push_str(&content[last..end]);
+    output
+}
+
+impl ReplacePlan {
+    /// 由其他工具算出的编辑生成计划：每个文件给出原内容和按字节范围的替换，
+    /// 以便复用同样的预览、原子写入和撤销
+    pub fn from_edits(root: &Path, edits: Vec<FileEdits>) -> Self {
+        let mut files = Vec::new();
+        for (path, original, mut ranges) in edits {
+            ranges.sort_by_key(|(range, _)| (range.start, range.end));
+            let replacements: Vec<Replacement> =
+                ranges.into_iter().map(|(range, text)| Replacement { start: range.start, end: range.end, text }).collect();
+            if let Some((updated, matches, lines)) = change_lines(&original, &replacements) {
+                files.push(FileChange { path, matches, lines, original, updated });
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Self { root: root.to_path_buf(), files }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn total_matches(&self) -> usize {
+        self.files.iter().map(|f| f.matches).sum()
+    }
+
+    /// 按文件分组的预览
+    pub fn preview(&self) -> String {
+        let truncate = |line: &str| {
+            if line.chars().count() > PREVIEW_LINE_CHARS {
+                format!("{}…", line.chars().take(PREVIEW_LINE_CHARS).collect::<String>())
+            } else {
+                line.to_string()
+            }
+        };
+
+        let mut output = String::new();
+        for file in &self.files {
+            let noun = if file.matches == 1 { "match" } else { "matches" };
+            output.push_str(&format!("📄 {} ({} {})\n", file.path.display(), file.matches, noun));
+            for change in &file.lines {
+                for (offset, line) in change.before.split('\n').enumerate() {
+                    output.push_str(&format!("  {:>5} - {}\n", change.line + offset, truncate(line)));
+                }
+                for (offset, line) in change.after.split('\n').enumerate() {
+                    output.push_str(&format!("  {:>5} + {}\n", change.line + offset, truncate(line)));
+                }
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// 原子写入所有文件并记入撤销日志
+    ///
+    /// 先核对文件自预览后未被修改，再把新内容写到同目录的临时文件，最后逐个重命名；
+    /// 任一步失败都会恢复已写入的文件
+    pub fn apply(&self, journal: &UndoJournal, description: &str) -> Result<UndoEntry> {
+        for file in &self.files {
+            let path = self.root.join(&file.path);
+            if std::fs::read_to_string(&path)? != file.original {
+                return Err(ClaudeError::fs_error(format!(
+                    "{} changed since the preview was generated; run the search again",
+                    file.path.display()
+                )));
+            }
+        }
+
+        let mut staged = Vec::new();
+        for file in &self.files {
+            let path = self.root.join(&file.path);
+            let mut staging = path.clone().into_os_string();
+            staging.push(STAGING_SUFFIX);
+            let staging = PathBuf::from(staging);
+            let written = std::fs::write(&staging, &file.updated)
+                .and_then(|_| std::fs::set_permissions(&staging, std::fs::metadata(&path)?.permissions()));
+            staged.push((staging, path));
+            if let Err(e) = written {
+                discard(&staged);
+                return Err(ClaudeError::fs_error(format!("Failed to write {}: {}", file.path.display(), e)));
+            }
+        }
+
+        let entry = UndoEntry::new(
+            description,
+            self.files
+                .iter()
+                .map(|file| FileSnapshot {
+                    path: self.root.join(&file.path),
+                    before: file.original.clone(),
+                    after: file.updated.clone(),
+                })
+                .collect(),
+        );
+        if let Err(e) = journal.record(&entry) {
+            discard(&staged);
+            return Err(e);
+        }
+
+        for (index, (staging, path)) in staged.iter().enumerate() {
+            if let Err(e) = std::fs::rename(staging, path) {
+                for (file, (_, path)) in self.files.iter().zip(&staged).take(index) {
+                    let _ = std::fs::write(path, &file.original);
+                }
+                discard(&staged[index..]);
+                let _ = journal.remove(&entry.id);
+                return Err(ClaudeError::fs_error(format!("Failed to replace {}: {}", path.display(), e)));
+            }
+        }
+        Ok(entry)
+    }
+}
+
+fn discard(staged: &[(PathBuf, PathBuf)]) {
+    for (staging, _) in staged {
+        let _ = std::fs::remove_file(staging);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_with_capture_groups_and_filters() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "let a = old_name(1);\nkeep();\nold_name(2) + old_name(3);\n").unwrap();
+        std::fs::write(root.join("notes.md"), "old_name(4)\n").unwrap();
+
+        let spec = ReplaceSpec {
+            pattern: r"old_name\((\d+)\)".to_string(),
+            replacement: "new_name(${1}u32)".to_string(),
+            include: vec!["*.rs".to_string()],
+            ..Default::default()
+        };
+        let plan = plan(root, &spec).unwrap();
+        assert_eq!(plan.files.len(), 1);
+        assert_eq!(plan.total_matches(), 3);
+        assert_eq!(
+            plan.files[0].lines,
+            vec![
+                LineChange { line: 1, before: "let a = old_name(1);".into(), after: "let a = new_name(1u32);".into() },
+                LineChange { line: 3, before: "old_name(2) + old_name(3);".into(), after: "new_name(2u32) + new_name(3u32);".into() },
+            ]
+        );
+
+        let literal = ReplaceSpec {
+            pattern: "old_name(".to_string(),
+            replacement: "$cost(".to_string(),
+            literal: true,
+            exclude: vec!["src".to_string()],
+            ..Default::default()
+        };
+        let plan = super::plan(root, &literal).unwrap();
+        assert_eq!(plan.files[0].path, PathBuf::from("notes.md"));
+        assert_eq!(plan.files[0].lines[0].after, "$cost(4)");
+    }
+
+    #[test]
+    fn test_apply_and_undo() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().join("project");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.txt"), "color: red\n").unwrap();
+        std::fs::write(root.join("b.txt"), "background-color: red\n").unwrap();
+        let journal = UndoJournal::new(temp_dir.path().join("undo"));
+
+        let spec = ReplaceSpec { pattern: "red".to_string(), replacement: "blue".to_string(), ..Default::default() };
+        let plan = plan(&root, &spec).unwrap();
+        let entry = plan.apply(&journal, "red -> blue").unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("b.txt")).unwrap(), "background-color: blue\n");
+        assert!(std::fs::read_dir(&root).unwrap().all(|e| !e.unwrap().file_name().to_string_lossy().ends_with(STAGING_SUFFIX)));
+
+        // 预览之后文件被改动则拒绝写入
+        std::fs::write(root.join("a.txt"), "color: blue;\n").unwrap();
+        assert!(plan.apply(&journal, "again").is_err());
+
+        let latest = journal.list().unwrap().into_iter().find(|e| e.touches(&root)).unwrap();
+        assert_eq!(latest.id, entry.id);
+        let outcome = journal.undo(&latest, false).unwrap();
+        assert_eq!(outcome.restored, vec![root.join("b.txt")]);
+        assert_eq!(outcome.conflicts, vec![root.join("a.txt")]);
+        assert_eq!(std::fs::read_to_string(root.join("b.txt")).unwrap(), "background-color: red\n");
+    }
+}
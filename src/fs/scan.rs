@@ -0,0 +1,483 @@
+//! 并行目录扫描
+//!
+//! 基于 rayon 在独立线程池中并行遍历目录、读取并哈希文件，
+//! 文件大小和修改时间未变化时复用上次扫描的结果，大仓库的初始化和审查无需重新读取全部文件
+
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::ignore::IgnoreRules;
+use crate::error::{ClaudeError, Result};
+
+/// 两次进度回调的最小间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 扫描选项
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// 工作线程数，限制同时读取的文件数量
+    pub threads: usize,
+    /// 是否包含以 `.` 开头的文件和目录
+    pub include_hidden: bool,
+    /// 跳过的目录名
+    pub skip_dirs: Vec<String>,
+    /// 超过该大小的文件不读取，也不计入结果
+    pub max_file_size: u64,
+    /// 是否跳过 .gitignore 忽略的文件和目录
+    pub respect_gitignore: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            threads: num_cpus::get().clamp(1, 8),
+            include_hidden: false,
+            skip_dirs: vec!["target".to_string(), "node_modules".to_string(), "__pycache__".to_string()],
+            max_file_size: 10 * 1024 * 1024,
+            respect_gitignore: false,
+        }
+    }
+}
+
+/// 扫描阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanPhase {
+    /// 遍历目录，总数未知
+    Discovering,
+    /// 读取并哈希文件
+    Hashing,
+}
+
+/// 扫描进度
+#[derive(Debug, Clone, Copy)]
+pub struct ScanProgress {
+    /// 当前阶段
+    pub phase: ScanPhase,
+    /// 已处理的文件数
+    pub processed: usize,
+    /// 文件总数（遍历阶段为已发现的数量）
+    pub total: usize,
+    /// 已处理文件的总字节数
+    pub bytes: u64,
+}
+
+/// 进度回调，可能在任意工作线程上调用
+pub type ProgressCallback<'a> = &'a (dyn Fn(ScanProgress) + Sync);
+
+/// 可跨线程传递的进度回调，用于在阻塞任务中扫描
+pub type SharedProgressCallback = std::sync::Arc<dyn Fn(ScanProgress) + Send + Sync>;
+
+/// 扫描到的文本文件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScannedFile {
+    /// 文件路径
+    pub path: PathBuf,
+    /// 文件大小（字节）
+    pub size: u64,
+    /// 内容的 MD5 哈希
+    pub hash: String,
+    /// 行数
+    pub line_count: usize,
+    /// 字符数
+    pub char_count: usize,
+    /// 修改时间（自 Unix 纪元的秒数和纳秒部分）
+    modified: Option<(u64, u32)>,
+}
+
+/// 扫描结果
+#[derive(Debug, Clone, Default)]
+pub struct ScanReport {
+    /// 文本文件（按路径排序），二进制和超大文件不包含在内
+    pub files: Vec<ScannedFile>,
+    /// 重新读取并哈希的文件数
+    pub hashed: usize,
+    /// 复用缓存结果的文件数
+    pub reused: usize,
+    /// 扫描耗时
+    pub elapsed: Duration,
+}
+
+/// 上次扫描的结果，用于增量哈希
+#[derive(Debug, Clone, Default)]
+pub struct ScanCache {
+    /// 存储文件路径
+    path: PathBuf,
+    /// 按文件路径索引的条目
+    entries: HashMap<PathBuf, ScannedFile>,
+    /// 更新时间
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct CacheFile {
+    updated_at: DateTime<Utc>,
+    files: Vec<ScannedFile>,
+}
+
+impl ScanCache {
+    /// 从指定路径加载，文件不存在或损坏时返回空缓存
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let (entries, updated_at) = match std::fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<CacheFile>(&content) {
+                Ok(file) => (
+                    file.files.into_iter().map(|f| (f.path.clone(), f)).collect(),
+                    Some(file.updated_at),
+                ),
+                Err(e) => {
+                    tracing::warn!("Ignoring corrupt scan cache {}: {}", path.display(), e);
+                    (HashMap::new(), None)
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (HashMap::new(), None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, entries, updated_at })
+    }
+
+    /// 保存缓存
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut files: Vec<&ScannedFile> = self.entries.values().collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let file = serde_json::json!({
+            "updated_at": self.updated_at.unwrap_or_else(Utc::now),
+            "files": files,
+        });
+        std::fs::write(&self.path, serde_json::to_string(&file)?)?;
+        Ok(())
+    }
+
+    /// 缓存的文件数
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 缓存是否为空
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 大小和修改时间都未变化时返回缓存的结果
+    fn lookup(&self, path: &Path, size: u64, modified: Option<(u64, u32)>) -> Option<&ScannedFile> {
+        self.entries
+            .get(path)
+            .filter(|entry| modified.is_some() && entry.size == size && entry.modified == modified)
+    }
+
+    /// 用本次扫描的结果替换缓存，已删除的文件随之移除
+    fn replace(&mut self, files: &[ScannedFile]) {
+        self.entries = files.iter().map(|f| (f.path.clone(), f.clone())).collect();
+        self.updated_at = Some(Utc::now());
+    }
+}
+
+/// 并行目录扫描器
+pub struct ParallelScanner {
+    /// 扫描选项
+    options: ScanOptions,
+    /// 专用线程池，不占用全局 rayon 线程池
+    pool: rayon::ThreadPool,
+}
+
+impl ParallelScanner {
+    /// 创建扫描器
+    pub fn new(options: ScanOptions) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(options.threads.max(1))
+            .thread_name(|i| format!("scan-{}", i))
+            .build()
+            .map_err(|e| ClaudeError::General(format!("Failed to create scan thread pool: {}", e)))?;
+        Ok(Self { options, pool })
+    }
+
+    /// 并行遍历目录，返回通过过滤条件的文件（按路径排序）
+    pub fn discover(
+        &self,
+        root: &Path,
+        filter: &(dyn Fn(&Path) -> bool + Sync),
+        progress: Option<ProgressCallback<'_>>,
+    ) -> Result<Vec<PathBuf>> {
+        if !root.is_dir() {
+            return Err(ClaudeError::fs_error(format!("Not a directory: {}", root.display())));
+        }
+
+        let found = Mutex::new(Vec::new());
+        let reporter = Reporter::new(progress);
+        let ignore = if self.options.respect_gitignore { IgnoreRules::for_ancestors(root) } else { IgnoreRules::default() };
+        self.pool.install(|| self.walk(root, &ignore, filter, &found, &reporter));
+
+        let mut files = found.into_inner().unwrap_or_else(|e| e.into_inner());
+        files.sort();
+        reporter.finish(ScanPhase::Discovering, files.len(), files.len());
+        Ok(files)
+    }
+
+    /// 扫描目录下的全部文本文件，`cache` 中未变化的文件不会重新读取
+    pub fn scan(
+        &self,
+        root: &Path,
+        cache: Option<&mut ScanCache>,
+        progress: Option<ProgressCallback<'_>>,
+    ) -> Result<ScanReport> {
+        let started = Instant::now();
+        let paths = self.discover(root, &|_| true, progress)?;
+
+        let reused = AtomicUsize::new(0);
+        let reporter = Reporter::new(progress);
+        let previous = cache.as_deref();
+        let files: Vec<ScannedFile> = self.pool.install(|| {
+            paths
+                .par_iter()
+                .filter_map(|path| {
+                    let result = self.scan_file(path, previous, &reused);
+                    reporter.advance(ScanPhase::Hashing, paths.len(), result.as_ref().map_or(0, |f| f.size));
+                    result
+                })
+                .collect()
+        });
+        reporter.finish(ScanPhase::Hashing, paths.len(), paths.len());
+
+        if let Some(cache) = cache {
+            cache.replace(&files);
+        }
+
+        let reused = reused.into_inner();
+        Ok(ScanReport {
+            hashed: files.len() - reused,
+            reused,
+            files,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    fn walk(
+        &self,
+        dir: &Path,
+        ignore: &IgnoreRules,
+        filter: &(dyn Fn(&Path) -> bool + Sync),
+        found: &Mutex<Vec<PathBuf>>,
+        reporter: &Reporter<'_>,
+    ) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let ignore = if self.options.respect_gitignore { ignore.with_dir(dir) } else { ignore.clone() };
+
+        let mut subdirs = Vec::new();
+        let mut files = Vec::new();
+        for entry in entries.flatten() {
+            // 不跟随符号链接，避免目录环
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !self.options.include_hidden && name.starts_with('.') {
+                continue;
+            }
+
+            let path = entry.path();
+            if ignore.is_ignored(&path, file_type.is_dir()) {
+                continue;
+            }
+            if file_type.is_dir() {
+                if !self.options.skip_dirs.iter().any(|skip| *skip == name) {
+                    subdirs.push(path);
+                }
+            } else if file_type.is_file() && filter(&path) {
+                files.push(path);
+            }
+        }
+
+        if !files.is_empty() {
+            let mut found = found.lock().unwrap_or_else(|e| e.into_inner());
+            found.extend(files);
+            reporter.report(ScanPhase::Discovering, found.len(), found.len());
+        }
+
+        subdirs.par_iter().for_each(|subdir| self.walk(subdir, &ignore, filter, found, reporter));
+    }
+
+    fn scan_file(&self, path: &Path, cache: Option<&ScanCache>, reused: &AtomicUsize) -> Option<ScannedFile> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|d| (d.as_secs(), d.subsec_nanos()));
+
+        if let Some(cached) = cache.and_then(|c| c.lookup(path, size, modified)) {
+            reused.fetch_add(1, Ordering::Relaxed);
+            return Some(cached.clone());
+        }
+        if size > self.options.max_file_size {
+            return None;
+        }
+
+        let bytes = std::fs::read(path).ok()?;
+        let text = std::str::from_utf8(&bytes).ok()?;
+        Some(ScannedFile {
+            path: path.to_path_buf(),
+            size: bytes.len() as u64,
+            hash: format!("{:x}", md5::compute(&bytes)),
+            line_count: text.lines().count(),
+            char_count: text.chars().count(),
+            modified,
+        })
+    }
+}
+
+impl ScannedFile {
+    /// 修改时间
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+            .map(|(secs, nanos)| UNIX_EPOCH + Duration::new(secs, nanos))
+    }
+}
+
+/// 节流的进度上报
+struct Reporter<'a> {
+    callback: Option<ProgressCallback<'a>>,
+    processed: AtomicUsize,
+    bytes: AtomicU64,
+    last: Mutex<Instant>,
+}
+
+impl<'a> Reporter<'a> {
+    fn new(callback: Option<ProgressCallback<'a>>) -> Self {
+        Self {
+            callback,
+            processed: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            last: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn advance(&self, phase: ScanPhase, total: usize, bytes: u64) {
+        let processed = self.processed.fetch_add(1, Ordering::Relaxed) + 1;
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        // 最后一次由 finish 上报，保证完成状态只通知一次
+        if processed < total {
+            self.report(phase, processed, total);
+        }
+    }
+
+    fn report(&self, phase: ScanPhase, processed: usize, total: usize) {
+        let Some(callback) = self.callback else {
+            return;
+        };
+        // 其他线程正在上报时直接跳过
+        let Ok(mut last) = self.last.try_lock() else {
+            return;
+        };
+        if last.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        *last = Instant::now();
+        callback(ScanProgress {
+            phase,
+            processed,
+            total,
+            bytes: self.bytes.load(Ordering::Relaxed),
+        });
+    }
+
+    fn finish(&self, phase: ScanPhase, processed: usize, total: usize) {
+        if let Some(callback) = self.callback {
+            callback(ScanProgress {
+                phase,
+                processed,
+                total,
+                bytes: self.bytes.load(Ordering::Relaxed),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_tree(root: &Path) {
+        for dir in ["src/a/b", "src/c", "target/debug", ".git", "docs"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for i in 0..50 {
+            std::fs::write(root.join(format!("src/a/b/f{}.rs", i)), format!("fn f{}() {{}}\n// 注释\n", i)).unwrap();
+        }
+        std::fs::write(root.join("src/c/lib.rs"), "pub mod a;").unwrap();
+        std::fs::write(root.join("docs/logo.bin"), [0xff, 0xfe, 0x00]).unwrap();
+        std::fs::write(root.join("target/debug/out.rs"), "skipped").unwrap();
+        std::fs::write(root.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+    }
+
+    #[test]
+    fn test_parallel_scan() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        let scanner = ParallelScanner::new(ScanOptions { threads: 4, ..ScanOptions::default() }).unwrap();
+
+        let updates = Mutex::new(Vec::new());
+        let progress = |p: ScanProgress| updates.lock().unwrap().push(p);
+        let report = scanner.scan(temp_dir.path(), None, Some(&progress)).unwrap();
+
+        // 二进制文件、target 和隐藏目录不计入
+        assert_eq!(report.files.len(), 51);
+        assert_eq!(report.hashed, 51);
+        assert!(report.files.windows(2).all(|w| w[0].path < w[1].path));
+        let lib = report.files.iter().find(|f| f.path.ends_with("src/c/lib.rs")).unwrap();
+        assert_eq!(lib.hash, format!("{:x}", md5::compute("pub mod a;")));
+        assert_eq!(lib.line_count, 1);
+
+        let last = *updates.lock().unwrap().last().unwrap();
+        assert_eq!(last.phase, ScanPhase::Hashing);
+        assert_eq!(last.processed, 52);
+    }
+
+    #[test]
+    fn test_incremental_hashing() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        let cache_path = temp_dir.path().join(".claude-code/scan_cache.json");
+        let scanner = ParallelScanner::new(ScanOptions::default()).unwrap();
+
+        let mut cache = ScanCache::load(cache_path.clone()).unwrap();
+        scanner.scan(temp_dir.path(), Some(&mut cache), None).unwrap();
+        cache.save().unwrap();
+
+        std::fs::write(temp_dir.path().join("src/c/lib.rs"), "pub mod a;\npub mod b;").unwrap();
+        std::fs::remove_file(temp_dir.path().join("src/a/b/f0.rs")).unwrap();
+
+        let mut cache = ScanCache::load(cache_path).unwrap();
+        assert_eq!(cache.len(), 51);
+        let report = scanner.scan(temp_dir.path(), Some(&mut cache), None).unwrap();
+        assert_eq!(report.files.len(), 50);
+        assert_eq!(report.hashed, 1);
+        assert_eq!(report.reused, 49);
+        assert_eq!(cache.len(), 50);
+    }
+
+    #[test]
+    fn test_discover_with_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        create_tree(temp_dir.path());
+        let options = ScanOptions { include_hidden: true, skip_dirs: Vec::new(), ..ScanOptions::default() };
+        let scanner = ParallelScanner::new(options).unwrap();
+
+        let found = scanner
+            .discover(temp_dir.path(), &|p| p.extension().is_some_and(|e| e == "rs"), None)
+            .unwrap();
+        assert_eq!(found.len(), 52);
+        assert!(scanner.discover(&temp_dir.path().join("missing"), &|_| true, None).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! 会话临时工作区
+//!
+//! 每个会话在产物目录下有一个临时目录，存放下载内容、生成的文件等不属于仓库的产物。
+//! 工具可以用 `scratch://<相对路径>` 访问其中的文件；目录受产物保留策略约束，由存储回收自动清理
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+
+/// 临时工作区的路径前缀
+pub const SCHEME: &str = "scratch://";
+
+/// 传给命令的环境变量，值为临时工作区的绝对路径
+pub const ENV_VAR: &str = "CLAUDE_SCRATCH_DIR";
+
+/// 工作区中的一个文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScratchFile {
+    /// 相对工作区的路径
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<std::time::SystemTime>,
+}
+
+impl ScratchFile {
+    /// `scratch://` 形式的地址
+    pub fn uri(&self) -> String {
+        format!("{}{}", SCHEME, self.path.to_string_lossy().replace('\\', "/"))
+    }
+}
+
+/// 一个会话的临时工作区
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scratchpad {
+    dir: PathBuf,
+}
+
+impl Scratchpad {
+    /// 所有会话工作区的上级目录，位于存储回收管理的产物目录中
+    pub fn root() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("claude-code")
+            .join("artifacts")
+            .join("scratch")
+    }
+
+    /// 会话的工作区，目录在首次写入时创建
+    pub fn for_session(session_id: &str) -> Self {
+        Self::in_dir(Self::root(), session_id)
+    }
+
+    pub fn in_dir(root: impl AsRef<Path>, session_id: &str) -> Self {
+        let name: String = session_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        Self { dir: root.as_ref().join(name) }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 创建目录并返回其路径
+    pub fn ensure(&self) -> Result<&Path> {
+        std::fs::create_dir_all(&self.dir)?;
+        Ok(&self.dir)
+    }
+
+    /// 解析 `scratch://` 地址；不是该形式的路径返回 `None`
+    pub fn resolve(&self, path: &str) -> Result<Option<PathBuf>> {
+        let Some(relative) = path.strip_prefix(SCHEME) else {
+            return Ok(None);
+        };
+        let relative = Path::new(relative.trim_start_matches('/'));
+        if !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(ClaudeError::sandbox_error(format!("Scratch path must stay inside the scratchpad: {}", path)));
+        }
+        Ok(Some(self.dir.join(relative)))
+    }
+
+    /// 工作区中的文件，按路径排序
+    pub fn files(&self) -> Vec<ScratchFile> {
+        let mut files: Vec<ScratchFile> = walkdir::WalkDir::new(&self.dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some(ScratchFile {
+                    path: entry.path().strip_prefix(&self.dir).ok()?.to_path_buf(),
+                    size: metadata.len(),
+                    modified: metadata.modified().ok(),
+                })
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        files
+    }
+
+    /// 列出文件的文本，供命令输出使用
+    pub fn describe(&self) -> String {
+        let files = self.files();
+        if files.is_empty() {
+            return format!("Scratchpad {} is empty.", self.dir.display());
+        }
+        let mut text = format!("Scratchpad {} ({} file(s)):", self.dir.display(), files.len());
+        for file in &files {
+            text.push_str(&format!("\n  {:<48} {:>10} bytes", file.uri(), file.size));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_and_list() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let scratch = Scratchpad::in_dir(temp_dir.path(), "session/1");
+        assert_eq!(scratch.dir(), temp_dir.path().join("session_1"));
+
+        assert_eq!(scratch.resolve("src/main.rs").unwrap(), None);
+        let report = scratch.resolve("scratch://reports/out.json").unwrap().unwrap();
+        assert_eq!(report, scratch.dir().join("reports/out.json"));
+        assert!(scratch.resolve("scratch://../escape").is_err());
+
+        std::fs::create_dir_all(report.parent().unwrap()).unwrap();
+        std::fs::write(&report, "{}").unwrap();
+        let files = scratch.files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].uri(), "scratch://reports/out.json");
+        assert_eq!(files[0].size, 2);
+        assert!(scratch.describe().contains("scratch://reports/out.json"));
+    }
+}
@@ -0,0 +1,312 @@
+//! 工作区文本搜索
+//!
+//! 并行遍历目录并遵守 .gitignore，按正则逐行搜索文本文件（跳过二进制文件）。支持按通配符和
+//! 文件类型过滤、上下文行，结果可以输出为 ripgrep 风格的匹配行、匹配的文件列表或逐文件计数
+
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use super::ignore::glob_regex;
+use super::scan::{ParallelScanner, ScanOptions};
+use crate::error::{ClaudeError, Result};
+
+/// 每行最多保留的字符数，压缩过的代码等超长行会被截断
+const MAX_LINE_CHARS: usize = 300;
+
+/// 检查是否含 NUL 字节以识别二进制文件的前缀长度
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+/// 文件类型及其扩展名
+pub const FILE_TYPES: &[(&str, &[&str])] = &[
+    ("rust", &["rs"]),
+    ("py", &["py", "pyi"]),
+    ("js", &["js", "mjs", "cjs", "jsx"]),
+    ("ts", &["ts", "mts", "cts", "tsx"]),
+    ("go", &["go"]),
+    ("java", &["java"]),
+    ("kotlin", &["kt", "kts"]),
+    ("c", &["c", "h"]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh", "hxx", "h"]),
+    ("cs", &["cs"]),
+    ("rb", &["rb"]),
+    ("php", &["php"]),
+    ("swift", &["swift"]),
+    ("sh", &["sh", "bash", "zsh"]),
+    ("md", &["md", "markdown"]),
+    ("json", &["json"]),
+    ("toml", &["toml"]),
+    ("yaml", &["yaml", "yml"]),
+    ("html", &["html", "htm"]),
+    ("css", &["css", "scss", "sass", "less"]),
+    ("sql", &["sql"]),
+];
+
+/// 搜索参数
+#[derive(Debug, Clone, Default)]
+pub struct SearchSpec {
+    pub pattern: String,
+    pub ignore_case: bool,
+    /// 只搜索匹配该通配符的文件；不含 `/` 时匹配文件名，否则匹配相对根目录的路径
+    pub glob: Option<String>,
+    /// 只搜索这些类型的文件，类型名见 [`FILE_TYPES`]
+    pub file_types: Vec<String>,
+    /// 匹配行前后各带的上下文行数
+    pub context: usize,
+}
+
+impl SearchSpec {
+    fn regex(&self) -> Result<Regex> {
+        if self.pattern.is_empty() {
+            return Err(ClaudeError::validation_error("pattern", "Search pattern must not be empty"));
+        }
+        RegexBuilder::new(&self.pattern)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map_err(|e| ClaudeError::validation_error("pattern", e.to_string()))
+    }
+
+    fn extensions(&self) -> Result<Vec<&'static str>> {
+        let mut extensions = Vec::new();
+        for name in &self.file_types {
+            let Some((_, exts)) = FILE_TYPES.iter().find(|(type_name, _)| type_name.eq_ignore_ascii_case(name.trim())) else {
+                let known: Vec<&str> = FILE_TYPES.iter().map(|(type_name, _)| *type_name).collect();
+                return Err(ClaudeError::validation_error(
+                    "type",
+                    format!("Unknown file type '{}', expected one of: {}", name, known.join(", ")),
+                ));
+            };
+            extensions.extend_from_slice(exts);
+        }
+        Ok(extensions)
+    }
+}
+
+/// 输出形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// `path:line:text` 形式的匹配行，上下文行用 `-` 分隔
+    Content,
+    /// 只列出有匹配的文件
+    FilesWithMatches,
+    /// 每个文件的匹配行数
+    Count,
+}
+
+impl std::str::FromStr for OutputMode {
+    type Err = ClaudeError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "content" => Ok(Self::Content),
+            "files_with_matches" => Ok(Self::FilesWithMatches),
+            "count" => Ok(Self::Count),
+            other => Err(ClaudeError::validation_error(
+                "output_mode",
+                format!("Unknown output mode '{}', expected 'content', 'files_with_matches' or 'count'", other),
+            )),
+        }
+    }
+}
+
+/// 一个匹配行
+#[derive(Debug, Clone, Serialize)]
+pub struct LineMatch {
+    /// 行号，从 1 开始
+    pub line: usize,
+    pub text: String,
+    /// 匹配行之前的上下文
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub before: Vec<String>,
+    /// 匹配行之后的上下文
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+}
+
+/// 一个文件中的匹配
+#[derive(Debug, Clone, Serialize)]
+pub struct FileMatches {
+    /// 相对搜索根目录的路径
+    pub path: PathBuf,
+    pub lines: Vec<LineMatch>,
+}
+
+/// 搜索结果，文件按路径排序
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchReport {
+    pub files: Vec<FileMatches>,
+    /// 实际读取的文件数
+    pub files_searched: usize,
+}
+
+/// 在 `root` 下的 `target`（文件或目录）中搜索，结果中的路径相对 `root`
+pub fn search(root: &Path, target: &Path, spec: &SearchSpec) -> Result<SearchReport> {
+    let regex = spec.regex()?;
+    let extensions = spec.extensions()?;
+    let glob = spec
+        .glob
+        .as_deref()
+        .map(|glob| glob_regex(glob.trim_start_matches("./")).map(|regex| (regex, !glob.contains('/'))))
+        .transpose()?;
+
+    let options = ScanOptions { respect_gitignore: true, ..ScanOptions::default() };
+    let max_file_size = options.max_file_size;
+    let paths = if target.is_file() {
+        vec![target.to_path_buf()]
+    } else {
+        let filter = |path: &Path| {
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            let glob_ok = glob.as_ref().is_none_or(|(regex, name_only)| match name_only {
+                true => relative.file_name().is_some_and(|name| regex.is_match(&name.to_string_lossy())),
+                false => regex.is_match(&relative.to_string_lossy().replace('\\', "/")),
+            });
+            let type_ok = extensions.is_empty()
+                || path.extension().is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)));
+            glob_ok && type_ok && std::fs::metadata(path).is_ok_and(|m| m.len() <= max_file_size)
+        };
+        ParallelScanner::new(options)?.discover(target, &filter, None)?
+    };
+
+    // 并行搜索，collect 保持路径顺序
+    let files = paths
+        .par_iter()
+        .filter_map(|path| {
+            let lines = search_file(path, &regex, spec.context)?;
+            Some(FileMatches { path: path.strip_prefix(root).unwrap_or(path).to_path_buf(), lines })
+        })
+        .collect();
+    Ok(SearchReport { files, files_searched: paths.len() })
+}
+
+/// 搜索单个文件，二进制或非 UTF-8 文件以及没有匹配时返回 `None`
+fn search_file(path: &Path, regex: &Regex, context: usize) -> Option<Vec<LineMatch>> {
+    let bytes = std::fs::read(path).ok()?;
+    if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return None;
+    }
+    let text = std::str::from_utf8(&bytes).ok()?;
+    if !regex.is_match(text) {
+        return None;
+    }
+
+    let lines: Vec<&str> = text.lines().collect();
+    let clip = |line: &&str| match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((index, _)) => format!("{}…", &line[..index]),
+        None => line.to_string(),
+    };
+    let matches: Vec<LineMatch> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| regex.is_match(line))
+        .map(|(index, line)| LineMatch {
+            line: index + 1,
+            text: clip(line),
+            before: lines[index.saturating_sub(context)..index].iter().map(clip).collect(),
+            after: lines[index + 1..(index + 1 + context).min(lines.len())].iter().map(clip).collect(),
+        })
+        .collect();
+    (!matches.is_empty()).then_some(matches)
+}
+
+impl SearchReport {
+    /// 匹配行总数
+    pub fn total_matches(&self) -> usize {
+        self.files.iter().map(|f| f.lines.len()).sum()
+    }
+
+    /// 渲染为文本，最多 `limit` 项（匹配行、文件或计数行），为 0 时不限。返回文本和是否被截断
+    pub fn render(&self, mode: OutputMode, limit: usize) -> (String, bool) {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut out = Vec::new();
+        let mut truncated = false;
+        match mode {
+            OutputMode::FilesWithMatches | OutputMode::Count => {
+                truncated = self.files.len() > limit;
+                for file in self.files.iter().take(limit) {
+                    let path = file.path.to_string_lossy();
+                    out.push(match mode {
+                        OutputMode::Count => format!("{}:{}", path, file.lines.len()),
+                        _ => path.into_owned(),
+                    });
+                }
+            }
+            OutputMode::Content => {
+                let mut remaining = limit;
+                'files: for file in &self.files {
+                    let path = file.path.to_string_lossy();
+                    // 上一次输出到的行号，用来合并相邻匹配的上下文
+                    let mut printed = 0;
+                    for (index, m) in file.lines.iter().enumerate() {
+                        if remaining == 0 {
+                            truncated = true;
+                            break 'files;
+                        }
+                        remaining -= 1;
+                        let first = m.line - m.before.len();
+                        if printed > 0 && first > printed + 1 && !m.before.is_empty() {
+                            out.push("--".to_string());
+                        }
+                        for (offset, text) in m.before.iter().enumerate() {
+                            if first + offset > printed {
+                                out.push(format!("{}-{}-{}", path, first + offset, text));
+                            }
+                        }
+                        out.push(format!("{}:{}:{}", path, m.line, m.text));
+                        printed = m.line;
+                        // 后面的上下文不越过下一处匹配，由它自己输出
+                        let next = file.lines.get(index + 1).map_or(usize::MAX, |next| next.line);
+                        for (offset, text) in m.after.iter().enumerate() {
+                            let line = m.line + 1 + offset;
+                            if line >= next {
+                                break;
+                            }
+                            out.push(format!("{}-{}-{}", path, line, text));
+                            printed = line;
+                        }
+                    }
+                }
+            }
+        }
+        (out.join("\n"), truncated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_respects_gitignore_and_filters() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        std::fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "fn main() {\n    todo!();\n}\n// TODO later\n").unwrap();
+        std::fs::write(root.join("src/notes.md"), "todo: docs\n").unwrap();
+        std::fs::write(root.join("build/out.rs"), "todo!()\n").unwrap();
+        std::fs::write(root.join("src/data.bin"), b"todo\0\x01").unwrap();
+
+        let spec = SearchSpec { pattern: "todo".to_string(), ignore_case: true, ..SearchSpec::default() };
+        let report = search(root, root, &spec).unwrap();
+        let paths: Vec<_> = report.files.iter().map(|f| f.path.to_string_lossy().into_owned()).collect();
+        assert_eq!(paths, ["src/lib.rs", "src/notes.md"]);
+        assert_eq!(report.total_matches(), 3);
+        assert_eq!(report.render(OutputMode::Count, 0).0, "src/lib.rs:2\nsrc/notes.md:1");
+
+        let rust = SearchSpec { file_types: vec!["rust".to_string()], context: 1, ..spec.clone() };
+        let report = search(root, &root.join("src"), &rust).unwrap();
+        assert_eq!(
+            report.render(OutputMode::Content, 0).0,
+            "src/lib.rs-1-fn main() {\nsrc/lib.rs:2:    todo!();\nsrc/lib.rs-3-}\nsrc/lib.rs:4:// TODO later"
+        );
+        let (first, truncated) = report.render(OutputMode::Content, 1);
+        assert!(truncated);
+        assert!(first.ends_with("src/lib.rs-3-}"));
+
+        let glob = SearchSpec { glob: Some("*.md".to_string()), ..spec.clone() };
+        assert_eq!(search(root, root, &glob).unwrap().render(OutputMode::FilesWithMatches, 0).0, "src/notes.md");
+        assert!(search(root, root, &SearchSpec { file_types: vec!["cobol".to_string()], ..spec }).is_err());
+    }
+}
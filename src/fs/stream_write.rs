@@ -0,0 +1,162 @@
+//! 流式写入生成的文件
+//!
+//! 模型生成大文件时，增量到达就写入目标旁边的临时文件，而不是在内存里攒完整内容；
+//! 结束后校验临时文件，通过才原子地移动到目标位置，失败或中断时目标文件保持不变
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+
+/// 临时文件的后缀，与目标在同一目录以保证重命名是原子的
+const PARTIAL_SUFFIX: &str = ".claude-partial";
+
+/// 把流式增量写入临时文件，提交时校验并替换目标
+pub struct StreamingFileWriter {
+    target: PathBuf,
+    partial: PathBuf,
+    writer: Option<BufWriter<File>>,
+    /// 尚未遇到换行的最后一行，用于去掉包裹整个文件的代码围栏
+    pending_line: String,
+    at_start: bool,
+    bytes_written: u64,
+    lines_written: usize,
+}
+
+impl StreamingFileWriter {
+    pub fn create(target: impl Into<PathBuf>) -> Result<Self> {
+        let target = target.into();
+        if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut partial = target.clone().into_os_string();
+        partial.push(PARTIAL_SUFFIX);
+        let partial = PathBuf::from(partial);
+        let file = File::create(&partial)
+            .map_err(|e| ClaudeError::fs_error(format!("Failed to create {}: {}", partial.display(), e)))?;
+        Ok(Self {
+            target,
+            partial,
+            writer: Some(BufWriter::new(file)),
+            pending_line: String::new(),
+            at_start: true,
+            bytes_written: 0,
+            lines_written: 0,
+        })
+    }
+
+    pub fn target(&self) -> &Path {
+        &self.target
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn lines_written(&self) -> usize {
+        self.lines_written
+    }
+
+    /// 追加一段增量；完整的行立即落盘，只保留最后一行未完成的部分
+    pub fn write(&mut self, delta: &str) -> Result<()> {
+        self.pending_line.push_str(delta);
+        while let Some(end) = self.pending_line.find('\n') {
+            let line: String = self.pending_line.drain(..=end).collect();
+            // 开头的 ```lang 是模型包裹内容的围栏，不属于文件
+            if std::mem::take(&mut self.at_start) && line.trim_start().starts_with("```") {
+                continue;
+            }
+            self.write_raw(&line)?;
+            self.lines_written += 1;
+        }
+        Ok(())
+    }
+
+    fn write_raw(&mut self, text: &str) -> Result<()> {
+        let writer = self.writer.as_mut().ok_or_else(|| ClaudeError::fs_error("Writer already closed"))?;
+        writer.write_all(text.as_bytes())?;
+        self.bytes_written += text.len() as u64;
+        Ok(())
+    }
+
+    /// 写完剩余内容并校验，通过后替换目标文件，返回写入的字节数
+    pub fn commit(mut self) -> Result<u64> {
+        let last = std::mem::take(&mut self.pending_line);
+        // 结尾的 ``` 同样是围栏
+        if last.trim() != "```" {
+            self.write_raw(&last)?;
+        }
+        let mut writer = self.writer.take().ok_or_else(|| ClaudeError::fs_error("Writer already closed"))?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+
+        validate(&self.partial, &self.target)?;
+        if let Ok(metadata) = std::fs::metadata(&self.target) {
+            std::fs::set_permissions(&self.partial, metadata.permissions())?;
+        }
+        std::fs::rename(&self.partial, &self.target)
+            .map_err(|e| ClaudeError::fs_error(format!("Failed to replace {}: {}", self.target.display(), e)))?;
+        Ok(self.bytes_written)
+    }
+}
+
+impl Drop for StreamingFileWriter {
+    /// 未提交（出错或被中断）时删除临时文件
+    fn drop(&mut self) {
+        if self.partial.exists() {
+            self.writer.take();
+            let _ = std::fs::remove_file(&self.partial);
+        }
+    }
+}
+
+/// 按目标扩展名校验生成的内容：结构化格式必须能解析，其他文件不能为空
+fn validate(partial: &Path, target: &Path) -> Result<()> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        ClaudeError::validation_error("output", format!("Generated content for {} is invalid: {}", target.display(), e))
+    };
+    if std::fs::metadata(partial)?.len() == 0 {
+        return Err(ClaudeError::validation_error("output", format!("Generated content for {} is empty", target.display())));
+    }
+    match target.extension().and_then(|e| e.to_str()) {
+        Some("json") => {
+            serde_json::from_reader::<_, serde::de::IgnoredAny>(BufReader::new(File::open(partial)?)).map_err(|e| invalid(&e))?;
+        }
+        Some("yaml") | Some("yml") => {
+            serde_yaml::from_reader::<_, serde::de::IgnoredAny>(BufReader::new(File::open(partial)?)).map_err(|e| invalid(&e))?;
+        }
+        Some("toml") => {
+            std::fs::read_to_string(partial)?.parse::<toml::Table>().map_err(|e| invalid(&e))?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_strips_fences_and_validates() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("out/config.json");
+
+        let mut writer = StreamingFileWriter::create(&target).unwrap();
+        for delta in ["```js", "on\n{\"na", "me\": \"demo\"}\n", "``", "`"] {
+            writer.write(delta).unwrap();
+        }
+        assert!(!target.exists());
+        writer.commit().unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "{\"name\": \"demo\"}\n");
+
+        // 校验失败时原文件不变，临时文件被清理
+        let mut writer = StreamingFileWriter::create(&target).unwrap();
+        writer.write("{\"truncated\": ").unwrap();
+        assert!(writer.commit().is_err());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "{\"name\": \"demo\"}\n");
+        assert_eq!(std::fs::read_dir(dir.path().join("out")).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,125 @@
+//! 文件改动的撤销日志
+//!
+//! 批量改动文件之前记下每个文件改动前后的内容，之后按记录恢复。
+//! 恢复时核对文件的当前内容，之后又被改过的文件不会被覆盖
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+
+/// 单个文件改动前后的内容
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    /// 绝对路径
+    pub path: PathBuf,
+    pub before: String,
+    pub after: String,
+}
+
+/// 一次可撤销的批量改动
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoEntry {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub description: String,
+    pub files: Vec<FileSnapshot>,
+}
+
+impl UndoEntry {
+    pub fn new(description: impl Into<String>, files: Vec<FileSnapshot>) -> Self {
+        let created_at = Utc::now();
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        Self {
+            id: format!("{}-{}", created_at.format("%Y%m%d%H%M%S"), &suffix[..8]),
+            created_at,
+            description: description.into(),
+            files,
+        }
+    }
+
+    /// 是否改动过 `root` 下的文件
+    pub fn touches(&self, root: &Path) -> bool {
+        self.files.iter().any(|file| file.path.starts_with(root))
+    }
+}
+
+/// 撤销的结果
+#[derive(Debug, Default)]
+pub struct UndoOutcome {
+    pub restored: Vec<PathBuf>,
+    /// 之后又被修改过而没有恢复的文件
+    pub conflicts: Vec<PathBuf>,
+}
+
+/// 撤销日志，每条记录保存为一个 JSON 文件
+#[derive(Debug, Clone)]
+pub struct UndoJournal {
+    dir: PathBuf,
+}
+
+impl UndoJournal {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 默认的日志目录
+    pub fn default_dir() -> PathBuf {
+        dirs::data_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("undo")
+    }
+
+    fn entry_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    pub fn record(&self, entry: &UndoEntry) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.entry_path(&entry.id), serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, id: &str) -> Result<()> {
+        match std::fs::remove_file(self.entry_path(id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// 所有记录，最新的在前；无法解析的文件被忽略
+    pub fn list(&self) -> Result<Vec<UndoEntry>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut records: Vec<UndoEntry> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+            .filter_map(|content| serde_json::from_str(&content).ok())
+            .collect();
+        records.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.id.cmp(&a.id)));
+        Ok(records)
+    }
+
+    /// 恢复一条记录中的文件；`force` 时覆盖之后又被修改过的文件。
+    /// 全部恢复后删除该记录
+    pub fn undo(&self, entry: &UndoEntry, force: bool) -> Result<UndoOutcome> {
+        let mut outcome = UndoOutcome::default();
+        for file in &entry.files {
+            let current = std::fs::read_to_string(&file.path).ok();
+            if !force && current.as_deref() != Some(file.after.as_str()) {
+                outcome.conflicts.push(file.path.clone());
+                continue;
+            }
+            std::fs::write(&file.path, &file.before)
+                .map_err(|e| ClaudeError::fs_error(format!("Failed to restore {}: {}", file.path.display(), e)))?;
+            outcome.restored.push(file.path.clone());
+        }
+        if outcome.conflicts.is_empty() {
+            self.remove(&entry.id)?;
+        }
+        Ok(outcome)
+    }
+}
@@ -0,0 +1,391 @@
+//! 本地存储回收
+//!
+//! 按保留策略清理会话记录、产物、缓存和日志：先删除超过保留天数的文件，
+//! 再从最旧的文件开始删除，直到每类数据的总大小回到上限以内。
+//! 最近修改过的文件视为仍在使用，不会被删除
+
+use crate::config::{ClaudeConfig, RetentionPolicy};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use walkdir::WalkDir;
+
+/// 最近这段时间内修改过的文件不会被回收
+const DEFAULT_MIN_AGE: Duration = Duration::from_secs(10 * 60);
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// 数据类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageClass {
+    Sessions,
+    Artifacts,
+    Caches,
+    Logs,
+}
+
+impl StorageClass {
+    /// 所有类别，按报告顺序排列
+    pub const ALL: [StorageClass; 4] = [Self::Sessions, Self::Artifacts, Self::Caches, Self::Logs];
+
+    /// 显示名称
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Sessions => "sessions",
+            Self::Artifacts => "artifacts",
+            Self::Caches => "caches",
+            Self::Logs => "logs",
+        }
+    }
+}
+
+/// 一个受保留策略约束的存储位置
+#[derive(Debug, Clone)]
+pub struct StorageLocation {
+    /// 数据类别
+    pub class: StorageClass,
+    /// 目录
+    pub dir: PathBuf,
+    /// 只统计以此开头的文件（设置后只扫描目录顶层）
+    pub prefix: Option<String>,
+    /// 保留策略
+    pub policy: RetentionPolicy,
+}
+
+impl StorageLocation {
+    /// 递归管理整个目录
+    pub fn new(class: StorageClass, dir: impl Into<PathBuf>, policy: RetentionPolicy) -> Self {
+        Self {
+            class,
+            dir: dir.into(),
+            prefix: None,
+            policy,
+        }
+    }
+
+    /// 只管理目录顶层中以 `prefix` 开头的文件，用于与其他文件共用的目录
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    fn files(&self) -> Vec<StoredFile> {
+        let walker = WalkDir::new(&self.dir).min_depth(1);
+        let walker = if self.prefix.is_some() { walker.max_depth(1) } else { walker };
+
+        walker
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| match &self.prefix {
+                Some(prefix) => entry.file_name().to_string_lossy().starts_with(prefix.as_str()),
+                None => true,
+            })
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some(StoredFile {
+                    path: entry.into_path(),
+                    size: metadata.len(),
+                    modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                })
+            })
+            .collect()
+    }
+}
+
+struct StoredFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// 文件被回收的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcReason {
+    /// 超过保留天数
+    Expired,
+    /// 所在类别超过总大小上限
+    OverQuota,
+}
+
+/// 将被（或已被）删除的文件
+#[derive(Debug, Clone)]
+pub struct GcCandidate {
+    pub class: StorageClass,
+    pub path: PathBuf,
+    pub size: u64,
+    pub reason: GcReason,
+}
+
+/// 一次回收的结果
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    /// 待删除（或已删除）的文件
+    pub candidates: Vec<GcCandidate>,
+    /// 扫描的文件数
+    pub scanned_files: usize,
+    /// 扫描的总字节数
+    pub scanned_bytes: u64,
+    /// 实际释放的字节数，演练时为 0
+    pub freed_bytes: u64,
+    /// 是否为演练
+    pub dry_run: bool,
+    /// 删除失败的说明
+    pub errors: Vec<String>,
+}
+
+impl GcReport {
+    /// 可回收的总字节数
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.candidates.iter().map(|c| c.size).sum()
+    }
+
+    /// 某一类别的可回收文件数和字节数
+    pub fn class_totals(&self, class: StorageClass) -> (usize, u64) {
+        self.candidates
+            .iter()
+            .filter(|c| c.class == class)
+            .fold((0, 0), |(count, bytes), c| (count + 1, bytes + c.size))
+    }
+}
+
+/// 按保留策略回收本地存储
+#[derive(Debug, Clone)]
+pub struct GarbageCollector {
+    locations: Vec<StorageLocation>,
+    min_age: Duration,
+}
+
+impl GarbageCollector {
+    /// 管理给定的存储位置
+    pub fn new(locations: Vec<StorageLocation>) -> Self {
+        Self {
+            locations,
+            min_age: DEFAULT_MIN_AGE,
+        }
+    }
+
+    /// 按配置管理默认的会话、产物、缓存和日志目录
+    pub fn from_config(config: &ClaudeConfig) -> Self {
+        let retention = &config.retention;
+        let mut locations = vec![StorageLocation::new(
+            StorageClass::Sessions,
+            crate::conversation::ConversationManager::default_storage_dir(),
+            retention.sessions.clone(),
+        )];
+        if let Some(data_dir) = dirs::data_dir() {
+            locations.push(StorageLocation::new(
+                StorageClass::Artifacts,
+                data_dir.join("claude-code").join("artifacts"),
+                retention.artifacts.clone(),
+            ));
+        }
+        if let Some(cache_dir) = dirs::cache_dir() {
+            locations.push(StorageLocation::new(
+                StorageClass::Caches,
+                cache_dir.join("claude-code"),
+                retention.caches.clone(),
+            ));
+        }
+        // 日志按天滚动，文件名以配置的日志文件名（不含扩展名）开头
+        if let Some(log_file) = &config.logging.file {
+            let dir = log_file.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            let stem = log_file.file_stem().and_then(|s| s.to_str()).unwrap_or("claude-code");
+            locations.push(StorageLocation::new(StorageClass::Logs, dir, retention.logs.clone()).with_prefix(stem));
+        }
+        Self::new(locations)
+    }
+
+    /// 计算将被回收的文件，不做删除
+    pub fn plan(&self) -> GcReport {
+        let now = SystemTime::now();
+        let mut report = GcReport {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        for location in &self.locations {
+            let mut files = location.files();
+            report.scanned_files += files.len();
+            report.scanned_bytes += files.iter().map(|f| f.size).sum::<u64>();
+
+            files.sort_by_key(|f| f.modified);
+            let age = |file: &StoredFile| now.duration_since(file.modified).unwrap_or_default();
+            let max_age = location.policy.max_age_days.map(|days| Duration::from_secs(days * SECONDS_PER_DAY));
+
+            let mut kept = Vec::new();
+            for file in files {
+                let file_age = age(&file);
+                if file_age >= self.min_age && max_age.is_some_and(|max| file_age > max) {
+                    report.candidates.push(candidate(location.class, file, GcReason::Expired));
+                } else {
+                    kept.push(file);
+                }
+            }
+
+            if let Some(max_mb) = location.policy.max_total_mb {
+                let quota = max_mb.saturating_mul(BYTES_PER_MB);
+                let mut total: u64 = kept.iter().map(|f| f.size).sum();
+                // kept 按修改时间升序，从最旧的开始删除
+                for file in kept {
+                    if total <= quota {
+                        break;
+                    }
+                    if age(&file) < self.min_age {
+                        continue;
+                    }
+                    total -= file.size;
+                    report.candidates.push(candidate(location.class, file, GcReason::OverQuota));
+                }
+            }
+        }
+
+        report
+    }
+
+    /// 执行回收，`dry_run` 为真时只报告
+    pub fn run(&self, dry_run: bool) -> GcReport {
+        let mut report = self.plan();
+        report.dry_run = dry_run;
+        if dry_run {
+            return report;
+        }
+
+        for candidate in &report.candidates {
+            match std::fs::remove_file(&candidate.path) {
+                Ok(()) => {
+                    report.freed_bytes += candidate.size;
+                    self.prune_empty_parents(&candidate.path);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => report.errors.push(format!("{}: {}", candidate.path.display(), e)),
+            }
+        }
+        report
+    }
+
+    /// 删除文件后逐级移除变空的上级目录（如过期会话的临时工作区），不移除存储位置本身
+    fn prune_empty_parents(&self, path: &Path) {
+        let Some(root) = self.locations.iter().map(|l| l.dir.as_path()).find(|dir| path.starts_with(dir)) else {
+            return;
+        };
+        let mut dir = path.parent();
+        while let Some(current) = dir.filter(|d| *d != root && d.starts_with(root)) {
+            if std::fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+
+    /// 在后台按间隔定期回收
+    pub fn spawn_background(self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let collector = self.clone();
+                match tokio::task::spawn_blocking(move || collector.run(false)).await {
+                    Ok(report) => {
+                        if !report.candidates.is_empty() {
+                            tracing::debug!(
+                                "Storage GC removed {} file(s), freed {} bytes",
+                                report.candidates.len() - report.errors.len(),
+                                report.freed_bytes
+                            );
+                        }
+                        for error in &report.errors {
+                            tracing::warn!("Storage GC failed to remove {}", error);
+                        }
+                    }
+                    Err(e) => tracing::warn!("Storage GC task failed: {}", e),
+                }
+            }
+        })
+    }
+}
+
+fn candidate(class: StorageClass, file: StoredFile, reason: GcReason) -> GcCandidate {
+    GcCandidate {
+        class,
+        path: file.path,
+        size: file.size,
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, size: usize, age_days: u64) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![b'x'; size]).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age_days * SECONDS_PER_DAY);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_expired_and_over_quota_files_are_collected() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let expired = write(dir, "expired.json", 10, 40);
+        let oldest = write(dir, "old.json", 400 * 1024, 5);
+        let newer = write(dir, "newer.json", 400 * 1024, 2);
+        let fresh = write(dir, "fresh.json", 400 * 1024, 0);
+
+        let policy = RetentionPolicy { max_age_days: Some(30), max_total_mb: Some(1) };
+        let collector = GarbageCollector::new(vec![StorageLocation::new(StorageClass::Sessions, dir, policy)]);
+
+        let plan = collector.run(true);
+        assert!(plan.dry_run);
+        assert_eq!(plan.scanned_files, 4);
+        let reasons: Vec<_> = plan.candidates.iter().map(|c| (c.path.clone(), c.reason)).collect();
+        assert_eq!(
+            reasons,
+            vec![(expired.clone(), GcReason::Expired), (oldest.clone(), GcReason::OverQuota)]
+        );
+        assert_eq!(plan.class_totals(StorageClass::Sessions), (2, 10 + 400 * 1024));
+        assert!(expired.exists());
+
+        let report = collector.run(false);
+        assert_eq!(report.freed_bytes, 10 + 400 * 1024);
+        assert!(!expired.exists() && !oldest.exists());
+        assert!(newer.exists() && fresh.exists());
+    }
+
+    #[test]
+    fn test_prefix_limits_scope_and_recent_files_are_kept() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let old_log = write(dir, "claude.2026-01-01", 10, 100);
+        let other = write(dir, "notes.txt", 10, 100);
+        let recent = dir.join("claude.today");
+        std::fs::write(&recent, vec![b'x'; 2 * BYTES_PER_MB as usize]).unwrap();
+
+        let policy = RetentionPolicy { max_age_days: Some(14), max_total_mb: Some(1) };
+        let location = StorageLocation::new(StorageClass::Logs, dir, policy).with_prefix("claude");
+        let report = GarbageCollector::new(vec![location]).run(false);
+
+        assert_eq!(report.candidates.len(), 1);
+        assert!(!old_log.exists());
+        assert!(other.exists());
+        assert!(recent.exists());
+    }
+
+    #[test]
+    fn test_emptied_directories_are_pruned() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let session = dir.join("scratch").join("old-session");
+        std::fs::create_dir_all(session.join("downloads")).unwrap();
+        write(&session.join("downloads"), "page.html", 10, 40);
+
+        let policy = RetentionPolicy { max_age_days: Some(30), max_total_mb: None };
+        GarbageCollector::new(vec![StorageLocation::new(StorageClass::Artifacts, dir, policy)]).run(false);
+
+        assert!(!dir.join("scratch").exists());
+        assert!(dir.exists());
+    }
+}
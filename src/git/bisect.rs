@@ -0,0 +1,146 @@
+//! 自动二分定位
+//!
+//! 解析 `git bisect` 的输出判断当前待测提交或已定位的首个坏提交，
+//! 按 `git bisect run` 的约定把测试命令的退出码映射为 good/bad/skip，
+//! 并生成请模型分析问题提交的提示
+
+/// 提示中提交差异的最大字符数
+const MAX_SHOW_CHARS: usize = 60_000;
+
+/// 提示中测试输出保留的末尾行数
+const TEST_OUTPUT_TAIL: usize = 40;
+
+/// `git bisect` 每一步之后的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BisectState {
+    /// 已检出待测提交
+    Testing {
+        commit: String,
+        /// 剩余的大致步数
+        steps_left: Option<u32>,
+    },
+    /// 已找到首个坏提交
+    Found { commit: String },
+    /// 只剩下被跳过的提交，无法确定
+    Inconclusive,
+}
+
+/// 解析 `git bisect start/good/bad/skip` 的输出
+pub fn parse_bisect_output(output: &str) -> Option<BisectState> {
+    if output.contains("only 'skip'ped commits left to test") {
+        return Some(BisectState::Inconclusive);
+    }
+    for line in output.lines() {
+        if let Some(commit) = line.strip_suffix(" is the first bad commit") {
+            return Some(BisectState::Found { commit: commit.trim().to_string() });
+        }
+    }
+
+    // Bisecting: 6 revisions left to test after this (roughly 3 steps)
+    // [0123abcd...] subject
+    let steps_left = output
+        .split("(roughly ")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|steps| steps.parse().ok());
+    output.lines().find_map(|line| {
+        let rest = line.strip_prefix('[')?;
+        let (commit, _) = rest.split_once(']')?;
+        commit
+            .chars()
+            .all(|c| c.is_ascii_hexdigit())
+            .then(|| BisectState::Testing { commit: commit.to_string(), steps_left })
+    })
+}
+
+/// 测试命令对一个提交的判定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Good,
+    Bad,
+    Skip,
+    /// 退出码不小于 128（如被信号终止），停止二分
+    Abort,
+}
+
+impl Verdict {
+    /// 按 `git bisect run` 的约定解释退出码：0 为好，125 跳过，1-127 为坏
+    pub fn from_exit_code(code: Option<i32>) -> Self {
+        match code {
+            Some(0) => Self::Good,
+            Some(125) => Self::Skip,
+            Some(1..=127) => Self::Bad,
+            _ => Self::Abort,
+        }
+    }
+
+    /// 对应的 `git bisect` 子命令
+    pub fn subcommand(&self) -> Option<&'static str> {
+        match self {
+            Self::Good => Some("good"),
+            Self::Bad => Some("bad"),
+            Self::Skip => Some("skip"),
+            Self::Abort => None,
+        }
+    }
+}
+
+/// 请模型总结问题提交和可能根因的提示
+pub fn culprit_prompt(test_command: &str, commit_show: &str, failing_output: &[String]) -> String {
+    let mut show: String = commit_show.chars().take(MAX_SHOW_CHARS).collect();
+    if show.len() < commit_show.len() {
+        show.push_str("\n… (diff truncated)");
+    }
+    let tail = &failing_output[failing_output.len().saturating_sub(TEST_OUTPUT_TAIL)..];
+
+    let mut prompt = format!(
+        "`git bisect` identified the commit below as the first one where `{}` fails.\n\
+         Summarize what the commit changed, explain the most likely root cause of the failure \
+         (point at specific files and lines), and suggest a fix. Be concise.\n\n\
+         ```\n{}\n```\n",
+        test_command, show
+    );
+    if !tail.is_empty() {
+        prompt.push_str(&format!("\nTest output at this commit (last {} lines):\n```\n{}\n```\n", tail.len(), tail.join("\n")));
+    }
+    prompt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bisect_output() {
+        let testing = "Bisecting: 6 revisions left to test after this (roughly 3 steps)\n\
+                       [4f9c2a1be0d7c3e8a6b5f4e3d2c1b0a9f8e7d6c5] Refactor parser";
+        assert_eq!(
+            parse_bisect_output(testing),
+            Some(BisectState::Testing {
+                commit: "4f9c2a1be0d7c3e8a6b5f4e3d2c1b0a9f8e7d6c5".to_string(),
+                steps_left: Some(3)
+            })
+        );
+
+        let found = "4f9c2a1be0d7c3e8a6b5f4e3d2c1b0a9f8e7d6c5 is the first bad commit\ncommit 4f9c2a1\nAuthor: A <a@example.com>";
+        assert_eq!(
+            parse_bisect_output(found),
+            Some(BisectState::Found { commit: "4f9c2a1be0d7c3e8a6b5f4e3d2c1b0a9f8e7d6c5".to_string() })
+        );
+        assert_eq!(
+            parse_bisect_output("There are only 'skip'ped commits left to test.\nThe first bad commit could be any of:"),
+            Some(BisectState::Inconclusive)
+        );
+        assert_eq!(parse_bisect_output("status: waiting for good commit(s)"), None);
+    }
+
+    #[test]
+    fn test_exit_codes_follow_bisect_run() {
+        assert_eq!(Verdict::from_exit_code(Some(0)), Verdict::Good);
+        assert_eq!(Verdict::from_exit_code(Some(101)), Verdict::Bad);
+        assert_eq!(Verdict::from_exit_code(Some(125)), Verdict::Skip);
+        assert_eq!(Verdict::from_exit_code(Some(130)), Verdict::Abort);
+        assert_eq!(Verdict::from_exit_code(None), Verdict::Abort);
+        assert_eq!(Verdict::Bad.subcommand(), Some("bad"));
+    }
+}
@@ -0,0 +1,305 @@
+//! 会话检查点
+//!
+//! 每轮对话结束时把整个工作区（包括未跟踪但未被忽略的文件）写成一个 git 提交对象，
+//! 记在 `refs/claude/checkpoints/<会话>/<回合>` 下；用临时索引完成，不动真实索引、分支和工作区。
+//! 两个检查点之间的差异就是这段回合里对文件做的全部改动
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tokio::process::Command as AsyncCommand;
+
+use crate::error::{ClaudeError, Result};
+
+/// 检查点引用的命名空间
+pub const CHECKPOINT_REF_PREFIX: &str = "refs/claude/checkpoints";
+
+/// 用户没有配置 git 身份时检查点提交使用的作者
+const CHECKPOINT_AUTHOR: (&str, &str) = ("claude-rust", "claude-rust@localhost");
+
+/// 一个检查点：某个会话第 `turn` 轮结束时（0 为会话开始前）的工作区
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Checkpoint {
+    pub session: String,
+    pub turn: usize,
+    pub commit: String,
+    pub created_at: DateTime<Utc>,
+    pub label: String,
+}
+
+/// 单个文件的增删行数，二进制文件为 `None`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileChangeStat {
+    pub path: String,
+    pub added: Option<usize>,
+    pub deleted: Option<usize>,
+}
+
+/// 两个检查点之间的累计差异
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckpointDiff {
+    pub from: String,
+    pub to: String,
+    pub files: Vec<FileChangeStat>,
+    pub patch: String,
+}
+
+impl CheckpointDiff {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// 文件统计和补丁；`color` 时用 ANSI 颜色标出增删行
+    pub fn render(&self, color: bool) -> String {
+        if self.is_empty() {
+            return "No file changes between these points.\n".to_string();
+        }
+        let added: usize = self.files.iter().filter_map(|f| f.added).sum();
+        let deleted: usize = self.files.iter().filter_map(|f| f.deleted).sum();
+        let mut output = format!("📊 {} file(s) changed, +{} -{}\n", self.files.len(), added, deleted);
+        for file in &self.files {
+            match (file.added, file.deleted) {
+                (Some(added), Some(deleted)) => output.push_str(&format!("  {:>6} {:>6}  {}\n", format!("+{}", added), format!("-{}", deleted), file.path)),
+                _ => output.push_str(&format!("  {:>13}  {}\n", "binary", file.path)),
+            }
+        }
+        output.push('\n');
+        for line in self.patch.lines() {
+            let code = match line.as_bytes().first() {
+                _ if !color => None,
+                Some(b'+') if !line.starts_with("+++") => Some("32"),
+                Some(b'-') if !line.starts_with("---") => Some("31"),
+                Some(b'@') => Some("36"),
+                _ if line.starts_with("diff --git") => Some("1"),
+                _ => None,
+            };
+            match code {
+                Some(code) => output.push_str(&format!("\x1b[{}m{}\x1b[0m\n", code, line)),
+                None => {
+                    output.push_str(line);
+                    output.push('\n');
+                }
+            }
+        }
+        output
+    }
+}
+
+/// 仓库中的检查点存储
+pub struct CheckpointStore {
+    working_dir: PathBuf,
+}
+
+impl CheckpointStore {
+    pub fn new(working_dir: impl Into<PathBuf>) -> Self {
+        Self { working_dir: working_dir.into() }
+    }
+
+    async fn git(&self, args: &[&str], index: Option<&Path>) -> Result<String> {
+        let mut command = AsyncCommand::new("git");
+        command.args(args).current_dir(&self.working_dir);
+        if let Some(index) = index {
+            command.env("GIT_INDEX_FILE", index);
+        }
+        for (name, value) in [("GIT_AUTHOR_NAME", CHECKPOINT_AUTHOR.0), ("GIT_AUTHOR_EMAIL", CHECKPOINT_AUTHOR.1)] {
+            if std::env::var_os(name).is_none() {
+                command.env(name, value);
+            }
+        }
+        for (name, value) in [("GIT_COMMITTER_NAME", CHECKPOINT_AUTHOR.0), ("GIT_COMMITTER_EMAIL", CHECKPOINT_AUTHOR.1)] {
+            if std::env::var_os(name).is_none() {
+                command.env(name, value);
+            }
+        }
+        let output = command
+            .output()
+            .await
+            .map_err(|e| ClaudeError::General(format!("Failed to run git: {}", e)))?;
+        if !output.status.success() {
+            return Err(ClaudeError::General(format!(
+                "git {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// 记录当前工作区为会话 `session` 第 `turn` 轮的检查点，同一回合再次记录时覆盖
+    pub async fn create(&self, session: &str, turn: usize, label: &str) -> Result<Checkpoint> {
+        // 从真实索引的副本开始，未修改的文件可以沿用其中的状态信息而不必重新哈希
+        let index = PathBuf::from(self.git(&["rev-parse", "--path-format=absolute", "--git-path", "claude-checkpoint.index"], None).await?.trim());
+        let real_index = PathBuf::from(self.git(&["rev-parse", "--path-format=absolute", "--git-path", "index"], None).await?.trim());
+        if real_index.is_file() {
+            std::fs::copy(&real_index, &index)?;
+        } else {
+            let _ = std::fs::remove_file(&index);
+        }
+
+        let tree = async {
+            self.git(&["add", "--all", "--", "."], Some(&index)).await?;
+            self.git(&["write-tree"], Some(&index)).await
+        }
+        .await;
+        let _ = std::fs::remove_file(&index);
+        let tree = tree?;
+
+        let message = format!("checkpoint {} turn {}: {}", session, turn, label);
+        let mut args = vec!["commit-tree", tree.trim(), "-m", &message];
+        let head = self.git(&["rev-parse", "--verify", "--quiet", "HEAD"], None).await.ok();
+        if let Some(head) = head.as_deref().map(str::trim) {
+            args.extend(["-p", head]);
+        }
+        let commit = self.git(&args, None).await?.trim().to_string();
+        let reference = format!("{}/{}/{}", CHECKPOINT_REF_PREFIX, session, turn);
+        self.git(&["update-ref", &reference, &commit], None).await?;
+
+        Ok(Checkpoint { session: session.to_string(), turn, commit, created_at: Utc::now(), label: label.to_string() })
+    }
+
+    /// 检查点列表，按会话和回合排序；`session` 为空时列出所有会话
+    pub async fn list(&self, session: Option<&str>) -> Result<Vec<Checkpoint>> {
+        let prefix = match session {
+            Some(session) => format!("{}/{}", CHECKPOINT_REF_PREFIX, session),
+            None => CHECKPOINT_REF_PREFIX.to_string(),
+        };
+        let output = self
+            .git(&["for-each-ref", "--format=%(refname)%09%(objectname)%09%(committerdate:iso-strict)%09%(contents:subject)", &prefix], None)
+            .await?;
+
+        let mut checkpoints: Vec<Checkpoint> = output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                let reference = fields.next()?.strip_prefix(CHECKPOINT_REF_PREFIX)?.trim_start_matches('/');
+                let (session, turn) = reference.rsplit_once('/')?;
+                let commit = fields.next()?.to_string();
+                let created_at = DateTime::parse_from_rfc3339(fields.next()?).ok()?.with_timezone(&Utc);
+                let subject = fields.next().unwrap_or_default();
+                let label = subject.split_once(": ").map_or(subject, |(_, label)| label).to_string();
+                Some(Checkpoint { session: session.to_string(), turn: turn.parse().ok()?, commit, created_at, label })
+            })
+            .collect();
+        checkpoints.sort_by(|a, b| (&a.session, a.turn).cmp(&(&b.session, b.turn)));
+        Ok(checkpoints)
+    }
+
+    /// 会话下一个检查点的回合号：还没有检查点时为 0（会话开始前）
+    pub async fn next_turn(&self, session: &str) -> Result<usize> {
+        Ok(self.list(Some(session)).await?.last().map_or(0, |c| c.turn + 1))
+    }
+
+    /// 最近记录过检查点的会话
+    pub async fn latest_session(&self) -> Result<Option<String>> {
+        Ok(self.list(None).await?.into_iter().max_by_key(|c| c.created_at).map(|c| c.session))
+    }
+
+    /// 把 `<回合>`、`<会话>:<回合>` 或任意 git 修订解析为提交
+    pub async fn resolve(&self, session: Option<&str>, spec: &str) -> Result<String> {
+        let (session, turn) = match spec.rsplit_once(':') {
+            Some((session, turn)) if turn.parse::<usize>().is_ok() => (Some(session.to_string()), turn),
+            _ => (session.map(str::to_string), spec),
+        };
+        let Ok(turn) = turn.trim_start_matches("turn").parse::<usize>() else {
+            let commit = self.git(&["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", spec)], None).await;
+            return commit
+                .map(|commit| commit.trim().to_string())
+                .map_err(|_| ClaudeError::validation_error("checkpoint", format!("'{}' is neither a turn number nor a git revision", spec)));
+        };
+
+        let session = match session {
+            Some(session) => session,
+            None => self
+                .latest_session()
+                .await?
+                .ok_or_else(|| ClaudeError::validation_error("checkpoint", "No checkpoints recorded in this repository yet"))?,
+        };
+        let checkpoints = self.list(Some(&session)).await?;
+        checkpoints.iter().find(|c| c.turn == turn).map(|c| c.commit.clone()).ok_or_else(|| {
+            let turns: Vec<String> = checkpoints.iter().map(|c| c.turn.to_string()).collect();
+            ClaudeError::validation_error(
+                "checkpoint",
+                format!("No checkpoint for turn {} in session {} (available turns: {})", turn, session, turns.join(", ")),
+            )
+        })
+    }
+
+    /// 两个提交之间的累计差异
+    pub async fn diff(&self, from: &str, to: &str) -> Result<CheckpointDiff> {
+        let numstat = self.git(&["diff", "--no-ext-diff", "--numstat", from, to], None).await?;
+        let files = numstat
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                let added = fields.next()?.parse().ok();
+                let deleted = fields.next()?.parse().ok();
+                Some(FileChangeStat { path: fields.next()?.to_string(), added, deleted })
+            })
+            .collect();
+        let patch = self.git(&["diff", "--no-ext-diff", "--no-color", from, to], None).await?;
+        Ok(CheckpointDiff { from: from.to_string(), to: to.to_string(), files, patch })
+    }
+
+    /// 会话第 `first` 到第 `last` 轮（含两端）对文件的改动：从第 `first` 轮之前最近的检查点比到第 `last` 轮
+    pub async fn diff_turns(&self, session: &str, first: usize, last: usize) -> Result<CheckpointDiff> {
+        if first == 0 || first > last {
+            return Err(ClaudeError::validation_error("turns", "Turns are numbered from 1 and the first must not be after the last"));
+        }
+        let checkpoints = self.list(Some(session)).await?;
+        let before = checkpoints
+            .iter()
+            .filter(|c| c.turn < first)
+            .max_by_key(|c| c.turn)
+            .ok_or_else(|| ClaudeError::validation_error("turns", format!("No checkpoint before turn {} in this session", first)))?;
+        let after = checkpoints
+            .iter()
+            .find(|c| c.turn == last)
+            .ok_or_else(|| ClaudeError::validation_error("turns", format!("No checkpoint for turn {} in this session", last)))?;
+        self.diff(&before.commit, &after.commit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checkpoints_and_turn_diff() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let git = |args: &[&str]| {
+            assert!(std::process::Command::new("git").args(args).current_dir(root).output().unwrap().status.success());
+        };
+        git(&["init", "-q"]);
+        std::fs::write(root.join("kept.txt"), "one\n").unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+
+        let store = CheckpointStore::new(root);
+        store.create("s1", 0, "session start").await.unwrap();
+        std::fs::write(root.join("kept.txt"), "one\ntwo\n").unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::write(root.join("target/ignored.txt"), "x").unwrap();
+        store.create("s1", 1, "add a line").await.unwrap();
+        std::fs::write(root.join("new.rs"), "fn main() {}\n").unwrap();
+        store.create("s1", 2, "new file").await.unwrap();
+
+        assert_eq!(store.next_turn("s1").await.unwrap(), 3);
+        let checkpoints = store.list(Some("s1")).await.unwrap();
+        assert_eq!(checkpoints.iter().map(|c| c.turn).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(checkpoints[2].label, "new file");
+        assert_eq!(store.latest_session().await.unwrap().as_deref(), Some("s1"));
+
+        let diff = store.diff_turns("s1", 1, 2).await.unwrap();
+        let paths: Vec<&str> = diff.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["kept.txt", "new.rs"]);
+        assert!(diff.patch.contains("+two"));
+        assert!(diff.render(false).starts_with("📊 2 file(s) changed, +2 -0"));
+
+        let from = store.resolve(None, "2").await.unwrap();
+        let to = store.resolve(None, "s1:2").await.unwrap();
+        assert!(store.diff(&from, &to).await.unwrap().is_empty());
+        assert!(store.resolve(None, "7").await.is_err());
+        // 真实索引没有被改动
+        git(&["diff", "--cached", "--quiet"]);
+    }
+}
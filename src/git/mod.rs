@@ -2,6 +2,11 @@
 //! 
 //! 实现Git操作集成，包括提交、分支管理、差异查看等
 
+pub mod bisect;
+pub mod checkpoint;
+pub mod refs;
+pub mod resolve;
+
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -263,6 +268,123 @@ impl GitManager {
         })
     }
 
+    /// 仓库根目录
+    pub async fn repository_root(&self) -> Result<PathBuf> {
+        let output = AsyncCommand::new("git")
+            .args(["rev-parse", "--show-toplevel"])
+            .current_dir(&self.working_dir)
+            .output()
+            .await
+            .map_err(|e| ClaudeError::General(format!("Failed to find repository root: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(ClaudeError::General("Not a git repository".to_string()));
+        }
+
+        Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+    }
+
+    /// 存在未解决合并冲突的文件（相对仓库根目录）
+    pub async fn conflicted_files(&self) -> Result<Vec<String>> {
+        let output = AsyncCommand::new("git")
+            .args(["diff", "--name-only", "--diff-filter=U"])
+            .current_dir(&self.working_dir)
+            .output()
+            .await
+            .map_err(|e| ClaudeError::General(format!("Failed to list conflicted files: {}", e)))?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(ClaudeError::General(format!("Git diff failed: {}", error)));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// 执行 `git bisect <args>`，返回标准输出
+    pub async fn bisect(&self, args: &[&str]) -> Result<String> {
+        let output = AsyncCommand::new("git")
+            .arg("bisect")
+            .args(args)
+            .current_dir(&self.working_dir)
+            .output()
+            .await
+            .map_err(|e| ClaudeError::General(format!("Failed to run git bisect: {}", e)))?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(ClaudeError::General(format!("Git bisect failed: {}", error.trim())));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// 提交的说明和完整差异（`git show --stat --patch`）
+    pub async fn show_commit(&self, rev: &str) -> Result<String> {
+        let output = AsyncCommand::new("git")
+            .args(["show", "--stat", "--patch", "--format=fuller", rev])
+            .current_dir(&self.working_dir)
+            .output()
+            .await
+            .map_err(|e| ClaudeError::General(format!("Failed to show commit: {}", e)))?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(ClaudeError::General(format!("Git show failed: {}", error.trim())));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// `rev` 之前最近的标签
+    pub async fn latest_tag(&self, rev: &str) -> Result<Option<String>> {
+        let output = AsyncCommand::new("git")
+            .args(["describe", "--tags", "--abbrev=0", rev])
+            .current_dir(&self.working_dir)
+            .output()
+            .await
+            .map_err(|e| ClaudeError::General(format!("Failed to describe commit: {}", e)))?;
+
+        let tag = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Ok((output.status.success() && !tag.is_empty()).then_some(tag))
+    }
+
+    /// 执行任意 git 子命令，返回合并后的标准输出和标准错误；失败时报告标准错误
+    pub async fn run(&self, args: &[String]) -> Result<String> {
+        let output = AsyncCommand::new("git")
+            .args(args)
+            .current_dir(&self.working_dir)
+            .output()
+            .await
+            .map_err(|e| ClaudeError::General(format!("Failed to run git: {}", e)))?;
+
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        if !output.status.success() {
+            return Err(ClaudeError::General(format!("git {} failed: {}", args.join(" "), text.trim())));
+        }
+        Ok(text)
+    }
+
+    /// 本地分支、远程分支和标签的短名称
+    pub async fn list_refs(&self) -> Result<Vec<refs::GitRef>> {
+        let output = AsyncCommand::new("git")
+            .args(["for-each-ref", "--format=%(refname)", "refs/heads", "refs/remotes", "refs/tags"])
+            .current_dir(&self.working_dir)
+            .output()
+            .await
+            .map_err(|e| ClaudeError::General(format!("Failed to list refs: {}", e)))?;
+
+        if !output.status.success() {
+            return Ok(Vec::new());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).lines().filter_map(refs::GitRef::parse).collect())
+    }
+
     /// 添加文件到暂存区
     pub async fn add_files(&self, files: &[String]) -> Result<()> {
         let mut cmd = AsyncCommand::new("git");
@@ -0,0 +1,201 @@
+//! Git 引用补全
+//!
+//! 交互模式中 `/git checkout`、`/git diff` 等命令的参数补全，候选为分支、标签和最近提交的短哈希。
+//! 查询结果缓存一小段时间，通过 `/git` 执行命令后立即作废
+
+use std::time::{Duration, Instant};
+
+use super::GitManager;
+
+/// 补全候选中最近提交的数量
+pub const RECENT_COMMITS: u32 = 20;
+
+/// 缓存有效期，覆盖在其他终端里执行的 git 操作
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// 参数是引用的子命令
+const REF_SUBCOMMANDS: &[&str] = &[
+    "branch", "checkout", "cherry-pick", "diff", "log", "merge", "rebase", "reset", "revert", "show", "switch", "tag",
+];
+
+/// `/git` 之后可补全的其他子命令
+const OTHER_SUBCOMMANDS: &[&str] = &["add", "blame", "commit", "fetch", "pull", "push", "stash", "status"];
+
+/// 引用的种类，决定候选的排列顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RefKind {
+    Branch,
+    Tag,
+    Remote,
+    Commit,
+}
+
+/// 一个可补全的引用
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitRef {
+    pub name: String,
+    pub kind: RefKind,
+}
+
+impl GitRef {
+    /// 解析 `git for-each-ref` 输出的完整引用名
+    pub fn parse(full_name: &str) -> Option<Self> {
+        let (kind, name) = if let Some(name) = full_name.strip_prefix("refs/heads/") {
+            (RefKind::Branch, name)
+        } else if let Some(name) = full_name.strip_prefix("refs/tags/") {
+            (RefKind::Tag, name)
+        } else {
+            (RefKind::Remote, full_name.strip_prefix("refs/remotes/")?)
+        };
+        // origin/HEAD 只是指向默认分支的符号引用
+        if kind == RefKind::Remote && name.ends_with("/HEAD") {
+            return None;
+        }
+        Some(Self { name: name.to_string(), kind })
+    }
+}
+
+/// 补全结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Completion {
+    /// 被补全部分在输入行中的起始字节位置
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+impl Completion {
+    /// 所有候选的公共前缀
+    pub fn common_prefix(&self) -> String {
+        let Some(first) = self.candidates.first() else {
+            return String::new();
+        };
+        let mut prefix = first.clone();
+        for candidate in &self.candidates[1..] {
+            let len = prefix
+                .chars()
+                .zip(candidate.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(c, _)| c.len_utf8())
+                .sum();
+            prefix.truncate(len);
+        }
+        prefix
+    }
+}
+
+/// 补全 `/git <子命令> <引用>` 形式的输入行
+pub fn complete_git_command(line: &str, refs: &[GitRef]) -> Completion {
+    let Some(rest) = line.strip_prefix("/git ") else {
+        return Completion::default();
+    };
+    let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &line[start..];
+    let args: Vec<&str> = rest[..rest.len() - word.len()].split_whitespace().collect();
+
+    let Some(subcommand) = args.first() else {
+        let candidates = REF_SUBCOMMANDS
+            .iter()
+            .chain(OTHER_SUBCOMMANDS)
+            .filter(|name| name.starts_with(word))
+            .map(|name| name.to_string())
+            .collect();
+        return Completion { start, candidates };
+    };
+    if !REF_SUBCOMMANDS.contains(subcommand) || word.starts_with('-') {
+        return Completion::default();
+    }
+
+    // 范围写法（main..feature、HEAD...topic）只补全最后一段
+    let start = start + word.rfind("..").map_or(0, |i| i + 2);
+    let partial = &line[start..];
+    let mut matches: Vec<&GitRef> = refs.iter().filter(|r| r.name.starts_with(partial)).collect();
+    matches.sort_by_key(|r| r.kind);
+    let mut candidates: Vec<String> = Vec::new();
+    for r in matches {
+        if !candidates.contains(&r.name) {
+            candidates.push(r.name.clone());
+        }
+    }
+    Completion { start, candidates }
+}
+
+/// 引用查询结果的缓存
+#[derive(Debug, Default)]
+pub struct RefCache {
+    refs: Vec<GitRef>,
+    fetched_at: Option<Instant>,
+}
+
+impl RefCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 执行过可能改变引用的 git 操作后调用
+    pub fn invalidate(&mut self) {
+        self.fetched_at = None;
+    }
+
+    /// 缓存过期时重新查询分支、标签和最近的提交
+    pub async fn refresh_if_stale(&mut self, git: &GitManager) {
+        if self.fetched_at.is_some_and(|at| at.elapsed() < CACHE_TTL) {
+            return;
+        }
+        let mut refs = git.list_refs().await.unwrap_or_default();
+        let commits = git.get_commit_history(Some(RECENT_COMMITS)).await.unwrap_or_default();
+        refs.extend(commits.into_iter().map(|commit| GitRef {
+            name: commit.hash.chars().take(7).collect(),
+            kind: RefKind::Commit,
+        }));
+        self.refs = refs;
+        self.fetched_at = Some(Instant::now());
+    }
+
+    pub fn complete(&self, line: &str) -> Completion {
+        complete_git_command(line, &self.refs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs() -> Vec<GitRef> {
+        [
+            "refs/heads/main",
+            "refs/heads/feature/login",
+            "refs/heads/feature/logout",
+            "refs/tags/v1.0",
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+        ]
+        .into_iter()
+        .filter_map(GitRef::parse)
+        .chain([GitRef { name: "f3a9c21".to_string(), kind: RefKind::Commit }])
+        .collect()
+    }
+
+    #[test]
+    fn test_complete_refs_and_subcommands() {
+        let refs = refs();
+        assert_eq!(refs.len(), 6);
+
+        let completion = complete_git_command("/git checkout feat", &refs);
+        assert_eq!(completion.start, "/git checkout ".len());
+        assert_eq!(completion.candidates, vec!["feature/login", "feature/logout"]);
+        assert_eq!(completion.common_prefix(), "feature/log");
+
+        assert_eq!(
+            complete_git_command("/git diff ", &refs).candidates,
+            vec!["main", "feature/login", "feature/logout", "v1.0", "origin/main", "f3a9c21"]
+        );
+        let range = complete_git_command("/git log HEAD...or", &refs);
+        assert_eq!(range.start, "/git log HEAD...".len());
+        assert_eq!(range.candidates, vec!["origin/main"]);
+
+        assert_eq!(complete_git_command("/git ch", &refs).candidates, vec!["checkout", "cherry-pick"]);
+        assert!(complete_git_command("/git add ma", &refs).candidates.is_empty());
+        assert!(complete_git_command("/git checkout -", &refs).candidates.is_empty());
+        assert!(complete_git_command("hello ma", &refs).candidates.is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+//! 合并冲突解决
+//!
+//! 解析文件中的冲突标记，为每个冲突块生成请模型给出合并结果的提示，
+//! 并按逐块的选择（模型方案、保留一侧、两侧都保留或跳过）重新写出文件
+
+use std::path::Path;
+
+/// 冲突块前后提供给模型的上下文行数
+const CONTEXT_LINES: usize = 10;
+
+/// 一个冲突块
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictHunk {
+    /// 冲突开始的行号（从 1 开始）
+    pub line: usize,
+    /// 当前分支一侧的标签
+    pub ours_label: String,
+    /// 当前分支一侧的内容
+    pub ours: String,
+    /// 共同祖先的内容（diff3 风格冲突才有）
+    pub base: Option<String>,
+    /// 合入分支一侧的标签
+    pub theirs_label: String,
+    /// 合入分支一侧的内容
+    pub theirs: String,
+    /// 冲突块原样的文本，跳过时写回
+    original: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Conflict(ConflictHunk),
+}
+
+/// 冲突块的处理方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// 保留当前分支一侧
+    Ours,
+    /// 保留合入分支一侧
+    Theirs,
+    /// 两侧都保留，当前分支在前
+    Both,
+    /// 使用给定的文本（通常是模型的方案）
+    Custom(String),
+    /// 暂不处理，保留冲突标记
+    Skip,
+}
+
+/// 含冲突标记的文件
+#[derive(Debug, Clone)]
+pub struct ConflictedFile {
+    segments: Vec<Segment>,
+}
+
+impl ConflictedFile {
+    /// 解析文件内容，未闭合的冲突标记按普通文本处理
+    pub fn parse(content: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let mut index = 0;
+
+        while index < lines.len() {
+            let line = lines[index];
+            if let Some(ours_label) = line.strip_prefix("<<<<<<<") {
+                if let Some((hunk, consumed)) = parse_hunk(&lines[index..], index + 1, ours_label) {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Conflict(hunk));
+                    index += consumed;
+                    continue;
+                }
+            }
+            text.push_str(line);
+            index += 1;
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Self { segments }
+    }
+
+    /// 所有冲突块
+    pub fn hunks(&self) -> Vec<&ConflictHunk> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Conflict(hunk) => Some(hunk),
+                Segment::Text(_) => None,
+            })
+            .collect()
+    }
+
+    /// 冲突块前后的上下文
+    pub fn context(&self, hunk_index: usize) -> (String, String) {
+        let Some(position) = self.hunk_position(hunk_index) else {
+            return (String::new(), String::new());
+        };
+        let before = position
+            .checked_sub(1)
+            .map(|i| self.segment_text(i))
+            .map(|text| last_lines(&text, CONTEXT_LINES))
+            .unwrap_or_default();
+        let after = self
+            .segments
+            .get(position + 1)
+            .map(|_| first_lines(&self.segment_text(position + 1), CONTEXT_LINES))
+            .unwrap_or_default();
+        (before, after)
+    }
+
+    /// 按每个冲突块的处理方式生成新内容，`resolutions` 不足的部分视为跳过
+    pub fn render(&self, resolutions: &[Resolution]) -> String {
+        let mut output = String::new();
+        let mut hunk_index = 0;
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Conflict(hunk) => {
+                    let resolution = resolutions.get(hunk_index).unwrap_or(&Resolution::Skip);
+                    output.push_str(&hunk.resolve(resolution));
+                    hunk_index += 1;
+                }
+            }
+        }
+        output
+    }
+
+    fn hunk_position(&self, hunk_index: usize) -> Option<usize> {
+        self.segments
+            .iter()
+            .enumerate()
+            .filter(|(_, segment)| matches!(segment, Segment::Conflict(_)))
+            .nth(hunk_index)
+            .map(|(position, _)| position)
+    }
+
+    fn segment_text(&self, position: usize) -> String {
+        match &self.segments[position] {
+            Segment::Text(text) => text.clone(),
+            Segment::Conflict(hunk) => hunk.original.clone(),
+        }
+    }
+}
+
+impl ConflictHunk {
+    /// 按处理方式生成替换冲突块的文本
+    pub fn resolve(&self, resolution: &Resolution) -> String {
+        match resolution {
+            Resolution::Ours => self.ours.clone(),
+            Resolution::Theirs => self.theirs.clone(),
+            Resolution::Both => format!("{}{}", self.ours, self.theirs),
+            Resolution::Custom(text) => {
+                if text.is_empty() || text.ends_with('\n') || !self.original.ends_with('\n') {
+                    text.clone()
+                } else {
+                    format!("{}\n", text)
+                }
+            }
+            Resolution::Skip => self.original.clone(),
+        }
+    }
+
+    /// 冲突块原样的文本（含冲突标记）
+    pub fn original(&self) -> &str {
+        &self.original
+    }
+}
+
+/// 从 `<<<<<<<` 所在行开始解析一个冲突块，返回冲突块和消耗的行数
+fn parse_hunk(lines: &[&str], line_number: usize, ours_label: &str) -> Option<(ConflictHunk, usize)> {
+    #[derive(PartialEq)]
+    enum Side {
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let mut side = Side::Ours;
+    let (mut ours, mut base, mut theirs) = (String::new(), None::<String>, String::new());
+    let mut original = lines[0].to_string();
+
+    for (offset, line) in lines.iter().enumerate().skip(1) {
+        original.push_str(line);
+        if side == Side::Ours && line.starts_with("|||||||") {
+            side = Side::Base;
+            base = Some(String::new());
+        } else if side != Side::Theirs && is_separator(line) {
+            side = Side::Theirs;
+        } else if side == Side::Theirs && line.starts_with(">>>>>>>") {
+            let hunk = ConflictHunk {
+                line: line_number,
+                ours_label: ours_label.trim().to_string(),
+                ours,
+                base,
+                theirs_label: line.trim_start_matches('>').trim().to_string(),
+                theirs,
+                original,
+            };
+            return Some((hunk, offset + 1));
+        } else {
+            match side {
+                Side::Ours => ours.push_str(line),
+                Side::Base => base.get_or_insert_with(String::new).push_str(line),
+                Side::Theirs => theirs.push_str(line),
+            }
+        }
+    }
+    None
+}
+
+fn is_separator(line: &str) -> bool {
+    line.trim_end_matches(['\r', '\n']) == "======="
+}
+
+fn last_lines(text: &str, count: usize) -> String {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    lines[lines.len().saturating_sub(count)..].concat()
+}
+
+fn first_lines(text: &str, count: usize) -> String {
+    text.split_inclusive('\n').take(count).collect()
+}
+
+/// 请模型解决单个冲突块的提示
+pub fn resolution_prompt(path: &str, file: &ConflictedFile, hunk_index: usize) -> Option<String> {
+    let hunk = *file.hunks().get(hunk_index)?;
+    let (before, after) = file.context(hunk_index);
+    let language = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    let mut prompt = format!(
+        "Resolve this merge conflict in `{}` (line {}).\n\
+         Combine the intent of both sides. Reply with only the merged code that replaces the conflict, \
+         inside a single ```{} code block, without conflict markers or explanation.\n\n",
+        path, hunk.line, language
+    );
+    prompt.push_str(&format!("Context before:\n```{}\n{}```\n\n", language, before));
+    prompt.push_str(&format!("Ours ({}):\n```{}\n{}```\n\n", hunk.ours_label, language, hunk.ours));
+    if let Some(base) = &hunk.base {
+        prompt.push_str(&format!("Common ancestor:\n```{}\n{}```\n\n", language, base));
+    }
+    prompt.push_str(&format!("Theirs ({}):\n```{}\n{}```\n\n", hunk.theirs_label, language, hunk.theirs));
+    prompt.push_str(&format!("Context after:\n```{}\n{}```\n", language, after));
+    Some(prompt)
+}
+
+/// 从模型回复中取出合并后的代码：优先取第一个代码块，没有代码块时使用整段回复
+pub fn extract_resolution(response: &str) -> String {
+    let mut lines = response.lines();
+    let opened = lines.by_ref().any(|line| line.trim_start().starts_with("```"));
+    if !opened {
+        return ensure_trailing_newline(response.trim());
+    }
+    let body: Vec<&str> = lines.take_while(|line| !line.trim_start().starts_with("```")).collect();
+    if body.is_empty() {
+        String::new()
+    } else {
+        ensure_trailing_newline(&body.join("\n"))
+    }
+}
+
+fn ensure_trailing_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+/// 根据项目文件推断解决冲突后运行的构建和测试命令
+pub fn detect_verify_command(project_dir: &Path) -> Option<String> {
+    const CANDIDATES: &[(&str, &str)] = &[
+        ("Cargo.toml", "cargo build && cargo test"),
+        ("package.json", "npm test"),
+        ("go.mod", "go build ./... && go test ./..."),
+        ("pyproject.toml", "python -m pytest"),
+        ("Makefile", "make test"),
+    ];
+    CANDIDATES
+        .iter()
+        .find(|(marker, _)| project_dir.join(marker).exists())
+        .map(|(_, command)| command.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFLICTED: &str = r#"fn main() {
+<<<<<<< HEAD
+    println!("hello");
+=======
+    println!("hi");
+>>>>>>> feature
+}
+<<<<<<< HEAD
+a
+||||||| base
+b
+=======
+c
+>>>>>>> feature
+"#;
+
+    #[test]
+    fn test_parse_and_render_hunks() {
+        let file = ConflictedFile::parse(CONFLICTED);
+        let hunks = file.hunks();
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].line, 2);
+        assert_eq!(hunks[0].ours_label, "HEAD");
+        assert_eq!(hunks[0].theirs_label, "feature");
+        assert_eq!(hunks[1].base.as_deref(), Some("b\n"));
+
+        let rendered = file.render(&[Resolution::Custom("    println!(\"hello, hi\");".to_string()), Resolution::Theirs]);
+        assert_eq!(rendered, "fn main() {\n    println!(\"hello, hi\");\n}\nc\n");
+
+        // 跳过的冲突块原样保留
+        let partial = file.render(&[Resolution::Both]);
+        assert!(partial.starts_with("fn main() {\n    println!(\"hello\");\n    println!(\"hi\");\n}\n<<<<<<< HEAD\n"));
+        assert_eq!(ConflictedFile::parse(&partial).hunks().len(), 1);
+    }
+
+    #[test]
+    fn test_prompt_and_response_extraction() {
+        let file = ConflictedFile::parse(CONFLICTED);
+        let prompt = resolution_prompt("src/main.rs", &file, 0).unwrap();
+        assert!(prompt.contains("Ours (HEAD)"));
+        assert!(prompt.contains("```rs\nfn main() {\n```"));
+        assert!(resolution_prompt("src/main.rs", &file, 5).is_none());
+
+        let response = "Here is the merge:\n```rust\nlet x = 1;\nlet y = 2;\n```\nDone.";
+        assert_eq!(extract_resolution(response), "let x = 1;\nlet y = 2;\n");
+        assert_eq!(extract_resolution("let x = 1;"), "let x = 1;\n");
+    }
+}
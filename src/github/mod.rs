@@ -0,0 +1,287 @@
+//! GitHub 集成
+//!
+//! 基于 REST API 的轻量客户端，提供拉取请求、差异、评审、议题和标签等操作。
+//! 令牌从 `GITHUB_TOKEN` 或 `GH_TOKEN` 环境变量读取
+
+pub mod review;
+pub mod triage;
+
+use reqwest::{Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::{ClaudeError, Result};
+
+/// 默认的 API 地址
+const DEFAULT_API_BASE: &str = "https://api.github.com";
+
+/// 每页条目数（GitHub 允许的最大值）
+const PER_PAGE: usize = 100;
+
+/// `owner/name` 形式的仓库
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RepoRef {
+    pub owner: String,
+    pub name: String,
+}
+
+impl RepoRef {
+    /// 解析 `owner/name`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim().trim_end_matches(".git");
+        let spec = spec.strip_prefix("https://github.com/").unwrap_or(spec);
+        match spec.split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => Ok(Self {
+                owner: owner.to_string(),
+                name: name.to_string(),
+            }),
+            _ => Err(ClaudeError::validation_error("repo", format!("expected owner/name, got '{}'", spec))),
+        }
+    }
+}
+
+impl fmt::Display for RepoRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+/// GitHub 用户
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub login: String,
+}
+
+/// 拉取请求的分支引用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitRef {
+    #[serde(rename = "ref")]
+    pub name: String,
+    pub sha: String,
+}
+
+/// 拉取请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub user: User,
+    pub head: GitRef,
+    pub base: GitRef,
+    #[serde(default)]
+    pub draft: bool,
+    pub html_url: String,
+}
+
+/// 标签
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Label {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// 议题
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Issue {
+    pub number: u64,
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub user: User,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    pub html_url: String,
+    /// 议题接口也会返回拉取请求，它们带有此字段
+    #[serde(default)]
+    pub pull_request: Option<serde_json::Value>,
+}
+
+/// 评审中的单条行内评论
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewComment {
+    pub path: String,
+    /// 新文件中的行号
+    pub line: u32,
+    pub body: String,
+}
+
+#[derive(Serialize)]
+struct ReviewCommentPayload<'a> {
+    path: &'a str,
+    line: u32,
+    side: &'static str,
+    body: &'a str,
+}
+
+#[derive(Serialize)]
+struct ReviewPayload<'a> {
+    commit_id: &'a str,
+    body: &'a str,
+    event: &'static str,
+    comments: Vec<ReviewCommentPayload<'a>>,
+}
+
+/// GitHub REST API 客户端
+#[derive(Clone)]
+pub struct GitHubClient {
+    client: reqwest::Client,
+    api_base: String,
+    token: Option<String>,
+}
+
+impl GitHubClient {
+    /// 使用给定令牌（可为空，只能访问公开仓库且限流更严格）
+    pub fn new(token: Option<String>) -> Self {
+        Self {
+            client: crate::network::shared_client(),
+            api_base: DEFAULT_API_BASE.to_string(),
+            token,
+        }
+    }
+
+    /// 从 `GITHUB_TOKEN` 或 `GH_TOKEN` 读取令牌
+    pub fn from_env() -> Self {
+        let token = ["GITHUB_TOKEN", "GH_TOKEN"]
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()));
+        Self::new(token)
+    }
+
+    /// 是否配置了令牌
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let mut builder = self
+            .client
+            .request(method, format!("{}{}", self.api_base, path))
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28")
+            .header("User-Agent", "claude-rust");
+        if let Some(token) = &self.token {
+            builder = builder.bearer_auth(token);
+        }
+        builder
+    }
+
+    async fn send(&self, builder: RequestBuilder) -> Result<reqwest::Response> {
+        let response = builder.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(ClaudeError::from_status(status.as_u16(), &body, None))
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        Ok(self.send(self.request(Method::GET, path)).await?.json().await?)
+    }
+
+    /// 逐页读取列表接口，直到某一页不足一整页
+    async fn get_paged<T: DeserializeOwned>(&self, path: &str, max_items: usize) -> Result<Vec<T>> {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let mut items = Vec::new();
+        for page in 1.. {
+            let batch: Vec<T> = self
+                .get_json(&format!("{}{}per_page={}&page={}", path, separator, PER_PAGE, page))
+                .await?;
+            let done = batch.len() < PER_PAGE;
+            items.extend(batch);
+            if done || items.len() >= max_items {
+                break;
+            }
+        }
+        items.truncate(max_items);
+        Ok(items)
+    }
+
+    /// 打开状态的拉取请求，按创建时间从新到旧
+    pub async fn list_open_pull_requests(&self, repo: &RepoRef) -> Result<Vec<PullRequest>> {
+        self.get_paged(&format!("/repos/{}/pulls?state=open&sort=created&direction=desc", repo), PER_PAGE)
+            .await
+    }
+
+    /// 拉取请求的统一差异
+    pub async fn pull_request_diff(&self, repo: &RepoRef, number: u64) -> Result<String> {
+        let builder = self
+            .request(Method::GET, &format!("/repos/{}/pulls/{}", repo, number))
+            .header("Accept", "application/vnd.github.v3.diff");
+        Ok(self.send(builder).await?.text().await?)
+    }
+
+    /// 打开状态的议题（不含拉取请求），按创建时间从新到旧，最多 `max_items` 个
+    pub async fn list_open_issues(&self, repo: &RepoRef, max_items: usize) -> Result<Vec<Issue>> {
+        let issues: Vec<Issue> = self
+            .get_paged(&format!("/repos/{}/issues?state=open&sort=created&direction=desc", repo), max_items)
+            .await?;
+        Ok(issues.into_iter().filter(|issue| issue.pull_request.is_none()).collect())
+    }
+
+    /// 仓库中定义的所有标签
+    pub async fn list_labels(&self, repo: &RepoRef) -> Result<Vec<Label>> {
+        self.get_paged(&format!("/repos/{}/labels", repo), usize::MAX).await
+    }
+
+    /// 为议题添加标签（保留已有标签）
+    pub async fn add_labels(&self, repo: &RepoRef, number: u64, labels: &[String]) -> Result<()> {
+        let builder = self
+            .request(Method::POST, &format!("/repos/{}/issues/{}/labels", repo, number))
+            .json(&serde_json::json!({ "labels": labels }));
+        self.send(builder).await?;
+        Ok(())
+    }
+
+    /// 在议题或拉取请求下发表评论
+    pub async fn create_issue_comment(&self, repo: &RepoRef, number: u64, body: &str) -> Result<()> {
+        let builder = self
+            .request(Method::POST, &format!("/repos/{}/issues/{}/comments", repo, number))
+            .json(&serde_json::json!({ "body": body }));
+        self.send(builder).await?;
+        Ok(())
+    }
+
+    /// 针对指定提交发表评审（只评论，不批准也不要求修改）
+    pub async fn create_review(
+        &self,
+        repo: &RepoRef,
+        number: u64,
+        commit_id: &str,
+        body: &str,
+        comments: &[ReviewComment],
+    ) -> Result<()> {
+        let payload = ReviewPayload {
+            commit_id,
+            body,
+            event: "COMMENT",
+            comments: comments
+                .iter()
+                .map(|c| ReviewCommentPayload { path: &c.path, line: c.line, side: "RIGHT", body: &c.body })
+                .collect(),
+        };
+        let builder = self
+            .request(Method::POST, &format!("/repos/{}/pulls/{}/reviews", repo, number))
+            .json(&payload);
+        self.send(builder).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_ref_parsing() {
+        let repo = RepoRef::parse("rust-lang/rust").unwrap();
+        assert_eq!((repo.owner.as_str(), repo.name.as_str()), ("rust-lang", "rust"));
+        assert_eq!(RepoRef::parse("https://github.com/tokio-rs/tokio.git").unwrap().to_string(), "tokio-rs/tokio");
+        assert!(RepoRef::parse("rust").is_err());
+        assert!(RepoRef::parse("a/b/c").is_err());
+    }
+}
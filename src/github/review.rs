@@ -0,0 +1,272 @@
+//! 拉取请求自动评审
+//!
+//! 解析统一差异得到每个文件新增的行，生成让模型以 JSON 给出总结和行内评论的提示，
+//! 只保留落在新增行上的评论（GitHub 拒绝评论差异之外的行）。
+//! 已评审的拉取请求按头提交记录在本地评审台账中，推送新提交后会再次评审
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::{PullRequest, RepoRef, ReviewComment};
+use crate::error::Result;
+
+/// 差异中的一个文件
+#[derive(Debug, Clone, Default)]
+pub struct DiffFile {
+    /// 新文件路径
+    pub path: String,
+    /// 新文件中新增的行号
+    pub added_lines: BTreeSet<u32>,
+    /// 该文件的差异文本
+    pub patch: String,
+}
+
+/// 解析统一差异，删除的文件不计入
+pub fn parse_unified_diff(diff: &str) -> Vec<DiffFile> {
+    let mut files = Vec::new();
+    let mut current: Option<DiffFile> = None;
+    let mut new_line = 0u32;
+
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            files.extend(current.take());
+            current = Some(DiffFile::default());
+        }
+        let Some(file) = current.as_mut() else {
+            continue;
+        };
+        file.patch.push_str(line);
+        file.patch.push('\n');
+
+        if let Some(path) = line.strip_prefix("+++ ") {
+            file.path = path.strip_prefix("b/").unwrap_or(path).to_string();
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            // @@ -a,b +c,d @@
+            new_line = header
+                .split_whitespace()
+                .find_map(|part| part.strip_prefix('+'))
+                .and_then(|range| range.split(',').next())
+                .and_then(|start| start.parse().ok())
+                .unwrap_or(0);
+        } else if line.starts_with('+') {
+            file.added_lines.insert(new_line);
+            new_line += 1;
+        } else if line.starts_with(' ') {
+            new_line += 1;
+        }
+    }
+    files.extend(current);
+    files.retain(|file| !file.path.is_empty() && file.path != "/dev/null");
+    files
+}
+
+/// 评审提示：差异超出 `max_diff_chars` 时按文件截断
+pub fn review_prompt(pr: &PullRequest, files: &[DiffFile], max_diff_chars: usize) -> String {
+    let mut prompt = format!(
+        "Review pull request #{} \"{}\" by @{} ({} → {}).\n\n",
+        pr.number, pr.title, pr.user.login, pr.head.name, pr.base.name
+    );
+    if let Some(body) = pr.body.as_deref().filter(|b| !b.trim().is_empty()) {
+        prompt.push_str(&format!("Description:\n{}\n\n", body.trim()));
+    }
+    prompt.push_str(
+        "Focus on correctness bugs, security problems, missing error handling and unclear code. \
+         Skip style nits and praise. Reply with only a JSON object:\n\
+         {\"summary\": \"<overall assessment in a few sentences>\", \
+         \"comments\": [{\"path\": \"<file>\", \"line\": <line number in the new file>, \"body\": \"<comment>\"}]}\n\
+         Only comment on lines that were added (prefixed with +). Use an empty list when there is nothing worth flagging.\n\n",
+    );
+
+    let mut used = 0;
+    let mut omitted = Vec::new();
+    for file in files {
+        if used + file.patch.len() > max_diff_chars {
+            omitted.push(file.path.as_str());
+            continue;
+        }
+        used += file.patch.len();
+        prompt.push_str(&format!("```diff\n{}```\n", file.patch));
+    }
+    if !omitted.is_empty() {
+        prompt.push_str(&format!("\n(Diff omitted for size: {})\n", omitted.join(", ")));
+    }
+    prompt
+}
+
+/// 模型给出的评审
+#[derive(Debug, Clone, Default)]
+pub struct ReviewDraft {
+    pub summary: String,
+    /// 落在新增行上的评论
+    pub comments: Vec<ReviewComment>,
+    /// 因不在差异范围内而舍弃的评论数
+    pub dropped: usize,
+}
+
+#[derive(Deserialize)]
+struct RawReview {
+    #[serde(default)]
+    summary: String,
+    #[serde(default)]
+    comments: Vec<ReviewComment>,
+}
+
+/// 从模型回复中提取 JSON 评审；无法解析时把整段回复作为总结
+pub fn parse_review_response(response: &str, files: &[DiffFile]) -> ReviewDraft {
+    let json = match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => "",
+    };
+    let Ok(raw) = serde_json::from_str::<RawReview>(json) else {
+        return ReviewDraft { summary: response.trim().to_string(), ..Default::default() };
+    };
+
+    let lines: HashMap<&str, &BTreeSet<u32>> = files.iter().map(|f| (f.path.as_str(), &f.added_lines)).collect();
+    let total = raw.comments.len();
+    let comments: Vec<ReviewComment> = raw
+        .comments
+        .into_iter()
+        .filter(|c| !c.body.trim().is_empty() && lines.get(c.path.as_str()).is_some_and(|added| added.contains(&c.line)))
+        .collect();
+    ReviewDraft { summary: raw.summary.trim().to_string(), dropped: total - comments.len(), comments }
+}
+
+/// 一次已完成的评审
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewRecord {
+    pub repo: String,
+    pub number: u64,
+    pub head_sha: String,
+    pub reviewed_at: DateTime<Utc>,
+    pub comments: usize,
+}
+
+/// 已评审拉取请求的台账，保存为 JSON 文件
+#[derive(Debug, Default)]
+pub struct ReviewLedger {
+    path: PathBuf,
+    records: Vec<ReviewRecord>,
+}
+
+impl ReviewLedger {
+    /// 默认的台账位置
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("claude-code")
+            .join("bot")
+            .join("reviewed.json")
+    }
+
+    /// 读取台账，文件不存在时为空
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let records = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, records })
+    }
+
+    /// 该拉取请求的当前头提交是否已评审过
+    pub fn is_reviewed(&self, repo: &RepoRef, number: u64, head_sha: &str) -> bool {
+        let repo = repo.to_string();
+        self.records.iter().any(|r| r.repo == repo && r.number == number && r.head_sha == head_sha)
+    }
+
+    /// 记录评审并写回文件
+    pub fn record(&mut self, repo: &RepoRef, number: u64, head_sha: &str, comments: usize) -> Result<()> {
+        self.records.push(ReviewRecord {
+            repo: repo.to_string(),
+            number,
+            head_sha: head_sha.to_string(),
+            reviewed_at: Utc::now(),
+            comments,
+        });
+        self.save()
+    }
+
+    /// 所有记录
+    pub fn records(&self) -> &[ReviewRecord] {
+        &self.records
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp = self.path.with_extension("json.tmp");
+        std::fs::write(&temp, serde_json::to_string_pretty(&self.records)?)?;
+        std::fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+
+    /// 台账文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -10,3 +10,4 @@ fn main() {
+ let a = 1;
+-let b = 2;
++let b = 3;
++let c = b.unwrap();
+ let d = 4;
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+";
+
+    #[test]
+    fn test_diff_lines_and_comment_filtering() {
+        let files = parse_unified_diff(DIFF);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "src/lib.rs");
+        assert_eq!(files[0].added_lines.iter().copied().collect::<Vec<_>>(), vec![11, 12]);
+
+        let response = r#"Here you go:
+{"summary": "One risky unwrap.", "comments": [
+  {"path": "src/lib.rs", "line": 12, "body": "This unwrap panics on None."},
+  {"path": "src/lib.rs", "line": 10, "body": "Unchanged line."},
+  {"path": "other.rs", "line": 1, "body": "Not in the diff."}
+]}"#;
+        let draft = parse_review_response(response, &files);
+        assert_eq!(draft.summary, "One risky unwrap.");
+        assert_eq!(draft.comments.len(), 1);
+        assert_eq!(draft.comments[0].line, 12);
+        assert_eq!(draft.dropped, 2);
+
+        assert_eq!(parse_review_response("Looks fine to me.", &files).summary, "Looks fine to me.");
+    }
+
+    #[test]
+    fn test_ledger_tracks_head_commits() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("bot").join("reviewed.json");
+        let repo = RepoRef::parse("acme/widgets").unwrap();
+
+        let mut ledger = ReviewLedger::load(&path).unwrap();
+        assert!(!ledger.is_reviewed(&repo, 7, "abc"));
+        ledger.record(&repo, 7, "abc", 2).unwrap();
+
+        let reloaded = ReviewLedger::load(&path).unwrap();
+        assert!(reloaded.is_reviewed(&repo, 7, "abc"));
+        assert!(!reloaded.is_reviewed(&repo, 7, "def"));
+        assert_eq!(reloaded.records().len(), 1);
+    }
+}
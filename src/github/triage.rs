@@ -0,0 +1,306 @@
+//! 议题分诊
+//!
+//! 用本地计算的文本向量（特征哈希的 TF-IDF）按余弦相似度聚合疑似重复的议题，
+//! 并请模型从仓库已有的标签中建议标签和优先级。建议只引用仓库中存在的标签
+
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+use super::{Issue, Label};
+
+/// 向量维度
+const DIMENSIONS: usize = 1024;
+
+/// 提示中每个议题正文的最大字符数
+const MAX_BODY_CHARS: usize = 600;
+
+/// 优先级，从高到低
+pub const PRIORITIES: [&str; 4] = ["P0", "P1", "P2", "P3"];
+
+const STOPWORDS: &[&str] = &[
+    "the", "a", "an", "and", "or", "to", "of", "in", "on", "for", "is", "it", "this", "that", "with", "be", "as",
+    "at", "by", "from", "when", "i", "we", "you", "my", "are", "was", "not", "but", "if", "can", "have", "has",
+    "do", "does", "should", "would", "there", "what", "how",
+];
+
+/// 把议题标题和正文转换为单位长度的向量；标题权重加倍
+pub fn embed_issues(issues: &[Issue]) -> Vec<Vec<f32>> {
+    let documents: Vec<Vec<String>> = issues
+        .iter()
+        .map(|issue| {
+            let mut tokens = tokenize(&issue.title);
+            tokens.extend(tokenize(&issue.title));
+            tokens.extend(tokenize(issue.body.as_deref().unwrap_or_default()));
+            tokens
+        })
+        .collect();
+
+    // 文档频率用于 IDF 加权
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for tokens in &documents {
+        for token in tokens.iter().map(String::as_str).collect::<HashSet<_>>() {
+            *document_frequency.entry(token).or_default() += 1;
+        }
+    }
+    let total = documents.len() as f32;
+
+    documents
+        .iter()
+        .map(|tokens| {
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for token in tokens {
+                *counts.entry(token.as_str()).or_default() += 1;
+            }
+            let mut vector = vec![0f32; DIMENSIONS];
+            for (token, count) in counts {
+                let idf = ((1.0 + total) / (1.0 + document_frequency[token] as f32)).ln() + 1.0;
+                let hash = fnv1a(token);
+                let sign = if hash & (1 << 63) == 0 { 1.0 } else { -1.0 };
+                vector[(hash % DIMENSIONS as u64) as usize] += sign * (1.0 + (count as f32).ln()) * idf;
+            }
+            normalize(&mut vector);
+            vector
+        })
+        .collect()
+}
+
+/// 小写单词及相邻词对；中日韩文字按单字切分
+fn tokenize(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if is_cjk(c) {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+            words.push(c.to_string());
+        } else if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+        } else {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+        }
+    }
+    words.extend((!word.is_empty()).then_some(word));
+    words.retain(|w| !STOPWORDS.contains(&w.as_str()) && (w.chars().count() > 1 || w.chars().any(is_cjk)));
+
+    let bigrams: Vec<String> = words.windows(2).map(|pair| format!("{} {}", pair[0], pair[1])).collect();
+    words.extend(bigrams);
+    words
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32, 0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF)
+}
+
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+/// 两个单位向量的余弦相似度
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// 一组疑似重复的议题，编号最小的作为原始议题
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateGroup {
+    pub original: u64,
+    /// 重复议题编号及其与原始议题的相似度
+    pub duplicates: Vec<(u64, f32)>,
+}
+
+/// 相似度不低于 `threshold` 的议题连成一组
+pub fn cluster_duplicates(issues: &[Issue], embeddings: &[Vec<f32>], threshold: f32) -> Vec<DuplicateGroup> {
+    let mut parent: Vec<usize> = (0..issues.len()).collect();
+    fn find(parent: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        parent[i] = root;
+        root
+    }
+
+    for i in 0..issues.len() {
+        for j in i + 1..issues.len() {
+            if cosine(&embeddings[i], &embeddings[j]) >= threshold {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..issues.len() {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().push(i);
+    }
+
+    let mut result: Vec<DuplicateGroup> = groups
+        .into_values()
+        .filter(|members| members.len() > 1)
+        .map(|mut members| {
+            members.sort_by_key(|&i| issues[i].number);
+            let original = members[0];
+            DuplicateGroup {
+                original: issues[original].number,
+                duplicates: members[1..]
+                    .iter()
+                    .map(|&i| (issues[i].number, cosine(&embeddings[original], &embeddings[i])))
+                    .collect(),
+            }
+        })
+        .collect();
+    result.sort_by_key(|group| group.original);
+    result
+}
+
+/// 请模型建议标签和优先级的提示
+pub fn triage_prompt(issues: &[Issue], labels: &[Label]) -> String {
+    let mut prompt = String::from(
+        "Triage these GitHub issues. For each issue choose the labels that apply from the available labels \
+         (never invent new ones) and a priority: P0 (critical, breaks core functionality or security), \
+         P1 (important bug), P2 (normal), P3 (minor or nice to have).\n\
+         Reply with only a JSON array: [{\"number\": <issue number>, \"labels\": [\"...\"], \"priority\": \"P2\", \
+         \"reason\": \"<one sentence>\"}]\n\nAvailable labels:\n",
+    );
+    for label in labels {
+        match label.description.as_deref().filter(|d| !d.is_empty()) {
+            Some(description) => prompt.push_str(&format!("- {}: {}\n", label.name, description)),
+            None => prompt.push_str(&format!("- {}\n", label.name)),
+        }
+    }
+    prompt.push_str("\nIssues:\n");
+    for issue in issues {
+        let body: String = issue.body.as_deref().unwrap_or_default().chars().take(MAX_BODY_CHARS).collect();
+        let existing: Vec<&str> = issue.labels.iter().map(|l| l.name.as_str()).collect();
+        prompt.push_str(&format!(
+            "\n#{} {}\nExisting labels: {}\n{}\n",
+            issue.number,
+            issue.title,
+            if existing.is_empty() { "none".to_string() } else { existing.join(", ") },
+            body.trim()
+        ));
+    }
+    prompt
+}
+
+/// 单个议题的分诊建议
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TriageSuggestion {
+    pub number: u64,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// 解析模型回复：去掉仓库中不存在或议题已有的标签，忽略未知议题和无效优先级
+pub fn parse_triage_response(response: &str, issues: &[Issue], labels: &[Label]) -> Vec<TriageSuggestion> {
+    let json = match (response.find('['), response.rfind(']')) {
+        (Some(start), Some(end)) if start < end => &response[start..=end],
+        _ => return Vec::new(),
+    };
+    let Ok(suggestions) = serde_json::from_str::<Vec<TriageSuggestion>>(json) else {
+        return Vec::new();
+    };
+
+    suggestions
+        .into_iter()
+        .filter_map(|mut suggestion| {
+            let issue = issues.iter().find(|issue| issue.number == suggestion.number)?;
+            suggestion.labels = suggestion
+                .labels
+                .iter()
+                .filter_map(|name| labels.iter().find(|label| label.name.eq_ignore_ascii_case(name)))
+                .filter(|label| !issue.labels.iter().any(|existing| existing.name == label.name))
+                .map(|label| label.name.clone())
+                .collect();
+            suggestion.priority = suggestion
+                .priority
+                .map(|p| p.trim().to_uppercase())
+                .filter(|p| PRIORITIES.contains(&p.as_str()));
+            Some(suggestion)
+        })
+        .collect()
+}
+
+/// 仓库中表示该优先级的标签，如 `P1`、`priority: P1`、`priority/p1`
+pub fn priority_label<'a>(priority: &str, labels: &'a [Label]) -> Option<&'a Label> {
+    labels.iter().find(|label| {
+        let name = label.name.to_lowercase();
+        let name = name
+            .strip_prefix("priority")
+            .map(|rest| rest.trim_start_matches([':', '/', '-', ' ']))
+            .unwrap_or(&name);
+        name.eq_ignore_ascii_case(priority)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::github::User;
+
+    fn issue(number: u64, title: &str, body: &str) -> Issue {
+        Issue {
+            number,
+            title: title.to_string(),
+            body: Some(body.to_string()),
+            user: User { login: "someone".to_string() },
+            labels: Vec::new(),
+            html_url: format!("https://github.com/acme/widgets/issues/{}", number),
+            pull_request: None,
+        }
+    }
+
+    fn label(name: &str) -> Label {
+        Label { name: name.to_string(), description: None }
+    }
+
+    #[test]
+    fn test_duplicates_are_clustered() {
+        let issues = vec![
+            issue(3, "Crash when opening large JSON files", "The app panics with out of memory when opening a 2GB JSON file."),
+            issue(9, "Panic opening big JSON file", "Opening a large JSON file crashes the app: out of memory panic."),
+            issue(12, "Add dark theme", "Please support a dark color theme in the settings page."),
+            issue(15, "深色主题支持", "希望设置页面支持深色主题"),
+        ];
+        let embeddings = embed_issues(&issues);
+        assert!((cosine(&embeddings[0], &embeddings[0]) - 1.0).abs() < 1e-4);
+
+        let groups = cluster_duplicates(&issues, &embeddings, 0.35);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].original, 3);
+        assert_eq!(groups[0].duplicates.iter().map(|(n, _)| *n).collect::<Vec<_>>(), vec![9]);
+    }
+
+    #[test]
+    fn test_suggestions_only_use_existing_labels() {
+        let mut issues = vec![issue(1, "Crash", ""), issue(2, "Typo", "")];
+        issues[1].labels.push(label("docs"));
+        let labels = vec![label("bug"), label("docs"), label("priority: P1")];
+
+        let response = r#"[
+            {"number": 1, "labels": ["Bug", "critical"], "priority": "p1", "reason": "Crashes on start."},
+            {"number": 2, "labels": ["docs"], "priority": "P9"},
+            {"number": 5, "labels": ["bug"]}
+        ]"#;
+        let suggestions = parse_triage_response(response, &issues, &labels);
+        assert_eq!(suggestions.len(), 2);
+        assert_eq!(suggestions[0].labels, vec!["bug"]);
+        assert_eq!(suggestions[0].priority.as_deref(), Some("P1"));
+        assert!(suggestions[1].labels.is_empty());
+        assert_eq!(suggestions[1].priority, None);
+
+        assert_eq!(priority_label("P1", &labels).map(|l| l.name.as_str()), Some("priority: P1"));
+        assert!(priority_label("P2", &labels).is_none());
+    }
+}
@@ -0,0 +1,483 @@
+//! 仓库健康报告
+//!
+//! 组合多个分析器：疑似死代码（基于符号索引）、超大文件、TODO 密度、测试与代码的比例、依赖过时程度。
+//! 每项给出 0–100 的分数和具体发现，按权重汇总为总分，可以附上模型写的改进建议并导出为 Markdown
+
+pub mod symbols;
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::fs::scan::{ParallelScanner, ScanOptions};
+use symbols::SymbolIndex;
+
+/// 参与分析的源文件扩展名
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "ts", "tsx", "mjs", "go", "java", "kt", "c", "h", "cpp", "hpp", "cs", "rb", "swift",
+];
+
+/// 计入 TODO 密度的标记
+const TODO_MARKERS: &[&str] = &["TODO", "FIXME", "HACK", "XXX"];
+
+/// Markdown 中每项最多列出的发现
+const MAX_LISTED_FINDINGS: usize = 20;
+
+/// 同时查询 crates.io 的请求数
+const REGISTRY_CONCURRENCY: usize = 8;
+
+/// 分析选项
+#[derive(Debug, Clone)]
+pub struct HealthOptions {
+    /// 超过这个行数的文件算作超大文件
+    pub max_file_lines: usize,
+    /// 是否联网查询依赖的最新版本
+    pub check_dependencies: bool,
+}
+
+impl Default for HealthOptions {
+    fn default() -> Self {
+        Self { max_file_lines: 1000, check_dependencies: true }
+    }
+}
+
+/// 一个分析器的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    /// 0–100，无法评估（如离线时的依赖检查）时为空，不计入总分
+    pub score: Option<u8>,
+    /// 在总分中的权重
+    pub weight: u32,
+    pub summary: String,
+    pub findings: Vec<String>,
+}
+
+/// 健康报告
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub root: PathBuf,
+    pub generated_at: DateTime<Utc>,
+    pub checks: Vec<CheckResult>,
+    /// 模型写的改进建议
+    pub recommendations: Option<String>,
+}
+
+/// 读入内存的源文件
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// 相对仓库根目录的路径
+    pub path: PathBuf,
+    pub text: String,
+}
+
+impl SourceFile {
+    fn is_test(&self) -> bool {
+        let path = self.path.to_string_lossy();
+        let name = self.path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        self.path.components().any(|c| matches!(c.as_os_str().to_str(), Some("tests" | "test" | "__tests__" | "spec")))
+            || name.starts_with("test_")
+            || name.ends_with("_test")
+            || name.ends_with(".test")
+            || name.ends_with(".spec")
+            || path.ends_with("Test.java")
+    }
+
+    /// 测试代码的行数：测试文件的全部行，以及 Rust 文件中 `#[cfg(test)]` 之后的行
+    fn test_lines(&self) -> usize {
+        if self.is_test() {
+            return self.text.lines().count();
+        }
+        match self.text.find("#[cfg(test)]") {
+            Some(start) => self.text[start..].lines().count(),
+            None => 0,
+        }
+    }
+}
+
+impl HealthReport {
+    /// 有分数的检查按权重平均
+    pub fn score(&self) -> u8 {
+        let (total, weights) = self
+            .checks
+            .iter()
+            .filter_map(|check| check.score.map(|score| (score as u32 * check.weight, check.weight)))
+            .fold((0, 0), |(total, weights), (score, weight)| (total + score, weights + weight));
+        if weights == 0 {
+            return 100;
+        }
+        (total as f64 / weights as f64).round() as u8
+    }
+
+    pub fn grade(&self) -> char {
+        match self.score() {
+            90..=100 => 'A',
+            80..=89 => 'B',
+            70..=79 => 'C',
+            60..=69 => 'D',
+            _ => 'F',
+        }
+    }
+
+    /// 交给模型撰写建议的摘要
+    pub fn prompt(&self) -> String {
+        let mut prompt = format!(
+            "Here is an automated health report for a code repository (overall score {}/100). \
+             Write 3-6 prioritised, concrete recommendations in markdown bullet points. \
+             Dead code candidates come from a name-based index and may include false positives.\n\n",
+            self.score()
+        );
+        for check in &self.checks {
+            let _ = writeln!(prompt, "## {} ({})\n{}", check.name, score_label(check.score), check.summary);
+            for finding in check.findings.iter().take(10) {
+                let _ = writeln!(prompt, "- {}", finding);
+            }
+            prompt.push('\n');
+        }
+        prompt
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = format!("# Repository health: {}\n\n", self.root.display());
+        let _ = writeln!(
+            output,
+            "Generated {} · Overall score **{}/100** ({})\n",
+            self.generated_at.format("%Y-%m-%d %H:%M UTC"),
+            self.score(),
+            self.grade()
+        );
+        output.push_str("| Check | Score | Summary |\n|---|---|---|\n");
+        for check in &self.checks {
+            let _ = writeln!(output, "| {} | {} | {} |", check.name, score_label(check.score), check.summary);
+        }
+        for check in self.checks.iter().filter(|check| !check.findings.is_empty()) {
+            let _ = writeln!(output, "\n## {}\n", check.name);
+            for finding in check.findings.iter().take(MAX_LISTED_FINDINGS) {
+                let _ = writeln!(output, "- {}", finding);
+            }
+            if check.findings.len() > MAX_LISTED_FINDINGS {
+                let _ = writeln!(output, "- … and {} more", check.findings.len() - MAX_LISTED_FINDINGS);
+            }
+        }
+        if let Some(recommendations) = &self.recommendations {
+            let _ = write!(output, "\n## Recommendations\n\n{}\n", recommendations.trim());
+        }
+        output
+    }
+}
+
+fn score_label(score: Option<u8>) -> String {
+    score.map_or_else(|| "n/a".to_string(), |score| format!("{}/100", score))
+}
+
+/// 分数随比例线性下降：`ratio` 达到 `zero_at` 时为 0
+fn linear_score(ratio: f64, zero_at: f64) -> u8 {
+    (100.0 * (1.0 - ratio / zero_at)).clamp(0.0, 100.0).round() as u8
+}
+
+/// 分析整个仓库
+pub async fn analyze(root: &Path, options: &HealthOptions) -> Result<HealthReport> {
+    let files = collect_sources(root)?;
+    let mut checks = analyze_sources(&files, options);
+    checks.push(if options.check_dependencies {
+        check_dependencies(root).await
+    } else {
+        skipped_dependency_check("Skipped (offline)")
+    });
+    Ok(HealthReport { root: root.to_path_buf(), generated_at: Utc::now(), checks, recommendations: None })
+}
+
+/// 读取仓库中的源文件
+pub fn collect_sources(root: &Path) -> Result<Vec<SourceFile>> {
+    let scanner = ParallelScanner::new(ScanOptions::default())?;
+    let is_source = |path: &Path| {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| SOURCE_EXTENSIONS.contains(&e))
+    };
+    let paths = scanner.discover(root, &is_source, None)?;
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| {
+            let text = std::fs::read_to_string(&path).ok()?;
+            let path = path.strip_prefix(root).map(Path::to_path_buf).unwrap_or(path);
+            Some(SourceFile { path, text })
+        })
+        .collect())
+}
+
+/// 只依赖源文件内容的分析器
+pub fn analyze_sources(files: &[SourceFile], options: &HealthOptions) -> Vec<CheckResult> {
+    vec![
+        check_dead_code(files),
+        check_oversized_files(files, options.max_file_lines),
+        check_todo_density(files),
+        check_test_ratio(files),
+    ]
+}
+
+fn check_dead_code(files: &[SourceFile]) -> CheckResult {
+    let mut index = SymbolIndex::default();
+    for file in files {
+        index.add_file(&file.path, &file.text);
+    }
+    let unreferenced = index.unreferenced();
+    let ratio = unreferenced.len() as f64 / index.definitions.len().max(1) as f64;
+    CheckResult {
+        name: "Dead code candidates".to_string(),
+        score: Some(linear_score(ratio, 0.25)),
+        weight: 2,
+        summary: format!("{} of {} definitions are never referenced", unreferenced.len(), index.definitions.len()),
+        findings: unreferenced
+            .iter()
+            .map(|symbol| format!("`{}` ({}) at {}:{}", symbol.name, symbol.kind, symbol.path.display(), symbol.line))
+            .collect(),
+    }
+}
+
+fn check_oversized_files(files: &[SourceFile], max_lines: usize) -> CheckResult {
+    let mut oversized: Vec<(usize, &SourceFile)> = files
+        .iter()
+        .map(|file| (file.text.lines().count(), file))
+        .filter(|(lines, _)| *lines > max_lines)
+        .collect();
+    oversized.sort_by_key(|(lines, _)| std::cmp::Reverse(*lines));
+    let ratio = oversized.len() as f64 / files.len().max(1) as f64;
+    CheckResult {
+        name: "Oversized files".to_string(),
+        score: Some(linear_score(ratio, 0.2)),
+        weight: 1,
+        summary: format!("{} of {} files exceed {} lines", oversized.len(), files.len(), max_lines),
+        findings: oversized
+            .iter()
+            .map(|(lines, file)| format!("{} ({} lines)", file.path.display(), lines))
+            .collect(),
+    }
+}
+
+fn check_todo_density(files: &[SourceFile]) -> CheckResult {
+    let mut per_file: Vec<(usize, &SourceFile)> = Vec::new();
+    let mut total_lines = 0;
+    for file in files {
+        total_lines += file.text.lines().count();
+        let markers = file
+            .text
+            .lines()
+            .filter(|line| TODO_MARKERS.iter().any(|marker| line.contains(marker)))
+            .count();
+        if markers > 0 {
+            per_file.push((markers, file));
+        }
+    }
+    per_file.sort_by_key(|(markers, _)| std::cmp::Reverse(*markers));
+    let markers: usize = per_file.iter().map(|(markers, _)| markers).sum();
+    let per_thousand = markers as f64 * 1000.0 / total_lines.max(1) as f64;
+    CheckResult {
+        name: "TODO density".to_string(),
+        score: Some(linear_score(per_thousand, 10.0)),
+        weight: 1,
+        summary: format!("{} markers, {:.1} per 1000 lines", markers, per_thousand),
+        findings: per_file
+            .iter()
+            .map(|(markers, file)| format!("{} ({} markers)", file.path.display(), markers))
+            .collect(),
+    }
+}
+
+fn check_test_ratio(files: &[SourceFile]) -> CheckResult {
+    let total: usize = files.iter().map(|file| file.text.lines().count()).sum();
+    let tests: usize = files.iter().map(SourceFile::test_lines).sum();
+    let code = total - tests;
+    let ratio = tests as f64 / code.max(1) as f64;
+    // 测试代码达到业务代码的 30% 即满分
+    let score = (ratio / 0.3 * 100.0).clamp(0.0, 100.0).round() as u8;
+    CheckResult {
+        name: "Test-to-code ratio".to_string(),
+        score: Some(score),
+        weight: 2,
+        summary: format!("{} test lines for {} code lines ({:.2})", tests, code, ratio),
+        findings: Vec::new(),
+    }
+}
+
+fn skipped_dependency_check(summary: &str) -> CheckResult {
+    CheckResult {
+        name: "Dependency staleness".to_string(),
+        score: None,
+        weight: 1,
+        summary: summary.to_string(),
+        findings: Vec::new(),
+    }
+}
+
+/// 对比 Cargo 依赖的锁定版本和 crates.io 上的最新稳定版本
+pub async fn check_dependencies(root: &Path) -> CheckResult {
+    let dependencies = match cargo_dependencies(root) {
+        Ok(dependencies) if !dependencies.is_empty() => dependencies,
+        Ok(_) => return skipped_dependency_check("No Cargo dependencies found"),
+        Err(e) => return skipped_dependency_check(&format!("Could not read Cargo.toml: {}", e)),
+    };
+    let client = match reqwest::Client::builder()
+        .user_agent(concat!("claude-rust/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(10))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return skipped_dependency_check(&format!("Registry unavailable: {}", e)),
+    };
+
+    let latest: Vec<(String, String, Option<String>)> = stream::iter(dependencies)
+        .map(|(name, current)| {
+            let client = client.clone();
+            async move {
+                let latest = latest_crate_version(&client, &name).await;
+                (name, current, latest)
+            }
+        })
+        .buffer_unordered(REGISTRY_CONCURRENCY)
+        .collect()
+        .await;
+
+    let checked = latest.iter().filter(|(_, _, latest)| latest.is_some()).count();
+    if checked == 0 {
+        return skipped_dependency_check("Skipped (crates.io unreachable)");
+    }
+    let mut stale: Vec<String> = latest
+        .iter()
+        .filter_map(|(name, current, latest)| {
+            let latest = latest.as_deref()?;
+            is_behind(current, latest).then(|| format!("{} {} → {}", name, current, latest))
+        })
+        .collect();
+    stale.sort();
+    CheckResult {
+        name: "Dependency staleness".to_string(),
+        score: Some(linear_score(stale.len() as f64 / checked as f64, 0.5)),
+        weight: 1,
+        summary: format!("{} of {} dependencies are a breaking release behind", stale.len(), checked),
+        findings: stale,
+    }
+}
+
+/// Cargo.toml 中声明的依赖及其版本：优先取 Cargo.lock 中锁定的版本
+fn cargo_dependencies(root: &Path) -> Result<Vec<(String, String)>> {
+    let manifest_path = root.join("Cargo.toml");
+    if !manifest_path.exists() {
+        return Ok(Vec::new());
+    }
+    let manifest: toml::Table = std::fs::read_to_string(&manifest_path)?
+        .parse()
+        .map_err(|e| crate::error::ClaudeError::config_error(format!("Invalid Cargo.toml: {}", e)))?;
+    let locked: HashMap<String, String> = std::fs::read_to_string(root.join("Cargo.lock"))
+        .ok()
+        .and_then(|lock| lock.parse::<toml::Table>().ok())
+        .and_then(|lock| lock.get("package")?.as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|package| {
+            Some((package.get("name")?.as_str()?.to_string(), package.get("version")?.as_str()?.to_string()))
+        })
+        .collect();
+
+    let mut dependencies = Vec::new();
+    for section in ["dependencies", "dev-dependencies", "build-dependencies"] {
+        let Some(table) = manifest.get(section).and_then(|s| s.as_table()) else {
+            continue;
+        };
+        for (name, spec) in table {
+            let requirement = match spec {
+                toml::Value::String(version) => Some(version.as_str()),
+                toml::Value::Table(spec) if spec.get("path").is_none() && spec.get("git").is_none() => {
+                    spec.get("version").and_then(|v| v.as_str())
+                }
+                _ => None,
+            };
+            let Some(requirement) = requirement else {
+                continue;
+            };
+            let package = match spec {
+                toml::Value::Table(spec) => spec.get("package").and_then(|p| p.as_str()).unwrap_or(name),
+                _ => name,
+            };
+            let version = locked
+                .get(package)
+                .cloned()
+                .unwrap_or_else(|| requirement.trim_start_matches(['^', '~', '=', ' ']).to_string());
+            dependencies.push((package.to_string(), version));
+        }
+    }
+    dependencies.sort();
+    dependencies.dedup_by(|a, b| a.0 == b.0);
+    Ok(dependencies)
+}
+
+async fn latest_crate_version(client: &reqwest::Client, name: &str) -> Option<String> {
+    let url = format!("https://crates.io/api/v1/crates/{}", name);
+    let body: serde_json::Value = client.get(url).send().await.ok()?.error_for_status().ok()?.json().await.ok()?;
+    body["crate"]["max_stable_version"].as_str().map(str::to_string)
+}
+
+/// 最新版本是否是不兼容的新版本（1.x 以上看主版本，0.x 看次版本）
+fn is_behind(current: &str, latest: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-', '+'])
+            .take(3)
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (current, latest) = (parse(current), parse(latest));
+    let part = |version: &[u64], index: usize| version.get(index).copied().unwrap_or(0);
+    if part(&latest, 0) != part(&current, 0) {
+        return part(&latest, 0) > part(&current, 0);
+    }
+    part(&current, 0) == 0 && part(&latest, 1) > part(&current, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(path: &str, text: &str) -> SourceFile {
+        SourceFile { path: PathBuf::from(path), text: text.to_string() }
+    }
+
+    #[test]
+    fn test_source_checks_and_markdown() {
+        let files = vec![
+            source(
+                "src/lib.rs",
+                "pub fn used() {}\npub fn orphan() {}\nfn main() { used(); }\n// TODO: tidy up\n#[cfg(test)]\nmod tests {\n    fn test_used() { super::used(); }\n}\n",
+            ),
+            source("app/views.py", "class Page:\n    def render(self):\n        return Page()\n# FIXME\n"),
+            source("tests/smoke.rs", "fn smoke() { claude::used(); }\nfn smoke_again() { smoke(); }\n"),
+        ];
+        let options = HealthOptions { max_file_lines: 5, check_dependencies: false };
+        let checks = analyze_sources(&files, &options);
+
+        assert_eq!(checks[0].findings, vec!["`orphan` (fn) at src/lib.rs:2", "`render` (def) at app/views.py:2", "`smoke_again` (fn) at tests/smoke.rs:2"]);
+        assert_eq!(checks[1].findings, vec!["src/lib.rs (8 lines)"]);
+        assert_eq!(checks[2].summary, "2 markers, 142.9 per 1000 lines");
+        assert_eq!(checks[3].summary, "6 test lines for 8 code lines (0.75)");
+        assert_eq!(checks[3].score, Some(100));
+
+        let report = HealthReport {
+            root: PathBuf::from("demo"),
+            generated_at: Utc::now(),
+            checks,
+            recommendations: Some("- Delete `orphan`".to_string()),
+        };
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("| Test-to-code ratio | 100/100 |"));
+        assert!(markdown.ends_with("## Recommendations\n\n- Delete `orphan`\n"));
+
+        assert!(is_behind("0.11.27", "0.12.4"));
+        assert!(is_behind("1.0.2", "2.0.0"));
+        assert!(!is_behind("1.0.2", "1.9.0"));
+    }
+}
@@ -0,0 +1,98 @@
+//! 符号索引
+//!
+//! 用按语言区分的正则找出函数、类型等定义，再统计所有源文件中每个标识符出现的次数。
+//! 只出现在自己定义处的符号就是疑似死代码：索引不做名称解析，注释和字符串里的出现也算引用，
+//! 因此结果偏保守
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// 按约定由框架或编译器调用的名称，不作为死代码候选
+const ENTRY_POINTS: &[&str] = &["main", "new", "default", "fmt", "from", "drop", "init", "setup", "teardown"];
+
+/// 一个定义
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    /// fn、struct、class、def 等
+    pub kind: String,
+    pub path: PathBuf,
+    /// 从 1 开始的行号
+    pub line: usize,
+}
+
+/// 定义及标识符出现次数
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    pub definitions: Vec<Symbol>,
+    occurrences: HashMap<String, usize>,
+}
+
+impl SymbolIndex {
+    pub fn add_file(&mut self, path: &Path, text: &str) {
+        if let Some(pattern) = definition_pattern(path) {
+            for (index, line) in text.lines().enumerate() {
+                if let Some(captures) = pattern.captures(line) {
+                    self.definitions.push(Symbol {
+                        name: captures[2].to_string(),
+                        kind: captures[1].to_string(),
+                        path: path.to_path_buf(),
+                        line: index + 1,
+                    });
+                }
+            }
+        }
+        for word in identifiers(text) {
+            *self.occurrences.entry(word.to_string()).or_default() += 1;
+        }
+    }
+
+    /// 标识符在所有文件中出现的次数（包括定义本身）
+    pub fn occurrences(&self, name: &str) -> usize {
+        self.occurrences.get(name).copied().unwrap_or(0)
+    }
+
+    /// 除定义外没有任何出现的符号
+    pub fn unreferenced(&self) -> Vec<&Symbol> {
+        self.definitions
+            .iter()
+            .filter(|symbol| {
+                !ENTRY_POINTS.contains(&symbol.name.as_str())
+                    && !symbol.name.starts_with('_')
+                    && !symbol.name.starts_with("test")
+                    && self.occurrences(&symbol.name) <= 1
+            })
+            .collect()
+    }
+}
+
+/// 文本中的标识符
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .filter(|word| word.chars().next().is_some_and(|c| !c.is_ascii_digit()))
+}
+
+/// 定义的正则：第 1 组是种类，第 2 组是名称
+fn definition_pattern(path: &Path) -> Option<&'static Regex> {
+    static RUST: OnceLock<Regex> = OnceLock::new();
+    static PYTHON: OnceLock<Regex> = OnceLock::new();
+    static SCRIPT: OnceLock<Regex> = OnceLock::new();
+    static GO: OnceLock<Regex> = OnceLock::new();
+
+    let (cell, pattern) = match path.extension()?.to_str()? {
+        "rs" => (
+            &RUST,
+            r"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:const\s+)?(?:async\s+)?(?:unsafe\s+)?(fn|struct|enum|trait|type|static)\s+([A-Za-z_]\w*)",
+        ),
+        "py" => (&PYTHON, r"^\s*(?:async\s+)?(def|class)\s+([A-Za-z_]\w*)"),
+        "js" | "jsx" | "ts" | "tsx" | "mjs" => (
+            &SCRIPT,
+            r"^\s*(?:export\s+)?(?:default\s+)?(?:async\s+)?(function|class)\s*\*?\s+([A-Za-z_$][\w$]*)",
+        ),
+        "go" => (&GO, r"^(func|type)\s+(?:\([^)]*\)\s*)?([A-Za-z_]\w*)"),
+        _ => return None,
+    };
+    Some(cell.get_or_init(|| Regex::new(pattern).expect("valid definition pattern")))
+}
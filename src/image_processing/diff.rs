@@ -0,0 +1,243 @@
+//! 图像比对
+//!
+//! 比较测试生成的前后截图，统计差异像素比例并生成标出差异的对比图，
+//! 用于视觉回归检查
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ClaudeError, Result};
+
+/// YIQ 色差的最大值，用于归一化感知差异
+const MAX_YIQ_DELTA: f64 = 35215.0;
+
+/// 比对方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffMode {
+    /// 逐像素比较各通道的最大差值
+    Pixel,
+    /// 按 YIQ 色彩空间的感知色差比较，忽略人眼难以察觉的变化
+    Perceptual,
+}
+
+impl std::str::FromStr for DiffMode {
+    type Err = ClaudeError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pixel" => Ok(Self::Pixel),
+            "perceptual" => Ok(Self::Perceptual),
+            other => Err(ClaudeError::validation_error(
+                "mode",
+                format!("Unknown diff mode '{}', expected 'pixel' or 'perceptual'", other),
+            )),
+        }
+    }
+}
+
+/// 比对选项
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// 比对方式
+    pub mode: DiffMode,
+    /// 容差（0.0-1.0），差异不超过该值的像素视为相同
+    pub threshold: f64,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            mode: DiffMode::Perceptual,
+            threshold: 0.1,
+        }
+    }
+}
+
+/// 差异区域
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 比对结果
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    /// 比对区域宽度（两图中较大者）
+    pub width: u32,
+    /// 比对区域高度（两图中较大者）
+    pub height: u32,
+    /// 差异像素数
+    pub differing_pixels: u64,
+    /// 总像素数
+    pub total_pixels: u64,
+    /// 两图尺寸是否不同，超出重叠区域的像素计为差异
+    pub size_mismatch: bool,
+    /// 包含全部差异像素的最小矩形
+    pub region: Option<DiffRegion>,
+    /// 对比图：后图淡化为灰度，差异像素标红
+    pub diff_image: RgbaImage,
+}
+
+impl ImageDiff {
+    /// 差异像素百分比
+    pub fn difference_percent(&self) -> f64 {
+        if self.total_pixels == 0 {
+            return 0.0;
+        }
+        self.differing_pixels as f64 * 100.0 / self.total_pixels as f64
+    }
+
+    /// 两图是否一致
+    pub fn is_identical(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+/// 比较两张图像
+pub fn compare(before: &DynamicImage, after: &DynamicImage, options: &DiffOptions) -> Result<ImageDiff> {
+    if !(0.0..=1.0).contains(&options.threshold) {
+        return Err(ClaudeError::validation_error("threshold", "Threshold must be between 0.0 and 1.0"));
+    }
+
+    let before = before.to_rgba8();
+    let after = after.to_rgba8();
+    let width = before.width().max(after.width());
+    let height = before.height().max(after.height());
+    let size_mismatch = before.dimensions() != after.dimensions();
+
+    let mut diff_image = RgbaImage::new(width, height);
+    let mut differing_pixels = 0u64;
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for y in 0..height {
+        for x in 0..width {
+            let a = before.get_pixel_checked(x, y);
+            let b = after.get_pixel_checked(x, y);
+            let differs = match (a, b) {
+                (Some(a), Some(b)) => pixel_differs(a, b, options),
+                _ => true,
+            };
+
+            let marked = if differs {
+                differing_pixels += 1;
+                bounds = Some(match bounds {
+                    Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                    None => (x, y, x, y),
+                });
+                Rgba([255, 0, 0, 255])
+            } else {
+                faded(b.or(a).copied().unwrap_or(Rgba([255, 255, 255, 255])))
+            };
+            diff_image.put_pixel(x, y, marked);
+        }
+    }
+
+    Ok(ImageDiff {
+        width,
+        height,
+        differing_pixels,
+        total_pixels: width as u64 * height as u64,
+        size_mismatch,
+        region: bounds.map(|(x0, y0, x1, y1)| DiffRegion {
+            x: x0,
+            y: y0,
+            width: x1 - x0 + 1,
+            height: y1 - y0 + 1,
+        }),
+        diff_image,
+    })
+}
+
+fn pixel_differs(a: &Rgba<u8>, b: &Rgba<u8>, options: &DiffOptions) -> bool {
+    if a == b {
+        return false;
+    }
+    match options.mode {
+        DiffMode::Pixel => {
+            let max_delta = a.0.iter().zip(b.0.iter()).map(|(x, y)| x.abs_diff(*y)).max().unwrap_or(0);
+            max_delta as f64 / 255.0 > options.threshold
+        }
+        DiffMode::Perceptual => yiq_delta(blend_white(a), blend_white(b)) / MAX_YIQ_DELTA > options.threshold * options.threshold,
+    }
+}
+
+/// 与白色背景混合去掉透明度
+fn blend_white(pixel: &Rgba<u8>) -> [f64; 3] {
+    let alpha = pixel.0[3] as f64 / 255.0;
+    let blend = |c: u8| 255.0 + (c as f64 - 255.0) * alpha;
+    [blend(pixel.0[0]), blend(pixel.0[1]), blend(pixel.0[2])]
+}
+
+fn yiq_delta(a: [f64; 3], b: [f64; 3]) -> f64 {
+    let [r, g, b] = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    let y = r * 0.29889531 + g * 0.58662247 + b * 0.11448223;
+    let i = r * 0.59597799 - g * 0.27417610 - b * 0.32180189;
+    let q = r * 0.21147017 - g * 0.52261711 + b * 0.31114694;
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+/// 淡化为浅灰色，突出标红的差异
+fn faded(pixel: Rgba<u8>) -> Rgba<u8> {
+    let [r, g, b] = blend_white(&pixel);
+    let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+    let value = (255.0 - (255.0 - luma) * 0.3) as u8;
+    Rgba([value, value, value, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(color))
+    }
+
+    #[test]
+    fn test_compare_changed_region() {
+        let before = solid(10, 10, [255, 255, 255, 255]);
+        let mut after = before.clone();
+        for y in 2..4 {
+            for x in 5..8 {
+                after.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+            }
+        }
+        // 轻微色差在默认容差内
+        after.put_pixel(0, 0, Rgba([250, 250, 250, 255]));
+
+        let diff = compare(
+            &DynamicImage::ImageRgba8(before),
+            &DynamicImage::ImageRgba8(after),
+            &DiffOptions::default(),
+        )
+        .unwrap();
+
+        assert_eq!(diff.differing_pixels, 6);
+        assert!((diff.difference_percent() - 6.0).abs() < f64::EPSILON);
+        assert_eq!(diff.region, Some(DiffRegion { x: 5, y: 2, width: 3, height: 2 }));
+        assert_eq!(*diff.diff_image.get_pixel(6, 3), Rgba([255, 0, 0, 255]));
+        assert!(!diff.size_mismatch);
+    }
+
+    #[test]
+    fn test_compare_modes_and_sizes() {
+        let before = DynamicImage::ImageRgba8(solid(4, 4, [100, 100, 100, 255]));
+        let after = DynamicImage::ImageRgba8(solid(4, 4, [110, 100, 100, 255]));
+
+        let strict = DiffOptions { mode: DiffMode::Pixel, threshold: 0.0 };
+        assert_eq!(compare(&before, &after, &strict).unwrap().differing_pixels, 16);
+        assert!(compare(&before, &after, &DiffOptions::default()).unwrap().is_identical());
+
+        let larger = DynamicImage::ImageRgba8(solid(4, 5, [100, 100, 100, 255]));
+        let diff = compare(&before, &larger, &DiffOptions::default()).unwrap();
+        assert!(diff.size_mismatch);
+        assert_eq!(diff.differing_pixels, 4);
+
+        let invalid = DiffOptions { threshold: 2.0, ..DiffOptions::default() };
+        assert!(compare(&before, &after, &invalid).is_err());
+        assert!("fuzzy".parse::<DiffMode>().is_err());
+    }
+}
@@ -2,6 +2,9 @@
 //! 
 //! 使用 image crate 实现图像处理功能，替代 Sharp
 
+pub mod diff;
+pub mod ocr;
+
 use image::{
     DynamicImage, ImageFormat, GenericImageView,
     imageops::{FilterType, resize},
@@ -0,0 +1,181 @@
+//! 截图文字识别
+//!
+//! 调用 tesseract 识别附加截图（错误弹窗、终端输出等）中的文字，
+//! 识别结果与图像一起发送，提高模型准确率并减少视觉 token 消耗
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::{timeout, Duration};
+
+use crate::config::ImageConfig;
+use crate::error::{ClaudeError, Result};
+
+/// 单张图片的识别超时时间
+const OCR_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 宽度小于该值的图片会先放大，tesseract 对小字号识别较差
+const MIN_OCR_WIDTH: u32 = 1200;
+
+/// 识别结果中至少包含的字母数字字符数，低于该值视为图片中没有文字
+const MIN_MEANINGFUL_CHARS: usize = 8;
+
+/// 基于 tesseract 的文字识别器
+#[derive(Debug, Clone)]
+pub struct OcrEngine {
+    /// tesseract 可执行文件
+    binary: PathBuf,
+    /// 识别语言
+    language: String,
+}
+
+impl Default for OcrEngine {
+    fn default() -> Self {
+        Self::from_config(&ImageConfig::default())
+    }
+}
+
+impl OcrEngine {
+    /// 根据配置创建识别器
+    pub fn from_config(config: &ImageConfig) -> Self {
+        Self {
+            binary: config.tesseract_path.clone().unwrap_or_else(|| PathBuf::from("tesseract")),
+            language: config.ocr_language.clone(),
+        }
+    }
+
+    /// tesseract 是否可用
+    pub async fn is_available(&self) -> bool {
+        let status = Command::new(&self.binary)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .status();
+        matches!(timeout(OCR_TIMEOUT, status).await, Ok(Ok(status)) if status.success())
+    }
+
+    /// 识别图片文件中的文字，没有可识别文字时返回 `None`
+    pub async fn recognize_file(&self, path: &Path) -> Result<Option<String>> {
+        let data = tokio::fs::read(path).await?;
+        let img = image::load_from_memory(&data)
+            .map_err(|e| ClaudeError::General(format!("Failed to decode image '{}': {}", path.display(), e)))?;
+        self.recognize(&img).await
+    }
+
+    /// 识别图像中的文字，没有可识别文字时返回 `None`
+    pub async fn recognize(&self, img: &DynamicImage) -> Result<Option<String>> {
+        let mut png = Vec::new();
+        preprocess(img)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| ClaudeError::General(format!("Failed to encode image for OCR: {}", e)))?;
+
+        let mut child = Command::new(&self.binary)
+            .args(["stdin", "stdout", "-l", &self.language, "--psm", "6"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| ClaudeError::General(format!("Failed to run {}: {}", self.binary.display(), e)))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&png).await?;
+        }
+
+        let output = timeout(OCR_TIMEOUT, child.wait_with_output())
+            .await
+            .map_err(|_| ClaudeError::General("OCR timed out".to_string()))??;
+        if !output.status.success() {
+            return Err(ClaudeError::General(format!(
+                "tesseract failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(clean_text(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+/// 预处理：转灰度、放大小图，并把深色背景（终端截图）反相为白底黑字
+pub fn preprocess(img: &DynamicImage) -> DynamicImage {
+    let mut gray = img.grayscale();
+
+    let (width, height) = gray.dimensions();
+    if width > 0 && width < MIN_OCR_WIDTH {
+        let scale = MIN_OCR_WIDTH.div_ceil(width).min(4);
+        gray = gray.resize(width * scale, height * scale, FilterType::CatmullRom);
+    }
+
+    let luma = gray.to_luma8();
+    let pixels = luma.pixels().len().max(1) as u64;
+    let mean = luma.pixels().map(|p| p.0[0] as u64).sum::<u64>() / pixels;
+    if mean < 128 {
+        gray.invert();
+    }
+    gray
+}
+
+/// 清理识别结果：去掉行尾空白和多余空行，文字过少时返回 `None`
+pub fn clean_text(raw: &str) -> Option<String> {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in raw.lines().map(str::trim_end) {
+        if line.trim().is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(if line.trim().is_empty() { "" } else { line });
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+
+    let text = lines.join("\n");
+    let meaningful = text.chars().filter(|c| c.is_alphanumeric()).count();
+    (meaningful >= MIN_MEANINGFUL_CHARS).then_some(text)
+}
+
+/// 格式化为附加在提示词中的文本
+pub fn format_attachment(source: &str, text: &str) -> String {
+    format!("[Text extracted from image {} via OCR]\n```\n{}\n```", source, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Luma, GrayImage};
+
+    #[test]
+    fn test_clean_text() {
+        let raw = "  \nerror[E0308]: mismatched types   \n\n\n  --> src/main.rs:4:5\n\n";
+        assert_eq!(
+            clean_text(raw).as_deref(),
+            Some("error[E0308]: mismatched types\n\n  --> src/main.rs:4:5")
+        );
+        assert_eq!(clean_text(" | . ~ \n"), None);
+    }
+
+    #[test]
+    fn test_preprocess_dark_screenshot() {
+        let dark = DynamicImage::ImageLuma8(GrayImage::from_pixel(300, 100, Luma([20])));
+        let processed = preprocess(&dark);
+
+        assert_eq!(processed.dimensions(), (1200, 400));
+        assert!(processed.to_luma8().get_pixel(0, 0).0[0] > 200);
+    }
+
+    #[tokio::test]
+    async fn test_missing_binary() {
+        let config = ImageConfig {
+            tesseract_path: Some(PathBuf::from("/nonexistent/tesseract")),
+            ..ImageConfig::default()
+        };
+        let engine = OcrEngine::from_config(&config);
+        assert!(!engine.is_available().await);
+
+        let img = DynamicImage::ImageLuma8(GrayImage::from_pixel(10, 10, Luma([255])));
+        assert!(engine.recognize(&img).await.is_err());
+    }
+}
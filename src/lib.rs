@@ -4,26 +4,76 @@
 //! including file system management, Git operations, syntax highlighting,
 //! memory management, and more.
 
+// 可移植的核心，不依赖异步运行时、子进程和终端，可以构建到 wasm32
+pub mod conversation;
+pub mod error;
+pub mod tokens;
+pub mod ui;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "native")]
 pub mod agent;
+#[cfg(feature = "native")]
+pub mod analytics;
+#[cfg(feature = "native")]
+pub mod bench;
+#[cfg(feature = "native")]
 pub mod cli;
+#[cfg(feature = "native")]
+pub mod collaboration;
+#[cfg(feature = "native")]
 pub mod config;
+#[cfg(feature = "native")]
 pub mod context;
-pub mod conversation;
+#[cfg(feature = "native")]
 pub mod cost;
-pub mod error;
+#[cfg(feature = "native")]
+pub mod database;
+#[cfg(feature = "native")]
 pub mod fs;
+#[cfg(feature = "native")]
+pub mod gc;
+#[cfg(feature = "native")]
 pub mod git;
+#[cfg(feature = "native")]
+pub mod github;
+#[cfg(feature = "native")]
+pub mod health;
+#[cfg(feature = "native")]
+pub mod locks;
+#[cfg(feature = "native")]
 pub mod mcp;
+#[cfg(feature = "native")]
+pub mod models;
+#[cfg(feature = "native")]
 pub mod network;
+#[cfg(feature = "native")]
 pub mod plugins;
+#[cfg(feature = "native")]
 pub mod process;
+#[cfg(feature = "native")]
+pub mod prompts;
+#[cfg(feature = "native")]
 pub mod refactor;
+#[cfg(feature = "native")]
+pub mod reports;
+#[cfg(feature = "native")]
 pub mod security;
+#[cfg(feature = "native")]
+pub mod snippets;
+#[cfg(feature = "native")]
 pub mod steering;
+#[cfg(feature = "native")]
 pub mod streaming;
+#[cfg(all(feature = "native", any(test, feature = "test-support")))]
+pub mod test_support;
+#[cfg(feature = "native")]
 pub mod tools;
-pub mod ui;
+#[cfg(feature = "native")]
 pub mod watcher;
+#[cfg(feature = "native")]
 pub mod web;
 
 #[cfg(feature = "image-processing")]
@@ -33,14 +83,24 @@ pub mod image_processing;
 pub mod syntax_highlighting;
 
 // Re-export commonly used types
+pub use error::{ClaudeError, Result};
+#[cfg(feature = "native")]
 pub use agent::{AgentLoop, AgentContext, AgentStatus, AgentResponse};
+#[cfg(feature = "native")]
 pub use context::{ContextManager, CompressedContext, ContextStats};
-pub use error::{ClaudeError, Result};
+#[cfg(feature = "native")]
 pub use config::{ClaudeConfig, ConfigManager};
+#[cfg(feature = "native")]
 pub use fs::FileSystemManager;
+#[cfg(feature = "native")]
 pub use git::GitManager;
+#[cfg(feature = "native")]
 pub use steering::{SteeringController, SteeringSession, AsyncMessageQueue};
+#[cfg(feature = "native")]
+pub use streaming::cancel::{CancelHandle, CancellableStream, PartialGeneration};
+#[cfg(feature = "native")]
 pub use tools::{Tool, ToolRegistry, ToolResult, ToolDefinition, ToolContext};
+#[cfg(feature = "native")]
 pub use ui::TerminalUI;
 
 #[cfg(feature = "syntax-highlighting")]
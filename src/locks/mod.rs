@@ -0,0 +1,585 @@
+//! 锁的顺序约束与诊断
+//!
+//! 对 tokio / std 锁的薄封装。每把锁带有名称和等级（rank），同一任务内只能按等级从低到高加锁，
+//! 违反顺序即可能与另一条路径形成环形等待。开启 `--debug-locks` 后记录每把锁的等待与持有时间、
+//! 检查加锁顺序，并由独立的看门狗线程报告长时间持有、长时间等待以及等待环（死锁）。
+//! 未开启时只多一次原子读
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex as StdMutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// 锁等级。同一任务持有某个等级的锁时，只能再获取等级更高的锁
+pub mod rank {
+    pub const AGENT_STATUS: u16 = 10;
+    pub const AGENT_CONVERSATION: u16 = 20;
+    pub const STEERING_INTERRUPT: u16 = 30;
+    pub const STEERING_ERROR: u16 = 40;
+    pub const STEERING_QUEUE: u16 = 41;
+    pub const STEERING_DONE: u16 = 42;
+    pub const STEERING_TOOL_CANCEL: u16 = 43;
+    pub const WORKFLOW_SCHEDULE: u16 = 50;
+    pub const PROCESS_ID: u16 = 60;
+    pub const PROCESS_TABLE: u16 = 61;
+    pub const JOB_TABLE: u16 = 70;
+    pub const ORCHESTRATOR_MAILBOX: u16 = 71;
+    pub const WEB_FETCH_CACHE: u16 = 80;
+    pub const PERMISSION_ALWAYS: u16 = 90;
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static REGISTRY: OnceLock<StdMutex<Registry>> = OnceLock::new();
+
+/// 开启诊断；`threshold` 为判定持有或等待过久的时长。重复调用只更新阈值
+pub fn enable(threshold: Duration) {
+    let first = !ENABLED.swap(true, Ordering::SeqCst);
+    registry().threshold = threshold;
+    if first {
+        let spawned = std::thread::Builder::new()
+            .name("lock-watchdog".to_string())
+            .spawn(watchdog);
+        if let Err(e) = spawned {
+            tracing::warn!("Failed to start lock watchdog: {}", e);
+        }
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    REGISTRY
+        .get_or_init(|| StdMutex::new(Registry::default()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// 加锁的一方：tokio 任务，或不在任务中时的线程
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Owner {
+    Task(tokio::task::Id),
+    Thread(std::thread::ThreadId),
+}
+
+impl Owner {
+    fn current() -> Self {
+        match tokio::task::try_id() {
+            Some(id) => Self::Task(id),
+            None => Self::Thread(std::thread::current().id()),
+        }
+    }
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Task(id) => write!(f, "task {}", id),
+            Self::Thread(id) => write!(f, "{:?}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    lock_id: u64,
+    name: &'static str,
+    rank: u16,
+    owner: Owner,
+    location: &'static Location<'static>,
+    since: Instant,
+    reported: bool,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    threshold: Duration,
+    next_entry: u64,
+    holds: HashMap<u64, Entry>,
+    waits: HashMap<u64, Entry>,
+    stats: HashMap<&'static str, LockStats>,
+    violations: Vec<OrderViolation>,
+    stalls: usize,
+    deadlocks: Vec<String>,
+}
+
+impl Registry {
+    fn stats(&mut self, name: &'static str, rank: u16) -> &mut LockStats {
+        self.stats.entry(name).or_insert_with(|| LockStats { name, rank, ..Default::default() })
+    }
+}
+
+/// 一把锁（按名称汇总）的统计
+#[derive(Debug, Clone, Default)]
+pub struct LockStats {
+    pub name: &'static str,
+    pub rank: u16,
+    pub acquisitions: u64,
+    /// 需要等待才拿到锁的次数
+    pub contended: u64,
+    pub max_wait: Duration,
+    pub max_hold: Duration,
+    /// 最长一次持有的加锁位置
+    pub max_hold_at: Option<String>,
+}
+
+/// 违反加锁顺序的一次记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderViolation {
+    pub acquiring: &'static str,
+    pub acquiring_rank: u16,
+    pub acquiring_at: String,
+    pub held: &'static str,
+    pub held_rank: u16,
+    pub held_at: String,
+}
+
+impl fmt::Display for OrderViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' (rank {}) acquired at {} while holding '{}' (rank {}) from {}",
+            self.acquiring, self.acquiring_rank, self.acquiring_at, self.held, self.held_rank, self.held_at
+        )
+    }
+}
+
+/// 等待中的加锁请求；拿到锁或请求被取消时撤销
+struct Waiting {
+    entry: u64,
+    name: &'static str,
+    rank: u16,
+    lock_id: u64,
+    started: Instant,
+}
+
+impl Waiting {
+    #[must_use]
+    fn begin(lock_id: u64, name: &'static str, rank: u16, location: &'static Location<'static>) -> Option<Self> {
+        if !is_enabled() {
+            return None;
+        }
+        let owner = Owner::current();
+        let mut registry = registry();
+
+        let inversions: Vec<OrderViolation> = registry
+            .holds
+            .values()
+            .filter(|held| held.owner == owner && held.rank >= rank)
+            .map(|held| OrderViolation {
+                acquiring: name,
+                acquiring_rank: rank,
+                acquiring_at: location.to_string(),
+                held: held.name,
+                held_rank: held.rank,
+                held_at: held.location.to_string(),
+            })
+            .collect();
+        for violation in inversions {
+            if !registry.violations.iter().any(|v| v.acquiring == violation.acquiring && v.held == violation.held) {
+                tracing::warn!("🔒 Lock order violation: {}", violation);
+                registry.violations.push(violation);
+            }
+        }
+
+        registry.next_entry += 1;
+        let entry = registry.next_entry;
+        let started = Instant::now();
+        registry.waits.insert(entry, Entry { lock_id, name, rank, owner, location, since: started, reported: false });
+        Some(Self { entry, name, rank, lock_id, started })
+    }
+
+    fn acquired(self) -> Held {
+        let wait = self.started.elapsed();
+        let mut registry = registry();
+        let Some(mut entry) = registry.waits.remove(&self.entry) else {
+            return Held { entry: self.entry, name: self.name, rank: self.rank };
+        };
+        let stats = registry.stats(self.name, self.rank);
+        stats.acquisitions += 1;
+        if wait > Duration::from_millis(1) {
+            stats.contended += 1;
+        }
+        stats.max_wait = stats.max_wait.max(wait);
+        debug_assert_eq!(entry.lock_id, self.lock_id);
+        entry.since = Instant::now();
+        entry.reported = false;
+        registry.holds.insert(self.entry, entry);
+        Held { entry: self.entry, name: self.name, rank: self.rank }
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        registry().waits.remove(&self.entry);
+    }
+}
+
+/// 持有中的锁；随守卫一起释放
+struct Held {
+    entry: u64,
+    name: &'static str,
+    rank: u16,
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        let mut registry = registry();
+        let Some(entry) = registry.holds.remove(&self.entry) else {
+            return;
+        };
+        let held = entry.since.elapsed();
+        let threshold = registry.threshold;
+        if held > threshold {
+            tracing::warn!("🔒 '{}' was held for {:?} (acquired at {})", self.name, held, entry.location);
+        }
+        let stats = registry.stats(self.name, self.rank);
+        if held > stats.max_hold {
+            stats.max_hold = held;
+            stats.max_hold_at = Some(entry.location.to_string());
+        }
+    }
+}
+
+/// 看门狗：不依赖 tokio 运行时，运行时卡住时照样能报告
+fn watchdog() {
+    loop {
+        let interval = (registry().threshold / 2).max(Duration::from_millis(10));
+        std::thread::sleep(interval);
+        scan();
+    }
+}
+
+/// 报告超过阈值仍未结束的等待与持有，并检查等待环
+fn scan() {
+    let mut registry = registry();
+    let threshold = registry.threshold;
+    let holds: Vec<Entry> = registry.holds.values().cloned().collect();
+
+    let mut stalled = 0;
+    for wait in registry.waits.values_mut().filter(|w| !w.reported && w.since.elapsed() > threshold) {
+        wait.reported = true;
+        stalled += 1;
+        let holders: Vec<String> = holds
+            .iter()
+            .filter(|h| h.lock_id == wait.lock_id)
+            .map(|h| format!("{} since {}", h.owner, h.location))
+            .collect();
+        tracing::warn!(
+            "🔒 {} has been waiting {:?} for '{}' at {} (held by: {})",
+            wait.owner,
+            wait.since.elapsed(),
+            wait.name,
+            wait.location,
+            if holders.is_empty() { "nobody".to_string() } else { holders.join(", ") }
+        );
+    }
+    registry.stalls += stalled;
+
+    for hold in registry.holds.values_mut().filter(|h| !h.reported && h.since.elapsed() > threshold) {
+        hold.reported = true;
+        tracing::warn!("🔒 {} is still holding '{}' after {:?} (acquired at {})", hold.owner, hold.name, hold.since.elapsed(), hold.location);
+    }
+
+    if let Some(cycle) = find_cycle(&registry) {
+        if !registry.deadlocks.contains(&cycle) {
+            tracing::error!("🔒 Deadlock detected: {}", cycle);
+            registry.deadlocks.push(cycle);
+        }
+    }
+}
+
+/// 在“等待者 → 持有者”图中找环，找到时返回描述
+fn find_cycle(registry: &Registry) -> Option<String> {
+    let waiting_on: HashMap<&Owner, &Entry> = registry.waits.values().map(|w| (&w.owner, w)).collect();
+    for start in waiting_on.keys() {
+        let mut path: Vec<&Entry> = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = *start;
+        while let Some(wait) = waiting_on.get(current) {
+            if !visited.insert(current) {
+                break;
+            }
+            path.push(wait);
+            let Some(holder) = registry.holds.values().find(|h| h.lock_id == wait.lock_id && h.owner != wait.owner) else {
+                break;
+            };
+            if &holder.owner == *start {
+                let steps: Vec<String> = path.iter().map(|w| format!("{} waits for '{}' at {}", w.owner, w.name, w.location)).collect();
+                return Some(steps.join(" → "));
+            }
+            current = &holder.owner;
+        }
+    }
+    None
+}
+
+/// 诊断汇总，程序退出时打印
+#[derive(Debug, Clone, Default)]
+pub struct LockReport {
+    pub threshold: Duration,
+    pub locks: Vec<LockStats>,
+    pub violations: Vec<OrderViolation>,
+    /// 超过阈值的等待次数
+    pub stalls: usize,
+    pub deadlocks: Vec<String>,
+}
+
+pub fn report() -> LockReport {
+    let registry = registry();
+    let mut locks: Vec<LockStats> = registry.stats.values().cloned().collect();
+    locks.sort_by(|a, b| b.max_hold.cmp(&a.max_hold).then(a.name.cmp(b.name)));
+    LockReport {
+        threshold: registry.threshold,
+        locks,
+        violations: registry.violations.clone(),
+        stalls: registry.stalls,
+        deadlocks: registry.deadlocks.clone(),
+    }
+}
+
+impl LockReport {
+    pub fn render(&self) -> String {
+        let mut out = format!("🔒 Lock diagnostics (threshold {:?})\n", self.threshold);
+        if self.locks.is_empty() {
+            out.push_str("   No instrumented locks were acquired\n");
+        }
+        for lock in &self.locks {
+            let slow = if lock.max_hold > self.threshold { " ⚠️" } else { "" };
+            out.push_str(&format!(
+                "   {:<22} rank {:>3}  {:>6} acquired  {:>5} contended  max wait {:>10.2?}  max hold {:>10.2?}{}\n",
+                lock.name, lock.rank, lock.acquisitions, lock.contended, lock.max_wait, lock.max_hold, slow
+            ));
+            if let (true, Some(at)) = (lock.max_hold > self.threshold, &lock.max_hold_at) {
+                out.push_str(&format!("      longest hold acquired at {}\n", at));
+            }
+        }
+        if self.stalls > 0 {
+            out.push_str(&format!("   ⏳ {} wait(s) exceeded the threshold\n", self.stalls));
+        }
+        for violation in &self.violations {
+            out.push_str(&format!("   ❌ Order violation: {}\n", violation));
+        }
+        for deadlock in &self.deadlocks {
+            out.push_str(&format!("   💀 Deadlock: {}\n", deadlock));
+        }
+        out
+    }
+}
+
+/// 带等级的异步互斥锁
+pub struct Mutex<T> {
+    id: u64,
+    name: &'static str,
+    rank: u16,
+    inner: tokio::sync::Mutex<T>,
+}
+
+pub struct MutexGuard<'a, T> {
+    inner: tokio::sync::MutexGuard<'a, T>,
+    _held: Option<Held>,
+}
+
+impl<T> Mutex<T> {
+    pub fn new(name: &'static str, rank: u16, value: T) -> Self {
+        Self { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), name, rank, inner: tokio::sync::Mutex::new(value) }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> impl std::future::Future<Output = MutexGuard<'_, T>> + '_ {
+        let location = Location::caller();
+        async move {
+            let waiting = Waiting::begin(self.id, self.name, self.rank, location);
+            let inner = self.inner.lock().await;
+            MutexGuard { inner, _held: waiting.map(Waiting::acquired) }
+        }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+/// 带等级的异步读写锁
+pub struct RwLock<T> {
+    id: u64,
+    name: &'static str,
+    rank: u16,
+    inner: tokio::sync::RwLock<T>,
+}
+
+pub struct RwLockReadGuard<'a, T> {
+    inner: tokio::sync::RwLockReadGuard<'a, T>,
+    _held: Option<Held>,
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    inner: tokio::sync::RwLockWriteGuard<'a, T>,
+    _held: Option<Held>,
+}
+
+impl<T> RwLock<T> {
+    pub fn new(name: &'static str, rank: u16, value: T) -> Self {
+        Self { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), name, rank, inner: tokio::sync::RwLock::new(value) }
+    }
+
+    #[track_caller]
+    pub fn read(&self) -> impl std::future::Future<Output = RwLockReadGuard<'_, T>> + '_ {
+        let location = Location::caller();
+        async move {
+            let waiting = Waiting::begin(self.id, self.name, self.rank, location);
+            let inner = self.inner.read().await;
+            RwLockReadGuard { inner, _held: waiting.map(Waiting::acquired) }
+        }
+    }
+
+    #[track_caller]
+    pub fn write(&self) -> impl std::future::Future<Output = RwLockWriteGuard<'_, T>> + '_ {
+        let location = Location::caller();
+        async move {
+            let waiting = Waiting::begin(self.id, self.name, self.rank, location);
+            let inner = self.inner.write().await;
+            RwLockWriteGuard { inner, _held: waiting.map(Waiting::acquired) }
+        }
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+/// 带等级的同步互斥锁，用于不跨越 `.await` 的短临界区。被毒化时继续使用内部数据
+pub struct BlockingMutex<T> {
+    id: u64,
+    name: &'static str,
+    rank: u16,
+    inner: StdMutex<T>,
+}
+
+pub struct BlockingMutexGuard<'a, T> {
+    inner: std::sync::MutexGuard<'a, T>,
+    _held: Option<Held>,
+}
+
+impl<T> BlockingMutex<T> {
+    pub fn new(name: &'static str, rank: u16, value: T) -> Self {
+        Self { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), name, rank, inner: StdMutex::new(value) }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> BlockingMutexGuard<'_, T> {
+        let waiting = Waiting::begin(self.id, self.name, self.rank, Location::caller());
+        let inner = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        BlockingMutexGuard { inner, _held: waiting.map(Waiting::acquired) }
+    }
+}
+
+impl<T> Deref for BlockingMutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for BlockingMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+macro_rules! impl_debug {
+    ($($ty:ident),*) => {
+        $(impl<T> fmt::Debug for $ty<T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct(stringify!($ty)).field("name", &self.name).field("rank", &self.rank).finish_non_exhaustive()
+            }
+        })*
+    };
+}
+
+impl_debug!(Mutex, RwLock, BlockingMutex);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_diagnostics_report_order_violations_and_deadlocks() {
+        enable(Duration::from_millis(20));
+
+        let low = Mutex::new("test.low", 1, 0);
+        let high = BlockingMutex::new("test.high", 2, 0);
+        {
+            let _high = high.lock();
+            let mut low = low.lock().await;
+            *low += 1;
+            tokio::time::sleep(Duration::from_millis(30)).await;
+        }
+        let report = report();
+        let violation = report.violations.iter().find(|v| v.acquiring == "test.low").unwrap();
+        assert_eq!(violation.held, "test.high");
+        assert!(violation.acquiring_at.contains("locks/mod.rs"));
+        let low_stats = report.locks.iter().find(|l| l.name == "test.low").unwrap();
+        assert_eq!(low_stats.acquisitions, 1);
+        assert!(low_stats.max_hold >= Duration::from_millis(30));
+        assert!(report.render().contains("Order violation"));
+
+        // 两个任务按相反顺序加锁，形成等待环
+        let a = std::sync::Arc::new(Mutex::new("test.a", 3, ()));
+        let b = std::sync::Arc::new(Mutex::new("test.b", 4, ()));
+        let barrier = std::sync::Arc::new(tokio::sync::Barrier::new(2));
+        let first = tokio::spawn({
+            let (a, b, barrier) = (a.clone(), b.clone(), barrier.clone());
+            async move {
+                let _a = a.lock().await;
+                barrier.wait().await;
+                let _b = b.lock().await;
+            }
+        });
+        let second = tokio::spawn({
+            let (a, b, barrier) = (a.clone(), b.clone(), barrier.clone());
+            async move {
+                let _b = b.lock().await;
+                barrier.wait().await;
+                let _a = a.lock().await;
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        scan();
+        let report = super::report();
+        assert!(report.deadlocks.iter().any(|d| d.contains("test.a") && d.contains("test.b")));
+        assert!(report.violations.iter().any(|v| v.acquiring == "test.a" && v.held == "test.b"));
+        first.abort();
+        second.abort();
+    }
+}
@@ -4,6 +4,7 @@
 
 mod agent;
 mod analytics;
+mod bench;
 mod cache;
 mod cli;
 mod cloud;
@@ -19,19 +20,30 @@ mod distributed;
 mod error;
 mod fs;
 mod gateway;
+mod gc;
 mod git;
+mod github;
+mod health;
 mod inference;
+mod locks;
 mod mcp;
 mod ml;
+mod models;
 mod monitoring;
 mod network;
 mod plugins;
 mod process;
+mod prompts;
 mod refactor;
+mod reports;
 mod search;
 mod security;
+mod snippets;
 mod steering;
 mod streaming;
+#[cfg(any(test, feature = "test-support"))]
+mod test_support;
+mod tokens;
 mod tools;
 mod ui;
 mod watcher;
@@ -57,8 +69,8 @@ use std::path::Path;
 #[tokio::main]
 async fn main() {
     if let Err(e) = run().await {
-        eprintln!("❌ Error: {}", e);
-        std::process::exit(1);
+        eprintln!("{}", e.render());
+        std::process::exit(e.exit_code());
     }
 }
 
@@ -67,10 +79,17 @@ async fn run() -> Result<()> {
     let cli = Cli::parse_args();
 
     // 初始化日志
-    init_logging(cli.debug)?;
+    init_logging(cli.debug, cli.serves_mcp())?;
+    let debug_locks = cli.debug_locks;
+    if debug_locks {
+        locks::enable(std::time::Duration::from_millis(500));
+    }
 
     tracing::info!("Starting Claude Code Rust v0.1.0");
 
+    // 首次进入目录时询问是否信任
+    cli::ensure_folder_trust(&cli)?;
+
     // 创建 CLI 处理器
     let cli_handler = match cli::ClaudeCodeCli::new().await {
         Ok(handler) => handler,
@@ -80,10 +99,13 @@ async fn run() -> Result<()> {
         }
     };
 
-    // 执行命令
-    cli_handler.execute(cli).await?;
-
-    Ok(())
+    // 执行命令，退出前等待后台的 Webhook 投递
+    let result = cli_handler.execute(cli).await;
+    network::webhooks::drain(std::time::Duration::from_secs(15)).await;
+    if debug_locks {
+        eprint!("{}", locks::report().render());
+    }
+    result
 }
 
 async fn handle_command(
@@ -113,7 +135,7 @@ async fn handle_command(
         Commands::Stream { url, realtime } => {
             handle_stream_command(url, realtime).await?;
         }
-        Commands::Api { message, model, stream, image, tools } => {
+        Commands::Api { message, model, stream, image, tools, .. } => {
             handle_api_command(message, model, stream, image, tools).await?;
         }
         Commands::Config { action } => {
@@ -123,7 +145,7 @@ async fn handle_command(
         Commands::Init { path, force } => {
             handle_init_command(path, force).await?;
         }
-        Commands::Review { target, review_type } => {
+        Commands::Review { target, review_type, .. } => {
             handle_review_command(target, review_type).await?;
         }
         Commands::Memory { action } => {
@@ -167,7 +189,7 @@ async fn handle_command(
         Commands::Image { command } => {
             handle_image_command(&command).await?;
         }
-        Commands::Model { set, list } => {
+        Commands::Model { set, list, .. } => {
             handle_model_command(set, list, config_manager).await?;
         }
         Commands::Resume { conversation_id } => {
@@ -209,6 +231,10 @@ async fn handle_command(
         Commands::Serve { port, host, static_dir, no_cors, no_compression } => {
             handle_serve_command(port, host, static_dir, no_cors, no_compression, config_manager).await?;
         }
+        // 新增命令由 ClaudeCodeCli::execute 处理
+        _ => {
+            tracing::info!("Command not handled by legacy dispatcher");
+        }
     }
 
     Ok(())
@@ -713,6 +739,7 @@ async fn handle_process_command(command: &cli::ProcessCommand) -> Result<()> {
                 capture_output: *capture,
                 timeout: None,
                 auto_restart: false,
+                limits: Default::default(),
             };
 
             match process_manager.start_process(config).await {
@@ -791,6 +818,10 @@ async fn handle_process_command(command: &cli::ProcessCommand) -> Result<()> {
                     if output.stdout.is_empty() && output.stderr.is_empty() {
                         println!("No output available");
                     }
+
+                    if let Some(violation) = &output.limit_violation {
+                        println!("⚠️  {}", violation.message);
+                    }
                 }
                 Err(e) => {
                     println!("❌ Failed to get output: {}", e);
@@ -1274,7 +1305,7 @@ async fn handle_mcp_command(action: cli::McpCommands, config_manager: &mut Confi
             }
         }
 
-        cli::McpCommands::Add { name, command, args } => {
+        cli::McpCommands::Add { name, command, args, .. } => {
             println!("🔌 Adding MCP server '{}'...", name);
 
             let server_config = config::McpServerConfig {
@@ -1284,6 +1315,7 @@ async fn handle_mcp_command(action: cli::McpCommands, config_manager: &mut Confi
                 env: HashMap::new(),
                 working_dir: None,
                 auto_start: false,
+                ..Default::default()
             };
 
             // 检查是否已存在同名服务器
@@ -1309,7 +1341,7 @@ async fn handle_mcp_command(action: cli::McpCommands, config_manager: &mut Confi
             }
         }
 
-        cli::McpCommands::Remove { name } => {
+        cli::McpCommands::Remove { name, .. } => {
             println!("🔌 Removing MCP server '{}'...", name);
 
             let config = config_manager.get_config_mut();
@@ -1353,6 +1385,18 @@ async fn handle_mcp_command(action: cli::McpCommands, config_manager: &mut Confi
             println!("💡 MCP server stop functionality needs to be implemented");
             println!("Server '{}' stop requested", name);
         }
+
+        cli::McpCommands::Resources { name } => {
+            println!("💡 Use the claude-rust library CLI to list resources of MCP server '{}'", name);
+        }
+
+        cli::McpCommands::ResetProjectChoices => {
+            println!("💡 Use the claude-rust library CLI to reset project MCP server choices");
+        }
+
+        cli::McpCommands::Serve => {
+            eprintln!("💡 Use the claude-rust library CLI to serve tools over MCP");
+        }
     }
 
     Ok(())
@@ -1574,6 +1618,12 @@ async fn handle_git_command(command: &cli::GitCommand) -> Result<()> {
                 }
             }
         }
+        cli::GitCommand::Resolve { .. } => {
+            println!("💡 Conflict resolution runs through the main CLI: claude git resolve");
+        }
+        cli::GitCommand::BisectAssist { .. } => {
+            println!("💡 Guided bisect runs through the main CLI: claude git bisect-assist");
+        }
     }
 
     Ok(())
@@ -1588,7 +1638,7 @@ async fn handle_highlight_command(command: &cli::HighlightCommand) -> Result<()>
         let highlighter = SyntaxHighlighter::new()?;
 
         match command {
-            cli::HighlightCommand::File { path, language } => {
+            cli::HighlightCommand::File { path, language, .. } => {
                 println!("🎨 Highlighting file: {}", path);
 
                 // 读取文件内容
@@ -1637,7 +1687,7 @@ async fn handle_highlight_command(command: &cli::HighlightCommand) -> Result<()>
                 }
             }
 
-            cli::HighlightCommand::Code { code, language } => {
+            cli::HighlightCommand::Code { code, language, .. } => {
                 println!("🎨 Highlighting code snippet");
                 println!("Language: {}", language);
                 println!("{}", "=".repeat(50));
@@ -1712,6 +1762,15 @@ async fn handle_highlight_command(command: &cli::HighlightCommand) -> Result<()>
                 println!("💡 Use 'claude-code-rust highlight file <path>' to highlight a file");
                 println!("💡 Use 'claude-code-rust highlight code <code> --language <lang>' to highlight code");
             }
+
+            cli::HighlightCommand::Themes => {
+                let mut themes = SyntaxHighlighter::with_user_themes()?.get_available_themes();
+                themes.sort();
+                println!("🎨 Available Themes");
+                for theme in themes {
+                    println!("  • {}", theme);
+                }
+            }
         }
     }
 
@@ -1746,10 +1805,10 @@ async fn handle_demo_command() -> Result<()> {
 
     // 添加一些示例消息
     let messages = vec![
-        Message { role: "user".to_string(), content: "Hello, Claude!".to_string() },
-        Message { role: "assistant".to_string(), content: "Hello! How can I help you today?".to_string() },
-        Message { role: "user".to_string(), content: "Can you help me write some Rust code?".to_string() },
-        Message { role: "assistant".to_string(), content: "Absolutely! I'd be happy to help you with Rust code.".to_string() },
+        Message { role: "user".to_string(), content: "Hello, Claude!".into() },
+        Message { role: "assistant".to_string(), content: "Hello! How can I help you today?".into() },
+        Message { role: "user".to_string(), content: "Can you help me write some Rust code?".into() },
+        Message { role: "assistant".to_string(), content: "Absolutely! I'd be happy to help you with Rust code.".into() },
     ];
 
     for message in messages {
@@ -2094,8 +2153,12 @@ async fn handle_stream_command(url: Option<String>, realtime: bool) -> Result<()
         enable_compression: true,
     };
 
-    // 创建流式客户端
+    // 创建流式客户端，用量计数器按配置的模型估算费用
+    let model = ConfigManager::new()
+        .map(|manager| manager.get_config().api.default_model.clone())
+        .unwrap_or_else(|_| claude_rust::config::ApiConfig::default().default_model);
     let mut client = StreamingClient::new(config.clone());
+    client.enable_ticker(&model);
 
     // 设置请求头
     let mut headers = HashMap::new();
@@ -2116,11 +2179,16 @@ async fn handle_stream_command(url: Option<String>, realtime: bool) -> Result<()
         // 启动流式处理任务
         let stream_handle = tokio::spawn({
             let test_url = test_url.clone();
+            let model = model.clone();
             async move {
                 let mut stream_client = StreamingClient::new(config.clone());
+                stream_client.enable_ticker(&model);
                 if let Err(e) = stream_client.start_stream(&test_url, headers).await {
                     eprintln!("❌ Stream error: {}", e);
                 }
+                if let Some(snapshot) = stream_client.ticker_snapshot() {
+                    eprintln!("\n{}", snapshot.format_summary());
+                }
             }
         });
 
@@ -2248,6 +2316,9 @@ async fn handle_stream_command(url: Option<String>, realtime: bool) -> Result<()
     println!("   • Events received: {}", stats.events_received);
     println!("   • Bytes received: {}", stats.bytes_received);
     println!("   • Error count: {}", stats.error_count);
+    if let Some(snapshot) = client.ticker_snapshot().filter(|_| !realtime) {
+        println!("   • Usage ({}): {}", model, snapshot.format_status());
+    }
 
     if let Some(first_event) = stats.first_event_time {
         if let Some(last_event) = stats.last_event_time {
@@ -2496,7 +2567,7 @@ async fn handle_compact_command_enhanced(instructions: Option<String>, level: Op
     for i in 0..10 {
         let message = Message {
             role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
-            content: format!("Sample message {} for compression testing", i),
+            content: format!("Sample message {} for compression testing", i).into(),
         };
         context_manager.add_message(message).await?;
     }
@@ -2606,7 +2677,7 @@ async fn handle_text_request(
                     match event.event_type.as_str() {
                         "content_block_delta" => {
                             if let Some(data) = event.data {
-                                if let Ok(delta) = serde_json::from_value::<crate::network::StreamDelta>(data) {
+                                if let Ok(delta) = serde_json::from_value::<crate::network::StreamDelta>(data["delta"].clone()) {
                                     if let Some(text) = delta.text {
                                         print!("{}", text);
                                         io::stdout().flush().unwrap();
@@ -2709,7 +2780,7 @@ async fn handle_multimodal_request(
                     match event.event_type.as_str() {
                         "content_block_delta" => {
                             if let Some(data) = event.data {
-                                if let Ok(delta) = serde_json::from_value::<crate::network::StreamDelta>(data) {
+                                if let Ok(delta) = serde_json::from_value::<crate::network::StreamDelta>(data["delta"].clone()) {
                                     if let Some(text) = delta.text {
                                         print!("{}", text);
                                         io::stdout().flush().unwrap();
@@ -2836,7 +2907,7 @@ async fn handle_tool_request(
                     match event.event_type.as_str() {
                         "content_block_delta" => {
                             if let Some(data) = event.data {
-                                if let Ok(delta) = serde_json::from_value::<crate::network::StreamDelta>(data) {
+                                if let Ok(delta) = serde_json::from_value::<crate::network::StreamDelta>(data["delta"].clone()) {
                                     if let Some(text) = delta.text {
                                         print!("{}", text);
                                         io::stdout().flush().unwrap();
@@ -3391,6 +3462,9 @@ async fn handle_tui_command() -> Result<()> {
     println!("Press 'q' to quit, 'h' for help");
 
     let mut app = TerminalApp::new();
+    if let Ok(manager) = ConfigManager::new() {
+        app.set_model(manager.get_config().api.default_model.clone());
+    }
 
     if let Err(e) = app.run().await {
         eprintln!("❌ Terminal UI error: {}", e);
@@ -0,0 +1,169 @@
+//! MCP 工具 Schema 缓存
+//!
+//! 按服务器版本缓存 `tools/list` 的结果并持久化到磁盘，
+//! 启动时可直接使用缓存的 Schema，无需等待服务器完成发现
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use crate::config::{McpServerConfig, McpTransport};
+use crate::error::{ClaudeError, Result};
+
+/// 单个服务器的缓存条目
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CachedServerSchema {
+    /// 服务器版本，版本变化时缓存失效
+    pub version: String,
+    /// 工具定义
+    pub tools: Vec<Value>,
+    /// 缓存时间
+    pub cached_at: DateTime<Utc>,
+}
+
+/// MCP 工具 Schema 缓存
+#[derive(Debug, Clone, Default)]
+pub struct McpSchemaCache {
+    /// 存储文件路径
+    path: PathBuf,
+    /// 按服务器名称索引的条目
+    entries: HashMap<String, CachedServerSchema>,
+}
+
+impl McpSchemaCache {
+    /// 默认存储路径（缓存目录下的 mcp_schemas.json）
+    pub fn default_path() -> Result<PathBuf> {
+        let cache_dir = dirs::cache_dir()
+            .ok_or_else(|| ClaudeError::config_error("Cannot find cache directory"))?;
+        Ok(cache_dir.join("claude-code").join("mcp_schemas.json"))
+    }
+
+    /// 从默认路径加载
+    pub fn load_default() -> Result<Self> {
+        Self::load(Self::default_path()?)
+    }
+
+    /// 从指定路径加载，文件不存在或损坏时返回空缓存
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring corrupt MCP schema cache {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, entries })
+    }
+
+    /// 保存缓存
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string(&self.entries)?)?;
+        Ok(())
+    }
+
+    /// 获取与版本匹配的工具定义
+    pub fn get(&self, server: &str, version: &str) -> Option<&[Value]> {
+        self.entries
+            .get(server)
+            .filter(|entry| entry.version == version)
+            .map(|entry| entry.tools.as_slice())
+    }
+
+    /// 写入服务器的工具定义，替换旧版本的条目
+    pub fn insert(&mut self, server: impl Into<String>, version: impl Into<String>, tools: Vec<Value>) {
+        self.entries.insert(
+            server.into(),
+            CachedServerSchema {
+                version: version.into(),
+                tools,
+                cached_at: Utc::now(),
+            },
+        );
+    }
+}
+
+/// 服务器启动配置的指纹
+///
+/// 服务器尚未报告版本时作为缓存版本使用，命令、参数、环境变量或工作目录变化都会使缓存失效，
+/// 远程服务器则是连接方式、地址和请求头
+pub fn config_fingerprint(config: &McpServerConfig) -> String {
+    let identity = match config.transport {
+        McpTransport::Stdio => {
+            let env: BTreeMap<&String, &String> = config.env.iter().collect();
+            serde_json::json!({
+                "command": config.command,
+                "args": config.args,
+                "env": env,
+                "working_dir": config.working_dir,
+            })
+        }
+        transport => {
+            let headers: BTreeMap<&String, &String> = config.headers.iter().collect();
+            serde_json::json!({
+                "transport": transport,
+                "url": config.url,
+                "headers": headers,
+            })
+        }
+    };
+    format!("cfg-{:x}", md5::compute(identity.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn server_config(args: &[&str]) -> McpServerConfig {
+        McpServerConfig {
+            name: "files".to_string(),
+            command: "npx".to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            env: HashMap::new(),
+            working_dir: None,
+            auto_start: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cache_roundtrip_and_versioning() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("schemas.json");
+
+        let mut cache = McpSchemaCache::load(path.clone()).unwrap();
+        cache.insert("files", "1.0.0", vec![json!({ "name": "read_file" })]);
+        cache.save().unwrap();
+
+        let cache = McpSchemaCache::load(path.clone()).unwrap();
+        assert_eq!(cache.get("files", "1.0.0").unwrap().len(), 1);
+        assert!(cache.get("files", "1.1.0").is_none());
+        assert!(cache.get("git", "1.0.0").is_none());
+
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(McpSchemaCache::load(path).unwrap().get("files", "1.0.0").is_none());
+    }
+
+    #[test]
+    fn test_config_fingerprint() {
+        let a = server_config(&["server-files", "/tmp"]);
+        let b = server_config(&["server-files", "/home"]);
+        assert_eq!(config_fingerprint(&a), config_fingerprint(&a.clone()));
+        assert_ne!(config_fingerprint(&a), config_fingerprint(&b));
+
+        let remote = |url: &str| McpServerConfig {
+            name: "files".to_string(),
+            transport: McpTransport::Http,
+            url: Some(url.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config_fingerprint(&remote("https://a.example/mcp")), config_fingerprint(&remote("https://a.example/mcp")));
+        assert_ne!(config_fingerprint(&remote("https://a.example/mcp")), config_fingerprint(&remote("https://b.example/mcp")));
+    }
+}
@@ -0,0 +1,402 @@
+//! MCP JSON-RPC 客户端
+//!
+//! 经由传输层（stdio、Streamable HTTP 或旧版 HTTP+SSE）收发 JSON-RPC 2.0 消息：按 id 把响应
+//! 交给等待中的请求，服务器发来的通知广播给订阅者，服务器的请求中只应答 `ping`。
+//! 连接建立后先完成 `initialize` 握手，记下服务器的能力
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::time::Duration;
+
+use super::{transport, McpError, McpMessage};
+use crate::error::{ClaudeError, Result};
+
+/// 客户端声明支持的协议版本
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// 单个请求的默认超时
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 通知广播的缓冲容量
+const NOTIFICATION_CAPACITY: usize = 64;
+
+/// 等待响应的请求；连接关闭后为 `None`，之后的请求立即失败
+type PendingRequests = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<Result<Value>>>>>>;
+
+/// 服务器名称和版本
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Implementation {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+/// 服务器声明的能力，值为各能力的选项对象
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerCapabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompts: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<Value>,
+}
+
+impl ServerCapabilities {
+    /// 已声明的能力名称
+    pub fn names(&self) -> Vec<&'static str> {
+        [("tools", &self.tools), ("resources", &self.resources), ("prompts", &self.prompts), ("logging", &self.logging)]
+            .into_iter()
+            .filter(|(_, capability)| capability.is_some())
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+/// `initialize` 握手的结果
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeResult {
+    pub protocol_version: String,
+    #[serde(default)]
+    pub capabilities: ServerCapabilities,
+    #[serde(default)]
+    pub server_info: Implementation,
+    /// 服务器给模型的使用说明
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+}
+
+/// 一个 MCP 连接
+pub struct McpClient {
+    /// 服务器名称，用于日志和错误信息
+    name: String,
+    outgoing: mpsc::UnboundedSender<McpMessage>,
+    pending: PendingRequests,
+    next_id: AtomicU64,
+    notifications: broadcast::Sender<McpMessage>,
+    server: OnceLock<InitializeResult>,
+    request_timeout: Duration,
+}
+
+impl McpClient {
+    /// 在一对按行分隔 JSON 的读写流上建立连接，例如子进程的 stdout 和 stdin
+    pub fn connect<R, W>(name: impl Into<String>, reader: R, writer: W) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (client, outgoing, incoming) = Self::open(name.into());
+        transport::stdio(&client.name, reader, writer, outgoing, incoming);
+        client
+    }
+
+    /// 通过 Streamable HTTP 连接远程服务器
+    pub fn connect_http(name: impl Into<String>, url: &str, headers: &HashMap<String, String>) -> Result<Self> {
+        let (client, outgoing, incoming) = Self::open(name.into());
+        transport::streamable_http(&client.name, url, headers, outgoing, incoming)?;
+        Ok(client)
+    }
+
+    /// 通过旧版 HTTP+SSE 连接远程服务器
+    pub async fn connect_sse(name: impl Into<String>, url: &str, headers: &HashMap<String, String>) -> Result<Self> {
+        let (client, outgoing, incoming) = Self::open(name.into());
+        transport::sse(&client.name, url, headers, outgoing, incoming).await?;
+        Ok(client)
+    }
+
+    /// 创建客户端和交给传输层的通道，并启动处理服务器消息的任务
+    fn open(name: String) -> (Self, transport::Outgoing, transport::Incoming) {
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel::<McpMessage>();
+        let (incoming, mut incoming_rx) = mpsc::unbounded_channel::<McpMessage>();
+        let pending: PendingRequests = Arc::new(Mutex::new(Some(HashMap::new())));
+        let notifications = broadcast::channel(NOTIFICATION_CAPACITY).0;
+
+        // 只持有发送端的弱引用，客户端释放后传输层随之关闭连接
+        let reply = outgoing.downgrade();
+        let (server_name, reader_pending, reader_notifications) = (name.clone(), pending.clone(), notifications.clone());
+        tokio::spawn(async move {
+            while let Some(message) = incoming_rx.recv().await {
+                dispatch(&server_name, message, &reader_pending, &reader_notifications, &reply);
+            }
+            // 连接关闭后所有等待中的请求失败
+            let closed = format!("MCP server '{}' closed the connection", server_name);
+            let waiting = reader_pending.lock().unwrap().take().unwrap_or_default();
+            for (_, sender) in waiting {
+                let _ = sender.send(Err(ClaudeError::mcp_error(closed.clone())));
+            }
+        });
+
+        let client = Self {
+            name,
+            outgoing,
+            pending,
+            next_id: AtomicU64::new(1),
+            notifications,
+            server: OnceLock::new(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+        };
+        (client, outgoing_rx, incoming)
+    }
+
+    /// 设置单个请求的超时
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 完成握手：发送 `initialize`，记下服务器能力后发送 `notifications/initialized`
+    pub async fn initialize(&self) -> Result<&InitializeResult> {
+        if let Some(server) = self.server.get() {
+            return Ok(server);
+        }
+        let params = serde_json::json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+        });
+        let result: InitializeResult = serde_json::from_value(self.request("initialize", params).await?)
+            .map_err(|e| ClaudeError::mcp_error(format!("Invalid initialize result from '{}': {}", self.name, e)))?;
+        tracing::info!(
+            "MCP server '{}' initialized: {} {} (protocol {}, capabilities: {})",
+            self.name,
+            result.server_info.name,
+            result.server_info.version,
+            result.protocol_version,
+            result.capabilities.names().join(", ")
+        );
+        self.notify("notifications/initialized", Value::Null)?;
+        Ok(self.server.get_or_init(|| result))
+    }
+
+    /// 握手后服务器报告的信息
+    pub fn server_info(&self) -> Option<&InitializeResult> {
+        self.server.get()
+    }
+
+    /// 发送请求并等待响应，服务器返回的错误转换为 `ClaudeError`
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        let not_connected = || ClaudeError::mcp_error(format!("MCP server '{}' is not connected", self.name));
+        self.pending.lock().unwrap().as_mut().ok_or_else(not_connected)?.insert(id, sender);
+        if self.outgoing.send(McpMessage::request(id, method, params)).is_err() {
+            self.remove_pending(id);
+            return Err(not_connected());
+        }
+        match tokio::time::timeout(self.request_timeout, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(ClaudeError::mcp_error(format!("MCP server '{}' closed the connection", self.name))),
+            Err(_) => {
+                self.remove_pending(id);
+                Err(ClaudeError::mcp_error(format!(
+                    "MCP request '{}' to '{}' timed out after {}s",
+                    method,
+                    self.name,
+                    self.request_timeout.as_secs()
+                )))
+            }
+        }
+    }
+
+    fn remove_pending(&self, id: u64) {
+        if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+            pending.remove(&id);
+        }
+    }
+
+    /// 发送通知，不等待响应
+    pub fn notify(&self, method: &str, params: Value) -> Result<()> {
+        self.outgoing
+            .send(McpMessage::notification(method, params))
+            .map_err(|_| ClaudeError::mcp_error(format!("MCP server '{}' is not connected", self.name)))
+    }
+
+    /// 订阅服务器发来的通知
+    pub fn subscribe(&self) -> broadcast::Receiver<McpMessage> {
+        self.notifications.subscribe()
+    }
+
+    /// `tools/list`，自动翻页
+    pub async fn list_tools(&self) -> Result<Vec<Value>> {
+        self.list_paginated("tools/list", "tools").await
+    }
+
+    /// `tools/call`，返回服务器的原始结果（`content` 和 `isError`）
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value> {
+        self.request("tools/call", serde_json::json!({ "name": name, "arguments": arguments })).await
+    }
+
+    /// `resources/list`，自动翻页
+    pub async fn list_resources(&self) -> Result<Vec<Value>> {
+        self.list_paginated("resources/list", "resources").await
+    }
+
+    /// `resources/read`，返回服务器的原始结果（`contents`）
+    pub async fn read_resource(&self, uri: &str) -> Result<Value> {
+        self.request("resources/read", serde_json::json!({ "uri": uri })).await
+    }
+
+    /// `prompts/list`，自动翻页
+    pub async fn list_prompts(&self) -> Result<Vec<Value>> {
+        self.list_paginated("prompts/list", "prompts").await
+    }
+
+    /// `prompts/get`，返回服务器的原始结果（`description` 和 `messages`）
+    pub async fn get_prompt(&self, name: &str, arguments: &HashMap<String, String>) -> Result<Value> {
+        self.request("prompts/get", serde_json::json!({ "name": name, "arguments": arguments })).await
+    }
+
+    /// 按 `nextCursor` 翻页，收集结果中 `field` 数组的全部元素
+    pub(crate) async fn list_paginated(&self, method: &str, field: &str) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => serde_json::json!({ "cursor": cursor }),
+                None => serde_json::json!({}),
+            };
+            let mut result = self.request(method, params).await?;
+            if let Some(Value::Array(page)) = result.get_mut(field).map(Value::take) {
+                items.extend(page);
+            }
+            match result.get("nextCursor").and_then(Value::as_str) {
+                Some(next) if !next.is_empty() => cursor = Some(next.to_string()),
+                _ => return Ok(items),
+            }
+        }
+    }
+}
+
+/// 处理读到的一条消息
+fn dispatch(
+    server_name: &str,
+    message: McpMessage,
+    pending: &PendingRequests,
+    notifications: &broadcast::Sender<McpMessage>,
+    reply: &mpsc::WeakUnboundedSender<McpMessage>,
+) {
+    match message {
+        McpMessage::Response { id, result, error, .. } => {
+            let sender = id.as_u64().and_then(|id| pending.lock().unwrap().as_mut()?.remove(&id));
+            let Some(sender) = sender else {
+                tracing::debug!("Dropping MCP response from '{}' with unknown id {}", server_name, id);
+                return;
+            };
+            let outcome = match error {
+                Some(McpError { code, message, .. }) => {
+                    Err(ClaudeError::mcp_error(format!("MCP server '{}' returned error {}: {}", server_name, code, message)))
+                }
+                None => Ok(result.unwrap_or(Value::Null)),
+            };
+            let _ = sender.send(outcome);
+        }
+        McpMessage::Request { id, method, .. } => {
+            let response = if method == "ping" {
+                McpMessage::response(id, serde_json::json!({}))
+            } else {
+                tracing::debug!("MCP server '{}' sent unsupported request '{}'", server_name, method);
+                McpMessage::error_response(id, McpError::method_not_found(&method))
+            };
+            if let Some(reply) = reply.upgrade() {
+                let _ = reply.send(response);
+            }
+        }
+        notification @ McpMessage::Notification { .. } => {
+            // 没有订阅者时丢弃
+            let _ = notifications.send(notification);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    /// 在内存管道上运行的简易服务器：应答 initialize、分页的 tools/list 和 tools/call，并向客户端发 ping
+    async fn serve(stream: tokio::io::DuplexStream) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let message: Value = serde_json::from_str(&line).unwrap();
+            let Some(id) = message.get("id").cloned() else {
+                continue;
+            };
+            if message.get("method").is_none() {
+                // 客户端对 ping 的应答
+                assert_eq!(message["result"], serde_json::json!({}));
+                continue;
+            }
+            let result = match message["method"].as_str().unwrap() {
+                "initialize" => {
+                    writer.write_all(b"{\"jsonrpc\":\"2.0\",\"id\":\"srv-1\",\"method\":\"ping\"}\n").await.unwrap();
+                    serde_json::json!({
+                        "protocolVersion": PROTOCOL_VERSION,
+                        "capabilities": { "tools": { "listChanged": true } },
+                        "serverInfo": { "name": "fake", "version": "1.2.3" },
+                    })
+                }
+                "tools/list" if message["params"]["cursor"].is_null() => {
+                    serde_json::json!({ "tools": [{ "name": "a" }], "nextCursor": "page-2" })
+                }
+                "tools/list" => serde_json::json!({ "tools": [{ "name": "b" }] }),
+                "tools/call" => serde_json::json!({ "content": [{ "type": "text", "text": message["params"]["arguments"]["x"] }] }),
+                "resources/list" => serde_json::json!({ "resources": [{ "uri": "note://1", "name": "first" }] }),
+                "resources/read" => serde_json::json!({ "contents": [{ "uri": message["params"]["uri"], "text": "note body" }] }),
+                _ => {
+                    let error = McpMessage::error_response(id, McpError::method_not_found(message["method"].as_str().unwrap()));
+                    writer.write_all(format!("{}\n", serde_json::to_string(&error).unwrap()).as_bytes()).await.unwrap();
+                    continue;
+                }
+            };
+            let response = McpMessage::response(id, result);
+            writer.write_all(format!("{}\n", serde_json::to_string(&response).unwrap()).as_bytes()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_requests_and_errors() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        tokio::spawn(serve(server_side));
+        let (reader, writer) = tokio::io::split(client_side);
+        let client = McpClient::connect("fake", reader, writer);
+
+        let server = client.initialize().await.unwrap();
+        assert_eq!(server.server_info.name, "fake");
+        assert_eq!(server.capabilities.names(), ["tools"]);
+
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools.iter().map(|t| t["name"].as_str().unwrap()).collect::<Vec<_>>(), ["a", "b"]);
+        let result = client.call_tool("echo", serde_json::json!({ "x": "hi" })).await.unwrap();
+        assert_eq!(result["content"][0]["text"], "hi");
+
+        assert_eq!(client.list_resources().await.unwrap()[0]["uri"], "note://1");
+        assert_eq!(client.read_resource("note://1").await.unwrap()["contents"][0]["text"], "note body");
+
+        let error = client.request("prompts/list", serde_json::json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("-32601"));
+    }
+
+    #[tokio::test]
+    async fn test_pending_requests_fail_when_server_exits() {
+        let (client_side, server_side) = tokio::io::duplex(4096);
+        let (reader, writer) = tokio::io::split(client_side);
+        let client = McpClient::connect("gone", reader, writer);
+        drop(server_side);
+        let error = client.request("tools/list", serde_json::json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("closed the connection") || error.to_string().contains("not connected"));
+    }
+}
@@ -0,0 +1,153 @@
+//! MCP 服务器健康检查
+//!
+//! 会话期间定时检查运行中的服务器：进程已退出或 `ping` 没有应答时按策略重启，重启次数有上限，
+//! 超过后放弃并把服务器标记为失败。每个事件都写入日志并追加到数据目录下的健康记录，
+//! `claude mcp list` 据此显示服务器最近的状况
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use tokio::time::Duration;
+
+use crate::error::Result;
+
+/// 检查和重启策略
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthPolicy {
+    /// 两次检查的间隔
+    pub interval: Duration,
+    /// 等待 `ping` 应答的时间
+    pub ping_timeout: Duration,
+    /// 一个会话中每个服务器最多自动重启的次数
+    pub max_restarts: u32,
+    /// 第一次重启前的等待时间，之后每次加倍
+    pub restart_backoff: Duration,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            ping_timeout: Duration::from_secs(10),
+            max_restarts: 3,
+            restart_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// 健康事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthEventKind {
+    /// 进程已退出
+    Crashed,
+    /// `ping` 失败或超时
+    Unhealthy,
+    /// 已重新启动
+    Restarted,
+    /// 重启失败，还会再试
+    RestartFailed,
+    /// 重启次数用完，不再尝试
+    GaveUp,
+}
+
+impl HealthEventKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Crashed => "crashed",
+            Self::Unhealthy => "unhealthy",
+            Self::Restarted => "restarted",
+            Self::RestartFailed => "restart failed",
+            Self::GaveUp => "gave up",
+        }
+    }
+}
+
+/// 一次健康事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthEvent {
+    pub at: DateTime<Utc>,
+    pub server: String,
+    pub kind: HealthEventKind,
+    pub detail: String,
+}
+
+impl HealthEvent {
+    pub fn new(server: &str, kind: HealthEventKind, detail: impl Into<String>) -> Self {
+        Self { at: Utc::now(), server: server.to_string(), kind, detail: detail.into() }
+    }
+}
+
+/// 服务器在本会话中的健康状况
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerHealth {
+    /// 已自动重启的次数
+    pub restarts: u32,
+    /// 最近一次检查通过的时间
+    pub last_ok: Option<DateTime<Utc>>,
+    /// 最近一次失败的原因
+    pub last_error: Option<String>,
+}
+
+/// 按行追加 JSON 的健康记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthLog {
+    path: PathBuf,
+}
+
+impl HealthLog {
+    /// 默认位置
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("mcp-health.jsonl")
+    }
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn append(&self, event: &HealthEvent) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(event)?)?;
+        Ok(())
+    }
+
+    /// 全部事件，按写入顺序；文件不存在时为空，损坏的行跳过
+    pub fn load(&self) -> Result<Vec<HealthEvent>> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+    }
+
+    /// 服务器最近一次事件
+    pub fn latest(&self, server: &str) -> Result<Option<HealthEvent>> {
+        Ok(self.load()?.into_iter().rev().find(|event| event.server == server))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_log_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = HealthLog::new(dir.path().join("health.jsonl"));
+        assert!(log.latest("files").unwrap().is_none());
+
+        log.append(&HealthEvent::new("files", HealthEventKind::Crashed, "exit status: 1")).unwrap();
+        log.append(&HealthEvent::new("git", HealthEventKind::Unhealthy, "ping timed out")).unwrap();
+        log.append(&HealthEvent::new("files", HealthEventKind::Restarted, "restart 1 of 3")).unwrap();
+        std::fs::OpenOptions::new().append(true).open(dir.path().join("health.jsonl")).unwrap().write_all(b"{broken\n").unwrap();
+
+        assert_eq!(log.load().unwrap().len(), 3);
+        assert_eq!(log.latest("files").unwrap().unwrap().kind, HealthEventKind::Restarted);
+        assert_eq!(log.latest("git").unwrap().unwrap().detail, "ping timed out");
+    }
+}
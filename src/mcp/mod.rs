@@ -2,21 +2,65 @@
 //! 
 //! 实现 MCP 服务器的启动、停止、配置管理和通信协议
 
+pub mod cache;
+pub mod client;
+pub mod health;
+pub mod prompts;
+pub mod resources;
+pub mod scope;
+pub mod server;
+pub mod tool;
+pub mod transport;
+
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::process::{Child, Command, Stdio};
+use std::process::Stdio;
 use std::sync::{Arc, Mutex};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::{Child as AsyncChild, Command as AsyncCommand};
-use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
 
-use crate::config::McpServerConfig;
+use crate::config::{McpServerConfig, McpTransport};
+use crate::tools::Tool;
 use crate::error::{ClaudeError, Result};
+use cache::{config_fingerprint, McpSchemaCache};
+use health::{HealthEvent, HealthEventKind, HealthLog, HealthPolicy, ServerHealth};
+pub use client::{InitializeResult, McpClient};
+pub use prompts::McpPrompt;
+
+/// 预热时单个服务器的启动超时
+const WARM_UP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 停止服务器时等待其自行退出的时间
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
 
 /// MCP 服务器管理器
 pub struct McpManager {
     /// 运行中的服务器
     running_servers: Arc<Mutex<HashMap<String, McpServerInstance>>>,
+    /// 工具 Schema 缓存
+    schema_cache: Arc<Mutex<McpSchemaCache>>,
+    /// 各服务器在本会话中的健康状况
+    health: Mutex<HashMap<String, ServerHealth>>,
+    /// 健康检查和重启策略
+    health_policy: HealthPolicy,
+    /// 健康事件记录，未设置时只写日志
+    health_log: Option<HealthLog>,
+}
+
+/// 启动预热结果
+#[derive(Debug, Clone, Default)]
+pub struct WarmUpReport {
+    /// 新启动的服务器
+    pub started: Vec<String>,
+    /// 已在运行的服务器
+    pub already_running: Vec<String>,
+    /// 启动失败的服务器及原因
+    pub failed: Vec<(String, String)>,
+    /// 命中 Schema 缓存的服务器
+    pub cached: Vec<String>,
+    /// 总耗时
+    pub elapsed: Duration,
 }
 
 /// MCP 服务器实例
@@ -27,10 +71,12 @@ pub struct McpServerInstance {
     process: Option<AsyncChild>,
     /// 状态
     status: McpServerStatus,
-    /// 消息发送通道
-    message_sender: Option<mpsc::UnboundedSender<McpMessage>>,
-    /// 消息接收通道
-    message_receiver: Option<mpsc::UnboundedReceiver<McpMessage>>,
+    /// 完成握手的 JSON-RPC 连接
+    client: Option<Arc<McpClient>>,
+    /// 连接时 `tools/list` 返回的工具
+    tools: Vec<serde_json::Value>,
+    /// 连接时 `prompts/list` 返回的提示
+    prompts: Vec<McpPrompt>,
 }
 
 /// MCP 服务器状态
@@ -43,178 +89,252 @@ pub enum McpServerStatus {
     Error(String),
 }
 
-/// MCP 消息
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
+/// JSON-RPC 2.0 协议版本标记
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// MCP 消息，即按行传输的 JSON-RPC 2.0 消息
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum McpMessage {
     Request {
-        id: Option<String>,
+        jsonrpc: String,
+        id: serde_json::Value,
         method: String,
+        #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
         params: serde_json::Value,
     },
     Response {
-        id: Option<String>,
+        jsonrpc: String,
+        id: serde_json::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         result: Option<serde_json::Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         error: Option<McpError>,
     },
     Notification {
+        jsonrpc: String,
         method: String,
+        #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
         params: serde_json::Value,
     },
 }
 
+impl McpMessage {
+    pub fn request(id: impl Into<serde_json::Value>, method: impl Into<String>, params: serde_json::Value) -> Self {
+        Self::Request { jsonrpc: JSONRPC_VERSION.to_string(), id: id.into(), method: method.into(), params }
+    }
+
+    pub fn notification(method: impl Into<String>, params: serde_json::Value) -> Self {
+        Self::Notification { jsonrpc: JSONRPC_VERSION.to_string(), method: method.into(), params }
+    }
 
+    pub fn response(id: serde_json::Value, result: serde_json::Value) -> Self {
+        Self::Response { jsonrpc: JSONRPC_VERSION.to_string(), id, result: Some(result), error: None }
+    }
+
+    pub fn error_response(id: serde_json::Value, error: McpError) -> Self {
+        Self::Response { jsonrpc: JSONRPC_VERSION.to_string(), id, result: None, error: Some(error) }
+    }
+}
 
 /// MCP 错误
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct McpError {
     pub code: i32,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub data: Option<serde_json::Value>,
 }
 
+impl McpError {
+    /// 方法不存在 (-32601)
+    pub fn method_not_found(method: &str) -> Self {
+        Self { code: -32601, message: format!("Method not found: {}", method), data: None }
+    }
+
+    /// 参数无效 (-32602)
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self { code: -32602, message: message.into(), data: None }
+    }
+
+    /// 消息无法解析 (-32700)
+    pub fn parse_error(message: impl Into<String>) -> Self {
+        Self { code: -32700, message: message.into(), data: None }
+    }
+}
+
 impl McpManager {
     /// 创建新的 MCP 管理器
     pub fn new() -> Self {
+        Self::with_schema_cache(McpSchemaCache::default())
+    }
+
+    /// 使用指定的 Schema 缓存创建管理器
+    pub fn with_schema_cache(schema_cache: McpSchemaCache) -> Self {
         Self {
             running_servers: Arc::new(Mutex::new(HashMap::new())),
+            schema_cache: Arc::new(Mutex::new(schema_cache)),
+            health: Mutex::new(HashMap::new()),
+            health_policy: HealthPolicy::default(),
+            health_log: None,
         }
     }
 
-    /// 启动 MCP 服务器
-    pub async fn start_server(&self, config: McpServerConfig) -> Result<()> {
-        let server_name = config.name.clone();
-        
-        tracing::info!("Starting MCP server: {}", server_name);
-        
-        // 检查服务器是否已经在运行
-        {
-            let servers = self.running_servers.lock().unwrap();
-            if let Some(instance) = servers.get(&server_name) {
-                if instance.status == McpServerStatus::Running {
-                    return Err(ClaudeError::mcp_error(format!(
-                        "Server '{}' is already running", server_name
-                    )));
-                }
-            }
-        }
+    /// 设置健康检查和重启策略
+    pub fn with_health_policy(mut self, policy: HealthPolicy) -> Self {
+        self.health_policy = policy;
+        self
+    }
 
-        // 创建服务器实例
-        let mut instance = McpServerInstance {
-            config: config.clone(),
-            process: None,
-            status: McpServerStatus::Starting,
-            message_sender: None,
-            message_receiver: None,
-        };
+    /// 把健康事件追加到记录中
+    pub fn with_health_log(mut self, log: HealthLog) -> Self {
+        self.health_log = Some(log);
+        self
+    }
 
-        // 启动子进程
-        let mut cmd = AsyncCommand::new(&config.command);
-        cmd.args(&config.args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+    /// 启动预热：并发启动所有自动启动的服务器，并检查 Schema 缓存
+    ///
+    /// 单个服务器启动失败或超时不影响其他服务器
+    pub async fn warm_up(&self, servers: &HashMap<String, McpServerConfig>) -> WarmUpReport {
+        let started_at = Instant::now();
+        let mut report = WarmUpReport::default();
+
+        let mut pending = Vec::new();
+        for config in servers.values().filter(|config| config.auto_start) {
+            if self.get_server_status(&config.name) == Some(McpServerStatus::Running) {
+                report.already_running.push(config.name.clone());
+            } else {
+                pending.push(config.clone());
+            }
 
-        // 设置环境变量
-        for (key, value) in &config.env {
-            cmd.env(key, value);
+            if self.cached_tools(config).is_some() {
+                report.cached.push(config.name.clone());
+            }
         }
 
-        // 设置工作目录
-        if let Some(working_dir) = &config.working_dir {
-            cmd.current_dir(working_dir);
+        let results = futures::future::join_all(pending.into_iter().map(|config| async move {
+            let name = config.name.clone();
+            let result = match tokio::time::timeout(WARM_UP_TIMEOUT, self.start_server(config)).await {
+                Ok(result) => result.map_err(|e| e.to_string()),
+                Err(_) => Err(format!("Timed out after {}s", WARM_UP_TIMEOUT.as_secs())),
+            };
+            (name, result)
+        }))
+        .await;
+
+        for (name, result) in results {
+            match result {
+                Ok(()) => report.started.push(name),
+                Err(e) => {
+                    tracing::warn!("MCP warm-up failed for '{}': {}", name, e);
+                    report.failed.push((name, e));
+                }
+            }
         }
 
-        let mut child = cmd.spawn()?;
+        report.elapsed = started_at.elapsed();
+        report
+    }
+
+    /// 获取服务器缓存的工具定义，服务器配置变化后缓存失效
+    pub fn cached_tools(&self, config: &McpServerConfig) -> Option<Vec<serde_json::Value>> {
+        let cache = self.schema_cache.lock().unwrap();
+        cache.get(&config.name, &config_fingerprint(config)).map(<[_]>::to_vec)
+    }
+
+    /// 缓存服务器的工具定义并写入磁盘
+    pub fn store_tools(&self, config: &McpServerConfig, tools: Vec<serde_json::Value>) -> Result<()> {
+        let mut cache = self.schema_cache.lock().unwrap();
+        cache.insert(config.name.clone(), config_fingerprint(config), tools);
+        cache.save()
+    }
+
+    /// 启动 MCP 服务器：本地服务器通过 stdio 启动进程，远程服务器通过 HTTP 连接，然后完成 `initialize` 握手
+    ///
+    /// 服务器声明了工具能力时同时列出工具并写入 Schema 缓存
+    pub async fn start_server(&self, config: McpServerConfig) -> Result<()> {
+        let server_name = config.name.clone();
         
-        // 设置通信通道
-        let (tx, rx) = mpsc::unbounded_channel();
-        instance.message_sender = Some(tx);
-        instance.message_receiver = Some(rx);
-
-        // 启动消息处理任务
-        let stdin = child.stdin.take().ok_or_else(|| {
-            ClaudeError::mcp_error("Failed to get stdin handle")
-        })?;
+        tracing::info!("Starting MCP server: {}", server_name);
         
-        let stdout = child.stdout.take().ok_or_else(|| {
-            ClaudeError::mcp_error("Failed to get stdout handle")
-        })?;
-
-        let stderr = child.stderr.take().ok_or_else(|| {
-            ClaudeError::mcp_error("Failed to get stderr handle")
-        })?;
+        // 检查服务器是否已经在运行
+        if self.get_server_status(&server_name) == Some(McpServerStatus::Running) {
+            return Err(ClaudeError::mcp_error(format!(
+                "Server '{}' is already running", server_name
+            )));
+        }
 
-        // 启动输出读取任务
-        let server_name_clone = server_name.clone();
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stdout);
-            let mut line = String::new();
-            
-            while let Ok(n) = reader.read_line(&mut line).await {
-                if n == 0 {
-                    break;
-                }
-                
-                tracing::debug!("MCP server '{}' stdout: {}", server_name_clone, line.trim());
-                
-                // 尝试解析 JSON-RPC 消息
-                if let Ok(message) = serde_json::from_str::<McpMessage>(&line) {
-                    tracing::debug!("Received MCP message: {:?}", message);
-
-                    // 处理接收到的消息
-                    match message {
-                        McpMessage::Request { id, method, params } => {
-                            tracing::info!("Received MCP request: method={}, id={:?}", method, id);
-                            // 可以在这里添加请求处理逻辑
-                        }
-                        McpMessage::Response { id, result, error } => {
-                            tracing::info!("Received MCP response: id={:?}", id);
-                            if let Some(error) = error {
-                                tracing::warn!("MCP response error: {:?}", error);
-                            }
-                        }
-                        McpMessage::Notification { method, params } => {
-                            tracing::info!("Received MCP notification: method={}", method);
-                            // 处理通知消息
-                        }
+        let (process, client) = match config.transport {
+            McpTransport::Stdio => {
+                let (process, client) = spawn_stdio(&config)?;
+                (Some(process), client)
+            }
+            transport => {
+                let url = config.url.as_deref().ok_or_else(|| {
+                    ClaudeError::config_error(format!("MCP server '{}' uses the {} transport but has no url", server_name, transport.as_str()))
+                })?;
+                let client = match transport {
+                    McpTransport::Sse => McpClient::connect_sse(server_name.clone(), url, &config.headers).await?,
+                    _ => McpClient::connect_http(server_name.clone(), url, &config.headers)?,
+                };
+                (None, client)
+            }
+        };
+        let client = Arc::new(client);
+        self.running_servers.lock().unwrap().insert(
+            server_name.clone(),
+            McpServerInstance { config: config.clone(), process, status: McpServerStatus::Starting, client: None, tools: Vec::new(), prompts: Vec::new() },
+        );
+
+        let tools = match client.initialize().await {
+            Ok(server) if server.capabilities.tools.is_some() => client.list_tools().await.map(Some),
+            Ok(_) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let tools = match tools {
+            Ok(tools) => tools,
+            Err(e) => {
+                tracing::error!("MCP server '{}' failed to initialize: {}", server_name, e);
+                if let Some(mut instance) = self.running_servers.lock().unwrap().remove(&server_name) {
+                    if let Some(process) = instance.process.as_mut() {
+                        let _ = process.start_kill();
                     }
                 }
-                
-                line.clear();
+                return Err(e);
+            }
+        };
+        let tools = tools.unwrap_or_default();
+        if !tools.is_empty() {
+            if let Err(e) = self.store_tools(&config, tools.clone()) {
+                tracing::warn!("Failed to cache tools of MCP server '{}': {}", server_name, e);
             }
-        });
+        }
 
-        // 启动错误输出读取任务
-        let server_name_clone = server_name.clone();
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stderr);
-            let mut line = String::new();
-            
-            while let Ok(n) = reader.read_line(&mut line).await {
-                if n == 0 {
-                    break;
+        // 提示不可用不影响服务器的其他功能
+        let prompts = if client.server_info().is_some_and(|server| server.capabilities.prompts.is_some()) {
+            match client.list_prompts().await {
+                Ok(listing) => listing.iter().filter_map(|prompt| McpPrompt::from_listing(&server_name, prompt)).collect(),
+                Err(e) => {
+                    tracing::warn!("Failed to list prompts of MCP server '{}': {}", server_name, e);
+                    Vec::new()
                 }
-                
-                tracing::warn!("MCP server '{}' stderr: {}", server_name_clone, line.trim());
-                line.clear();
             }
-        });
-
-        instance.process = Some(child);
-        instance.status = McpServerStatus::Running;
+        } else {
+            Vec::new()
+        };
 
-        // 存储服务器实例
-        {
-            let mut servers = self.running_servers.lock().unwrap();
-            servers.insert(server_name.clone(), instance);
+        if let Some(instance) = self.running_servers.lock().unwrap().get_mut(&server_name) {
+            instance.client = Some(client);
+            instance.tools = tools;
+            instance.prompts = prompts;
+            instance.status = McpServerStatus::Running;
         }
 
         tracing::info!("MCP server '{}' started successfully", server_name);
         Ok(())
     }
 
-    /// 停止 MCP 服务器
+    /// 停止 MCP 服务器：关闭连接让服务器自行退出，超时后强制结束进程
     pub async fn stop_server(&self, server_name: &str) -> Result<()> {
         tracing::info!("Stopping MCP server: {}", server_name);
 
@@ -226,15 +346,20 @@ impl McpManager {
         };
 
         instance.status = McpServerStatus::Stopping;
+        // 丢弃连接会关闭服务器的 stdin
+        instance.client = None;
 
         if let Some(mut process) = instance.process.take() {
-            // 尝试优雅关闭
-            if let Err(e) = process.kill().await {
-                tracing::warn!("Failed to kill MCP server '{}': {}", server_name, e);
-            }
-
-            // 等待进程结束
-            match process.wait().await {
+            let status = match tokio::time::timeout(SHUTDOWN_GRACE, process.wait()).await {
+                Ok(status) => status,
+                Err(_) => {
+                    if let Err(e) = process.kill().await {
+                        tracing::warn!("Failed to kill MCP server '{}': {}", server_name, e);
+                    }
+                    process.wait().await
+                }
+            };
+            match status {
                 Ok(status) => {
                     tracing::info!("MCP server '{}' exited with status: {}", server_name, status);
                 }
@@ -248,26 +373,235 @@ impl McpManager {
         Ok(())
     }
 
-    /// 发送消息到 MCP 服务器
-    pub async fn send_message(&self, server_name: &str, message: McpMessage) -> Result<()> {
+    /// 运行中服务器的连接
+    pub fn client(&self, server_name: &str) -> Option<Arc<McpClient>> {
         let servers = self.running_servers.lock().unwrap();
-        let instance = servers.get(server_name).ok_or_else(|| {
-            ClaudeError::mcp_error(format!("Server '{}' not found", server_name))
+        servers.get(server_name).and_then(|instance| instance.client.clone())
+    }
+
+    /// 向运行中的服务器发送请求
+    pub async fn request(&self, server_name: &str, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let client = self.client(server_name).ok_or_else(|| {
+            ClaudeError::mcp_error(format!("Server '{}' is not running", server_name))
         })?;
+        client.request(method, params).await
+    }
 
-        if instance.status != McpServerStatus::Running {
-            return Err(ClaudeError::mcp_error(format!(
-                "Server '{}' is not running", server_name
-            )));
+    /// 握手时服务器报告的名称、版本和能力
+    pub fn server_info(&self, server_name: &str) -> Option<InitializeResult> {
+        self.client(server_name).and_then(|client| client.server_info().cloned())
+    }
+
+    /// 在后台定时检查服务器健康，管理器释放后停止
+    pub fn spawn_health_monitor(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let manager = Arc::downgrade(self);
+        let interval = self.health_policy.interval;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(manager) = manager.upgrade() else { break };
+                manager.check_health().await;
+            }
+        })
+    }
+
+    /// 检查一遍运行中的服务器：进程已退出或 `ping` 失败时按策略重启，返回本轮产生的事件
+    pub async fn check_health(&self) -> Vec<HealthEvent> {
+        let candidates: Vec<(String, Option<String>, Option<Arc<McpClient>>)> = {
+            let mut servers = self.running_servers.lock().unwrap();
+            servers
+                .iter_mut()
+                .filter(|(_, instance)| instance.status == McpServerStatus::Running)
+                .map(|(name, instance)| {
+                    let exited = instance
+                        .process
+                        .as_mut()
+                        .and_then(|process| process.try_wait().ok().flatten())
+                        .map(|status| format!("process exited ({})", status));
+                    (name.clone(), exited, instance.client.clone())
+                })
+                .collect()
+        };
+
+        let mut events = Vec::new();
+        for (name, exited, client) in candidates {
+            let failure = match (exited, client) {
+                (Some(reason), _) => Some((HealthEventKind::Crashed, reason)),
+                (None, Some(client)) => {
+                    let timeout = self.health_policy.ping_timeout;
+                    match tokio::time::timeout(timeout, client.request("ping", serde_json::json!({}))).await {
+                        Ok(Ok(_)) => {
+                            self.health.lock().unwrap().entry(name.clone()).or_default().last_ok = Some(chrono::Utc::now());
+                            None
+                        }
+                        Ok(Err(e)) => Some((HealthEventKind::Unhealthy, e.to_string())),
+                        Err(_) => Some((HealthEventKind::Unhealthy, format!("no response to ping within {}s", timeout.as_secs()))),
+                    }
+                }
+                (None, None) => None,
+            };
+            if let Some((kind, detail)) = failure {
+                events.extend(self.recover(&name, kind, detail).await);
+            }
         }
+        events
+    }
 
-        if let Some(sender) = &instance.message_sender {
-            sender.send(message).map_err(|e| {
-                ClaudeError::mcp_error(format!("Failed to send message: {}", e))
-            })?;
+    /// 重启出故障的服务器，失败时退避后重试，直到本会话的重启次数用完
+    async fn recover(&self, server_name: &str, kind: HealthEventKind, detail: String) -> Vec<HealthEvent> {
+        let mut events = vec![self.record_health_event(HealthEvent::new(server_name, kind, detail.clone()))];
+        let Some(config) = self.server_config(server_name) else {
+            return events;
+        };
+        if let Err(e) = self.stop_server(server_name).await {
+            tracing::debug!("Failed to stop MCP server '{}' before restarting: {}", server_name, e);
         }
 
-        Ok(())
+        let max_restarts = self.health_policy.max_restarts;
+        let mut last_error = detail;
+        loop {
+            let restarts = self.health.lock().unwrap().entry(server_name.to_string()).or_default().restarts;
+            if restarts >= max_restarts {
+                let detail = format!("no restarts left after {}: {}", restarts, last_error);
+                events.push(self.record_health_event(HealthEvent::new(server_name, HealthEventKind::GaveUp, detail)));
+                // 保留失败状态，状态查询和 `claude mcp list` 仍能看到这个服务器
+                self.running_servers.lock().unwrap().insert(
+                    server_name.to_string(),
+                    McpServerInstance {
+                        config,
+                        process: None,
+                        status: McpServerStatus::Error(last_error),
+                        client: None,
+                        tools: Vec::new(),
+                        prompts: Vec::new(),
+                    },
+                );
+                return events;
+            }
+
+            tokio::time::sleep(self.health_policy.restart_backoff * 2u32.saturating_pow(restarts)).await;
+            self.health.lock().unwrap().entry(server_name.to_string()).or_default().restarts = restarts + 1;
+            match self.start_server(config.clone()).await {
+                Ok(()) => {
+                    let detail = format!("restart {} of {}", restarts + 1, max_restarts);
+                    events.push(self.record_health_event(HealthEvent::new(server_name, HealthEventKind::Restarted, detail)));
+                    return events;
+                }
+                Err(e) => {
+                    last_error = e.to_string();
+                    let event = HealthEvent::new(server_name, HealthEventKind::RestartFailed, last_error.clone());
+                    events.push(self.record_health_event(event));
+                }
+            }
+        }
+    }
+
+    fn record_health_event(&self, event: HealthEvent) -> HealthEvent {
+        match event.kind {
+            HealthEventKind::Restarted => tracing::info!("MCP server '{}' {}: {}", event.server, event.kind.label(), event.detail),
+            HealthEventKind::GaveUp => tracing::error!("MCP server '{}' {}: {}", event.server, event.kind.label(), event.detail),
+            _ => tracing::warn!("MCP server '{}' {}: {}", event.server, event.kind.label(), event.detail),
+        }
+        if event.kind != HealthEventKind::Restarted {
+            self.health.lock().unwrap().entry(event.server.clone()).or_default().last_error = Some(event.detail.clone());
+        }
+        if let Some(log) = &self.health_log {
+            if let Err(e) = log.append(&event) {
+                tracing::warn!("Failed to record MCP health event: {}", e);
+            }
+        }
+        event
+    }
+
+    /// 服务器在本会话中的健康状况
+    pub fn health(&self, server_name: &str) -> Option<ServerHealth> {
+        self.health.lock().unwrap().get(server_name).cloned()
+    }
+
+    /// 健康事件记录
+    pub fn health_log(&self) -> Option<&HealthLog> {
+        self.health_log.as_ref()
+    }
+
+    /// 运行中的服务器连接时报告的工具
+    pub fn tools(&self, server_name: &str) -> Vec<serde_json::Value> {
+        let servers = self.running_servers.lock().unwrap();
+        servers.get(server_name).map(|instance| instance.tools.clone()).unwrap_or_default()
+    }
+
+    /// 把所有运行中服务器的工具注册到工具表，已注册的跳过，返回新注册的工具名称
+    pub async fn register_tools(self: &Arc<Self>, registry: &crate::tools::ToolRegistry) -> Result<Vec<String>> {
+        let mut servers = self.list_running_servers();
+        servers.sort();
+        let mut registered = Vec::new();
+        for server in servers {
+            for schema in self.tools(&server) {
+                let Some(tool) = tool::McpTool::new(self.clone(), &server, &schema) else {
+                    tracing::warn!("Skipping unnamed tool from MCP server '{}'", server);
+                    continue;
+                };
+                let name = tool.definition().name;
+                if registry.get_tool(&name).await.is_none() {
+                    registry.register_tool(Arc::new(tool)).await?;
+                    registered.push(name);
+                }
+            }
+        }
+        Ok(registered)
+    }
+
+    /// 所有运行中服务器提供的提示，按命令名排列
+    pub fn prompts(&self) -> Vec<McpPrompt> {
+        let servers = self.running_servers.lock().unwrap();
+        let mut prompts: Vec<McpPrompt> = servers.values().flat_map(|instance| instance.prompts.iter().cloned()).collect();
+        prompts.sort_by_key(McpPrompt::command);
+        prompts
+    }
+
+    /// 按交互命令名（不含 `/`）查找提示
+    pub fn find_prompt(&self, command: &str) -> Option<McpPrompt> {
+        self.prompts().into_iter().find(|prompt| prompt.command() == command)
+    }
+
+    /// 用参数展开提示，返回合并后的输入文本
+    pub async fn get_prompt(&self, prompt: &McpPrompt, arguments: &HashMap<String, String>) -> Result<String> {
+        prompt.validate(arguments)?;
+        let client = self.client(&prompt.server).ok_or_else(|| {
+            ClaudeError::mcp_error(format!("Server '{}' is not running", prompt.server))
+        })?;
+        let result = client.get_prompt(&prompt.name, arguments).await?;
+        Ok(prompts::messages_text(&result))
+    }
+
+    /// 运行中服务器提供的资源
+    pub async fn list_resources(&self, server_name: &str) -> Result<Vec<serde_json::Value>> {
+        self.resource_client(server_name)?.list_resources().await
+    }
+
+    /// 读取运行中服务器的资源，`reference` 为资源 URI 或名称，返回资源 URI 和文本内容
+    pub async fn read_resource(&self, server_name: &str, reference: &str) -> Result<(String, String)> {
+        let client = self.resource_client(server_name)?;
+        // 列出失败时按 URI 直接读取
+        let listed = client.list_resources().await.unwrap_or_default();
+        let uri = resources::resolve(&listed, reference).unwrap_or(reference).to_string();
+        let result = client.read_resource(&uri).await?;
+        Ok((uri, resources::contents_text(&result)))
+    }
+
+    fn resource_client(&self, server_name: &str) -> Result<Arc<McpClient>> {
+        let client = self.client(server_name).ok_or_else(|| {
+            ClaudeError::mcp_error(format!("Server '{}' is not running", server_name))
+        })?;
+        if client.server_info().is_some_and(|server| server.capabilities.resources.is_none()) {
+            return Err(ClaudeError::mcp_error(format!("MCP server '{}' does not provide resources", server_name)));
+        }
+        Ok(client)
+    }
+
+    /// 服务器的配置
+    pub fn server_config(&self, server_name: &str) -> Option<McpServerConfig> {
+        let servers = self.running_servers.lock().unwrap();
+        servers.get(server_name).map(|instance| instance.config.clone())
     }
 
     /// 获取服务器状态
@@ -301,16 +635,77 @@ impl McpManager {
 
 impl Drop for McpManager {
     fn drop(&mut self) {
-        // 在析构时尝试停止所有服务器
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async {
-            if let Err(e) = self.stop_all_servers().await {
-                tracing::error!("Failed to stop all MCP servers during cleanup: {}", e);
+        // 在析构时结束所有服务器进程；可能处于异步运行时中，因此不能阻塞等待
+        let mut servers = match self.running_servers.lock() {
+            Ok(servers) => servers,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for (server_name, instance) in servers.iter_mut() {
+            if let Some(process) = instance.process.as_mut() {
+                if let Err(e) = process.start_kill() {
+                    tracing::error!("Failed to stop MCP server '{}' during cleanup: {}", server_name, e);
+                }
             }
-        });
+        }
     }
 }
 
+/// 启动 stdio 服务器进程，在它的 stdout 和 stdin 上建立连接
+fn spawn_stdio(config: &McpServerConfig) -> Result<(AsyncChild, McpClient)> {
+    // 启动子进程
+    let mut cmd = AsyncCommand::new(&config.command);
+    cmd.args(&config.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    // 设置环境变量
+    for (key, value) in &config.env {
+        cmd.env(key, value);
+    }
+
+    // 设置工作目录
+    if let Some(working_dir) = &config.working_dir {
+        cmd.current_dir(working_dir);
+    }
+
+    let mut child = cmd.spawn().map_err(|e| {
+        ClaudeError::mcp_error(format!("Failed to start MCP server '{}' ({}): {}", config.name, config.command, e))
+    })?;
+
+    let stdin = child.stdin.take().ok_or_else(|| {
+        ClaudeError::mcp_error("Failed to get stdin handle")
+    })?;
+    
+    let stdout = child.stdout.take().ok_or_else(|| {
+        ClaudeError::mcp_error("Failed to get stdout handle")
+    })?;
+
+    let stderr = child.stderr.take().ok_or_else(|| {
+        ClaudeError::mcp_error("Failed to get stderr handle")
+    })?;
+
+    // 启动错误输出读取任务
+    let server_name = config.name.clone();
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stderr);
+        let mut line = String::new();
+        
+        while let Ok(n) = reader.read_line(&mut line).await {
+            if n == 0 {
+                break;
+            }
+            
+            tracing::warn!("MCP server '{}' stderr: {}", server_name, line.trim());
+            line.clear();
+        }
+    });
+
+    let client = McpClient::connect(config.name.clone(), stdout, stdin);
+    Ok((child, client))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -324,24 +719,166 @@ mod tests {
 
     #[test]
     fn test_mcp_message_serialization() {
-        let message = McpMessage::Request {
-            id: Some("test-id".to_string()),
-            method: "test-method".to_string(),
-            params: serde_json::json!({"key": "value"}),
-        };
+        let message = McpMessage::request("test-id", "test-method", serde_json::json!({"key": "value"}));
 
         let json = serde_json::to_string(&message).unwrap();
+        assert_eq!(json, r#"{"jsonrpc":"2.0","id":"test-id","method":"test-method","params":{"key":"value"}}"#);
         let deserialized: McpMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, message);
 
-        match (&message, &deserialized) {
-            (
-                McpMessage::Request { id: id1, method: method1, .. },
-                McpMessage::Request { id: id2, method: method2, .. }
-            ) => {
-                assert_eq!(id1, id2);
-                assert_eq!(method1, method2);
-            }
-            _ => panic!("Message types don't match"),
-        }
+        let response: McpMessage = serde_json::from_str(r#"{"jsonrpc":"2.0","id":7,"error":{"code":-32601,"message":"nope"}}"#).unwrap();
+        assert!(matches!(response, McpMessage::Response { error: Some(McpError { code: -32601, .. }), .. }));
+        let notification: McpMessage = serde_json::from_str(r#"{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}"#).unwrap();
+        assert_eq!(notification, McpMessage::notification("notifications/tools/list_changed", serde_json::Value::Null));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_start_server_performs_handshake_over_stdio() {
+        // 只应答 initialize 的最小服务器，stdin 关闭后退出
+        let script = r#"while IFS= read -r line; do
+  case "$line" in
+    *'"method":"initialize"'*) echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"logging":{}},"serverInfo":{"name":"sh-server","version":"0.1"}}}' ;;
+  esac
+done"#;
+        let config = McpServerConfig {
+            name: "sh".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            env: HashMap::new(),
+            working_dir: None::<PathBuf>,
+            auto_start: false,
+            ..Default::default()
+        };
+        let manager = McpManager::new();
+        manager.start_server(config.clone()).await.unwrap();
+        assert_eq!(manager.get_server_status("sh"), Some(McpServerStatus::Running));
+        let server = manager.server_info("sh").unwrap();
+        assert_eq!((server.server_info.name.as_str(), server.capabilities.names()), ("sh-server", vec!["logging"]));
+        assert!(manager.start_server(config).await.is_err());
+
+        let started = Instant::now();
+        manager.stop_server("sh").await.unwrap();
+        assert!(started.elapsed() < SHUTDOWN_GRACE, "server should exit once stdin closes");
+        assert!(manager.list_running_servers().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_register_tools_wraps_server_tools() {
+        let script = r#"while IFS= read -r line; do
+  case "$line" in
+    *'"method":"initialize"'*) echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"sh-tools","version":"0.1"}}}' ;;
+    *'"method":"tools/list"'*) echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"echo","description":"Echo text","inputSchema":{"type":"object","properties":{"text":{"type":"string"}},"required":["text"]}}]}}' ;;
+    *'"method":"tools/call"'*'"text":"hi"'*) echo '{"jsonrpc":"2.0","id":3,"result":{"content":[{"type":"text","text":"hi back"}]}}' ;;
+  esac
+done"#;
+        let config = McpServerConfig {
+            name: "shell".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            ..Default::default()
+        };
+        let manager = Arc::new(McpManager::new());
+        manager.start_server(config).await.unwrap();
+
+        let registry = crate::tools::ToolRegistry::new();
+        assert_eq!(manager.register_tools(&registry).await.unwrap(), ["mcp__shell__echo"]);
+        assert!(manager.register_tools(&registry).await.unwrap().is_empty());
+
+        let context = crate::tools::ToolContext::new("test".to_string());
+        let result = registry.execute_tool("mcp__shell__echo", serde_json::json!({ "text": "hi" }), &context).await.unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.data, "hi back");
+
+        manager.stop_server("shell").await.unwrap();
+        let result = registry.execute_tool("mcp__shell__echo", serde_json::json!({ "text": "hi" }), &context).await;
+        assert!(result.map_or(true, |result| !result.success));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_prompts_are_listed_and_expanded() {
+        let script = r#"while IFS= read -r line; do
+  case "$line" in
+    *'"method":"initialize"'*) echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"prompts":{}},"serverInfo":{"name":"sh-prompts","version":"0.1"}}}' ;;
+    *'"method":"prompts/list"'*) echo '{"jsonrpc":"2.0","id":2,"result":{"prompts":[{"name":"greet","arguments":[{"name":"who","required":true}]}]}}' ;;
+    *'"method":"prompts/get"'*'"who":"Ada"'*) echo '{"jsonrpc":"2.0","id":3,"result":{"messages":[{"role":"user","content":{"type":"text","text":"Say hello to Ada"}}]}}' ;;
+  esac
+done"#;
+        let config = McpServerConfig {
+            name: "words".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            ..Default::default()
+        };
+        let manager = McpManager::new();
+        manager.start_server(config).await.unwrap();
+
+        let prompt = manager.find_prompt("mcp__words__greet").unwrap();
+        assert_eq!((prompt.server.as_str(), prompt.arguments.len()), ("words", 1));
+        assert!(manager.get_prompt(&prompt, &HashMap::new()).await.is_err(), "required argument is checked before the request");
+        let arguments = prompt.parse_arguments("Ada").unwrap();
+        assert_eq!(manager.get_prompt(&prompt, &arguments).await.unwrap(), "Say hello to Ada");
+
+        manager.stop_server("words").await.unwrap();
+        assert!(manager.prompts().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_health_check_restarts_crashed_servers() {
+        let dir = tempfile::tempdir().unwrap();
+        // 第一次启动完成握手后退出；`$1` 为 `flaky` 时之后的启动正常应答，否则立即退出
+        let script = r#"marker="$2"
+if [ -e "$marker" ] && [ "$1" != flaky ]; then exit 1; fi
+while IFS= read -r line; do
+  case "$line" in
+    *'"method":"initialize"'*) echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{},"serverInfo":{"name":"sh-flaky","version":"0.1"}}}' ;;
+    *'"method":"notifications/initialized"'*) [ -e "$marker" ] || { touch "$marker"; exit 1; } ;;
+    *'"method":"ping"'*) echo '{"jsonrpc":"2.0","id":2,"result":{}}' ;;
+  esac
+done"#;
+        let config = |name: &str| McpServerConfig {
+            name: name.to_string(),
+            command: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                script.to_string(),
+                "sh".to_string(),
+                name.to_string(),
+                dir.path().join(name).display().to_string(),
+            ],
+            ..Default::default()
+        };
+        let policy = HealthPolicy {
+            ping_timeout: Duration::from_secs(2),
+            max_restarts: 2,
+            restart_backoff: Duration::ZERO,
+            ..Default::default()
+        };
+        let log = HealthLog::new(dir.path().join("health.jsonl"));
+        let manager = McpManager::new().with_health_policy(policy).with_health_log(log.clone());
+        manager.start_server(config("flaky")).await.unwrap();
+        manager.start_server(config("broken")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let events = manager.check_health().await;
+        let kinds = |server: &str| -> Vec<HealthEventKind> {
+            events.iter().filter(|event| event.server == server).map(|event| event.kind).collect()
+        };
+        assert_eq!(kinds("flaky").last(), Some(&HealthEventKind::Restarted));
+        assert_eq!(manager.get_server_status("flaky"), Some(McpServerStatus::Running));
+        assert_eq!(manager.health("flaky").unwrap().restarts, 1);
+
+        let broken = kinds("broken");
+        assert_eq!(broken.iter().filter(|kind| **kind == HealthEventKind::RestartFailed).count(), 2);
+        assert_eq!(broken.last(), Some(&HealthEventKind::GaveUp));
+        assert!(matches!(manager.get_server_status("broken"), Some(McpServerStatus::Error(_))));
+        assert_eq!(log.latest("broken").unwrap().unwrap().kind, HealthEventKind::GaveUp);
+
+        assert!(manager.check_health().await.is_empty(), "healthy and given-up servers produce no events");
+        assert!(manager.health("flaky").unwrap().last_ok.is_some());
+        manager.stop_server("flaky").await.unwrap();
     }
 }
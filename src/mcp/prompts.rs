@@ -0,0 +1,145 @@
+//! MCP 提示
+//!
+//! 连接服务器时取回 `prompts/list`，每个提示在交互模式中作为 `/mcp__<服务器>__<提示>` 命令使用。
+//! 命令后的 `key=value` 填入提示参数，缺少的参数逐个询问；`prompts/get` 返回的消息合并为一条用户输入
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::error::{ClaudeError, Result};
+
+/// 提示声明的参数
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PromptArgument {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// 一个服务器提供的提示
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct McpPrompt {
+    /// 所属服务器
+    #[serde(skip)]
+    pub server: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+}
+
+impl McpPrompt {
+    /// 由 `prompts/list` 返回的提示描述创建，格式不对时返回 `None`
+    pub fn from_listing(server: &str, listing: &Value) -> Option<Self> {
+        let prompt: McpPrompt = serde_json::from_value(listing.clone()).ok()?;
+        Some(Self { server: server.to_string(), ..prompt })
+    }
+
+    /// 交互模式中的命令，不含开头的 `/`
+    pub fn command(&self) -> String {
+        super::tool::tool_name(&self.server, &self.name)
+    }
+
+    /// 解析命令后的参数：只声明了一个参数且文本中没有 `key=value` 时整段作为该参数的值
+    pub fn parse_arguments(&self, text: &str) -> Result<HashMap<String, String>> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(HashMap::new());
+        }
+        match self.arguments.as_slice() {
+            [only] if !text.split_whitespace().any(|word| word.contains('=')) => {
+                Ok(HashMap::from([(only.name.clone(), text.to_string())]))
+            }
+            _ => crate::prompts::parse_inline_assignments(text),
+        }
+    }
+
+    /// 尚未填写的参数，必填的在前
+    pub fn missing<'a>(&'a self, values: &HashMap<String, String>) -> Vec<&'a PromptArgument> {
+        let mut missing: Vec<&PromptArgument> = self.arguments.iter().filter(|argument| !values.contains_key(&argument.name)).collect();
+        missing.sort_by_key(|argument| !argument.required);
+        missing
+    }
+
+    /// 检查必填参数，并拒绝未声明的参数
+    pub fn validate(&self, values: &HashMap<String, String>) -> Result<()> {
+        if let Some(unknown) = values.keys().find(|key| !self.arguments.iter().any(|argument| &argument.name == *key)) {
+            return Err(ClaudeError::validation_error(
+                unknown.clone(),
+                format!("Prompt '{}' has no argument '{}'", self.command(), unknown),
+            ));
+        }
+        if let Some(argument) = self.arguments.iter().find(|argument| argument.required && !values.contains_key(&argument.name)) {
+            return Err(ClaudeError::validation_error(argument.name.clone(), "Required prompt argument missing"));
+        }
+        Ok(())
+    }
+}
+
+/// `prompts/get` 的消息合并为一条输入：用户消息原样保留，其他角色加上角色前缀，非文本内容给出说明
+pub fn messages_text(result: &Value) -> String {
+    let Some(messages) = result.get("messages").and_then(Value::as_array) else {
+        return String::new();
+    };
+    messages
+        .iter()
+        .map(|message| {
+            let content = &message["content"];
+            let text = match content.get("type").and_then(Value::as_str) {
+                Some("text") => content.get("text").and_then(Value::as_str).unwrap_or_default().to_string(),
+                Some("resource") => match content.pointer("/resource/text").and_then(Value::as_str) {
+                    Some(text) => text.to_string(),
+                    None => format!("[resource: {}]", content.pointer("/resource/uri").and_then(Value::as_str).unwrap_or_default()),
+                },
+                Some(other) => format!("[{} content]", other),
+                None => content.to_string(),
+            };
+            match message.get("role").and_then(Value::as_str) {
+                Some("user") | None => text,
+                Some(role) => format!("{}: {}", role, text),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_prompt_arguments_and_messages() {
+        let listing = json!({
+            "name": "review pr",
+            "description": "Review a pull request",
+            "arguments": [
+                { "name": "number", "required": true },
+                { "name": "focus", "description": "Area to focus on" }
+            ]
+        });
+        let prompt = McpPrompt::from_listing("github", &listing).unwrap();
+        assert_eq!(prompt.command(), "mcp__github__review_pr");
+
+        let values = prompt.parse_arguments("focus=error handling").unwrap();
+        assert_eq!(values["focus"], "error handling");
+        assert_eq!(prompt.missing(&values).iter().map(|a| a.name.as_str()).collect::<Vec<_>>(), ["number"]);
+        assert!(prompt.validate(&values).is_err());
+        assert!(prompt.validate(&prompt.parse_arguments("number=12").unwrap()).is_ok());
+        assert!(prompt.validate(&prompt.parse_arguments("number=12 color=red").unwrap()).is_err());
+
+        let single = McpPrompt { arguments: vec![PromptArgument { name: "topic".to_string(), ..Default::default() }], ..prompt };
+        assert_eq!(single.parse_arguments("rust lifetimes").unwrap()["topic"], "rust lifetimes");
+
+        let result = json!({ "messages": [
+            { "role": "user", "content": { "type": "text", "text": "Review PR 12" } },
+            { "role": "assistant", "content": { "type": "text", "text": "Which files?" } },
+            { "role": "user", "content": { "type": "image", "data": "...", "mimeType": "image/png" } },
+        ] });
+        assert_eq!(messages_text(&result), "Review PR 12\n\nassistant: Which files?\n\n[image content]");
+    }
+}
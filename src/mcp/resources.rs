@@ -0,0 +1,70 @@
+//! MCP 资源引用
+//!
+//! 输入中的 `@服务器:资源` 引用运行中服务器的资源，资源可以写 URI 或 `resources/list` 中的名称。
+//! 读取到的文本内容附加到发给模型的消息中，二进制内容只给出说明
+
+use serde_json::Value;
+
+/// 拆分 `服务器:资源` 引用，服务器名和资源都不能为空
+pub fn parse_mention(mention: &str) -> Option<(&str, &str)> {
+    let (server, resource) = mention.split_once(':')?;
+    (!server.is_empty() && !resource.is_empty()).then_some((server, resource))
+}
+
+/// 在 `resources/list` 的结果中按 URI 或名称查找资源，返回它的 URI
+pub fn resolve<'a>(resources: &'a [Value], reference: &str) -> Option<&'a str> {
+    let uri_of = |resource: &'a Value| resource.get("uri").and_then(Value::as_str);
+    resources
+        .iter()
+        .find(|resource| uri_of(resource) == Some(reference))
+        .or_else(|| resources.iter().find(|resource| resource.get("name").and_then(Value::as_str) == Some(reference)))
+        .and_then(uri_of)
+}
+
+/// `resources/read` 结果中的内容转换为文本：文本内容原样拼接，二进制内容给出类型和大小
+pub fn contents_text(result: &Value) -> String {
+    let Some(contents) = result.get("contents").and_then(Value::as_array) else {
+        return String::new();
+    };
+    contents
+        .iter()
+        .map(|content| match content.get("text").and_then(Value::as_str) {
+            Some(text) => text.to_string(),
+            None => format!(
+                "[binary resource {} ({}), {} base64 bytes]",
+                content.get("uri").and_then(Value::as_str).unwrap_or_default(),
+                content.get("mimeType").and_then(Value::as_str).unwrap_or("unknown type"),
+                content.get("blob").and_then(Value::as_str).map_or(0, str::len)
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mentions_resolve_and_render() {
+        assert_eq!(parse_mention("github:repo://issues/1"), Some(("github", "repo://issues/1")));
+        assert_eq!(parse_mention("src/main.rs"), None);
+        assert_eq!(parse_mention(":x"), None);
+
+        let resources = vec![
+            json!({ "uri": "file:///notes.md", "name": "notes" }),
+            json!({ "uri": "notes", "name": "shadowed" }),
+        ];
+        assert_eq!(resolve(&resources, "file:///notes.md"), Some("file:///notes.md"));
+        assert_eq!(resolve(&resources, "notes"), Some("notes"), "an exact URI wins over a name");
+        assert_eq!(resolve(&resources, "shadowed"), Some("notes"));
+        assert_eq!(resolve(&resources, "missing"), None);
+
+        let result = json!({ "contents": [
+            { "uri": "file:///a.md", "text": "hello" },
+            { "uri": "file:///logo.png", "mimeType": "image/png", "blob": "aGVsbG8=" },
+        ] });
+        assert_eq!(contents_text(&result), "hello\n[binary resource file:///logo.png (image/png), 8 base64 bytes]");
+    }
+}
@@ -0,0 +1,391 @@
+//! MCP 服务器配置范围
+//!
+//! 服务器可以配置在三个范围，同名时 local 优先于 project，project 优先于 user：
+//! - local：只对自己、只在当前项目生效，保存在配置目录下的 `mcp-local.json`，按项目路径分组
+//! - project：项目根目录的 `.mcp.json`，可以提交到仓库与团队共享
+//! - user：主配置文件的 `mcp_servers`，在所有项目中生效
+//!
+//! `.mcp.json` 来自仓库，其中的服务器第一次使用前需要用户批准；批准按配置指纹记录，
+//! 命令、参数或地址变化后重新询问
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use super::cache::config_fingerprint;
+use crate::config::McpServerConfig;
+use crate::error::{ClaudeError, Result};
+
+/// 项目范围的配置文件名
+pub const PROJECT_FILE: &str = ".mcp.json";
+
+/// 配置范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum McpScope {
+    /// 只对自己、只在当前项目生效
+    Local,
+    /// 项目的 `.mcp.json`，与团队共享
+    Project,
+    /// 所有项目
+    User,
+}
+
+impl McpScope {
+    /// 按优先级从高到低
+    pub const ALL: [McpScope; 3] = [McpScope::Local, McpScope::Project, McpScope::User];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Project => "project",
+            Self::User => "user",
+        }
+    }
+}
+
+/// `.mcp.json` 的内容，`mcpServers` 以服务器名称为键
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct McpJson {
+    #[serde(rename = "mcpServers", default)]
+    pub servers: BTreeMap<String, Value>,
+}
+
+impl McpJson {
+    /// 文件不存在时为空
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| ClaudeError::config_error(format!("Invalid MCP config {}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// 解析全部服务器；没有写 `auto_start` 的服务器随会话启动，与其他 MCP 客户端的 `.mcp.json` 一致
+    pub fn configs(&self) -> Result<HashMap<String, McpServerConfig>> {
+        self.servers
+            .iter()
+            .map(|(name, entry)| {
+                let mut entry = entry.clone();
+                if let Some(fields) = entry.as_object_mut() {
+                    fields.insert("name".to_string(), Value::String(name.clone()));
+                    fields.entry("auto_start").or_insert(Value::Bool(true));
+                }
+                let config: McpServerConfig = serde_json::from_value(entry)
+                    .map_err(|e| ClaudeError::config_error(format!("Invalid MCP server '{}': {}", name, e)))?;
+                Ok((name.clone(), config))
+            })
+            .collect()
+    }
+
+    /// 按其他 MCP 客户端也能读取的形式写入：名称作为键，连接方式写作 `type`，省略空字段
+    pub fn insert(&mut self, config: &McpServerConfig) -> Result<()> {
+        let mut entry = serde_json::to_value(config)?;
+        if let Some(fields) = entry.as_object_mut() {
+            fields.remove("name");
+            if let Some(transport) = fields.remove("transport") {
+                fields.insert("type".to_string(), transport);
+            }
+            fields.retain(|_, value| match value {
+                Value::Null => false,
+                Value::Array(items) => !items.is_empty(),
+                Value::Object(map) => !map.is_empty(),
+                _ => true,
+            });
+        }
+        self.servers.insert(config.name.clone(), entry);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.servers.remove(name).is_some()
+    }
+}
+
+/// 生效的服务器及其来源范围
+#[derive(Debug, Clone)]
+pub struct ScopedServer {
+    pub scope: McpScope,
+    pub config: McpServerConfig,
+}
+
+/// 合并三个范围，同名时 local 优先于 project，project 优先于 user
+pub fn merge(
+    local: HashMap<String, McpServerConfig>,
+    project: HashMap<String, McpServerConfig>,
+    user: HashMap<String, McpServerConfig>,
+) -> BTreeMap<String, ScopedServer> {
+    let mut merged = BTreeMap::new();
+    for (scope, servers) in [(McpScope::User, user), (McpScope::Project, project), (McpScope::Local, local)] {
+        for (name, config) in servers {
+            merged.insert(name, ScopedServer { scope, config });
+        }
+    }
+    merged
+}
+
+/// 当前项目 local、project 两个范围的配置文件
+#[derive(Debug, Clone)]
+pub struct ProjectScopes {
+    /// 项目根目录
+    project_dir: PathBuf,
+    /// 各项目 local 范围配置所在的文件
+    local_path: PathBuf,
+}
+
+impl ProjectScopes {
+    /// local 范围默认保存在配置目录下的 `mcp-local.json`
+    pub fn default_local_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().ok_or_else(|| ClaudeError::config_error("Cannot find config directory"))?;
+        Ok(config_dir.join("claude-code").join("mcp-local.json"))
+    }
+
+    /// 当前目录作为项目根目录
+    pub fn for_current_dir() -> Result<Self> {
+        Ok(Self::new(std::env::current_dir()?, Self::default_local_path()?))
+    }
+
+    pub fn new(project_dir: PathBuf, local_path: PathBuf) -> Self {
+        let project_dir = project_dir.canonicalize().unwrap_or(project_dir);
+        Self { project_dir, local_path }
+    }
+
+    pub fn project_dir(&self) -> &Path {
+        &self.project_dir
+    }
+
+    /// 项目的 `.mcp.json`
+    pub fn project_file(&self) -> PathBuf {
+        self.project_dir.join(PROJECT_FILE)
+    }
+
+    pub fn local_file(&self) -> &Path {
+        &self.local_path
+    }
+
+    /// 读取一个范围的配置；user 范围在主配置文件中，这里为空
+    pub fn load(&self, scope: McpScope) -> Result<McpJson> {
+        match scope {
+            McpScope::Local => Ok(self.load_local()?.remove(&self.project_key()).unwrap_or_default()),
+            McpScope::Project => McpJson::load(&self.project_file()),
+            McpScope::User => Ok(McpJson::default()),
+        }
+    }
+
+    /// 一个范围中的服务器；文件损坏时打印警告并跳过该范围，不影响其他范围和会话启动
+    pub fn servers(&self, scope: McpScope) -> HashMap<String, McpServerConfig> {
+        match self.load(scope).and_then(|servers| servers.configs()) {
+            Ok(servers) => servers,
+            Err(e) => {
+                tracing::warn!("Ignoring {} MCP servers: {}", scope.as_str(), e);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// 写回一个范围的配置；local 范围中其他项目的配置保持不变
+    pub fn save(&self, scope: McpScope, servers: &McpJson) -> Result<()> {
+        match scope {
+            McpScope::Local => {
+                let mut projects = self.load_local()?;
+                if servers.servers.is_empty() {
+                    projects.remove(&self.project_key());
+                } else {
+                    projects.insert(self.project_key(), servers.clone());
+                }
+                if let Some(parent) = self.local_path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&self.local_path, serde_json::to_string_pretty(&projects)?)?;
+                Ok(())
+            }
+            McpScope::Project => servers.save(&self.project_file()),
+            McpScope::User => Err(ClaudeError::config_error("User-scoped MCP servers live in the main config file")),
+        }
+    }
+
+    fn load_local(&self) -> Result<BTreeMap<String, McpJson>> {
+        match std::fs::read_to_string(&self.local_path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| ClaudeError::config_error(format!("Invalid MCP config {}: {}", self.local_path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn project_key(&self) -> String {
+        self.project_dir.display().to_string()
+    }
+}
+
+/// 对一个项目服务器的批准或拒绝
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectApproval {
+    pub project: PathBuf,
+    pub server: String,
+    /// 做决定时的配置指纹
+    pub fingerprint: String,
+    pub approved: bool,
+    pub decided_at: DateTime<Utc>,
+}
+
+/// 项目服务器的批准记录
+#[derive(Debug, Clone, Default)]
+pub struct ApprovalStore {
+    store_path: PathBuf,
+    approvals: Vec<ProjectApproval>,
+}
+
+impl ApprovalStore {
+    /// 默认存储路径（配置目录下的 mcp_project_approvals.json）
+    pub fn default_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir().ok_or_else(|| ClaudeError::config_error("Cannot find config directory"))?;
+        Ok(config_dir.join("claude-code").join("mcp_project_approvals.json"))
+    }
+
+    pub fn load_default() -> Result<Self> {
+        Self::load(Self::default_path()?)
+    }
+
+    /// 文件不存在时返回空记录
+    pub fn load(store_path: PathBuf) -> Result<Self> {
+        let approvals = match std::fs::read_to_string(&store_path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { store_path, approvals })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.store_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.store_path, serde_json::to_string_pretty(&self.approvals)?)?;
+        Ok(())
+    }
+
+    /// 已做的决定；配置在决定之后变化过时视为尚未决定
+    pub fn decision(&self, project: &Path, config: &McpServerConfig) -> Option<bool> {
+        let fingerprint = config_fingerprint(config);
+        self.approvals
+            .iter()
+            .find(|approval| approval.project == project && approval.server == config.name && approval.fingerprint == fingerprint)
+            .map(|approval| approval.approved)
+    }
+
+    /// 记录决定，替换同一服务器之前的决定
+    pub fn record(&mut self, project: &Path, config: &McpServerConfig, approved: bool) {
+        self.approvals.retain(|approval| !(approval.project == project && approval.server == config.name));
+        self.approvals.push(ProjectApproval {
+            project: project.to_path_buf(),
+            server: config.name.clone(),
+            fingerprint: config_fingerprint(config),
+            approved,
+            decided_at: Utc::now(),
+        });
+    }
+
+    /// 清除项目的全部决定，返回清除的条数
+    pub fn reset(&mut self, project: &Path) -> usize {
+        let before = self.approvals.len();
+        self.approvals.retain(|approval| approval.project != project);
+        before - self.approvals.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn server(name: &str, command: &str) -> McpServerConfig {
+        McpServerConfig { name: name.to_string(), command: command.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_scopes_merge_and_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(
+            project.join(PROJECT_FILE),
+            json!({ "mcpServers": {
+                "github": { "command": "npx", "args": ["-y", "github-mcp"] },
+                "docs": { "type": "http", "url": "https://docs.example.com/mcp", "auto_start": false },
+            } })
+            .to_string(),
+        )
+        .unwrap();
+        let scopes = ProjectScopes::new(project.clone(), dir.path().join("local.json"));
+
+        let shared = scopes.load(McpScope::Project).unwrap().configs().unwrap();
+        assert_eq!(shared["github"].name, "github");
+        assert!(shared["github"].auto_start);
+        assert_eq!(shared["docs"].transport, crate::config::McpTransport::Http);
+        assert!(!shared["docs"].auto_start);
+
+        let mut local = scopes.load(McpScope::Local).unwrap();
+        local.insert(&server("github", "./my-github-mcp")).unwrap();
+        scopes.save(McpScope::Local, &local).unwrap();
+        let other = ProjectScopes::new(dir.path().to_path_buf(), dir.path().join("local.json"));
+        assert!(other.load(McpScope::Local).unwrap().servers.is_empty(), "local servers belong to one project");
+
+        let user = HashMap::from([("github".to_string(), server("github", "gh-mcp")), ("files".to_string(), server("files", "fs-mcp"))]);
+        let merged = merge(scopes.load(McpScope::Local).unwrap().configs().unwrap(), shared, user);
+        let scope_of = |name: &str| (merged[name].scope, merged[name].config.command.as_str());
+        assert_eq!(scope_of("github"), (McpScope::Local, "./my-github-mcp"));
+        assert_eq!(scope_of("docs").0, McpScope::Project);
+        assert_eq!(scope_of("files"), (McpScope::User, "fs-mcp"));
+
+        local.remove("github");
+        scopes.save(McpScope::Local, &local).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("local.json")).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_broken_scope_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(PROJECT_FILE), "{ \"mcpServers\": ").unwrap();
+        std::fs::write(dir.path().join("local.json"), "not json").unwrap();
+        let scopes = ProjectScopes::new(dir.path().to_path_buf(), dir.path().join("local.json"));
+
+        assert!(scopes.load(McpScope::Project).is_err());
+        assert!(scopes.servers(McpScope::Project).is_empty());
+        assert!(scopes.servers(McpScope::Local).is_empty());
+
+        std::fs::write(dir.path().join(PROJECT_FILE), json!({ "mcpServers": { "bad": { "args": 1 } } }).to_string()).unwrap();
+        assert!(scopes.servers(McpScope::Project).is_empty());
+    }
+
+    #[test]
+    fn test_project_approvals_follow_config_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let mut store = ApprovalStore::load(dir.path().join("approvals.json")).unwrap();
+        let github = server("github", "npx");
+        assert_eq!(store.decision(&project, &github), None);
+
+        store.record(&project, &github, true);
+        store.record(&project, &server("shady", "curl"), false);
+        store.save().unwrap();
+
+        let mut store = ApprovalStore::load(dir.path().join("approvals.json")).unwrap();
+        assert_eq!(store.decision(&project, &github), Some(true));
+        assert_eq!(store.decision(&project, &server("shady", "curl")), Some(false));
+        assert_eq!(store.decision(&project, &server("github", "./evil.sh")), None, "a changed command asks again");
+        assert_eq!(store.decision(dir.path(), &github), None);
+        assert_eq!(store.reset(&project), 2);
+    }
+}
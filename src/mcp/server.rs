@@ -0,0 +1,399 @@
+//! MCP 服务器模式
+//!
+//! `claude mcp serve` 把本程序作为 stdio MCP 服务器运行，文件、搜索、bash 和 git 工具经由 `tools/list`
+//! 和 `tools/call` 提供给其他 MCP 客户端（Claude Desktop、IDE 等）。标准输出只用于协议消息，日志写到标准错误。
+//! 调用仍经过权限配置中的拒绝规则；需要确认的调用由客户端向用户确认
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+use super::client::PROTOCOL_VERSION;
+use super::{McpError, McpMessage};
+use crate::error::{ClaudeError, Result};
+use crate::tools::permission::{PermissionDecider, PermissionDecision, PermissionRequest};
+use crate::tools::schema::TypedTool;
+use crate::tools::{SecurityLevel, ToolContext, ToolDefinition, ToolRegistry, ToolResult};
+
+/// 对外提供的内置工具
+pub const SERVED_TOOLS: &[&str] = &["read", "write", "edit", "ls", "glob", "grep", "replace", "bash", GIT_TOOL];
+
+/// 只读 git 工具的名称
+pub const GIT_TOOL: &str = "git";
+
+/// `git` 工具允许的子命令，修改仓库的操作走 bash
+const GIT_READ_ONLY: &[&str] = &["status", "diff", "log", "show", "blame", "branch", "tag", "rev-parse", "ls-files"];
+
+/// `branch` / `tag` 只能列出，允许的选项
+const GIT_LIST_FLAGS: &[&str] = &[
+    "-a", "--all", "-r", "--remotes", "-v", "-vv", "--verbose", "-l", "--list", "--show-current", "-i",
+    "--ignore-case", "--column", "--no-column", "--color", "--no-color", "--omit-empty",
+];
+
+/// `branch` / `tag` 允许的带值选项，值可以写在下一个参数中
+const GIT_LIST_VALUE_OPTIONS: &[&str] =
+    &["--contains", "--no-contains", "--merged", "--no-merged", "--points-at", "--sort", "--format"];
+
+/// `name` 是否为 `option` 本身或它的缩写（git 接受长选项的前缀）；`--output-indicator-new`
+/// 之类以 `option` 开头的其他选项不算
+fn abbreviates(name: &str, option: &str) -> bool {
+    name.len() > 2 && option.starts_with(name)
+}
+
+/// 检查参数是否只读：`--output` 会写文件，`diff --no-index` 和指向工作树外的路径会读取仓库外的文件，
+/// `branch` / `tag` 除列出外的用法都会创建、删除、移动或强制改写引用
+fn check_read_only(subcommand: &str, args: &[String]) -> std::result::Result<(), String> {
+    if !GIT_READ_ONLY.contains(&subcommand) {
+        return Err(format!("git {} is not allowed; use one of: {}", subcommand, GIT_READ_ONLY.join(", ")));
+    }
+    let option = |arg: &str| arg.split('=').next().unwrap_or(arg).to_string();
+    if let Some(arg) = args.iter().find(|arg| abbreviates(&option(arg), "--output")) {
+        return Err(format!("git {} {} writes to a file and is not allowed", subcommand, arg));
+    }
+    if let Some(arg) = args.iter().find(|arg| arg.len() > "--no-".len() && abbreviates(arg, "--no-index")) {
+        return Err(format!("git {} {} reads files outside the repository and is not allowed", subcommand, arg));
+    }
+    // 工作树中的 `git diff` 遇到工作树外的路径时会自动按 `--no-index` 比较
+    let outside = |arg: &&String| {
+        let path = std::path::Path::new(arg.as_str());
+        !arg.starts_with('-') && (path.is_absolute() || path.components().any(|c| c == std::path::Component::ParentDir))
+    };
+    if subcommand == "diff" {
+        if let Some(arg) = args.iter().find(outside) {
+            return Err(format!("git diff {} points outside the working tree and is not allowed", arg));
+        }
+    }
+    if !matches!(subcommand, "branch" | "tag") {
+        return Ok(());
+    }
+
+    let (mut listing, mut names) = (false, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = option(arg);
+        if GIT_LIST_VALUE_OPTIONS.contains(&name.as_str()) {
+            if !arg.contains('=') {
+                args.next();
+            }
+        } else if GIT_LIST_FLAGS.contains(&name.as_str()) || (subcommand == "tag" && arg.starts_with("-n")) {
+            listing |= matches!(arg.as_str(), "-l" | "--list");
+        } else if arg.starts_with('-') {
+            return Err(format!("git {} {} is not allowed; only listing options are accepted", subcommand, arg));
+        } else {
+            names = true;
+        }
+    }
+    if names && !listing {
+        return Err(format!("git {} with a name would create a {}; pass --list to filter by pattern", subcommand, subcommand));
+    }
+    Ok(())
+}
+
+/// 需要确认的调用已由客户端向用户确认，这里直接放行
+pub struct ClientApproval;
+
+#[async_trait]
+impl PermissionDecider for ClientApproval {
+    async fn decide(&self, _request: &PermissionRequest<'_>) -> PermissionDecision {
+        PermissionDecision::Allow
+    }
+}
+
+crate::tool_input! {
+    /// git 工具输入
+    pub struct GitInput {
+        /// Read-only git subcommand: status, diff, log, show, blame, branch, tag, rev-parse or ls-files
+        pub subcommand: String,
+        /// Further arguments, e.g. ["--stat", "HEAD~3"]
+        pub args: Vec<String> = Vec::<String>::new(),
+    }
+}
+
+/// 在工作目录中执行只读 git 子命令
+pub struct GitTool;
+
+#[async_trait]
+impl TypedTool for GitTool {
+    type Input = GitInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder(GIT_TOOL)
+            .description("Inspect the git repository in the working directory with a read-only subcommand (status, diff, log, show, blame, branch, tag, rev-parse, ls-files); branch and tag can only list, and options that write files are rejected")
+            .category("git")
+            .security_level(SecurityLevel::Medium)
+            .requires_confirmation(true)
+            .input::<GitInput>()
+            .build()
+    }
+
+    async fn run(&self, input: GitInput, context: &ToolContext) -> Result<ToolResult> {
+        if let Err(reason) = check_read_only(&input.subcommand, &input.args) {
+            return Ok(ToolResult::error(reason));
+        }
+        let mut args = vec![input.subcommand];
+        args.extend(input.args);
+        let git = crate::git::GitManager::new(context.working_directory.clone().into());
+        match git.run(&args).await {
+            Ok(output) => Ok(ToolResult::success(Value::String(output))),
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
+    }
+}
+
+/// stdio MCP 服务器
+pub struct McpServer {
+    registry: Arc<ToolRegistry>,
+    /// 对外提供的工具名称
+    tools: Vec<String>,
+    context: ToolContext,
+}
+
+impl McpServer {
+    /// 在注册表中补上 `git` 工具，对外提供 [`SERVED_TOOLS`] 中已注册的工具
+    pub async fn new(registry: Arc<ToolRegistry>, context: ToolContext) -> Result<Self> {
+        if registry.get_tool(GIT_TOOL).await.is_none() {
+            registry.register_tool(Arc::new(GitTool)).await?;
+        }
+        let mut tools = Vec::new();
+        for name in SERVED_TOOLS {
+            if registry.get_tool(name).await.is_some() {
+                tools.push(name.to_string());
+            }
+        }
+        Ok(Self { registry, tools, context })
+    }
+
+    /// 对外提供的工具名称
+    pub fn tools(&self) -> &[String] {
+        &self.tools
+    }
+
+    /// 处理一条消息，通知和响应没有回复
+    pub async fn handle(&self, message: McpMessage) -> Option<McpMessage> {
+        let McpMessage::Request { id, method, params, .. } = message else {
+            return None;
+        };
+        let result = match method.as_str() {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.list_tools().await })),
+            "tools/call" => self.call_tool(&params).await,
+            _ => Err(McpError::method_not_found(&method)),
+        };
+        Some(match result {
+            Ok(result) => McpMessage::response(id, result),
+            Err(error) => McpMessage::error_response(id, error),
+        })
+    }
+
+    async fn list_tools(&self) -> Vec<Value> {
+        let mut tools = Vec::new();
+        for name in &self.tools {
+            let Some(tool) = self.registry.get_tool(name).await else { continue };
+            let definition = tool.definition();
+            let read_only = definition.security_level == SecurityLevel::Safe;
+            tools.push(json!({
+                "name": definition.name,
+                "description": definition.description,
+                "inputSchema": definition.input_schema(),
+                "annotations": {
+                    "readOnlyHint": read_only,
+                    "destructiveHint": definition.security_level == SecurityLevel::Dangerous,
+                },
+            }));
+        }
+        tools
+    }
+
+    async fn call_tool(&self, params: &Value) -> std::result::Result<Value, McpError> {
+        let name = params.get("name").and_then(Value::as_str).unwrap_or_default();
+        if !self.tools.iter().any(|tool| tool == name) {
+            return Err(McpError::invalid_params(format!("Unknown tool: {}", name)));
+        }
+        let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+        // 工具自身的失败作为 `isError` 结果返回给客户端，而不是协议错误
+        let (text, is_error) = match self.registry.execute_tool(name, arguments, &self.context).await {
+            Ok(result) if result.success => (result.to_model_content(), false),
+            Ok(result) => (result.error.unwrap_or_else(|| "unknown error".to_string()), true),
+            Err(e) => (e.to_string(), true),
+        };
+        Ok(json!({ "content": [{ "type": "text", "text": text }], "isError": is_error }))
+    }
+
+    /// 每行读取一条 JSON-RPC 消息，请求并发处理；输入结束后等待进行中的调用完成再返回
+    pub async fn serve<R, W>(self: Arc<Self>, reader: R, mut writer: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let (replies, mut outgoing) = mpsc::unbounded_channel::<McpMessage>();
+        let output = tokio::spawn(async move {
+            while let Some(message) = outgoing.recv().await {
+                let mut line = serde_json::to_vec(&message)?;
+                line.push(b'\n');
+                writer.write_all(&line).await?;
+                writer.flush().await?;
+            }
+            Ok::<_, ClaudeError>(())
+        });
+
+        let mut calls = tokio::task::JoinSet::new();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message = match serde_json::from_str::<McpMessage>(&line) {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::debug!("Unparseable MCP message: {} ({})", line.trim(), e);
+                    let _ = replies.send(McpMessage::error_response(Value::Null, McpError::parse_error(e.to_string())));
+                    continue;
+                }
+            };
+            let server = self.clone();
+            let replies = replies.clone();
+            calls.spawn(async move {
+                if let Some(reply) = server.handle(message).await {
+                    let _ = replies.send(reply);
+                }
+            });
+        }
+
+        while calls.join_next().await.is_some() {}
+        drop(replies);
+        output.await.map_err(|e| ClaudeError::General(format!("MCP output task failed: {}", e)))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_tools_over_stdio() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello from the server").unwrap();
+        let registry = Arc::new(ToolRegistry::new());
+        registry.register_tool(Arc::new(crate::tools::builtin::ReadTool::new())).await.unwrap();
+        let context = ToolContext { working_directory: dir.path().display().to_string(), ..ToolContext::new("mcp-serve".to_string()) };
+        let server = Arc::new(McpServer::new(registry, context).await.unwrap());
+        assert_eq!(server.tools(), ["read", "git"]);
+
+        let input = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": PROTOCOL_VERSION}}),
+            json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/list"}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "read", "arguments": {"path": "notes.txt"}}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": {"name": "git", "arguments": {"subcommand": "push"}}}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "tools/call", "params": {"name": "bash", "arguments": {"command": "ls"}}}),
+            json!({"jsonrpc": "2.0", "id": 6, "method": "resources/list"}),
+        ];
+        let input: String = input.iter().map(|message| format!("{}\n", message)).collect();
+        let (writer, mut output) = tokio::io::duplex(64 * 1024);
+        server.serve(input.as_bytes(), writer).await.unwrap();
+
+        let mut text = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut output, &mut text).await.unwrap();
+        let replies: std::collections::HashMap<u64, Value> = text
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .map(|reply| (reply["id"].as_u64().unwrap(), reply))
+            .collect();
+        assert_eq!(replies.len(), 6, "the notification gets no reply");
+        assert_eq!(replies[&1]["result"]["capabilities"], json!({ "tools": {} }));
+
+        let tools = replies[&2]["result"]["tools"].as_array().unwrap();
+        assert_eq!(tools.iter().map(|tool| tool["name"].as_str().unwrap()).collect::<Vec<_>>(), ["read", "git"]);
+        assert_eq!(tools[0]["annotations"]["readOnlyHint"], json!(true));
+        assert_eq!(tools[1]["inputSchema"]["required"], json!(["subcommand"]));
+
+        assert_eq!(replies[&3]["result"]["isError"], json!(false));
+        assert!(replies[&3]["result"]["content"][0]["text"].as_str().unwrap().contains("hello from the server"));
+        assert_eq!(replies[&4]["result"]["isError"], json!(true));
+        assert_eq!(replies[&5]["error"]["code"], json!(-32602));
+        assert_eq!(replies[&6]["error"]["code"], json!(-32601));
+    }
+
+    #[test]
+    fn test_git_tool_rejects_writing_arguments() {
+        let check = |line: &str| {
+            let mut words = line.split_whitespace().map(str::to_string);
+            let subcommand = words.next().unwrap();
+            check_read_only(&subcommand, &words.collect::<Vec<_>>())
+        };
+        for line in [
+            "branch -D feature",
+            "branch -d feature",
+            "branch --delete feature",
+            "branch --del feature",
+            "branch -f main HEAD~1",
+            "branch -m main old",
+            "branch --set-upstream-to=origin/main",
+            "branch feature",
+            "tag -d v1",
+            "tag v2",
+            "tag -f v1 HEAD",
+            "diff --output=/tmp/patch",
+            "log --output=notes.txt",
+            "show --outp=/etc/cron.d/job",
+            "diff --output /tmp/patch",
+            "diff --no-index /home/u/.ssh/id_rsa /dev/null",
+            "diff --no-index -- a b",
+            "diff /home/u/.ssh/id_rsa /dev/null",
+            "diff HEAD -- ../../secret",
+            "push origin main",
+        ] {
+            assert!(check(line).is_err(), "{} should be rejected", line);
+        }
+        for line in [
+            "status --short",
+            "diff --stat HEAD~3",
+            "diff HEAD~3..HEAD -- src/lib.rs",
+            "diff --output-indicator-new=+ --output-indicator-old=-",
+            "log -p --output-indicator-context=~",
+            "log --oneline -n 5",
+            "branch",
+            "branch -a -vv",
+            "branch --contains HEAD",
+            "branch --list feat*",
+            "branch --show-current",
+            "tag -l v1.*",
+            "tag -n3 --sort=-creatordate",
+        ] {
+            assert!(check(line).is_ok(), "{} should be allowed", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_git_tool_cannot_delete_branches() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git").args(args).current_dir(dir.path()).output().unwrap().status;
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        git(&["init", "-q", "-b", "main"]);
+        git(&["-c", "user.name=t", "-c", "user.email=t@example.com", "commit", "-q", "--allow-empty", "-m", "init"]);
+        git(&["branch", "feature"]);
+
+        let registry = ToolRegistry::new();
+        registry.register_tool(Arc::new(GitTool)).await.unwrap();
+        let context = ToolContext { working_directory: dir.path().display().to_string(), ..ToolContext::new("git".to_string()) };
+        let definition = registry.get_tool(GIT_TOOL).await.unwrap().definition();
+        assert_ne!(definition.security_level, SecurityLevel::Safe);
+
+        let deleted = registry
+            .execute_tool(GIT_TOOL, json!({"subcommand": "branch", "args": ["-D", "feature"]}), &context)
+            .await
+            .unwrap();
+        assert!(!deleted.success);
+        let listed = registry.execute_tool(GIT_TOOL, json!({"subcommand": "branch"}), &context).await.unwrap();
+        assert!(listed.to_model_content().contains("feature"));
+    }
+}
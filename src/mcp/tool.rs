@@ -0,0 +1,140 @@
+//! MCP 服务器提供的工具
+//!
+//! 连接服务器后把 `tools/list` 返回的每个工具包装为普通工具注册到 `ToolRegistry`，
+//! 名称为 `mcp__<服务器>__<工具>`。调用时按服务器名称取当前连接，服务器重启后仍然可用
+
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::McpManager;
+use crate::error::{ClaudeError, Result};
+use crate::tools::{SecurityLevel, Tool, ToolContext, ToolDefinition, ToolResult};
+
+/// MCP 工具名称前缀
+pub const TOOL_PREFIX: &str = "mcp__";
+
+/// 注册到工具表中的名称；API 只接受字母、数字、`_` 和 `-`，其他字符替换为 `_`
+pub fn tool_name(server: &str, tool: &str) -> String {
+    let sanitize = |name: &str| -> String {
+        name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' }).collect()
+    };
+    format!("{}{}__{}", TOOL_PREFIX, sanitize(server), sanitize(tool))
+}
+
+/// 一个 MCP 服务器工具
+pub struct McpTool {
+    manager: Arc<McpManager>,
+    server: String,
+    /// 服务器上的原始名称
+    tool: String,
+    definition: ToolDefinition,
+}
+
+impl McpTool {
+    /// 由 `tools/list` 返回的工具描述创建，缺少名称时返回 `None`
+    ///
+    /// 标注为只读（`annotations.readOnlyHint`）的工具视为安全，其余工具需要写权限
+    pub fn new(manager: Arc<McpManager>, server: &str, schema: &Value) -> Option<Self> {
+        let tool = schema.get("name")?.as_str()?.to_string();
+        let read_only = schema.pointer("/annotations/readOnlyHint").and_then(Value::as_bool).unwrap_or(false);
+        let description = schema.get("description").and_then(Value::as_str).unwrap_or_default();
+        let definition = ToolDefinition::builder(tool_name(server, &tool))
+            .description(format!("[MCP server: {}] {}", server, description).trim_end().to_string())
+            .category("mcp")
+            .security_level(if read_only { SecurityLevel::Safe } else { SecurityLevel::Medium })
+            .requires_confirmation(!read_only)
+            .input_schema(schema.get("inputSchema").unwrap_or(&Value::Null))
+            .build();
+        Some(Self { manager, server: server.to_string(), tool, definition })
+    }
+}
+
+#[async_trait]
+impl Tool for McpTool {
+    fn definition(&self) -> ToolDefinition {
+        self.definition.clone()
+    }
+
+    async fn execute(&self, parameters: Value, _context: &ToolContext) -> Result<ToolResult> {
+        let client = self
+            .manager
+            .client(&self.server)
+            .ok_or_else(|| ClaudeError::mcp_error(format!("MCP server '{}' is not running", self.server)))?;
+        let arguments = if parameters.is_null() { Value::Object(Default::default()) } else { parameters };
+        let result = client.call_tool(&self.tool, arguments).await?;
+        let text = content_text(&result);
+        if result.get("isError").and_then(Value::as_bool).unwrap_or(false) {
+            return Ok(ToolResult::error(text));
+        }
+        Ok(ToolResult::success(Value::String(text)))
+    }
+}
+
+/// `tools/call` 结果中的内容转换为文本：文本块原样拼接，图片和资源给出说明
+fn content_text(result: &Value) -> String {
+    let Some(content) = result.get("content").and_then(Value::as_array) else {
+        return result.get("structuredContent").map(Value::to_string).unwrap_or_default();
+    };
+    content
+        .iter()
+        .map(|block| match block.get("type").and_then(Value::as_str) {
+            Some("text") => block.get("text").and_then(Value::as_str).unwrap_or_default().to_string(),
+            Some("image") | Some("audio") => format!(
+                "[{} content: {}]",
+                block["type"].as_str().unwrap_or_default(),
+                block.get("mimeType").and_then(Value::as_str).unwrap_or("unknown type")
+            ),
+            Some("resource") => {
+                let resource = &block["resource"];
+                match resource.get("text").and_then(Value::as_str) {
+                    Some(text) => text.to_string(),
+                    None => format!("[resource: {}]", resource.get("uri").and_then(Value::as_str).unwrap_or_default()),
+                }
+            }
+            _ => block.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_definition_and_content() {
+        assert_eq!(tool_name("my server", "read.file"), "mcp__my_server__read_file");
+
+        let manager = Arc::new(McpManager::new());
+        let schema = json!({
+            "name": "search",
+            "description": "Search issues",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Search text" },
+                    "limit": { "type": ["integer", "null"], "default": 10 }
+                },
+                "required": ["query"]
+            },
+            "annotations": { "readOnlyHint": true }
+        });
+        let tool = McpTool::new(manager, "github", &schema).unwrap();
+        let definition = tool.definition();
+        assert_eq!(definition.name, "mcp__github__search");
+        assert_eq!(definition.description, "[MCP server: github] Search issues");
+        assert_eq!(definition.security_level, SecurityLevel::Safe);
+        let input_schema = definition.input_schema();
+        assert_eq!(input_schema["required"], json!(["query"]));
+        assert_eq!(input_schema["properties"]["limit"]["type"], json!(["integer", "null"]));
+
+        let result = json!({ "content": [
+            { "type": "text", "text": "first" },
+            { "type": "image", "data": "...", "mimeType": "image/png" },
+            { "type": "resource", "resource": { "uri": "file:///a.txt", "text": "contents" } },
+        ] });
+        assert_eq!(content_text(&result), "first\n[image content: image/png]\ncontents");
+    }
+}
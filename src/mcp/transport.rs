@@ -0,0 +1,449 @@
+//! MCP 传输层
+//!
+//! 传输层经由一对通道和客户端交换消息：客户端的发送端全部释放后传输层关闭连接，
+//! 传输层释放所有接收消息的发送端表示连接已断开。
+//! stdio 在按行分隔的 JSON 流上收发；Streamable HTTP 把每条消息 POST 到同一地址，
+//! 响应为 JSON 或 SSE 流；旧版 HTTP+SSE 用 GET 事件流接收，POST 到服务器在 `endpoint`
+//! 事件中给出的地址发送
+
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE};
+use reqwest::Url;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use super::{McpError, McpMessage};
+use crate::error::{ClaudeError, Result};
+use crate::streaming::sse::SseDecoder;
+
+/// 客户端待发出的消息
+pub type Outgoing = mpsc::UnboundedReceiver<McpMessage>;
+
+/// 交回客户端的服务器消息
+pub type Incoming = mpsc::UnboundedSender<McpMessage>;
+
+/// Streamable HTTP 的会话 id 头
+const SESSION_HEADER: &str = "mcp-session-id";
+
+/// 旧版 SSE 等待服务器给出消息端点的时间
+const ENDPOINT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 传输失败时替代服务器响应的 JSON-RPC 错误码
+const TRANSPORT_ERROR: i32 = -32000;
+
+/// 在一对按行分隔 JSON 的读写流上收发，例如子进程的 stdout 和 stdin
+pub fn stdio<R, W>(name: &str, reader: R, mut writer: W, mut outgoing: Outgoing, incoming: Incoming)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let server_name = name.to_string();
+    tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            let mut line = match serde_json::to_vec(&message) {
+                Ok(line) => line,
+                Err(e) => {
+                    tracing::error!("Failed to encode MCP message for '{}': {}", server_name, e);
+                    continue;
+                }
+            };
+            line.push(b'\n');
+            if let Err(e) = async {
+                writer.write_all(&line).await?;
+                writer.flush().await
+            }
+            .await
+            {
+                tracing::warn!("MCP server '{}' stopped accepting input: {}", server_name, e);
+                break;
+            }
+        }
+    });
+
+    let server_name = name.to_string();
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<McpMessage>(&line) {
+                Ok(message) => {
+                    if incoming.send(message).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => tracing::debug!("Ignoring non-JSON-RPC output from MCP server '{}': {} ({})", server_name, line.trim(), e),
+            }
+        }
+    });
+}
+
+/// Streamable HTTP：每条消息 POST 到 `url`，记住服务器分配的会话 id，连接关闭时结束会话
+pub fn streamable_http(name: &str, url: &str, headers: &HashMap<String, String>, mut outgoing: Outgoing, incoming: Incoming) -> Result<()> {
+    let url = parse_url(url)?;
+    let poster = Arc::new(Poster::new(name, headers, incoming)?);
+    tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            poster.dispatch(&url, message).await;
+        }
+        poster.end_session(&url).await;
+    });
+    Ok(())
+}
+
+/// 旧版 HTTP+SSE：打开事件流并等到服务器给出消息端点后返回
+pub async fn sse(name: &str, url: &str, headers: &HashMap<String, String>, mut outgoing: Outgoing, incoming: Incoming) -> Result<()> {
+    let url = parse_url(url)?;
+    let poster = Arc::new(Poster::new(name, headers, incoming.clone())?);
+    let response = poster
+        .http
+        .get(url.clone())
+        .headers(poster.headers.clone())
+        .header(ACCEPT, "text/event-stream")
+        .send()
+        .await
+        .map_err(|e| ClaudeError::mcp_error(format!("Failed to connect to MCP server '{}' at {}: {}", name, url, e)))?;
+    let response = check_status(name, response).await?;
+
+    // 事件流和发送任务任一结束，另一个也随之结束
+    let closed = CancellationToken::new();
+    let (endpoint_tx, endpoint_rx) = oneshot::channel::<String>();
+    let (server_name, stream_closed) = (name.to_string(), closed.clone());
+    tokio::spawn(async move {
+        let _close = stream_closed.clone().drop_guard();
+        let mut endpoint_tx = Some(endpoint_tx);
+        let mut decoder = SseDecoder::new();
+        let mut stream = response.bytes_stream();
+        loop {
+            let chunk = tokio::select! {
+                _ = stream_closed.cancelled() => break,
+                chunk = stream.next() => chunk,
+            };
+            let chunk = match chunk {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(e)) => {
+                    tracing::warn!("Event stream of MCP server '{}' failed: {}", server_name, e);
+                    break;
+                }
+                None => break,
+            };
+            let mut messages = Vec::new();
+            let decoded = decoder.decode(&chunk, |event| match event.event {
+                Some("endpoint") => {
+                    if let Some(sender) = endpoint_tx.take() {
+                        let _ = sender.send(event.data.trim().to_string());
+                    }
+                }
+                Some("message") | None => messages.extend(parse_message(&server_name, event.data)),
+                Some(_) => {}
+            });
+            if let Err(e) = decoded {
+                tracing::warn!("Invalid event stream from MCP server '{}': {}", server_name, e);
+                break;
+            }
+            if messages.into_iter().any(|message| incoming.send(message).is_err()) {
+                break;
+            }
+        }
+    });
+
+    let endpoint = match tokio::time::timeout(ENDPOINT_TIMEOUT, endpoint_rx).await {
+        Ok(Ok(endpoint)) => endpoint,
+        Ok(Err(_)) => {
+            return Err(ClaudeError::mcp_error(format!("MCP server '{}' closed the event stream before sending its endpoint", name)));
+        }
+        Err(_) => {
+            closed.cancel();
+            return Err(ClaudeError::mcp_error(format!(
+                "MCP server '{}' sent no endpoint within {}s",
+                name,
+                ENDPOINT_TIMEOUT.as_secs()
+            )));
+        }
+    };
+    // 端点可以是相对地址，但必须和事件流同源，避免把认证头发给其他主机
+    let endpoint = url
+        .join(&endpoint)
+        .ok()
+        .filter(|endpoint| endpoint.origin() == url.origin())
+        .ok_or_else(|| {
+            closed.cancel();
+            ClaudeError::mcp_error(format!("MCP server '{}' sent an invalid endpoint '{}'", name, endpoint))
+        })?;
+
+    tokio::spawn(async move {
+        let _close = closed.clone().drop_guard();
+        loop {
+            let message = tokio::select! {
+                _ = closed.cancelled() => break,
+                message = outgoing.recv() => message,
+            };
+            match message {
+                Some(message) => poster.dispatch(&endpoint, message).await,
+                None => break,
+            }
+        }
+    });
+    Ok(())
+}
+
+/// 通过 HTTP POST 发送消息并把响应中的消息交回客户端
+struct Poster {
+    name: String,
+    http: reqwest::Client,
+    headers: HeaderMap,
+    session: Mutex<Option<String>>,
+    incoming: Incoming,
+}
+
+impl Poster {
+    fn new(name: &str, headers: &HashMap<String, String>, incoming: Incoming) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            http: crate::network::shared_client(),
+            headers: header_map(headers)?,
+            session: Mutex::new(None),
+            incoming,
+        })
+    }
+
+    /// 请求在后台发送，响应可能是持续较久的事件流；通知和应答按顺序发送
+    async fn dispatch(self: &Arc<Self>, url: &Url, message: McpMessage) {
+        if matches!(message, McpMessage::Request { .. }) {
+            let (poster, url) = (self.clone(), url.clone());
+            tokio::spawn(async move { poster.send(&url, message).await });
+        } else {
+            self.send(url, message).await;
+        }
+    }
+
+    /// 发送一条消息，失败时用错误响应代替服务器对请求的应答
+    async fn send(&self, url: &Url, message: McpMessage) {
+        let Err(e) = self.post(url, &message).await else {
+            return;
+        };
+        match message {
+            McpMessage::Request { id, .. } => {
+                let error = McpError { code: TRANSPORT_ERROR, message: e.to_string(), data: None };
+                let _ = self.incoming.send(McpMessage::error_response(id, error));
+            }
+            _ => tracing::warn!("Failed to send message to MCP server '{}': {}", self.name, e),
+        }
+    }
+
+    async fn post(&self, url: &Url, message: &McpMessage) -> Result<()> {
+        let mut request = self
+            .http
+            .post(url.clone())
+            .headers(self.headers.clone())
+            .header(ACCEPT, "application/json, text/event-stream")
+            .json(message);
+        if let Some(session) = self.session.lock().unwrap().clone() {
+            request = request.header(SESSION_HEADER, session);
+        }
+        let response = check_status(&self.name, request.send().await?).await?;
+        if let Some(session) = response.headers().get(SESSION_HEADER).and_then(|value| value.to_str().ok()) {
+            *self.session.lock().unwrap() = Some(session.to_string());
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if content_type.starts_with("text/event-stream") {
+            let mut decoder = SseDecoder::new();
+            let mut stream = response.bytes_stream();
+            while let Some(chunk) = stream.next().await {
+                let mut messages = Vec::new();
+                decoder.decode(&chunk?, |event| {
+                    if matches!(event.event, Some("message") | None) {
+                        messages.extend(parse_message(&self.name, event.data));
+                    }
+                })?;
+                if messages.into_iter().any(|message| self.incoming.send(message).is_err()) {
+                    break;
+                }
+            }
+        } else if content_type.starts_with("application/json") {
+            // 单条消息或批量数组
+            let messages = match response.json::<Value>().await? {
+                Value::Array(items) => items,
+                item => vec![item],
+            };
+            for message in messages {
+                match serde_json::from_value::<McpMessage>(message) {
+                    Ok(message) => {
+                        let _ = self.incoming.send(message);
+                    }
+                    Err(e) => tracing::debug!("Ignoring invalid message from MCP server '{}': {}", self.name, e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 通知服务器结束 Streamable HTTP 会话
+    async fn end_session(&self, url: &Url) {
+        let Some(session) = self.session.lock().unwrap().take() else {
+            return;
+        };
+        let request = self.http.delete(url.clone()).headers(self.headers.clone()).header(SESSION_HEADER, session);
+        if let Err(e) = request.send().await {
+            tracing::debug!("Failed to end session with MCP server '{}': {}", self.name, e);
+        }
+    }
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    Url::parse(url).map_err(|e| ClaudeError::validation_error("url", format!("Invalid MCP server URL '{}': {}", url, e)))
+}
+
+fn header_map(headers: &HashMap<String, String>) -> Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let invalid = || ClaudeError::validation_error("headers", format!("Invalid HTTP header '{}'", name));
+        map.insert(
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?,
+            HeaderValue::from_str(value).map_err(|_| invalid())?,
+        );
+    }
+    Ok(map)
+}
+
+async fn check_status(name: &str, response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(ClaudeError::mcp_error(format!("MCP server '{}' responded with HTTP {}: {}", name, status, body.trim())))
+}
+
+fn parse_message(name: &str, data: &str) -> Option<McpMessage> {
+    if data.trim().is_empty() {
+        return None;
+    }
+    serde_json::from_str(data)
+        .map_err(|e| tracing::debug!("Ignoring invalid event from MCP server '{}': {} ({})", name, data, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::client::McpClient;
+    use super::*;
+    use crate::test_support::{MockAnthropicServer, MockReply};
+    use serde_json::json;
+
+    fn initialize_result() -> Value {
+        json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "remote", "version": "2.0.0" },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_streamable_http_session_headers_and_sse_responses() {
+        let tools = McpMessage::response(json!(2), json!({ "tools": [{ "name": "search" }] }));
+        let server = MockAnthropicServer::start([
+            MockReply::Json {
+                status: 200,
+                body: serde_json::to_value(McpMessage::response(json!(1), initialize_result())).unwrap(),
+                headers: vec![("mcp-session-id".to_string(), "session-1".to_string())],
+            },
+            MockReply::Json { status: 202, body: Value::Null, headers: Vec::new() },
+            MockReply::Stream {
+                chunks: vec![format!("event: message\ndata: {}\n\n", serde_json::to_string(&tools).unwrap()).into_bytes()],
+                delay: Duration::ZERO,
+            },
+            MockReply::error(401, "unauthorized", "bad token"),
+            MockReply::Json { status: 200, body: Value::Null, headers: Vec::new() },
+        ])
+        .await;
+        let url = format!("{}/mcp", server.base_url());
+        let headers = HashMap::from([("Authorization".to_string(), "Bearer secret".to_string())]);
+
+        let client = McpClient::connect_http("remote", &url, &headers).unwrap();
+        assert_eq!(client.initialize().await.unwrap().server_info.name, "remote");
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools[0]["name"], "search");
+        let error = client.request("tools/call", json!({})).await.unwrap_err();
+        assert!(error.to_string().contains("HTTP 401"), "{}", error);
+
+        drop(client);
+        for _ in 0..100 {
+            if server.requests().len() == 5 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let requests = server.requests();
+        let methods: Vec<_> = requests.iter().map(|r| (r.method.as_str(), r.body["method"].as_str().unwrap_or(""))).collect();
+        assert_eq!(
+            methods,
+            [
+                ("POST", "initialize"),
+                ("POST", "notifications/initialized"),
+                ("POST", "tools/list"),
+                ("POST", "tools/call"),
+                ("DELETE", ""),
+            ]
+        );
+        assert!(requests.iter().all(|r| r.path == "/mcp" && r.headers["authorization"] == "Bearer secret"));
+        assert!(!requests[0].headers.contains_key("mcp-session-id"));
+        assert!(requests[1..].iter().all(|r| r.headers["mcp-session-id"] == "session-1"));
+    }
+
+    #[tokio::test]
+    async fn test_legacy_sse_endpoint_and_messages() {
+        use axum::response::sse::{Event, Sse};
+        use axum::routing::{get, post};
+        use std::convert::Infallible;
+
+        // 事件流先给出相对端点，POST 来的请求经事件流应答
+        let (events, events_rx) = mpsc::unbounded_channel::<Event>();
+        let events_rx = Arc::new(Mutex::new(Some(events_rx)));
+        let app = axum::Router::new()
+            .route(
+                "/sse",
+                get(move || {
+                    let receiver = events_rx.lock().unwrap().take().unwrap();
+                    let endpoint = Event::default().event("endpoint").data("/messages?session=1");
+                    let stream = futures::stream::once(async { endpoint })
+                        .chain(tokio_stream::wrappers::UnboundedReceiverStream::new(receiver))
+                        .map(Ok::<_, Infallible>);
+                    async move { Sse::new(stream) }
+                }),
+            )
+            .route(
+                "/messages",
+                post(move |axum::Json(message): axum::Json<Value>| async move {
+                    if let (Some(id), Some(method)) = (message.get("id"), message["method"].as_str()) {
+                        let result = match method {
+                            "initialize" => initialize_result(),
+                            _ => json!({ "tools": [] }),
+                        };
+                        let response = McpMessage::response(id.clone(), result);
+                        let _ = events.send(Event::default().event("message").data(serde_json::to_string(&response).unwrap()));
+                    }
+                    axum::http::StatusCode::ACCEPTED
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/sse", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = McpClient::connect_sse("legacy", &url, &HashMap::new()).await.unwrap();
+        assert_eq!(client.initialize().await.unwrap().server_info.version, "2.0.0");
+        assert!(client.list_tools().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+//! 模型能力注册表
+//!
+//! 记录每个模型的上下文长度、输出上限以及是否支持图像输入、工具调用、扩展思考和提示缓存，
+//! 在请求发出前拦截模型不支持的功能。内置表可以用远程清单覆盖，下载的清单缓存在数据目录中
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+
+/// 需要模型支持的功能
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Vision,
+    ToolUse,
+    Thinking,
+    PromptCaching,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::Vision => "image input",
+            Capability::ToolUse => "tool use",
+            Capability::Thinking => "extended thinking",
+            Capability::PromptCaching => "prompt caching",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 一个模型的能力描述
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelCapabilities {
+    pub name: String,
+    /// 其他可用的名称，如 `claude-3-5-sonnet-latest`
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// 最大上下文长度（token）
+    pub max_context: u32,
+    /// 单次回复的最大输出 token 数
+    pub max_output: u32,
+    #[serde(default)]
+    pub vision: bool,
+    #[serde(default)]
+    pub tool_use: bool,
+    #[serde(default)]
+    pub thinking: bool,
+    #[serde(default)]
+    pub prompt_caching: bool,
+}
+
+impl ModelCapabilities {
+    pub fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Vision => self.vision,
+            Capability::ToolUse => self.tool_use,
+            Capability::Thinking => self.thinking,
+            Capability::PromptCaching => self.prompt_caching,
+        }
+    }
+
+    fn matches(&self, model: &str) -> bool {
+        self.name == model || self.aliases.iter().any(|alias| alias == model)
+    }
+}
+
+/// 远程清单的格式
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelManifest {
+    #[serde(default)]
+    pub version: Option<String>,
+    pub models: Vec<ModelCapabilities>,
+}
+
+/// 模型能力注册表
+#[derive(Debug, Clone)]
+pub struct ModelRegistry {
+    models: Vec<ModelCapabilities>,
+    /// 已合并的远程清单版本
+    manifest_version: Option<String>,
+}
+
+impl Default for ModelRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl ModelRegistry {
+    /// 只包含内置模型的注册表
+    pub fn builtin() -> Self {
+        Self { models: builtin_models(), manifest_version: None }
+    }
+
+    /// 内置模型加上缓存的远程清单；缓存损坏时忽略
+    pub fn load() -> Self {
+        let mut registry = Self::builtin();
+        if let Some(manifest) = Self::read_cached(&Self::cache_path()) {
+            registry.merge(manifest);
+        }
+        registry
+    }
+
+    /// 远程清单的缓存位置
+    pub fn cache_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("claude-code")
+            .join("models.json")
+    }
+
+    fn read_cached(path: &Path) -> Option<ModelManifest> {
+        let content = std::fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(manifest) => Some(manifest),
+            Err(e) => {
+                tracing::warn!("Ignoring invalid model manifest cache {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// 合并清单：同名模型被替换，新模型追加在后
+    pub fn merge(&mut self, manifest: ModelManifest) {
+        for model in manifest.models {
+            match self.models.iter_mut().find(|m| m.name == model.name) {
+                Some(existing) => *existing = model,
+                None => self.models.push(model),
+            }
+        }
+        self.manifest_version = manifest.version.or(self.manifest_version.take());
+    }
+
+    /// 下载远程清单、写入缓存并合并，返回清单中的模型数量
+    pub async fn update_from(&mut self, url: &str) -> Result<usize> {
+        let response = reqwest::get(url).await?.error_for_status()?;
+        let manifest: ModelManifest = response.json().await?;
+        let count = manifest.models.len();
+
+        let path = Self::cache_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(&manifest)?)?;
+        self.merge(manifest);
+        Ok(count)
+    }
+
+    pub fn manifest_version(&self) -> Option<&str> {
+        self.manifest_version.as_deref()
+    }
+
+    pub fn models(&self) -> &[ModelCapabilities] {
+        &self.models
+    }
+
+    /// 按名称或别名查找
+    pub fn get(&self, model: &str) -> Option<&ModelCapabilities> {
+        self.models.iter().find(|m| m.matches(model))
+    }
+
+    /// 检查模型是否支持某项功能。未登记的模型不做拦截，交给 API 判断
+    pub fn require(&self, model: &str, capability: Capability) -> Result<()> {
+        let Some(capabilities) = self.get(model) else {
+            tracing::debug!("Model {} is not in the registry, not checking {}", model, capability);
+            return Ok(());
+        };
+        if capabilities.supports(capability) {
+            return Ok(());
+        }
+        let alternatives: Vec<&str> = self
+            .models
+            .iter()
+            .filter(|m| m.supports(capability))
+            .map(|m| m.name.as_str())
+            .take(3)
+            .collect();
+        let mut message = format!("{} does not support {}", capabilities.name, capability);
+        if !alternatives.is_empty() {
+            message.push_str(&format!("; use a model that does, e.g. {}", alternatives.join(", ")));
+        }
+        Err(ClaudeError::validation_error("model", message))
+    }
+
+    /// 把请求的输出上限限制在模型允许的范围内
+    pub fn clamp_max_tokens(&self, model: &str, max_tokens: u32) -> u32 {
+        self.get(model).map_or(max_tokens, |m| max_tokens.min(m.max_output))
+    }
+
+    /// 估算的输入 token 数超过模型上下文长度时报错
+    pub fn check_context(&self, model: &str, input_tokens: u32) -> Result<()> {
+        match self.get(model) {
+            Some(m) if input_tokens > m.max_context => Err(ClaudeError::validation_error(
+                "model",
+                format!("Input of ~{} tokens exceeds the {} token context window of {}", input_tokens, m.max_context, m.name),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn model(name: &str, aliases: &[&str], max_output: u32, vision: bool, thinking: bool, prompt_caching: bool) -> ModelCapabilities {
+    ModelCapabilities {
+        name: name.to_string(),
+        aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+        max_context: 200_000,
+        max_output,
+        vision,
+        tool_use: true,
+        thinking,
+        prompt_caching,
+    }
+}
+
+/// 内置的模型能力表
+pub fn builtin_models() -> Vec<ModelCapabilities> {
+    vec![
+        model("claude-opus-4-20250514", &["claude-opus-4-0"], 32_000, true, true, true),
+        model("claude-sonnet-4-20250514", &["claude-sonnet-4-0"], 64_000, true, true, true),
+        model("claude-3-7-sonnet-20250219", &["claude-3-7-sonnet-latest"], 64_000, true, true, true),
+        model("claude-3-5-sonnet-20241022", &["claude-3-5-sonnet-latest"], 8_192, true, false, true),
+        // 3.5 Haiku 只接受文本输入
+        model("claude-3-5-haiku-20241022", &["claude-3-5-haiku-latest"], 8_192, false, false, true),
+        model("claude-3-opus-20240229", &["claude-3-opus-latest"], 4_096, true, false, true),
+        model("claude-3-sonnet-20240229", &[], 4_096, true, false, false),
+        model("claude-3-haiku-20240307", &[], 4_096, true, false, true),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gating_and_manifest_merge() {
+        let mut registry = ModelRegistry::builtin();
+        assert!(registry.require("claude-3-5-sonnet-latest", Capability::Vision).is_ok());
+        let err = registry.require("claude-3-5-haiku-20241022", Capability::Vision).unwrap_err();
+        assert!(err.to_string().contains("does not support image input"));
+        assert!(registry.require("some-future-model", Capability::Thinking).is_ok());
+        assert_eq!(registry.clamp_max_tokens("claude-3-haiku-20240307", 8192), 4096);
+        assert!(registry.check_context("claude-3-haiku-20240307", 250_000).is_err());
+
+        let manifest: ModelManifest = serde_json::from_str(
+            r#"{"version": "2025-06", "models": [
+                {"name": "claude-3-5-haiku-20241022", "max_context": 200000, "max_output": 8192, "vision": true, "tool_use": true},
+                {"name": "claude-next", "max_context": 500000, "max_output": 128000, "thinking": true}
+            ]}"#,
+        )
+        .unwrap();
+        registry.merge(manifest);
+        assert_eq!(registry.manifest_version(), Some("2025-06"));
+        assert!(registry.require("claude-3-5-haiku-20241022", Capability::Vision).is_ok());
+        assert!(registry.require("claude-next", Capability::ToolUse).is_err());
+        assert_eq!(registry.models().len(), builtin_models().len() + 1);
+    }
+}
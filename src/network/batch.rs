@@ -0,0 +1,382 @@
+//! 消息批处理
+//!
+//! 评审、分诊、基准测试这类离线任务可以通过 Message Batches API 提交：请求先攒成一个批次，服务端异步处理，
+//! 轮询到结束后取回结果。延迟从秒级变成分钟级，换来批处理的半价计费
+
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+use super::{ClaudeRequest, ClaudeResponse, NetworkManager, Usage};
+use crate::cost::CostTracker;
+use crate::error::{ClaudeError, Result};
+
+/// 批处理相对标准价格的计费比例
+pub const BATCH_PRICE_FACTOR: f64 = 0.5;
+
+/// 提交的一条请求
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchRequest {
+    pub custom_id: String,
+    pub params: ClaudeRequest,
+}
+
+/// 批次中各状态的请求数
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestCounts {
+    #[serde(default)]
+    pub processing: u32,
+    #[serde(default)]
+    pub succeeded: u32,
+    #[serde(default)]
+    pub errored: u32,
+    #[serde(default)]
+    pub canceled: u32,
+    #[serde(default)]
+    pub expired: u32,
+}
+
+impl RequestCounts {
+    pub fn total(&self) -> u32 {
+        self.processing + self.succeeded + self.errored + self.canceled + self.expired
+    }
+}
+
+/// 服务端的批次状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageBatch {
+    pub id: String,
+    /// in_progress、canceling 或 ended
+    pub processing_status: String,
+    #[serde(default)]
+    pub request_counts: RequestCounts,
+    #[serde(default)]
+    pub results_url: Option<String>,
+}
+
+impl MessageBatch {
+    pub fn is_ended(&self) -> bool {
+        self.processing_status == "ended"
+    }
+}
+
+/// 单条请求的处理结果
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    Succeeded { message: serde_json::Value },
+    Errored { error: serde_json::Value },
+    Canceled,
+    Expired,
+}
+
+/// 结果文件中的一行
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub result: BatchOutcome,
+}
+
+impl BatchOutcome {
+    /// 转换为与同步请求相同的响应；失败、取消和过期的请求转换为错误
+    pub fn into_response(self) -> Result<ClaudeResponse> {
+        let message = match self {
+            BatchOutcome::Succeeded { message } => message,
+            BatchOutcome::Errored { error } => {
+                let detail = error.get("error").unwrap_or(&error);
+                let kind = detail.get("type").and_then(|t| t.as_str()).unwrap_or("api_error");
+                let message = detail.get("message").and_then(|m| m.as_str()).unwrap_or("Batch request failed");
+                return Err(ClaudeError::from_api_error(kind, message.to_string()));
+            }
+            BatchOutcome::Canceled => return Err(ClaudeError::General("Batch request was canceled".to_string())),
+            BatchOutcome::Expired => {
+                return Err(ClaudeError::General("Batch request expired before it was processed".to_string()))
+            }
+        };
+        let text = |key: &str| message.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let content = message
+            .get("content")
+            .and_then(|c| c.as_array())
+            .map(|blocks| {
+                blocks
+                    .iter()
+                    .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("")
+            })
+            .unwrap_or_default();
+        Ok(ClaudeResponse {
+            id: text("id").unwrap_or_default(),
+            response_type: text("type").unwrap_or_else(|| "message".to_string()),
+            role: text("role").unwrap_or_else(|| "assistant".to_string()),
+            content,
+            model: text("model").unwrap_or_default(),
+            stop_reason: text("stop_reason"),
+            stop_sequence: text("stop_sequence"),
+            usage: message.get("usage").and_then(|u| serde_json::from_value::<Usage>(u.clone()).ok()),
+        })
+    }
+}
+
+impl NetworkManager {
+    fn batch_headers(&self) -> Result<reqwest::header::HeaderMap> {
+        let api_key = std::env::var("ANTHROPIC_API_KEY")
+            .map_err(|_| ClaudeError::auth_error("ANTHROPIC_API_KEY environment variable not set"))?;
+        self.build_claude_headers(&api_key)
+    }
+
+    async fn batch_call(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let response = super::send_tracked(request.headers(self.batch_headers()?).timeout(self.timeout)).await?;
+        if !response.status().is_success() {
+            return Err(super::error_from_response(response).await);
+        }
+        Ok(response)
+    }
+
+    /// 提交一个批次
+    pub async fn create_message_batch(&self, requests: &[BatchRequest]) -> Result<MessageBatch> {
+        let url = format!("{}/v1/messages/batches", self.base_url);
+        let body = serde_json::json!({ "requests": requests });
+        Ok(self.batch_call(self.client.post(&url).json(&body)).await?.json().await?)
+    }
+
+    /// 查询批次状态
+    pub async fn get_message_batch(&self, id: &str) -> Result<MessageBatch> {
+        let url = format!("{}/v1/messages/batches/{}", self.base_url, id);
+        Ok(self.batch_call(self.client.get(&url)).await?.json().await?)
+    }
+
+    /// 取消批次，已完成的请求仍会出现在结果中
+    pub async fn cancel_message_batch(&self, id: &str) -> Result<MessageBatch> {
+        let url = format!("{}/v1/messages/batches/{}/cancel", self.base_url, id);
+        Ok(self.batch_call(self.client.post(&url)).await?.json().await?)
+    }
+
+    /// 下载已结束批次的结果（JSONL）
+    pub async fn message_batch_results(&self, batch: &MessageBatch) -> Result<Vec<BatchResult>> {
+        let url = batch
+            .results_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/v1/messages/batches/{}/results", self.base_url, batch.id));
+        let text = self.batch_call(self.client.get(&url)).await?.text().await?;
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+}
+
+/// 批次进度回调
+pub type BatchProgress = Box<dyn Fn(&MessageBatch) + Send + Sync>;
+
+/// 批处理的用量和费用汇总
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchSummary {
+    pub batches: usize,
+    pub requests: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// 按批处理价格计算的费用（美元）
+    pub cost: f64,
+    /// 与标准价格相比节省的费用
+    pub saved: f64,
+}
+
+struct Pending {
+    request: ClaudeRequest,
+    reply: oneshot::Sender<Result<ClaudeResponse>>,
+}
+
+/// 把并发发出的请求攒成批次
+///
+/// 调用方像同步请求一样 `send().await`；第一个请求到达后等待 `window`，期间到达的请求一起提交，
+/// 批次结束后各自收到自己的结果。成功请求的用量按批处理价格记入成本账本
+pub struct BatchCollector {
+    client: Arc<NetworkManager>,
+    window: Duration,
+    poll_interval: Duration,
+    pending: Mutex<Vec<Pending>>,
+    ledger: Option<Mutex<CostTracker>>,
+    summary: Mutex<BatchSummary>,
+    progress: Option<BatchProgress>,
+}
+
+impl BatchCollector {
+    pub fn new(client: Arc<NetworkManager>) -> Self {
+        Self {
+            client,
+            window: Duration::from_secs(2),
+            poll_interval: Duration::from_secs(15),
+            pending: Mutex::new(Vec::new()),
+            ledger: None,
+            summary: Mutex::new(BatchSummary::default()),
+            progress: None,
+        }
+    }
+
+    /// 攒批的等待时间
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// 把成功请求的用量记入成本账本
+    pub fn with_ledger(mut self, ledger: CostTracker) -> Self {
+        self.ledger = Some(Mutex::new(ledger));
+        self
+    }
+
+    pub fn with_progress(mut self, progress: impl Fn(&MessageBatch) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    pub fn summary(&self) -> BatchSummary {
+        self.summary.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// 加入下一个批次并等待结果
+    pub async fn send(self: &Arc<Self>, request: ClaudeRequest) -> Result<ClaudeResponse> {
+        let (reply, receiver) = oneshot::channel();
+        let first = {
+            let mut pending = self.pending.lock().map_err(|_| ClaudeError::General("Batch queue poisoned".to_string()))?;
+            pending.push(Pending { request, reply });
+            pending.len() == 1
+        };
+        if first {
+            let collector = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(collector.window).await;
+                collector.flush().await;
+            });
+        }
+        receiver
+            .await
+            .map_err(|_| ClaudeError::General("Batch was dropped before it finished".to_string()))?
+    }
+
+    /// 提交当前攒下的请求，轮询到结束后分发结果
+    async fn flush(&self) {
+        let pending = match self.pending.lock() {
+            Ok(mut pending) => std::mem::take(&mut *pending),
+            Err(_) => return,
+        };
+        if pending.is_empty() {
+            return;
+        }
+
+        let requests: Vec<BatchRequest> = pending
+            .iter()
+            .enumerate()
+            .map(|(index, p)| BatchRequest {
+                custom_id: format!("req-{}", index),
+                // 批处理不支持流式
+                params: ClaudeRequest { stream: None, ..p.request.clone() },
+            })
+            .collect();
+
+        let job = format!("message batch of {} request(s)", requests.len());
+        match self.run(&requests).await {
+            Ok(mut results) => {
+                let succeeded = results.iter().filter(|r| matches!(r.result, BatchOutcome::Succeeded { .. })).count();
+                super::webhooks::notify(super::webhooks::WebhookEvent::JobCompleted {
+                    job,
+                    summary: format!("{} of {} request(s) succeeded", succeeded, requests.len()),
+                });
+                for (index, p) in pending.into_iter().enumerate() {
+                    let response = results
+                        .iter()
+                        .position(|r| r.custom_id == requests[index].custom_id)
+                        .map(|position| results.swap_remove(position).result.into_response())
+                        .unwrap_or_else(|| Err(ClaudeError::General("Batch returned no result for this request".to_string())));
+                    if let Ok(response) = &response {
+                        self.record(&p.request.model, response);
+                    }
+                    let _ = p.reply.send(response);
+                }
+            }
+            Err(e) => {
+                super::webhooks::notify(super::webhooks::WebhookEvent::JobFailed { job, error: e.to_string() });
+                for p in pending {
+                    let _ = p.reply.send(Err(e.clone()));
+                }
+            }
+        }
+    }
+
+    async fn run(&self, requests: &[BatchRequest]) -> Result<Vec<BatchResult>> {
+        let mut batch = self.client.create_message_batch(requests).await?;
+        tracing::info!("Submitted message batch {} with {} request(s)", batch.id, requests.len());
+        if let Ok(mut summary) = self.summary.lock() {
+            summary.batches += 1;
+            summary.requests += requests.len();
+        }
+        loop {
+            if let Some(progress) = &self.progress {
+                progress(&batch);
+            }
+            if batch.is_ended() {
+                break;
+            }
+            tokio::time::sleep(self.poll_interval).await;
+            batch = self.client.get_message_batch(&batch.id).await?;
+        }
+        self.client.message_batch_results(&batch).await
+    }
+
+    fn record(&self, model: &str, response: &ClaudeResponse) {
+        let Some(usage) = &response.usage else {
+            return;
+        };
+        let model = if response.model.is_empty() { model } else { &response.model };
+        let standard = crate::cost::lookup_default_pricing(model)
+            .map_or(0.0, |pricing| pricing.cost(usage.input_tokens, usage.output_tokens));
+        if let Ok(mut summary) = self.summary.lock() {
+            summary.input_tokens += usage.input_tokens as u64;
+            summary.output_tokens += usage.output_tokens as u64;
+            summary.cost += standard * BATCH_PRICE_FACTOR;
+            summary.saved += standard * (1.0 - BATCH_PRICE_FACTOR);
+        }
+        if let Some(Ok(mut ledger)) = self.ledger.as_ref().map(|l| l.lock()) {
+            if let Err(e) = ledger.record_batch_call(model, usage.input_tokens, usage.output_tokens) {
+                tracing::warn!("Failed to record batch usage: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_results_convert_to_responses() {
+        let lines = [
+            r#"{"custom_id":"req-0","result":{"type":"succeeded","message":{"id":"msg_1","type":"message","role":"assistant","model":"claude-3-haiku-20240307","content":[{"type":"text","text":"Looks "},{"type":"text","text":"good"}],"stop_reason":"end_turn","usage":{"input_tokens":12,"output_tokens":3}}}}"#,
+            r#"{"custom_id":"req-1","result":{"type":"errored","error":{"type":"error","error":{"type":"invalid_request_error","message":"max_tokens too large"}}}}"#,
+            r#"{"custom_id":"req-2","result":{"type":"expired"}}"#,
+        ];
+        let results: Vec<BatchResult> = lines.iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let mut responses = results.into_iter().map(|r| (r.custom_id, r.result.into_response()));
+
+        let (id, ok) = responses.next().unwrap();
+        let ok = ok.unwrap();
+        assert_eq!((id.as_str(), ok.content.as_str()), ("req-0", "Looks good"));
+        assert_eq!(ok.usage.unwrap().output_tokens, 3);
+        assert!(responses.next().unwrap().1.unwrap_err().to_string().contains("max_tokens too large"));
+        assert!(responses.next().unwrap().1.is_err());
+
+        let batch: MessageBatch = serde_json::from_str(
+            r#"{"id":"msgbatch_1","processing_status":"in_progress","request_counts":{"processing":2,"succeeded":1}}"#,
+        )
+        .unwrap();
+        assert!(!batch.is_ended());
+        assert_eq!(batch.request_counts.total(), 3);
+    }
+}
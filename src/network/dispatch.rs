@@ -0,0 +1,336 @@
+//! API 请求调度
+//!
+//! 进程内所有 Claude API 调用先在这里排队：限制同时进行的请求数和每分钟请求数，交互式请求排在后台批量
+//! 任务前面。收到 429 或响应头显示配额用尽时，整个队列暂停到服务端给出的时间，恢复后先只放行一个请求，
+//! 成功后再恢复并发，避免多个会话同时重试又一起被限流
+
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use crate::error::ClaudeError;
+
+/// 没有 Retry-After 时的首次暂停时长，连续限流时翻倍
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+/// 暂停时长上限
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+static DISPATCHER: OnceLock<Arc<ApiDispatcher>> = OnceLock::new();
+
+tokio::task_local! {
+    static PRIORITY: Priority;
+}
+
+/// 请求优先级，数值大的先发送
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// 批量、报告等不需要立即结果的任务
+    Background,
+    /// 用户正在等待的回合
+    Interactive,
+}
+
+/// 当前任务的请求优先级，未指定时为交互式
+pub fn current_priority() -> Priority {
+    PRIORITY.try_with(|priority| *priority).unwrap_or(Priority::Interactive)
+}
+
+/// 以后台优先级运行 `future` 中发出的所有 API 请求
+pub async fn background<F: std::future::Future>(future: F) -> F::Output {
+    PRIORITY.scope(Priority::Background, future).await
+}
+
+/// 进程内共享的调度器
+pub fn dispatcher() -> Arc<ApiDispatcher> {
+    DISPATCHER.get_or_init(|| Arc::new(ApiDispatcher::new(10, 0))).clone()
+}
+
+/// 按配置初始化共享调度器，只在第一次调用时生效
+pub fn init_dispatcher(max_concurrent: u32, requests_per_minute: u32) -> bool {
+    DISPATCHER.set(Arc::new(ApiDispatcher::new(max_concurrent, requests_per_minute))).is_ok()
+}
+
+/// 调度器状态快照
+#[derive(Debug, Clone, PartialEq)]
+pub struct DispatchStats {
+    pub in_flight: usize,
+    pub queued_interactive: usize,
+    pub queued_background: usize,
+    /// 限流暂停的剩余时间
+    pub paused_for: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct State {
+    max_concurrent: usize,
+    in_flight: usize,
+    /// 排队中的 (优先级, 序号)
+    queue: Vec<(Priority, u64)>,
+    next_ticket: u64,
+    paused_until: Option<Instant>,
+    /// 限流后恢复期间只允许一个请求
+    recovering: bool,
+    consecutive_limits: u32,
+    /// 每分钟请求数限制的令牌桶，0 表示不限制
+    requests_per_minute: u32,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl State {
+    fn refill(&mut self, now: Instant) {
+        if self.requests_per_minute > 0 {
+            let rate = self.requests_per_minute as f64 / 60.0;
+            let capacity = (self.max_concurrent as f64).min(self.requests_per_minute as f64).max(1.0);
+            self.tokens = (self.tokens + now.duration_since(self.refilled_at).as_secs_f64() * rate).min(capacity);
+        }
+        self.refilled_at = now;
+    }
+
+    /// 暂停发送，恢复后先只放行一个请求
+    fn pause(&mut self, duration: Duration) {
+        let until = Instant::now() + duration;
+        self.paused_until = Some(self.paused_until.map_or(until, |current| current.max(until)));
+        self.recovering = true;
+    }
+
+    /// 排在最前面的请求：优先级最高，同优先级先到先得
+    fn head(&self) -> Option<u64> {
+        self.queue
+            .iter()
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .map(|(_, ticket)| *ticket)
+    }
+
+    /// 轮到 `ticket` 时开始请求；否则返回需要等待的时长（`None` 表示等其他请求结束）
+    fn try_start(&mut self, ticket: u64, now: Instant) -> std::result::Result<(), Option<Duration>> {
+        if self.head() != Some(ticket) {
+            return Err(None);
+        }
+        if let Some(until) = self.paused_until.filter(|until| *until > now) {
+            return Err(Some(until - now));
+        }
+        let limit = if self.recovering { 1 } else { self.max_concurrent };
+        if self.in_flight >= limit {
+            return Err(None);
+        }
+        if self.requests_per_minute > 0 {
+            self.refill(now);
+            if self.tokens < 1.0 {
+                let rate = self.requests_per_minute as f64 / 60.0;
+                return Err(Some(Duration::from_secs_f64((1.0 - self.tokens) / rate)));
+            }
+            self.tokens -= 1.0;
+        }
+        self.queue.retain(|(_, queued)| *queued != ticket);
+        self.in_flight += 1;
+        Ok(())
+    }
+}
+
+/// API 请求调度器
+#[derive(Debug)]
+pub struct ApiDispatcher {
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+impl ApiDispatcher {
+    pub fn new(max_concurrent: u32, requests_per_minute: u32) -> Self {
+        let max_concurrent = max_concurrent.max(1) as usize;
+        Self {
+            state: Mutex::new(State {
+                max_concurrent,
+                in_flight: 0,
+                queue: Vec::new(),
+                next_ticket: 0,
+                paused_until: None,
+                recovering: false,
+                consecutive_limits: 0,
+                requests_per_minute,
+                tokens: (max_concurrent as f64).min(requests_per_minute as f64).max(1.0),
+                refilled_at: Instant::now(),
+            }),
+            notify: Notify::new(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// 排队等待发送许可，许可释放前请求计入并发数
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> DispatchPermit {
+        let ticket = {
+            let mut state = self.state();
+            let ticket = state.next_ticket;
+            state.next_ticket += 1;
+            state.queue.push((priority, ticket));
+            ticket
+        };
+        // 等待中被取消时把自己移出队列
+        let mut queued = QueuedTicket { dispatcher: self, ticket: Some(ticket) };
+        let queued_at = Instant::now();
+
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let wait = self.state().try_start(ticket, Instant::now());
+            match wait {
+                Ok(()) => {
+                    queued.ticket = None;
+                    // 下一个排队的请求可能也可以开始了
+                    self.notify.notify_waiters();
+                    return DispatchPermit { dispatcher: self.clone(), waited: queued_at.elapsed() };
+                }
+                Err(Some(delay)) => {
+                    let _ = tokio::time::timeout(delay, notified).await;
+                }
+                Err(None) => notified.await,
+            }
+        }
+    }
+
+    /// 收到限流响应：暂停整个队列
+    pub fn rate_limited(&self, retry_after: Option<Duration>) {
+        let mut state = self.state();
+        state.consecutive_limits += 1;
+        let backoff = retry_after.unwrap_or_else(|| {
+            BASE_BACKOFF.saturating_mul(1 << (state.consecutive_limits - 1).min(6)).min(MAX_BACKOFF)
+        });
+        state.pause(backoff);
+        tracing::warn!("API rate limited; pausing queued requests for {:.1}s", backoff.as_secs_f64());
+    }
+
+    /// 请求成功：根据响应头的剩余配额决定是否预先暂停
+    pub fn succeeded(&self, headers: &HeaderMap) {
+        let mut state = self.state();
+        state.consecutive_limits = 0;
+        state.recovering = false;
+        if let Some(reset_in) = exhausted_quota_reset(headers, Utc::now()) {
+            state.pause(reset_in);
+        }
+        drop(state);
+        self.notify.notify_waiters();
+    }
+
+    pub fn stats(&self) -> DispatchStats {
+        let state = self.state();
+        let now = Instant::now();
+        DispatchStats {
+            in_flight: state.in_flight,
+            queued_interactive: state.queue.iter().filter(|(p, _)| *p == Priority::Interactive).count(),
+            queued_background: state.queue.iter().filter(|(p, _)| *p == Priority::Background).count(),
+            paused_for: state.paused_until.filter(|until| *until > now).map(|until| until - now),
+        }
+    }
+}
+
+/// 排队中的请求，未拿到许可就被丢弃时移出队列
+struct QueuedTicket<'a> {
+    dispatcher: &'a ApiDispatcher,
+    ticket: Option<u64>,
+}
+
+impl Drop for QueuedTicket<'_> {
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket {
+            self.dispatcher.state().queue.retain(|(_, queued)| *queued != ticket);
+            self.dispatcher.notify.notify_waiters();
+        }
+    }
+}
+
+/// 发送许可；流式请求应持有到流结束
+#[derive(Debug)]
+pub struct DispatchPermit {
+    dispatcher: Arc<ApiDispatcher>,
+    waited: Duration,
+}
+
+impl DispatchPermit {
+    /// 在队列中等待的时间
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+
+    /// 请求成功时记录响应头中的配额（流式请求没有可用的响应头时传空）
+    pub fn record_success(&self, headers: &HeaderMap) {
+        self.dispatcher.succeeded(headers);
+    }
+
+    /// 请求失败时记录限流
+    pub fn record_error(&self, error: &ClaudeError) {
+        if error.status() == Some(429) {
+            self.dispatcher.rate_limited(error.retry_after());
+        }
+    }
+}
+
+impl Drop for DispatchPermit {
+    fn drop(&mut self) {
+        self.dispatcher.state().in_flight -= 1;
+        self.dispatcher.notify.notify_waiters();
+    }
+}
+
+/// 响应头显示请求或 token 配额已用尽时，返回距离配额重置的时间
+fn exhausted_quota_reset(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    ["requests", "tokens", "input-tokens", "output-tokens"]
+        .iter()
+        .filter_map(|kind| {
+            let header = |name: &str| headers.get(format!("anthropic-ratelimit-{}-{}", kind, name))?.to_str().ok();
+            if header("remaining")?.trim().parse::<u64>().ok()? > 0 {
+                return None;
+            }
+            let reset = DateTime::parse_from_rfc3339(header("reset")?.trim()).ok()?;
+            (reset.with_timezone(&Utc) - now).to_std().ok()
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_interactive_requests_jump_the_queue() {
+        let dispatcher = Arc::new(ApiDispatcher::new(1, 0));
+        let first = dispatcher.acquire(Priority::Background).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for (name, priority) in [("batch", Priority::Background), ("chat", Priority::Interactive)] {
+            let (dispatcher, order) = (dispatcher.clone(), order.clone());
+            tasks.push(tokio::spawn(async move {
+                let _permit = dispatcher.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(dispatcher.stats().queued_background, 1);
+        assert_eq!(dispatcher.stats().queued_interactive, 1);
+
+        drop(first);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec!["chat", "batch"]);
+
+        // 限流后整个队列暂停
+        dispatcher.rate_limited(Some(Duration::from_millis(50)));
+        assert!(dispatcher.stats().paused_for.is_some());
+        let permit = dispatcher.acquire(Priority::Interactive).await;
+        assert!(permit.waited() >= Duration::from_millis(40));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-ratelimit-requests-remaining", "0".parse().unwrap());
+        headers.insert("anthropic-ratelimit-requests-reset", "2030-01-01T00:00:30Z".parse().unwrap());
+        let now = DateTime::parse_from_rfc3339("2030-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(exhausted_quota_reset(&headers, now), Some(Duration::from_secs(30)));
+    }
+}
@@ -0,0 +1,306 @@
+//! 网页抓取
+//!
+//! web_fetch 工具的实现：按 `security.allowed_domains` 检查目标和每次重定向的域名，下载页面，
+//! 把 HTML 转成 Markdown 并按配置的字符数截断。同一会话内重复抓取同一 URL 时直接返回缓存。
+//! 回环、私有和链路本地地址在 DNS 解析之后拦截，除非主机在白名单中显式列出
+
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::html::html_to_markdown;
+use crate::config::ClaudeConfig;
+use crate::error::{ClaudeError, Result};
+use crate::locks::{rank, BlockingMutex};
+
+/// 下载的响应体上限，超出部分丢弃
+const MAX_BODY_BYTES: usize = 5 * 1024 * 1024;
+
+/// 最多跟随的重定向次数
+const MAX_REDIRECTS: usize = 10;
+
+/// 抓取到的页面
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchedPage {
+    /// 重定向之后的最终地址
+    pub url: String,
+    pub status: u16,
+    pub content_type: String,
+    pub title: Option<String>,
+    /// Markdown 或纯文本内容，可能已截断
+    pub content: String,
+    /// 截断前的字符数
+    pub total_chars: usize,
+    pub truncated: bool,
+}
+
+/// 域名是否在白名单中：与某项相同或是其子域名；`*.` 前缀的写法等同于该域名。
+/// 白名单为空时不按域名限制，但解析到内网地址的主机仍被拦截，见 [`is_internal_address`]
+pub fn domain_allowed(allowed: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allowed.is_empty()
+        || allowed.iter().any(|domain| {
+            let domain = domain.trim().trim_start_matches("*.").trim_end_matches('.').to_ascii_lowercase();
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+}
+
+/// 只能在本机或内网访问的地址：回环、私有、链路本地（含云元数据服务）、运营商级 NAT、未指定和广播地址
+pub fn is_internal_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal_address(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback() || ip.is_unspecified() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+            }
+        },
+    }
+}
+
+/// 主机在白名单中显式列出时才可以访问内网地址
+fn internal_allowed(allowed: &[String], host: &str) -> bool {
+    !allowed.is_empty() && domain_allowed(allowed, host)
+}
+
+/// 检查 URL 的主机：不在白名单中，或是未显式列出的内网 IP 时返回原因。
+/// 域名指向的地址由 [`PublicResolver`] 在连接前检查
+fn check_host(allowed: &[String], url: &Url) -> std::result::Result<(), String> {
+    let host = url.host_str().unwrap_or_default();
+    if !domain_allowed(allowed, host) {
+        return Err(format!("Domain '{}' is not in security.allowed_domains", host));
+    }
+    let ip = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>();
+    if ip.is_ok_and(is_internal_address) && !internal_allowed(allowed, host) {
+        return Err(format!(
+            "'{}' is a loopback, private or link-local address; list it in security.allowed_domains to fetch it",
+            host
+        ));
+    }
+    Ok(())
+}
+
+/// 丢弃解析结果中的内网地址。检查发生在连接之前，重定向和 DNS 重绑定都无法绕过
+struct PublicResolver {
+    allowed: Vec<String>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let allow_internal = internal_allowed(&self.allowed, &host);
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| allow_internal || !is_internal_address(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("'{}' resolves only to loopback, private or link-local addresses", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// 带会话缓存的网页抓取器
+pub struct WebFetcher {
+    client: reqwest::Client,
+    allowed_domains: Vec<String>,
+    max_chars: usize,
+    cache: BlockingMutex<HashMap<String, Arc<FetchedPage>>>,
+}
+
+impl WebFetcher {
+    pub fn new(config: &ClaudeConfig) -> Result<Self> {
+        let allowed_domains = config.security.allowed_domains.clone();
+        let redirect_allowlist = allowed_domains.clone();
+        // 重定向到白名单之外的域名时停止
+        let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match check_host(&redirect_allowlist, attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(_) => attempt.stop(),
+            }
+        });
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("claude-code-rust/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(config.web_fetch.timeout_secs))
+            .redirect(redirect)
+            .dns_resolver(Arc::new(PublicResolver { allowed: allowed_domains.clone() }))
+            .build()
+            .map_err(|e| ClaudeError::General(format!("Failed to create HTTP client: {}", e)))?;
+        Ok(Self {
+            client,
+            allowed_domains,
+            max_chars: config.web_fetch.max_chars,
+            cache: BlockingMutex::new("web_fetch.cache", rank::WEB_FETCH_CACHE, HashMap::new()),
+        })
+    }
+
+    /// 抓取页面，返回页面和是否来自缓存
+    pub async fn fetch(&self, url: &str) -> Result<(Arc<FetchedPage>, bool)> {
+        let parsed = Url::parse(url).map_err(|e| ClaudeError::validation_error("url", format!("Invalid URL '{}': {}", url, e)))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(ClaudeError::validation_error("url", format!("Unsupported URL scheme '{}'", parsed.scheme())));
+        }
+        check_host(&self.allowed_domains, &parsed).map_err(ClaudeError::sandbox_error)?;
+        if let Some(page) = self.cache.lock().get(parsed.as_str()) {
+            return Ok((page.clone(), true));
+        }
+
+        let mut response = self.client.get(parsed.clone()).send().await?;
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response.headers().get(reqwest::header::LOCATION).and_then(|l| l.to_str().ok()).unwrap_or_default();
+            return Err(ClaudeError::sandbox_error(format!(
+                "{} redirected to {}, which is outside security.allowed_domains",
+                url, location
+            )));
+        }
+        let final_url = response.url().clone();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("text/plain")
+            .to_string();
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        let is_html = mime == "text/html" || mime == "application/xhtml+xml";
+        if !is_html && !mime.starts_with("text/") && !mime.ends_with("json") && !mime.ends_with("xml") {
+            return Err(ClaudeError::General(format!("Cannot read {} content from {}", mime, url)));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_BODY_BYTES {
+                body.truncate(MAX_BODY_BYTES);
+                break;
+            }
+        }
+        let text = String::from_utf8_lossy(&body);
+        let (title, content) = if is_html {
+            let document = html_to_markdown(&text, Some(&final_url));
+            (document.title, document.markdown)
+        } else {
+            (None, text.into_owned())
+        };
+
+        let total_chars = content.chars().count();
+        let truncated = total_chars > self.max_chars;
+        let content = match content.char_indices().nth(self.max_chars) {
+            Some((index, _)) => format!(
+                "{}\n\n[Content truncated: showing {} of {} characters]",
+                &content[..index],
+                self.max_chars,
+                total_chars
+            ),
+            None => content,
+        };
+        let page = Arc::new(FetchedPage {
+            url: final_url.to_string(),
+            status: status.as_u16(),
+            content_type,
+            title,
+            content,
+            total_chars,
+            truncated,
+        });
+        // 只缓存成功的响应，错误页面下次重新请求
+        if status.is_success() {
+            self.cache.lock().insert(parsed.to_string(), page.clone());
+        }
+        Ok((page, false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_fetch_converts_truncates_and_caches() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/docs", listener.local_addr().unwrap());
+        // 只应答一次，第二次抓取必须来自缓存
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = [0; 4096];
+            let _ = socket.read(&mut buffer).await.unwrap();
+            let body = "<html><head><title>Docs</title></head><body><h1>Hello</h1><p>A long paragraph of text</p></body></html>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: text/html; charset=utf-8\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let mut config = ClaudeConfig::default();
+        config.security.allowed_domains = vec!["127.0.0.1".to_string()];
+        config.web_fetch.max_chars = 12;
+        let fetcher = WebFetcher::new(&config).unwrap();
+
+        let (page, cached) = fetcher.fetch(&url).await.unwrap();
+        assert!(!cached);
+        assert_eq!(page.title.as_deref(), Some("Docs"));
+        assert!(page.truncated);
+        assert_eq!(page.content, "# Hello\n\nA l\n\n[Content truncated: showing 12 of 33 characters]");
+        server.await.unwrap();
+
+        let (again, cached) = fetcher.fetch(&url).await.unwrap();
+        assert!(cached);
+        assert_eq!(again.content, page.content);
+
+        let denied = fetcher.fetch("https://example.com/").await.unwrap_err();
+        assert!(denied.to_string().contains("not in security.allowed_domains"));
+        assert!(fetcher.fetch("http://127.0.0.2/").await.unwrap_err().to_string().contains("not in security.allowed_domains"));
+        assert!(domain_allowed(&["*.rust-lang.org".to_string()], "doc.rust-lang.org"));
+        assert!(!domain_allowed(&["rust-lang.org".to_string()], "evilrust-lang.org"));
+    }
+
+    #[tokio::test]
+    async fn test_open_allowlist_blocks_internal_addresses() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let fetcher = WebFetcher::new(&ClaudeConfig::default()).unwrap();
+        assert!(ClaudeConfig::default().security.allowed_domains.is_empty());
+
+        for url in [
+            format!("http://127.0.0.1:{}/", port),
+            format!("http://[::ffff:127.0.0.1]:{}/", port),
+            "http://169.254.169.254/latest/meta-data/".to_string(),
+            "http://10.0.0.1/".to_string(),
+        ] {
+            let error = fetcher.fetch(&url).await.unwrap_err().to_string();
+            assert!(error.contains("loopback, private or link-local"), "{}: {}", url, error);
+        }
+        // 域名在解析之后检查，连接不会到达本机的监听端口
+        let error = fetcher.fetch(&format!("http://localhost:{}/", port)).await.unwrap_err();
+        assert!(format!("{:?}", error).contains("resolves only to loopback"), "{:?}", error);
+        assert!(tokio::time::timeout(Duration::from_millis(100), listener.accept()).await.is_err());
+
+        assert!(is_internal_address("fd12::1".parse().unwrap()));
+        assert!(is_internal_address("fe80::1".parse().unwrap()));
+        assert!(is_internal_address("100.64.0.1".parse().unwrap()));
+        assert!(!is_internal_address("93.184.216.34".parse().unwrap()));
+        assert!(!is_internal_address("2606:2800:220:1::".parse().unwrap()));
+    }
+}
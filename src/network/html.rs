@@ -0,0 +1,500 @@
+//! HTML 转 Markdown
+//!
+//! 为 web_fetch 工具把网页转换成适合模型阅读的 Markdown：保留标题、段落、列表、链接、
+//! 代码块、引用和表格，丢弃脚本、样式等不可见内容。面向常见网页的宽松解析，
+//! 未闭合的标签在遇到上级元素结束时一并结束
+
+use reqwest::Url;
+
+/// 内容不输出的元素
+const SKIPPED: &[&str] = &["script", "style", "noscript", "template", "svg", "iframe", "canvas", "object"];
+
+/// 前后换段的块级元素
+const BLOCKS: &[&str] = &[
+    "p", "div", "section", "article", "main", "header", "footer", "nav", "aside", "figure", "figcaption",
+    "form", "fieldset", "details", "summary", "dl", "table", "address",
+];
+
+/// 转换结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HtmlDocument {
+    /// `<title>` 的内容
+    pub title: Option<String>,
+    pub markdown: String,
+}
+
+/// 收集内容的元素：链接、引用和表格需要拿到完整内容后再输出
+#[derive(Debug, Default)]
+struct Frame {
+    tag: String,
+    buf: String,
+    href: Option<String>,
+    /// 表格行中已经结束的单元格
+    cells: Vec<String>,
+    /// 表格行是否包含表头单元格
+    header: bool,
+}
+
+struct Converter<'a> {
+    base: Option<&'a Url>,
+    frames: Vec<Frame>,
+    /// 位于不输出内容的元素中时，记下该元素名和嵌套层数
+    skipping: Option<(String, usize)>,
+    pre_depth: usize,
+    /// 列表栈：是否有序、下一个序号
+    lists: Vec<(bool, usize)>,
+    title: Option<String>,
+    in_title: bool,
+    /// 已经输出过表头分隔行的表格层数
+    table_rows: usize,
+}
+
+/// 把 HTML 转换为 Markdown，相对链接按 `base` 解析
+pub fn html_to_markdown(html: &str, base: Option<&Url>) -> HtmlDocument {
+    let mut converter = Converter {
+        base,
+        frames: vec![Frame::default()],
+        skipping: None,
+        pre_depth: 0,
+        lists: Vec::new(),
+        title: None,
+        in_title: false,
+        table_rows: 0,
+    };
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            converter.text(rest);
+            break;
+        };
+        converter.text(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            converter.text(rest);
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        match tag.strip_prefix('/') {
+            Some(name) => converter.close(&name.trim().to_ascii_lowercase()),
+            None => converter.open(tag),
+        }
+    }
+    while converter.frames.len() > 1 {
+        converter.pop_frame();
+    }
+
+    let markdown = tidy(&converter.frames.pop().unwrap_or_default().buf);
+    HtmlDocument { title: converter.title, markdown }
+}
+
+impl Converter<'_> {
+    fn buf(&mut self) -> &mut String {
+        &mut self.frames.last_mut().expect("root frame").buf
+    }
+
+    fn text(&mut self, raw: &str) {
+        if self.skipping.is_some() || raw.is_empty() {
+            return;
+        }
+        let text = decode_entities(raw);
+        if self.in_title {
+            let title = self.title.get_or_insert_with(String::new);
+            title.push_str(&collapse_whitespace(&text));
+            return;
+        }
+        if self.pre_depth > 0 {
+            self.buf().push_str(&text);
+            return;
+        }
+        let collapsed = collapse_whitespace(&text);
+        let buf = self.buf();
+        let at_line_start = buf.is_empty() || buf.ends_with('\n') || buf.ends_with(' ');
+        buf.push_str(if at_line_start { collapsed.trim_start() } else { &collapsed });
+    }
+
+    /// 确保以空行结束，开始新的段落
+    fn block(&mut self) {
+        let buf = self.buf();
+        let trimmed = buf.trim_end_matches([' ', '\t']).len();
+        buf.truncate(trimmed);
+        if !buf.is_empty() && !buf.ends_with("\n\n") {
+            buf.push_str(if buf.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+    }
+
+    /// 确保以换行结束
+    fn line(&mut self) {
+        let buf = self.buf();
+        let trimmed = buf.trim_end_matches([' ', '\t']).len();
+        buf.truncate(trimmed);
+        if !buf.is_empty() && !buf.ends_with('\n') {
+            buf.push('\n');
+        }
+    }
+
+    fn resolve(&self, link: &str) -> String {
+        match self.base.and_then(|base| base.join(link).ok()) {
+            Some(url) => url.to_string(),
+            None => link.to_string(),
+        }
+    }
+
+    fn open(&mut self, tag: &str) {
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        let attrs = &tag[name_end..];
+
+        if let Some((skipped, depth)) = &mut self.skipping {
+            if *skipped == name {
+                *depth += 1;
+            }
+            return;
+        }
+        if SKIPPED.contains(&name.as_str()) {
+            self.skipping = Some((name, 1));
+            return;
+        }
+
+        match name.as_str() {
+            "title" => self.in_title = true,
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block();
+                let level = name[1..].parse().unwrap_or(1);
+                self.buf().push_str(&format!("{} ", "#".repeat(level)));
+            }
+            "br" => {
+                let buf = self.buf();
+                let trimmed = buf.trim_end_matches(' ').len();
+                buf.truncate(trimmed);
+                buf.push('\n');
+            }
+            "hr" => {
+                self.block();
+                self.buf().push_str("---");
+                self.block();
+            }
+            "strong" | "b" => self.buf().push_str("**"),
+            "em" | "i" => self.buf().push('*'),
+            "code" if self.pre_depth == 0 => self.buf().push('`'),
+            "pre" => {
+                self.block();
+                self.buf().push_str("```\n");
+                self.pre_depth += 1;
+            }
+            "ul" | "ol" => {
+                if self.lists.is_empty() {
+                    self.block();
+                } else {
+                    self.line();
+                }
+                self.lists.push((name == "ol", 1));
+            }
+            "li" => {
+                self.line();
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some((true, next)) => {
+                        *next += 1;
+                        format!("{}. ", *next - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.buf().push_str(&format!("{}{}", indent, marker));
+            }
+            "dt" | "dd" => self.line(),
+            "img" => {
+                if let Some(src) = attribute(attrs, "src") {
+                    let alt = attribute(attrs, "alt").unwrap_or_default();
+                    let image = format!("![{}]({})", alt, self.resolve(&src));
+                    self.buf().push_str(&image);
+                }
+            }
+            "a" => {
+                let href = attribute(attrs, "href");
+                self.frames.push(Frame { tag: name, href, ..Frame::default() });
+            }
+            "blockquote" => {
+                self.block();
+                self.frames.push(Frame { tag: name, ..Frame::default() });
+            }
+            "tr" => {
+                self.close_until(&["td", "th", "tr"], "table");
+                self.frames.push(Frame { tag: name, ..Frame::default() });
+            }
+            "td" | "th" => {
+                self.close_until(&["td", "th"], "tr");
+                self.frames.push(Frame { tag: name, ..Frame::default() });
+            }
+            _ if BLOCKS.contains(&name.as_str()) => {
+                if name == "table" {
+                    self.table_rows = 0;
+                }
+                self.block()
+            }
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        if let Some((skipped, depth)) = &mut self.skipping {
+            if skipped == name {
+                *depth -= 1;
+                if *depth == 0 {
+                    self.skipping = None;
+                }
+            }
+            return;
+        }
+
+        match name {
+            "title" => self.in_title = false,
+            "strong" | "b" => self.buf().push_str("**"),
+            "em" | "i" => self.buf().push('*'),
+            "code" if self.pre_depth == 0 => self.buf().push('`'),
+            "pre" if self.pre_depth > 0 => {
+                self.pre_depth -= 1;
+                self.line();
+                self.buf().push_str("```");
+                self.block();
+            }
+            "ul" | "ol" => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.block();
+                } else {
+                    self.line();
+                }
+            }
+            "a" | "blockquote" | "tr" | "td" | "th" if self.frames.iter().skip(1).any(|frame| frame.tag == name) => {
+                while self.frames.last().is_some_and(|frame| frame.tag != name) {
+                    self.pop_frame();
+                }
+                self.pop_frame();
+            }
+            "a" | "blockquote" | "tr" | "td" | "th" => {}
+            "table" => {
+                self.close_until(&["td", "th", "tr"], "");
+                self.block();
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.block(),
+            _ if BLOCKS.contains(&name) => self.block(),
+            _ => {}
+        }
+    }
+
+    /// 结束栈顶属于 `tags` 的元素，遇到 `stop` 或其他元素时停止
+    fn close_until(&mut self, tags: &[&str], stop: &str) {
+        while self.frames.len() > 1 {
+            let top = &self.frames[self.frames.len() - 1].tag;
+            if top == stop || !tags.contains(&top.as_str()) {
+                break;
+            }
+            self.pop_frame();
+        }
+    }
+
+    /// 结束栈顶元素，把它的内容写入上一层
+    fn pop_frame(&mut self) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        match frame.tag.as_str() {
+            "a" => {
+                let text = frame.buf.trim().to_string();
+                let link = frame
+                    .href
+                    .filter(|href| !href.starts_with('#') && !href.starts_with("javascript:") && !text.is_empty())
+                    .map(|href| format!("[{}]({})", text, self.resolve(&href)));
+                let parent = self.buf();
+                if !parent.is_empty() && !parent.ends_with([' ', '\n']) && frame.buf.starts_with(' ') {
+                    parent.push(' ');
+                }
+                parent.push_str(&link.unwrap_or(text));
+            }
+            "blockquote" => {
+                let quoted: Vec<String> = tidy(&frame.buf)
+                    .lines()
+                    .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+                    .collect();
+                self.buf().push_str(&quoted.join("\n"));
+                self.block();
+            }
+            "td" | "th" => {
+                let cell = frame.buf.split_whitespace().collect::<Vec<_>>().join(" ").replace('|', "\\|");
+                match self.frames.last_mut() {
+                    Some(row) if row.tag == "tr" => {
+                        row.cells.push(cell);
+                        row.header |= frame.tag == "th";
+                    }
+                    _ => self.buf().push_str(&cell),
+                }
+            }
+            "tr" => {
+                if frame.cells.is_empty() {
+                    return;
+                }
+                self.line();
+                let mut row = format!("| {} |", frame.cells.join(" | "));
+                if frame.header && self.table_rows == 0 {
+                    row.push_str(&format!("\n|{}", " --- |".repeat(frame.cells.len())));
+                }
+                self.table_rows += 1;
+                self.buf().push_str(&row);
+                self.buf().push('\n');
+            }
+            _ => self.buf().push_str(&frame.buf),
+        }
+    }
+}
+
+/// 读取标签属性值，支持单引号、双引号和不加引号的写法
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    let mut rest = attrs;
+    while let Some(position) = rest.to_ascii_lowercase().find(name) {
+        let before = rest[..position].chars().last();
+        let after = rest[position + name.len()..].trim_start();
+        rest = &rest[position + name.len()..];
+        if before.is_some_and(|c| !c.is_whitespace()) {
+            continue;
+        }
+        let Some(value) = after.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let raw = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value.split(|c: char| c.is_whitespace() || c == '>').next().unwrap_or_default(),
+        };
+        return Some(decode_entities(raw));
+    }
+    None
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !space {
+                out.push(' ');
+            }
+            space = true;
+        } else {
+            out.push(c);
+            space = false;
+        }
+    }
+    out
+}
+
+/// 解码常见的命名实体和数字实体
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest[1..].find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &rest[1..end + 1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                "mdash" => Some('—'),
+                "ndash" => Some('–'),
+                "hellip" => Some('…'),
+                "copy" => Some('©'),
+                "rsquo" => Some('’'),
+                "lsquo" => Some('‘'),
+                "rdquo" => Some('”'),
+                "ldquo" => Some('“'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(str::parse::<u32>))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end + 2))
+        });
+        match decoded {
+            Some((c, length)) => {
+                out.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 去掉行尾空白，连续的空行合并为一行
+fn tidy(markdown: &str) -> String {
+    let mut out = Vec::new();
+    for line in markdown.lines().map(str::trim_end) {
+        if line.is_empty() && out.last().is_none_or(|last: &&str| last.is_empty()) {
+            continue;
+        }
+        out.push(line);
+    }
+    out.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_markdown() {
+        let html = r#"<!DOCTYPE html><html><head><title>Docs &amp; Guides</title><style>body { color: red }</style></head>
+<body><nav><a href="/">Home</a></nav>
+<h1>Getting   started</h1>
+<p>Install with <code>cargo add tokio</code> and read the <a href="guide/intro.html">intro</a>.<br>Then <strong>run</strong> it.</p>
+<script>alert("hi")</script>
+<ul><li>First<ul><li>Nested</li></ul></li><li>Second &#8212; done</li></ul>
+<ol><li>One<li>Two</ol>
+<pre><code>fn main() {
+    println!("&lt;hi&gt;");
+}</code></pre>
+<blockquote><p>Quoted text</p></blockquote>
+<table><tr><th>Name</th><th>Value</th><tr><td>a</td><td>1</td></table>
+</body></html>"#;
+        let base = Url::parse("https://docs.example.com/book/").unwrap();
+        let document = html_to_markdown(html, Some(&base));
+
+        assert_eq!(document.title.as_deref(), Some("Docs & Guides"));
+        assert_eq!(
+            document.markdown,
+            "[Home](https://docs.example.com/)\n\n\
+             # Getting started\n\n\
+             Install with `cargo add tokio` and read the [intro](https://docs.example.com/book/guide/intro.html).\n\
+             Then **run** it.\n\n\
+             - First\n  - Nested\n- Second — done\n\n\
+             1. One\n2. Two\n\n\
+             ```\nfn main() {\n    println!(\"<hi>\");\n}\n```\n\n\
+             > Quoted text\n\n\
+             | Name | Value |\n| --- | --- |\n| a | 1 |"
+        );
+    }
+}
@@ -2,14 +2,204 @@
 //! 
 //! 使用 reqwest 实现 HTTP 客户端，支持 API 调用和文件下载
 
-use reqwest::{Client, Method, Response, header::HeaderMap};
+use reqwest::{Client, Method, RequestBuilder, Response, header::HeaderMap};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 use tracing::{info, warn, error, debug};
 
+use crate::config::HttpPoolConfig;
 use crate::error::{ClaudeError, Result};
 
+pub mod batch;
+pub mod dispatch;
+pub mod fetch;
+pub mod html;
+pub mod webhooks;
+
+/// 消息事件流
+pub type MessageEventStream = std::pin::Pin<Box<dyn futures::Stream<Item = Result<StreamEvent>> + Send>>;
+
+/// 非流式请求的默认超时
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 进程内共享的 HTTP 客户端，所有请求和流复用同一连接池
+static SHARED_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// 共享客户端的连接池配置，由 `configure_shared_client` 记录
+static SHARED_CLIENT_CONFIG: OnceLock<HttpPoolConfig> = OnceLock::new();
+
+/// 进程内共享的连接指标
+static CONNECTION_METRICS: ConnectionMetrics = ConnectionMetrics::new();
+
+/// 按连接池配置创建 HTTP 客户端
+pub fn build_client(config: &HttpPoolConfig) -> Result<Client> {
+    let mut builder = Client::builder()
+        .user_agent("claude-code-rust/0.1.0")
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_secs))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .tcp_keepalive(Duration::from_secs(config.tcp_keepalive_secs))
+        .tcp_nodelay(true);
+
+    builder = if config.http2 {
+        builder
+            .http2_adaptive_window(true)
+            .http2_keep_alive_interval(Duration::from_secs(config.http2_keep_alive_interval_secs))
+            .http2_keep_alive_timeout(Duration::from_secs(10))
+            .http2_keep_alive_while_idle(true)
+    } else {
+        builder.http1_only()
+    };
+
+    Ok(builder.build()?)
+}
+
+/// 记录共享客户端的连接池配置，客户端在第一次使用时才按它创建；仅首次调用生效
+pub fn configure_shared_client(config: &HttpPoolConfig) -> bool {
+    SHARED_CLIENT_CONFIG.set(config.clone()).is_ok()
+}
+
+/// 使用配置初始化共享客户端，仅首次调用生效，返回是否已应用该配置
+pub fn init_shared_client(config: &HttpPoolConfig) -> Result<bool> {
+    if SHARED_CLIENT.get().is_some() {
+        return Ok(false);
+    }
+    let client = build_client(config)?;
+    Ok(SHARED_CLIENT.set(client).is_ok())
+}
+
+/// 获取共享客户端，未初始化时按记录的配置（没有则用默认配置）创建
+pub fn shared_client() -> Client {
+    SHARED_CLIENT
+        .get_or_init(|| {
+            let config = SHARED_CLIENT_CONFIG.get().cloned().unwrap_or_default();
+            build_client(&config).expect("Failed to create HTTP client")
+        })
+        .clone()
+}
+
+/// 连接指标
+#[derive(Debug)]
+pub struct ConnectionMetrics {
+    requests: AtomicU64,
+    failures: AtomicU64,
+    http2_responses: AtomicU64,
+    http1_responses: AtomicU64,
+    total_time_to_headers_us: AtomicU64,
+    last_time_to_headers_us: AtomicU64,
+}
+
+/// 连接指标快照
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConnectionStats {
+    /// 请求数
+    pub requests: u64,
+    /// 连接或传输失败数
+    pub failures: u64,
+    /// 通过 HTTP/2 返回的响应数
+    pub http2_responses: u64,
+    /// 通过 HTTP/1.x 返回的响应数
+    pub http1_responses: u64,
+    /// 平均响应头到达时间（毫秒），连接复用时显著降低
+    pub average_time_to_headers_ms: f64,
+    /// 最近一次响应头到达时间（毫秒）
+    pub last_time_to_headers_ms: f64,
+}
+
+impl ConnectionStats {
+    /// 格式化为单行摘要
+    pub fn format_report(&self) -> String {
+        format!(
+            "Connections: {} request(s), {} over HTTP/2, {} over HTTP/1.x, {} failed, response headers in {:.1}ms (avg {:.1}ms)",
+            self.requests,
+            self.http2_responses,
+            self.http1_responses,
+            self.failures,
+            self.last_time_to_headers_ms,
+            self.average_time_to_headers_ms
+        )
+    }
+}
+
+impl ConnectionMetrics {
+    const fn new() -> Self {
+        Self {
+            requests: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            http2_responses: AtomicU64::new(0),
+            http1_responses: AtomicU64::new(0),
+            total_time_to_headers_us: AtomicU64::new(0),
+            last_time_to_headers_us: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, result: &reqwest::Result<Response>, elapsed: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let response = match result {
+            Ok(response) => response,
+            Err(_) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let micros = elapsed.as_micros() as u64;
+        self.total_time_to_headers_us.fetch_add(micros, Ordering::Relaxed);
+        self.last_time_to_headers_us.store(micros, Ordering::Relaxed);
+        if response.version() == reqwest::Version::HTTP_2 {
+            self.http2_responses.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.http1_responses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 获取快照
+    pub fn snapshot(&self) -> ConnectionStats {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let failures = self.failures.load(Ordering::Relaxed);
+        let completed = requests.saturating_sub(failures);
+        let total_us = self.total_time_to_headers_us.load(Ordering::Relaxed);
+
+        ConnectionStats {
+            requests,
+            failures,
+            http2_responses: self.http2_responses.load(Ordering::Relaxed),
+            http1_responses: self.http1_responses.load(Ordering::Relaxed),
+            average_time_to_headers_ms: if completed == 0 { 0.0 } else { total_us as f64 / completed as f64 / 1000.0 },
+            last_time_to_headers_ms: self.last_time_to_headers_us.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+}
+
+/// 共享客户端的连接指标
+pub fn connection_stats() -> ConnectionStats {
+    CONNECTION_METRICS.snapshot()
+}
+
+/// 发送请求并记录连接指标
+async fn send_tracked(request: RequestBuilder) -> Result<Response> {
+    let started = Instant::now();
+    let result = request.send().await;
+    CONNECTION_METRICS.record(&result, started.elapsed());
+    Ok(result?)
+}
+
+/// 把失败的 HTTP 响应转换为带状态码和重试等待时间的错误
+pub async fn error_from_response(response: Response) -> ClaudeError {
+    let status = response.status().as_u16();
+    let retry_after = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let body = response.text().await.unwrap_or_default();
+    ClaudeError::from_status(status, &body, retry_after)
+}
+
 /// Claude API 请求结构
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClaudeRequest {
@@ -28,6 +218,9 @@ pub struct ClaudeRequest {
     /// 温度参数
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
+    /// 核采样参数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
     /// 系统提示
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system: Option<String>,
@@ -39,7 +232,20 @@ pub struct Message {
     /// 角色 (user, assistant, system)
     pub role: String,
     /// 消息内容
-    pub content: String,
+    pub content: crate::conversation::SharedText,
+}
+
+/// 内容为文本或内容块的消息，工具调用循环中携带 `tool_use` 与 `tool_result`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContentMessage {
+    pub role: String,
+    pub content: MessageContent,
+}
+
+impl ContentMessage {
+    pub fn new(role: impl Into<String>, blocks: Vec<ContentBlock>) -> Self {
+        Self { role: role.into(), content: MessageContent::Blocks(blocks) }
+    }
 }
 
 /// 工具定义
@@ -90,7 +296,7 @@ pub struct StreamEvent {
     /// 事件类型
     #[serde(rename = "type")]
     pub event_type: String,
-    /// 事件数据
+    /// 事件数据（完整的事件 JSON 负载）
     pub data: Option<serde_json::Value>,
 }
 
@@ -105,48 +311,37 @@ pub struct ApiError {
 }
 
 /// HTTP 客户端管理器
+///
+/// 所有实例共享同一个连接池；超时只作用于非流式请求，长时间的流式响应不会被截断
 pub struct NetworkManager {
     client: Client,
     base_url: String,
     default_headers: HashMap<String, String>,
+    /// 非流式请求超时
+    timeout: Duration,
 }
 
 impl NetworkManager {
     /// 创建新的网络管理器
     pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("claude-code-rust/0.1.0")
-            .build()
-            .expect("Failed to create HTTP client");
-
         let mut default_headers = HashMap::new();
         default_headers.insert("Content-Type".to_string(), "application/json".to_string());
         default_headers.insert("anthropic-version".to_string(), "2023-06-01".to_string());
 
         Self {
-            client,
+            client: shared_client(),
             base_url: "https://api.anthropic.com".to_string(),
             default_headers,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
         }
     }
 
     /// 创建带自定义配置的网络管理器
     pub fn with_config(base_url: String, timeout: Duration) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(timeout)
-            .user_agent("claude-code-rust/0.1.0")
-            .build()?;
-
-        let mut default_headers = HashMap::new();
-        default_headers.insert("Content-Type".to_string(), "application/json".to_string());
-        default_headers.insert("anthropic-version".to_string(), "2023-06-01".to_string());
-
-        Ok(Self {
-            client,
-            base_url,
-            default_headers,
-        })
+        let mut manager = Self::new();
+        manager.base_url = base_url;
+        manager.timeout = timeout;
+        Ok(manager)
     }
 
     /// 设置默认头部
@@ -154,9 +349,9 @@ impl NetworkManager {
         self.default_headers.insert(key, value);
     }
 
-    /// 设置 API 密钥
+    /// 设置 API 密钥（Anthropic API 使用 x-api-key 头认证）
     pub fn set_api_key(&mut self, api_key: String) {
-        self.default_headers.insert("Authorization".to_string(), format!("Bearer {}", api_key));
+        self.default_headers.insert("x-api-key".to_string(), api_key);
     }
 
     /// 发送 GET 请求
@@ -199,14 +394,10 @@ impl NetworkManager {
             request = request.json(body);
         }
 
-        let response = request.send().await?;
+        let response = send_tracked(request.timeout(self.timeout)).await?;
         
         if !response.status().is_success() {
-            return Err(ClaudeError::network_error(format!(
-                "HTTP request failed: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_else(|_| "Unknown error".to_string())
-            )));
+            return Err(error_from_response(response).await);
         }
 
         Ok(response)
@@ -214,14 +405,10 @@ impl NetworkManager {
 
     /// 下载文件
     pub async fn download_file(&self, url: &str) -> Result<Vec<u8>> {
-        let response = self.client.get(url).send().await?;
+        let response = send_tracked(self.client.get(url).timeout(self.timeout)).await?;
         
         if !response.status().is_success() {
-            return Err(ClaudeError::network_error(format!(
-                "Download failed: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_else(|_| "Unknown error".to_string())
-            )));
+            return Err(error_from_response(response).await);
         }
 
         let bytes = response.bytes().await?;
@@ -245,14 +432,10 @@ impl NetworkManager {
             }
         }
 
-        let response = request.send().await?;
+        let response = send_tracked(request.timeout(self.timeout)).await?;
         
         if !response.status().is_success() {
-            return Err(ClaudeError::network_error(format!(
-                "Upload failed: {} - {}",
-                response.status(),
-                response.text().await.unwrap_or_else(|_| "Unknown error".to_string())
-            )));
+            return Err(error_from_response(response).await);
         }
 
         Ok(response)
@@ -275,34 +458,23 @@ impl NetworkManager {
         request = request.header("Accept", "text/event-stream");
         request = request.header("Cache-Control", "no-cache");
 
-        let response = request
-            .json(body)
-            .send()
-            .await?;
+        let response = send_tracked(request.json(body)).await?;
 
         if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ClaudeError::network_error(format!("HTTP {}: {}", status, text)));
+            return Err(error_from_response(response).await);
         }
 
         Ok(response.bytes_stream().map(|result| {
-            result.map_err(|e| ClaudeError::Network(e))
+            result.map_err(ClaudeError::from)
         }))
     }
 
     /// 发送 Server-Sent Events 流式请求
-    pub async fn post_sse_stream<T: Serialize>(&self, endpoint: &str, body: &T) -> Result<impl futures::Stream<Item = Result<String>>> {
-        use futures::StreamExt;
-
+    ///
+    /// 按字节解析事件，跨网络块的行和多字节字符会被正确拼接
+    pub async fn post_sse_stream<T: Serialize>(&self, endpoint: &str, body: &T) -> Result<impl futures::Stream<Item = Result<crate::streaming::SseEvent>>> {
         let stream = self.post_stream(endpoint, body).await?;
-
-        Ok(stream.map(|chunk_result| {
-            chunk_result.and_then(|chunk| {
-                String::from_utf8(chunk.to_vec())
-                    .map_err(|e| ClaudeError::General(format!("UTF-8 decode error: {}", e)))
-            })
-        }))
+        Ok(crate::streaming::event_stream(stream))
     }
 
     /// 发送请求到 Claude API
@@ -312,7 +484,7 @@ impl NetworkManager {
 
         // 获取 API 密钥
         let api_key = std::env::var("ANTHROPIC_API_KEY")
-            .map_err(|_| ClaudeError::config_error("ANTHROPIC_API_KEY environment variable not set"))?;
+            .map_err(|_| ClaudeError::auth_error("ANTHROPIC_API_KEY environment variable not set"))?;
 
         // 构建请求头
         let headers = self.build_claude_headers(&api_key)?;
@@ -320,20 +492,27 @@ impl NetworkManager {
         // 构建请求 URL
         let url = format!("{}/v1/messages", self.base_url);
 
+        // 按限流和优先级排队
+        let permit = dispatch::dispatcher().acquire(dispatch::current_priority()).await;
+
         // 发送请求
-        let response = self.client
-            .post(&url)
-            .headers(headers)
-            .json(&request)
-            .send()
-            .await?;
+        let response = send_tracked(
+            self.client
+                .post(&url)
+                .headers(headers)
+                .json(&request)
+                .timeout(self.timeout),
+        )
+        .await?;
 
         // 检查响应状态
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            error!("Claude API error: {}", error_text);
-            return Err(ClaudeError::network_error(&format!("API request failed: {}", error_text)));
+            let error = error_from_response(response).await;
+            permit.record_error(&error);
+            error!("Claude API error: {}", error);
+            return Err(error);
         }
+        permit.record_success(response.headers());
 
         // 解析响应
         let response_text = response.text().await?;
@@ -349,7 +528,7 @@ impl NetworkManager {
                 // 如果解析失败，尝试解析为错误响应
                 if let Ok(api_error) = serde_json::from_str::<ApiError>(&response_text) {
                     error!("Claude API error: {}", api_error.message);
-                    Err(ClaudeError::network_error(&api_error.message))
+                    Err(ClaudeError::from_api_error(&api_error.error_type, api_error.message))
                 } else {
                     error!("Failed to parse Claude response: {}", e);
                     Err(ClaudeError::network_error(&format!("Failed to parse response: {}", e)))
@@ -385,12 +564,13 @@ impl NetworkManager {
             model: "claude-3-haiku-20240307".to_string(),
             messages: vec![Message {
                 role: "user".to_string(),
-                content: "Hello".to_string(),
+                content: "Hello".into(),
             }],
             max_tokens: 10,
             stream: None,
             tools: None,
             temperature: None,
+            top_p: None,
             system: None,
         };
 
@@ -452,45 +632,72 @@ impl ClaudeApiClient {
     }
 
     /// 发送消息到 Claude
-    pub async fn send_message(&self, request: &MessageRequest) -> Result<MessageResponse> {
-        let response = self.network.post("v1/messages", request).await?;
+    pub async fn send_message<M: Serialize>(&self, request: &MessageRequest<M>) -> Result<MessageResponse> {
+        let permit = dispatch::dispatcher().acquire(dispatch::current_priority()).await;
+        let response = self.network.post("v1/messages", request).await.inspect_err(|e| permit.record_error(e))?;
+        permit.record_success(response.headers());
         let message_response: MessageResponse = response.json().await?;
         Ok(message_response)
     }
 
     /// 发送流式消息到 Claude
-    pub async fn send_message_stream(&self, request: &MessageRequest) -> Result<impl futures::Stream<Item = Result<StreamEvent>>> {
+    pub async fn send_message_stream<M: Serialize + Clone>(
+        &self,
+        request: &MessageRequest<M>,
+    ) -> Result<impl futures::Stream<Item = Result<StreamEvent>>> {
         use futures::StreamExt;
 
         // 创建流式请求
         let mut stream_request = request.clone();
         stream_request.stream = Some(true);
 
-        let stream = self.network.post_sse_stream("v1/messages", &stream_request).await?;
-
-        Ok(stream.filter_map(|line_result| async move {
-            match line_result {
-                Ok(line) => {
-                    // 解析 SSE 格式
-                    if line.starts_with("data: ") {
-                        let data = &line[6..]; // 移除 "data: " 前缀
-                        if data == "[DONE]" {
-                            return None; // 流结束
-                        }
-
-                        match serde_json::from_str::<StreamEvent>(data) {
-                            Ok(event) => Some(Ok(event)),
-                            Err(e) => Some(Err(ClaudeError::Json(e))),
-                        }
-                    } else {
-                        None // 忽略非数据行
-                    }
-                }
-                Err(e) => Some(Err(e)),
+        // 许可随流一起保留，流结束前计入并发数
+        let permit = dispatch::dispatcher().acquire(dispatch::current_priority()).await;
+        let stream = self
+            .network
+            .post_sse_stream("v1/messages", &stream_request)
+            .await
+            .inspect_err(|e| permit.record_error(e))?;
+        permit.record_success(&HeaderMap::new());
+
+        let events = stream.filter_map(|event_result| async move {
+            let event = match event_result {
+                Ok(event) => event,
+                Err(e) => return Some(Err(e)),
+            };
+
+            // 保留完整负载，usage 等字段位于事件顶层
+            match event.data {
+                serde_json::Value::Object(_) => Some(Ok(StreamEvent {
+                    event_type: event
+                        .data
+                        .get("type")
+                        .and_then(|t| t.as_str())
+                        .unwrap_or("unknown")
+                        .to_string(),
+                    data: Some(event.data),
+                })),
+                // 没有数据的事件和结束标记
+                serde_json::Value::Null => None,
+                serde_json::Value::String(ref data) if data == "[DONE]" => None,
+                other => Some(Err(ClaudeError::General(format!("Invalid stream event payload: {}", other)))),
             }
+        });
+        Ok(events.map(move |event| {
+            let _permit = &permit;
+            event
         }))
     }
 
+    /// 发送可以随时取消的流式消息，返回事件流和取消句柄
+    pub async fn send_message_stream_cancellable(
+        &self,
+        request: &MessageRequest,
+    ) -> Result<(crate::streaming::cancel::CancellableStream<MessageEventStream>, crate::streaming::cancel::CancelHandle)> {
+        let stream: MessageEventStream = Box::pin(self.send_message_stream(request).await?);
+        Ok(crate::streaming::cancel::CancellableStream::new(stream, &request.model))
+    }
+
     /// 获取模型列表
     pub async fn list_models(&self) -> Result<Vec<Model>> {
         let response = self.network.get("v1/models").await?;
@@ -501,12 +708,12 @@ impl ClaudeApiClient {
 
 
     /// 创建简单文本消息请求
-    pub fn create_text_request(&self, model: &str, messages: Vec<(String, String)>) -> MessageRequest {
+    pub fn create_text_request<C: Into<crate::conversation::SharedText>>(&self, model: &str, messages: Vec<(String, C)>) -> MessageRequest {
         let messages: Vec<Message> = messages
             .into_iter()
             .map(|(role, content)| Message {
                 role,
-                content,
+                content: content.into(),
             })
             .collect();
 
@@ -527,10 +734,10 @@ impl ClaudeApiClient {
     }
 
     /// 创建带工具的消息请求
-    pub fn create_tool_request(
+    pub fn create_tool_request<C: Into<crate::conversation::SharedText>>(
         &self,
         model: &str,
-        messages: Vec<(String, String)>,
+        messages: Vec<(String, C)>,
         tools: Vec<Tool>,
         tool_choice: Option<ToolChoice>,
     ) -> MessageRequest {
@@ -538,7 +745,7 @@ impl ClaudeApiClient {
             .into_iter()
             .map(|(role, content)| Message {
                 role,
-                content,
+                content: content.into(),
             })
             .collect();
 
@@ -567,7 +774,7 @@ impl ClaudeApiClient {
     ) -> MessageRequest {
         let message = Message {
             role,
-            content: format!("Image content with {} blocks", content_blocks.len()),
+            content: format!("Image content with {} blocks", content_blocks.len()).into(),
         };
 
         MessageRequest {
@@ -630,12 +837,12 @@ impl ClaudeApiClient {
     }
 }
 
-/// 消息请求结构
+/// 消息请求结构，消息类型默认为纯文本的 [`Message`]
 #[derive(Debug, Clone, Serialize)]
-pub struct MessageRequest {
+pub struct MessageRequest<M = Message> {
     pub model: String,
     pub max_tokens: u32,
-    pub messages: Vec<Message>,
+    pub messages: Vec<M>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -763,6 +970,93 @@ pub enum ContentBlock {
     },
 }
 
+/// 把流式事件拼回完整的 [`MessageResponse`]
+#[derive(Debug, Default)]
+pub struct MessageAssembler {
+    message: Option<serde_json::Value>,
+    /// 按索引排列的内容块，工具参数在结束前是 JSON 片段
+    blocks: Vec<(serde_json::Value, String)>,
+    stop_reason: Option<String>,
+    output_tokens: Option<u64>,
+}
+
+impl MessageAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 处理一个事件，返回其中的文本增量
+    pub fn push(&mut self, event: &StreamEvent) -> Result<Option<String>> {
+        let Some(payload) = event.data.as_ref() else {
+            return Ok(None);
+        };
+        let index = payload["index"].as_u64().unwrap_or(0) as usize;
+        match event.event_type.as_str() {
+            "message_start" => self.message = Some(payload["message"].clone()),
+            "content_block_start" => {
+                if self.blocks.len() <= index {
+                    self.blocks.resize(index + 1, (serde_json::Value::Null, String::new()));
+                }
+                self.blocks[index] = (payload["content_block"].clone(), String::new());
+            }
+            "content_block_delta" => {
+                let Some((block, partial)) = self.blocks.get_mut(index) else {
+                    return Err(ClaudeError::General(format!("Stream delta for unknown content block {}", index)));
+                };
+                let delta = &payload["delta"];
+                if let Some(text) = delta["text"].as_str() {
+                    let current = block["text"].as_str().unwrap_or_default();
+                    block["text"] = serde_json::Value::String(format!("{}{}", current, text));
+                    return Ok(Some(text.to_string()));
+                }
+                if let Some(json) = delta["partial_json"].as_str() {
+                    partial.push_str(json);
+                }
+            }
+            "message_delta" => {
+                if let Some(reason) = payload["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(reason.to_string());
+                }
+                if let Some(tokens) = payload["usage"]["output_tokens"].as_u64() {
+                    self.output_tokens = Some(tokens);
+                }
+            }
+            "error" => {
+                let message = payload["error"]["message"].as_str().unwrap_or("Unknown stream error");
+                return Err(ClaudeError::from_api_error(payload["error"]["type"].as_str().unwrap_or_default(), message));
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /// 流结束后的完整回复
+    pub fn finish(self) -> Result<MessageResponse> {
+        let mut message = self
+            .message
+            .ok_or_else(|| ClaudeError::General("Stream ended before message_start".to_string()))?;
+        let content = self
+            .blocks
+            .into_iter()
+            .filter(|(block, _)| !block.is_null())
+            .map(|(mut block, partial)| {
+                if !partial.is_empty() {
+                    block["input"] = serde_json::from_str(&partial)?;
+                }
+                Ok(block)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        message["content"] = serde_json::Value::Array(content);
+        if self.stop_reason.is_some() {
+            message["stop_reason"] = serde_json::json!(self.stop_reason);
+        }
+        if let Some(tokens) = self.output_tokens {
+            message["usage"]["output_tokens"] = serde_json::json!(tokens);
+        }
+        Ok(serde_json::from_value(message)?)
+    }
+}
+
 /// 图像源
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct ImageSource {
@@ -799,6 +1093,15 @@ pub enum ResponseContentBlock {
     },
 }
 
+impl From<ResponseContentBlock> for ContentBlock {
+    fn from(block: ResponseContentBlock) -> Self {
+        match block {
+            ResponseContentBlock::Text { text } => ContentBlock::Text { text },
+            ResponseContentBlock::ToolUse { id, name, input } => ContentBlock::ToolUse { id, name, input },
+        }
+    }
+}
+
 /// 模型信息
 #[derive(Debug, Deserialize)]
 pub struct Model {
@@ -827,13 +1130,67 @@ mod tests {
 
     #[tokio::test]
     async fn test_network_manager_creation() {
-        let manager = NetworkManager::new(
+        let manager = NetworkManager::with_config(
             "https://api.example.com".to_string(),
             Duration::from_secs(30)
         );
         assert!(manager.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_connection_reuse_and_auth_header() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start(vec![MockReply::text("ok"); 3]).await;
+        let mut manager = NetworkManager::with_config(server.base_url().to_string(), Duration::from_secs(5)).unwrap();
+        manager.set_api_key("sk-test".to_string());
+        let before = connection_stats();
+
+        for _ in 0..3 {
+            let response: serde_json::Value = manager.get("v1/ping").await.unwrap().json().await.unwrap();
+            assert_eq!(response["content"][0]["text"], "ok");
+        }
+
+        assert_eq!(server.connections(), 1);
+        let request = server.requests().pop().unwrap();
+        assert_eq!(request.path, "/v1/ping");
+        assert_eq!(request.headers["x-api-key"], "sk-test");
+        assert!(!request.headers.contains_key("authorization"));
+
+        let after = connection_stats();
+        assert!(after.requests >= before.requests + 3);
+        assert!(after.http1_responses >= before.http1_responses + 3);
+    }
+
+    #[tokio::test]
+    async fn test_messages_against_mock_server() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "bash", serde_json::json!({"command": "ls"})),
+            MockReply::overloaded(),
+            MockReply::rate_limited(7),
+        ])
+        .await;
+        let client = ClaudeApiClient::new("sk-test".to_string(), Some(server.base_url().to_string())).unwrap();
+        let request = client.create_text_request("claude-mock", vec![("user".to_string(), "list files")]);
+
+        let response = client.send_message(&request).await.unwrap();
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        assert!(matches!(&response.content[0], ResponseContentBlock::ToolUse { name, input, .. } if name == "bash" && input["command"] == "ls"));
+        let sent = &server.requests()[0];
+        assert_eq!(sent.body["messages"][0]["content"], "list files");
+        assert_eq!(sent.headers["anthropic-version"], "2023-06-01");
+
+        let overloaded = client.send_message(&request).await.unwrap_err();
+        assert!(overloaded.is_retryable());
+        assert!(overloaded.to_string().contains("Overloaded"));
+
+        let limited = client.send_message(&request).await.unwrap_err();
+        assert!(matches!(limited, ClaudeError::RateLimit { .. }));
+        assert_eq!(limited.retry_after(), Some(Duration::from_secs(7)));
+    }
+
     #[test]
     fn test_message_request_serialization() {
         let request = MessageRequest {
@@ -841,7 +1198,7 @@ mod tests {
             max_tokens: 1000,
             messages: vec![Message {
                 role: "user".to_string(),
-                content: MessageContent::Text("Hello, Claude!".to_string()),
+                content: "Hello, Claude!".into(),
             }],
             system: Some("You are a helpful assistant.".to_string()),
             temperature: Some(0.7),
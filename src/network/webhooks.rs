@@ -0,0 +1,441 @@
+//! Webhook 通知
+//!
+//! 任务完成或失败、预算越过阈值、权限提升被拦截时，把事件 POST 到配置的 Webhook，供团队接入
+//! Slack 或值班系统。设置了密钥的请求带 HMAC-SHA256 签名，投递失败按指数退避重试，每次尝试都
+//! 写入投递日志
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::config::{WebhookConfig, WebhookFormat};
+use crate::error::{ClaudeError, Result};
+
+/// 预算花费达到这些比例时各通知一次
+pub const BUDGET_THRESHOLDS: [f64; 3] = [0.5, 0.8, 1.0];
+
+/// 签名请求头，值为 `sha256=<hex>`，签名内容是 `<时间戳>.<请求体>`
+pub const SIGNATURE_HEADER: &str = "X-Claude-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Claude-Timestamp";
+
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+static NOTIFIER: OnceLock<WebhookNotifier> = OnceLock::new();
+
+/// `init` 记录的 Webhook 配置
+static WEBHOOKS: OnceLock<HashMap<String, WebhookConfig>> = OnceLock::new();
+
+/// 可以通知的事件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", content = "data")]
+pub enum WebhookEvent {
+    #[serde(rename = "job.completed")]
+    JobCompleted { job: String, summary: String },
+    #[serde(rename = "job.failed")]
+    JobFailed { job: String, error: String },
+    #[serde(rename = "budget.threshold")]
+    BudgetThreshold {
+        /// 预算所属的用户或项目
+        scope: String,
+        /// 越过的比例，如 0.8
+        threshold: f64,
+        spent_usd: f64,
+        budget_usd: f64,
+    },
+    #[serde(rename = "permission.escalation")]
+    PermissionEscalation {
+        /// 发起操作的工具或用户
+        actor: String,
+        action: String,
+        decision: String,
+    },
+    /// `claude webhooks test` 发送的测试事件
+    #[serde(rename = "webhook.test")]
+    Test,
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::JobCompleted { .. } => "job.completed",
+            WebhookEvent::JobFailed { .. } => "job.failed",
+            WebhookEvent::BudgetThreshold { .. } => "budget.threshold",
+            WebhookEvent::PermissionEscalation { .. } => "permission.escalation",
+            WebhookEvent::Test => "webhook.test",
+        }
+    }
+
+    /// 给人看的一行描述，Slack 格式直接使用
+    pub fn summary(&self) -> String {
+        match self {
+            WebhookEvent::JobCompleted { job, summary } => format!("✅ {} finished: {}", job, summary),
+            WebhookEvent::JobFailed { job, error } => format!("❌ {} failed: {}", job, error),
+            WebhookEvent::BudgetThreshold { scope, threshold, spent_usd, budget_usd } => format!(
+                "💰 {} has used {:.0}% of its budget (${:.2} of ${:.2})",
+                scope,
+                threshold * 100.0,
+                spent_usd,
+                budget_usd
+            ),
+            WebhookEvent::PermissionEscalation { actor, action, decision } => {
+                format!("🔐 Permission escalation by {} {}: {}", actor, decision, action)
+            }
+            WebhookEvent::Test => "🔔 Test notification from Claude Code".to_string(),
+        }
+    }
+}
+
+/// 预算从 `before` 涨到 `after` 时越过的阈值
+pub fn crossed_thresholds(before: f64, after: f64, budget: f64) -> Vec<f64> {
+    if budget <= 0.0 {
+        return Vec::new();
+    }
+    BUDGET_THRESHOLDS
+        .into_iter()
+        .filter(|threshold| before < threshold * budget && after >= threshold * budget)
+        .collect()
+}
+
+/// 订阅是否包含该事件：支持精确名称、`job.*` 形式的前缀和 `*`
+fn subscribed(config: &WebhookConfig, event: &str) -> bool {
+    config.events.is_empty()
+        || config.events.iter().any(|pattern| {
+            pattern == "*"
+                || pattern == event
+                || pattern.strip_suffix(".*").is_some_and(|prefix| event.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('.')))
+        })
+}
+
+/// `sha256=<hex>` 形式的签名
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// 投递日志中的一次尝试
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    pub delivery_id: String,
+    pub webhook: String,
+    pub event: String,
+    pub attempt: u32,
+    pub at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+/// 只追加的投递日志
+#[derive(Debug, Clone)]
+pub struct DeliveryLog {
+    path: PathBuf,
+    lock: std::sync::Arc<Mutex<()>>,
+}
+
+impl DeliveryLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), lock: Default::default() }
+    }
+
+    /// 默认位置：数据目录下的 `claude-code/webhooks.jsonl`
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("claude-code")
+            .join("webhooks.jsonl")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, record: &DeliveryRecord) -> Result<()> {
+        let _guard = self.lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// 最近的 `limit` 条记录，旧的在前
+    pub fn recent(&self, limit: usize) -> Result<Vec<DeliveryRecord>> {
+        let data = match std::fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let records: Vec<DeliveryRecord> = data.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+        Ok(records[records.len().saturating_sub(limit)..].to_vec())
+    }
+}
+
+/// 投递事件到所有订阅的 Webhook
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    webhooks: HashMap<String, WebhookConfig>,
+    client: reqwest::Client,
+    log: DeliveryLog,
+    base_backoff: Duration,
+    pending: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl WebhookNotifier {
+    pub fn new(webhooks: HashMap<String, WebhookConfig>, client: reqwest::Client, log: DeliveryLog) -> Self {
+        Self { webhooks, client, log, base_backoff: BASE_BACKOFF, pending: Mutex::new(Vec::new()) }
+    }
+
+    /// 调整首次重试的等待时间
+    pub fn with_base_backoff(mut self, backoff: Duration) -> Self {
+        self.base_backoff = backoff;
+        self
+    }
+
+    pub fn log(&self) -> &DeliveryLog {
+        &self.log
+    }
+
+    /// 在后台投递事件，不等待结果
+    pub fn notify(&self, event: WebhookEvent) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("No async runtime to deliver webhook event {}", event.name());
+            return;
+        };
+        let mut pending = self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        pending.retain(|task| !task.is_finished());
+        for (name, config) in self.webhooks.iter().filter(|(_, config)| subscribed(config, event.name())) {
+            let delivery = self.delivery(name, config);
+            let event = event.clone();
+            pending.push(runtime.spawn(async move {
+                let _ = delivery.deliver(&event).await;
+            }));
+        }
+    }
+
+    /// 投递到一个 Webhook 并等待结果（含重试）
+    pub async fn deliver_to(&self, name: &str, event: &WebhookEvent) -> Result<u16> {
+        let config = self
+            .webhooks
+            .get(name)
+            .ok_or_else(|| ClaudeError::validation_error("webhook", format!("no webhook named '{}'", name)))?;
+        self.delivery(name, config).deliver(event).await
+    }
+
+    /// 等待后台投递完成，最多等待 `timeout`
+    pub async fn drain(&self, timeout: Duration) {
+        let tasks = std::mem::take(&mut *self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        if tasks.is_empty() {
+            return;
+        }
+        if tokio::time::timeout(timeout, futures::future::join_all(tasks)).await.is_err() {
+            tracing::warn!("Gave up waiting for webhook deliveries after {:?}", timeout);
+        }
+    }
+
+    fn delivery(&self, name: &str, config: &WebhookConfig) -> Delivery {
+        Delivery {
+            name: name.to_string(),
+            config: config.clone(),
+            client: self.client.clone(),
+            log: self.log.clone(),
+            base_backoff: self.base_backoff,
+        }
+    }
+}
+
+/// 一次投递（含重试）所需的全部状态，可以移入后台任务
+struct Delivery {
+    name: String,
+    config: WebhookConfig,
+    client: reqwest::Client,
+    log: DeliveryLog,
+    base_backoff: Duration,
+}
+
+impl Delivery {
+    fn body(&self, event: &WebhookEvent, delivery_id: &str, at: DateTime<Utc>) -> Result<Vec<u8>> {
+        let body = match self.config.format {
+            WebhookFormat::Json => {
+                let mut payload = serde_json::to_value(event)?;
+                payload["id"] = delivery_id.into();
+                payload["timestamp"] = at.to_rfc3339().into();
+                payload
+            }
+            WebhookFormat::Slack => serde_json::json!({ "text": event.summary() }),
+        };
+        Ok(serde_json::to_vec(&body)?)
+    }
+
+    async fn deliver(&self, event: &WebhookEvent) -> Result<u16> {
+        let delivery_id = uuid::Uuid::new_v4().to_string();
+        let body = self.body(event, &delivery_id, Utc::now())?;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let timestamp = Utc::now().timestamp();
+            let mut request = self
+                .client
+                .post(&self.config.url)
+                .timeout(Duration::from_secs(10))
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Claude-Event", event.name())
+                .header("X-Claude-Delivery", &delivery_id)
+                .header(TIMESTAMP_HEADER, timestamp);
+            if let Some(secret) = &self.config.secret {
+                request = request.header(SIGNATURE_HEADER, sign(secret, timestamp, &body));
+            }
+
+            let (status, error) = match request.body(body.clone()).send().await {
+                Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+                Ok(response) => (Some(response.status().as_u16()), Some(format!("HTTP {}", response.status()))),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            let record = DeliveryRecord {
+                delivery_id: delivery_id.clone(),
+                webhook: self.name.clone(),
+                event: event.name().to_string(),
+                attempt,
+                at: Utc::now(),
+                status,
+                error: error.clone(),
+                delivered: error.is_none(),
+            };
+            if let Err(e) = self.log.append(&record) {
+                tracing::warn!("Failed to write webhook delivery log {}: {}", self.log.path().display(), e);
+            }
+
+            let Some(error) = error else {
+                return Ok(status.unwrap_or_default());
+            };
+            // 客户端错误（除 408、429）重试也不会成功
+            let retryable = status.is_none_or(|status| status >= 500 || status == 408 || status == 429);
+            if !retryable || attempt > self.config.max_retries {
+                tracing::warn!("Webhook {} failed to deliver {}: {}", self.name, event.name(), error);
+                return Err(ClaudeError::General(format!("webhook '{}' delivery failed: {}", self.name, error)));
+            }
+            let backoff = self.base_backoff.saturating_mul(1 << (attempt - 1).min(6)).min(MAX_BACKOFF);
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+/// 记录共享通知器的配置，只在第一次调用时生效；通知器在第一次使用时才创建
+pub fn init(webhooks: &HashMap<String, WebhookConfig>) -> bool {
+    WEBHOOKS.set(webhooks.clone()).is_ok()
+}
+
+/// 共享的通知器，未初始化时为 `None`
+pub fn notifier() -> Option<&'static WebhookNotifier> {
+    let webhooks = WEBHOOKS.get()?;
+    Some(NOTIFIER.get_or_init(|| {
+        WebhookNotifier::new(webhooks.clone(), super::shared_client(), DeliveryLog::new(DeliveryLog::default_path()))
+    }))
+}
+
+/// 通知事件；没有配置 Webhook 时什么也不做
+pub fn notify(event: WebhookEvent) {
+    if WEBHOOKS.get().is_some_and(|webhooks| !webhooks.is_empty()) {
+        if let Some(notifier) = notifier() {
+            notifier.notify(event);
+        }
+    }
+}
+
+/// 进程退出前等待后台投递
+pub async fn drain(timeout: Duration) {
+    if let Some(notifier) = NOTIFIER.get() {
+        notifier.drain(timeout).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_signed_delivery_retries_until_accepted() {
+        // 第一次返回 503，第二次返回 204，记录收到的请求
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for status in ["503 Service Unavailable", "204 No Content"] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 4096];
+                loop {
+                    let read = socket.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else { continue };
+                    let length = head
+                        .lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length: ").map(str::to_string))
+                        .and_then(|length| length.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        requests.push(text);
+                        break;
+                    }
+                }
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = WebhookConfig {
+            url,
+            secret: Some("s3cret".to_string()),
+            events: vec!["job.*".to_string()],
+            format: WebhookFormat::Json,
+            max_retries: 3,
+        };
+        assert!(subscribed(&config, "job.failed"));
+        assert!(!subscribed(&config, "budget.threshold"));
+
+        let notifier = WebhookNotifier::new(
+            HashMap::from([("ops".to_string(), config)]),
+            reqwest::Client::new(),
+            DeliveryLog::new(dir.path().join("deliveries.jsonl")),
+        )
+        .with_base_backoff(Duration::from_millis(10));
+        let event = WebhookEvent::JobFailed { job: "bench".to_string(), error: "timeout".to_string() };
+        assert_eq!(notifier.deliver_to("ops", &event).await.unwrap(), 204);
+
+        let requests = server.await.unwrap();
+        let last = &requests[1];
+        let header = |name: &str| {
+            last.lines()
+                .find_map(|line| line.split_once(": ").filter(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, v)| v.to_string()))
+                .unwrap()
+        };
+        let body = last.split("\r\n\r\n").nth(1).unwrap();
+        let timestamp: i64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(header(SIGNATURE_HEADER), sign("s3cret", timestamp, body.as_bytes()));
+        assert!(body.contains(r#""event":"job.failed""#));
+
+        let log = notifier.log().recent(10).unwrap();
+        assert_eq!(log.iter().map(|r| (r.attempt, r.status, r.delivered)).collect::<Vec<_>>(), vec![
+            (1, Some(503), false),
+            (2, Some(204), true)
+        ]);
+        assert_eq!(crossed_thresholds(4.0, 8.5, 10.0), vec![0.5, 0.8]);
+    }
+}
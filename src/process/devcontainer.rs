@@ -0,0 +1,280 @@
+//! 开发容器支持
+//!
+//! 识别项目中的 `.devcontainer` 配置，查找正在运行的对应容器，
+//! 并把工具执行的命令改写为 `docker exec`，使构建和测试在项目约定的环境中运行
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::process::Command;
+
+use crate::error::{ClaudeError, Result};
+
+/// 检测到开发容器时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DevContainerMode {
+    /// 交互模式启动时询问
+    #[default]
+    Ask,
+    /// 容器在运行时自动切换
+    Always,
+    /// 始终在本机执行
+    Never,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DevContainerFile {
+    name: Option<String>,
+    workspace_folder: Option<String>,
+    remote_user: Option<String>,
+    container_user: Option<String>,
+    #[serde(default)]
+    remote_env: HashMap<String, serde_json::Value>,
+}
+
+/// 项目的开发容器配置
+#[derive(Debug, Clone, PartialEq)]
+pub struct DevContainer {
+    /// devcontainer.json 的位置
+    pub config_path: PathBuf,
+    /// 项目在本机的目录
+    pub local_folder: PathBuf,
+    pub name: String,
+    /// 项目在容器中的目录
+    pub workspace_folder: String,
+    /// 在容器中执行命令的用户
+    pub user: Option<String>,
+    /// 额外的环境变量
+    pub env: HashMap<String, String>,
+}
+
+impl DevContainer {
+    /// 在项目根目录查找 `.devcontainer/devcontainer.json`、`.devcontainer.json`
+    /// 或 `.devcontainer/<name>/devcontainer.json`
+    pub fn detect(root: &Path) -> Result<Option<Self>> {
+        let mut candidates = vec![root.join(".devcontainer").join("devcontainer.json"), root.join(".devcontainer.json")];
+        if let Ok(entries) = std::fs::read_dir(root.join(".devcontainer")) {
+            let mut nested: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path().join("devcontainer.json"))
+                .collect();
+            nested.sort();
+            candidates.extend(nested);
+        }
+        match candidates.into_iter().find(|path| path.is_file()) {
+            Some(path) => Self::load(root, &path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn load(root: &Path, config_path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(config_path)?;
+        let file: DevContainerFile = serde_json::from_str(&strip_jsonc(&content)).map_err(|e| {
+            ClaudeError::config_error(format!("Invalid {}: {}", config_path.display(), e))
+        })?;
+
+        let folder_name = root.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let expand = |value: &str| {
+            value
+                .replace("${localWorkspaceFolderBasename}", &folder_name)
+                .replace("${localWorkspaceFolder}", &root.to_string_lossy())
+        };
+        Ok(Self {
+            config_path: config_path.to_path_buf(),
+            local_folder: root.to_path_buf(),
+            name: file.name.unwrap_or_else(|| folder_name.clone()),
+            workspace_folder: file
+                .workspace_folder
+                .map(|folder| expand(&folder))
+                .unwrap_or_else(|| format!("/workspaces/{}", folder_name)),
+            user: file.remote_user.or(file.container_user),
+            env: file
+                .remote_env
+                .into_iter()
+                .filter_map(|(key, value)| value.as_str().map(|v| (key, expand(v))))
+                .collect(),
+        })
+    }
+
+    /// 查找为该项目启动的容器（VS Code 和 devcontainer CLI 都会打上 `devcontainer.local_folder` 标签）
+    pub async fn find_running(&self) -> Result<Option<String>> {
+        if super::tooling::find_executable("docker").is_none() {
+            return Ok(None);
+        }
+        let output = Command::new("docker")
+            .args(["ps", "--quiet", "--filter"])
+            .arg(format!("label=devcontainer.local_folder={}", self.local_folder.display()))
+            .output()
+            .await?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(ClaudeError::General(format!("docker ps failed: {}", error.trim())));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).lines().next().map(|id| id.trim().to_string()))
+    }
+}
+
+/// 去掉 JSONC 中的注释和结尾逗号
+fn strip_jsonc(content: &str) -> String {
+    // 先去掉注释，再去掉 `}` 或 `]` 之前的逗号；两步都跳过字符串内容
+    let mut without_comments = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        match (in_string, c, chars.peek()) {
+            (true, '\\', _) => {
+                without_comments.push(c);
+                without_comments.extend(chars.next());
+            }
+            (_, '"', _) => {
+                in_string = !in_string;
+                without_comments.push(c);
+            }
+            (false, '/', Some('/')) => {
+                while chars.peek().is_some_and(|&next| next != '\n') {
+                    chars.next();
+                }
+            }
+            (false, '/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for next in chars.by_ref() {
+                    if previous == '*' && next == '/' {
+                        break;
+                    }
+                    previous = next;
+                }
+            }
+            _ => without_comments.push(c),
+        }
+    }
+
+    let mut output = String::with_capacity(without_comments.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in without_comments.char_indices() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' && matches!(without_comments[index + 1..].trim_start().chars().next(), Some('}' | ']')) {
+            continue;
+        }
+        output.push(c);
+    }
+    output
+}
+
+/// 工具命令的执行位置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ExecutionTarget {
+    /// 本机
+    #[default]
+    Host,
+    /// 正在运行的开发容器
+    DevContainer {
+        container_id: String,
+        container: DevContainer,
+    },
+}
+
+/// 在 CLI、工具和界面之间共享的执行位置
+pub type SharedExecutionTarget = Arc<RwLock<ExecutionTarget>>;
+
+impl ExecutionTarget {
+    /// 状态栏中显示的名称
+    pub fn label(&self) -> String {
+        match self {
+            Self::Host => "host".to_string(),
+            Self::DevContainer { container, .. } => format!("devcontainer: {}", container.name),
+        }
+    }
+
+    /// 在 `cwd` 中用 bash 执行 `command` 的程序和参数
+    pub fn shell_command(&self, command: &str, cwd: &Path) -> (String, Vec<String>) {
+        let Self::DevContainer { container_id, container } = self else {
+            return ("bash".to_string(), vec!["-c".to_string(), command.to_string()]);
+        };
+
+        let mut args = vec!["exec".to_string(), "-i".to_string(), "-w".to_string(), container_path(container, cwd)];
+        if let Some(user) = &container.user {
+            args.extend(["-u".to_string(), user.clone()]);
+        }
+        let mut env: Vec<_> = container.env.iter().collect();
+        env.sort();
+        for (key, value) in env {
+            args.extend(["-e".to_string(), format!("{}={}", key, value)]);
+        }
+        args.extend([container_id.clone(), "bash".to_string(), "-c".to_string(), command.to_string()]);
+        ("docker".to_string(), args)
+    }
+}
+
+/// 把本机路径映射为容器中的路径；项目之外的目录映射到容器的工作区根目录
+fn container_path(container: &DevContainer, cwd: &Path) -> String {
+    match cwd.strip_prefix(&container.local_folder) {
+        Ok(relative) if relative.as_os_str().is_empty() => container.workspace_folder.clone(),
+        Ok(relative) => format!(
+            "{}/{}",
+            container.workspace_folder.trim_end_matches('/'),
+            relative.to_string_lossy().replace('\\', "/")
+        ),
+        Err(_) => container.workspace_folder.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_jsonc_config() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().join("webapp");
+        std::fs::create_dir_all(root.join(".devcontainer")).unwrap();
+        std::fs::write(
+            root.join(".devcontainer").join("devcontainer.json"),
+            r#"{
+                // Rust toolchain
+                "name": "Rust // dev",
+                "image": "mcr.microsoft.com/devcontainers/rust:1",
+                /* mounted by VS Code */
+                "workspaceFolder": "/src/${localWorkspaceFolderBasename}",
+                "remoteUser": "vscode",
+                "remoteEnv": { "CARGO_TERM_COLOR": "always", "UNSET": null, },
+            }"#,
+        )
+        .unwrap();
+
+        let container = DevContainer::detect(&root).unwrap().unwrap();
+        assert_eq!(container.name, "Rust // dev");
+        assert_eq!(container.workspace_folder, "/src/webapp");
+        assert_eq!(container.user.as_deref(), Some("vscode"));
+        assert_eq!(container.env.len(), 1);
+
+        assert!(DevContainer::detect(temp_dir.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_commands_run_through_docker_exec() {
+        let container = DevContainer {
+            config_path: PathBuf::from("/home/me/app/.devcontainer.json"),
+            local_folder: PathBuf::from("/home/me/app"),
+            name: "app".to_string(),
+            workspace_folder: "/workspaces/app".to_string(),
+            user: None,
+            env: HashMap::new(),
+        };
+        assert_eq!(ExecutionTarget::Host.shell_command("ls", Path::new("/tmp")).0, "bash");
+
+        let target = ExecutionTarget::DevContainer { container_id: "abc123".to_string(), container };
+        let (program, args) = target.shell_command("cargo test", Path::new("/home/me/app/crates/core"));
+        assert_eq!(program, "docker");
+        assert_eq!(args, vec!["exec", "-i", "-w", "/workspaces/app/crates/core", "abc123", "bash", "-c", "cargo test"]);
+        assert_eq!(target.label(), "devcontainer: app");
+    }
+}
@@ -0,0 +1,516 @@
+//! 子进程资源限制
+//!
+//! 为代理启动的命令设置 CPU、内存、进程数和文件大小上限：Unix 使用 rlimit，
+//! Linux 在 cgroup v2 已委派给当前用户时额外使用 cgroup，Windows 使用 Job Object。
+//! 命令因触及上限而终止时给出说明，工具结果中据此告诉模型命令失败的原因
+
+use serde::{Deserialize, Serialize};
+use std::process::ExitStatus;
+use tokio::process::{Child, Command};
+
+/// 资源上限，未设置的项不限制
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// CPU 时间上限（秒）
+    #[serde(default)]
+    pub cpu_seconds: Option<u64>,
+    /// 内存上限（字节）
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+    /// 同时存在的进程数上限（Linux cgroup 和 Windows 有效）
+    #[serde(default)]
+    pub max_processes: Option<u64>,
+    /// 单个写入文件的大小上限（字节，仅 Unix）
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// 是否没有设置任何上限
+    pub fn is_empty(&self) -> bool {
+        self.cpu_seconds.is_none()
+            && self.memory_bytes.is_none()
+            && self.max_processes.is_none()
+            && self.max_file_size.is_none()
+    }
+}
+
+/// 触及的资源类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKind {
+    Cpu,
+    Memory,
+    Processes,
+    FileSize,
+}
+
+/// 命令因资源上限被终止的说明
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitViolation {
+    /// 资源类型
+    pub kind: LimitKind,
+    /// 配置的上限值（秒、字节或进程数）
+    pub limit: u64,
+    /// 给用户和模型的说明
+    pub message: String,
+}
+
+impl LimitViolation {
+    fn new(kind: LimitKind, limit: u64) -> Self {
+        let message = match kind {
+            LimitKind::Cpu => format!("Command was killed after exceeding its CPU time limit of {}s", limit),
+            LimitKind::Memory => format!("Command ran out of memory: it is limited to {}", format_bytes(limit)),
+            LimitKind::Processes => format!("Command hit the limit of {} concurrent processes", limit),
+            LimitKind::FileSize => format!("Command tried to write a file larger than the {} limit", format_bytes(limit)),
+        };
+        Self { kind, limit, message }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const MIB: u64 = 1024 * 1024;
+    if bytes >= MIB && bytes.is_multiple_of(MIB) {
+        format!("{} MiB", bytes / MIB)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+/// 内存不足时常见的错误输出
+const OUT_OF_MEMORY_MARKERS: &[&str] = &[
+    "cannot allocate memory",
+    "out of memory",
+    "memoryerror",
+    "memory allocation",
+    "bad_alloc",
+];
+
+/// 施加在单个子进程上的限制，进程结束后用于判断是否触及上限
+pub struct LimitGuard {
+    limits: ResourceLimits,
+    #[cfg(target_os = "linux")]
+    cgroup: Option<cgroup::Scope>,
+    #[cfg(windows)]
+    job: Option<job::Job>,
+}
+
+impl LimitGuard {
+    /// 在启动前为命令配置限制
+    pub fn apply(cmd: &mut Command, limits: &ResourceLimits) -> Self {
+        #[cfg(target_os = "linux")]
+        let cgroup = cgroup::Scope::create(limits);
+
+        #[cfg(unix)]
+        {
+            #[cfg(target_os = "linux")]
+            let (procs_file, memory_in_cgroup) = match &cgroup {
+                Some(scope) => (Some(scope.procs_file()), scope.limits_memory()),
+                None => (None, false),
+            };
+            #[cfg(not(target_os = "linux"))]
+            let (procs_file, memory_in_cgroup): (Option<std::ffi::CString>, bool) = (None, false);
+
+            // cgroup 已限制内存时不再设置 RLIMIT_AS，很多运行时会预留远超实际使用的虚拟地址空间
+            let rlimits = rlimit::Plan {
+                cpu_seconds: limits.cpu_seconds,
+                address_space: limits.memory_bytes.filter(|_| !memory_in_cgroup),
+                file_size: limits.max_file_size,
+            };
+            if procs_file.is_some() || !rlimits.is_empty() {
+                // SAFETY: 闭包只调用异步信号安全的系统调用，不分配内存也不获取锁
+                unsafe {
+                    cmd.pre_exec(move || {
+                        if let Some(procs) = &procs_file {
+                            rlimit::join_cgroup(procs);
+                        }
+                        rlimits.apply()
+                    });
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = cmd;
+
+        Self {
+            limits: limits.clone(),
+            #[cfg(target_os = "linux")]
+            cgroup,
+            #[cfg(windows)]
+            job: job::Job::create(limits),
+        }
+    }
+
+    /// 启动后把子进程纳入限制（Windows 加入 Job Object）
+    pub fn attach(&mut self, child: &Child) {
+        #[cfg(windows)]
+        if let (Some(job), Some(handle)) = (&self.job, child.raw_handle()) {
+            if let Err(e) = job.assign(handle) {
+                tracing::warn!("Failed to apply resource limits to process: {}", e);
+            }
+        }
+        #[cfg(not(windows))]
+        let _ = child;
+    }
+
+    /// 根据退出状态和错误输出判断命令是否因触及上限而终止
+    pub fn check(&self, status: &ExitStatus, stderr: &str) -> Option<LimitViolation> {
+        if status.success() || self.limits.is_empty() {
+            return None;
+        }
+        let violation = |kind, limit: Option<u64>| limit.map(|limit| LimitViolation::new(kind, limit));
+
+        #[cfg(target_os = "linux")]
+        if let Some(scope) = &self.cgroup {
+            if scope.oom_killed() {
+                return violation(LimitKind::Memory, self.limits.memory_bytes);
+            }
+            if scope.pids_exhausted() {
+                return violation(LimitKind::Processes, self.limits.max_processes);
+            }
+        }
+
+        #[cfg(windows)]
+        if let Some(job) = &self.job {
+            if let Some(kind) = job.violation(status, &self.limits) {
+                let limit = match kind {
+                    LimitKind::Cpu => self.limits.cpu_seconds,
+                    LimitKind::Memory => self.limits.memory_bytes,
+                    LimitKind::Processes => self.limits.max_processes,
+                    LimitKind::FileSize => self.limits.max_file_size,
+                };
+                return violation(kind, limit);
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+
+            // 经 shell 执行时，被信号终止的子命令表现为 128 + 信号值的退出码
+            let signal = status.signal().or_else(|| status.code().filter(|code| *code > 128).map(|code| code - 128));
+            match signal {
+                Some(libc::SIGXCPU) => return violation(LimitKind::Cpu, self.limits.cpu_seconds),
+                Some(libc::SIGXFSZ) => return violation(LimitKind::FileSize, self.limits.max_file_size),
+                Some(libc::SIGKILL) if self.limits.cpu_seconds.is_some() && self.limits.memory_bytes.is_none() => {
+                    return violation(LimitKind::Cpu, self.limits.cpu_seconds);
+                }
+                _ => {}
+            }
+        }
+
+        let stderr = stderr.to_lowercase();
+        if OUT_OF_MEMORY_MARKERS.iter().any(|marker| stderr.contains(marker)) {
+            return violation(LimitKind::Memory, self.limits.memory_bytes);
+        }
+        if stderr.contains("fork") && stderr.contains("resource temporarily unavailable") {
+            return violation(LimitKind::Processes, self.limits.max_processes);
+        }
+        None
+    }
+}
+
+#[cfg(unix)]
+mod rlimit {
+    use std::ffi::CString;
+    use std::io;
+
+    /// 需要在子进程中设置的 rlimit
+    #[derive(Clone, Copy)]
+    pub struct Plan {
+        pub cpu_seconds: Option<u64>,
+        pub address_space: Option<u64>,
+        pub file_size: Option<u64>,
+    }
+
+    impl Plan {
+        pub fn is_empty(&self) -> bool {
+            self.cpu_seconds.is_none() && self.address_space.is_none() && self.file_size.is_none()
+        }
+
+        /// 在 fork 之后、exec 之前调用
+        pub fn apply(&self) -> io::Result<()> {
+            // 软上限触发 SIGXCPU，再过一秒由硬上限强制终止
+            if let Some(seconds) = self.cpu_seconds {
+                set(libc::RLIMIT_CPU, seconds, seconds.saturating_add(1))?;
+            }
+            if let Some(bytes) = self.address_space {
+                set(libc::RLIMIT_AS, bytes, bytes)?;
+            }
+            if let Some(bytes) = self.file_size {
+                set(libc::RLIMIT_FSIZE, bytes, bytes)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    type Resource = libc::__rlimit_resource_t;
+    #[cfg(not(target_os = "linux"))]
+    type Resource = libc::c_int;
+
+    /// 只降低上限：已有的硬上限更低时保留原值
+    fn set(resource: Resource, soft: u64, hard: u64) -> io::Result<()> {
+        let mut current = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        // SAFETY: 传入有效的 rlimit 指针
+        if unsafe { libc::getrlimit(resource, &mut current) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let hard = (hard as libc::rlim_t).min(current.rlim_max);
+        let limit = libc::rlimit {
+            rlim_cur: (soft as libc::rlim_t).min(hard),
+            rlim_max: hard,
+        };
+        // SAFETY: 传入有效的 rlimit 指针
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// 把当前进程加入 cgroup，失败时退回只使用 rlimit
+    pub fn join_cgroup(procs: &CString) {
+        // SAFETY: 路径为有效的 C 字符串，写入的缓冲区长度正确
+        unsafe {
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd >= 0 {
+                libc::write(fd, b"0".as_ptr().cast(), 1);
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod cgroup {
+    use super::ResourceLimits;
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+    static NEXT_SCOPE: AtomicU64 = AtomicU64::new(0);
+
+    /// 为单个命令创建的 cgroup v2 子组
+    pub struct Scope {
+        dir: PathBuf,
+        memory: bool,
+    }
+
+    impl Scope {
+        /// 当前 cgroup 已把所需控制器委派给子组时创建，否则返回 `None`
+        pub fn create(limits: &ResourceLimits) -> Option<Self> {
+            if limits.memory_bytes.is_none() && limits.max_processes.is_none() {
+                return None;
+            }
+            let membership = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+            let relative = membership.lines().find_map(|line| line.strip_prefix("0::"))?;
+            let parent = Path::new(CGROUP_ROOT).join(relative.trim_start_matches('/'));
+            let controllers = std::fs::read_to_string(parent.join("cgroup.subtree_control")).ok()?;
+            let available = |name: &str| controllers.split_whitespace().any(|c| c == name);
+            if (limits.memory_bytes.is_some() && !available("memory"))
+                || (limits.max_processes.is_some() && !available("pids"))
+            {
+                return None;
+            }
+
+            let dir = parent.join(format!(
+                "claude-tool-{}-{}",
+                std::process::id(),
+                NEXT_SCOPE.fetch_add(1, Ordering::Relaxed)
+            ));
+            std::fs::create_dir(&dir).ok()?;
+            let scope = Self { dir, memory: limits.memory_bytes.is_some() };
+
+            let mut settings = Vec::new();
+            if let Some(bytes) = limits.memory_bytes {
+                settings.push(("memory.max", bytes.to_string()));
+                settings.push(("memory.swap.max", "0".to_string()));
+            }
+            if let Some(count) = limits.max_processes {
+                settings.push(("pids.max", count.to_string()));
+            }
+            for (file, value) in settings {
+                if let Err(e) = std::fs::write(scope.dir.join(file), value) {
+                    tracing::debug!("cgroup {} unavailable, falling back to rlimits: {}", file, e);
+                    return None;
+                }
+            }
+            Some(scope)
+        }
+
+        pub fn procs_file(&self) -> CString {
+            CString::new(self.dir.join("cgroup.procs").as_os_str().as_bytes()).unwrap_or_default()
+        }
+
+        pub fn limits_memory(&self) -> bool {
+            self.memory
+        }
+
+        pub fn oom_killed(&self) -> bool {
+            self.event_count("memory.events", "oom_kill") > 0
+        }
+
+        pub fn pids_exhausted(&self) -> bool {
+            self.event_count("pids.events", "max") > 0
+        }
+
+        fn event_count(&self, file: &str, key: &str) -> u64 {
+            std::fs::read_to_string(self.dir.join(file))
+                .ok()
+                .and_then(|content| {
+                    content.lines().find_map(|line| {
+                        let (name, count) = line.split_once(' ')?;
+                        (name == key).then(|| count.trim().parse().ok()).flatten()
+                    })
+                })
+                .unwrap_or(0)
+        }
+    }
+
+    impl Drop for Scope {
+        fn drop(&mut self) {
+            // 仍有残留进程时目录无法删除，交由系统在进程退出后回收
+            let _ = std::fs::remove_dir(&self.dir);
+        }
+    }
+}
+
+#[cfg(windows)]
+mod job {
+    use super::{LimitKind, ResourceLimits};
+    use std::os::windows::io::RawHandle;
+    use std::process::ExitStatus;
+    use windows_sys::Win32::Foundation::{CloseHandle, ERROR_NOT_ENOUGH_QUOTA, HANDLE};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, QueryInformationJobObject,
+        SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_ACTIVE_PROCESS,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE, JOB_OBJECT_LIMIT_PROCESS_MEMORY, JOB_OBJECT_LIMIT_PROCESS_TIME,
+    };
+
+    /// 100 纳秒为单位的每秒刻度数
+    const TICKS_PER_SECOND: i64 = 10_000_000;
+
+    pub struct Job {
+        handle: HANDLE,
+    }
+
+    // SAFETY: Job Object 句柄可以在线程间共享使用
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    impl Job {
+        pub fn create(limits: &ResourceLimits) -> Option<Self> {
+            if limits.cpu_seconds.is_none() && limits.memory_bytes.is_none() && limits.max_processes.is_none() {
+                return None;
+            }
+            // SAFETY: 空名称和默认安全属性创建匿名 Job Object
+            let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+            if handle == 0 {
+                return None;
+            }
+            let job = Self { handle };
+
+            // SAFETY: 结构体全零是合法的初始值
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            if let Some(seconds) = limits.cpu_seconds {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+                info.BasicLimitInformation.PerProcessUserTimeLimit = seconds as i64 * TICKS_PER_SECOND;
+            }
+            if let Some(bytes) = limits.memory_bytes {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                info.ProcessMemoryLimit = bytes as usize;
+            }
+            if let Some(count) = limits.max_processes {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_ACTIVE_PROCESS;
+                info.BasicLimitInformation.ActiveProcessLimit = count as u32;
+            }
+            // SAFETY: 传入与信息类别匹配的结构体及其大小
+            let ok = unsafe {
+                SetInformationJobObject(
+                    job.handle,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const _,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                )
+            };
+            (ok != 0).then_some(job)
+        }
+
+        pub fn assign(&self, process: RawHandle) -> std::io::Result<()> {
+            // SAFETY: 句柄来自仍在运行的子进程
+            if unsafe { AssignProcessToJobObject(self.handle, process as HANDLE) } == 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        pub fn violation(&self, status: &ExitStatus, limits: &ResourceLimits) -> Option<LimitKind> {
+            if limits.cpu_seconds.is_some() && status.code() == Some(ERROR_NOT_ENOUGH_QUOTA as i32) {
+                return Some(LimitKind::Cpu);
+            }
+            let limit = limits.memory_bytes?;
+            // SAFETY: 结构体全零是合法的初始值，查询写入不超过给定大小
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+            let ok = unsafe {
+                QueryInformationJobObject(
+                    self.handle,
+                    JobObjectExtendedLimitInformation,
+                    &mut info as *mut _ as *mut _,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                    std::ptr::null_mut(),
+                )
+            };
+            (ok != 0 && info.PeakProcessMemoryUsed as u64 >= limit).then_some(LimitKind::Memory)
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: 句柄由 CreateJobObjectW 创建且只关闭一次
+            unsafe {
+                CloseHandle(self.handle);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    async fn run(script: &str, limits: &ResourceLimits) -> Option<LimitViolation> {
+        let mut cmd = Command::new("bash");
+        cmd.arg("-c").arg(script);
+        let mut guard = LimitGuard::apply(&mut cmd, limits);
+        let child = cmd.stderr(std::process::Stdio::piped()).spawn().unwrap();
+        guard.attach(&child);
+        let output = child.wait_with_output().await.unwrap();
+        guard.check(&output.status, &String::from_utf8_lossy(&output.stderr))
+    }
+
+    #[tokio::test]
+    async fn test_cpu_limit_reported() {
+        let limits = ResourceLimits { cpu_seconds: Some(1), ..Default::default() };
+        let violation = run("while :; do :; done", &limits).await.unwrap();
+        assert_eq!(violation.kind, LimitKind::Cpu);
+        assert!(violation.message.contains("CPU time limit of 1s"));
+
+        assert!(run("exit 3", &limits).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_size_limit_reported() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let target = temp_dir.path().join("big.bin");
+        let limits = ResourceLimits { max_file_size: Some(1024 * 1024), ..Default::default() };
+
+        let script = format!("head -c 2097152 /dev/zero > '{}'", target.display());
+        let violation = run(&script, &limits).await.unwrap();
+        assert_eq!(violation.kind, LimitKind::FileSize);
+        assert!(violation.message.contains("1 MiB"));
+        assert_eq!(std::fs::metadata(&target).unwrap().len(), 1024 * 1024);
+    }
+}
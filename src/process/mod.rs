@@ -2,23 +2,41 @@
 //! 
 //! 实现子进程启动、监控、通信和资源管理
 
+pub mod devcontainer;
+pub mod limits;
+pub mod tooling;
+
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::process::Stdio;
-use std::sync::{Arc, Mutex};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
 use tokio::sync::mpsc;
-use tokio::time::{timeout, Duration};
+use tokio::time::{timeout, Duration, Instant};
 
 use crate::error::{ClaudeError, Result};
+use crate::locks::{rank, BlockingMutex};
+use limits::{LimitGuard, LimitViolation, ResourceLimits};
+
+/// `run` 登记在进程表中的条目，离开作用域时移除
+struct TableEntry<'a> {
+    table: &'a BlockingMutex<HashMap<String, ProcessInstance>>,
+    id: String,
+}
+
+impl Drop for TableEntry<'_> {
+    fn drop(&mut self) {
+        self.table.lock().remove(&self.id);
+    }
+}
 
 /// 进程管理器
 pub struct ProcessManager {
     /// 运行中的进程
-    processes: Arc<Mutex<HashMap<String, ProcessInstance>>>,
+    processes: Arc<BlockingMutex<HashMap<String, ProcessInstance>>>,
     /// 下一个进程ID
-    next_id: Arc<Mutex<u32>>,
+    next_id: Arc<BlockingMutex<u32>>,
 }
 
 /// 进程实例
@@ -39,6 +57,10 @@ pub struct ProcessInstance {
     pub stderr_receiver: Option<mpsc::UnboundedReceiver<String>>,
     /// 退出码
     pub exit_code: Option<i32>,
+    /// 施加的资源限制
+    pub limit_guard: Option<LimitGuard>,
+    /// 进程因触及资源上限而终止的说明
+    pub limit_violation: Option<LimitViolation>,
 }
 
 /// 进程配置
@@ -60,6 +82,9 @@ pub struct ProcessConfig {
     pub capture_output: bool,
     /// 是否自动重启
     pub auto_restart: bool,
+    /// 资源上限
+    #[serde(default)]
+    pub limits: ResourceLimits,
 }
 
 /// 进程状态
@@ -87,14 +112,72 @@ pub struct ProcessOutput {
     pub stdout: Vec<String>,
     pub stderr: Vec<String>,
     pub exit_code: Option<i32>,
+    /// 进程因触及资源上限而终止的说明
+    pub limit_violation: Option<LimitViolation>,
+}
+
+/// 运行到结束的命令的结果
+#[derive(Debug, Clone)]
+pub struct CompletedProcess {
+    pub stdout: String,
+    pub stderr: String,
+    /// 被信号终止或超时时为 `None`
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    /// 超时后被终止
+    pub timed_out: bool,
+    /// 超出输出上限而省略的字节数（两个流合计）
+    pub truncated_bytes: usize,
+    pub duration: Duration,
+    /// 进程因触及资源上限而终止的说明
+    pub limit_violation: Option<LimitViolation>,
+}
+
+/// 超时终止进程后，等待仍持有输出管道的子孙进程的时间
+const OUTPUT_DRAIN_GRACE: Duration = Duration::from_secs(1);
+
+/// 读取一个输出流，超过 `max_bytes` 时保留开头和结尾各一半，中间替换为省略说明。
+/// 返回文本和省略的字节数
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, max_bytes: usize) -> (String, usize) {
+    let tail_limit = max_bytes / 2;
+    let head_limit = max_bytes - tail_limit;
+    let mut head = Vec::new();
+    let mut tail = VecDeque::new();
+    let mut total = 0;
+    let mut buf = [0u8; 8192];
+    while let Ok(n) = reader.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        total += n;
+        let take = head_limit.saturating_sub(head.len()).min(n);
+        head.extend_from_slice(&buf[..take]);
+        tail.extend(&buf[take..n]);
+        let excess = tail.len().saturating_sub(tail_limit);
+        tail.drain(..excess);
+    }
+
+    let omitted = total - head.len() - tail.len();
+    let mut text = String::from_utf8_lossy(&head).into_owned();
+    if omitted > 0 {
+        text.push_str(&format!("\n... [{} bytes truncated] ...\n", omitted));
+    }
+    text.push_str(&String::from_utf8_lossy(tail.make_contiguous()));
+    (text, omitted)
+}
+
+impl Default for ProcessManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProcessManager {
     /// 创建新的进程管理器
     pub fn new() -> Self {
         Self {
-            processes: Arc::new(Mutex::new(HashMap::new())),
-            next_id: Arc::new(Mutex::new(1)),
+            processes: Arc::new(BlockingMutex::new("process.table", rank::PROCESS_TABLE, HashMap::new())),
+            next_id: Arc::new(BlockingMutex::new("process.next_id", rank::PROCESS_ID, 1)),
         }
     }
 
@@ -106,7 +189,7 @@ impl ProcessManager {
         
         // 检查进程是否已存在
         {
-            let processes = self.processes.lock().unwrap();
+            let processes = self.processes.lock();
             if processes.contains_key(&process_id) {
                 return Err(ClaudeError::General(format!(
                     "Process with ID '{}' already exists", process_id
@@ -124,6 +207,8 @@ impl ProcessManager {
             stdout_receiver: None,
             stderr_receiver: None,
             exit_code: None,
+            limit_guard: None,
+            limit_violation: None,
         };
 
         // 构建命令
@@ -151,10 +236,15 @@ impl ProcessManager {
                 .stderr(Stdio::inherit());
         }
 
+        // 设置资源限制
+        let mut limit_guard = LimitGuard::apply(&mut cmd, &config.limits);
+
         // 启动子进程
         let mut child = cmd.spawn().map_err(|e| {
             ClaudeError::General(format!("Failed to start process '{}': {}", config.name, e))
         })?;
+        limit_guard.attach(&child);
+        instance.limit_guard = Some(limit_guard);
 
         // 设置通信通道
         if config.capture_output {
@@ -241,7 +331,7 @@ impl ProcessManager {
 
         // 存储进程实例
         {
-            let mut processes = self.processes.lock().unwrap();
+            let mut processes = self.processes.lock();
             processes.insert(process_id.clone(), instance);
         }
 
@@ -252,12 +342,101 @@ impl ProcessManager {
         Ok(process_id)
     }
 
+    /// 运行命令直到结束并捕获输出，每个流最多保留 `max_output_bytes` 字节。
+    /// 超过 `config.timeout` 时终止进程；运行期间命令出现在进程列表中
+    pub async fn run(&self, config: ProcessConfig, max_output_bytes: usize) -> Result<CompletedProcess> {
+        let process_id = self.generate_process_id();
+        tracing::debug!("Running '{}' as {}", config.name, process_id);
+
+        let mut cmd = Command::new(&config.command);
+        cmd.args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(working_dir) = &config.working_dir {
+            cmd.current_dir(working_dir);
+        }
+        let mut limit_guard = LimitGuard::apply(&mut cmd, &config.limits);
+
+        let started = Instant::now();
+        let mut child = cmd.spawn().map_err(|e| {
+            ClaudeError::General(format!("Failed to start process '{}': {}", config.name, e))
+        })?;
+        limit_guard.attach(&child);
+        let stdout = child.stdout.take().map(|out| tokio::spawn(read_capped(out, max_output_bytes)));
+        let stderr = child.stderr.take().map(|err| tokio::spawn(read_capped(err, max_output_bytes)));
+
+        // 调用方丢弃 future（工具超时或被取消）时 kill_on_drop 结束子进程，这里负责移除表项
+        let entry = TableEntry { table: &self.processes, id: process_id.clone() };
+        self.processes.lock().insert(process_id.clone(), ProcessInstance {
+            id: process_id.clone(),
+            config: config.clone(),
+            child: None,
+            status: ProcessStatus::Running,
+            stdin_sender: None,
+            stdout_receiver: None,
+            stderr_receiver: None,
+            exit_code: None,
+            limit_guard: None,
+            limit_violation: None,
+        });
+        let status = match config.timeout {
+            Some(secs) => match timeout(Duration::from_secs(secs), child.wait()).await {
+                Ok(status) => Some(status),
+                Err(_) => {
+                    if let Err(e) = child.kill().await {
+                        tracing::warn!("Failed to kill timed out process '{}': {}", config.name, e);
+                    }
+                    None
+                }
+            },
+            None => Some(child.wait().await),
+        };
+        drop(entry);
+        let status = status.transpose()?;
+        let duration = started.elapsed();
+
+        // 正常结束时读完输出；超时时后台子进程可能仍持有管道，只再等一小会儿
+        let collect = |reader: Option<tokio::task::JoinHandle<(String, usize)>>| async {
+            let Some(reader) = reader else {
+                return (String::new(), 0);
+            };
+            let abort = reader.abort_handle();
+            let drained = match status {
+                Some(_) => Ok(reader.await),
+                None => timeout(OUTPUT_DRAIN_GRACE, reader).await,
+            };
+            match drained {
+                Ok(Ok(output)) => output,
+                _ => {
+                    abort.abort();
+                    (String::new(), 0)
+                }
+            }
+        };
+        let (stdout, stdout_truncated) = collect(stdout).await;
+        let (stderr, stderr_truncated) = collect(stderr).await;
+
+        Ok(CompletedProcess {
+            exit_code: status.and_then(|s| s.code()),
+            success: status.is_some_and(|s| s.success()),
+            timed_out: status.is_none(),
+            truncated_bytes: stdout_truncated + stderr_truncated,
+            duration,
+            limit_violation: status.and_then(|s| limit_guard.check(&s, &stderr)),
+            stdout,
+            stderr,
+        })
+    }
+
     /// 停止进程
     pub async fn stop_process(&self, process_id: &str) -> Result<()> {
         tracing::info!("Stopping process: {}", process_id);
 
         let mut instance = {
-            let mut processes = self.processes.lock().unwrap();
+            let mut processes = self.processes.lock();
             processes.remove(process_id).ok_or_else(|| {
                 ClaudeError::General(format!("Process '{}' not found", process_id))
             })?
@@ -291,7 +470,7 @@ impl ProcessManager {
 
     /// 发送输入到进程
     pub async fn send_input(&self, process_id: &str, input: &str) -> Result<()> {
-        let processes = self.processes.lock().unwrap();
+        let processes = self.processes.lock();
         let instance = processes.get(process_id).ok_or_else(|| {
             ClaudeError::General(format!("Process '{}' not found", process_id))
         })?;
@@ -317,7 +496,7 @@ impl ProcessManager {
 
     /// 获取进程输出
     pub async fn get_process_output(&self, process_id: &str) -> Result<ProcessOutput> {
-        let mut processes = self.processes.lock().unwrap();
+        let mut processes = self.processes.lock();
         let instance = processes.get_mut(process_id).ok_or_else(|| {
             ClaudeError::General(format!("Process '{}' not found", process_id))
         })?;
@@ -343,18 +522,19 @@ impl ProcessManager {
             stdout,
             stderr,
             exit_code: instance.exit_code,
+            limit_violation: instance.limit_violation.clone(),
         })
     }
 
     /// 获取进程状态
     pub fn get_process_status(&self, process_id: &str) -> Option<ProcessStatus> {
-        let processes = self.processes.lock().unwrap();
+        let processes = self.processes.lock();
         processes.get(process_id).map(|instance| instance.status.clone())
     }
 
     /// 列出所有进程
     pub fn list_processes(&self) -> Vec<(String, ProcessStatus)> {
-        let processes = self.processes.lock().unwrap();
+        let processes = self.processes.lock();
         processes.iter()
             .map(|(id, instance)| (id.clone(), instance.status.clone()))
             .collect()
@@ -399,7 +579,7 @@ impl ProcessManager {
 
     /// 生成进程ID
     fn generate_process_id(&self) -> String {
-        let mut next_id = self.next_id.lock().unwrap();
+        let mut next_id = self.next_id.lock();
         let id = format!("proc_{}", *next_id);
         *next_id += 1;
         id
@@ -415,12 +595,19 @@ impl ProcessManager {
                 tokio::time::sleep(Duration::from_secs(1)).await;
                 
                 let should_break = {
-                    let mut processes_guard = processes.lock().unwrap();
+                    let mut processes_guard = processes.lock();
                     if let Some(instance) = processes_guard.get_mut(&process_id) {
                         if let Some(child) = &mut instance.child {
                             match child.try_wait() {
                                 Ok(Some(status)) => {
                                     instance.exit_code = status.code();
+                                    instance.limit_violation = instance
+                                        .limit_guard
+                                        .as_ref()
+                                        .and_then(|guard| guard.check(&status, ""));
+                                    if let Some(violation) = &instance.limit_violation {
+                                        tracing::warn!("Process '{}': {}", process_id, violation.message);
+                                    }
                                     instance.status = ProcessStatus::Stopped;
                                     tracing::info!("Process '{}' finished with status: {:?}", process_id, status);
                                     true
@@ -0,0 +1,296 @@
+//! 外部工具检测与安装
+//!
+//! 检查 git、ripgrep 和（MCP 服务器需要的）Node.js 是否可用，
+//! 识别系统包管理器并生成对应的安装命令。安装通过 ProcessManager 执行
+
+use std::path::{Path, PathBuf};
+
+use super::{ProcessConfig, ProcessManager, ProcessOutput};
+use crate::config::ClaudeConfig;
+use crate::error::Result;
+
+/// 单个包的安装超时（秒）
+const INSTALL_TIMEOUT_SECS: u64 = 15 * 60;
+
+/// 需要检查的外部工具
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Git,
+    Ripgrep,
+    Node,
+}
+
+impl Tool {
+    /// 可执行文件名
+    pub fn binary(&self) -> &'static str {
+        match self {
+            Self::Git => "git",
+            Self::Ripgrep => "rg",
+            Self::Node => "node",
+        }
+    }
+
+    /// 显示名称
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Git => "git",
+            Self::Ripgrep => "ripgrep",
+            Self::Node => "Node.js",
+        }
+    }
+
+    /// 用途说明
+    pub fn purpose(&self) -> &'static str {
+        match self {
+            Self::Git => "version control, diffs and branch-aware sessions",
+            Self::Ripgrep => "fast code search",
+            Self::Node => "MCP servers started with node/npx",
+        }
+    }
+}
+
+/// 一个工具的检测结果
+#[derive(Debug, Clone)]
+pub struct ToolStatus {
+    pub tool: Tool,
+    /// 找到的可执行文件
+    pub path: Option<PathBuf>,
+    /// 缺少时是否影响当前配置（如配置了依赖 Node.js 的 MCP 服务器）
+    pub required: bool,
+}
+
+/// 检查所有工具；只有配置了以 node/npx/npm 启动的 MCP 服务器时 Node.js 才视为必需
+pub fn check_tools(config: &ClaudeConfig) -> Vec<ToolStatus> {
+    let node_required = config
+        .mcp_servers
+        .values()
+        .any(|server| matches!(program_name(&server.command).as_str(), "node" | "npx" | "npm"));
+
+    [(Tool::Git, true), (Tool::Ripgrep, true), (Tool::Node, node_required)]
+        .into_iter()
+        .map(|(tool, required)| ToolStatus { tool, path: find_executable(tool.binary()), required })
+        .collect()
+}
+
+fn program_name(command: &str) -> String {
+    Path::new(command)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// 在 PATH 中查找可执行文件
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let extensions: Vec<String> = if cfg!(windows) {
+        std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE;.CMD;.BAT".to_string())
+            .split(';')
+            .map(|ext| ext.to_string())
+            .collect()
+    } else {
+        vec![String::new()]
+    };
+
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .flat_map(|dir| extensions.iter().map(move |ext| dir.join(format!("{}{}", name, ext))))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// 系统包管理器
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Homebrew,
+    Apt,
+    Dnf,
+    Pacman,
+    Zypper,
+    Apk,
+    Winget,
+    Scoop,
+    Chocolatey,
+}
+
+impl PackageManager {
+    /// 按平台惯例的优先顺序检测第一个可用的包管理器
+    pub fn detect() -> Option<Self> {
+        let candidates: &[Self] = if cfg!(target_os = "macos") {
+            &[Self::Homebrew]
+        } else if cfg!(windows) {
+            &[Self::Winget, Self::Scoop, Self::Chocolatey]
+        } else {
+            &[Self::Apt, Self::Dnf, Self::Pacman, Self::Zypper, Self::Apk, Self::Homebrew]
+        };
+        candidates.iter().copied().find(|manager| find_executable(manager.binary()).is_some())
+    }
+
+    /// 包管理器的可执行文件名
+    pub fn binary(&self) -> &'static str {
+        match self {
+            Self::Homebrew => "brew",
+            Self::Apt => "apt-get",
+            Self::Dnf => "dnf",
+            Self::Pacman => "pacman",
+            Self::Zypper => "zypper",
+            Self::Apk => "apk",
+            Self::Winget => "winget",
+            Self::Scoop => "scoop",
+            Self::Chocolatey => "choco",
+        }
+    }
+
+    /// 工具在该包管理器中的包名
+    pub fn packages(&self, tool: Tool) -> Vec<&'static str> {
+        match (self, tool) {
+            (Self::Winget, Tool::Git) => vec!["Git.Git"],
+            (Self::Winget, Tool::Ripgrep) => vec!["BurntSushi.ripgrep.MSVC"],
+            (Self::Winget, Tool::Node) => vec!["OpenJS.NodeJS.LTS"],
+            (_, Tool::Git) => vec!["git"],
+            (_, Tool::Ripgrep) => vec!["ripgrep"],
+            (Self::Homebrew, Tool::Node) => vec!["node"],
+            (Self::Scoop, Tool::Node) => vec!["nodejs-lts"],
+            (Self::Chocolatey | Self::Dnf | Self::Zypper, Tool::Node) => vec!["nodejs"],
+            (Self::Apt | Self::Pacman | Self::Apk, Tool::Node) => vec!["nodejs", "npm"],
+        }
+    }
+
+    /// 是否需要管理员权限（Linux 系统包管理器）
+    fn needs_root(&self) -> bool {
+        matches!(self, Self::Apt | Self::Dnf | Self::Pacman | Self::Zypper | Self::Apk)
+    }
+
+    /// 安装工具的完整命令（程序和参数），非 root 用户通过 sudo 执行需要权限的命令
+    pub fn install_command(&self, tool: Tool) -> (String, Vec<String>) {
+        let packages = self.packages(tool).into_iter().map(str::to_string);
+        let mut args: Vec<String> = match self {
+            Self::Homebrew => vec!["install".into()],
+            Self::Apt => vec!["install".into(), "-y".into()],
+            Self::Dnf => vec!["install".into(), "-y".into()],
+            Self::Pacman => vec!["-S".into(), "--noconfirm".into(), "--needed".into()],
+            Self::Zypper => vec!["--non-interactive".into(), "install".into()],
+            Self::Apk => vec!["add".into()],
+            Self::Winget => vec!["install".into(), "-e".into(), "--accept-package-agreements".into(), "--id".into()],
+            Self::Scoop => vec!["install".into()],
+            Self::Chocolatey => vec!["install".into(), "-y".into()],
+        };
+        args.extend(packages);
+
+        if self.needs_root() && !is_root() {
+            args.insert(0, self.binary().to_string());
+            ("sudo".to_string(), args)
+        } else {
+            (self.binary().to_string(), args)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+/// 一次安装的结果
+#[derive(Debug, Clone)]
+pub struct InstallOutcome {
+    pub tool: Tool,
+    /// 执行的命令行
+    pub command_line: String,
+    /// 包管理器的退出码
+    pub exit_code: Option<i32>,
+    /// 安装后是否能找到可执行文件
+    pub available: bool,
+}
+
+impl InstallOutcome {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0) && self.available
+    }
+}
+
+/// 通过 ProcessManager 安装工具，输出直接显示在终端上
+pub async fn install_tool(processes: &ProcessManager, manager: PackageManager, tool: Tool) -> Result<InstallOutcome> {
+    let (command, args) = manager.install_command(tool);
+    let command_line = std::iter::once(command.as_str()).chain(args.iter().map(String::as_str)).collect::<Vec<_>>().join(" ");
+
+    let config = ProcessConfig {
+        name: format!("install-{}", tool.binary()),
+        command,
+        args,
+        env: std::collections::HashMap::new(),
+        working_dir: None,
+        timeout: Some(INSTALL_TIMEOUT_SECS),
+        capture_output: false,
+        auto_restart: false,
+        limits: Default::default(),
+    };
+    let process_id = processes.start_process(config).await?;
+    let ProcessOutput { exit_code, .. } = processes.wait_for_process(&process_id, Some(INSTALL_TIMEOUT_SECS)).await?;
+
+    Ok(InstallOutcome {
+        tool,
+        command_line,
+        exit_code,
+        available: find_executable(tool.binary()).is_some(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_commands() {
+        let (command, args) = PackageManager::Homebrew.install_command(Tool::Ripgrep);
+        assert_eq!((command.as_str(), args), ("brew", vec!["install".to_string(), "ripgrep".to_string()]));
+
+        let (command, args) = PackageManager::Apt.install_command(Tool::Node);
+        let expected_tail = vec!["install", "-y", "nodejs", "npm"];
+        if is_root() {
+            assert_eq!(command, "apt-get");
+            assert_eq!(args, expected_tail);
+        } else {
+            assert_eq!(command, "sudo");
+            assert_eq!(args[0], "apt-get");
+            assert_eq!(args[1..], expected_tail);
+        }
+
+        assert_eq!(PackageManager::Winget.packages(Tool::Git), vec!["Git.Git"]);
+    }
+
+    #[test]
+    fn test_node_required_only_for_node_mcp_servers() {
+        let mut config = ClaudeConfig::default();
+        let node = |statuses: Vec<ToolStatus>| statuses.into_iter().find(|s| s.tool == Tool::Node).unwrap();
+        assert!(!node(check_tools(&config)).required);
+
+        config.mcp_servers.insert(
+            "fs".to_string(),
+            crate::config::McpServerConfig {
+                name: "fs".to_string(),
+                command: "/usr/local/bin/npx".to_string(),
+                args: vec!["-y".to_string(), "@modelcontextprotocol/server-filesystem".to_string()],
+                env: Default::default(),
+                working_dir: None,
+                auto_start: true,
+                ..Default::default()
+            },
+        );
+        assert!(node(check_tools(&config)).required);
+    }
+}
@@ -0,0 +1,400 @@
+//! 提示模板库
+//!
+//! 模板是带 YAML 前言的 Markdown 文件，项目级放在 `.claude/prompts/<名称>.md`，用户级放在
+//! `~/.claude/prompts/<名称>.md`，同名时项目级优先。前言声明变量的类型、是否必填和默认值，
+//! 正文用 `{{变量}}` 引用。项目目录下的 `defaults.yaml` 可以为本项目覆盖变量默认值：
+//!
+//! ```yaml
+//! variables:        # 对所有模板生效
+//!   language: rust
+//! templates:        # 只对指定模板生效，优先于上面的公共默认值
+//!   review:
+//!     focus: security
+//! ```
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::error::{ClaudeError, Result};
+
+/// 项目级默认值文件名
+const DEFAULTS_FILE: &str = "defaults.yaml";
+
+/// 模板的保存位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptScope {
+    Project,
+    User,
+}
+
+impl std::fmt::Display for PromptScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Project => write!(f, "project"),
+            Self::User => write!(f, "user"),
+        }
+    }
+}
+
+/// 变量类型
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    #[default]
+    String,
+    Integer,
+    Number,
+    Boolean,
+    /// 相对当前目录已存在的路径
+    Path,
+    /// 取值限定在 `values` 中
+    Enum,
+}
+
+impl std::fmt::Display for VariableType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::String => "string",
+            Self::Integer => "integer",
+            Self::Number => "number",
+            Self::Boolean => "boolean",
+            Self::Path => "path",
+            Self::Enum => "enum",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 模板变量声明
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptVariable {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub kind: VariableType,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// 没有默认值的变量默认必填
+    #[serde(default)]
+    pub required: Option<bool>,
+    #[serde(default)]
+    pub default: Option<String>,
+    /// `enum` 类型的可选值
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+impl PromptVariable {
+    pub fn is_required(&self) -> bool {
+        self.required.unwrap_or(self.default.is_none())
+    }
+
+    /// 检查取值是否符合类型，返回规范化后的文本
+    fn check(&self, value: &str) -> Result<String> {
+        let invalid = |expected: String| {
+            ClaudeError::validation_error(&self.name, format!("'{}' is not a valid {}", value, expected))
+        };
+        match self.kind {
+            VariableType::String => Ok(value.to_string()),
+            VariableType::Integer => value.trim().parse::<i64>().map(|n| n.to_string()).map_err(|_| invalid("integer".into())),
+            VariableType::Number => value.trim().parse::<f64>().map(|_| value.trim().to_string()).map_err(|_| invalid("number".into())),
+            VariableType::Boolean => match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" | "on" => Ok("true".to_string()),
+                "false" | "no" | "0" | "off" => Ok("false".to_string()),
+                _ => Err(invalid("boolean (true/false)".into())),
+            },
+            VariableType::Path if Path::new(value).exists() => Ok(value.to_string()),
+            VariableType::Path => Err(invalid("path (it does not exist)".into())),
+            VariableType::Enum if self.values.iter().any(|v| v == value) => Ok(value.to_string()),
+            VariableType::Enum => Err(invalid(format!("choice (one of: {})", self.values.join(", ")))),
+        }
+    }
+}
+
+/// 模板前言
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FrontMatter {
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    variables: Vec<PromptVariable>,
+}
+
+/// 一个提示模板
+#[derive(Debug, Clone)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub scope: PromptScope,
+    pub path: PathBuf,
+    pub description: Option<String>,
+    /// 模板指定的模型，命令行未指定时使用
+    pub model: Option<String>,
+    pub variables: Vec<PromptVariable>,
+    pub body: String,
+}
+
+impl PromptTemplate {
+    /// 解析模板文本；正文引用了未声明的变量时报错
+    pub fn parse(name: &str, scope: PromptScope, path: PathBuf, text: &str) -> Result<Self> {
+        let (front, body) = match text.strip_prefix("---\n").and_then(|rest| rest.split_once("\n---")) {
+            Some((front, body)) => {
+                let front: FrontMatter = serde_yaml::from_str(front).map_err(|e| {
+                    ClaudeError::validation_error("prompt", format!("Invalid front matter in {}: {}", path.display(), e))
+                })?;
+                (front, body.strip_prefix('\n').unwrap_or(body))
+            }
+            None => (FrontMatter::default(), text),
+        };
+
+        for placeholder in placeholders(body) {
+            if !front.variables.iter().any(|v| v.name == placeholder) {
+                return Err(ClaudeError::validation_error(
+                    "prompt",
+                    format!("Template '{}' uses {{{{{}}}}} but does not declare it", name, placeholder),
+                ));
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            scope,
+            path,
+            description: front.description,
+            model: front.model,
+            variables: front.variables,
+            body: body.trim_end().to_string(),
+        })
+    }
+
+    /// 合并取值并校验：命令行 > 项目默认值 > 模板默认值；缺少必填变量或传入未声明变量时报错
+    pub fn resolve(&self, defaults: &HashMap<String, String>, values: &HashMap<String, String>) -> Result<BTreeMap<String, String>> {
+        if let Some(unknown) = values.keys().find(|key| !self.variables.iter().any(|v| &v.name == *key)) {
+            return Err(ClaudeError::validation_error(
+                unknown,
+                format!("Template '{}' has no variable '{}' (variables: {})", self.name, unknown, self.variable_names()),
+            ));
+        }
+
+        let mut resolved = BTreeMap::new();
+        let mut missing = Vec::new();
+        for variable in &self.variables {
+            let value = values.get(&variable.name).or_else(|| defaults.get(&variable.name)).or(variable.default.as_ref());
+            match value {
+                Some(value) => {
+                    resolved.insert(variable.name.clone(), variable.check(value)?);
+                }
+                None if variable.is_required() => missing.push(variable.name.as_str()),
+                None => {
+                    resolved.insert(variable.name.clone(), String::new());
+                }
+            }
+        }
+        if !missing.is_empty() {
+            return Err(ClaudeError::validation_error(
+                "prompt",
+                format!("Missing required variable(s) for '{}': {} (pass --var name=value)", self.name, missing.join(", ")),
+            ));
+        }
+        Ok(resolved)
+    }
+
+    /// 用已解析的取值填充正文
+    pub fn render(&self, values: &BTreeMap<String, String>) -> String {
+        placeholder_pattern()
+            .replace_all(&self.body, |caps: &regex::Captures| values.get(&caps[1]).cloned().unwrap_or_default())
+            .into_owned()
+    }
+
+    fn variable_names(&self) -> String {
+        if self.variables.is_empty() {
+            return "none".to_string();
+        }
+        self.variables.iter().map(|v| v.name.as_str()).collect::<Vec<_>>().join(", ")
+    }
+}
+
+fn placeholder_pattern() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_\-]*)\s*\}\}").unwrap())
+}
+
+fn placeholders(body: &str) -> impl Iterator<Item = &str> {
+    placeholder_pattern().captures_iter(body).filter_map(|caps| caps.get(1)).map(|m| m.as_str())
+}
+
+/// 解析 `key=value` 形式的变量参数
+pub fn parse_assignments<S: AsRef<str>>(args: &[S]) -> Result<HashMap<String, String>> {
+    args.iter()
+        .map(|arg| {
+            let arg = arg.as_ref();
+            arg.split_once('=')
+                .filter(|(key, _)| !key.trim().is_empty())
+                .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                .ok_or_else(|| ClaudeError::validation_error("var", format!("Expected key=value, got '{}'", arg)))
+        })
+        .collect()
+}
+
+/// 解析交互模式中的 `key=value ...`：不含 `=` 的词接在前一个值后面，值里可以有空格
+pub fn parse_inline_assignments(text: &str) -> Result<HashMap<String, String>> {
+    let mut assignments: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match assignments.last_mut() {
+            Some(last) if !word.contains('=') => {
+                last.push(' ');
+                last.push_str(word);
+            }
+            _ => assignments.push(word.to_string()),
+        }
+    }
+    parse_assignments(&assignments)
+}
+
+/// 项目级默认值
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ProjectDefaults {
+    #[serde(default)]
+    variables: HashMap<String, serde_yaml::Value>,
+    #[serde(default)]
+    templates: HashMap<String, HashMap<String, serde_yaml::Value>>,
+}
+
+/// YAML 标量按文本使用，`port: 8080` 和 `port: "8080"` 等价
+fn scalar_text(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// 项目级和用户级模板的合并视图
+#[derive(Debug)]
+pub struct PromptLibrary {
+    project_dir: PathBuf,
+    user_dir: Option<PathBuf>,
+    defaults: ProjectDefaults,
+}
+
+impl PromptLibrary {
+    /// 打开项目 `root` 的模板库
+    pub fn open(root: &Path) -> Result<Self> {
+        Self::with_dirs(root.join(".claude").join("prompts"), dirs::home_dir().map(|home| home.join(".claude").join("prompts")))
+    }
+
+    pub fn with_dirs(project_dir: PathBuf, user_dir: Option<PathBuf>) -> Result<Self> {
+        let defaults = match std::fs::read_to_string(project_dir.join(DEFAULTS_FILE)) {
+            Ok(content) => serde_yaml::from_str(&content).map_err(|e| {
+                ClaudeError::config_error(format!("Invalid {}: {}", project_dir.join(DEFAULTS_FILE).display(), e))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ProjectDefaults::default(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { project_dir, user_dir, defaults })
+    }
+
+    fn dirs(&self) -> impl Iterator<Item = (&Path, PromptScope)> {
+        std::iter::once((self.project_dir.as_path(), PromptScope::Project))
+            .chain(self.user_dir.as_deref().map(|dir| (dir, PromptScope::User)))
+    }
+
+    /// 按名称加载模板，项目级优先
+    pub fn get(&self, name: &str) -> Result<PromptTemplate> {
+        crate::snippets::validate_name(name)?;
+        for (dir, scope) in self.dirs() {
+            let path = dir.join(format!("{}.md", name));
+            match std::fs::read_to_string(&path) {
+                Ok(text) => return PromptTemplate::parse(name, scope, path, &text),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(ClaudeError::validation_error("name", format!("No prompt template named '{}'", name)))
+    }
+
+    /// 全部模板，按名称排序；被项目级同名模板覆盖的用户级模板不列出，解析失败的单独返回
+    pub fn list(&self) -> (Vec<PromptTemplate>, Vec<(PathBuf, ClaudeError)>) {
+        let mut templates: BTreeMap<String, PromptTemplate> = BTreeMap::new();
+        let mut broken = Vec::new();
+        for (dir, scope) in self.dirs() {
+            let Ok(entries) = std::fs::read_dir(dir) else { continue };
+            for path in entries.flatten().map(|entry| entry.path()) {
+                let Some(name) = path.extension().filter(|ext| *ext == "md").and(path.file_stem()).and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                if templates.contains_key(name) {
+                    continue;
+                }
+                let name = name.to_string();
+                match std::fs::read_to_string(&path).map_err(ClaudeError::from).and_then(|text| PromptTemplate::parse(&name, scope, path.clone(), &text)) {
+                    Ok(template) => {
+                        templates.insert(name, template);
+                    }
+                    Err(e) => broken.push((path, e)),
+                }
+            }
+        }
+        (templates.into_values().collect(), broken)
+    }
+
+    /// 本项目对模板 `name` 的变量默认值
+    pub fn defaults_for(&self, name: &str) -> HashMap<String, String> {
+        let specific = self.defaults.templates.get(name).into_iter().flatten();
+        self.defaults
+            .variables
+            .iter()
+            .chain(specific)
+            .filter_map(|(key, value)| Some((key.clone(), scalar_text(value)?)))
+            .collect()
+    }
+
+    /// 加载、校验并渲染模板
+    pub fn render(&self, name: &str, values: &HashMap<String, String>) -> Result<(PromptTemplate, String)> {
+        let template = self.get(name)?;
+        let resolved = template.resolve(&self.defaults_for(name), values)?;
+        let rendered = template.render(&resolved);
+        Ok((template, rendered))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_with_project_defaults() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let project = temp_dir.path().join("project");
+        let user = temp_dir.path().join("user");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(&user).unwrap();
+        std::fs::write(
+            project.join("review.md"),
+            "---\ndescription: Review a file\nvariables:\n  - name: file\n  - name: focus\n    type: enum\n    values: [security, performance]\n    default: performance\n  - name: limit\n    type: integer\n    default: \"5\"\n---\nReview {{file}} for {{ focus }} issues; list at most {{limit}}.\n",
+        )
+        .unwrap();
+        std::fs::write(user.join("review.md"), "shadowed").unwrap();
+        std::fs::write(user.join("broken.md"), "Uses {{undeclared}}").unwrap();
+        std::fs::write(project.join(DEFAULTS_FILE), "templates:\n  review:\n    focus: security\n").unwrap();
+
+        let library = PromptLibrary::with_dirs(project, Some(user)).unwrap();
+        let (templates, broken) = library.list();
+        assert_eq!(templates.iter().map(|t| (t.name.as_str(), t.scope)).collect::<Vec<_>>(), vec![("review", PromptScope::Project)]);
+        assert_eq!(broken.len(), 1);
+
+        let values = parse_assignments(&["file=src/lib.rs", "limit=3"]).unwrap();
+        let (_, rendered) = library.render("review", &values).unwrap();
+        assert_eq!(rendered, "Review src/lib.rs for security issues; list at most 3.");
+
+        assert!(library.render("review", &HashMap::new()).unwrap_err().to_string().contains("file"));
+        assert!(library.render("review", &parse_assignments(&["file=x", "limit=many"]).unwrap()).is_err());
+        assert!(library.render("review", &parse_assignments(&["file=x", "colour=red"]).unwrap()).is_err());
+        assert!(parse_assignments(&["novalue"]).is_err());
+        assert_eq!(parse_inline_assignments("file=a.rs note=check the docs").unwrap()["note"], "check the docs");
+    }
+}
@@ -0,0 +1,828 @@
+//! 文件移动后的导入修复
+//!
+//! 重构移动或重命名文件后，用 tree-sitter 解析工作区中的 Rust、Python 和 TypeScript/JavaScript 文件，
+//! 机械地改写指向旧位置的 `use` 路径和 `mod` 声明、`import` 语句以及相对路径导入。
+//! 无法原地改写的导入（例如 `use crate::{moved::item, other}`）连同原因一起列出，交给模型处理
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use tree_sitter::{Language, Node, Parser, Tree};
+
+use crate::error::{ClaudeError, Result};
+use crate::fs::replace::ReplacePlan;
+use crate::fs::scan::{ParallelScanner, ScanOptions};
+
+/// 参与修复的源文件扩展名
+const SOURCE_EXTENSIONS: &[&str] = &["rs", "py", "ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+/// 省略扩展名的相对导入依次尝试的扩展名
+const SCRIPT_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+/// 一次文件移动，路径相对工作区根目录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileMove {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl FileMove {
+    /// 解析 `old/path -> new/path`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (from, to) = spec
+            .split_once("->")
+            .ok_or_else(|| ClaudeError::validation_error("moves", format!("Expected 'old/path -> new/path', got '{}'", spec)))?;
+        Ok(Self { from: relative_path(from)?, to: relative_path(to)? })
+    }
+}
+
+fn relative_path(path: &str) -> Result<PathBuf> {
+    let path = Path::new(path.trim());
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(ClaudeError::validation_error(
+                    "moves",
+                    format!("'{}' must be relative to the workspace root", path.display()),
+                ))
+            }
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        return Err(ClaudeError::validation_error("moves", "Empty path in move"));
+    }
+    Ok(normalized)
+}
+
+/// 没有指定移动时，从 git 中找出相对 HEAD 的重命名；
+/// 尚未暂存的移动按文件名把删除的文件和新的未跟踪文件配对（只配对唯一的候选）
+pub fn detect_moves(root: &Path) -> Result<Vec<FileMove>> {
+    let git = |args: &[&str]| -> Result<String> {
+        let output = std::process::Command::new("git").args(args).current_dir(root).output()?;
+        if !output.status.success() {
+            return Err(ClaudeError::General(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    let mut moves = Vec::new();
+    let mut deleted = Vec::new();
+    for line in git(&["-c", "core.quotepath=off", "diff", "-M", "--name-status", "HEAD"])?.lines() {
+        match line.split('\t').collect::<Vec<_>>().as_slice() {
+            [status, from, to] if status.starts_with('R') => moves.push(FileMove { from: from.into(), to: to.into() }),
+            ["D", path] => deleted.push(PathBuf::from(path)),
+            _ => {}
+        }
+    }
+    let untracked: Vec<PathBuf> = git(&["-c", "core.quotepath=off", "ls-files", "--others", "--exclude-standard"])?
+        .lines()
+        .map(PathBuf::from)
+        .collect();
+    for from in deleted {
+        let mut candidates = untracked.iter().filter(|path| path.file_name() == from.file_name());
+        if let (Some(to), None) = (candidates.next(), candidates.next()) {
+            moves.push(FileMove { from, to: to.clone() });
+        }
+    }
+    Ok(moves)
+}
+
+/// 无法自动修复的导入
+#[derive(Debug, Clone, Serialize)]
+pub struct UnresolvedImport {
+    pub file: PathBuf,
+    pub line: usize,
+    pub import: String,
+    pub reason: String,
+}
+
+/// 修复结果：改动计划（可预览、写入和撤销）和需要人工处理的导入
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportFixReport {
+    /// 展开目录后的逐文件移动
+    pub moves: Vec<FileMove>,
+    pub plan: ReplacePlan,
+    pub unresolved: Vec<UnresolvedImport>,
+}
+
+/// 扫描 `root` 下的源文件，计算 `moves` 之后需要的导入改动，不写入文件
+pub fn fix_imports(root: &Path, moves: &[FileMove]) -> Result<ImportFixReport> {
+    let moves = expand_directories(root, moves)?;
+    let scanner = ParallelScanner::new(ScanOptions::default())?;
+    let filter = |path: &Path| {
+        path.extension().and_then(|e| e.to_str()).is_some_and(|ext| SOURCE_EXTENSIONS.contains(&ext))
+    };
+
+    let mut fixer = Fixer::new(root, &moves);
+    fixer.rust_mod_declarations(&moves);
+    for path in scanner.discover(root, &filter, None)? {
+        let Ok(relative) = path.strip_prefix(root) else { continue };
+        let relative = relative.to_path_buf();
+        let Some(source) = fixer.source(&relative) else { continue };
+        match relative.extension().and_then(|e| e.to_str()).unwrap_or_default() {
+            "rs" => fixer.rust_file(&relative, &source),
+            "py" => fixer.python_file(&relative, &source),
+            _ => fixer.script_file(&relative, &source),
+        }
+    }
+
+    let Fixer { sources, edits, unresolved, .. } = fixer;
+    let edits = edits
+        .into_iter()
+        .filter_map(|(path, mut ranges)| {
+            ranges.sort_by_key(|(range, _)| (range.start, range.end));
+            ranges.dedup();
+            Some((path.clone(), sources.get(&path)?.clone(), ranges))
+        })
+        .collect();
+    Ok(ImportFixReport { moves, plan: ReplacePlan::from_edits(root, edits), unresolved })
+}
+
+/// 目标是目录的移动展开为其中每个文件的移动
+fn expand_directories(root: &Path, moves: &[FileMove]) -> Result<Vec<FileMove>> {
+    let scanner = ParallelScanner::new(ScanOptions::default())?;
+    let mut expanded = Vec::new();
+    for file_move in moves {
+        let target = root.join(&file_move.to);
+        if !target.is_dir() {
+            expanded.push(file_move.clone());
+            continue;
+        }
+        for path in scanner.discover(&target, &|_| true, None)? {
+            let Ok(inner) = path.strip_prefix(&target) else { continue };
+            expanded.push(FileMove { from: file_move.from.join(inner), to: file_move.to.join(inner) });
+        }
+    }
+    Ok(expanded)
+}
+
+/// 收集改动的工作状态
+struct Fixer<'a> {
+    root: &'a Path,
+    old_to_new: HashMap<PathBuf, PathBuf>,
+    new_to_old: HashMap<PathBuf, PathBuf>,
+    /// 同一 crate 内移动的 Rust 模块：(crate 源码目录, 旧模块路径, 新模块路径)，长路径优先
+    rust_moves: Vec<(PathBuf, Vec<String>, Vec<String>)>,
+    /// 移动的 Python 模块：(旧模块, 新模块)，长路径优先
+    python_moves: Vec<(Vec<String>, Vec<String>)>,
+    sources: HashMap<PathBuf, String>,
+    edits: HashMap<PathBuf, Vec<(Range<usize>, String)>>,
+    unresolved: Vec<UnresolvedImport>,
+}
+
+impl<'a> Fixer<'a> {
+    fn new(root: &'a Path, moves: &[FileMove]) -> Self {
+        let mut rust_moves = Vec::new();
+        let mut python_moves = Vec::new();
+        let mut unresolved = Vec::new();
+        for file_move in moves {
+            if let (Some((old_crate, old)), Some((new_crate, new))) = (rust_module(&file_move.from), rust_module(&file_move.to)) {
+                if old_crate != new_crate {
+                    unresolved.push(UnresolvedImport {
+                        file: file_move.to.clone(),
+                        line: 1,
+                        import: format!("crate::{}", old.join("::")),
+                        reason: "The file moved to another crate; imports through the crate name and Cargo dependencies must be updated by hand".to_string(),
+                    });
+                } else if old != new {
+                    rust_moves.push((old_crate, old, new));
+                }
+            }
+            if let (Some(old), Some(new)) = (python_module(&file_move.from), python_module(&file_move.to)) {
+                if old != new {
+                    python_moves.push((old, new));
+                }
+            }
+        }
+        rust_moves.sort_by_key(|(_, old, _)| std::cmp::Reverse(old.len()));
+        python_moves.sort_by_key(|(old, _)| std::cmp::Reverse(old.len()));
+
+        Self {
+            root,
+            old_to_new: moves.iter().map(|m| (m.from.clone(), m.to.clone())).collect(),
+            new_to_old: moves.iter().map(|m| (m.to.clone(), m.from.clone())).collect(),
+            rust_moves,
+            python_moves,
+            sources: HashMap::new(),
+            edits: HashMap::new(),
+            unresolved,
+        }
+    }
+
+    fn source(&mut self, path: &Path) -> Option<String> {
+        if let Some(source) = self.sources.get(path) {
+            return Some(source.clone());
+        }
+        let source = std::fs::read_to_string(self.root.join(path)).ok()?;
+        self.sources.insert(path.to_path_buf(), source.clone());
+        Some(source)
+    }
+
+    /// 文件移动前的位置
+    fn old_path(&self, current: &Path) -> PathBuf {
+        self.new_to_old.get(current).cloned().unwrap_or_else(|| current.to_path_buf())
+    }
+
+    /// 旧位置上的文件现在的位置
+    fn new_path(&self, old: &Path) -> PathBuf {
+        self.old_to_new.get(old).cloned().unwrap_or_else(|| old.to_path_buf())
+    }
+
+    /// 移动之前该路径上是否有文件
+    fn existed(&self, old: &Path) -> bool {
+        self.old_to_new.contains_key(old) || (!self.new_to_old.contains_key(old) && self.root.join(old).is_file())
+    }
+
+    fn edit(&mut self, file: &Path, range: Range<usize>, text: String) {
+        self.edits.entry(file.to_path_buf()).or_default().push((range, text));
+    }
+
+    fn unresolved(&mut self, file: &Path, source: &str, node: Node, reason: String) {
+        self.unresolved.push(UnresolvedImport {
+            file: file.to_path_buf(),
+            line: node.start_position().row + 1,
+            import: source[node.byte_range()].to_string(),
+            reason: reason.trim().to_string(),
+        });
+    }
+
+    // ---- Rust ----
+
+    fn map_rust(&self, krate: &Path, path: Vec<String>) -> Vec<String> {
+        for (moved_crate, old, new) in &self.rust_moves {
+            if moved_crate == krate && path.starts_with(old) {
+                return new.iter().chain(&path[old.len()..]).cloned().collect();
+            }
+        }
+        path
+    }
+
+    /// 以 `crate`、`self`、`super` 开头的路径：按文件移动前的位置解析，映射到移动后的模块，
+    /// 与从当前位置解析的结果不同时改写为 `crate::` 绝对路径
+    fn rust_file(&mut self, file: &Path, source: &str) {
+        let Some((krate, current)) = rust_module(file) else { return };
+        let previous = rust_module(&self.old_path(file)).map_or_else(|| current.clone(), |(_, module)| module);
+        let Some(tree) = parse(tree_sitter_rust::LANGUAGE.into(), source) else { return };
+
+        for node in descendants(&tree) {
+            if !matches!(node.kind(), "crate" | "self" | "super") {
+                continue;
+            }
+            let Some(parent) = node.parent() else { continue };
+            let mut segments = vec![text(node, source).to_string()];
+            let mut end = node;
+            if is_path_of(parent, node) && is_scoped_path(parent) {
+                let mut scoped = parent;
+                while let Some(name) = scoped.child_by_field_name("name") {
+                    segments.push(text(name, source).to_string());
+                    end = scoped;
+                    match scoped.parent() {
+                        Some(outer) if is_scoped_path(outer) && is_path_of(outer, scoped) => scoped = outer,
+                        _ => break,
+                    }
+                }
+            } else if !(parent.kind() == "use_wildcard" || (parent.kind() == "scoped_use_list" && is_path_of(parent, node))) {
+                continue;
+            }
+
+            let Some(target) = absolute(&segments, &previous).map(|path| self.map_rust(&krate, path)) else { continue };
+            if absolute(&segments, &current).as_ref() != Some(&target) {
+                let rewritten = std::iter::once("crate".to_string()).chain(target.iter().cloned()).collect::<Vec<_>>().join("::");
+                self.edit(file, node.start_byte()..end.end_byte(), rewritten);
+            }
+
+            // `use crate::a::{b, c::d}`：列表中的项自己移动了就无法只改前缀
+            let Some(list) = end.parent().filter(|p| p.kind() == "scoped_use_list" && is_path_of(*p, end)).and_then(|p| p.child_by_field_name("list")) else {
+                continue;
+            };
+            let mut cursor = list.walk();
+            for item in list.named_children(&mut cursor) {
+                let path_node = match item.kind() {
+                    "identifier" | "scoped_identifier" => item,
+                    "use_as_clause" => match item.child_by_field_name("path") {
+                        Some(path) => path,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let item_segments: Vec<String> = text(path_node, source).split("::").map(|s| s.trim().to_string()).collect();
+                let full: Vec<String> = segments.iter().chain(&item_segments).cloned().collect();
+                let Some(moved) = absolute(&full, &previous).map(|path| self.map_rust(&krate, path)) else { continue };
+                let kept: Vec<String> = target.iter().chain(&item_segments).cloned().collect();
+                if moved != kept {
+                    self.unresolved(
+                        file,
+                        source,
+                        item,
+                        format!("`{}` now lives at crate::{}; move it out of this use list", item_segments.join("::"), moved.join("::")),
+                    );
+                }
+            }
+        }
+    }
+
+    /// 把 `mod name;` 从旧的父模块移到新的父模块
+    fn rust_mod_declarations(&mut self, moves: &[FileMove]) {
+        for file_move in moves {
+            let (Some((old_crate, old)), Some((new_crate, new))) = (rust_module(&file_move.from), rust_module(&file_move.to)) else {
+                continue;
+            };
+            let (Some((old_name, old_parent)), Some((new_name, new_parent))) = (old.split_last(), new.split_last()) else { continue };
+            if old_crate != new_crate || old == new {
+                continue;
+            }
+
+            let old_parent_file = parent_module_files(&old_crate, old_parent)
+                .into_iter()
+                .map(|path| self.new_path(&path))
+                .find(|path| self.root.join(path).is_file());
+            let new_parent_file = parent_module_files(&new_crate, new_parent).into_iter().find(|path| self.root.join(path).is_file());
+            if old_parent_file == new_parent_file && old_name == new_name {
+                continue;
+            }
+
+            let mut declaration = format!("mod {};", new_name);
+            if let Some(old_parent_file) = &old_parent_file {
+                if let Some(source) = self.source(old_parent_file) {
+                    if let Some((range, text)) = mod_declaration(&source, old_name) {
+                        declaration = text.replacen(&format!("mod {}", old_name), &format!("mod {}", new_name), 1);
+                        self.edit(old_parent_file, range, String::new());
+                    }
+                }
+            }
+
+            match new_parent_file {
+                Some(new_parent_file) => {
+                    let Some(source) = self.source(&new_parent_file) else { continue };
+                    if mod_declaration(&source, new_name).is_none() {
+                        let at = mod_insertion_point(&source);
+                        let prefix = if at == source.len() && !source.is_empty() && !source.ends_with('\n') { "\n" } else { "" };
+                        self.edit(&new_parent_file, at..at, format!("{}{}\n", prefix, declaration));
+                    }
+                }
+                None => self.unresolved.push(UnresolvedImport {
+                    file: file_move.to.clone(),
+                    line: 1,
+                    import: declaration,
+                    reason: format!(
+                        "No module file declares crate::{}; create it and declare `mod {};` there",
+                        new_parent.join("::"),
+                        new_name
+                    ),
+                }),
+            }
+        }
+    }
+
+    // ---- Python ----
+
+    fn map_python(&self, module: &[String]) -> Option<Vec<String>> {
+        self.python_moves
+            .iter()
+            .find(|(old, _)| module.starts_with(old))
+            .map(|(old, new)| new.iter().chain(&module[old.len()..]).cloned().collect())
+    }
+
+    fn python_file(&mut self, file: &Path, source: &str) {
+        let Some(current) = python_module(file) else { return };
+        let previous = python_module(&self.old_path(file)).unwrap_or_else(|| current.clone());
+        let package = |module: &[String], path: &Path| -> Vec<String> {
+            let is_package = path.file_stem().is_some_and(|stem| stem == "__init__");
+            if is_package { module.to_vec() } else { module[..module.len().saturating_sub(1)].to_vec() }
+        };
+        let previous_package = package(&previous, &self.old_path(file));
+        let current_package = package(&current, file);
+        let Some(tree) = parse(tree_sitter_python::LANGUAGE.into(), source) else { return };
+
+        // `import a.b` 改写后，代码中 `a.b.x` 形式的引用一并改写
+        let mut renamed_attributes: Vec<(String, String)> = Vec::new();
+        let nodes = descendants(&tree);
+        for node in &nodes {
+            match node.kind() {
+                "import_statement" => {
+                    let mut cursor = node.walk();
+                    for child in node.children_by_field_name("name", &mut cursor) {
+                        let (dotted, aliased) = match child.kind() {
+                            "aliased_import" => match child.child_by_field_name("name") {
+                                Some(name) => (name, true),
+                                None => continue,
+                            },
+                            _ => (child, false),
+                        };
+                        let module: Vec<String> = text(dotted, source).split('.').map(str::to_string).collect();
+                        let Some(moved) = self.map_python(&module) else { continue };
+                        let (old_text, new_text) = (module.join("."), moved.join("."));
+                        if aliased {
+                            self.edit(file, dotted.byte_range(), new_text);
+                        } else if module.len() == 1 {
+                            // 保留原来绑定的名字
+                            self.edit(file, dotted.byte_range(), format!("{} as {}", new_text, old_text));
+                        } else {
+                            self.edit(file, dotted.byte_range(), new_text.clone());
+                            renamed_attributes.push((old_text, new_text));
+                        }
+                    }
+                }
+                "import_from_statement" => self.python_from_import(file, source, *node, &previous_package, &current_package),
+                _ => {}
+            }
+        }
+
+        if renamed_attributes.is_empty() {
+            return;
+        }
+        for node in nodes {
+            if node.kind() != "attribute" || node.parent().is_some_and(|p| p.kind() == "dotted_name") {
+                continue;
+            }
+            let reference = text(node, source);
+            if let Some((_, new_text)) = renamed_attributes.iter().find(|(old_text, _)| old_text == reference) {
+                self.edit(file, node.byte_range(), new_text.clone());
+            }
+        }
+    }
+
+    fn python_from_import(&mut self, file: &Path, source: &str, node: Node, previous_package: &[String], current_package: &[String]) {
+        let Some(module_node) = node.child_by_field_name("module_name") else { return };
+        let resolve = |package: &[String]| -> Option<Vec<String>> {
+            if module_node.kind() != "relative_import" {
+                return Some(text(module_node, source).split('.').map(str::to_string).collect());
+            }
+            let module_text = text(module_node, source);
+            let dots = module_text.chars().take_while(|c| *c == '.').count();
+            let mut base = package.to_vec();
+            for _ in 1..dots {
+                base.pop()?;
+            }
+            base.extend(module_text[dots..].split('.').filter(|s| !s.is_empty()).map(str::to_string));
+            Some(base)
+        };
+        let Some(module) = resolve(previous_package) else { return };
+        let target = self.map_python(&module).unwrap_or_else(|| module.clone());
+        let now = resolve(current_package);
+
+        let mut cursor = node.walk();
+        let names: Vec<Node> = node.children_by_field_name("name", &mut cursor).collect();
+        let moved_names: Vec<(Node, String, Option<String>, Vec<String>)> = names
+            .iter()
+            .filter_map(|name| {
+                let (imported, alias) = match name.kind() {
+                    "aliased_import" => (name.child_by_field_name("name")?, name.child_by_field_name("alias")),
+                    _ => (*name, None),
+                };
+                let imported = text(imported, source).to_string();
+                let full: Vec<String> = module.iter().cloned().chain(std::iter::once(imported.clone())).collect();
+                let moved = self.map_python(&full)?;
+                let kept: Vec<String> = target.iter().cloned().chain(std::iter::once(imported.clone())).collect();
+                (moved != kept).then(|| (*name, imported, alias.map(|a| text(a, source).to_string()), moved))
+            })
+            .collect();
+
+        match moved_names.as_slice() {
+            [] => {
+                if now.as_ref() != Some(&target) {
+                    self.edit(file, module_node.byte_range(), target.join("."));
+                }
+            }
+            // `from a import moved`：整条语句改写到模块的新位置，保留原来绑定的名字
+            [(_, imported, alias, moved)] if names.len() == 1 => {
+                let binding = alias.clone().unwrap_or_else(|| imported.clone());
+                let (last, parent) = moved.split_last().expect("moved module path is never empty");
+                let name = if *last == binding { last.clone() } else { format!("{} as {}", last, binding) };
+                let statement = if parent.is_empty() {
+                    format!("import {}", name)
+                } else {
+                    format!("from {} import {}", parent.join("."), name)
+                };
+                self.edit(file, node.byte_range(), statement);
+            }
+            _ => {
+                for (name, imported, _, moved) in moved_names {
+                    self.unresolved(file, source, name, format!("`{}` moved to {}; import it separately", imported, moved.join(".")));
+                }
+                if now.as_ref() != Some(&target) {
+                    self.edit(file, module_node.byte_range(), target.join("."));
+                }
+            }
+        }
+    }
+
+    // ---- TypeScript / JavaScript ----
+
+    fn script_file(&mut self, file: &Path, source: &str) {
+        let language: Language = match file.extension().and_then(|e| e.to_str()) {
+            Some("tsx" | "jsx") => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            _ => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+        };
+        let Some(tree) = parse(language, source) else { return };
+        let old_dir = self.old_path(file).parent().map(Path::to_path_buf).unwrap_or_default();
+        let new_dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        for node in descendants(&tree) {
+            let specifier = match node.kind() {
+                "import_statement" | "export_statement" => node.child_by_field_name("source"),
+                "call_expression" => {
+                    let callee = node.child_by_field_name("function");
+                    let is_import = callee.is_some_and(|f| f.kind() == "import" || text(f, source) == "require");
+                    let first = node.child_by_field_name("arguments").and_then(|args| args.named_child(0));
+                    first.filter(|arg| is_import && arg.kind() == "string")
+                }
+                _ => None,
+            };
+            let Some(specifier) = specifier else { continue };
+            let range = specifier.start_byte() + 1..specifier.end_byte().saturating_sub(1);
+            let Some(spec) = source.get(range.clone()) else { continue };
+            if !(spec.starts_with("./") || spec.starts_with("../")) {
+                continue;
+            }
+
+            let Some((old_target, style)) = self.resolve_script(&old_dir, spec, true) else { continue };
+            let new_target = self.new_path(&old_target);
+            if self.resolve_script(&new_dir, spec, false).map(|(path, _)| path).as_ref() == Some(&new_target) {
+                continue;
+            }
+            let pointed = match style {
+                SpecifierStyle::Exact => new_target.clone(),
+                SpecifierStyle::WithoutExtension => new_target.with_extension(""),
+                SpecifierStyle::Directory => new_target.parent().map(Path::to_path_buf).unwrap_or_default(),
+            };
+            self.edit(file, range, relative_specifier(&new_dir, &pointed));
+        }
+    }
+
+    /// 在移动前（`before`）或移动后的布局中解析相对导入
+    fn resolve_script(&self, dir: &Path, spec: &str, before: bool) -> Option<(PathBuf, SpecifierStyle)> {
+        let base = join_normalized(dir, spec)?;
+        let exists = |path: &Path| if before { self.existed(path) } else { self.root.join(path).is_file() };
+        if exists(&base) {
+            return Some((base, SpecifierStyle::Exact));
+        }
+        for ext in SCRIPT_EXTENSIONS {
+            let mut candidate = base.clone().into_os_string();
+            candidate.push(format!(".{}", ext));
+            let candidate = PathBuf::from(candidate);
+            if exists(&candidate) {
+                return Some((candidate, SpecifierStyle::WithoutExtension));
+            }
+        }
+        SCRIPT_EXTENSIONS
+            .iter()
+            .map(|ext| base.join(format!("index.{}", ext)))
+            .find(|candidate| exists(candidate))
+            .map(|candidate| (candidate, SpecifierStyle::Directory))
+    }
+}
+
+/// 相对导入的写法，改写时保持一致
+#[derive(Debug, Clone, Copy)]
+enum SpecifierStyle {
+    /// 带扩展名
+    Exact,
+    /// 省略扩展名
+    WithoutExtension,
+    /// 指向目录（`index.*`）
+    Directory,
+}
+
+fn parse(language: Language, source: &str) -> Option<Tree> {
+    let mut parser = Parser::new();
+    parser.set_language(&language).ok()?;
+    parser.parse(source, None)
+}
+
+/// 按先序遍历返回语法树中的所有节点
+fn descendants(tree: &Tree) -> Vec<Node<'_>> {
+    let mut nodes = Vec::new();
+    let mut cursor = tree.walk();
+    loop {
+        nodes.push(cursor.node());
+        if cursor.goto_first_child() {
+            continue;
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                break;
+            }
+            if !cursor.goto_parent() {
+                return nodes;
+            }
+        }
+    }
+}
+
+fn text<'s>(node: Node, source: &'s str) -> &'s str {
+    &source[node.byte_range()]
+}
+
+fn is_scoped_path(node: Node) -> bool {
+    matches!(node.kind(), "scoped_identifier" | "scoped_type_identifier")
+}
+
+fn is_path_of(parent: Node, child: Node) -> bool {
+    parent.child_by_field_name("path") == Some(child)
+}
+
+/// Rust 文件所在 crate 的 `src` 目录和模块路径，不在 `src` 下时返回 `None`
+fn rust_module(path: &Path) -> Option<(PathBuf, Vec<String>)> {
+    if path.extension()? != "rs" {
+        return None;
+    }
+    let parts: Vec<String> = path.components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    let src = parts.iter().rposition(|part| part == "src")?;
+    let mut module = parts[src + 1..].to_vec();
+    let file = module.pop()?;
+    let stem = file.trim_end_matches(".rs");
+    match stem {
+        "mod" => {}
+        "lib" | "main" if module.is_empty() => {}
+        _ => module.push(stem.to_string()),
+    }
+    Some((parts[..=src].iter().collect(), module))
+}
+
+/// 可能声明 `parent` 的子模块的文件（`lib.rs`/`main.rs`、`parent.rs` 或 `parent/mod.rs`）
+fn parent_module_files(krate: &Path, parent: &[String]) -> Vec<PathBuf> {
+    if parent.is_empty() {
+        return vec![krate.join("lib.rs"), krate.join("main.rs")];
+    }
+    let dir: PathBuf = parent.iter().collect();
+    vec![krate.join(dir.with_extension("rs")), krate.join(&dir).join("mod.rs")]
+}
+
+/// 把相对路径段解析为 crate 内的绝对模块路径
+fn absolute(segments: &[String], module: &[String]) -> Option<Vec<String>> {
+    let (first, rest) = segments.split_first()?;
+    let mut path = match first.as_str() {
+        "crate" => Vec::new(),
+        "self" => module.to_vec(),
+        "super" => module.split_last()?.1.to_vec(),
+        _ => return None,
+    };
+    for segment in rest {
+        if segment == "super" {
+            path.pop()?;
+        } else {
+            path.push(segment.clone());
+        }
+    }
+    Some(path)
+}
+
+/// 顶层的 `mod name;` 声明（含前面的属性），返回要删除的整行范围和声明文本
+fn mod_declaration(source: &str, name: &str) -> Option<(Range<usize>, String)> {
+    let tree = parse(tree_sitter_rust::LANGUAGE.into(), source)?;
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let item = root.named_children(&mut cursor).find(|item| {
+        item.kind() == "mod_item"
+            && item.child_by_field_name("body").is_none()
+            && item.child_by_field_name("name").is_some_and(|n| text(n, source) == name)
+    })?;
+    let mut first = item;
+    while let Some(previous) = first.prev_named_sibling().filter(|p| p.kind() == "attribute_item") {
+        first = previous;
+    }
+    let start = source[..first.start_byte()].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[item.end_byte()..].find('\n').map_or(source.len(), |i| item.end_byte() + i + 1);
+    Some((start..end, source[first.start_byte()..item.end_byte()].to_string()))
+}
+
+/// 新的 `mod` 声明插入到最后一个 `mod name;` 之后，没有时插入到文件开头的文档注释和内部属性之后
+fn mod_insertion_point(source: &str) -> usize {
+    let line_after = |byte: usize| source[byte..].find('\n').map_or(source.len(), |i| byte + i + 1);
+    if let Some(tree) = parse(tree_sitter_rust::LANGUAGE.into(), source) {
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let last = root
+            .named_children(&mut cursor)
+            .filter(|item| item.kind() == "mod_item" && item.child_by_field_name("body").is_none())
+            .last();
+        if let Some(last) = last {
+            return line_after(last.end_byte());
+        }
+    }
+    let mut at = 0;
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if !(trimmed.starts_with("//!") || trimmed.starts_with("#![") || (trimmed.is_empty() && at > 0)) {
+            break;
+        }
+        at += line.len();
+    }
+    at
+}
+
+/// Python 模块路径；`src/` 布局的项目去掉开头的 `src`
+fn python_module(path: &Path) -> Option<Vec<String>> {
+    if path.extension()? != "py" {
+        return None;
+    }
+    let mut module: Vec<String> = path.with_extension("").components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    if module.first().is_some_and(|first| first == "src") && module.len() > 1 {
+        module.remove(0);
+    }
+    if module.last().is_some_and(|last| last == "__init__") {
+        module.pop();
+    }
+    (!module.is_empty()).then_some(module)
+}
+
+/// 按词法规则拼接相对路径，越过工作区根目录时返回 `None`
+fn join_normalized(dir: &Path, spec: &str) -> Option<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for component in Path::new(spec).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::ParentDir => {
+                if !path.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// 从 `from_dir` 指向 `target` 的相对导入，总以 `./` 或 `../` 开头
+fn relative_specifier(from_dir: &Path, target: &Path) -> String {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to: Vec<Component> = target.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<String> = vec!["..".to_string(); from.len() - common];
+    parts.extend(to[common..].iter().map(|c| c.as_os_str().to_string_lossy().into_owned()));
+    if from.len() == common {
+        parts.insert(0, ".".to_string());
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::undo::UndoJournal;
+
+    #[test]
+    fn test_fixes_imports_after_moves() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let files = [
+            ("src/lib.rs", "pub mod core;\nmod util;\nmod app;\n"),
+            ("src/core/mod.rs", "pub mod other;\n"),
+            ("src/core/other.rs", ""),
+            ("src/core/helpers.rs", "use super::app::run;\npub fn parse() {}\n"),
+            (
+                "src/app.rs",
+                "use crate::util::{parse, self};\nuse crate::{util::parse as p, core};\npub fn run() { crate::util::parse(); }\n",
+            ),
+            ("pkg/__init__.py", ""),
+            ("pkg/new_mod.py", "thing = 1\n"),
+            ("main.py", "from pkg.old_mod import thing\nimport pkg.old_mod\npkg.old_mod.thing\nfrom pkg import old_mod\n"),
+            ("web/theme.js", ""),
+            ("web/components/Button.tsx", "import styles from \"./theme.js\";\n"),
+            ("web/app.ts", "import { Button } from './Button';\nconst lazy = import('./theme.js');\n"),
+        ];
+        for (path, content) in files {
+            std::fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            std::fs::write(root.join(path), content).unwrap();
+        }
+        let moves: Vec<FileMove> = [
+            "src/util.rs -> src/core/helpers.rs",
+            "pkg/old_mod.py -> pkg/new_mod.py",
+            "./web/Button.tsx -> web/components/Button.tsx",
+        ]
+        .iter()
+        .map(|spec| FileMove::parse(spec).unwrap())
+        .collect();
+        assert!(FileMove::parse("../outside.rs -> x.rs").is_err());
+
+        let report = fix_imports(root, &moves).unwrap();
+        assert_eq!(report.unresolved.len(), 1, "{:?}", report.unresolved);
+        assert_eq!(report.unresolved[0].import, "util::parse as p");
+        report.plan.apply(&UndoJournal::new(root.join(".undo")), "fix imports").unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(root.join(path)).unwrap();
+        assert_eq!(read("src/lib.rs"), "pub mod core;\nmod app;\n");
+        assert_eq!(read("src/core/mod.rs"), "pub mod other;\nmod helpers;\n");
+        assert_eq!(read("src/core/helpers.rs"), "use crate::app::run;\npub fn parse() {}\n");
+        assert_eq!(
+            read("src/app.rs"),
+            "use crate::core::helpers::{parse, self};\nuse crate::{util::parse as p, core};\npub fn run() { crate::core::helpers::parse(); }\n"
+        );
+        assert_eq!(
+            read("main.py"),
+            "from pkg.new_mod import thing\nimport pkg.new_mod\npkg.new_mod.thing\nfrom pkg import new_mod as old_mod\n"
+        );
+        assert_eq!(read("web/components/Button.tsx"), "import styles from \"../theme.js\";\n");
+        assert_eq!(read("web/app.ts"), "import { Button } from './components/Button';\nconst lazy = import('./theme.js');\n");
+    }
+}
@@ -10,6 +10,8 @@ use serde::{Deserialize, Serialize};
 use crate::error::{ClaudeError, Result};
 use crate::fs::FileSystemManager;
 
+pub mod imports;
+
 /// 重构建议
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RefactorSuggestion {
@@ -0,0 +1,131 @@
+//! 报告图表
+//!
+//! 用 plotters 把折线图和柱状图渲染为 SVG 字符串，报告里既可以内联到 HTML，也可以另存为文件
+
+use plotters::prelude::*;
+
+use crate::error::{ClaudeError, Result};
+
+const WIDTH: u32 = 720;
+const HEIGHT: u32 = 360;
+
+/// 折线图中的一条线
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// 图表数据
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChartData {
+    Line(Vec<Series>),
+    /// (标签, 数值)
+    Bar(Vec<(String, f64)>),
+}
+
+/// 一张图表
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chart {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub data: ChartData,
+}
+
+impl Chart {
+    pub fn line(title: impl Into<String>, x_label: impl Into<String>, y_label: impl Into<String>, series: Vec<Series>) -> Self {
+        Self { title: title.into(), x_label: x_label.into(), y_label: y_label.into(), data: ChartData::Line(series) }
+    }
+
+    pub fn bar(title: impl Into<String>, x_label: impl Into<String>, y_label: impl Into<String>, bars: Vec<(String, f64)>) -> Self {
+        Self { title: title.into(), x_label: x_label.into(), y_label: y_label.into(), data: ChartData::Bar(bars) }
+    }
+
+    /// 渲染为 SVG
+    pub fn to_svg(&self) -> Result<String> {
+        let mut svg = String::new();
+        {
+            let root = SVGBackend::with_string(&mut svg, (WIDTH, HEIGHT)).into_drawing_area();
+            root.fill(&WHITE).map_err(chart_error)?;
+            let mut builder = ChartBuilder::on(&root);
+            builder.caption(&self.title, ("sans-serif", 20)).margin(12).x_label_area_size(40).y_label_area_size(56);
+
+            match &self.data {
+                ChartData::Line(series) => {
+                    let points = || series.iter().flat_map(|s| s.points.iter());
+                    let x_min = points().map(|p| p.0).fold(f64::INFINITY, f64::min);
+                    let x_max = points().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+                    let (x_min, x_max) = if x_min < x_max { (x_min, x_max) } else { (0.0, x_max.max(0.0) + 1.0) };
+                    let y_max = upper_bound(points().map(|p| p.1));
+
+                    let mut chart = builder.build_cartesian_2d(x_min..x_max, 0f64..y_max).map_err(chart_error)?;
+                    chart
+                        .configure_mesh()
+                        .x_desc(self.x_label.as_str())
+                        .y_desc(self.y_label.as_str())
+                        .draw()
+                        .map_err(chart_error)?;
+                    for (index, series) in series.iter().enumerate() {
+                        let color = Palette99::pick(index);
+                        chart
+                            .draw_series(LineSeries::new(series.points.iter().copied(), color.stroke_width(2)))
+                            .map_err(chart_error)?
+                            .label(series.name.as_str())
+                            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 16, y)], color.stroke_width(2)));
+                    }
+                    if series.len() > 1 {
+                        chart
+                            .configure_series_labels()
+                            .border_style(BLACK)
+                            .background_style(WHITE.mix(0.8))
+                            .draw()
+                            .map_err(chart_error)?;
+                    }
+                }
+                ChartData::Bar(bars) => {
+                    let y_max = upper_bound(bars.iter().map(|(_, value)| *value));
+                    let label = |x: &SegmentValue<u32>| match x {
+                        SegmentValue::CenterOf(index) => {
+                            bars.get(*index as usize).map(|(label, _)| label.clone()).unwrap_or_default()
+                        }
+                        _ => String::new(),
+                    };
+
+                    let mut chart = builder
+                        .build_cartesian_2d((0u32..bars.len().max(1) as u32).into_segmented(), 0f64..y_max)
+                        .map_err(chart_error)?;
+                    chart
+                        .configure_mesh()
+                        .disable_x_mesh()
+                        .x_labels(bars.len().max(1))
+                        .x_label_formatter(&label)
+                        .x_desc(self.x_label.as_str())
+                        .y_desc(self.y_label.as_str())
+                        .draw()
+                        .map_err(chart_error)?;
+                    chart
+                        .draw_series(
+                            Histogram::vertical(&chart)
+                                .style(Palette99::pick(0).filled())
+                                .margin(8)
+                                .data(bars.iter().enumerate().map(|(index, (_, value))| (index as u32, *value))),
+                        )
+                        .map_err(chart_error)?;
+                }
+            }
+            root.present().map_err(chart_error)?;
+        }
+        Ok(svg)
+    }
+}
+
+/// 纵轴上限：最大值留出一成空白，全为 0 时取 1
+fn upper_bound(values: impl Iterator<Item = f64>) -> f64 {
+    let max = values.fold(0.0, f64::max);
+    if max > 0.0 { max * 1.1 } else { 1.0 }
+}
+
+fn chart_error(e: impl std::fmt::Display) -> ClaudeError {
+    ClaudeError::General(format!("Failed to render chart: {}", e))
+}
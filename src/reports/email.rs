@@ -0,0 +1,71 @@
+//! 报告邮件
+//!
+//! 通过配置中的 SMTP 服务器发送已保存的报告：正文是纯文本（markdown）和 HTML 的 alternative，
+//! markdown 原文和图表 SVG 作为附件，方便不支持内联 SVG 的邮件客户端查看
+
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use super::SavedReport;
+use crate::config::SmtpConfig;
+use crate::error::{ClaudeError, Result};
+
+/// 发送报告，`to` 为空时发给配置中的默认收件人
+pub async fn send_report(smtp: &SmtpConfig, report: &SavedReport, to: &[String]) -> Result<Vec<String>> {
+    let recipients: Vec<String> = if to.is_empty() { smtp.to.clone() } else { to.to_vec() };
+    if recipients.is_empty() {
+        return Err(ClaudeError::config_error("No report recipients: set reports.smtp.to or pass --to"));
+    }
+
+    let markdown = std::fs::read_to_string(&report.markdown)?;
+    let html = std::fs::read_to_string(&report.html)?;
+    let subject = markdown
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .unwrap_or("Report")
+        .to_string();
+
+    let mut builder = Message::builder().from(parse_mailbox(&smtp.from)?).subject(subject);
+    for recipient in &recipients {
+        builder = builder.to(parse_mailbox(recipient)?);
+    }
+
+    let mut body = MultiPart::mixed().multipart(MultiPart::alternative_plain_html(markdown.clone(), html));
+    let file_name = |path: &std::path::Path| path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    body = body.singlepart(
+        Attachment::new(file_name(&report.markdown)).body(markdown, content_type("text/markdown; charset=utf-8")?),
+    );
+    for chart in &report.charts {
+        body = body.singlepart(Attachment::new(file_name(chart)).body(std::fs::read(chart)?, content_type("image/svg+xml")?));
+    }
+    let message = builder.multipart(body).map_err(|e| ClaudeError::General(format!("Failed to build report email: {}", e)))?;
+
+    let mut transport = if smtp.starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host)
+            .map_err(|e| ClaudeError::General(format!("Invalid SMTP relay {}: {}", smtp.host, e)))?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.host)
+    }
+    .port(smtp.port);
+    if let Some(username) = &smtp.username {
+        transport = transport.credentials(Credentials::new(username.clone(), smtp.password.clone().unwrap_or_default()));
+    }
+    transport
+        .build()
+        .send(message)
+        .await
+        .map_err(|e| ClaudeError::General(format!("Failed to send report email via {}: {}", smtp.host, e)))?;
+    Ok(recipients)
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|e| ClaudeError::validation_error("email", format!("Invalid address {}: {}", address, e)))
+}
+
+fn content_type(value: &str) -> Result<ContentType> {
+    ContentType::parse(value).map_err(|e| ClaudeError::General(format!("Invalid content type {}: {}", value, e)))
+}
@@ -0,0 +1,344 @@
+//! 长报告
+//!
+//! 审查、健康检查、变更日志这类较长的输出可以写成报告：正文为 markdown，附带着色的 diff 和图表，
+//! 同时渲染出一份自包含的 HTML，保存在 `reports/` 下，并可以通过 SMTP 以邮件发送
+
+pub mod chart;
+pub mod email;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub use chart::{Chart, Series};
+
+use crate::conversation::share::escape_html;
+use crate::error::{ClaudeError, Result};
+
+/// 嵌入报告的单个 diff 的最大行数
+const MAX_DIFF_LINES: usize = 500;
+
+/// 报告的一节
+#[derive(Debug, Clone, PartialEq)]
+pub enum Section {
+    Markdown(String),
+    Diff { title: String, diff: String },
+    Chart(Chart),
+}
+
+/// 一份报告
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// 报告类型，用作文件名前缀（如 `review`、`health`）
+    pub kind: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub sections: Vec<Section>,
+}
+
+/// 保存到磁盘的报告文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedReport {
+    pub markdown: PathBuf,
+    pub html: PathBuf,
+    pub charts: Vec<PathBuf>,
+}
+
+impl Report {
+    pub fn new(kind: impl Into<String>, title: impl Into<String>) -> Self {
+        Self { kind: kind.into(), title: title.into(), created_at: Utc::now(), sections: Vec::new() }
+    }
+
+    pub fn markdown(&mut self, text: impl Into<String>) -> &mut Self {
+        self.sections.push(Section::Markdown(text.into()));
+        self
+    }
+
+    /// 嵌入一个统一格式的 diff，过长时截断
+    pub fn diff(&mut self, title: impl Into<String>, diff: &str) -> &mut Self {
+        let lines: Vec<&str> = diff.lines().collect();
+        let mut diff = lines.iter().take(MAX_DIFF_LINES).copied().collect::<Vec<_>>().join("\n");
+        if lines.len() > MAX_DIFF_LINES {
+            diff.push_str(&format!("\n… {} more lines", lines.len() - MAX_DIFF_LINES));
+        }
+        self.sections.push(Section::Diff { title: title.into(), diff });
+        self
+    }
+
+    pub fn chart(&mut self, chart: Chart) -> &mut Self {
+        self.sections.push(Section::Chart(chart));
+        self
+    }
+
+    /// 文件名（不含扩展名）：`<类型>-<时间>`
+    pub fn file_stem(&self) -> String {
+        format!("{}-{}", self.kind, self.created_at.format("%Y%m%d-%H%M%S"))
+    }
+
+    fn chart_file(&self, index: usize) -> String {
+        format!("{}-chart-{}.svg", self.file_stem(), index + 1)
+    }
+
+    /// markdown 正文，图表以同目录下的 SVG 文件引用
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n\n_Generated {}_\n", self.title, self.created_at.format("%Y-%m-%d %H:%M UTC"));
+        let mut charts = 0;
+        for section in &self.sections {
+            out.push('\n');
+            match section {
+                Section::Markdown(text) => out.push_str(text.trim_end()),
+                Section::Diff { title, diff } => out.push_str(&format!("### {}\n\n```diff\n{}\n```", title, diff)),
+                Section::Chart(chart) => {
+                    out.push_str(&format!("![{}]({})", chart.title, self.chart_file(charts)));
+                    charts += 1;
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn render_charts(&self) -> Result<Vec<String>> {
+        self.sections
+            .iter()
+            .filter_map(|section| match section {
+                Section::Chart(chart) => Some(chart.to_svg()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// 自包含的 HTML，图表内联为 SVG
+    fn render_html(&self, charts: &[String]) -> String {
+        let mut body = format!(
+            "<h1>{}</h1>\n<p class=\"meta\">Generated {}</p>\n",
+            escape_html(&self.title),
+            self.created_at.format("%Y-%m-%d %H:%M UTC")
+        );
+        let mut charts = charts.iter();
+        for section in &self.sections {
+            match section {
+                Section::Markdown(text) => {
+                    let options = pulldown_cmark::Options::ENABLE_TABLES
+                        | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
+                        | pulldown_cmark::Options::ENABLE_TASKLISTS;
+                    // 报告内容来自模型和提交信息，其中的 HTML 按文本显示
+                    let events = pulldown_cmark::Parser::new_ext(text, options).map(|event| match event {
+                        pulldown_cmark::Event::Html(html) => pulldown_cmark::Event::Text(html),
+                        event => event,
+                    });
+                    pulldown_cmark::html::push_html(&mut body, events);
+                }
+                Section::Diff { title, diff } => {
+                    body.push_str(&format!("<h3>{}</h3>\n<pre class=\"diff\">", escape_html(title)));
+                    for line in diff.lines() {
+                        let class = match line.chars().next() {
+                            Some('+') if !line.starts_with("+++") => "add",
+                            Some('-') if !line.starts_with("---") => "del",
+                            Some('@') => "hunk",
+                            _ => "ctx",
+                        };
+                        body.push_str(&format!("<span class=\"{}\">{}</span>\n", class, escape_html(line)));
+                    }
+                    body.push_str("</pre>\n");
+                }
+                Section::Chart(_) => {
+                    if let Some(svg) = charts.next() {
+                        body.push_str(&format!("<figure class=\"chart\">\n{}\n</figure>\n", svg));
+                    }
+                }
+            }
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+             body {{ font-family: -apple-system, Segoe UI, sans-serif; max-width: 960px; margin: 2em auto; line-height: 1.5; }}\n\
+             .meta {{ color: #666; }}\n\
+             pre {{ background: #f6f8fa; padding: 1em; overflow-x: auto; }}\n\
+             table {{ border-collapse: collapse; }} td, th {{ border: 1px solid #d0d7de; padding: 4px 10px; }}\n\
+             .add {{ color: #116329; }} .del {{ color: #82071e; }} .hunk {{ color: #8250df; }}\n\
+             figure.chart {{ margin: 1.5em 0; }}\n\
+             </style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            escape_html(&self.title),
+            body
+        )
+    }
+
+    /// 把 markdown、HTML 和图表写入 `dir`
+    pub fn save(&self, dir: &Path) -> Result<SavedReport> {
+        std::fs::create_dir_all(dir)?;
+        let svgs = self.render_charts()?;
+        let mut charts = Vec::new();
+        for (index, svg) in svgs.iter().enumerate() {
+            let path = dir.join(self.chart_file(index));
+            std::fs::write(&path, svg)?;
+            charts.push(path);
+        }
+        let markdown = dir.join(format!("{}.md", self.file_stem()));
+        std::fs::write(&markdown, self.to_markdown())?;
+        let html = dir.join(format!("{}.html", self.file_stem()));
+        std::fs::write(&html, self.render_html(&svgs))?;
+        Ok(SavedReport { markdown, html, charts })
+    }
+}
+
+impl SavedReport {
+    /// 由报告的任一文件（`.md`、`.html` 或不带扩展名的路径）找回整份报告
+    pub fn open(path: &Path) -> Result<Self> {
+        let stem = path.with_extension("");
+        let name = stem.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let dir = stem.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let report = Self {
+            markdown: dir.join(format!("{}.md", name)),
+            html: dir.join(format!("{}.html", name)),
+            charts: chart_files(dir, &name)?,
+        };
+        if !report.markdown.is_file() || !report.html.is_file() {
+            return Err(ClaudeError::validation_error(
+                "report",
+                format!("{} is not a saved report (expected {}.md and {}.html)", path.display(), name, name),
+            ));
+        }
+        Ok(report)
+    }
+}
+
+/// `dir` 中保存的报告，新的在前
+pub fn list(dir: &Path) -> Result<Vec<SavedReport>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut reports = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "md") {
+            if let Ok(report) = SavedReport::open(&path) {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok();
+                reports.push((modified, report));
+            }
+        }
+    }
+    reports.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(reports.into_iter().map(|(_, report)| report).collect())
+}
+
+fn chart_files(dir: &Path, stem: &str) -> Result<Vec<PathBuf>> {
+    let prefix = format!("{}-chart-", stem);
+    let mut charts: Vec<(usize, PathBuf)> = std::fs::read_dir(dir)?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let index = name.strip_prefix(&prefix)?.strip_suffix(".svg")?.parse().ok()?;
+            Some((index, entry.path()))
+        })
+        .collect();
+    charts.sort_by_key(|(index, _)| *index);
+    Ok(charts.into_iter().map(|(_, path)| path).collect())
+}
+
+/// 由 `git log --numstat --date=short --pretty=format:>%h%x09%ad%x09%s` 的输出和 `git diff --stat`
+/// 生成变更日志报告
+pub fn changelog(range: &str, log: &str, diffstat: &str) -> Report {
+    struct Commit<'a> {
+        hash: &'a str,
+        date: &'a str,
+        subject: &'a str,
+        added: u64,
+        deleted: u64,
+    }
+
+    let mut commits: Vec<Commit> = Vec::new();
+    for line in log.lines() {
+        if let Some(header) = line.strip_prefix('>') {
+            let mut fields = header.splitn(3, '\t');
+            let (hash, date, subject) = (fields.next().unwrap_or(""), fields.next().unwrap_or(""), fields.next().unwrap_or(""));
+            commits.push(Commit { hash, date, subject, added: 0, deleted: 0 });
+        } else if let Some(commit) = commits.last_mut() {
+            // 二进制文件的行数为 "-"
+            let mut fields = line.split('\t');
+            if let (Some(added), Some(deleted), Some(_)) = (fields.next(), fields.next(), fields.next()) {
+                commit.added += added.parse::<u64>().unwrap_or(0);
+                commit.deleted += deleted.parse::<u64>().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut report = Report::new("changelog", format!("Changelog {}", range));
+    let (added, deleted) = commits.iter().fold((0, 0), |(a, d), c| (a + c.added, d + c.deleted));
+    report.markdown(format!("{} commit(s), +{} / -{} lines.", commits.len(), added, deleted));
+    if commits.is_empty() {
+        return report;
+    }
+
+    let mut list = String::from("## Commits\n\n");
+    for commit in &commits {
+        list.push_str(&format!("- `{}` {} ({}, +{} / -{})\n", commit.hash, commit.subject, commit.date, commit.added, commit.deleted));
+    }
+    report.markdown(list);
+
+    let mut days: BTreeMap<&str, (u64, u64, u64)> = BTreeMap::new();
+    for commit in &commits {
+        let day = days.entry(commit.date).or_default();
+        day.0 += 1;
+        day.1 += commit.added;
+        day.2 += commit.deleted;
+    }
+    report.chart(Chart::bar(
+        "Commits per day",
+        "Date",
+        "Commits",
+        days.iter().map(|(date, (count, _, _))| (date.to_string(), *count as f64)).collect(),
+    ));
+    let first = days.keys().next().and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+    let offset = |date: &str| {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+        date.zip(first).map_or(0.0, |(date, first)| (date - first).num_days() as f64)
+    };
+    let series = |name: &str, pick: fn(&(u64, u64, u64)) -> u64| Series {
+        name: name.to_string(),
+        points: days.iter().map(|(date, totals)| (offset(date), pick(totals) as f64)).collect(),
+    };
+    report.chart(Chart::line(
+        "Lines changed per day",
+        format!("Days since {}", days.keys().next().unwrap_or(&"")),
+        "Lines",
+        vec![series("added", |t| t.1), series("deleted", |t| t.2)],
+    ));
+
+    if !diffstat.trim().is_empty() {
+        report.markdown(format!("## Files changed\n\n```\n{}\n```", diffstat.trim_end()));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changelog_report_saves_markdown_html_and_charts() {
+        let log = ">a1b2c3d\t2026-03-01\tAdd parser\n10\t2\tsrc/parser.rs\n-\t-\tlogo.png\n\n\
+                   >e4f5a6b\t2026-03-03\tFix <escape> bug\n3\t1\tsrc/html.rs\n";
+        let mut report = changelog("v1.0..HEAD", log, " src/parser.rs | 12 ++++++++++--\n");
+        report.diff("src/html.rs", "@@ -1 +1 @@\n-old <b>\n+new");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let saved = report.save(dir.path()).unwrap();
+        assert_eq!(saved.charts.len(), 2);
+        assert_eq!(SavedReport::open(&saved.html).unwrap(), saved);
+        assert_eq!(list(dir.path()).unwrap(), vec![saved.clone()]);
+
+        let markdown = std::fs::read_to_string(&saved.markdown).unwrap();
+        assert!(markdown.starts_with("# Changelog v1.0..HEAD"));
+        assert!(markdown.contains("2 commit(s), +13 / -3 lines."));
+        assert!(markdown.contains(&format!("![Commits per day]({}-chart-1.svg)", report.file_stem())));
+
+        let html = std::fs::read_to_string(&saved.html).unwrap();
+        assert!(html.contains("<li><code>e4f5a6b</code> Fix &lt;escape&gt; bug"));
+        assert!(html.contains("<span class=\"del\">-old &lt;b&gt;</span>"));
+        assert_eq!(html.matches("<svg").count(), 2);
+        assert!(std::fs::read_to_string(&saved.charts[0]).unwrap().contains("2026-03-03"));
+    }
+}
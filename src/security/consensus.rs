@@ -0,0 +1,413 @@
+//! 高风险操作的多模型共识
+//!
+//! 大批量删除、强制推送、数据库迁移等破坏性命令在执行前交给两个模型（或两套配置）分别评审，
+//! 两者一致时按结论放行或拒绝；判断不一致（或有一方无法给出结论）时展示双方理由，由用户明确确认
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::command_guard::{split_segments, tokenize};
+use crate::config::ConsensusConfig;
+use crate::error::Result;
+use crate::network::{ClaudeRequest, Message, NetworkManager};
+
+/// 高风险操作类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskKind {
+    LargeDelete,
+    ForcePush,
+    Migration,
+}
+
+impl std::fmt::Display for RiskKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::LargeDelete => "large delete",
+            Self::ForcePush => "force push",
+            Self::Migration => "migration",
+        })
+    }
+}
+
+/// 待评审的操作
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskyAction {
+    pub kind: RiskKind,
+    pub command: String,
+    /// 给评审模型和用户看的一句话概括
+    pub summary: String,
+}
+
+/// 判断命令是否属于需要共识的高风险操作
+pub fn classify(command: &str, large_delete_paths: usize) -> Option<RiskyAction> {
+    let lower = command.to_lowercase();
+    if ["drop table", "drop database", "drop schema", "truncate table"].iter().any(|sql| lower.contains(sql)) {
+        return Some(action(RiskKind::Migration, command, "Runs SQL that drops or truncates data".to_string()));
+    }
+
+    for segment in split_segments(command) {
+        let tokens = tokenize(&segment);
+        let Some(program) = tokens.first().map(|t| t.rsplit('/').next().unwrap_or(t)) else { continue };
+        let args = &tokens[1..];
+        let found = match program {
+            "rm" => {
+                let recursive = args.iter().any(|a| {
+                    a == "--recursive" || (a.starts_with('-') && !a.starts_with("--") && (a.contains('r') || a.contains('R')))
+                });
+                let paths: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+                (recursive || paths.len() >= large_delete_paths).then(|| {
+                    let shown: Vec<&str> = paths.iter().take(5).map(|p| p.as_str()).collect();
+                    let more = if paths.len() > shown.len() { format!(" and {} more", paths.len() - shown.len()) } else { String::new() };
+                    let how = if recursive { "Recursively deletes" } else { "Deletes" };
+                    (RiskKind::LargeDelete, format!("{} {}{}", how, shown.join(", "), more))
+                })
+            }
+            "find" if args.iter().any(|a| a == "-delete") => {
+                Some((RiskKind::LargeDelete, "Deletes every file matched by a find expression".to_string()))
+            }
+            "git" => classify_git(args),
+            _ => classify_migration(program, args),
+        };
+        if let Some((kind, summary)) = found {
+            return Some(action(kind, command, summary));
+        }
+    }
+    None
+}
+
+fn action(kind: RiskKind, command: &str, summary: String) -> RiskyAction {
+    RiskyAction { kind, command: command.to_string(), summary }
+}
+
+fn classify_git(args: &[String]) -> Option<(RiskKind, String)> {
+    match args.iter().find(|a| !a.starts_with('-'))?.as_str() {
+        "push" => {
+            let force = args.iter().any(|a| {
+                a == "-f" || a == "--force" || a.starts_with("--force-with-lease") || a == "--mirror"
+            }) || args.iter().any(|a| a.starts_with('+'));
+            let target: Vec<&str> = args.iter().skip_while(|a| *a != "push").skip(1).filter(|a| !a.starts_with('-')).map(String::as_str).collect();
+            force.then(|| (RiskKind::ForcePush, format!("Force pushes {}", if target.is_empty() { "the current branch".to_string() } else { target.join(" ") })))
+        }
+        "clean" if args.iter().any(|a| a.starts_with('-') && !a.starts_with("--") && a.contains('f') && a.contains('d')) => {
+            Some((RiskKind::LargeDelete, "Deletes all untracked files and directories in the work tree".to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn classify_migration(program: &str, args: &[String]) -> Option<(RiskKind, String)> {
+    let has = |word: &str| args.iter().any(|a| a == word);
+    let migrates = match program {
+        "diesel" | "sqlx" | "sea-orm-cli" => (has("migration") || has("migrate")) && (has("run") || has("redo") || has("revert")),
+        "rails" | "rake" | "bin/rails" => args.iter().any(|a| a.starts_with("db:migrate") || a.starts_with("db:rollback") || a == "db:reset"),
+        "alembic" => has("upgrade") || has("downgrade"),
+        "prisma" | "npx" => has("migrate") && (has("deploy") || has("dev") || has("reset")),
+        "python" | "python3" => has("manage.py") && has("migrate"),
+        "flyway" | "liquibase" => has("migrate") || has("update") || has("clean"),
+        "knex" => args.iter().any(|a| a.starts_with("migrate:")),
+        _ => false,
+    };
+    migrates.then(|| (RiskKind::Migration, format!("Applies database migrations with {}", program)))
+}
+
+/// 评审结论
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Approve,
+    Reject,
+    /// 评审失败或回复无法解析
+    Unavailable,
+}
+
+/// 一个评审方的判断
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Judgment {
+    /// 评审方（模型名）
+    pub judge: String,
+    pub verdict: Verdict,
+    pub rationale: String,
+}
+
+/// 评审方：收到评审提示，返回模型的原始回复
+#[async_trait]
+pub trait Judge: Send + Sync {
+    fn name(&self) -> String;
+    async fn judge(&self, prompt: &str) -> Result<String>;
+}
+
+/// 判断不一致时向用户确认
+#[async_trait]
+pub trait Confirm: Send + Sync {
+    async fn confirm(&self, action: &RiskyAction, judgments: &[Judgment]) -> bool;
+}
+
+/// 通过 Claude API 评审
+pub struct ModelJudge {
+    client: NetworkManager,
+    model: String,
+}
+
+impl ModelJudge {
+    pub fn new(client: NetworkManager, model: impl Into<String>) -> Self {
+        Self { client, model: model.into() }
+    }
+}
+
+#[async_trait]
+impl Judge for ModelJudge {
+    fn name(&self) -> String {
+        self.model.clone()
+    }
+
+    async fn judge(&self, prompt: &str) -> Result<String> {
+        let request = ClaudeRequest {
+            model: self.model.clone(),
+            messages: vec![Message { role: "user".to_string(), content: prompt.into() }],
+            max_tokens: 512,
+            stream: Some(false),
+            tools: None,
+            temperature: Some(0.0),
+            top_p: None,
+            system: None,
+        };
+        Ok(self.client.send_claude_request(request).await?.content)
+    }
+}
+
+/// 在终端展示双方理由并询问；没有终端时视为拒绝
+pub struct TerminalConfirm;
+
+#[async_trait]
+impl Confirm for TerminalConfirm {
+    async fn confirm(&self, action: &RiskyAction, judgments: &[Judgment]) -> bool {
+        use std::io::IsTerminal;
+
+        if !std::io::stdin().is_terminal() {
+            return false;
+        }
+        println!("\n⚖️  Reviewers disagree about this {}: {}", action.kind, action.summary);
+        println!("   $ {}", action.command);
+        for judgment in judgments {
+            println!("   • {} ({:?}): {}", judgment.judge, judgment.verdict, judgment.rationale);
+        }
+        tokio::task::spawn_blocking(|| {
+            use std::io::Write;
+
+            print!("Run it anyway? [y/N] ");
+            std::io::stdout().flush().ok();
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer).ok();
+            matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+        })
+        .await
+        .unwrap_or(false)
+    }
+}
+
+/// 共识的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// 双方都同意执行
+    Approved,
+    /// 双方都拒绝
+    Rejected,
+    /// 判断不一致，用户确认执行
+    UserApproved,
+    /// 判断不一致，用户拒绝（或无法询问）
+    UserRejected,
+}
+
+impl Resolution {
+    pub fn proceeds(self) -> bool {
+        matches!(self, Self::Approved | Self::UserApproved)
+    }
+}
+
+/// 一次共识评审的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsensusOutcome {
+    pub action: RiskyAction,
+    pub judgments: Vec<Judgment>,
+    pub resolution: Resolution,
+}
+
+impl ConsensusOutcome {
+    /// 拒绝时返回给模型的说明
+    pub fn message(&self) -> String {
+        let reasons: Vec<String> = self.judgments.iter().map(|j| format!("{}: {}", j.judge, j.rationale)).collect();
+        let by = match self.resolution {
+            Resolution::UserRejected => "the user after the reviewers disagreed",
+            _ => "both reviewers",
+        };
+        format!("Consensus check: this {} was rejected by {}. {}", self.action.kind, by, reasons.join(" | "))
+    }
+}
+
+/// 共识检查
+pub struct ConsensusGate {
+    primary: Arc<dyn Judge>,
+    reviewer: Arc<dyn Judge>,
+    confirm: Arc<dyn Confirm>,
+    large_delete_paths: usize,
+}
+
+impl ConsensusGate {
+    pub fn new(primary: Arc<dyn Judge>, reviewer: Arc<dyn Judge>, confirm: Arc<dyn Confirm>) -> Self {
+        Self { primary, reviewer, confirm, large_delete_paths: ConsensusConfig::default().large_delete_paths }
+    }
+
+    /// 主评审使用当前模型和 API 地址，第二评审使用配置中的模型和（可选的）独立 API 地址
+    pub fn from_config(config: &crate::config::ClaudeConfig) -> Result<Self> {
+        let consensus = &config.permissions.consensus;
+        let primary_model = consensus.primary_model.clone().unwrap_or_else(|| config.api.default_model.clone());
+        let timeout = std::time::Duration::from_secs(config.api.timeout.max(1));
+        let primary_client = NetworkManager::with_config(config.api.base_url.clone(), timeout)?;
+        let reviewer_base_url = consensus.reviewer_base_url.clone().unwrap_or_else(|| config.api.base_url.clone());
+        let reviewer_client = NetworkManager::with_config(reviewer_base_url, timeout)?;
+        Ok(Self {
+            primary: Arc::new(ModelJudge::new(primary_client, primary_model)),
+            reviewer: Arc::new(ModelJudge::new(reviewer_client, consensus.reviewer_model.clone())),
+            confirm: Arc::new(TerminalConfirm),
+            large_delete_paths: consensus.large_delete_paths,
+        })
+    }
+
+    /// 命令需要共识时返回待评审的操作
+    pub fn classify(&self, command: &str) -> Option<RiskyAction> {
+        classify(command, self.large_delete_paths)
+    }
+
+    /// 两方并行评审，不一致时询问用户
+    pub async fn review(&self, action: &RiskyAction, working_directory: &str) -> ConsensusOutcome {
+        let prompt = review_prompt(action, working_directory);
+        let (primary, reviewer) = tokio::join!(
+            judgment(self.primary.as_ref(), &prompt),
+            judgment(self.reviewer.as_ref(), &prompt)
+        );
+        let judgments = vec![primary, reviewer];
+
+        let resolution = match (judgments[0].verdict, judgments[1].verdict) {
+            (Verdict::Approve, Verdict::Approve) => Resolution::Approved,
+            (Verdict::Reject, Verdict::Reject) => Resolution::Rejected,
+            _ if self.confirm.confirm(action, &judgments).await => Resolution::UserApproved,
+            _ => Resolution::UserRejected,
+        };
+        if matches!(resolution, Resolution::UserApproved | Resolution::UserRejected) {
+            crate::network::webhooks::notify(crate::network::webhooks::WebhookEvent::PermissionEscalation {
+                actor: "consensus check".to_string(),
+                action: action.command.clone(),
+                decision: if resolution.proceeds() { "approved by user" } else { "rejected by user" }.to_string(),
+            });
+        }
+        ConsensusOutcome { action: action.clone(), judgments, resolution }
+    }
+}
+
+fn review_prompt(action: &RiskyAction, working_directory: &str) -> String {
+    format!(
+        "A coding agent wants to run a high-risk {} in {}.\n\nCommand:\n{}\n\nSummary: {}\n\n\
+         Decide independently whether running this command is safe and appropriate without further human review. \
+         Consider what data could be lost or which shared history could be rewritten.\n\
+         Reply with JSON only: {{\"verdict\": \"approve\" or \"reject\", \"rationale\": \"one or two sentences\"}}",
+        action.kind, working_directory, action.command, action.summary
+    )
+}
+
+async fn judgment(judge: &dyn Judge, prompt: &str) -> Judgment {
+    let (verdict, rationale) = match judge.judge(prompt).await {
+        Ok(reply) => parse_judgment(&reply),
+        Err(e) => (Verdict::Unavailable, format!("review failed: {}", e)),
+    };
+    Judgment { judge: judge.name(), verdict, rationale }
+}
+
+/// 解析 `{"verdict": ..., "rationale": ...}`，允许回复中夹带其他文字
+fn parse_judgment(reply: &str) -> (Verdict, String) {
+    #[derive(Deserialize)]
+    struct Reply {
+        verdict: String,
+        #[serde(default)]
+        rationale: String,
+    }
+
+    let json = match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => reply,
+    };
+    match serde_json::from_str::<Reply>(json) {
+        Ok(parsed) => {
+            let verdict = match parsed.verdict.trim().to_lowercase().as_str() {
+                "approve" | "approved" | "yes" => Verdict::Approve,
+                "reject" | "rejected" | "no" => Verdict::Reject,
+                _ => Verdict::Unavailable,
+            };
+            (verdict, parsed.rationale)
+        }
+        Err(_) => (Verdict::Unavailable, format!("unparseable review: {}", reply.trim().chars().take(200).collect::<String>())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Fixed(&'static str, &'static str);
+
+    #[async_trait]
+    impl Judge for Fixed {
+        fn name(&self) -> String {
+            self.0.to_string()
+        }
+
+        async fn judge(&self, _prompt: &str) -> Result<String> {
+            Ok(self.1.to_string())
+        }
+    }
+
+    struct Answer(bool, AtomicUsize);
+
+    #[async_trait]
+    impl Confirm for Answer {
+        async fn confirm(&self, _action: &RiskyAction, judgments: &[Judgment]) -> bool {
+            assert_eq!(judgments.len(), 2);
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_classifies_and_escalates_disagreement() {
+        assert_eq!(classify("rm -rf build/", 10).unwrap().kind, RiskKind::LargeDelete);
+        assert_eq!(classify("git push -f origin feature/x", 10).unwrap().kind, RiskKind::ForcePush);
+        assert_eq!(classify("cd api && diesel migration run", 10).unwrap().kind, RiskKind::Migration);
+        assert_eq!(classify("psql -c 'DROP TABLE users'", 10).unwrap().kind, RiskKind::Migration);
+        assert!(classify("rm a.txt b.txt", 10).is_none());
+        assert!(classify("git push origin main", 10).is_none());
+
+        let approve = r#"{"verdict": "approve", "rationale": "build output is regenerated"}"#;
+        let reject = r#"Sure. {"verdict": "reject", "rationale": "the branch is shared"}"#;
+        let action = classify("git push --force origin feature/x", 10).unwrap();
+
+        let confirm = Arc::new(Answer(true, AtomicUsize::new(0)));
+        let agree = ConsensusGate::new(Arc::new(Fixed("a", approve)), Arc::new(Fixed("b", approve)), confirm.clone());
+        assert_eq!(agree.review(&action, "/repo").await.resolution, Resolution::Approved);
+        assert_eq!(confirm.1.load(Ordering::SeqCst), 0);
+
+        let split = ConsensusGate::new(Arc::new(Fixed("a", approve)), Arc::new(Fixed("b", reject)), confirm.clone());
+        let outcome = split.review(&action, "/repo").await;
+        assert_eq!(outcome.resolution, Resolution::UserApproved);
+        assert_eq!(outcome.judgments[1].rationale, "the branch is shared");
+        assert_eq!(confirm.1.load(Ordering::SeqCst), 1);
+
+        let denied = ConsensusGate::new(Arc::new(Fixed("a", "hmm")), Arc::new(Fixed("b", approve)), Arc::new(Answer(false, AtomicUsize::new(0))));
+        let outcome = denied.review(&action, "/repo").await;
+        assert_eq!(outcome.judgments[0].verdict, Verdict::Unavailable);
+        assert!(!outcome.resolution.proceeds());
+        assert!(outcome.message().contains("rejected by the user"));
+    }
+}
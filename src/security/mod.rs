@@ -1,3 +1,7 @@
+pub mod command_guard;
+pub mod consensus;
+pub mod trust;
+
 use crate::error::{ClaudeError, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
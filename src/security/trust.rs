@@ -0,0 +1,137 @@
+//! 项目文件夹信任
+//!
+//! 首次在新目录运行时询问是否信任该目录，记录受信任的路径；
+//! 未受信任目录中的钩子、命令和 MCP 服务器不会被自动加载
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+
+/// 受信任的文件夹
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TrustedFolder {
+    /// 规范化后的路径
+    pub path: PathBuf,
+    /// 信任时间
+    pub trusted_at: DateTime<Utc>,
+}
+
+/// 文件夹信任记录
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    /// 存储文件路径
+    store_path: PathBuf,
+    /// 受信任的文件夹
+    folders: Vec<TrustedFolder>,
+}
+
+impl TrustStore {
+    /// 默认存储路径（配置目录下的 trusted_folders.json）
+    pub fn default_path() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir()
+            .ok_or_else(|| ClaudeError::config_error("Cannot find config directory"))?;
+        Ok(config_dir.join("claude-code").join("trusted_folders.json"))
+    }
+
+    /// 从默认路径加载
+    pub fn load_default() -> Result<Self> {
+        Self::load(Self::default_path()?)
+    }
+
+    /// 从指定路径加载，文件不存在时返回空记录
+    pub fn load(store_path: PathBuf) -> Result<Self> {
+        let folders = match std::fs::read_to_string(&store_path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { store_path, folders })
+    }
+
+    /// 保存信任记录
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.store_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.store_path, serde_json::to_string_pretty(&self.folders)?)?;
+        Ok(())
+    }
+
+    /// 目录本身或其任一上级目录受信任时返回 true
+    pub fn is_trusted(&self, dir: &Path) -> bool {
+        let dir = normalize(dir);
+        self.folders.iter().any(|folder| dir.starts_with(&folder.path))
+    }
+
+    /// 信任目录，返回是否为新增记录
+    pub fn trust(&mut self, dir: &Path) -> bool {
+        let path = normalize(dir);
+        if self.folders.iter().any(|folder| folder.path == path) {
+            return false;
+        }
+        self.folders.push(TrustedFolder {
+            path,
+            trusted_at: Utc::now(),
+        });
+        true
+    }
+
+    /// 撤销对目录的信任，返回是否存在该记录
+    pub fn revoke(&mut self, dir: &Path) -> bool {
+        let path = normalize(dir);
+        let before = self.folders.len();
+        self.folders.retain(|folder| folder.path != path);
+        self.folders.len() != before
+    }
+
+    /// 所有受信任的文件夹
+    pub fn list(&self) -> &[TrustedFolder] {
+        &self.folders
+    }
+}
+
+/// 当前工作目录是否受信任，无法读取信任记录时视为不受信任
+pub fn is_current_dir_trusted() -> bool {
+    let Ok(dir) = std::env::current_dir() else {
+        return false;
+    };
+    TrustStore::load_default()
+        .map(|store| store.is_trusted(&dir))
+        .unwrap_or(false)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_trust_and_revoke() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("project");
+        let nested = project.join("src");
+        std::fs::create_dir_all(&nested).unwrap();
+        let store_path = temp_dir.path().join("trust.json");
+
+        let mut store = TrustStore::load(store_path.clone()).unwrap();
+        assert!(!store.is_trusted(&project));
+
+        assert!(store.trust(&project));
+        assert!(!store.trust(&project));
+        assert!(store.is_trusted(&nested));
+        assert!(!store.is_trusted(temp_dir.path()));
+        store.save().unwrap();
+
+        let mut reloaded = TrustStore::load(store_path).unwrap();
+        assert_eq!(reloaded.list().len(), 1);
+        assert!(reloaded.revoke(&project));
+        assert!(!reloaded.revoke(&project));
+        assert!(!reloaded.is_trusted(&nested));
+    }
+}
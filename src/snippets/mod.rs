@@ -0,0 +1,373 @@
+//! 代码片段库
+//!
+//! 把模型回复中的代码块保存为命名片段，之后列出、搜索，或插入文件和提示中。
+//! 片段分项目级（`.claude-code/snippets`）和用户级（配置目录下的 `claude-code/snippets`）两处保存，
+//! 每处用 `index.json` 记录元数据，片段内容单独存为文件；同名时项目级优先
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::error::{ClaudeError, Result};
+
+/// 索引文件名
+const INDEX_FILE: &str = "index.json";
+
+/// 片段的保存位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnippetScope {
+    Project,
+    User,
+}
+
+impl std::fmt::Display for SnippetScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Project => write!(f, "project"),
+            Self::User => write!(f, "user"),
+        }
+    }
+}
+
+/// 片段元数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    pub name: String,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// 内容文件名，相对片段目录
+    pub file: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 回复中的一个代码块
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    pub language: Option<String>,
+    pub code: String,
+}
+
+/// 提取 Markdown 文本中的围栏代码块
+pub fn code_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(Option<String>, Vec<&str>)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match current.as_mut() {
+            None => {
+                if let Some(info) = trimmed.strip_prefix("```") {
+                    let language = info.split_whitespace().next().map(str::to_string);
+                    current = Some((language, Vec::new()));
+                }
+            }
+            Some(_) if trimmed.starts_with("```") => {
+                if let Some((language, lines)) = current.take() {
+                    blocks.push(CodeBlock { language, code: lines.join("\n") });
+                }
+            }
+            Some((_, lines)) => lines.push(line),
+        }
+    }
+    blocks
+}
+
+/// 片段名只允许字母、数字、`-`、`_` 和 `.`，且不以 `.` 开头
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(ClaudeError::validation_error(
+            "name",
+            format!("Invalid snippet name '{}': use letters, digits, '-', '_' or '.'", name),
+        ))
+    }
+}
+
+/// 常见语言对应的文件扩展名，未知语言用 `txt`
+fn extension_for(language: Option<&str>) -> &'static str {
+    match language.map(|l| l.to_ascii_lowercase()).as_deref() {
+        Some("rust" | "rs") => "rs",
+        Some("python" | "py") => "py",
+        Some("javascript" | "js") => "js",
+        Some("typescript" | "ts") => "ts",
+        Some("tsx") => "tsx",
+        Some("go") => "go",
+        Some("java") => "java",
+        Some("c") => "c",
+        Some("cpp" | "c++") => "cpp",
+        Some("sh" | "bash" | "shell" | "zsh") => "sh",
+        Some("sql") => "sql",
+        Some("json") => "json",
+        Some("yaml" | "yml") => "yaml",
+        Some("toml") => "toml",
+        Some("html") => "html",
+        Some("css") => "css",
+        Some("markdown" | "md") => "md",
+        _ => "txt",
+    }
+}
+
+/// 一个目录下的片段
+#[derive(Debug)]
+pub struct SnippetStore {
+    dir: PathBuf,
+    scope: SnippetScope,
+    snippets: Vec<Snippet>,
+}
+
+impl SnippetStore {
+    /// 读取目录下的索引，不存在时为空
+    pub fn load(dir: impl Into<PathBuf>, scope: SnippetScope) -> Result<Self> {
+        let dir = dir.into();
+        let snippets = match std::fs::read_to_string(dir.join(INDEX_FILE)) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { dir, scope, snippets })
+    }
+
+    /// 项目级片段目录
+    pub fn project_dir(root: &Path) -> PathBuf {
+        root.join(".claude-code").join("snippets")
+    }
+
+    /// 用户级片段目录
+    pub fn user_dir() -> PathBuf {
+        dirs::config_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("snippets")
+    }
+
+    pub fn scope(&self) -> SnippetScope {
+        self.scope
+    }
+
+    pub fn list(&self) -> &[Snippet] {
+        &self.snippets
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Snippet> {
+        self.snippets.iter().find(|s| s.name == name)
+    }
+
+    /// 读取片段内容
+    pub fn content(&self, snippet: &Snippet) -> Result<String> {
+        std::fs::read_to_string(self.dir.join(&snippet.file))
+            .map_err(|e| ClaudeError::fs_error(format!("Failed to read snippet '{}': {}", snippet.name, e)))
+    }
+
+    /// 保存片段，同名时覆盖内容并保留创建时间
+    pub fn save(&mut self, name: &str, language: Option<&str>, description: Option<&str>, content: &str) -> Result<&Snippet> {
+        validate_name(name)?;
+        std::fs::create_dir_all(&self.dir)?;
+
+        let now = Utc::now();
+        let file = format!("{}.{}", name, extension_for(language));
+        let created_at = match self.snippets.iter().position(|s| s.name == name) {
+            Some(index) => {
+                let old = self.snippets.remove(index);
+                if old.file != file {
+                    let _ = std::fs::remove_file(self.dir.join(&old.file));
+                }
+                old.created_at
+            }
+            None => now,
+        };
+        let mut body = content.to_string();
+        if !body.ends_with('\n') {
+            body.push('\n');
+        }
+        std::fs::write(self.dir.join(&file), body)?;
+
+        self.snippets.push(Snippet {
+            name: name.to_string(),
+            language: language.map(str::to_string),
+            description: description.map(str::to_string),
+            file,
+            created_at,
+            updated_at: now,
+        });
+        self.snippets.sort_by(|a, b| a.name.cmp(&b.name));
+        self.write_index()?;
+        Ok(self.get(name).expect("snippet was just saved"))
+    }
+
+    /// 删除片段，返回是否存在
+    pub fn remove(&mut self, name: &str) -> Result<bool> {
+        let Some(index) = self.snippets.iter().position(|s| s.name == name) else {
+            return Ok(false);
+        };
+        let snippet = self.snippets.remove(index);
+        let _ = std::fs::remove_file(self.dir.join(&snippet.file));
+        self.write_index()?;
+        Ok(true)
+    }
+
+    fn write_index(&self) -> Result<()> {
+        std::fs::write(self.dir.join(INDEX_FILE), serde_json::to_string_pretty(&self.snippets)?)?;
+        Ok(())
+    }
+}
+
+/// 项目级和用户级片段的合并视图
+#[derive(Debug)]
+pub struct SnippetLibrary {
+    pub project: SnippetStore,
+    pub user: SnippetStore,
+}
+
+impl SnippetLibrary {
+    pub fn open(root: &Path) -> Result<Self> {
+        Ok(Self {
+            project: SnippetStore::load(SnippetStore::project_dir(root), SnippetScope::Project)?,
+            user: SnippetStore::load(SnippetStore::user_dir(), SnippetScope::User)?,
+        })
+    }
+
+    pub fn store_mut(&mut self, scope: SnippetScope) -> &mut SnippetStore {
+        match scope {
+            SnippetScope::Project => &mut self.project,
+            SnippetScope::User => &mut self.user,
+        }
+    }
+
+    /// 按名称查找，项目级优先
+    pub fn find(&self, name: &str) -> Option<(&SnippetStore, &Snippet)> {
+        [&self.project, &self.user]
+            .into_iter()
+            .find_map(|store| store.get(name).map(|snippet| (store, snippet)))
+    }
+
+    /// 名称、语言或描述包含查询词（不区分大小写）的片段；查询为空时返回全部
+    pub fn search(&self, query: &str) -> Vec<(&SnippetStore, &Snippet)> {
+        let query = query.to_lowercase();
+        [&self.project, &self.user]
+            .into_iter()
+            .flat_map(|store| store.list().iter().map(move |snippet| (store, snippet)))
+            .filter(|(_, snippet)| {
+                [Some(&snippet.name), snippet.language.as_ref(), snippet.description.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .any(|field| field.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    /// 把提示中的 `@snippet:<name>` 展开为对应的代码块，找不到的引用保持原样
+    pub fn expand_references(&self, prompt: &str) -> String {
+        reference_pattern()
+            .replace_all(prompt, |caps: &regex::Captures| {
+                let name = &caps[1];
+                match self.find(name).and_then(|(store, snippet)| Some((snippet, store.content(snippet).ok()?))) {
+                    Some((snippet, content)) => format!(
+                        "\n```{}\n{}\n```\n",
+                        snippet.language.as_deref().unwrap_or(""),
+                        content.trim_end_matches('\n')
+                    ),
+                    None => caps[0].to_string(),
+                }
+            })
+            .into_owned()
+    }
+
+    /// 提示中引用到且存在的片段名称及内容
+    pub fn referenced(&self, prompt: &str) -> Vec<(String, String)> {
+        reference_pattern()
+            .captures_iter(prompt)
+            .filter_map(|caps| {
+                let (store, snippet) = self.find(&caps[1])?;
+                Some((snippet.name.clone(), store.content(snippet).ok()?))
+            })
+            .collect()
+    }
+}
+
+fn reference_pattern() -> &'static Regex {
+    static REFERENCE: OnceLock<Regex> = OnceLock::new();
+    REFERENCE.get_or_init(|| Regex::new(r"@snippet:([A-Za-z0-9_\-.]*[A-Za-z0-9_\-])").unwrap())
+}
+
+/// 把片段插入文件：`line` 为插入位置之前的行数（0 表示文件开头），缺省时追加到末尾
+pub fn insert_into_file(path: &Path, content: &str, line: Option<usize>) -> Result<()> {
+    let existing = match std::fs::read_to_string(path) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let mut block = content.trim_end_matches('\n').to_string();
+    block.push('\n');
+
+    let updated = match line {
+        Some(line) => {
+            let mut lines: Vec<&str> = existing.split_inclusive('\n').collect();
+            if line > lines.len() {
+                return Err(ClaudeError::validation_error(
+                    "line",
+                    format!("{} has only {} lines", path.display(), lines.len()),
+                ));
+            }
+            lines.insert(line, &block);
+            lines.concat()
+        }
+        None if existing.is_empty() || existing.ends_with('\n') => existing + &block,
+        None => format!("{}\n{}", existing, block),
+    };
+    std::fs::write(path, updated)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_blocks() {
+        let reply = "Here you go:\n```rust\nfn main() {}\n```\nand\n```\necho hi\n```";
+        assert_eq!(
+            code_blocks(reply),
+            vec![
+                CodeBlock { language: Some("rust".to_string()), code: "fn main() {}".to_string() },
+                CodeBlock { language: None, code: "echo hi".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_store_search_and_expand() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut library = SnippetLibrary {
+            project: SnippetStore::load(temp_dir.path().join("project"), SnippetScope::Project).unwrap(),
+            user: SnippetStore::load(temp_dir.path().join("user"), SnippetScope::User).unwrap(),
+        };
+        library.user.save("retry", Some("rust"), Some("Exponential backoff"), "loop {}").unwrap();
+        library.project.save("retry", Some("python"), None, "while True: pass").unwrap();
+        assert!(library.project.save("../escape", None, None, "x").is_err());
+
+        let (store, snippet) = library.find("retry").unwrap();
+        assert_eq!(store.scope(), SnippetScope::Project);
+        assert_eq!(snippet.file, "retry.py");
+        assert_eq!(library.search("BACKOFF").len(), 1);
+
+        let reloaded = SnippetStore::load(temp_dir.path().join("user"), SnippetScope::User).unwrap();
+        assert_eq!(reloaded.content(reloaded.get("retry").unwrap()).unwrap(), "loop {}\n");
+
+        assert_eq!(
+            library.expand_references("Use @snippet:retry. Not @snippet:missing"),
+            "Use \n```python\nwhile True: pass\n```\n. Not @snippet:missing"
+        );
+
+        let target = temp_dir.path().join("main.py");
+        std::fs::write(&target, "import os\nprint(1)\n").unwrap();
+        insert_into_file(&target, "import sys", Some(1)).unwrap();
+        insert_into_file(&target, "# end", None).unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "import os\nimport sys\nprint(1)\n# end\n");
+    }
+}
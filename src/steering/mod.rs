@@ -4,10 +4,12 @@
 
 use std::collections::VecDeque;
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex, Notify};
+use tokio::sync::{mpsc, Notify};
 use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
 use serde::{Deserialize, Serialize};
 use crate::error::{ClaudeError, Result};
+use crate::locks::{rank, BlockingMutex, Mutex};
 
 /// 异步消息队列系统 (h2A 类的 Rust 实现)
 pub struct AsyncMessageQueue<T> {
@@ -30,10 +32,10 @@ where
     /// 创建新的异步消息队列
     pub fn new() -> Self {
         Self {
-            queue: Arc::new(Mutex::new(VecDeque::new())),
+            queue: Arc::new(Mutex::new("steering.queue", rank::STEERING_QUEUE, VecDeque::new())),
             read_notify: Arc::new(Notify::new()),
-            is_done: Arc::new(Mutex::new(false)),
-            error_state: Arc::new(Mutex::new(None)),
+            is_done: Arc::new(Mutex::new("steering.done", rank::STEERING_DONE, false)),
+            error_state: Arc::new(Mutex::new("steering.error", rank::STEERING_ERROR, None)),
             cleanup_callback: None,
         }
     }
@@ -169,6 +171,39 @@ pub struct SteeringController {
     interrupt_receiver: Arc<Mutex<mpsc::UnboundedReceiver<()>>>,
     /// 是否启用实时模式
     real_time_enabled: bool,
+    /// 中止正在执行的工具
+    tool_canceller: ToolCanceller,
+}
+
+/// 中止正在执行的工具的句柄，可以交给其他任务（例如界面的按键处理）
+///
+/// 每次取消后换上新的令牌，之后开始的工具不受影响
+#[derive(Clone)]
+pub struct ToolCanceller {
+    current: Arc<BlockingMutex<CancellationToken>>,
+}
+
+impl ToolCanceller {
+    fn new() -> Self {
+        Self {
+            current: Arc::new(BlockingMutex::new(
+                "steering.tool_cancel",
+                rank::STEERING_TOOL_CANCEL,
+                CancellationToken::new(),
+            )),
+        }
+    }
+
+    /// 供下一批工具使用的令牌
+    pub fn token(&self) -> CancellationToken {
+        self.current.lock().child_token()
+    }
+
+    /// 中止所有用当前令牌执行的工具
+    pub fn cancel(&self) {
+        let previous = std::mem::take(&mut *self.current.lock());
+        previous.cancel();
+    }
 }
 
 impl SteeringController {
@@ -179,11 +214,27 @@ impl SteeringController {
         Self {
             message_queue: AsyncMessageQueue::new(),
             interrupt_sender,
-            interrupt_receiver: Arc::new(Mutex::new(interrupt_receiver)),
+            interrupt_receiver: Arc::new(Mutex::new("steering.interrupt", rank::STEERING_INTERRUPT, interrupt_receiver)),
             real_time_enabled: true,
+            tool_canceller: ToolCanceller::new(),
         }
     }
 
+    /// 中止正在执行的工具的句柄
+    pub fn tool_canceller(&self) -> ToolCanceller {
+        self.tool_canceller.clone()
+    }
+
+    /// 供下一批工具使用的取消令牌
+    pub fn tool_cancellation_token(&self) -> CancellationToken {
+        self.tool_canceller.token()
+    }
+
+    /// 中止正在执行的工具，Agent 把它们作为失败结果交给模型并继续运行
+    pub fn cancel_tools(&self) {
+        self.tool_canceller.cancel();
+    }
+
     /// 启用/禁用实时模式
     pub fn set_real_time_mode(&mut self, enabled: bool) {
         self.real_time_enabled = enabled;
@@ -208,8 +259,10 @@ impl SteeringController {
         self.message_queue.enqueue(message).await
     }
 
-    /// 发送中断信号
+    /// 发送中断信号，同时中止正在执行的工具
     pub async fn send_interrupt(&self, reason: String) -> Result<()> {
+        self.cancel_tools();
+
         // 发送中断消息到队列
         let message = SteeringMessage::Interrupt { reason };
         self.message_queue.enqueue(message).await?;
@@ -0,0 +1,225 @@
+//! 可取消的流式生成
+//!
+//! 把消息事件流包装为 [`CancellableStream`] 后得到一个 [`CancelHandle`]。嵌入方可以在任意线程调用
+//! `cancel()`：流随即结束并断开连接，调用同步返回到此为止生成的文本、用量，以及取消前是否已有
+//! 带副作用的工具执行过
+
+use futures::stream::Stream;
+use futures::task::AtomicWaker;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use super::{TickerSnapshot, UsageTicker};
+use crate::error::Result;
+use crate::network::StreamEvent;
+
+/// 取消（或查询）时的生成进度
+#[derive(Debug, Clone)]
+pub struct PartialGeneration {
+    /// 已生成的文本
+    pub text: String,
+    /// 截至此时的用量，输出 token 含按字符估算的部分
+    pub usage: TickerSnapshot,
+    /// 模型发起的工具调用，按出现顺序
+    pub tool_calls: Vec<String>,
+    /// 嵌入方报告的、已经执行过的带副作用的工具
+    pub side_effects: Vec<String>,
+    /// 生成已经正常结束
+    pub completed: bool,
+    pub cancelled: bool,
+}
+
+impl PartialGeneration {
+    pub fn had_side_effects(&self) -> bool {
+        !self.side_effects.is_empty()
+    }
+}
+
+struct Progress {
+    text: String,
+    ticker: UsageTicker,
+    tool_calls: Vec<String>,
+    side_effects: Vec<String>,
+    completed: bool,
+    cancelled: bool,
+}
+
+impl Progress {
+    fn snapshot(&self) -> PartialGeneration {
+        PartialGeneration {
+            text: self.text.clone(),
+            usage: self.ticker.snapshot(),
+            tool_calls: self.tool_calls.clone(),
+            side_effects: self.side_effects.clone(),
+            completed: self.completed,
+            cancelled: self.cancelled,
+        }
+    }
+
+    fn record(&mut self, event: &StreamEvent) {
+        let payload = event.data.clone().unwrap_or_default();
+        self.ticker.record_event(&event.event_type, &payload);
+        match event.event_type.as_str() {
+            "content_block_delta" => {
+                if let Some(text) = payload["delta"]["text"].as_str() {
+                    self.text.push_str(text);
+                }
+            }
+            "content_block_start" if payload["content_block"]["type"] == "tool_use" => {
+                let name = payload["content_block"]["name"].as_str().unwrap_or("unknown");
+                self.tool_calls.push(name.to_string());
+            }
+            "message_stop" => self.completed = true,
+            _ => {}
+        }
+    }
+}
+
+struct Shared {
+    progress: Mutex<Progress>,
+    waker: AtomicWaker,
+}
+
+impl Shared {
+    fn progress(&self) -> std::sync::MutexGuard<'_, Progress> {
+        self.progress.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// 取消句柄，可以克隆并发送到其他线程
+#[derive(Clone)]
+pub struct CancelHandle {
+    shared: Arc<Shared>,
+}
+
+impl CancelHandle {
+    /// 取消生成并返回到此为止的进度。之后流不再产生事件；生成已经结束时只返回最终结果
+    pub fn cancel(&self) -> PartialGeneration {
+        let snapshot = {
+            let mut progress = self.shared.progress();
+            if !progress.completed {
+                progress.cancelled = true;
+            }
+            progress.snapshot()
+        };
+        self.shared.waker.wake();
+        snapshot
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.progress().cancelled
+    }
+
+    /// 当前进度，不影响生成
+    pub fn snapshot(&self) -> PartialGeneration {
+        self.shared.progress().snapshot()
+    }
+
+    /// 执行了写文件、运行命令等带副作用的工具后调用，取消时据此判断是否需要回滚
+    pub fn record_side_effect(&self, tool: impl Into<String>) {
+        self.shared.progress().side_effects.push(tool.into());
+    }
+}
+
+/// 可取消的消息事件流，取消后丢弃底层流以断开连接
+pub struct CancellableStream<S> {
+    inner: Option<S>,
+    shared: Arc<Shared>,
+}
+
+impl<S> CancellableStream<S>
+where
+    S: Stream<Item = Result<StreamEvent>> + Unpin,
+{
+    /// `model` 用于估算费用
+    pub fn new(inner: S, model: &str) -> (Self, CancelHandle) {
+        let shared = Arc::new(Shared {
+            progress: Mutex::new(Progress {
+                text: String::new(),
+                ticker: UsageTicker::new(model),
+                tool_calls: Vec::new(),
+                side_effects: Vec::new(),
+                completed: false,
+                cancelled: false,
+            }),
+            waker: AtomicWaker::new(),
+        });
+        (Self { inner: Some(inner), shared: shared.clone() }, CancelHandle { shared })
+    }
+}
+
+impl<S> Stream for CancellableStream<S>
+where
+    S: Stream<Item = Result<StreamEvent>> + Unpin,
+{
+    type Item = Result<StreamEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.shared.waker.register(cx.waker());
+        if self.shared.progress().cancelled {
+            self.inner = None;
+        }
+        let Some(inner) = self.inner.as_mut() else {
+            return Poll::Ready(None);
+        };
+
+        let polled = Pin::new(inner).poll_next(cx);
+        match polled {
+            Poll::Ready(Some(Ok(event))) => {
+                // 与 cancel() 在同一把锁下判断，取消后返回的快照之外不会再交出事件
+                let mut progress = self.shared.progress();
+                if progress.cancelled {
+                    drop(progress);
+                    self.inner = None;
+                    return Poll::Ready(None);
+                }
+                progress.record(&event);
+                Poll::Ready(Some(Ok(event)))
+            }
+            Poll::Ready(None) => {
+                self.inner = None;
+                Poll::Ready(None)
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn event(payload: serde_json::Value) -> Result<StreamEvent> {
+        Ok(StreamEvent { event_type: payload["type"].as_str().unwrap().to_string(), data: Some(payload) })
+    }
+
+    #[tokio::test]
+    async fn test_cancel_returns_partial_output_and_ends_stream() {
+        let events = futures::stream::iter(vec![
+            event(serde_json::json!({"type": "message_start", "message": {"usage": {"input_tokens": 40, "output_tokens": 1}}})),
+            event(serde_json::json!({"type": "content_block_delta", "delta": {"type": "text_delta", "text": "Renaming the "}})),
+            event(serde_json::json!({"type": "content_block_start", "content_block": {"type": "tool_use", "name": "bash"}})),
+        ])
+        .chain(futures::stream::pending());
+        let (mut stream, handle) = CancellableStream::new(Box::pin(events), "claude-3-haiku-20240307");
+
+        for _ in 0..3 {
+            stream.next().await.unwrap().unwrap();
+        }
+        handle.record_side_effect("bash");
+
+        let waiter = tokio::spawn(async move { stream.next().await.is_none() });
+        tokio::task::yield_now().await;
+        let partial = std::thread::spawn(move || handle.cancel()).join().unwrap();
+        assert!(waiter.await.unwrap());
+
+        assert!(partial.cancelled && !partial.completed);
+        assert_eq!(partial.text, "Renaming the ");
+        assert_eq!(partial.usage.input_tokens, 40);
+        assert!(partial.usage.output_tokens >= 1);
+        assert_eq!(partial.tool_calls, vec!["bash"]);
+        assert!(partial.had_side_effects());
+    }
+}
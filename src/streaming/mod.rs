@@ -2,7 +2,10 @@
 //! 
 //! 实现 Server-Sent Events (SSE) 解析和实时输出处理
 
-use std::collections::HashMap;
+pub mod cancel;
+pub mod sse;
+
+use std::collections::{HashMap, VecDeque};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
@@ -13,6 +16,7 @@ use tokio::sync::{broadcast, mpsc};
 use tokio::time::interval;
 
 use crate::error::{ClaudeError, Result};
+use sse::{RawEvent, SseDecoder};
 
 /// SSE 事件类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -123,10 +127,8 @@ impl Default for StreamConfig {
 
 /// SSE 解析器
 pub struct SseParser {
-    /// 缓冲区
-    buffer: String,
-    /// 当前事件
-    current_event: Option<SseEvent>,
+    /// 字节流解码器
+    decoder: SseDecoder,
     /// 解析统计
     stats: StreamStats,
 }
@@ -135,99 +137,59 @@ impl SseParser {
     /// 创建新的 SSE 解析器
     pub fn new() -> Self {
         Self {
-            buffer: String::new(),
-            current_event: None,
+            decoder: SseDecoder::new(),
             stats: StreamStats::default(),
         }
     }
 
     /// 解析 SSE 数据块
     pub fn parse_chunk(&mut self, chunk: &str) -> Result<Vec<SseEvent>> {
-        self.buffer.push_str(chunk);
+        self.parse_bytes(chunk.as_bytes())
+    }
+
+    /// 解析原始字节块，块边界可以落在多字节字符或行的中间
+    pub fn parse_bytes(&mut self, chunk: &[u8]) -> Result<Vec<SseEvent>> {
         self.stats.bytes_received += chunk.len() as u64;
-        
+
         let mut events = Vec::new();
-        
-        // 按行分割处理
-        while let Some(line_end) = self.buffer.find('\n') {
-            let line = self.buffer[..line_end].trim_end_matches('\r').to_string();
-            self.buffer.drain(..=line_end);
-
-            if line.is_empty() {
-                // 空行表示事件结束
-                if let Some(event) = self.current_event.take() {
-                    events.push(event);
-                    self.stats.events_received += 1;
-
-                    if self.stats.first_event_time.is_none() {
-                        self.stats.first_event_time = Some(Instant::now());
-                    }
-                    self.stats.last_event_time = Some(Instant::now());
-                }
-            } else {
-                self.parse_line(&line)?;
-            }
+        let result = self.decoder.decode(chunk, |raw| events.push(Self::build_event(raw)));
+        if result.is_err() {
+            self.stats.error_count += 1;
         }
-        
+        self.record_events(events.len());
+        result.map(|_| events)
+    }
+
+    /// 流结束时取出缺少结尾空行的最后一个事件
+    pub fn finish(&mut self) -> Result<Vec<SseEvent>> {
+        let mut events = Vec::new();
+        self.decoder.finish(|raw| events.push(Self::build_event(raw)))?;
+        self.record_events(events.len());
         Ok(events)
     }
 
-    /// 解析单行 SSE 数据
-    fn parse_line(&mut self, line: &str) -> Result<()> {
-        if line.starts_with(':') {
-            // 注释行，忽略
-            return Ok(());
+    fn build_event(raw: RawEvent<'_>) -> SseEvent {
+        SseEvent {
+            event_type: Self::parse_event_type(raw.event.unwrap_or("unknown")),
+            data: match raw.data {
+                "" => serde_json::Value::Null,
+                // 不是有效的 JSON 时作为字符串处理
+                data => serde_json::from_str(data).unwrap_or_else(|_| serde_json::Value::String(data.to_string())),
+            },
+            id: raw.id.map(str::to_string),
+            retry: raw.retry,
+            timestamp: Instant::now(),
         }
-        
-        let (field, value) = if let Some(colon_pos) = line.find(':') {
-            let field = &line[..colon_pos];
-            let value = line[colon_pos + 1..].trim_start();
-            (field, value)
-        } else {
-            (line, "")
-        };
-        
-        // 确保有当前事件
-        if self.current_event.is_none() {
-            self.current_event = Some(SseEvent {
-                event_type: SseEventType::Custom("unknown".to_string()),
-                data: serde_json::Value::Null,
-                id: None,
-                retry: None,
-                timestamp: Instant::now(),
-            });
-        }
-        
-        let event = self.current_event.as_mut().unwrap();
-        
-        match field {
-            "event" => {
-                event.event_type = Self::parse_event_type(value);
-            }
-            "data" => {
-                // 解析 JSON 数据
-                match serde_json::from_str(value) {
-                    Ok(json_value) => event.data = json_value,
-                    Err(_) => {
-                        // 如果不是有效的 JSON，作为字符串处理
-                        event.data = serde_json::Value::String(value.to_string());
-                    }
-                }
-            }
-            "id" => {
-                event.id = Some(value.to_string());
-            }
-            "retry" => {
-                if let Ok(retry_ms) = value.parse::<u64>() {
-                    event.retry = Some(retry_ms);
-                }
-            }
-            _ => {
-                // 未知字段，忽略
-            }
+    }
+
+    fn record_events(&mut self, count: usize) {
+        if count == 0 {
+            return;
         }
-        
-        Ok(())
+        let now = Instant::now();
+        self.stats.events_received += count as u64;
+        self.stats.first_event_time.get_or_insert(now);
+        self.stats.last_event_time = Some(now);
     }
 
     /// 解析事件类型
@@ -252,12 +214,45 @@ impl SseParser {
 
     /// 重置解析器
     pub fn reset(&mut self) {
-        self.buffer.clear();
-        self.current_event = None;
+        self.decoder.reset();
         self.stats = StreamStats::default();
     }
 }
 
+/// 把字节流转换为 SSE 事件流
+///
+/// 只有消费者拉取下一个事件时才会读取网络数据，消费变慢时背压会一直传到 TCP 连接
+pub fn event_stream<S, B>(bytes: S) -> impl Stream<Item = Result<SseEvent>>
+where
+    S: Stream<Item = Result<B>>,
+    B: AsRef<[u8]>,
+{
+    let state = (Box::pin(bytes), SseParser::new(), VecDeque::new(), false);
+    futures::stream::unfold(state, |(mut bytes, mut parser, mut pending, mut done)| async move {
+        loop {
+            if let Some(event) = pending.pop_front() {
+                return Some((Ok(event), (bytes, parser, pending, done)));
+            }
+            if done {
+                return None;
+            }
+
+            let result = match bytes.next().await {
+                Some(Ok(chunk)) => parser.parse_bytes(chunk.as_ref()),
+                Some(Err(e)) => Err(e),
+                None => {
+                    done = true;
+                    parser.finish()
+                }
+            };
+            match result {
+                Ok(events) => pending.extend(events),
+                Err(e) => return Some((Err(e), (bytes, parser, pending, true))),
+            }
+        }
+    })
+}
+
 /// 流式响应处理器
 pub struct StreamProcessor {
     /// 配置
@@ -268,6 +263,8 @@ pub struct StreamProcessor {
     parser: SseParser,
     /// 事件发送器
     event_sender: broadcast::Sender<SseEvent>,
+    /// 有界事件通道，消费者处理不过来时暂停读取
+    event_channel: Option<mpsc::Sender<SseEvent>>,
     /// 状态发送器
     state_sender: broadcast::Sender<StreamState>,
     /// 统计信息
@@ -286,10 +283,20 @@ impl StreamProcessor {
             parser: SseParser::new(),
             event_sender,
             state_sender,
+            event_channel: None,
             stats: StreamStats::default(),
         }
     }
 
+    /// 获取带背压的事件接收器
+    ///
+    /// 与广播订阅不同，通道容量为 `buffer_size`，通道满时处理数据块会等待消费者，而不是丢弃事件
+    pub fn event_channel(&mut self) -> mpsc::Receiver<SseEvent> {
+        let (sender, receiver) = mpsc::channel(self.config.buffer_size.max(1));
+        self.event_channel = Some(sender);
+        receiver
+    }
+
     /// 获取事件接收器
     pub fn subscribe_events(&self) -> broadcast::Receiver<SseEvent> {
         self.event_sender.subscribe()
@@ -302,6 +309,11 @@ impl StreamProcessor {
 
     /// 处理数据块
     pub async fn process_chunk(&mut self, chunk: &str) -> Result<()> {
+        self.process_bytes(chunk.as_bytes()).await.map(|_| ())
+    }
+
+    /// 处理原始字节块，返回解析出的事件
+    pub async fn process_bytes(&mut self, chunk: &[u8]) -> Result<Vec<SseEvent>> {
         if self.state == StreamState::Disconnected {
             self.set_state(StreamState::Connected).await;
         }
@@ -310,12 +322,29 @@ impl StreamProcessor {
             self.set_state(StreamState::Streaming).await;
         }
         
-        let events = self.parser.parse_chunk(chunk)?;
-        
+        let events = self.parser.parse_bytes(chunk)?;
+        self.dispatch(&events).await;
+        Ok(events)
+    }
+
+    /// 流结束，派发缺少结尾空行的最后一个事件
+    pub async fn finish(&mut self) -> Result<Vec<SseEvent>> {
+        let events = self.parser.finish()?;
+        self.dispatch(&events).await;
+        Ok(events)
+    }
+
+    async fn dispatch(&mut self, events: &[SseEvent]) {
         for event in events {
+            if let Some(channel) = &self.event_channel {
+                if channel.send(event.clone()).await.is_err() {
+                    self.event_channel = None;
+                }
+            }
+
             // 发送事件
-            if let Err(_) = self.event_sender.send(event.clone()) {
-                tracing::warn!("No active event subscribers");
+            if self.event_sender.receiver_count() > 0 {
+                let _ = self.event_sender.send(event.clone());
             }
             
             // 处理特殊事件
@@ -333,8 +362,6 @@ impl StreamProcessor {
                 _ => {}
             }
         }
-        
-        Ok(())
     }
 
     /// 设置状态
@@ -471,6 +498,8 @@ pub struct StreamingClient {
     processor: StreamProcessor,
     /// 实时输出
     output: RealTimeOutput,
+    /// 用量计数器
+    ticker: Option<UsageTicker>,
 }
 
 impl StreamingClient {
@@ -489,9 +518,20 @@ impl StreamingClient {
             config,
             processor,
             output,
+            ticker: None,
         }
     }
 
+    /// 为指定模型启用用量计数器
+    pub fn enable_ticker(&mut self, model: &str) {
+        self.ticker = Some(UsageTicker::new(model));
+    }
+
+    /// 获取当前用量快照
+    pub fn ticker_snapshot(&self) -> Option<TickerSnapshot> {
+        self.ticker.as_ref().map(|t| t.snapshot())
+    }
+
     /// 开始流式请求
     pub async fn start_stream(&mut self, url: &str, headers: HashMap<String, String>) -> Result<()> {
         let mut request = self.client.get(url);
@@ -509,9 +549,7 @@ impl StreamingClient {
             .map_err(|e| ClaudeError::network_error(format!("Failed to start stream: {}", e)))?;
 
         if !response.status().is_success() {
-            return Err(ClaudeError::network_error(
-                format!("HTTP error: {}", response.status())
-            ));
+            return Err(crate::network::error_from_response(response).await);
         }
 
         // 处理流式响应
@@ -521,24 +559,26 @@ impl StreamingClient {
             let chunk = chunk_result
                 .map_err(|e| ClaudeError::network_error(format!("Stream error: {}", e)))?;
 
-            let text = String::from_utf8_lossy(&chunk);
-
             // 处理 SSE 事件
-            self.processor.process_chunk(&text).await?;
+            let events = self.processor.process_bytes(&chunk).await?;
 
             // 处理实时输出
-            self.process_output_events().await?;
+            self.process_output_events(events);
         }
 
+        let events = self.processor.finish().await?;
+        self.process_output_events(events);
+
         Ok(())
     }
 
     /// 处理输出事件
-    async fn process_output_events(&mut self) -> Result<()> {
-        let mut event_receiver = self.processor.subscribe_events();
+    fn process_output_events(&mut self, events: Vec<SseEvent>) {
+        for event in events {
+            if let Some(ticker) = self.ticker.as_mut() {
+                ticker.record_sse_event(&event);
+            }
 
-        // 非阻塞地处理事件
-        while let Ok(event) = event_receiver.try_recv() {
             match event.event_type {
                 SseEventType::ContentBlockDelta => {
                     if let Some(text) = event.data.get("delta").and_then(|d| d.get("text")) {
@@ -560,8 +600,6 @@ impl StreamingClient {
                 }
             }
         }
-
-        Ok(())
     }
 
     /// 获取事件订阅器
@@ -574,6 +612,11 @@ impl StreamingClient {
         self.processor.subscribe_state()
     }
 
+    /// 获取带背压的事件接收器
+    pub fn event_channel(&mut self) -> mpsc::Receiver<SseEvent> {
+        self.processor.event_channel()
+    }
+
     /// 获取输出接收器
     pub fn take_output_receiver(&mut self) -> Option<mpsc::UnboundedReceiver<String>> {
         self.output.take_receiver()
@@ -586,7 +629,11 @@ impl StreamingClient {
 
     /// 处理数据块（用于测试和模拟）
     pub async fn process_chunk(&mut self, chunk: &str) -> Result<()> {
-        self.processor.process_chunk(chunk).await
+        let events = self.processor.process_bytes(chunk.as_bytes()).await?;
+        if let Some(ticker) = self.ticker.as_mut() {
+            events.iter().for_each(|event| ticker.record_sse_event(event));
+        }
+        Ok(())
     }
 
     /// 重置客户端
@@ -595,3 +642,287 @@ impl StreamingClient {
         self.output = RealTimeOutput::new(Duration::from_millis(100));
     }
 }
+
+/// 流式用量快照
+#[derive(Debug, Clone, Serialize)]
+pub struct TickerSnapshot {
+    /// 输入 token 数
+    pub input_tokens: u32,
+    /// 已生成的输出 token 数（含估算部分）
+    pub output_tokens: u32,
+    /// 估算成本（美元），未知模型时为 None
+    pub estimated_cost: Option<f64>,
+    /// 已耗时（毫秒）
+    pub elapsed_ms: u64,
+    /// 输出速率（tokens/秒）
+    pub tokens_per_second: f64,
+}
+
+impl TickerSnapshot {
+    /// 格式化为状态栏文本
+    pub fn format_status(&self) -> String {
+        let cost = match self.estimated_cost {
+            Some(cost) => format!("${:.4}", cost),
+            None => "$?".to_string(),
+        };
+        format!(
+            "↑{} ↓{} tok | {} | {:.1}s | {:.1} tok/s",
+            self.input_tokens,
+            self.output_tokens,
+            cost,
+            self.elapsed_ms as f64 / 1000.0,
+            self.tokens_per_second
+        )
+    }
+
+    /// 格式化为流结束后的摘要行
+    pub fn format_summary(&self) -> String {
+        format!("📊 {}", self.format_status())
+    }
+}
+
+/// 流式用量实时计数器
+///
+/// 由流式事件驱动：`message_start` 提供输入 token，`message_delta` 提供权威的输出 token，
+/// 两次 `message_delta` 之间的文本增量按 4 字符/token 估算，保证计数器持续跳动。
+#[derive(Debug, Clone)]
+pub struct UsageTicker {
+    /// 模型定价
+    pricing: Option<crate::cost::ModelPricing>,
+    /// 开始时间
+    started_at: Instant,
+    /// 第一个输出 token 到达的时间
+    first_token_at: Option<Instant>,
+    /// 输入 token 数
+    input_tokens: u32,
+    /// 服务端确认的输出 token 数
+    reported_output_tokens: u32,
+    /// 自上次确认以来累计的文本字符数
+    pending_chars: usize,
+}
+
+impl UsageTicker {
+    /// 为指定模型创建计数器
+    pub fn new(model: &str) -> Self {
+        Self {
+            pricing: crate::cost::lookup_default_pricing(model),
+            started_at: Instant::now(),
+            first_token_at: None,
+            input_tokens: 0,
+            reported_output_tokens: 0,
+            pending_chars: 0,
+        }
+    }
+
+    /// 记录一个 SSE 事件
+    pub fn record_sse_event(&mut self, event: &SseEvent) {
+        let event_type = match &event.event_type {
+            SseEventType::MessageStart => "message_start",
+            SseEventType::ContentBlockDelta => "content_block_delta",
+            SseEventType::MessageDelta => "message_delta",
+            _ => return,
+        };
+        self.record_event(event_type, &event.data);
+    }
+
+    /// 记录一个流式事件（事件类型 + 完整 JSON 负载）
+    pub fn record_event(&mut self, event_type: &str, payload: &serde_json::Value) {
+        match event_type {
+            "message_start" => {
+                if let Some(usage) = payload.get("message").and_then(|m| m.get("usage")) {
+                    self.apply_usage(usage);
+                }
+            }
+            "content_block_delta" => {
+                let delta = payload.get("delta");
+                let text = delta
+                    .and_then(|d| d.get("text").or_else(|| d.get("partial_json")))
+                    .and_then(|t| t.as_str());
+                if let Some(text) = text {
+                    if self.first_token_at.is_none() {
+                        self.first_token_at = Some(Instant::now());
+                    }
+                    self.pending_chars += text.chars().count();
+                }
+            }
+            "message_delta" => {
+                if let Some(usage) = payload.get("usage") {
+                    self.apply_usage(usage);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 应用服务端返回的 usage 字段
+    fn apply_usage(&mut self, usage: &serde_json::Value) {
+        if let Some(input) = usage.get("input_tokens").and_then(|v| v.as_u64()) {
+            self.input_tokens = input as u32;
+        }
+        if let Some(output) = usage.get("output_tokens").and_then(|v| v.as_u64()) {
+            self.reported_output_tokens = self.reported_output_tokens.max(output as u32);
+            self.pending_chars = 0;
+        }
+    }
+
+    /// 当前输出 token 数（确认值 + 估算值）
+    pub fn output_tokens(&self) -> u32 {
+        self.reported_output_tokens + self.pending_chars.div_ceil(4) as u32
+    }
+
+    /// 获取当前快照
+    pub fn snapshot(&self) -> TickerSnapshot {
+        let output_tokens = self.output_tokens();
+        let elapsed = self.started_at.elapsed();
+        let generating = self
+            .first_token_at
+            .map(|t| t.elapsed())
+            .unwrap_or(elapsed)
+            .as_secs_f64();
+        let tokens_per_second = if generating > 0.0 {
+            output_tokens as f64 / generating
+        } else {
+            0.0
+        };
+
+        TickerSnapshot {
+            input_tokens: self.input_tokens,
+            output_tokens,
+            estimated_cost: self
+                .pricing
+                .as_ref()
+                .map(|p| p.cost(self.input_tokens, output_tokens)),
+            elapsed_ms: elapsed.as_millis() as u64,
+            tokens_per_second,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA: &[u8] = b"event: content_block_delta\ndata: {\"delta\":{\"text\":\"hi\"}}\n\n";
+
+    #[tokio::test]
+    async fn test_event_stream_reassembles_split_frames() {
+        let body = "event: content_block_delta\ndata: {\"delta\":{\"text\":\"你好\"}}\n\nevent: message_stop\ndata: {}".as_bytes();
+        // 在多字节字符中间切分
+        let split = body.iter().position(|&b| b >= 0x80).unwrap() + 1;
+        let chunks = vec![Ok(body[..split].to_vec()), Ok(body[split..].to_vec())];
+
+        let events: Vec<SseEvent> = event_stream(futures::stream::iter(chunks))
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].data["delta"]["text"], "你好");
+        assert!(matches!(events[1].event_type, SseEventType::MessageStop));
+    }
+
+    #[tokio::test]
+    async fn test_message_stream_against_mock_server() {
+        use crate::network::ClaudeApiClient;
+        use crate::test_support::{sse_event, MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([
+            // 每 7 字节一块，事件行和多字节字符都会被切开
+            MockReply::text_stream(&["你好", ", ", "world"]).rechunked(7, Duration::from_millis(1)),
+            MockReply::tool_use_stream("toolu_1", "read", &["{\"path\":", "\"a.rs\"}"]),
+            MockReply::raw_stream([
+                sse_event("content_block_delta", serde_json::json!({"type": "content_block_delta", "delta": {"type": "text_delta", "text": "ok"}})),
+                "event: content_block_delta\ndata: [1, 2]\n\n".to_string(),
+            ]),
+        ])
+        .await;
+        let client = ClaudeApiClient::new("sk-test".to_string(), Some(server.base_url().to_string())).unwrap();
+        let request = client.create_text_request("claude-mock", vec![("user".to_string(), "hi")]);
+
+        let events: Vec<_> = client.send_message_stream(&request).await.unwrap().map(|e| e.unwrap()).collect().await;
+        let text: String = events.iter().filter_map(|e| e.data.as_ref()?["delta"]["text"].as_str()).collect();
+        assert_eq!(text, "你好, world");
+        assert_eq!(events.last().unwrap().event_type, "message_stop");
+        assert_eq!(server.requests()[0].body["stream"], true);
+        assert_eq!(server.requests()[0].headers["accept"], "text/event-stream");
+
+        let events: Vec<_> = client.send_message_stream(&request).await.unwrap().map(|e| e.unwrap()).collect().await;
+        let input: String = events.iter().filter_map(|e| e.data.as_ref()?["delta"]["partial_json"].as_str()).collect();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&input).unwrap()["path"], "a.rs");
+
+        // 损坏的事件作为错误交给调用方，之前的事件不受影响
+        let results: Vec<_> = client.send_message_stream(&request).await.unwrap().collect().await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_event_channel_backpressure() {
+        let config = StreamConfig { buffer_size: 1, ..StreamConfig::default() };
+        let mut processor = StreamProcessor::new(config);
+        let mut receiver = processor.event_channel();
+
+        processor.process_bytes(DELTA).await.unwrap();
+        // 通道已满，第二个事件要等消费者取走第一个
+        let blocked = tokio::time::timeout(Duration::from_millis(50), processor.process_bytes(DELTA)).await;
+        assert!(blocked.is_err());
+
+        let consumer = tokio::spawn(async move {
+            let mut count = 0;
+            while receiver.recv().await.is_some() {
+                count += 1;
+            }
+            count
+        });
+        processor.process_bytes(DELTA).await.unwrap();
+        drop(processor);
+        assert_eq!(consumer.await.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_usage_ticker_counts_stream_events() {
+        let model = "claude-3-haiku-20240307";
+        let pricing = crate::cost::lookup_default_pricing(model).unwrap();
+        let mut ticker = UsageTicker::new(model);
+        assert_eq!(ticker.snapshot().output_tokens, 0);
+
+        ticker.record_event("message_start", &serde_json::json!({ "message": { "usage": { "input_tokens": 100, "output_tokens": 1 } } }));
+        ticker.record_event("content_block_delta", &serde_json::json!({ "delta": { "type": "text_delta", "text": "hello world!" } }));
+        ticker.record_event("content_block_delta", &serde_json::json!({ "delta": { "type": "input_json_delta", "partial_json": "{\"a\"" } }));
+        // 确认值之外的文本按 4 字符/token 估算
+        let snapshot = ticker.snapshot();
+        assert_eq!(snapshot.input_tokens, 100);
+        assert_eq!(snapshot.output_tokens, 1 + 4);
+
+        // message_delta 的确认值替换估算值，且不会倒退
+        ticker.record_event("message_delta", &serde_json::json!({ "usage": { "output_tokens": 20 } }));
+        assert_eq!(ticker.output_tokens(), 20);
+        ticker.record_event("message_delta", &serde_json::json!({ "usage": { "output_tokens": 3 } }));
+        assert_eq!(ticker.output_tokens(), 20);
+
+        let snapshot = ticker.snapshot();
+        assert_eq!(snapshot.estimated_cost, Some(pricing.cost(100, 20)));
+        assert!(snapshot.format_status().starts_with("↑100 ↓20 tok | $"));
+
+        let unknown = UsageTicker::new("not-a-model").snapshot();
+        assert_eq!(unknown.estimated_cost, None);
+        assert!(unknown.format_summary().contains("$?"));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_client_feeds_ticker() {
+        let mut client = StreamingClient::new(StreamConfig::default());
+        client.process_chunk("event: content_block_delta\ndata: {\"delta\":{\"text\":\"ignored\"}}\n\n").await.unwrap();
+        assert!(client.ticker_snapshot().is_none());
+
+        client.enable_ticker("claude-3-haiku-20240307");
+        client
+            .process_chunk("event: message_start\ndata: {\"message\":{\"usage\":{\"input_tokens\":7}}}\n\nevent: content_block_delta\ndata: {\"delta\":{\"text\":\"12345678\"}}\n\n")
+            .await
+            .unwrap();
+        let snapshot = client.ticker_snapshot().unwrap();
+        assert_eq!((snapshot.input_tokens, snapshot.output_tokens), (7, 2));
+        assert!(snapshot.estimated_cost.is_some());
+    }
+}
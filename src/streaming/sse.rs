@@ -0,0 +1,322 @@
+//! SSE 字节流解码
+//!
+//! 直接在网络字节块上切分行，只对完整的行做 UTF-8 解码，
+//! 跨块截断的多字节字符和帧不会损坏或引发 panic。
+//! 行不跨块时直接借用输入切片，字段缓冲区在事件之间复用
+
+use crate::error::{ClaudeError, Result};
+
+/// 单个事件（含未完成的行）允许的最大字节数，防止异常流耗尽内存
+pub const MAX_EVENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// 解码出的原始事件，字段借用自解码器内部缓冲区
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawEvent<'a> {
+    /// `event` 字段
+    pub event: Option<&'a str>,
+    /// `data` 字段，多行数据以 `\n` 连接
+    pub data: &'a str,
+    /// `id` 字段
+    pub id: Option<&'a str>,
+    /// `retry` 字段（毫秒）
+    pub retry: Option<u64>,
+}
+
+/// SSE 增量解码器
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    /// 跨块的未完成行
+    pending: Vec<u8>,
+    /// 上一块以 `\r` 结尾，下一块开头的 `\n` 属于同一个换行
+    skip_lf: bool,
+    /// 当前事件是否已有字段
+    has_fields: bool,
+    event: String,
+    has_event: bool,
+    data: String,
+    has_data: bool,
+    id: String,
+    has_id: bool,
+    retry: Option<u64>,
+}
+
+impl SseDecoder {
+    /// 创建解码器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 输入一个字节块，每解析出一个完整事件调用一次 `on_event`
+    pub fn decode<F>(&mut self, mut chunk: &[u8], mut on_event: F) -> Result<()>
+    where
+        F: FnMut(RawEvent<'_>),
+    {
+        if self.skip_lf && !chunk.is_empty() {
+            self.skip_lf = false;
+            chunk = chunk.strip_prefix(b"\n").unwrap_or(chunk);
+        }
+
+        while !chunk.is_empty() {
+            let Some(end) = chunk.iter().position(|&b| b == b'\n' || b == b'\r') else {
+                if self.pending.len() + chunk.len() > MAX_EVENT_SIZE {
+                    return Err(self.oversized());
+                }
+                self.pending.extend_from_slice(chunk);
+                break;
+            };
+
+            let terminator_len = match (chunk[end], chunk.get(end + 1)) {
+                (b'\r', Some(b'\n')) => 2,
+                (b'\r', None) => {
+                    self.skip_lf = true;
+                    1
+                }
+                _ => 1,
+            };
+
+            if self.pending.is_empty() {
+                self.process_line(&chunk[..end], &mut on_event)?;
+            } else {
+                let mut line = std::mem::take(&mut self.pending);
+                line.extend_from_slice(&chunk[..end]);
+                self.process_line(&line, &mut on_event)?;
+                line.clear();
+                self.pending = line;
+            }
+            chunk = &chunk[end + terminator_len..];
+        }
+
+        Ok(())
+    }
+
+    /// 流结束：处理最后一行，并派发缺少结尾空行的事件
+    pub fn finish<F>(&mut self, mut on_event: F) -> Result<()>
+    where
+        F: FnMut(RawEvent<'_>),
+    {
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.process_line(&line, &mut on_event)?;
+        }
+        self.dispatch(&mut on_event);
+        self.skip_lf = false;
+        Ok(())
+    }
+
+    /// 丢弃未完成的行和事件
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.skip_lf = false;
+        self.clear_event();
+    }
+
+    fn process_line<F>(&mut self, line: &[u8], on_event: &mut F) -> Result<()>
+    where
+        F: FnMut(RawEvent<'_>),
+    {
+        if line.is_empty() {
+            self.dispatch(on_event);
+            return Ok(());
+        }
+        if line[0] == b':' {
+            return Ok(());
+        }
+
+        let (field, value) = match line.iter().position(|&b| b == b':') {
+            Some(colon) => {
+                let value = &line[colon + 1..];
+                (&line[..colon], value.strip_prefix(b" ").unwrap_or(value))
+            }
+            None => (line, &line[line.len()..]),
+        };
+
+        match field {
+            b"event" => {
+                self.event.clear();
+                self.event.push_str(&String::from_utf8_lossy(value));
+                self.has_event = true;
+            }
+            b"data" => {
+                if self.data.len() + value.len() > MAX_EVENT_SIZE {
+                    return Err(self.oversized());
+                }
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(&String::from_utf8_lossy(value));
+                self.has_data = true;
+            }
+            b"id" if !value.contains(&0) => {
+                self.id.clear();
+                self.id.push_str(&String::from_utf8_lossy(value));
+                self.has_id = true;
+            }
+            b"retry" => {
+                if let Some(retry) = std::str::from_utf8(value).ok().and_then(|v| v.parse().ok()) {
+                    self.retry = Some(retry);
+                }
+            }
+            _ => return Ok(()),
+        }
+        self.has_fields = true;
+        Ok(())
+    }
+
+    fn dispatch<F>(&mut self, on_event: &mut F)
+    where
+        F: FnMut(RawEvent<'_>),
+    {
+        if self.has_fields {
+            on_event(RawEvent {
+                event: self.has_event.then_some(self.event.as_str()),
+                data: &self.data,
+                id: self.has_id.then_some(self.id.as_str()),
+                retry: self.retry,
+            });
+        }
+        self.clear_event();
+    }
+
+    fn clear_event(&mut self) {
+        self.has_fields = false;
+        self.event.clear();
+        self.has_event = false;
+        self.data.clear();
+        self.has_data = false;
+        self.id.clear();
+        self.has_id = false;
+        self.retry = None;
+    }
+
+    fn oversized(&mut self) -> ClaudeError {
+        self.reset();
+        ClaudeError::General(format!("SSE event exceeds {} bytes", MAX_EVENT_SIZE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct OwnedEvent {
+        event: Option<String>,
+        data: String,
+        id: Option<String>,
+        retry: Option<u64>,
+    }
+
+    const SAMPLE: &[u8] = "event: content_block_delta\r\ndata: {\"text\":\"你好🌍\"}\r\n\r\n\
+        : keep-alive\n\nevent:ping\ndata\n\n\
+        id: 7\nretry: 1500\ndata: line one\ndata: line two\nunknown: x\n\r\
+        data: cr only\r\r\
+        data: tail without blank line"
+        .as_bytes();
+
+    /// 可复现的线性同余随机数生成器
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) as usize) % bound
+        }
+    }
+
+    fn decode_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<OwnedEvent>> {
+        let mut decoder = SseDecoder::new();
+        let mut events = Vec::new();
+        let mut collect = |raw: RawEvent<'_>| {
+            events.push(OwnedEvent {
+                event: raw.event.map(str::to_string),
+                data: raw.data.to_string(),
+                id: raw.id.map(str::to_string),
+                retry: raw.retry,
+            })
+        };
+        for chunk in chunks {
+            decoder.decode(chunk, &mut collect)?;
+        }
+        decoder.finish(&mut collect)?;
+        Ok(events)
+    }
+
+    fn random_chunks<'a>(input: &'a [u8], rng: &mut Lcg, max_len: usize) -> Vec<&'a [u8]> {
+        let mut chunks = Vec::new();
+        let mut rest = input;
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at((rng.next(max_len) + 1).min(rest.len()));
+            chunks.push(chunk);
+            rest = tail;
+        }
+        chunks
+    }
+
+    #[test]
+    fn test_decode_fields() {
+        let events = decode_chunks([SAMPLE]).unwrap();
+        let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+
+        assert_eq!(
+            data,
+            vec!["{\"text\":\"你好🌍\"}", "", "line one\nline two", "cr only", "tail without blank line"]
+        );
+        assert_eq!(events[0].event.as_deref(), Some("content_block_delta"));
+        assert_eq!(events[1].event.as_deref(), Some("ping"));
+        assert_eq!(events[2].id.as_deref(), Some("7"));
+        assert_eq!(events[2].retry, Some(1500));
+        assert_eq!(events[3].event, None);
+
+        // 截断的多字节字符在行内被替换，而不是 panic
+        let truncated = decode_chunks([&b"data: \xe4\xbd\n\n"[..]]).unwrap();
+        assert_eq!(truncated[0].data, "\u{fffd}");
+    }
+
+    #[test]
+    fn test_split_at_every_offset() {
+        let expected = decode_chunks([SAMPLE]).unwrap();
+        for split in 0..=SAMPLE.len() {
+            let (head, tail) = SAMPLE.split_at(split);
+            assert_eq!(decode_chunks([head, tail]).unwrap(), expected, "split at byte {}", split);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_random_chunking() {
+        let expected = decode_chunks([SAMPLE]).unwrap();
+        let mut rng = Lcg(0x5eed);
+        for _ in 0..2000 {
+            let chunks = random_chunks(SAMPLE, &mut rng, 8);
+            assert_eq!(decode_chunks(chunks).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_fuzz_malformed_streams() {
+        const ALPHABET: &[u8] = b"data:event:id:retry: \r\n\n\n\x00\xe4\xbd\xa0\xf0\x9f\x8c\x8d\x80\xff9";
+        let mut rng = Lcg(42);
+        for _ in 0..1000 {
+            let len = rng.next(256);
+            let input: Vec<u8> = (0..len).map(|_| ALPHABET[rng.next(ALPHABET.len())]).collect();
+
+            // 任意输入都不能 panic，且分块方式不影响结果
+            let whole = decode_chunks([input.as_slice()]).unwrap();
+            let chunks = random_chunks(&input, &mut rng, 5);
+            assert_eq!(decode_chunks(chunks).unwrap(), whole);
+        }
+    }
+
+    #[test]
+    fn test_oversized_event() {
+        let mut decoder = SseDecoder::new();
+        let line = vec![b'a'; MAX_EVENT_SIZE / 2 + 1];
+        decoder.decode(b"data: ", |_| {}).unwrap();
+        decoder.decode(&line, |_| {}).unwrap();
+        assert!(decoder.decode(&line, |_| {}).is_err());
+
+        // 出错后解码器被重置，可以继续使用
+        let mut count = 0;
+        decoder.decode(b"data: ok\n\n", |_| count += 1).unwrap();
+        assert_eq!(count, 1);
+    }
+}
@@ -1,12 +1,14 @@
 //! 语法高亮模块
 //! 
-//! 使用 syntect 实现代码语法高亮，替代 Highlight.js
+//! 使用 syntect 实现代码语法高亮，替代 Highlight.js。
+//! 除内置主题外，还会加载配置目录 `themes/` 下用户提供的 `.tmTheme` 文件
 
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Style, Theme, ThemeSet};
 use syntect::parsing::{SyntaxSet, SyntaxReference};
 use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 
 use crate::error::{ClaudeError, Result};
 
@@ -20,6 +22,56 @@ pub struct SyntaxHighlighter {
     current_theme: String,
     /// 语法缓存
     syntax_cache: HashMap<String, String>,
+    /// 用户主题名称
+    user_themes: BTreeSet<String>,
+}
+
+/// 深色终端的默认主题
+pub const DEFAULT_DARK_THEME: &str = "base16-ocean.dark";
+
+/// 浅色终端的默认主题
+pub const DEFAULT_LIGHT_THEME: &str = "InspiredGitHub";
+
+/// 终端背景色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalBackground {
+    /// 深色背景
+    Dark,
+    /// 浅色背景
+    Light,
+}
+
+impl TerminalBackground {
+    /// 解析配置值：`light`、`dark` 或 `auto`（自动检测）
+    pub fn from_setting(setting: &str) -> Self {
+        match setting.to_lowercase().as_str() {
+            "light" => Self::Light,
+            "dark" => Self::Dark,
+            _ => Self::detect(),
+        }
+    }
+
+    /// 根据 `COLORFGBG` 环境变量检测终端背景，无法判断时视为深色
+    pub fn detect() -> Self {
+        std::env::var("COLORFGBG")
+            .ok()
+            .and_then(|value| Self::from_colorfgbg(&value))
+            .unwrap_or(Self::Dark)
+    }
+
+    /// 解析 `COLORFGBG`（形如 `15;0`，最后一段为背景色的 ANSI 编号）
+    pub fn from_colorfgbg(value: &str) -> Option<Self> {
+        let background: u8 = value.rsplit(';').next()?.trim().parse().ok()?;
+        Some(if background == 7 || background >= 9 { Self::Light } else { Self::Dark })
+    }
+
+    /// 该背景下的默认主题
+    pub fn default_theme(self) -> &'static str {
+        match self {
+            Self::Dark => DEFAULT_DARK_THEME,
+            Self::Light => DEFAULT_LIGHT_THEME,
+        }
+    }
 }
 
 /// 高亮配置
@@ -71,11 +123,76 @@ impl SyntaxHighlighter {
         Ok(Self {
             syntax_set,
             theme_set,
-            current_theme: "base16-ocean.dark".to_string(),
+            current_theme: DEFAULT_DARK_THEME.to_string(),
             syntax_cache: HashMap::new(),
+            user_themes: BTreeSet::new(),
         })
     }
 
+    /// 创建语法高亮器并加载用户主题，加载失败的主题文件会被跳过
+    pub fn with_user_themes() -> Result<Self> {
+        let mut highlighter = Self::new()?;
+        if let Ok(dir) = Self::user_themes_dir() {
+            if dir.is_dir() {
+                highlighter.load_user_themes(&dir)?;
+            }
+        }
+        Ok(highlighter)
+    }
+
+    /// 用户主题目录（配置目录下的 themes）
+    pub fn user_themes_dir() -> Result<PathBuf> {
+        let config_dir = dirs::config_dir()
+            .ok_or_else(|| ClaudeError::config_error("Cannot find config directory"))?;
+        Ok(config_dir.join("claude-code").join("themes"))
+    }
+
+    /// 加载目录中的 `.tmTheme` 文件，主题名为文件名（不含扩展名），返回成功加载的主题名
+    pub fn load_user_themes(&mut self, dir: &Path) -> Result<Vec<String>> {
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("tmtheme"))
+            })
+            .collect();
+        paths.sort();
+
+        let mut loaded = Vec::new();
+        for path in paths {
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string) else {
+                continue;
+            };
+            match ThemeSet::get_theme(&path) {
+                Ok(theme) => {
+                    self.theme_set.themes.insert(name.clone(), theme);
+                    self.user_themes.insert(name.clone());
+                    loaded.push(name);
+                }
+                Err(e) => tracing::warn!("Skipping theme {}: {}", path.display(), e),
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// 是否为用户主题
+    pub fn is_user_theme(&self, theme_name: &str) -> bool {
+        self.user_themes.contains(theme_name)
+    }
+
+    /// 选择主题：优先使用指定的主题，否则使用与终端背景匹配的默认主题
+    pub fn resolve_theme(&self, requested: Option<&str>, background: TerminalBackground) -> Result<String> {
+        match requested {
+            Some(name) if self.theme_set.themes.contains_key(name) => Ok(name.to_string()),
+            Some(name) => Err(ClaudeError::config_error(format!(
+                "Theme '{}' not found. Run `highlight themes` to list available themes",
+                name
+            ))),
+            None => Ok(background.default_theme().to_string()),
+        }
+    }
+
     /// 设置主题
     pub fn set_theme(&mut self, theme_name: &str) -> Result<()> {
         if !self.theme_set.themes.contains_key(theme_name) {
@@ -95,6 +212,16 @@ impl SyntaxHighlighter {
         self.theme_set.themes.keys().cloned().collect()
     }
 
+    /// 获取内置主题列表
+    pub fn get_builtin_themes(&self) -> Vec<String> {
+        self.theme_set.themes.keys().filter(|name| !self.is_user_theme(name)).cloned().collect()
+    }
+
+    /// 获取用户主题列表
+    pub fn get_user_themes(&self) -> Vec<String> {
+        self.user_themes.iter().cloned().collect()
+    }
+
     /// 获取可用语言列表
     pub fn get_available_languages(&self) -> Vec<String> {
         self.syntax_set.syntaxes()
@@ -386,4 +513,57 @@ fn main() {
         assert!(highlight_result.detected_language.is_some());
         assert!(!highlight_result.highlighted_code.is_empty());
     }
+
+    #[test]
+    fn test_user_themes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join("paper.tmTheme"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Paper</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key>
+                <string>#FFFFFF</string>
+                <key>foreground</key>
+                <string>#222222</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>"#,
+        )
+        .unwrap();
+        std::fs::write(temp_dir.path().join("broken.tmTheme"), "not a theme").unwrap();
+
+        let mut highlighter = SyntaxHighlighter::new().unwrap();
+        let loaded = highlighter.load_user_themes(temp_dir.path()).unwrap();
+        assert_eq!(loaded, vec!["paper".to_string()]);
+        assert_eq!(highlighter.get_user_themes(), vec!["paper".to_string()]);
+        assert!(!highlighter.get_builtin_themes().contains(&"paper".to_string()));
+
+        let theme = highlighter.resolve_theme(Some("paper"), TerminalBackground::Dark).unwrap();
+        let config = HighlightConfig { theme, ..HighlightConfig::default() };
+        assert!(highlighter.highlight_code("let x = 1;", Some("rust"), &config).is_ok());
+        assert!(highlighter.resolve_theme(Some("missing"), TerminalBackground::Dark).is_err());
+    }
+
+    #[test]
+    fn test_background_detection() {
+        assert_eq!(TerminalBackground::from_colorfgbg("15;0"), Some(TerminalBackground::Dark));
+        assert_eq!(TerminalBackground::from_colorfgbg("0;default;15"), Some(TerminalBackground::Light));
+        assert_eq!(TerminalBackground::from_colorfgbg("default"), None);
+        assert_eq!(TerminalBackground::from_setting("light").default_theme(), DEFAULT_LIGHT_THEME);
+
+        let highlighter = SyntaxHighlighter::new().unwrap();
+        let theme = highlighter.resolve_theme(None, TerminalBackground::Light).unwrap();
+        assert!(highlighter.get_available_themes().contains(&theme));
+    }
 }
@@ -0,0 +1,348 @@
+//! 测试支撑：模拟 Anthropic API 服务器
+//!
+//! 在本机随机端口上监听，按 HTTP/1.1 收下请求并依次返回预先编排的回复：普通的 Messages 响应、
+//! SSE 事件流、过载和限流错误，以及故意写坏的事件。收到的每个请求都会记录下来供断言，
+//! 网络、流式和 Agent 的测试可以借此覆盖从发出请求到解析回复的完整链路。
+//! 只在测试或启用 `test-support` 特性时编译
+
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 剧本用完后返回的错误响应体
+const EXHAUSTED: &str = r#"{"type":"error","error":{"type":"api_error","message":"mock server has no scripted reply left"}}"#;
+
+/// 编排好的一次回复
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    /// 以 JSON 返回的响应
+    Json { status: u16, body: Value, headers: Vec<(String, String)> },
+    /// SSE 响应，每个块单独写出，块之间停顿 `delay`；发完后关闭连接
+    Stream { chunks: Vec<Vec<u8>>, delay: Duration },
+    /// 等待 `delay` 后再给出内层回复，用于超时和取消的测试
+    Delayed { delay: Duration, reply: Box<MockReply> },
+}
+
+impl MockReply {
+    /// 一条完整的 Messages 响应，`content` 为内容块数组
+    pub fn message(content: Value, stop_reason: &str) -> Self {
+        Self::Json {
+            status: 200,
+            body: json!({
+                "id": "msg_mock",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-mock",
+                "content": content,
+                "stop_reason": stop_reason,
+                "stop_sequence": null,
+                "usage": {"input_tokens": 10, "output_tokens": 5},
+            }),
+            headers: Vec::new(),
+        }
+    }
+
+    /// 只有一段文本的回复
+    pub fn text(text: &str) -> Self {
+        Self::message(json!([{"type": "text", "text": text}]), "end_turn")
+    }
+
+    /// 请求调用工具的回复
+    pub fn tool_use(id: &str, name: &str, input: Value) -> Self {
+        Self::message(json!([{"type": "tool_use", "id": id, "name": name, "input": input}]), "tool_use")
+    }
+
+    /// API 错误响应
+    pub fn error(status: u16, error_type: &str, message: &str) -> Self {
+        Self::Json {
+            status,
+            body: json!({"type": "error", "error": {"type": error_type, "message": message}}),
+            headers: Vec::new(),
+        }
+    }
+
+    /// 529 过载
+    pub fn overloaded() -> Self {
+        Self::error(529, "overloaded_error", "Overloaded")
+    }
+
+    /// 429 限流，带 `retry-after`
+    pub fn rate_limited(retry_after_secs: u64) -> Self {
+        match Self::error(429, "rate_limit_error", "Rate limited") {
+            Self::Json { status, body, .. } => {
+                Self::Json { status, body, headers: vec![("retry-after".to_string(), retry_after_secs.to_string())] }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// 按 Messages 流式协议逐段输出文本
+    pub fn text_stream(parts: &[&str]) -> Self {
+        let mut events = vec![
+            message_start(),
+            sse_event("content_block_start", json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}})),
+        ];
+        events.extend(parts.iter().map(|part| {
+            sse_event(
+                "content_block_delta",
+                json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": part}}),
+            )
+        }));
+        events.extend(message_end(0, "end_turn", parts.len()));
+        Self::Stream { chunks: events.into_iter().map(String::into_bytes).collect(), delay: Duration::ZERO }
+    }
+
+    /// 按流式协议输出一次工具调用，参数 JSON 分成 `json_parts` 逐段发送
+    pub fn tool_use_stream(id: &str, name: &str, json_parts: &[&str]) -> Self {
+        let mut events = vec![
+            message_start(),
+            sse_event(
+                "content_block_start",
+                json!({"type": "content_block_start", "index": 0, "content_block": {"type": "tool_use", "id": id, "name": name, "input": {}}}),
+            ),
+        ];
+        events.extend(json_parts.iter().map(|part| {
+            sse_event(
+                "content_block_delta",
+                json!({"type": "content_block_delta", "index": 0, "delta": {"type": "input_json_delta", "partial_json": part}}),
+            )
+        }));
+        events.extend(message_end(0, "tool_use", json_parts.len()));
+        Self::Stream { chunks: events.into_iter().map(String::into_bytes).collect(), delay: Duration::ZERO }
+    }
+
+    /// 原样写出的 SSE 块，可以在任意位置切开事件，也可以写入损坏的数据
+    pub fn raw_stream<S: Into<String>>(chunks: impl IntoIterator<Item = S>) -> Self {
+        Self::Stream { chunks: chunks.into_iter().map(|chunk| chunk.into().into_bytes()).collect(), delay: Duration::ZERO }
+    }
+
+    /// 把流重新切成每块 `size` 字节（可能切在多字节字符中间），块间停顿 `delay`
+    pub fn rechunked(self, size: usize, delay: Duration) -> Self {
+        match self {
+            Self::Stream { chunks, .. } => {
+                let chunks = chunks.concat().chunks(size.max(1)).map(<[u8]>::to_vec).collect();
+                Self::Stream { chunks, delay }
+            }
+            other => other,
+        }
+    }
+
+    pub fn delayed(self, delay: Duration) -> Self {
+        Self::Delayed { delay, reply: Box::new(self) }
+    }
+}
+
+/// 一个 SSE 事件帧
+pub fn sse_event(event: &str, data: Value) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+fn message_start() -> String {
+    sse_event(
+        "message_start",
+        json!({"type": "message_start", "message": {"id": "msg_mock", "type": "message", "role": "assistant", "model": "claude-mock", "content": [], "usage": {"input_tokens": 10, "output_tokens": 1}}}),
+    )
+}
+
+fn message_end(index: usize, stop_reason: &str, output_tokens: usize) -> Vec<String> {
+    vec![
+        sse_event("content_block_stop", json!({"type": "content_block_stop", "index": index})),
+        sse_event(
+            "message_delta",
+            json!({"type": "message_delta", "delta": {"stop_reason": stop_reason}, "usage": {"output_tokens": output_tokens}}),
+        ),
+        sse_event("message_stop", json!({"type": "message_stop"})),
+    ]
+}
+
+/// 服务器收到的请求
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// 头部名称已转为小写
+    pub headers: HashMap<String, String>,
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    script: Mutex<VecDeque<MockReply>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+    connections: AtomicUsize,
+}
+
+/// 模拟的 Anthropic API 服务器，丢弃时停止监听
+pub struct MockAnthropicServer {
+    base_url: String,
+    state: Arc<State>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockAnthropicServer {
+    /// 在随机端口上启动，按顺序返回 `script` 中的回复
+    pub async fn start(script: impl IntoIterator<Item = MockReply>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock server");
+        let base_url = format!("http://{}", listener.local_addr().expect("mock server address"));
+        let state = Arc::new(State { script: Mutex::new(script.into_iter().collect()), ..State::default() });
+
+        let shared = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                shared.connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve_connection(socket, shared.clone()));
+            }
+        });
+        Self { base_url, state, task }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// 在剧本末尾追加回复
+    pub fn push(&self, reply: MockReply) {
+        self.state.script.lock().unwrap().push_back(reply);
+    }
+
+    /// 到目前为止收到的请求
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// 接受过的 TCP 连接数
+    pub fn connections(&self) -> usize {
+        self.state.connections.load(Ordering::SeqCst)
+    }
+
+    /// 剧本中还没用到的回复数
+    pub fn remaining(&self) -> usize {
+        self.state.script.lock().unwrap().len()
+    }
+}
+
+impl Drop for MockAnthropicServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// 在一个连接上依次处理请求，直到客户端关闭或回复了需要关闭连接的流
+async fn serve_connection(mut socket: TcpStream, state: Arc<State>) {
+    let mut buffer = Vec::new();
+    while let Some(request) = read_request(&mut socket, &mut buffer).await {
+        state.requests.lock().unwrap().push(request);
+        let reply = state.script.lock().unwrap().pop_front();
+        let keep_alive = match reply {
+            Some(reply) => write_reply(&mut socket, reply).await,
+            None => write_json(&mut socket, 500, EXHAUSTED, &[]).await,
+        };
+        if !keep_alive {
+            break;
+        }
+    }
+}
+
+/// 读取一个请求；`buffer` 保存已读到但属于下一个请求的字节
+async fn read_request(socket: &mut TcpStream, buffer: &mut Vec<u8>) -> Option<RecordedRequest> {
+    let mut chunk = [0u8; 8192];
+    let head_end = loop {
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        let n = socket.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+        buffer.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split_whitespace();
+    let (method, path) = (request_line.next()?.to_string(), request_line.next()?.to_string());
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let length = headers.get("content-length").and_then(|l| l.parse::<usize>().ok()).unwrap_or(0);
+    while buffer.len() < head_end + length {
+        let n = socket.read(&mut chunk).await.ok().filter(|&n| n > 0)?;
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+    let body = serde_json::from_slice(&buffer[head_end..head_end + length]).unwrap_or(Value::Null);
+    buffer.drain(..head_end + length);
+    Some(RecordedRequest { method, path, headers, body })
+}
+
+/// 写出回复，返回连接是否还能继续使用
+async fn write_reply(socket: &mut TcpStream, reply: MockReply) -> bool {
+    match reply {
+        MockReply::Json { status, body, headers } => write_json(socket, status, &body.to_string(), &headers).await,
+        MockReply::Stream { chunks, delay } => {
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncache-control: no-cache\r\nconnection: close\r\n\r\n";
+            if socket.write_all(head.as_bytes()).await.is_err() {
+                return false;
+            }
+            for chunk in chunks {
+                if socket.write_all(&chunk).await.is_err() || socket.flush().await.is_err() {
+                    return false;
+                }
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            let _ = socket.shutdown().await;
+            false
+        }
+        MockReply::Delayed { delay, reply } => {
+            tokio::time::sleep(delay).await;
+            Box::pin(write_reply(socket, *reply)).await
+        }
+    }
+}
+
+async fn write_json(socket: &mut TcpStream, status: u16, body: &str, headers: &[(String, String)]) -> bool {
+    let reason = match status {
+        200 => "OK",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        529 => "Overloaded",
+        _ => "Mock",
+    };
+    let mut response = format!("HTTP/1.1 {} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n", status, reason, body.len());
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    response.push_str(body);
+    socket.write_all(response.as_bytes()).await.is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_script_is_served_in_order() {
+        let server = MockAnthropicServer::start([MockReply::text("first"), MockReply::rate_limited(3)]).await;
+        let client = crate::network::shared_client();
+        let url = format!("{}/v1/messages", server.base_url());
+
+        let first: Value = client.post(&url).json(&json!({"n": 1})).send().await.unwrap().json().await.unwrap();
+        assert_eq!(first["content"][0]["text"], "first");
+        let second = client.post(&url).json(&json!({"n": 2})).send().await.unwrap();
+        assert_eq!(second.status().as_u16(), 429);
+        assert_eq!(second.headers()["retry-after"], "3");
+        let exhausted = client.post(&url).send().await.unwrap();
+        assert_eq!(exhausted.status().as_u16(), 500);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!((requests[1].method.as_str(), requests[1].path.as_str()), ("POST", "/v1/messages"));
+        assert_eq!(requests[1].body["n"], 2);
+        assert_eq!(server.remaining(), 0);
+    }
+}
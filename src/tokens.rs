@@ -0,0 +1,129 @@
+//! Token 估算与上下文用量
+//!
+//! 不依赖分词器的 Token 估算和上下文用量分级。后端的上下文管理和 Web UI（通过 WASM）
+//! 使用同一套规则，两边显示的数字保持一致
+
+use serde::{Deserialize, Serialize};
+
+/// 用量达到该比例时提示 (60%)
+pub const WARNING_THRESHOLD: f64 = 0.6;
+
+/// 用量达到该比例时警告 (80%)
+pub const ERROR_THRESHOLD: f64 = 0.8;
+
+/// 用量达到该比例时自动压缩 (92%)
+pub const COMPRESSION_THRESHOLD: f64 = 0.92;
+
+/// 估算文本的 Token 数
+///
+/// ASCII 文本约 4 个字符一个 Token，中日韩文字约一个字一个 Token，
+/// 其他非 ASCII 字符约 2 个一个 Token
+pub fn estimate_tokens(text: &str) -> u32 {
+    let (mut ascii, mut cjk, mut other) = (0u32, 0u32, 0u32);
+    for c in text.chars() {
+        if c.is_ascii() {
+            ascii += 1;
+        } else if is_cjk(c) {
+            cjk += 1;
+        } else {
+            other += 1;
+        }
+    }
+    ascii.div_ceil(4) + cjk + other.div_ceil(2)
+}
+
+/// `text` 中估算 Token 数不超过 `max_tokens` 的最长前缀的字节长度，总在字符边界上
+pub fn prefix_within(text: &str, max_tokens: u32) -> usize {
+    let (mut ascii, mut cjk, mut other) = (0u32, 0u32, 0u32);
+    for (index, c) in text.char_indices() {
+        if c.is_ascii() {
+            ascii += 1;
+        } else if is_cjk(c) {
+            cjk += 1;
+        } else {
+            other += 1;
+        }
+        if ascii.div_ceil(4) + cjk + other.div_ceil(2) > max_tokens {
+            return index;
+        }
+    }
+    text.len()
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32, 0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF)
+}
+
+/// 上下文用量等级
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextLevel {
+    Normal,
+    Warning,
+    Error,
+    /// 达到自动压缩阈值
+    Compress,
+}
+
+/// 上下文用量
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ContextMeter {
+    pub used: u32,
+    pub limit: u32,
+}
+
+impl ContextMeter {
+    pub fn new(used: u32, limit: u32) -> Self {
+        Self { used, limit }
+    }
+
+    /// 已用比例，上限为 0 时视为已满
+    pub fn ratio(&self) -> f64 {
+        if self.limit == 0 {
+            return 1.0;
+        }
+        self.used as f64 / self.limit as f64
+    }
+
+    pub fn level(&self) -> ContextLevel {
+        match self.ratio() {
+            r if r >= COMPRESSION_THRESHOLD => ContextLevel::Compress,
+            r if r >= ERROR_THRESHOLD => ContextLevel::Error,
+            r if r >= WARNING_THRESHOLD => ContextLevel::Warning,
+            _ => ContextLevel::Normal,
+        }
+    }
+
+    /// 文本进度条，如 `████░░░░░░ 42%`
+    pub fn bar(&self, width: usize) -> String {
+        let ratio = self.ratio().clamp(0.0, 1.0);
+        let filled = (ratio * width as f64).round() as usize;
+        format!("{}{} {:.0}%", "█".repeat(filled), "░".repeat(width - filled), ratio * 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("hello world!"), 3);
+        assert_eq!(estimate_tokens("你好世界"), 4);
+        assert_eq!(estimate_tokens("héllo"), 2);
+        assert_eq!(prefix_within("hello world!", 2), 8);
+        assert_eq!(prefix_within("你好世界", 3), "你好世".len());
+        assert_eq!(prefix_within("short", 10), 5);
+    }
+
+    #[test]
+    fn test_context_meter_levels() {
+        assert_eq!(ContextMeter::new(10, 100).level(), ContextLevel::Normal);
+        assert_eq!(ContextMeter::new(60, 100).level(), ContextLevel::Warning);
+        assert_eq!(ContextMeter::new(85, 100).level(), ContextLevel::Error);
+        assert_eq!(ContextMeter::new(95, 100).level(), ContextLevel::Compress);
+        assert_eq!(ContextMeter::new(1, 0).level(), ContextLevel::Compress);
+        assert_eq!(ContextMeter::new(42, 100).bar(10), "████░░░░░░ 42%");
+    }
+}
@@ -3,9 +3,31 @@
 //! 实现 Claude Code 的核心内置工具
 
 use super::*;
+use crate::fs::notebook::{CellType, Notebook};
 use crate::fs::FileSystemManager;
 use std::path::Path;
-use tokio::process::Command;
+
+/// 路径越出工作目录时的结果
+fn path_traversal_error() -> ToolResult {
+    ToolResult::error(ClaudeError::sandbox_error("Path traversal not allowed").to_string())
+}
+
+/// 解析工具参数中的路径：`scratch://` 指向会话临时工作区，其他路径相对工作目录；
+/// 越出工作目录或工作区、或含有 `..` 时返回 `None`
+fn resolve_tool_path(context: &ToolContext, path: &str) -> Option<std::path::PathBuf> {
+    match crate::fs::scratch::Scratchpad::for_session(&context.session_id).resolve(path) {
+        Ok(Some(scratch_path)) => Some(scratch_path),
+        Ok(None) => {
+            // `starts_with` 只比较路径组件，`src/../../etc` 仍以工作目录开头
+            if Path::new(path).components().any(|c| c == std::path::Component::ParentDir) {
+                return None;
+            }
+            let full_path = Path::new(&context.working_directory).join(path);
+            full_path.starts_with(&context.working_directory).then_some(full_path)
+        }
+        Err(_) => None,
+    }
+}
 
 /// 文件读取工具
 pub struct ReadTool {
@@ -31,7 +53,7 @@ impl Tool for ReadTool {
                 ToolParameter {
                     name: "path".to_string(),
                     param_type: "string".to_string(),
-                    description: "Path to the file to read".to_string(),
+                    description: "Path to the file to read (scratch://<path> for the session scratchpad)".to_string(),
                     required: true,
                     default: None,
                     constraints: None,
@@ -59,11 +81,10 @@ impl Tool for ReadTool {
                 message: "Path parameter is required".to_string(),
             })?;
 
-        // 安全检查：确保路径在工作目录内
-        let full_path = Path::new(&context.working_directory).join(path);
-        if !full_path.starts_with(&context.working_directory) {
-            return Ok(ToolResult::error("Path traversal not allowed".to_string()));
-        }
+        // 安全检查：确保路径在工作目录或会话临时工作区内
+        let Some(full_path) = resolve_tool_path(context, path) else {
+            return Ok(path_traversal_error());
+        };
 
         match self.fs_manager.read_file(&full_path).await {
             Ok(content) => {
@@ -102,7 +123,7 @@ impl Tool for WriteTool {
                 ToolParameter {
                     name: "path".to_string(),
                     param_type: "string".to_string(),
-                    description: "Path to the file to write".to_string(),
+                    description: "Path to the file to write (scratch://<path> for the session scratchpad)".to_string(),
                     required: true,
                     default: None,
                     constraints: None,
@@ -150,10 +171,9 @@ impl Tool for WriteTool {
             .unwrap_or(false);
 
         // 安全检查
-        let full_path = Path::new(&context.working_directory).join(path);
-        if !full_path.starts_with(&context.working_directory) {
-            return Ok(ToolResult::error("Path traversal not allowed".to_string()));
-        }
+        let Some(full_path) = resolve_tool_path(context, path) else {
+            return Ok(path_traversal_error());
+        };
 
         // 创建父目录（如果需要）
         if create_dirs {
@@ -177,12 +197,12 @@ impl Tool for WriteTool {
     }
 }
 
-/// 目录列表工具
-pub struct ListTool {
+/// 精确字符串替换的文件编辑工具
+pub struct EditTool {
     fs_manager: FileSystemManager,
 }
 
-impl ListTool {
+impl EditTool {
     pub fn new() -> Self {
         Self {
             fs_manager: FileSystemManager::new(vec![std::env::current_dir().unwrap_or_default()]),
@@ -190,104 +210,472 @@ impl ListTool {
     }
 }
 
+impl Default for EditTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+crate::tool_input! {
+    /// 文件编辑工具输入
+    pub struct EditInput {
+        /// Path to the file to edit (scratch://<path> for the session scratchpad)
+        pub path: String,
+        /// Exact text to replace, including whitespace and indentation; it must occur exactly once unless replace_all is true
+        pub old_string: String,
+        /// Text to replace it with
+        pub new_string: String,
+        /// Replace every occurrence of old_string
+        pub replace_all: bool = false,
+    }
+}
+
 #[async_trait]
-impl Tool for ListTool {
+impl TypedTool for EditTool {
+    type Input = EditInput;
+
     fn definition(&self) -> ToolDefinition {
-        ToolDefinition {
-            name: "list".to_string(),
-            description: "List files and directories".to_string(),
-            version: "1.0.0".to_string(),
-            parameters: vec![
-                ToolParameter {
-                    name: "path".to_string(),
-                    param_type: "string".to_string(),
-                    description: "Path to list (default: current directory)".to_string(),
-                    required: false,
-                    default: Some(Value::String(".".to_string())),
-                    constraints: None,
-                },
-                ToolParameter {
-                    name: "recursive".to_string(),
-                    param_type: "boolean".to_string(),
-                    description: "List recursively".to_string(),
-                    required: false,
-                    default: Some(Value::Bool(false)),
-                    constraints: None,
-                },
-                ToolParameter {
-                    name: "show_hidden".to_string(),
-                    param_type: "boolean".to_string(),
-                    description: "Show hidden files".to_string(),
-                    required: false,
-                    default: Some(Value::Bool(false)),
-                    constraints: None,
-                },
-            ],
-            category: "filesystem".to_string(),
-            requires_confirmation: false,
-            security_level: SecurityLevel::Safe,
+        ToolDefinition::builder("edit")
+            .description("Edit a file by replacing an exact string with another. Read the file first and include enough surrounding context in old_string to make it unique; the result is a unified diff of the change")
+            .category("filesystem")
+            .requires_confirmation(true)
+            .security_level(SecurityLevel::Medium)
+            .input::<EditInput>()
+            .build()
+    }
+
+    async fn run(&self, input: EditInput, context: &ToolContext) -> Result<ToolResult> {
+        let EditInput { path, old_string, new_string, replace_all } = input;
+        if old_string.is_empty() {
+            return Ok(ToolResult::error("old_string must not be empty; use the write tool to create a file".to_string()));
+        }
+        if old_string == new_string {
+            return Ok(ToolResult::error("old_string and new_string are identical; nothing to change".to_string()));
+        }
+
+        let Some(full_path) = resolve_tool_path(context, &path) else {
+            return Ok(path_traversal_error());
+        };
+        let before = match self.fs_manager.read_file(&full_path).await {
+            Ok(content) => content,
+            Err(e) => return Ok(ToolResult::error(format!("Failed to read file: {}", e))),
+        };
+
+        // 多处匹配时拒绝替换，避免改到模型没有看到的位置
+        let occurrences = before.matches(&old_string).count();
+        let after = match occurrences {
+            0 => return Ok(ToolResult::error(format!("old_string was not found in {}", path))),
+            1 => before.replacen(&old_string, &new_string, 1),
+            _ if replace_all => before.replace(&old_string, &new_string),
+            n => {
+                return Ok(ToolResult::error(format!(
+                    "old_string occurs {} times in {}; include more context to make it unique or set replace_all",
+                    n, path
+                )))
+            }
+        };
+
+        if let Err(e) = self.fs_manager.write_file(&full_path, &after).await {
+            return Ok(ToolResult::error(format!("Failed to write file: {}", e)));
         }
+        Ok(ToolResult::success(serde_json::json!({
+            "path": path,
+            "replacements": occurrences,
+            "diff": crate::fs::unified_diff(&path, &before, &after),
+        }))
+        .with_render(RenderHint::Diff { source: Some("diff".to_string()) }))
     }
+}
 
-    async fn execute(&self, parameters: Value, context: &ToolContext) -> Result<ToolResult> {
-        let path = parameters.get("path")
-            .and_then(|v| v.as_str())
-            .unwrap_or(".");
+/// Jupyter 笔记本读取工具
+pub struct NotebookReadTool {
+    fs_manager: FileSystemManager,
+}
 
-        let recursive = parameters.get("recursive")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
+impl NotebookReadTool {
+    pub fn new() -> Self {
+        Self { fs_manager: FileSystemManager::new(vec![std::env::current_dir().unwrap_or_default()]) }
+    }
+}
 
-        let show_hidden = parameters.get("show_hidden")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
+impl Default for NotebookReadTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        // 安全检查
-        let full_path = Path::new(&context.working_directory).join(path);
-        if !full_path.starts_with(&context.working_directory) {
-            return Ok(ToolResult::error("Path traversal not allowed".to_string()));
+crate::tool_input! {
+    /// 笔记本读取工具输入
+    pub struct NotebookReadInput {
+        /// Path to the .ipynb file
+        pub path: String,
+    }
+}
+
+#[async_trait]
+impl TypedTool for NotebookReadTool {
+    type Input = NotebookReadInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("notebook_read")
+            .description("Read a Jupyter notebook (.ipynb) as a list of cells with their index, id, type, source and text outputs")
+            .category("filesystem")
+            .input::<NotebookReadInput>()
+            .build()
+    }
+
+    async fn run(&self, input: NotebookReadInput, context: &ToolContext) -> Result<ToolResult> {
+        let Some(full_path) = resolve_tool_path(context, &input.path) else {
+            return Ok(path_traversal_error());
+        };
+        let notebook = match self.fs_manager.read_file(&full_path).await.and_then(|content| Notebook::parse(&content)) {
+            Ok(notebook) => notebook,
+            Err(e) => return Ok(ToolResult::error(format!("Failed to read notebook: {}", e))),
+        };
+        Ok(ToolResult::success(serde_json::json!({
+            "path": input.path,
+            "language": notebook.language(),
+            "cells": notebook.cell_views(),
+        })))
+    }
+}
+
+/// Jupyter 笔记本单元格编辑工具
+pub struct NotebookEditTool {
+    fs_manager: FileSystemManager,
+}
+
+impl NotebookEditTool {
+    pub fn new() -> Self {
+        Self { fs_manager: FileSystemManager::new(vec![std::env::current_dir().unwrap_or_default()]) }
+    }
+}
+
+impl Default for NotebookEditTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+crate::tool_input! {
+    /// 笔记本编辑工具输入
+    pub struct NotebookEditInput {
+        /// Path to the .ipynb file
+        pub path: String,
+        /// One of "replace", "insert" or "delete"
+        pub operation: String = "replace",
+        /// Zero-based index of the cell to edit; for insert, the position of the new cell
+        pub cell_index: Option<usize>,
+        /// Id of the cell to edit, instead of cell_index; for insert, the new cell goes after it
+        pub cell_id: Option<String>,
+        /// "code", "markdown" or "raw"; required for insert, changes the cell type on replace
+        pub cell_type: Option<String>,
+        /// New cell source; required for replace and insert
+        pub source: Option<String>,
+    }
+}
+
+#[async_trait]
+impl TypedTool for NotebookEditTool {
+    type Input = NotebookEditInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("notebook_edit")
+            .description("Edit a Jupyter notebook (.ipynb) cell by cell: replace a cell's source, insert a new cell or delete a cell. Outputs and metadata of the notebook and its cells are preserved; prefer this over editing the raw JSON")
+            .category("filesystem")
+            .requires_confirmation(true)
+            .security_level(SecurityLevel::Medium)
+            .input::<NotebookEditInput>()
+            .build()
+    }
+
+    async fn run(&self, input: NotebookEditInput, context: &ToolContext) -> Result<ToolResult> {
+        let Some(full_path) = resolve_tool_path(context, &input.path) else {
+            return Ok(path_traversal_error());
+        };
+        let mut notebook = match self.fs_manager.read_file(&full_path).await.and_then(|content| Notebook::parse(&content)) {
+            Ok(notebook) => notebook,
+            Err(e) => return Ok(ToolResult::error(format!("Failed to read notebook: {}", e))),
+        };
+        let cell_type = match input.cell_type.as_deref().map(str::parse::<CellType>).transpose() {
+            Ok(cell_type) => cell_type,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        let located = match (&input.cell_id, input.cell_index) {
+            (Some(id), _) => match notebook.find(id) {
+                Some(index) => Some(index),
+                None => return Ok(ToolResult::error(format!("No cell with id '{}' in {}", id, input.path))),
+            },
+            (None, index) => index,
+        };
+
+        let mut diff = None;
+        let outcome = match (input.operation.as_str(), located, input.source.as_deref()) {
+            ("replace", Some(index), Some(source)) => {
+                let before = notebook.cell(index).map(|cell| cell.source).unwrap_or_default();
+                diff = Some(crate::fs::unified_diff(&format!("{}#cell{}", input.path, index), &before, source));
+                notebook.replace(index, source, cell_type).map(|_| (index, None))
+            }
+            ("insert", located, Some(source)) => {
+                // 按 id 定位时插在该单元格之后，未指定位置时追加到末尾
+                let index = match (&input.cell_id, located) {
+                    (Some(_), Some(index)) => index + 1,
+                    (_, Some(index)) => index,
+                    (_, None) => notebook.len(),
+                };
+                match cell_type {
+                    Some(cell_type) => notebook.insert(index, cell_type, source).map(|id| (index, id)),
+                    None => Err(ClaudeError::validation_error("cell_type", "cell_type is required for insert")),
+                }
+            }
+            ("delete", Some(index), _) => notebook.delete(index).map(|_| (index, None)),
+            ("replace" | "delete", None, _) => {
+                Err(ClaudeError::validation_error("cell_index", "Specify the cell with cell_index or cell_id"))
+            }
+            ("replace" | "insert", _, None) => Err(ClaudeError::validation_error("source", "source is required")),
+            (other, _, _) => Err(ClaudeError::validation_error(
+                "operation",
+                format!("Unknown operation '{}', expected replace, insert or delete", other),
+            )),
+        };
+        let (index, new_id) = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+
+        let written = match notebook.to_json() {
+            Ok(content) => self.fs_manager.write_file(&full_path, &content).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            return Ok(ToolResult::error(format!("Failed to write notebook: {}", e)));
+        }
+        let mut data = serde_json::json!({
+            "path": input.path,
+            "operation": input.operation,
+            "cell_index": index,
+            "cell_count": notebook.len(),
+        });
+        if let Some(id) = new_id {
+            data["cell_id"] = Value::String(id);
+        }
+        match diff {
+            Some(diff) => {
+                data["diff"] = Value::String(diff);
+                Ok(ToolResult::success(data).with_render(RenderHint::Diff { source: Some("diff".to_string()) }))
+            }
+            None => Ok(ToolResult::success(data)),
+        }
+    }
+}
+
+/// 目录列表工具，返回带大小、修改时间和类型标记的结构化条目
+pub struct LsTool;
+
+crate::tool_input! {
+    /// 目录列表工具输入
+    pub struct LsInput {
+        /// Directory to list, relative to the working directory
+        pub path: String = ".",
+        /// How many levels to descend: 1 lists only direct children
+        pub depth: usize = 1,
+        /// Include entries whose name starts with a dot
+        pub show_hidden: bool = false,
+        /// Extra glob patterns to skip, matched against the entry name or its path relative to `path`, e.g. ["target", "*.lock"]
+        pub ignore: Vec<String> = Vec::<String>::new(),
+        /// Skip entries excluded by .gitignore
+        pub respect_gitignore: bool = true,
+        /// Maximum number of entries to return (0 for no limit)
+        pub limit: usize = 500,
+    }
+}
+
+/// 列出 `dir` 下至多 `depth` 层的条目，按路径排序；符号链接不跟随
+fn collect_ls_entries(
+    base: &Path,
+    dir: &Path,
+    depth: usize,
+    input: &LsInput,
+    ignore: &[regex::Regex],
+    rules: &crate::fs::ignore::IgnoreRules,
+    entries: &mut Vec<serde_json::Value>,
+) -> std::io::Result<()> {
+    let rules = if input.respect_gitignore { rules.with_dir(dir) } else { rules.clone() };
+    let mut children: Vec<std::fs::DirEntry> = std::fs::read_dir(dir)?.filter_map(|entry| entry.ok()).collect();
+    children.sort_by_key(|entry| entry.file_name());
+    for child in children {
+        let path = child.path();
+        let name = child.file_name().to_string_lossy().into_owned();
+        let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+            continue;
+        };
+        let relative = path.strip_prefix(base).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+        let is_dir = metadata.is_dir();
+        if (!input.show_hidden && name.starts_with('.'))
+            || ignore.iter().any(|regex| regex.is_match(&name) || regex.is_match(&relative))
+            || (input.respect_gitignore && rules.is_ignored(&path, is_dir))
+        {
+            continue;
         }
 
-        match self.fs_manager.list_directory(&full_path).await {
-            Ok(entries) => {
-                let filtered_entries: Vec<_> = entries
-                    .into_iter()
-                    .filter(|entry| {
-                        if show_hidden {
-                            true
-                        } else {
-                            // 获取文件名并检查是否以 . 开头
-                            if let Some(file_name) = entry.file_name() {
-                                if let Some(name_str) = file_name.to_str() {
-                                    !name_str.starts_with('.')
-                                } else {
-                                    true
-                                }
-                            } else {
-                                true
-                            }
-                        }
-                    })
-                    .map(|entry| serde_json::json!({
-                        "path": entry.to_string_lossy(),
-                        "name": entry.file_name().unwrap_or_default().to_string_lossy(),
-                        "is_dir": entry.is_dir()
-                    }))
-                    .collect();
+        let kind = if metadata.file_type().is_symlink() {
+            "symlink"
+        } else if is_dir {
+            "dir"
+        } else {
+            "file"
+        };
+        #[cfg(unix)]
+        let executable = {
+            use std::os::unix::fs::PermissionsExt;
+            !is_dir && metadata.permissions().mode() & 0o111 != 0
+        };
+        #[cfg(not(unix))]
+        let executable = false;
+        let mut entry = serde_json::json!({
+            "path": relative,
+            "name": name,
+            "type": kind,
+            "size": metadata.len(),
+            "modified": metadata.modified().ok().map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339()),
+            "readonly": metadata.permissions().readonly(),
+            "executable": executable,
+        });
+        if kind == "symlink" {
+            entry["target"] = std::fs::read_link(&path).ok().map(|target| target.to_string_lossy().into_owned()).into();
+        }
+        entries.push(entry);
+
+        if is_dir && depth > 1 {
+            collect_ls_entries(base, &path, depth - 1, input, ignore, &rules, entries)?;
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl TypedTool for LsTool {
+    type Input = LsInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("ls")
+            .description("List a directory with each entry's type (file, dir or symlink), size in bytes, modification time and permission flags, skipping hidden and .gitignore'd entries by default. Use it instead of ls through bash")
+            .category("filesystem")
+            .input::<LsInput>()
+            .build()
+    }
+
+    async fn run(&self, input: LsInput, context: &ToolContext) -> Result<ToolResult> {
+        let Some(dir) = resolve_tool_path(context, &input.path) else {
+            return Ok(path_traversal_error());
+        };
+        if !dir.is_dir() {
+            return Ok(ToolResult::error(format!("Not a directory: {}", input.path)));
+        }
+        let ignore = match input.ignore.iter().map(|pattern| crate::fs::ignore::glob_regex(pattern)).collect::<Result<Vec<_>>>() {
+            Ok(ignore) => ignore,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
 
+        let path = input.path.clone();
+        let limit = if input.limit == 0 { usize::MAX } else { input.limit };
+        let entries = tokio::task::spawn_blocking(move || {
+            let rules = if input.respect_gitignore {
+                crate::fs::ignore::IgnoreRules::for_ancestors(&dir)
+            } else {
+                crate::fs::ignore::IgnoreRules::default()
+            };
+            let mut entries = Vec::new();
+            collect_ls_entries(&dir, &dir, input.depth.max(1), &input, &ignore, &rules, &mut entries).map(|_| entries)
+        })
+        .await
+        .map_err(|e| ClaudeError::General(format!("Listing task failed: {}", e)))?;
+
+        match entries {
+            Ok(entries) => {
+                let count = entries.len();
+                let entries: Vec<_> = entries.into_iter().take(limit).collect();
                 Ok(ToolResult::success(serde_json::json!({
                     "path": path,
-                    "entries": filtered_entries,
-                    "count": filtered_entries.len()
-                })))
+                    "count": count,
+                    "truncated": count > entries.len(),
+                    "entries": entries,
+                }))
+                .with_render(RenderHint::FileList { source: Some("entries".to_string()) }))
             }
             Err(e) => Ok(ToolResult::error(format!("Failed to list directory: {}", e))),
         }
     }
 }
 
-/// Bash 命令执行工具
-pub struct BashTool;
+/// Bash 命令的最长超时（秒），模型请求更长时按此截断
+const BASH_MAX_TIMEOUT_SECS: u64 = 600;
+
+/// Bash 命令执行工具，命令经由 [`ProcessManager`](crate::process::ProcessManager) 运行
+pub struct BashTool {
+    guard: crate::security::command_guard::CommandGuard,
+    limits: crate::process::limits::ResourceLimits,
+    target: crate::process::devcontainer::SharedExecutionTarget,
+    consensus: Option<Arc<crate::security::consensus::ConsensusGate>>,
+    record_lessons: bool,
+    processes: Arc<crate::process::ProcessManager>,
+    max_output_bytes: usize,
+}
+
+impl Default for BashTool {
+    fn default() -> Self {
+        Self {
+            guard: Default::default(),
+            limits: Default::default(),
+            target: Default::default(),
+            consensus: None,
+            record_lessons: false,
+            processes: Default::default(),
+            max_output_bytes: crate::config::BashGuardConfig::default().max_output_bytes,
+        }
+    }
+}
+
+impl BashTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 标准输出和标准错误各自返回给模型的字节上限
+    pub fn with_output_limit(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    /// 使用指定的命令防护规则
+    pub fn with_guard(guard: crate::security::command_guard::CommandGuard) -> Self {
+        Self { guard, ..Self::default() }
+    }
+
+    /// 为执行的命令设置资源上限
+    pub fn with_limits(mut self, limits: crate::process::limits::ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// 命令在共享的执行位置（本机或开发容器）中运行
+    pub fn with_target(mut self, target: crate::process::devcontainer::SharedExecutionTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// 破坏性命令执行前先经过多模型共识检查
+    pub fn with_consensus(mut self, consensus: Arc<crate::security::consensus::ConsensusGate>) -> Self {
+        self.consensus = Some(consensus);
+        self
+    }
+
+    /// 命令因找不到程序或路径而失败时，为所在仓库记下一条教训
+    pub fn with_lessons(mut self, record_lessons: bool) -> Self {
+        self.record_lessons = record_lessons;
+        self
+    }
+}
 
 #[async_trait]
 impl Tool for BashTool {
@@ -308,11 +696,19 @@ impl Tool for BashTool {
                 ToolParameter {
                     name: "timeout".to_string(),
                     param_type: "number".to_string(),
-                    description: "Timeout in seconds (default: 30)".to_string(),
+                    description: format!("Timeout in seconds (default: 30, max: {})", BASH_MAX_TIMEOUT_SECS),
                     required: false,
                     default: Some(Value::Number(serde_json::Number::from(30))),
                     constraints: None,
                 },
+                ToolParameter {
+                    name: "cwd".to_string(),
+                    param_type: "string".to_string(),
+                    description: "Directory to run the command in, relative to the working directory; it cannot leave the working directory (default: the working directory)".to_string(),
+                    required: false,
+                    default: None,
+                    constraints: None,
+                },
             ],
             category: "system".to_string(),
             requires_confirmation: true,
@@ -330,108 +726,1254 @@ impl Tool for BashTool {
 
         let timeout = parameters.get("timeout")
             .and_then(|v| v.as_u64())
-            .unwrap_or(30);
+            .unwrap_or(30)
+            .min(BASH_MAX_TIMEOUT_SECS);
 
-        // 安全检查：禁止危险命令
-        let dangerous_commands = ["rm -rf", "sudo", "su", "chmod 777", "mkfs", "dd"];
-        for dangerous in &dangerous_commands {
-            if command.contains(dangerous) {
-                return Ok(ToolResult::error(format!("Dangerous command not allowed: {}", dangerous)));
+        // 工作目录固定在会话工作目录之内，符号链接和 `..` 解析后再比较
+        let cwd = match parameters.get("cwd").and_then(|v| v.as_str()) {
+            Some(cwd) => {
+                let root = std::fs::canonicalize(&context.working_directory);
+                let dir = std::fs::canonicalize(Path::new(&context.working_directory).join(cwd));
+                match (root, dir) {
+                    (Ok(root), Ok(dir)) if dir.starts_with(&root) && dir.is_dir() => dir,
+                    (Ok(root), Ok(dir)) if dir.starts_with(&root) => {
+                        return Ok(ToolResult::error(format!("'{}' is not a directory", cwd)));
+                    }
+                    (_, Err(e)) => return Ok(ToolResult::error(format!("Cannot use '{}' as the working directory: {}", cwd, e))),
+                    _ => return Ok(path_traversal_error()),
+                }
             }
-        }
+            None => std::path::PathBuf::from(&context.working_directory),
+        };
 
-        let mut cmd = Command::new("bash");
-        cmd.arg("-c")
-           .arg(command)
-           .current_dir(&context.working_directory);
-
-        // 设置环境变量
-        for (key, value) in &context.environment {
-            cmd.env(key, value);
-        }
-
-        let start_time = std::time::Instant::now();
-        
-        match tokio::time::timeout(
-            std::time::Duration::from_secs(timeout),
-            cmd.output()
-        ).await {
-            Ok(Ok(output)) => {
-                let execution_time = start_time.elapsed().as_millis() as u64;
-                
-                let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                
-                Ok(ToolResult::success(serde_json::json!({
-                    "stdout": stdout,
-                    "stderr": stderr,
-                    "exit_code": output.status.code().unwrap_or(-1),
-                    "success": output.status.success(),
-                    "execution_time_ms": execution_time
-                })))
+        // 安全检查：命令防护规则，拦截原因返回给模型以便选择更安全的做法
+        let verdict = self.guard.check_in(command, &cwd);
+        if let crate::security::command_guard::GuardVerdict::Blocked { rule, .. } = &verdict {
+            if rule == "privilege-escalation" {
+                crate::network::webhooks::notify(crate::network::webhooks::WebhookEvent::PermissionEscalation {
+                    actor: "bash tool".to_string(),
+                    action: command.to_string(),
+                    decision: "blocked".to_string(),
+                });
             }
-            Ok(Err(e)) => Ok(ToolResult::error(format!("Failed to execute command: {}", e))),
-            Err(_) => Ok(ToolResult::error(format!("Command timed out after {} seconds", timeout))),
         }
-    }
-}
+        if let Some(message) = verdict.message() {
+            let mut result = ToolResult::error(message);
+            result.data = serde_json::to_value(&verdict)?;
+            return Ok(result);
+        }
 
-/// 注册所有内置工具
-pub async fn register_builtin_tools(registry: &ToolRegistry) -> Result<()> {
-    registry.register_tool(Arc::new(ReadTool::new())).await?;
-    registry.register_tool(Arc::new(WriteTool::new())).await?;
-    registry.register_tool(Arc::new(ListTool::new())).await?;
-    registry.register_tool(Arc::new(BashTool)).await?;
-    
-    tracing::info!("Registered {} builtin tools", 4);
-    Ok(())
-}
+        // 破坏性命令：两方评审，不一致时由用户决定
+        if let Some((gate, action)) = self.consensus.as_ref().and_then(|gate| Some((gate, gate.classify(command)?))) {
+            let outcome = gate.review(&action, &context.working_directory).await;
+            if !outcome.resolution.proceeds() {
+                let mut result = ToolResult::error(outcome.message());
+                result.data = serde_json::to_value(&outcome)?;
+                return Ok(result);
+            }
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+        let target = self.target.read().map(|target| target.clone()).unwrap_or_default();
+        let (program, args) = target.shell_command(command, &cwd);
 
-    #[tokio::test]
-    async fn test_read_tool() {
-        let temp_dir = TempDir::new().unwrap();
-        let file_path = temp_dir.path().join("test.txt");
-        tokio::fs::write(&file_path, "Hello, World!").await.unwrap();
+        // 设置环境变量；在本机执行时告知会话临时工作区的位置
+        let mut env = context.environment.clone();
+        if target == crate::process::devcontainer::ExecutionTarget::Host {
+            let scratch = crate::fs::scratch::Scratchpad::for_session(&context.session_id);
+            if let Ok(dir) = scratch.ensure() {
+                env.insert(crate::fs::scratch::ENV_VAR.to_string(), dir.to_string_lossy().into_owned());
+            }
+        }
 
-        let tool = ReadTool::new();
-        let context = ToolContext {
-            working_directory: temp_dir.path().to_string_lossy().to_string(),
-            ..ToolContext::new("test".to_string())
+        let config = crate::process::ProcessConfig {
+            name: "bash".to_string(),
+            command: program,
+            args,
+            env,
+            working_dir: Some(cwd.to_string_lossy().into_owned()),
+            timeout: Some(timeout),
+            capture_output: true,
+            auto_restart: false,
+            limits: self.limits.clone(),
+        };
+        let output = match self.processes.run(config, self.max_output_bytes).await {
+            Ok(output) => output,
+            Err(e) => return Ok(ToolResult::error(format!("Failed to execute command: {}", e))),
         };
 
-        let parameters = serde_json::json!({
-            "path": "test.txt"
+        let data = serde_json::json!({
+            "stdout": output.stdout,
+            "stderr": output.stderr,
+            "exit_code": output.exit_code.unwrap_or(-1),
+            "success": output.success,
+            "execution_time_ms": output.duration.as_millis() as u64,
+            "timed_out": output.timed_out,
+            "truncated_bytes": output.truncated_bytes,
+            "limit_exceeded": output.limit_violation,
+            "cwd": cwd.to_string_lossy(),
+            "target": target.label(),
         });
 
-        let result = tool.execute(parameters, &context).await.unwrap();
-        assert!(result.success);
-        assert_eq!(result.data["content"], "Hello, World!");
+        if self.record_lessons && !output.success && !output.timed_out {
+            if let Some(lesson) = crate::conversation::lessons::Lesson::from_command_failure(command, output.exit_code, &output.stderr) {
+                let recorded = crate::conversation::lessons::LessonStore::for_repo(Path::new(&context.working_directory))
+                    .and_then(|mut store| store.record(lesson).map(|_| ()));
+                if let Err(e) = recorded {
+                    tracing::debug!("Failed to record lesson: {}", e);
+                }
+            }
+        }
+
+        // 超时或触及资源上限时以错误返回，让模型知道命令为何被终止；已产生的输出仍然附上
+        let failure = match (&output.limit_violation, output.timed_out) {
+            (_, true) => Some(format!("Command timed out after {} seconds", timeout)),
+            (Some(violation), _) => Some(violation.message.clone()),
+            (None, false) => None,
+        };
+        match failure {
+            Some(message) => {
+                let mut result = ToolResult::error(message);
+                result.data = data;
+                Ok(result)
+            }
+            None => Ok(ToolResult::success(data)),
+        }
     }
+}
 
-    #[tokio::test]
-    async fn test_write_tool() {
-        let temp_dir = TempDir::new().unwrap();
-        let tool = WriteTool::new();
-        let context = ToolContext {
-            working_directory: temp_dir.path().to_string_lossy().to_string(),
-            ..ToolContext::new("test".to_string())
+/// 正则文本搜索工具
+pub struct GrepTool;
+
+crate::tool_input! {
+    /// 文本搜索工具输入
+    pub struct GrepInput {
+        /// Regular expression to search for (Rust regex syntax)
+        pub pattern: String,
+        /// File or directory to search, relative to the working directory
+        pub path: String = ".",
+        /// Only search files matching this glob, e.g. "*.rs" or "src/**/*.ts"
+        pub glob: Option<String>,
+        /// Only search files of these types, comma-separated: rust, py, js, ts, go, java, c, cpp, md, json, toml, yaml, ...
+        pub file_type: Option<String>,
+        /// Match case-insensitively
+        pub ignore_case: bool = false,
+        /// Lines of context to show before and after each match (content mode only)
+        pub context: usize = 0,
+        /// "content" for matching lines, "files_with_matches" for file paths, "count" for matches per file
+        pub output_mode: String = "content",
+        /// Maximum number of lines, files or counts to return (0 for no limit)
+        pub head_limit: usize = 200,
+    }
+}
+
+#[async_trait]
+impl TypedTool for GrepTool {
+    type Input = GrepInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("grep")
+            .description("Search file contents with a regular expression across the workspace, skipping .gitignore'd and binary files. Prefer this over running grep or rg through bash")
+            .category("search")
+            .input::<GrepInput>()
+            .build()
+    }
+
+    async fn run(&self, input: GrepInput, context: &ToolContext) -> Result<ToolResult> {
+        use crate::fs::search::{search, OutputMode, SearchSpec};
+
+        let mode: OutputMode = input.output_mode.parse()?;
+        let Some(target) = resolve_tool_path(context, &input.path) else {
+            return Ok(path_traversal_error());
+        };
+        if !target.exists() {
+            return Ok(ToolResult::error(format!("Path not found: {}", input.path)));
+        }
+        let spec = SearchSpec {
+            pattern: input.pattern,
+            ignore_case: input.ignore_case,
+            glob: input.glob,
+            file_types: input.file_type.map(|types| types.split(',').map(str::to_string).collect()).unwrap_or_default(),
+            context: input.context,
+        };
+        let root = std::path::PathBuf::from(&context.working_directory);
+        let report = tokio::task::spawn_blocking(move || search(&root, &target, &spec))
+            .await
+            .map_err(|e| ClaudeError::General(format!("Search task failed: {}", e)))?;
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
         };
 
-        let parameters = serde_json::json!({
-            "path": "test.txt",
-            "content": "Hello, Rust!"
-        });
+        let (output, truncated) = report.render(mode, input.head_limit);
+        let output = match (report.files.is_empty(), truncated) {
+            (true, _) => "No matches found".to_string(),
+            (false, true) => format!("{}\n... (results truncated at {}; narrow the search or raise head_limit)", output, input.head_limit),
+            (false, false) => output,
+        };
+        Ok(ToolResult::success(serde_json::json!({
+            "output": output,
+            "files": report.files.len(),
+            "matches": report.total_matches(),
+            "files_searched": report.files_searched,
+            "truncated": truncated,
+        }))
+        .with_render(RenderHint::Text { source: Some("output".to_string()) }))
+    }
+}
 
-        let result = tool.execute(parameters, &context).await.unwrap();
-        assert!(result.success);
+/// 文件通配符查找工具
+pub struct GlobTool {
+    fs_manager: FileSystemManager,
+}
+
+impl GlobTool {
+    pub fn new() -> Self {
+        Self::with_roots(vec![std::env::current_dir().unwrap_or_default()])
+    }
+
+    /// 只在这些目录内查找
+    pub fn with_roots(roots: Vec<std::path::PathBuf>) -> Self {
+        Self { fs_manager: FileSystemManager::new(roots) }
+    }
+}
+
+impl Default for GlobTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+crate::tool_input! {
+    /// 文件通配符查找工具输入
+    pub struct GlobInput {
+        /// Glob matched against paths relative to `path`, e.g. "src/**/*.rs" or "*.toml"; `*` stays within one directory, `**` spans directories
+        pub pattern: String,
+        /// Directory to search from, relative to the working directory
+        pub path: String = ".",
+        /// Maximum number of files to return (0 for no limit)
+        pub limit: usize = 100,
+    }
+}
+
+#[async_trait]
+impl TypedTool for GlobTool {
+    type Input = GlobInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("glob")
+            .description("Find files by name pattern, most recently modified first, skipping .gitignore'd files. Use it instead of find or ls through bash")
+            .category("search")
+            .input::<GlobInput>()
+            .build()
+    }
+
+    async fn run(&self, input: GlobInput, context: &ToolContext) -> Result<ToolResult> {
+        let Some(base) = resolve_tool_path(context, &input.path) else {
+            return Ok(path_traversal_error());
+        };
+        let files = match self.fs_manager.glob(&base, &input.pattern).await {
+            Ok(files) => files,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+
+        let limit = if input.limit == 0 { usize::MAX } else { input.limit };
+        let working_directory = Path::new(&context.working_directory);
+        let paths: Vec<String> = files
+            .iter()
+            .take(limit)
+            .map(|path| path.strip_prefix(working_directory).unwrap_or(path).to_string_lossy().into_owned())
+            .collect();
+        Ok(ToolResult::success(serde_json::json!({
+            "pattern": input.pattern,
+            "count": files.len(),
+            "truncated": files.len() > paths.len(),
+            "files": paths,
+        }))
+        .with_render(RenderHint::FileList { source: Some("files".to_string()) }))
+    }
+}
+
+/// 会话任务清单工具
+pub struct TodoWriteTool {
+    root: std::path::PathBuf,
+}
+
+impl TodoWriteTool {
+    pub fn new() -> Self {
+        Self::with_root(crate::tools::todo::TodoStore::root())
+    }
+
+    /// 把清单保存在指定目录中
+    pub fn with_root(root: impl Into<std::path::PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl Default for TodoWriteTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+crate::tool_input! {
+    /// 任务清单工具输入
+    pub struct TodoWriteInput {
+        /// The complete, updated task list; replaces the previous list. Each item is {"content": "...", "status": "pending" | "in_progress" | "completed", "active_form": "..."}, where active_form is the present-continuous label shown while the task is in progress
+        pub todos: Vec<crate::tools::todo::TodoItem>,
+    }
+}
+
+#[async_trait]
+impl TypedTool for TodoWriteTool {
+    type Input = TodoWriteInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("todo_write")
+            .description("Create and update the task list for the current session. Use it to plan multi-step work and to show progress: keep exactly one task in_progress while working and mark tasks completed as soon as they are done")
+            .category("agent")
+            .input::<TodoWriteInput>()
+            .build()
+    }
+
+    async fn run(&self, input: TodoWriteInput, context: &ToolContext) -> Result<ToolResult> {
+        let store = crate::tools::todo::TodoStore::in_dir(&self.root, &context.session_id);
+        if let Err(e) = store.save(&input.todos) {
+            return Ok(ToolResult::error(e.to_string()));
+        }
+        let completed = input
+            .todos
+            .iter()
+            .filter(|item| item.status == crate::tools::todo::TodoStatus::Completed)
+            .count();
+        Ok(ToolResult::success(serde_json::json!({
+            "todos": input.todos,
+            "completed": completed,
+            "total": input.todos.len(),
+            "summary": crate::tools::todo::summary(&input.todos),
+        }))
+        .with_render(RenderHint::Table {
+            columns: vec!["status".to_string(), "content".to_string()],
+            source: Some("todos".to_string()),
+        }))
+    }
+}
+
+/// 续读被截断的工具输出
+pub struct ToolOutputTool {
+    root: std::path::PathBuf,
+}
+
+impl ToolOutputTool {
+    pub fn new() -> Self {
+        Self::with_root(crate::tools::continuation::OutputSpool::root())
+    }
+
+    /// 从指定目录读取保存的输出
+    pub fn with_root(root: impl Into<std::path::PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl Default for ToolOutputTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+crate::tool_input! {
+    /// 续读工具输入
+    pub struct ToolOutputInput {
+        /// Continuation cursor from a truncated tool result, e.g. "3f9a1c2b7d4e:40960"
+        pub cursor: String,
+        /// Maximum number of tokens to return
+        pub max_tokens: u32 = 10_000,
+    }
+}
+
+#[async_trait]
+impl TypedTool for ToolOutputTool {
+    type Input = ToolOutputInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("tool_output")
+            .description("Read the next part of a tool result that was truncated. Pass the cursor from the truncation notice; the result ends with a new cursor while more output remains")
+            .category("agent")
+            .input::<ToolOutputInput>()
+            .build()
+    }
+
+    async fn run(&self, input: ToolOutputInput, context: &ToolContext) -> Result<ToolResult> {
+        let spool = crate::tools::continuation::OutputSpool::in_dir(&self.root, &context.session_id);
+        match spool.read(&input.cursor, input.max_tokens) {
+            Ok(page) => Ok(ToolResult::success(Value::String(page.to_model_content()))),
+            Err(e) => Ok(ToolResult::error(e.to_string())),
+        }
+    }
+}
+
+/// 网页抓取工具，抓取结果在会话内缓存
+pub struct WebFetchTool {
+    fetcher: crate::network::fetch::WebFetcher,
+}
+
+impl WebFetchTool {
+    pub fn new(config: &crate::config::ClaudeConfig) -> Result<Self> {
+        Ok(Self { fetcher: crate::network::fetch::WebFetcher::new(config)? })
+    }
+}
+
+crate::tool_input! {
+    /// 网页抓取工具输入
+    pub struct WebFetchInput {
+        /// Fully qualified http(s) URL to fetch
+        pub url: String,
+    }
+}
+
+#[async_trait]
+impl TypedTool for WebFetchTool {
+    type Input = WebFetchInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("web_fetch")
+            .description("Fetch a web page and return its content as Markdown (plain text and JSON are returned as-is). Long pages are truncated; repeated fetches of the same URL are served from a cache")
+            .category("web")
+            .security_level(SecurityLevel::Medium)
+            .input::<WebFetchInput>()
+            .build()
+    }
+
+    async fn run(&self, input: WebFetchInput, _context: &ToolContext) -> Result<ToolResult> {
+        let (page, cached) = match self.fetcher.fetch(&input.url).await {
+            Ok(fetched) => fetched,
+            Err(e) => return Ok(ToolResult::error(format!("Failed to fetch {}: {}", input.url, e))),
+        };
+        let mut data = serde_json::to_value(&*page)?;
+        data["cached"] = Value::Bool(cached);
+        let result = if (200..300).contains(&page.status) {
+            ToolResult::success(data)
+        } else {
+            let mut result = ToolResult::error(format!("{} returned HTTP {}", page.url, page.status));
+            result.data = data;
+            result
+        };
+        Ok(result.with_render(RenderHint::Text { source: Some("content".to_string()) }))
+    }
+}
+
+/// 项目范围的搜索替换工具
+pub struct ReplaceTool;
+
+crate::tool_input! {
+    /// 搜索替换工具输入
+    pub struct ReplaceInput {
+        /// Regular expression to search for, or plain text when literal is true
+        pub pattern: String,
+        /// Replacement text; $1 or ${name} insert capture groups unless literal is true
+        pub replacement: String,
+        /// Treat pattern and replacement as plain text
+        pub literal: bool = false,
+        /// Match case-insensitively
+        pub ignore_case: bool = false,
+        /// Only touch paths matching these globs, relative to the working directory (e.g. "src/**/*.rs")
+        pub paths: Option<Vec<String>>,
+        /// Skip paths matching these globs
+        pub exclude: Option<Vec<String>>,
+        /// Write the changes; when false only a preview of every match is returned
+        pub apply: bool = false,
+    }
+}
+
+#[async_trait]
+impl TypedTool for ReplaceTool {
+    type Input = ReplaceInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("replace")
+            .description("Search and replace across the project with regex capture groups or literal text. Preview first, then call again with apply=true; applied changes can be undone with `claude replace --undo`")
+            .category("filesystem")
+            .requires_confirmation(true)
+            .security_level(SecurityLevel::Medium)
+            .input::<ReplaceInput>()
+            .build()
+    }
+
+    async fn run(&self, input: ReplaceInput, context: &ToolContext) -> Result<ToolResult> {
+        use crate::fs::replace::{plan, ReplaceSpec};
+        use crate::fs::undo::UndoJournal;
+
+        let spec = ReplaceSpec {
+            pattern: input.pattern,
+            replacement: input.replacement,
+            literal: input.literal,
+            ignore_case: input.ignore_case,
+            include: input.paths.unwrap_or_default(),
+            exclude: input.exclude.unwrap_or_default(),
+        };
+        let root = std::path::PathBuf::from(&context.working_directory);
+        let apply = input.apply;
+        let outcome = tokio::task::spawn_blocking(move || -> Result<_> {
+            let plan = plan(&root, &spec)?;
+            let entry = if apply && !plan.is_empty() {
+                let description = format!("replace {} -> {}", spec.pattern, spec.replacement);
+                Some(plan.apply(&UndoJournal::new(UndoJournal::default_dir()), &description)?)
+            } else {
+                None
+            };
+            Ok((plan, entry))
+        })
+        .await
+        .map_err(|e| ClaudeError::General(format!("Replace task failed: {}", e)))?;
+
+        let (plan, entry) = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        Ok(ToolResult::success(serde_json::json!({
+            "applied": entry.is_some(),
+            "undo_id": entry.map(|e| e.id),
+            "total_matches": plan.total_matches(),
+            "files": plan.files,
+            "preview": plan.preview(),
+        }))
+        .with_render(RenderHint::Text { source: Some("preview".to_string()) }))
+    }
+}
+
+/// 文件移动后的导入修复工具
+pub struct FixImportsTool;
+
+crate::tool_input! {
+    /// 导入修复工具输入
+    pub struct FixImportsInput {
+        /// Moves as "old/path -> new/path" relative to the working directory; directories expand to their files. Defaults to the renames git reports against HEAD
+        pub moves: Option<Vec<String>>,
+        /// Write the fixes; when false only a preview is returned
+        pub apply: bool = false,
+    }
+}
+
+#[async_trait]
+impl TypedTool for FixImportsTool {
+    type Input = FixImportsInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("fix_imports")
+            .description("After moving or renaming files, rewrite imports, `use` paths and `mod` declarations that still point at the old locations (Rust, Python, TypeScript/JavaScript). Preview first, then call again with apply=true; imports that cannot be fixed mechanically are listed in `unresolved` for you to handle")
+            .category("filesystem")
+            .requires_confirmation(true)
+            .security_level(SecurityLevel::Medium)
+            .input::<FixImportsInput>()
+            .build()
+    }
+
+    async fn run(&self, input: FixImportsInput, context: &ToolContext) -> Result<ToolResult> {
+        use crate::fs::undo::UndoJournal;
+        use crate::refactor::imports::{detect_moves, fix_imports, FileMove};
+
+        let root = std::path::PathBuf::from(&context.working_directory);
+        let apply = input.apply;
+        let outcome = tokio::task::spawn_blocking(move || -> Result<_> {
+            let moves = match input.moves {
+                Some(specs) => specs.iter().map(|spec| FileMove::parse(spec)).collect::<Result<Vec<_>>>()?,
+                None => detect_moves(&root)?,
+            };
+            let report = fix_imports(&root, &moves)?;
+            let entry = if apply && !report.plan.is_empty() {
+                Some(report.plan.apply(&UndoJournal::new(UndoJournal::default_dir()), "fix imports after moves")?)
+            } else {
+                None
+            };
+            Ok((report, entry))
+        })
+        .await
+        .map_err(|e| ClaudeError::General(format!("Import fix task failed: {}", e)))?;
+
+        let (report, entry) = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        if report.moves.is_empty() {
+            return Ok(ToolResult::error("No moved files found; pass moves as \"old/path -> new/path\"".to_string()));
+        }
+        Ok(ToolResult::success(serde_json::json!({
+            "applied": entry.is_some(),
+            "undo_id": entry.map(|e| e.id),
+            "moves": report.moves,
+            "edits": report.plan.total_matches(),
+            "files": report.plan.files,
+            "unresolved": report.unresolved,
+            "preview": report.plan.preview(),
+        }))
+        .with_render(RenderHint::Text { source: Some("preview".to_string()) }))
+    }
+}
+
+/// 工作区状态工具（`git status`），计划模式下可用
+pub struct GitStatusTool;
+
+crate::tool_input! {
+    /// git 状态工具输入
+    pub struct GitStatusInput {}
+}
+
+#[async_trait]
+impl TypedTool for GitStatusTool {
+    type Input = GitStatusInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("git_status")
+            .description("Show the current branch and the staged, unstaged and untracked files of the git repository in the working directory")
+            .category("git")
+            .input::<GitStatusInput>()
+            .build()
+    }
+
+    async fn run(&self, _input: GitStatusInput, context: &ToolContext) -> Result<ToolResult> {
+        run_git(context, &["status", "--short", "--branch"]).await
+    }
+}
+
+/// 未提交改动的差异工具（`git diff`），计划模式下可用
+pub struct GitDiffTool;
+
+crate::tool_input! {
+    /// git 差异工具输入
+    pub struct GitDiffInput {
+        /// Show staged changes instead of unstaged ones
+        pub staged: bool = false,
+        /// Limit the diff to this file or directory, relative to the working directory
+        pub path: Option<String>,
+    }
+}
+
+#[async_trait]
+impl TypedTool for GitDiffTool {
+    type Input = GitDiffInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("git_diff")
+            .description("Show the uncommitted changes of the git repository in the working directory as a unified diff")
+            .category("git")
+            .input::<GitDiffInput>()
+            .build()
+    }
+
+    async fn run(&self, input: GitDiffInput, context: &ToolContext) -> Result<ToolResult> {
+        let mut args = vec!["diff", "--no-ext-diff"];
+        if input.staged {
+            args.push("--cached");
+        }
+        if let Some(path) = input.path.as_deref() {
+            if resolve_tool_path(context, path).is_none() {
+                return Ok(path_traversal_error());
+            }
+            // 路径放在 `--` 之后，不会被当作选项
+            args.extend(["--", path]);
+        }
+        run_git(context, &args).await
+    }
+}
+
+/// 在工作目录中运行固定参数的 git 命令
+async fn run_git(context: &ToolContext, args: &[&str]) -> Result<ToolResult> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let git = crate::git::GitManager::new(context.working_directory.clone().into());
+    match git.run(&args).await {
+        Ok(output) => Ok(ToolResult::success(Value::String(output))),
+        Err(e) => Ok(ToolResult::error(e.to_string())),
+    }
+}
+
+/// 截图比对工具
+#[cfg(feature = "image-processing")]
+pub struct ImageDiffTool;
+
+#[cfg(feature = "image-processing")]
+crate::tool_input! {
+    /// 截图比对工具输入
+    pub struct ImageDiffInput {
+        /// Path of the baseline ("before") image
+        pub before: String,
+        /// Path of the new ("after") image
+        pub after: String,
+        /// Where to write the diff image (default: <after>.diff.png next to the after image)
+        pub output: Option<String>,
+        /// Comparison mode: "perceptual" ignores changes the eye cannot see, "pixel" compares raw channels
+        pub mode: String = "perceptual",
+        /// Tolerance between 0.0 and 1.0; differences at or below it are ignored
+        pub threshold: f64 = 0.1,
+    }
+}
+
+#[cfg(feature = "image-processing")]
+#[async_trait]
+impl TypedTool for ImageDiffTool {
+    type Input = ImageDiffInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("image_diff")
+            .description("Compare two screenshots for visual regressions and write a diff image highlighting changed pixels")
+            .category("image")
+            .input::<ImageDiffInput>()
+            // 差异图会写入工作目录中的任意路径，覆盖已有文件
+            .requires_confirmation(true)
+            .security_level(SecurityLevel::Medium)
+            .build()
+    }
+
+    async fn run(&self, input: ImageDiffInput, context: &ToolContext) -> Result<ToolResult> {
+        use crate::image_processing::diff::{compare, DiffOptions};
+
+        let (Some(before_path), Some(after_path)) = (resolve_tool_path(context, &input.before), resolve_tool_path(context, &input.after)) else {
+            return Ok(path_traversal_error());
+        };
+        let output_path = match &input.output {
+            Some(output) => match resolve_tool_path(context, output) {
+                Some(path) => path,
+                None => return Ok(path_traversal_error()),
+            },
+            None => after_path.with_extension("diff.png"),
+        };
+
+        let options = DiffOptions {
+            mode: input.mode.parse()?,
+            threshold: input.threshold,
+        };
+        let load = |path: &Path| {
+            image::open(path).map_err(|e| ClaudeError::General(format!("Failed to open image '{}': {}", path.display(), e)))
+        };
+        let before = match load(&before_path) {
+            Ok(img) => img,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        let after = match load(&after_path) {
+            Ok(img) => img,
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+
+        let diff = compare(&before, &after, &options)?;
+        diff.diff_image
+            .save(&output_path)
+            .map_err(|e| ClaudeError::General(format!("Failed to save diff image '{}': {}", output_path.display(), e)))?;
+
+        Ok(ToolResult::success(serde_json::json!({
+            "identical": diff.is_identical(),
+            "difference_percent": (diff.difference_percent() * 1000.0).round() / 1000.0,
+            "differing_pixels": diff.differing_pixels,
+            "total_pixels": diff.total_pixels,
+            "width": diff.width,
+            "height": diff.height,
+            "size_mismatch": diff.size_mismatch,
+            "region": diff.region,
+            "diff_image": output_path.to_string_lossy(),
+        })))
+    }
+}
+
+/// 子 Agent 任务工具：把搜索、重构等长任务交给独立的 Agent 循环，只把总结返回给调用方
+pub struct TaskTool {
+    client: Arc<crate::network::ClaudeApiClient>,
+    config: crate::config::ClaudeConfig,
+    /// 子 Agent 可以使用的工具
+    tools: Arc<ToolRegistry>,
+}
+
+impl TaskTool {
+    pub fn new(client: Arc<crate::network::ClaudeApiClient>, config: crate::config::ClaudeConfig, tools: Arc<ToolRegistry>) -> Self {
+        Self { client, config, tools }
+    }
+}
+
+crate::tool_input! {
+    /// 子 Agent 任务输入
+    pub struct TaskInput {
+        /// Short (3-5 word) description of the task
+        pub description: String,
+        /// Complete instructions for the sub-agent; it does not see this conversation
+        pub prompt: String,
+        /// Tools the sub-agent may use (default: all tools except task)
+        pub allowed_tools: Option<Vec<String>>,
+        /// Maximum input plus output tokens the sub-agent may spend
+        pub token_budget: u64 = 100000,
+    }
+}
+
+#[async_trait]
+impl TypedTool for TaskTool {
+    type Input = TaskInput;
+
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition::builder("task")
+            .description("Delegate a self-contained task (a broad search, a multi-file refactor) to a sub-agent with its own context and tools. Only its final summary is returned, so use it to keep large intermediate results out of this conversation")
+            .category("agent")
+            .security_level(SecurityLevel::Medium)
+            .input::<TaskInput>()
+            .build()
+    }
+
+    async fn run(&self, input: TaskInput, context: &ToolContext) -> Result<ToolResult> {
+        use crate::agent::{AgentContext, AgentLoop};
+
+        let tools = match self.tools.subset(input.allowed_tools.as_deref()).await {
+            Ok(tools) => Arc::new(tools),
+            Err(e) => return Ok(ToolResult::error(e.to_string())),
+        };
+        let session_id = format!("{}-task-{}", context.session_id, &uuid::Uuid::new_v4().to_string()[..8]);
+        let tool_context = ToolContext { session_id: session_id.clone(), ..context.clone() };
+        let (agent, _responses) = AgentLoop::new(
+            AgentContext::new(session_id, self.config.clone()),
+            crate::conversation::ConversationManager::new(),
+        );
+        let mut agent = agent
+            .with_client(self.client.clone())
+            .with_tools(tools)
+            .with_tool_context(tool_context)
+            .with_token_budget(input.token_budget);
+
+        tracing::info!("Starting sub-agent task: {}", input.description);
+        let prompt = format!(
+            "{}\n\nWork autonomously. When you are done, reply with a concise summary of what you found or changed; it is the only part of your work the requester will see.",
+            input.prompt
+        );
+        if let Err(e) = agent.run(vec![prompt]).await {
+            return Ok(ToolResult::error(format!("Sub-agent task '{}' failed: {}", input.description, e)));
+        }
+
+        let (input_tokens, output_tokens) = agent.usage();
+        Ok(ToolResult::success(serde_json::json!({
+            "description": input.description,
+            "result": agent.final_text(),
+            "turns": agent.latency_stats().last_turn().map_or(0, |t| t.turn),
+            "input_tokens": input_tokens,
+            "output_tokens": output_tokens,
+            "budget_exhausted": agent.budget_exhausted(),
+        })))
+    }
+}
+
+/// 有 API 密钥时注册 `task` 工具，子 Agent 可以使用注册表中已有的全部工具
+async fn register_task_tool(registry: &ToolRegistry, config: &crate::config::ClaudeConfig) -> Result<()> {
+    let Some(api_key) = config.api.anthropic_api_key.clone().or_else(|| std::env::var("ANTHROPIC_API_KEY").ok()) else {
+        tracing::debug!("No API key configured, the task tool is unavailable");
+        return Ok(());
+    };
+    let client = Arc::new(crate::network::ClaudeApiClient::new(api_key, Some(config.api.base_url.clone()))?);
+    let tools = Arc::new(registry.subset(None).await?);
+    registry.register_tool(Arc::new(TaskTool::new(client, config.clone(), tools))).await
+}
+
+/// 注册所有内置工具
+pub async fn register_builtin_tools(registry: &ToolRegistry) -> Result<()> {
+    registry.register_tool(Arc::new(ReadTool::new())).await?;
+    registry.register_tool(Arc::new(WriteTool::new())).await?;
+    registry.register_tool(Arc::new(EditTool::new())).await?;
+    registry.register_tool(Arc::new(LsTool)).await?;
+    registry.register_tool(Arc::new(BashTool::new())).await?;
+    registry.register_tool(Arc::new(GrepTool)).await?;
+    registry.register_tool(Arc::new(GlobTool::new())).await?;
+    registry.register_tool(Arc::new(TodoWriteTool::new())).await?;
+    registry.register_tool(Arc::new(ToolOutputTool::new())).await?;
+    registry.register_tool(Arc::new(NotebookReadTool::new())).await?;
+    registry.register_tool(Arc::new(NotebookEditTool::new())).await?;
+    registry.register_tool(Arc::new(WebFetchTool::new(&crate::config::ClaudeConfig::default())?)).await?;
+    registry.register_tool(Arc::new(ReplaceTool)).await?;
+    registry.register_tool(Arc::new(FixImportsTool)).await?;
+    registry.register_tool(Arc::new(GitStatusTool)).await?;
+    registry.register_tool(Arc::new(GitDiffTool)).await?;
+    #[cfg(feature = "image-processing")]
+    registry.register_tool(Arc::new(ImageDiffTool)).await?;
+    
+    tracing::info!("Registered {} builtin tools", registry.list_tools().await.len());
+    Ok(())
+}
+
+/// 按配置注册所有内置工具
+pub async fn register_builtin_tools_with_config(
+    registry: &ToolRegistry,
+    config: &crate::config::ClaudeConfig,
+    target: crate::process::devcontainer::SharedExecutionTarget,
+) -> Result<()> {
+    let guard = crate::security::command_guard::CommandGuard::from_config(&config.permissions.bash)?;
+
+    registry.register_tool(Arc::new(ReadTool::new())).await?;
+    registry.register_tool(Arc::new(WriteTool::new())).await?;
+    registry.register_tool(Arc::new(EditTool::new())).await?;
+    registry.register_tool(Arc::new(LsTool)).await?;
+    let mut bash = BashTool::with_guard(guard)
+        .with_limits(config.permissions.bash.limits.clone())
+        .with_target(target.clone())
+        .with_lessons(config.lessons.enabled)
+        .with_output_limit(config.permissions.bash.max_output_bytes);
+    if config.permissions.consensus.enabled {
+        bash = bash.with_consensus(Arc::new(crate::security::consensus::ConsensusGate::from_config(config)?));
+    }
+    registry.register_tool(Arc::new(bash)).await?;
+    registry.register_tool(Arc::new(GrepTool)).await?;
+    registry.register_tool(Arc::new(GlobTool::new())).await?;
+    registry.register_tool(Arc::new(TodoWriteTool::new())).await?;
+    registry.register_tool(Arc::new(ToolOutputTool::new())).await?;
+    registry.register_tool(Arc::new(NotebookReadTool::new())).await?;
+    registry.register_tool(Arc::new(NotebookEditTool::new())).await?;
+    registry.register_tool(Arc::new(WebFetchTool::new(config)?)).await?;
+    registry.register_tool(Arc::new(ReplaceTool)).await?;
+    registry.register_tool(Arc::new(FixImportsTool)).await?;
+    registry.register_tool(Arc::new(GitStatusTool)).await?;
+    registry.register_tool(Arc::new(GitDiffTool)).await?;
+    #[cfg(feature = "image-processing")]
+    registry.register_tool(Arc::new(ImageDiffTool)).await?;
+    register_task_tool(registry, config).await?;
+    super::custom::register_custom_tools(registry, config, target).await?;
+
+    tracing::info!("Registered {} builtin tools", registry.list_tools().await.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_read_tool() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        tokio::fs::write(&file_path, "Hello, World!").await.unwrap();
+
+        let tool = ReadTool::new();
+        let context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("test".to_string())
+        };
+
+        let parameters = serde_json::json!({
+            "path": "test.txt"
+        });
+
+        let result = tool.execute(parameters, &context).await.unwrap();
+        assert!(result.success);
+        assert_eq!(result.data["content"], "Hello, World!");
+    }
+
+    #[tokio::test]
+    async fn test_write_tool() {
+        let temp_dir = TempDir::new().unwrap();
+        let tool = WriteTool::new();
+        let context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("test".to_string())
+        };
+
+        let parameters = serde_json::json!({
+            "path": "test.txt",
+            "content": "Hello, Rust!"
+        });
+
+        let result = tool.execute(parameters, &context).await.unwrap();
+        assert!(result.success);
 
         // 验证文件内容
         let content = tokio::fs::read_to_string(temp_dir.path().join("test.txt")).await.unwrap();
         assert_eq!(content, "Hello, Rust!");
     }
+
+    #[tokio::test]
+    async fn test_file_tools_reject_parent_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = temp_dir.path().join("workspace");
+        std::fs::create_dir_all(workspace.join("src")).unwrap();
+        std::fs::write(temp_dir.path().join("secret.txt"), "secret").unwrap();
+        let context = ToolContext {
+            working_directory: workspace.to_string_lossy().to_string(),
+            ..ToolContext::new("test".to_string())
+        };
+
+        let read = ReadTool::new().execute(serde_json::json!({ "path": "src/../../secret.txt" }), &context).await.unwrap();
+        assert!(read.error.unwrap().contains("Path traversal"));
+        let write = WriteTool::new()
+            .execute(serde_json::json!({ "path": "../escaped.txt", "content": "x" }), &context)
+            .await
+            .unwrap();
+        assert!(write.error.unwrap().contains("Path traversal"));
+        assert!(!temp_dir.path().join("escaped.txt").exists());
+        let ls = LsTool.execute(serde_json::json!({ "path": ".." }), &context).await.unwrap();
+        assert!(ls.error.unwrap().contains("Path traversal"));
+
+        let inside = WriteTool::new()
+            .execute(serde_json::json!({ "path": "./src/lib.rs", "content": "x" }), &context)
+            .await
+            .unwrap();
+        assert!(inside.success);
+    }
+
+    #[tokio::test]
+    async fn test_edit_tool_replaces_exact_string() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("lib.rs");
+        tokio::fs::write(&file_path, "fn a() {}\nfn b() {}\nfn a() {}\n").await.unwrap();
+        let context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("test".to_string())
+        };
+        let tool = EditTool::new();
+
+        let ambiguous = tool
+            .execute(serde_json::json!({"path": "lib.rs", "old_string": "fn a", "new_string": "fn c"}), &context)
+            .await
+            .unwrap();
+        assert!(ambiguous.error.unwrap().contains("occurs 2 times"));
+
+        let result = tool
+            .execute(serde_json::json!({"path": "lib.rs", "old_string": "fn b() {}", "new_string": "fn b() { a() }"}), &context)
+            .await
+            .unwrap();
+        assert!(result.success);
+        assert_eq!(result.data["replacements"], 1);
+        let diff = result.data["diff"].as_str().unwrap();
+        assert!(diff.contains("--- a/lib.rs"));
+        assert!(diff.contains("-fn b() {}\n+fn b() { a() }"));
+
+        let all = tool
+            .execute(serde_json::json!({"path": "lib.rs", "old_string": "fn a", "new_string": "fn c", "replace_all": true}), &context)
+            .await
+            .unwrap();
+        assert_eq!(all.data["replacements"], 2);
+        let missing = tool
+            .execute(serde_json::json!({"path": "lib.rs", "old_string": "fn a", "new_string": "fn d"}), &context)
+            .await
+            .unwrap();
+        assert!(missing.error.unwrap().contains("not found"));
+        assert_eq!(tokio::fs::read_to_string(&file_path).await.unwrap(), "fn c() {}\nfn b() { a() }\nfn c() {}\n");
+    }
+
+    #[tokio::test]
+    async fn test_glob_tool_sorts_by_mtime_within_roots() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("project");
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".gitignore"), "generated.rs\n").unwrap();
+        for (name, age) in [("src/old.rs", 100), ("src/nested/new.rs", 0), ("src/generated.rs", 0), ("Cargo.toml", 50)] {
+            let path = root.join(name);
+            std::fs::write(&path, "").unwrap();
+            let modified = std::time::SystemTime::now() - std::time::Duration::from_secs(age);
+            std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        }
+        let context = ToolContext {
+            working_directory: root.to_string_lossy().to_string(),
+            ..ToolContext::new("test".to_string())
+        };
+
+        let tool = GlobTool::with_roots(vec![root.clone()]);
+        let result = tool.execute(serde_json::json!({"pattern": "src/**/*.rs"}), &context).await.unwrap();
+        assert_eq!(result.data["files"], serde_json::json!(["src/nested/new.rs", "src/old.rs"]));
+        let top = tool.execute(serde_json::json!({"pattern": "*.toml"}), &context).await.unwrap();
+        assert_eq!(top.data["files"], serde_json::json!(["Cargo.toml"]));
+
+        // 工作目录不在允许的根目录内时拒绝查找
+        let outside = GlobTool::with_roots(vec![temp_dir.path().join("elsewhere")]);
+        let denied = outside.execute(serde_json::json!({"pattern": "**/*"}), &context).await.unwrap();
+        assert!(denied.error.unwrap().contains("outside the allowed directories"));
+    }
+
+    #[tokio::test]
+    async fn test_ls_tool_reports_metadata_and_honors_ignores() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(root.join("Cargo.lock"), "").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "pub fn a() {}\n").unwrap();
+        std::fs::write(root.join("src/nested/mod.rs"), "").unwrap();
+        let context = ToolContext {
+            working_directory: root.to_string_lossy().to_string(),
+            ..ToolContext::new("test".to_string())
+        };
+
+        let result = LsTool.execute(serde_json::json!({"ignore": ["*.lock"]}), &context).await.unwrap();
+        let entries = result.data["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((&entries[0]["path"], &entries[0]["type"]), (&serde_json::json!("src"), &serde_json::json!("dir")));
+        assert!(entries[0]["modified"].is_string());
+
+        let nested = LsTool.execute(serde_json::json!({"path": "src", "depth": 2, "show_hidden": true}), &context).await.unwrap();
+        let paths: Vec<&str> = nested.data["entries"].as_array().unwrap().iter().map(|e| e["path"].as_str().unwrap()).collect();
+        assert_eq!(paths, ["lib.rs", "nested", "nested/mod.rs"]);
+        assert_eq!(nested.data["entries"][0]["size"], 14);
+
+        let all = LsTool
+            .execute(serde_json::json!({"show_hidden": true, "respect_gitignore": false, "limit": 2}), &context)
+            .await
+            .unwrap();
+        assert_eq!((all.data["count"].as_u64(), all.data["truncated"].as_bool()), (Some(5), Some(true)));
+        assert!(!LsTool.execute(serde_json::json!({"path": "/"}), &context).await.unwrap().success);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bash_tool_reports_limit() {
+        let tool = BashTool::new().with_limits(crate::process::limits::ResourceLimits {
+            cpu_seconds: Some(1),
+            ..Default::default()
+        });
+        let context = ToolContext::new("test".to_string());
+
+        let result = tool.execute(serde_json::json!({ "command": "while :; do :; done" }), &context).await.unwrap();
+        assert!(!result.success);
+        assert!(result.error.unwrap().contains("CPU time limit"));
+        assert_eq!(result.data["limit_exceeded"]["kind"], "cpu");
+    }
+
+    #[tokio::test]
+    async fn test_bash_tool_guardrail() {
+        let tool = BashTool::new();
+        let context = ToolContext::new("test".to_string());
+
+        let result = tool.execute(serde_json::json!({ "command": "rm -rf /" }), &context).await.unwrap();
+        assert!(!result.success);
+        assert!(result.error.unwrap().contains("recursive-delete-root"));
+        assert_eq!(result.data["rule"], "recursive-delete-root");
+    }
+
+    #[tokio::test]
+    async fn test_bash_tool_pins_cwd_and_caps_output() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        let tool = BashTool::new().with_output_limit(100);
+        let context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("test".to_string())
+        };
+
+        let result = tool.execute(serde_json::json!({ "command": "pwd; printf 'x%.0s' $(seq 1000)", "cwd": "sub" }), &context).await.unwrap();
+        assert!(result.success);
+        let stdout = result.data["stdout"].as_str().unwrap();
+        assert!(stdout.starts_with(&temp_dir.path().canonicalize().unwrap().join("sub").to_string_lossy().to_string()));
+        assert!(stdout.contains("bytes truncated"));
+        assert!(stdout.ends_with(&"x".repeat(50)));
+        assert!(result.data["truncated_bytes"].as_u64().unwrap() > 900);
+
+        let escaped = tool.execute(serde_json::json!({ "command": "ls", "cwd": "sub/../.." }), &context).await.unwrap();
+        assert!(escaped.error.unwrap().contains("Path traversal"));
+
+        let timed_out = tool.execute(serde_json::json!({ "command": "echo started; sleep 5", "timeout": 1 }), &context).await.unwrap();
+        assert!(!timed_out.success);
+        assert!(timed_out.error.unwrap().contains("timed out after 1 seconds"));
+        assert_eq!(timed_out.data["timed_out"], true);
+        assert_eq!(timed_out.data["stdout"], "started\n");
+    }
+
+    #[tokio::test]
+    async fn test_task_tool_runs_sub_agent_with_allowlist() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "ls", serde_json::json!({"path": "."})),
+            MockReply::text("The directory contains notes.txt"),
+        ])
+        .await;
+        let temp_dir = TempDir::new().unwrap();
+        tokio::fs::write(temp_dir.path().join("notes.txt"), "x").await.unwrap();
+        let context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("parent".to_string())
+        };
+
+        let parent = ToolRegistry::new();
+        register_builtin_tools(&parent).await.unwrap();
+        let client = Arc::new(crate::network::ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let tool = TaskTool::new(client, crate::config::ClaudeConfig::default(), Arc::new(parent.subset(None).await.unwrap()));
+
+        let result = tool
+            .execute(serde_json::json!({"description": "list files", "prompt": "What is in this directory?", "allowed_tools": ["ls"]}), &context)
+            .await
+            .unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.data["result"], "The directory contains notes.txt");
+        assert_eq!(result.data["turns"], 2);
+        assert_eq!(result.data["budget_exhausted"], false);
+
+        let requests = server.requests();
+        let tools: Vec<&str> = requests[0].body["tools"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+        assert_eq!(tools, ["ls"]);
+        assert!(requests[0].body["messages"][0]["content"][0]["text"].as_str().unwrap().starts_with("What is in this directory?"));
+        assert!(requests[1].body["messages"][2]["content"][0]["content"].as_str().unwrap().contains("notes.txt"));
+
+        let unknown = tool
+            .execute(serde_json::json!({"description": "x", "prompt": "x", "allowed_tools": ["nope"]}), &context)
+            .await
+            .unwrap();
+        assert!(unknown.error.unwrap().contains("Unknown tool 'nope'"));
+    }
+
+    #[tokio::test]
+    async fn test_task_sub_agent_respects_parent_permission_rules() {
+        use crate::test_support::{MockAnthropicServer, MockReply};
+        use crate::tools::permission::RuleDecider;
+
+        let server = MockAnthropicServer::start([
+            MockReply::tool_use("toolu_1", "write", serde_json::json!({"path": "pwned.txt", "content": "x"})),
+            MockReply::text("Done"),
+        ])
+        .await;
+        let temp_dir = TempDir::new().unwrap();
+        let context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("parent".to_string())
+        };
+
+        // 与 CLI 相同的顺序：子 Agent 的注册表在设置权限决策之前创建
+        let parent = ToolRegistry::new();
+        register_builtin_tools(&parent).await.unwrap();
+        let client = Arc::new(crate::network::ClaudeApiClient::new("test-key".to_string(), Some(server.base_url().to_string())).unwrap());
+        let tool = TaskTool::new(client, crate::config::ClaudeConfig::default(), Arc::new(parent.subset(None).await.unwrap()));
+        let decider = RuleDecider::from_config(&crate::config::PermissionConfig::default()).with_rules(&[], &["write".to_string()]);
+        parent.set_permission_decider(Arc::new(decider)).await;
+
+        let result = tool
+            .execute(serde_json::json!({"description": "write a file", "prompt": "Create pwned.txt"}), &context)
+            .await
+            .unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert!(!temp_dir.path().join("pwned.txt").exists(), "the denied write must not run in the sub-agent");
+        let tool_result = server.requests()[1].body["messages"][2]["content"][0].clone();
+        assert_eq!(tool_result["is_error"], true);
+        assert!(tool_result["content"].as_str().unwrap().contains("denied by permission rules"), "{}", tool_result);
+    }
+
+    #[cfg(feature = "image-processing")]
+    #[tokio::test]
+    async fn test_image_diff_tool() {
+        let temp_dir = TempDir::new().unwrap();
+        let before = image::RgbaImage::from_pixel(8, 8, image::Rgba([255, 255, 255, 255]));
+        let mut after = before.clone();
+        after.put_pixel(3, 3, image::Rgba([0, 0, 0, 255]));
+        before.save(temp_dir.path().join("before.png")).unwrap();
+        after.save(temp_dir.path().join("after.png")).unwrap();
+
+        let context = ToolContext {
+            working_directory: temp_dir.path().to_string_lossy().to_string(),
+            ..ToolContext::new("test".to_string())
+        };
+        let result = ImageDiffTool
+            .execute(serde_json::json!({ "before": "before.png", "after": "after.png" }), &context)
+            .await
+            .unwrap();
+
+        assert!(result.success);
+        assert_eq!(result.data["differing_pixels"], 1);
+        assert_eq!(result.data["difference_percent"], 1.563);
+        assert!(temp_dir.path().join("after.diff.png").exists());
+
+        let definition = Tool::definition(&ImageDiffTool);
+        assert!(definition.requires_confirmation);
+        assert_eq!(definition.security_level, SecurityLevel::Medium);
+        let escaped = ImageDiffTool
+            .execute(serde_json::json!({ "before": "before.png", "after": "after.png", "output": "../diff.png" }), &context)
+            .await
+            .unwrap();
+        assert!(escaped.error.unwrap().contains("Path traversal"));
+    }
 }
@@ -0,0 +1,136 @@
+//! 工具输出续读
+//!
+//! 超出 Token 预算的工具输出只把开头交给模型，完整输出保存在数据目录中以会话 id 命名的目录里。
+//! 截断说明附带续读游标 `<输出 id>:<字节偏移>`，模型用 `tool_output` 工具按游标取回后续部分，
+//! 每次同样受预算限制并给出下一个游标
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+use crate::tokens::{estimate_tokens, prefix_within};
+
+/// 从游标位置起的一段输出
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputPage {
+    pub content: String,
+    /// 还有剩余内容时指向下一段
+    pub next_cursor: Option<String>,
+    /// 剩余内容的估算 Token 数
+    pub remaining_tokens: u32,
+}
+
+impl OutputPage {
+    /// 交给模型的文本：内容加上续读说明
+    pub fn to_model_content(&self) -> String {
+        match &self.next_cursor {
+            Some(cursor) => format!(
+                "{}\n\n[Output truncated: about {} more tokens. Call tool_output with cursor \"{}\" to read the next part.]",
+                self.content, self.remaining_tokens, cursor
+            ),
+            None => self.content.clone(),
+        }
+    }
+}
+
+/// 一个会话被截断的完整输出
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSpool {
+    dir: PathBuf,
+}
+
+impl OutputSpool {
+    /// 所有会话输出所在的目录
+    pub fn root() -> PathBuf {
+        dirs::data_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("tool-output")
+    }
+
+    pub fn for_session(session_id: &str) -> Self {
+        Self::in_dir(Self::root(), session_id)
+    }
+
+    pub fn in_dir(root: impl AsRef<Path>, session_id: &str) -> Self {
+        let name: String = session_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        Self { dir: root.as_ref().join(name) }
+    }
+
+    /// 输出在预算内时原样返回；否则保存完整输出，返回开头部分和续读说明。`max_tokens` 为 0 时不截断
+    pub fn truncate(&self, text: &str, max_tokens: u32) -> Result<String> {
+        if max_tokens == 0 || estimate_tokens(text) <= max_tokens {
+            return Ok(text.to_string());
+        }
+        let id = uuid::Uuid::new_v4().simple().to_string()[..12].to_string();
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.dir.join(format!("{}.txt", id)), text)?;
+        Ok(page(&id, text, 0, max_tokens).to_model_content())
+    }
+
+    /// 读取游标处的下一段
+    pub fn read(&self, cursor: &str, max_tokens: u32) -> Result<OutputPage> {
+        let invalid = || ClaudeError::validation_error("cursor", format!("Invalid continuation cursor '{}'", cursor));
+        let (id, offset) = cursor.split_once(':').ok_or_else(invalid)?;
+        let offset: usize = offset.parse().map_err(|_| invalid())?;
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid());
+        }
+        let text = std::fs::read_to_string(self.dir.join(format!("{}.txt", id)))
+            .map_err(|_| ClaudeError::validation_error("cursor", format!("No saved output for cursor '{}'", cursor)))?;
+        if !text.is_char_boundary(offset) {
+            return Err(invalid());
+        }
+        Ok(page(id, &text, offset, max_tokens.max(1)))
+    }
+}
+
+/// 从 `offset` 起不超过预算的一段，能在后半段找到换行时在换行处断开
+fn page(id: &str, text: &str, offset: usize, max_tokens: u32) -> OutputPage {
+    let rest = &text[offset..];
+    let mut len = prefix_within(rest, max_tokens);
+    if len < rest.len() {
+        if let Some(newline) = rest[..len].rfind('\n').filter(|newline| *newline >= len / 2) {
+            len = newline + 1;
+        }
+        // 预算小于一个字符时至少前进一个字符
+        if len == 0 {
+            len = rest.chars().next().map_or(0, char::len_utf8);
+        }
+    }
+    let remaining = &rest[len..];
+    OutputPage {
+        content: rest[..len].to_string(),
+        next_cursor: (!remaining.is_empty()).then(|| format!("{}:{}", id, offset + len)),
+        remaining_tokens: estimate_tokens(remaining),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_and_read_continuation() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = OutputSpool::in_dir(dir.path(), "session/1");
+        assert_eq!(spool.truncate("short", 10).unwrap(), "short");
+
+        let text: String = (0..100).map(|i| format!("line {:03}\n", i)).collect();
+        let head = spool.truncate(&text, 10).unwrap();
+        assert!(head.starts_with("line 000\nline 001\nline 002\nline 003\n\n\n[Output truncated"));
+        let cursor = head.split('"').nth(1).unwrap().to_string();
+
+        let mut collected = head[..head.find("\n\n[").unwrap()].to_string();
+        let mut next = Some(cursor);
+        while let Some(cursor) = next {
+            let page = spool.read(&cursor, 50).unwrap();
+            collected.push_str(&page.content);
+            next = page.next_cursor;
+        }
+        assert_eq!(collected, text);
+
+        assert!(spool.read("nope", 10).is_err());
+        assert!(spool.read("../x:0", 10).is_err());
+        assert!(spool.read("abc:0", 10).unwrap_err().to_string().contains("No saved output"));
+    }
+}
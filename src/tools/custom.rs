@@ -0,0 +1,299 @@
+//! 用户定义的 shell 工具
+//!
+//! 配置文件中的 `custom_tools` 声明名称、描述、参数 Schema 和命令模板，启动时注册为普通工具。
+//! 模型传入的参数经 shell 转义后填入模板，再和 bash 工具一样在当前执行位置（本机或开发容器）运行。
+//! 占位符不能写在引号中（引号会抵消转义）；不在 `--` 之后的占位符拒绝以 `-` 开头的值，避免被当作选项
+
+use async_trait::async_trait;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use super::schema::parameters_from_schema;
+use super::{SecurityLevel, Tool, ToolContext, ToolDefinition, ToolParameter, ToolRegistry, ToolResult};
+use crate::config::CustomToolConfig;
+use crate::error::{ClaudeError, Result};
+use crate::process::devcontainer::SharedExecutionTarget;
+use crate::process::{ProcessConfig, ProcessManager};
+
+fn placeholder_regex() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").expect("valid placeholder regex"))
+}
+
+/// 用单引号转义，只含安全字符的值原样保留
+pub fn shell_quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+/// 参数值在命令中的形式：数组展开为多个单词，缺失的可选参数为空
+fn render_value(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => shell_quote(text),
+        Some(Value::Array(items)) => items.iter().map(|item| render_value(Some(item))).collect::<Vec<_>>().join(" "),
+        Some(Value::Object(_)) => shell_quote(&value.map(Value::to_string).unwrap_or_default()),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// 把参数填入命令模板
+pub fn render_command(template: &str, arguments: &Map<String, Value>) -> String {
+    placeholder_regex()
+        .replace_all(template, |captures: &regex::Captures| render_value(arguments.get(&captures[1])))
+        .into_owned()
+}
+
+/// 占位符前的模板是否停在未闭合的引号中
+fn in_quotes(prefix: &str) -> bool {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in prefix.chars() {
+        match (quote, c) {
+            (_, _) if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+    }
+    quote.is_some()
+}
+
+/// 模板中不在 `--` 之后出现的占位符，这些参数的值会被命令当作选项解析
+fn option_placeholders(template: &str) -> Vec<String> {
+    placeholder_regex()
+        .captures_iter(template)
+        .filter(|captures| {
+            let start = captures.get(0).map_or(0, |m| m.start());
+            !template[..start].split_whitespace().any(|token| token == "--")
+        })
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+fn starts_with_dash(value: &Value) -> bool {
+    match value {
+        Value::String(text) => text.starts_with('-'),
+        Value::Array(items) => items.iter().any(starts_with_dash),
+        _ => false,
+    }
+}
+
+/// 由配置生成的工具
+pub struct ShellTemplateTool {
+    spec: CustomToolConfig,
+    target: SharedExecutionTarget,
+    processes: Arc<ProcessManager>,
+    max_output_bytes: usize,
+}
+
+impl ShellTemplateTool {
+    /// 检查配置：名称只能包含字母、数字、`_` 和 `-`，模板中的占位符必须在 Schema 中声明且不在引号中
+    pub fn new(spec: CustomToolConfig, target: SharedExecutionTarget, max_output_bytes: usize) -> Result<Self> {
+        let field = format!("custom_tools.{}", spec.name);
+        if spec.name.is_empty() || !spec.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(ClaudeError::validation_error(field, "Tool names may only contain letters, digits, '_' and '-'"));
+        }
+        if spec.command.trim().is_empty() {
+            return Err(ClaudeError::validation_error(field, "command must not be empty"));
+        }
+        let properties = spec.input_schema.get("properties").and_then(Value::as_object);
+        for captures in placeholder_regex().captures_iter(&spec.command) {
+            if !properties.is_some_and(|properties| properties.contains_key(&captures[1])) {
+                return Err(ClaudeError::validation_error(
+                    field,
+                    format!("Placeholder '{{{{{}}}}}' is not declared in input_schema.properties", &captures[1]),
+                ));
+            }
+            let start = captures.get(0).map_or(0, |m| m.start());
+            if in_quotes(&spec.command[..start]) {
+                return Err(ClaudeError::validation_error(
+                    field,
+                    format!("Placeholder '{{{{{}}}}}' must not be quoted; values are shell-quoted automatically", &captures[1]),
+                ));
+            }
+        }
+        Ok(Self { spec, target, processes: Arc::new(ProcessManager::new()), max_output_bytes })
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        parameters_from_schema(&self.spec.input_schema)
+    }
+}
+
+#[async_trait]
+impl Tool for ShellTemplateTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.spec.name.clone(),
+            description: self.spec.description.clone(),
+            version: "1.0.0".to_string(),
+            parameters: self.parameters(),
+            category: "custom".to_string(),
+            requires_confirmation: self.spec.requires_confirmation,
+            security_level: SecurityLevel::Dangerous,
+        }
+    }
+
+    async fn execute(&self, parameters: Value, context: &ToolContext) -> Result<ToolResult> {
+        let mut arguments = parameters.as_object().cloned().unwrap_or_default();
+        for parameter in self.parameters() {
+            if let (false, Some(default)) = (arguments.contains_key(&parameter.name), parameter.default) {
+                arguments.insert(parameter.name, default);
+            }
+        }
+        for name in option_placeholders(&self.spec.command) {
+            if arguments.get(&name).is_some_and(starts_with_dash) {
+                return Ok(ToolResult::error(format!(
+                    "Argument '{}' must not start with '-'; put the placeholder after `--` in the command template to accept such values",
+                    name
+                )));
+            }
+        }
+        let command = render_command(&self.spec.command, &arguments);
+        let cwd = match &self.spec.working_dir {
+            Some(dir) => Path::new(&context.working_directory).join(dir),
+            None => Path::new(&context.working_directory).to_path_buf(),
+        };
+
+        let target = self.target.read().map(|target| target.clone()).unwrap_or_default();
+        let (program, args) = target.shell_command(&command, &cwd);
+        let config = ProcessConfig {
+            name: self.spec.name.clone(),
+            command: program,
+            args,
+            env: context.environment.clone(),
+            working_dir: Some(cwd.to_string_lossy().into_owned()),
+            timeout: Some(self.spec.timeout_secs),
+            capture_output: true,
+            auto_restart: false,
+            limits: Default::default(),
+        };
+        let output = match self.processes.run(config, self.max_output_bytes).await {
+            Ok(output) => output,
+            Err(e) => return Ok(ToolResult::error(format!("Failed to execute command: {}", e))),
+        };
+
+        let data = serde_json::json!({
+            "command": command,
+            "stdout": output.stdout,
+            "stderr": output.stderr,
+            "exit_code": output.exit_code.unwrap_or(-1),
+            "success": output.success,
+            "timed_out": output.timed_out,
+            "truncated_bytes": output.truncated_bytes,
+        });
+        if output.timed_out {
+            let mut result = ToolResult::error(format!("Command timed out after {} seconds", self.spec.timeout_secs));
+            result.data = data;
+            return Ok(result);
+        }
+        Ok(ToolResult::success(data))
+    }
+}
+
+/// 注册配置中的所有自定义工具
+pub async fn register_custom_tools(
+    registry: &ToolRegistry,
+    config: &crate::config::ClaudeConfig,
+    target: SharedExecutionTarget,
+) -> Result<()> {
+    for spec in &config.custom_tools {
+        let tool = ShellTemplateTool::new(spec.clone(), target.clone(), config.permissions.bash.max_output_bytes)?;
+        registry.register_tool(Arc::new(tool)).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shell_template_tool_quotes_arguments() {
+        let spec = CustomToolConfig {
+            name: "greet".to_string(),
+            description: "Print a greeting".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Who to greet" },
+                    "times": { "type": "integer", "default": 2, "minimum": 1 },
+                    "flags": { "type": "array", "items": { "type": "string" } }
+                },
+                "required": ["name"]
+            }),
+            command: "for i in $(seq {{times}}); do echo hello {{ name }} {{flags}}; done".to_string(),
+            working_dir: None,
+            timeout_secs: 10,
+            requires_confirmation: true,
+        };
+        let tool = ShellTemplateTool::new(spec.clone(), Default::default(), 1024).unwrap();
+        let definition = tool.definition();
+        assert_eq!(definition.input_schema()["required"], serde_json::json!(["name"]));
+        assert_eq!(definition.input_schema()["properties"]["times"]["minimum"], 1);
+
+        let registry = ToolRegistry::new();
+        registry.register_tool(Arc::new(tool)).await.unwrap();
+        let mut context = ToolContext::new("test".to_string());
+        context.permissions.push("execute".to_string());
+        let result = registry
+            .execute_tool("greet", serde_json::json!({ "name": "it's me; rm -rf /" }), &context)
+            .await
+            .unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.data["stdout"], "hello it's me; rm -rf /\nhello it's me; rm -rf /\n");
+        assert_eq!(render_command("x {{flags}}", serde_json::json!({ "flags": ["-a", "b c"] }).as_object().unwrap()), "x -a 'b c'");
+
+        let undeclared = CustomToolConfig { command: "deploy {{env}}".to_string(), ..spec };
+        assert!(ShellTemplateTool::new(undeclared, Default::default(), 1024).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shell_template_tool_rejects_quoted_placeholders_and_options() {
+        let spec = CustomToolConfig {
+            name: "search".to_string(),
+            description: "Search the sources".to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": { "pattern": { "type": "string" } },
+                "required": ["pattern"]
+            }),
+            command: "printf '%s\\n' {{pattern}}".to_string(),
+            working_dir: None,
+            timeout_secs: 10,
+            requires_confirmation: true,
+        };
+
+        // 引号中的占位符会让 `$(…)` 在双引号里展开
+        for command in ["grep \"{{pattern}}\" src", "grep '{{pattern}}' src", "echo \"it's {{pattern}}\""] {
+            let quoted = CustomToolConfig { command: command.to_string(), ..spec.clone() };
+            let error = ShellTemplateTool::new(quoted, Default::default(), 1024).err().unwrap();
+            assert!(error.to_string().contains("must not be quoted"), "{}", command);
+        }
+        let escaped = CustomToolConfig { command: "echo \\\" {{pattern}}".to_string(), ..spec.clone() };
+        assert!(ShellTemplateTool::new(escaped, Default::default(), 1024).is_ok());
+
+        let mut context = ToolContext::new("test".to_string());
+        context.permissions.push("execute".to_string());
+        let tool = ShellTemplateTool::new(spec.clone(), Default::default(), 1024).unwrap();
+        let option = tool.execute(serde_json::json!({ "pattern": "--output=/etc/passwd" }), &context).await.unwrap();
+        assert!(option.error.unwrap().contains("must not start with '-'"));
+        let substitution = tool.execute(serde_json::json!({ "pattern": "$(echo pwned)" }), &context).await.unwrap();
+        assert_eq!(substitution.data["stdout"], "$(echo pwned)\n");
+
+        let separated = CustomToolConfig { command: "printf '%s\\n' -- {{pattern}}".to_string(), ..spec };
+        let tool = ShellTemplateTool::new(separated, Default::default(), 1024).unwrap();
+        let result = tool.execute(serde_json::json!({ "pattern": "-v" }), &context).await.unwrap();
+        assert!(result.success, "{:?}", result.error);
+    }
+}
@@ -0,0 +1,159 @@
+//! 工具使用统计
+//!
+//! 注册表在内存中按工具累计调用次数、耗时、失败率和输出字节数；设置了 [`MetricsLog`] 时
+//! 每次调用还会追加一行记录到以会话 id 命名的 JSONL 文件，`claude stats tools` 读取它汇总一个会话
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+use super::ToolUsageStats;
+use crate::error::Result;
+
+/// 一次工具调用的记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    pub tool: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub duration_ms: u64,
+    pub success: bool,
+    /// 结果（或错误信息）序列化后的字节数
+    pub output_bytes: u64,
+}
+
+/// 按工具汇总，按总耗时从大到小排列
+pub fn summarize(records: &[ToolCallRecord]) -> Vec<(String, ToolUsageStats)> {
+    let mut totals: HashMap<&str, ToolUsageStats> = HashMap::new();
+    for record in records {
+        totals.entry(&record.tool).or_default().record(record.duration_ms, record.success, record.output_bytes);
+    }
+    let mut summary: Vec<(String, ToolUsageStats)> = totals.into_iter().map(|(tool, stats)| (tool.to_string(), stats)).collect();
+    sort_by_time(&mut summary);
+    summary
+}
+
+/// 按总耗时从大到小、其次按名称排列
+pub fn sort_by_time(summary: &mut [(String, ToolUsageStats)]) {
+    summary.sort_by(|a, b| b.1.total_execution_time_ms.cmp(&a.1.total_execution_time_ms).then_with(|| a.0.cmp(&b.0)));
+}
+
+/// 渲染为终端表格
+pub fn render_table(summary: &[(String, ToolUsageStats)]) -> String {
+    let mut lines = vec![format!(
+        "  {:<24} {:>6} {:>9} {:>10} {:>9} {:>9} {:>10}",
+        "tool", "calls", "failed", "total", "avg", "max", "output"
+    )];
+    for (tool, stats) in summary {
+        lines.push(format!(
+            "  {:<24} {:>6} {:>8.0}% {:>10} {:>9} {:>9} {:>10}",
+            tool,
+            stats.call_count,
+            stats.failure_rate() * 100.0,
+            format_ms(stats.total_execution_time_ms),
+            format_ms(stats.average_execution_time_ms as u64),
+            format_ms(stats.max_execution_time_ms),
+            format_bytes(stats.output_bytes),
+        ));
+    }
+    lines.join("\n")
+}
+
+fn format_ms(ms: u64) -> String {
+    if ms >= 1000 {
+        format!("{:.1}s", ms as f64 / 1000.0)
+    } else {
+        format!("{}ms", ms)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{}B", bytes),
+        1024..=1_048_575 => format!("{:.1}KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1}MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// 按会话保存的调用记录
+#[derive(Debug, Clone)]
+pub struct MetricsLog {
+    dir: PathBuf,
+}
+
+impl MetricsLog {
+    pub fn root() -> PathBuf {
+        dirs::data_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("tool-metrics")
+    }
+
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, session_id: &str) -> PathBuf {
+        let name: String = session_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.jsonl", name))
+    }
+
+    /// 追加一条记录
+    pub async fn append(&self, session_id: &str, record: &ToolCallRecord) -> Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(self.path(session_id)).await?;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// 读取会话的所有记录，跳过无法解析的行
+    pub fn load(&self, session_id: &str) -> Result<Vec<ToolCallRecord>> {
+        let content = std::fs::read_to_string(self.path(session_id))?;
+        Ok(content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect())
+    }
+
+    /// 最近有工具调用的会话
+    pub fn latest_session(&self) -> Option<String> {
+        std::fs::read_dir(&self.dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "jsonl"))
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .max_by_key(|(modified, _)| *modified)
+            .and_then(|(_, path)| Some(Path::new(path.file_stem()?).to_string_lossy().into_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_metrics_log_summarizes_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = MetricsLog::new(dir.path());
+        let record = |tool: &str, duration_ms, success, output_bytes| ToolCallRecord {
+            tool: tool.to_string(),
+            timestamp: chrono::Utc::now(),
+            duration_ms,
+            success,
+            output_bytes,
+        };
+        log.append("s1", &record("read", 10, true, 2048)).await.unwrap();
+        log.append("s1", &record("bash", 1500, false, 100)).await.unwrap();
+        log.append("s1", &record("bash", 500, true, 300)).await.unwrap();
+        assert_eq!(log.latest_session().as_deref(), Some("s1"));
+
+        let summary = summarize(&log.load("s1").unwrap());
+        assert_eq!(summary[0].0, "bash");
+        let bash = &summary[0].1;
+        assert_eq!((bash.call_count, bash.error_count, bash.total_execution_time_ms, bash.max_execution_time_ms), (2, 1, 2000, 1500));
+        assert_eq!(bash.failure_rate(), 0.5);
+        assert_eq!(bash.output_bytes, 400);
+        let table = render_table(&summary);
+        assert!(table.contains("bash") && table.contains("50%") && table.contains("2.0s") && table.contains("2.0KB"));
+    }
+}
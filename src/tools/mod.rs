@@ -3,8 +3,18 @@
 //! 基于原版 Claude Code 的工具调用机制，实现完整的工具注册、执行和管理系统
 
 pub mod builtin;
-
-use std::collections::HashMap;
+pub mod continuation;
+pub mod custom;
+pub mod metrics;
+pub mod output;
+pub mod permission;
+pub mod schema;
+pub mod selection;
+pub mod todo;
+
+use futures::FutureExt;
+use std::collections::{HashMap, HashSet};
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
@@ -13,6 +23,9 @@ use async_trait::async_trait;
 
 use crate::error::{ClaudeError, Result};
 
+pub use output::RenderHint;
+pub use schema::{ToolInput, TypedTool};
+
 /// 工具执行结果
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolResult {
@@ -26,6 +39,9 @@ pub struct ToolResult {
     pub execution_time_ms: u64,
     /// 输出日志
     pub logs: Vec<String>,
+    /// 渲染提示，供 UI 展示结构化数据
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render: Option<RenderHint>,
 }
 
 impl ToolResult {
@@ -37,6 +53,7 @@ impl ToolResult {
             error: None,
             execution_time_ms: 0,
             logs: Vec::new(),
+            render: None,
         }
     }
 
@@ -48,6 +65,7 @@ impl ToolResult {
             error: Some(error),
             execution_time_ms: 0,
             logs: Vec::new(),
+            render: None,
         }
     }
 
@@ -124,6 +142,12 @@ pub struct ToolContext {
     pub session_id: String,
     /// 调试模式
     pub debug_mode: bool,
+    /// 未单独配置的工具的执行超时，`None` 为不限制
+    pub default_timeout: Option<std::time::Duration>,
+    /// 按工具名称配置的执行超时
+    pub tool_timeouts: HashMap<String, std::time::Duration>,
+    /// 触发后正在执行的工具立即中止
+    pub cancellation: tokio_util::sync::CancellationToken,
 }
 
 impl ToolContext {
@@ -138,9 +162,28 @@ impl ToolContext {
             permissions: vec!["read".to_string(), "write".to_string()],
             session_id,
             debug_mode: false,
+            default_timeout: None,
+            tool_timeouts: HashMap::new(),
+            cancellation: tokio_util::sync::CancellationToken::new(),
         }
     }
 
+    /// 按配置设置工具执行超时
+    pub fn with_timeouts(mut self, config: &crate::config::ToolTimeoutConfig) -> Self {
+        self.default_timeout = config.default_secs.map(std::time::Duration::from_secs);
+        self.tool_timeouts = config
+            .per_tool
+            .iter()
+            .map(|(name, secs)| (name.clone(), std::time::Duration::from_secs(*secs)))
+            .collect();
+        self
+    }
+
+    /// 工具的执行超时
+    pub fn timeout_for(&self, tool_name: &str) -> Option<std::time::Duration> {
+        self.tool_timeouts.get(tool_name).copied().or(self.default_timeout)
+    }
+
     /// 检查权限
     pub fn has_permission(&self, permission: &str) -> bool {
         self.permissions.contains(&permission.to_string())
@@ -201,12 +244,28 @@ pub trait Tool: Send + Sync {
     }
 }
 
+/// 批量执行时同时运行的只读工具数上限
+pub const DEFAULT_MAX_PARALLEL_TOOLS: usize = 8;
+
 /// 工具注册表
 pub struct ToolRegistry {
     /// 已注册的工具
     tools: RwLock<HashMap<String, Arc<dyn Tool>>>,
     /// 工具使用统计
     usage_stats: Mutex<HashMap<String, ToolUsageStats>>,
+    /// 预序列化的 API 工具定义，注册新工具时失效
+    api_schemas: RwLock<Option<Arc<Vec<Value>>>>,
+    /// 记录工具崩溃等安全事件的审计日志，由 `subset` 创建的注册表共享同一份
+    audit_logger: Arc<RwLock<Option<Arc<crate::security::AuditLogger>>>>,
+    /// 批量执行时的并发上限
+    max_parallel: usize,
+    /// 每次执行前询问的权限决策，未设置时不检查；由 `subset` 创建的注册表共享同一份，
+    /// 子注册表创建之后才设置的决策同样生效
+    permission_decider: Arc<RwLock<Option<Arc<dyn permission::PermissionDecider>>>>,
+    /// 按会话持久化的调用记录，未设置时只在内存中统计；由 `subset` 创建的注册表共享同一份
+    metrics_log: Arc<RwLock<Option<Arc<metrics::MetricsLog>>>>,
+    /// 运行时停用的工具，由 `subset` 创建的注册表共享同一份
+    disabled: Arc<RwLock<HashSet<String>>>,
 }
 
 /// 工具使用统计
@@ -222,6 +281,35 @@ pub struct ToolUsageStats {
     pub total_execution_time_ms: u64,
     /// 平均执行时间
     pub average_execution_time_ms: f64,
+    /// 最长执行时间
+    pub max_execution_time_ms: u64,
+    /// 结果产生的总字节数
+    pub output_bytes: u64,
+}
+
+impl ToolUsageStats {
+    /// 累计一次调用
+    pub fn record(&mut self, execution_time_ms: u64, success: bool, output_bytes: u64) {
+        self.call_count += 1;
+        if success {
+            self.success_count += 1;
+        } else {
+            self.error_count += 1;
+        }
+        self.total_execution_time_ms += execution_time_ms;
+        self.average_execution_time_ms = self.total_execution_time_ms as f64 / self.call_count as f64;
+        self.max_execution_time_ms = self.max_execution_time_ms.max(execution_time_ms);
+        self.output_bytes += output_bytes;
+    }
+
+    /// 失败比例，没有调用时为 0
+    pub fn failure_rate(&self) -> f64 {
+        if self.call_count == 0 {
+            0.0
+        } else {
+            self.error_count as f64 / self.call_count as f64
+        }
+    }
 }
 
 impl ToolRegistry {
@@ -230,9 +318,36 @@ impl ToolRegistry {
         Self {
             tools: RwLock::new(HashMap::new()),
             usage_stats: Mutex::new(HashMap::new()),
+            api_schemas: RwLock::new(None),
+            audit_logger: Arc::new(RwLock::new(None)),
+            max_parallel: DEFAULT_MAX_PARALLEL_TOOLS,
+            permission_decider: Arc::new(RwLock::new(None)),
+            metrics_log: Arc::new(RwLock::new(None)),
+            disabled: Arc::new(RwLock::new(HashSet::new())),
         }
     }
 
+    /// 设置执行前的权限决策
+    pub async fn set_permission_decider(&self, decider: Arc<dyn permission::PermissionDecider>) {
+        *self.permission_decider.write().await = Some(decider);
+    }
+
+    /// 把每次调用追加到按会话保存的记录中，供 `claude stats tools` 汇总
+    pub async fn set_metrics_log(&self, log: Arc<metrics::MetricsLog>) {
+        *self.metrics_log.write().await = Some(log);
+    }
+
+    /// 设置批量执行时只读工具的并发上限
+    pub fn with_max_parallel(mut self, max_parallel: usize) -> Self {
+        self.max_parallel = max_parallel.max(1);
+        self
+    }
+
+    /// 设置审计日志，工具崩溃时记录 panic 信息
+    pub async fn set_audit_logger(&self, logger: Arc<crate::security::AuditLogger>) {
+        *self.audit_logger.write().await = Some(logger);
+    }
+
     /// 注册工具
     pub async fn register_tool(&self, tool: Arc<dyn Tool>) -> Result<()> {
         let definition = tool.definition();
@@ -246,6 +361,7 @@ impl ToolRegistry {
         
         let tool_name = definition.name.clone();
         tools.insert(tool_name.clone(), tool);
+        *self.api_schemas.write().await = None;
 
         // 初始化统计信息
         let mut stats = self.usage_stats.lock().await;
@@ -261,7 +377,74 @@ impl ToolRegistry {
         tools.get(name).cloned()
     }
 
-    /// 列出所有工具
+    /// 只包含指定工具的新注册表，`names` 为 `None` 时包含全部工具
+    ///
+    /// 新注册表与当前注册表共享启用状态、权限决策、审计日志和调用记录，之后停用的工具在两边都不可用，
+    /// 之后设置的权限决策在两边都生效
+    pub async fn subset(&self, names: Option<&[String]>) -> Result<ToolRegistry> {
+        let subset = ToolRegistry {
+            audit_logger: self.audit_logger.clone(),
+            max_parallel: self.max_parallel,
+            permission_decider: self.permission_decider.clone(),
+            metrics_log: self.metrics_log.clone(),
+            disabled: self.disabled.clone(),
+            ..ToolRegistry::new()
+        };
+        let tools: Vec<Arc<dyn Tool>> = match names {
+            Some(names) => {
+                let tools = self.tools.read().await;
+                names
+                    .iter()
+                    .map(|name| {
+                        tools
+                            .get(name)
+                            .cloned()
+                            .ok_or_else(|| ClaudeError::validation_error("tools", format!("Unknown tool '{}'", name)))
+                    })
+                    .collect::<Result<_>>()?
+            }
+            None => self.tools.read().await.values().cloned().collect(),
+        };
+        for tool in tools {
+            subset.register_tool(tool).await?;
+        }
+        Ok(subset)
+    }
+
+    /// 在运行时启用或停用工具；停用的工具不发送给模型，调用时返回错误
+    pub async fn set_enabled(&self, name: &str, enabled: bool) -> Result<()> {
+        if !self.tools.read().await.contains_key(name) {
+            return Err(ClaudeError::validation_error("tools", format!("Unknown tool '{}'", name)));
+        }
+        let mut disabled = self.disabled.write().await;
+        if enabled {
+            disabled.remove(name);
+        } else {
+            disabled.insert(name.to_string());
+        }
+        tracing::info!("Tool '{}' {}", name, if enabled { "enabled" } else { "disabled" });
+        Ok(())
+    }
+
+    /// 工具当前是否可用
+    pub async fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.read().await.contains(name)
+    }
+
+    /// 所有工具及其是否启用，按名称排列
+    pub async fn availability(&self) -> Vec<(ToolDefinition, bool)> {
+        let disabled = self.disabled.read().await.clone();
+        self.list_tools()
+            .await
+            .into_iter()
+            .map(|definition| {
+                let enabled = !disabled.contains(&definition.name);
+                (definition, enabled)
+            })
+            .collect()
+    }
+
+    /// 列出所有工具，包括停用的工具
     pub async fn list_tools(&self) -> Vec<ToolDefinition> {
         let tools = self.tools.read().await;
         let mut definitions = Vec::new();
@@ -274,6 +457,56 @@ impl ToolRegistry {
         definitions
     }
 
+    /// 获取 Claude API 格式的已启用工具定义（name、description、input_schema）
+    ///
+    /// 全部工具的定义会被缓存，首次调用可在启动预热阶段完成
+    pub async fn api_schemas(&self) -> Arc<Vec<Value>> {
+        let schemas = self.all_api_schemas().await;
+        let disabled = self.disabled.read().await;
+        if disabled.is_empty() {
+            return schemas;
+        }
+        Arc::new(
+            schemas
+                .iter()
+                .filter(|schema| !schema["name"].as_str().is_some_and(|name| disabled.contains(name)))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    async fn all_api_schemas(&self) -> Arc<Vec<Value>> {
+        if let Some(schemas) = self.api_schemas.read().await.as_ref() {
+            return schemas.clone();
+        }
+
+        let schemas = Arc::new(
+            self.list_tools()
+                .await
+                .iter()
+                .map(|definition| {
+                    serde_json::json!({
+                        "name": definition.name,
+                        "description": definition.description,
+                        "input_schema": definition.input_schema(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        );
+        *self.api_schemas.write().await = Some(schemas.clone());
+        schemas
+    }
+
+    /// 按选择策略挑出本次要发送的工具定义
+    pub async fn select_api_schemas(
+        &self,
+        prompt: &str,
+        config: &crate::config::ToolSelectionConfig,
+        force: &[String],
+    ) -> selection::ToolSelection {
+        selection::select(&self.api_schemas().await, prompt, config, force)
+    }
+
     /// 执行工具
     pub async fn execute_tool(
         &self,
@@ -284,6 +517,9 @@ impl ToolRegistry {
         let tool = self.get_tool(name).await.ok_or_else(|| {
             ClaudeError::General(format!("Tool '{}' not found", name))
         })?;
+        if !self.is_enabled(name).await {
+            return Err(ClaudeError::General(format!("Tool '{}' is disabled for this session", name)));
+        }
 
         // 验证参数
         tool.validate_parameters(&parameters)?;
@@ -291,17 +527,53 @@ impl ToolRegistry {
         // 检查安全性
         tool.check_security(context)?;
 
+        // 询问权限决策，拒绝原因返回给模型
+        if let Some(decider) = self.permission_decider.read().await.clone() {
+            let definition = tool.definition();
+            let request = permission::PermissionRequest { tool: &definition, input: &parameters, context };
+            if let permission::PermissionDecision::Deny(reason) = decider.decide(&request).await {
+                tracing::info!("Permission denied for tool '{}': {}", name, reason);
+                return Err(ClaudeError::permission_error(reason));
+            }
+        }
+
         // 记录开始时间
         let start_time = std::time::Instant::now();
 
-        // 执行工具
-        let result = tool.execute(parameters, context).await;
+        // 执行工具，工具内部 panic 转换为失败结果而不是终止整个进程；超时或被取消时丢弃执行中的 future
+        let execution = AssertUnwindSafe(tool.execute(parameters, context)).catch_unwind();
+        let deadline = async {
+            match context.timeout_for(name) {
+                Some(limit) => tokio::time::sleep(limit).await,
+                None => std::future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            outcome = execution => match outcome {
+                Ok(result) => result,
+                Err(payload) => {
+                    let message = panic_message(payload.as_ref());
+                    tracing::error!("Tool '{}' panicked: {}", name, message);
+                    self.audit_panic(name, &message, context).await;
+                    Err(ClaudeError::tool_failure(name, format!("tool panicked: {}", message)))
+                }
+            },
+            _ = deadline => {
+                let limit = context.timeout_for(name).unwrap_or_default();
+                tracing::warn!("Tool '{}' timed out after {:?}", name, limit);
+                Err(ClaudeError::tool_failure(name, format!("timed out after {}s", limit.as_secs_f64())))
+            }
+            _ = context.cancellation.cancelled() => {
+                tracing::info!("Tool '{}' was cancelled", name);
+                Err(ClaudeError::tool_failure(name, "cancelled"))
+            }
+        };
 
         // 计算执行时间
         let execution_time = start_time.elapsed().as_millis() as u64;
 
         // 更新统计信息
-        self.update_stats(name, &result, execution_time).await;
+        self.update_stats(name, &result, execution_time, &context.session_id).await;
 
         // 添加执行时间到结果
         match result {
@@ -310,33 +582,93 @@ impl ToolRegistry {
                 Ok(tool_result)
             }
             Err(e) => {
-                let error_result = ToolResult::error(e.to_string())
+                let failure = match e {
+                    ClaudeError::ToolFailure { .. } => e,
+                    e => ClaudeError::tool_failure(name, e.to_string()),
+                };
+                let error_result = ToolResult::error(failure.to_string())
                     .with_execution_time(execution_time);
                 Ok(error_result)
             }
         }
     }
 
+    /// 依次执行一组 `(工具名称, 参数)`，结果按调用顺序返回
+    ///
+    /// 相邻的只读工具并发执行，并发数受 `max_parallel` 限制；其他工具会修改状态，
+    /// 必须等前面的调用完成后单独执行
+    pub async fn execute_batch(&self, calls: Vec<(String, Value)>, context: &ToolContext) -> Vec<Result<ToolResult>> {
+        let semaphore = tokio::sync::Semaphore::new(self.max_parallel);
+        let mut results = Vec::with_capacity(calls.len());
+        let mut calls = calls.into_iter().peekable();
+        while let Some((name, parameters)) = calls.next() {
+            if !crate::agent::plan::is_read_only(&name) {
+                results.push(self.execute_tool(&name, parameters, context).await);
+                continue;
+            }
+            let mut run = vec![(name, parameters)];
+            while let Some(call) = calls.next_if(|(name, _)| crate::agent::plan::is_read_only(name)) {
+                run.push(call);
+            }
+            let semaphore = &semaphore;
+            let executions = run.into_iter().map(|(name, parameters)| async move {
+                let _permit = semaphore.acquire().await;
+                self.execute_tool(&name, parameters, context).await
+            });
+            results.extend(futures::future::join_all(executions).await);
+        }
+        results
+    }
+
+    /// 在审计日志中记录工具崩溃
+    async fn audit_panic(&self, name: &str, message: &str, context: &ToolContext) {
+        use crate::security::{AuditEventType, AuditLogEntry, AuditResult};
+
+        let Some(logger) = self.audit_logger.read().await.clone() else {
+            return;
+        };
+        let entry = AuditLogEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: chrono::Utc::now().timestamp() as u64,
+            user_id: None,
+            session_id: Some(context.session_id.clone()),
+            event_type: AuditEventType::SecurityEvent,
+            resource: Some(name.to_string()),
+            action: "tool_panic".to_string(),
+            result: AuditResult::Error,
+            ip_address: None,
+            user_agent: None,
+            details: HashMap::from([("panic".to_string(), message.to_string())]),
+        };
+        if let Err(e) = logger.log_event(entry).await {
+            tracing::warn!("Failed to record tool panic in audit log: {}", e);
+        }
+    }
+
     /// 更新统计信息
-    async fn update_stats(&self, tool_name: &str, result: &Result<ToolResult>, execution_time: u64) {
-        let mut stats = self.usage_stats.lock().await;
-        if let Some(tool_stats) = stats.get_mut(tool_name) {
-            tool_stats.call_count += 1;
-            tool_stats.total_execution_time_ms += execution_time;
-            tool_stats.average_execution_time_ms = 
-                tool_stats.total_execution_time_ms as f64 / tool_stats.call_count as f64;
-
-            match result {
-                Ok(tool_result) => {
-                    if tool_result.success {
-                        tool_stats.success_count += 1;
-                    } else {
-                        tool_stats.error_count += 1;
-                    }
-                }
-                Err(_) => {
-                    tool_stats.error_count += 1;
-                }
+    async fn update_stats(&self, tool_name: &str, result: &Result<ToolResult>, execution_time: u64, session_id: &str) {
+        // 输出字节数按结果数据和错误信息的序列化长度估算，即大致进入上下文的量
+        let (success, output_bytes) = match result {
+            Ok(tool_result) => {
+                let data = serde_json::to_vec(&tool_result.data).map(|bytes| bytes.len()).unwrap_or_default();
+                (tool_result.success, (data + tool_result.error.as_ref().map_or(0, String::len)) as u64)
+            }
+            Err(e) => (false, e.to_string().len() as u64),
+        };
+        if let Some(tool_stats) = self.usage_stats.lock().await.get_mut(tool_name) {
+            tool_stats.record(execution_time, success, output_bytes);
+        }
+
+        if let Some(log) = self.metrics_log.read().await.clone() {
+            let record = metrics::ToolCallRecord {
+                tool: tool_name.to_string(),
+                timestamp: chrono::Utc::now(),
+                duration_ms: execution_time,
+                success,
+                output_bytes,
+            };
+            if let Err(e) = log.append(session_id, &record).await {
+                tracing::warn!("Failed to record tool metrics: {}", e);
             }
         }
     }
@@ -352,6 +684,18 @@ impl ToolRegistry {
         let stats = self.usage_stats.lock().await;
         stats.clone()
     }
+
+    /// 调用过的工具的统计，按总耗时从大到小排列
+    pub async fn usage_report(&self) -> Vec<(String, ToolUsageStats)> {
+        let mut report: Vec<(String, ToolUsageStats)> = self
+            .get_all_stats()
+            .await
+            .into_iter()
+            .filter(|(_, stats)| stats.call_count > 0)
+            .collect();
+        metrics::sort_by_time(&mut report);
+        report
+    }
 }
 
 impl Default for ToolRegistry {
@@ -360,6 +704,15 @@ impl Default for ToolRegistry {
     }
 }
 
+/// 提取 panic 负载中的消息
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -423,6 +776,184 @@ mod tests {
         assert_eq!(result.data["output"], "Processed: test");
     }
 
+    #[tokio::test]
+    async fn test_disabled_tools_are_hidden_and_rejected() {
+        let registry = ToolRegistry::new();
+        registry.register_tool(Arc::new(TestTool)).await.unwrap();
+        registry.register_tool(Arc::new(builtin::LsTool)).await.unwrap();
+        let subset = registry.subset(None).await.unwrap();
+        let context = ToolContext::new("test-session".to_string());
+
+        registry.set_enabled("test_tool", false).await.unwrap();
+        assert!(registry.set_enabled("missing", false).await.is_err());
+        let names: Vec<_> = registry.api_schemas().await.iter().map(|s| s["name"].clone()).collect();
+        assert_eq!(names, ["ls"]);
+        let error = registry.execute_tool("test_tool", serde_json::json!({"input": "x"}), &context).await.unwrap_err();
+        assert!(error.to_string().contains("disabled"));
+        // 子注册表共享启用状态
+        assert!(!subset.is_enabled("test_tool").await);
+        assert_eq!(subset.api_schemas().await.len(), 1);
+        let availability: Vec<_> = registry.availability().await.into_iter().map(|(d, enabled)| (d.name, enabled)).collect();
+        assert_eq!(availability, [("ls".to_string(), true), ("test_tool".to_string(), false)]);
+
+        registry.set_enabled("test_tool", true).await.unwrap();
+        assert!(registry.execute_tool("test_tool", serde_json::json!({"input": "x"}), &context).await.unwrap().success);
+        assert_eq!(subset.api_schemas().await.len(), 2);
+    }
+
+    /// 执行时 panic 的测试工具
+    struct PanickingTool;
+
+    #[async_trait]
+    impl Tool for PanickingTool {
+        fn definition(&self) -> ToolDefinition {
+            ToolDefinition {
+                name: "panicking".to_string(),
+                description: "Always panics".to_string(),
+                version: "1.0.0".to_string(),
+                parameters: Vec::new(),
+                category: "test".to_string(),
+                requires_confirmation: false,
+                security_level: SecurityLevel::Safe,
+            }
+        }
+
+        async fn execute(&self, _parameters: Value, _context: &ToolContext) -> Result<ToolResult> {
+            let items: Vec<u32> = Vec::new();
+            Ok(ToolResult::success(serde_json::json!({ "first": items[3] })))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panicking_tool_is_isolated() {
+        use crate::security::{AuditConfig, AuditLevel, AuditLogger};
+
+        let registry = ToolRegistry::new();
+        registry.register_tool(Arc::new(PanickingTool)).await.unwrap();
+        registry.register_tool(Arc::new(TestTool)).await.unwrap();
+        let audit = Arc::new(AuditLogger::new(AuditConfig {
+            enabled: true,
+            log_level: AuditLevel::Standard,
+            retention_days: 1,
+            rotation_size_mb: 1,
+            remote_server: None,
+        }));
+        registry.set_audit_logger(audit.clone()).await;
+
+        let context = ToolContext::new("test-session".to_string());
+        let result = registry.execute_tool("panicking", serde_json::json!({}), &context).await.unwrap();
+        assert!(!result.success);
+        let error = result.error.unwrap();
+        assert!(error.starts_with("Tool 'panicking' failed: tool panicked"), "{}", error);
+        assert!(error.contains("index out of bounds"));
+        assert_eq!(registry.get_tool_stats("panicking").await.unwrap().error_count, 1);
+
+        // 注册表在崩溃后仍可继续使用
+        let result = registry.execute_tool("test_tool", serde_json::json!({"input": "ok"}), &context).await.unwrap();
+        assert!(result.success);
+
+        let logs = audit.get_logs(0, u64::MAX).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].action, "tool_panic");
+        assert_eq!(logs[0].resource.as_deref(), Some("panicking"));
+        assert!(logs[0].details["panic"].contains("index out of bounds"));
+    }
+
+    /// 记录最大并发数的只读测试工具
+    struct SlowReadTool {
+        running: std::sync::atomic::AtomicUsize,
+        peak: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Tool for SlowReadTool {
+        fn definition(&self) -> ToolDefinition {
+            ToolDefinition::builder("read").description("Slow read").build()
+        }
+
+        async fn execute(&self, parameters: Value, _context: &ToolContext) -> Result<ToolResult> {
+            use std::sync::atomic::Ordering;
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(parameters["delay"].as_u64().unwrap_or(0))).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(ToolResult::success(parameters))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch_runs_read_only_tools_concurrently_in_order() {
+        use std::sync::atomic::Ordering;
+
+        let reader = Arc::new(SlowReadTool { running: Default::default(), peak: Default::default() });
+        let registry = ToolRegistry::new().with_max_parallel(2);
+        registry.register_tool(reader.clone()).await.unwrap();
+        registry.register_tool(Arc::new(TestTool)).await.unwrap();
+        let context = ToolContext::new("test-session".to_string());
+
+        let calls = vec![
+            ("read".to_string(), serde_json::json!({ "id": 0, "delay": 60 })),
+            ("read".to_string(), serde_json::json!({ "id": 1, "delay": 10 })),
+            ("read".to_string(), serde_json::json!({ "id": 2, "delay": 30 })),
+            ("test_tool".to_string(), serde_json::json!({ "input": "barrier" })),
+            ("read".to_string(), serde_json::json!({ "id": 4, "delay": 0 })),
+        ];
+        let results = registry.execute_batch(calls, &context).await;
+        let ids: Vec<Value> = results.iter().map(|result| result.as_ref().unwrap().data["id"].clone()).collect();
+        assert_eq!(ids, vec![0.into(), 1.into(), 2.into(), Value::Null, 4.into()]);
+        assert_eq!(results[3].as_ref().unwrap().data["output"], "Processed: barrier");
+        assert_eq!(reader.peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tool_timeout_and_cancellation() {
+        let reader = Arc::new(SlowReadTool { running: Default::default(), peak: Default::default() });
+        let registry = ToolRegistry::new();
+        registry.register_tool(reader.clone()).await.unwrap();
+        let mut context = ToolContext::new("test-session".to_string()).with_timeouts(&crate::config::ToolTimeoutConfig {
+            default_secs: None,
+            per_tool: HashMap::from([("read".to_string(), 1)]),
+        });
+        assert_eq!(context.timeout_for("bash"), None);
+
+        let result = registry.execute_tool("read", serde_json::json!({ "delay": 5000 }), &context).await.unwrap();
+        assert_eq!(result.error.as_deref(), Some("Tool 'read' failed: timed out after 1s"));
+
+        context.tool_timeouts.clear();
+        let canceller = crate::steering::SteeringController::new().tool_canceller();
+        context.cancellation = canceller.token();
+        let cancel = tokio::spawn({
+            let canceller = canceller.clone();
+            async move {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                canceller.cancel();
+            }
+        });
+        let result = registry.execute_tool("read", serde_json::json!({ "delay": 5000 }), &context).await.unwrap();
+        assert_eq!(result.error.as_deref(), Some("Tool 'read' failed: cancelled"));
+        cancel.await.unwrap();
+        assert_eq!(registry.get_tool_stats("read").await.unwrap().error_count, 2);
+
+        // 取消后换上的新令牌不受影响
+        context.cancellation = canceller.token();
+        let result = registry.execute_tool("read", serde_json::json!({ "delay": 0 }), &context).await.unwrap();
+        assert!(result.success);
+    }
+
+    #[tokio::test]
+    async fn test_api_schema_cache() {
+        let registry = ToolRegistry::new();
+        registry.register_tool(Arc::new(TestTool)).await.unwrap();
+
+        let schemas = registry.api_schemas().await;
+        assert_eq!(schemas[0]["name"], "test_tool");
+        assert_eq!(schemas[0]["input_schema"]["required"], serde_json::json!(["input"]));
+        assert!(Arc::ptr_eq(&schemas, &registry.api_schemas().await));
+
+        registry.register_tool(Arc::new(builtin::LsTool)).await.unwrap();
+        assert_eq!(registry.api_schemas().await.len(), 2);
+    }
+
     #[tokio::test]
     async fn test_tool_validation() {
         let tool = TestTool;
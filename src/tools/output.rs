@@ -0,0 +1,211 @@
+//! 工具输出渲染
+//!
+//! `ToolResult` 携带结构化数据和渲染提示：UI 层按提示渲染为表格、差异或文件列表，
+//! 模型始终收到规范的文本/JSON 形式
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::ToolResult;
+
+/// 工具结果的渲染提示
+///
+/// `source` 指向 `data` 中的字段，为空时使用 `data` 本身
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RenderHint {
+    /// 纯文本
+    Text {
+        #[serde(default)]
+        source: Option<String>,
+    },
+    /// 表格，行为对象数组，按列名取值
+    Table {
+        columns: Vec<String>,
+        #[serde(default)]
+        source: Option<String>,
+    },
+    /// 统一差异格式
+    Diff {
+        #[serde(default)]
+        source: Option<String>,
+    },
+    /// 文件列表，元素为路径字符串或带 `path`/`is_dir` 的对象
+    FileList {
+        #[serde(default)]
+        source: Option<String>,
+    },
+    /// 代码块
+    Code {
+        language: String,
+        #[serde(default)]
+        source: Option<String>,
+    },
+}
+
+impl RenderHint {
+    fn source(&self) -> Option<&str> {
+        match self {
+            Self::Text { source }
+            | Self::Table { source, .. }
+            | Self::Diff { source }
+            | Self::FileList { source }
+            | Self::Code { source, .. } => source.as_deref(),
+        }
+    }
+}
+
+impl ToolResult {
+    /// 设置渲染提示
+    pub fn with_render(mut self, render: RenderHint) -> Self {
+        self.render = Some(render);
+        self
+    }
+
+    /// 发送给模型的规范内容：字符串数据原样返回，其余为 JSON
+    pub fn to_model_content(&self) -> String {
+        if !self.success {
+            return format!("Error: {}", self.error.as_deref().unwrap_or("unknown error"));
+        }
+        match &self.data {
+            Value::String(text) => text.clone(),
+            Value::Null => String::new(),
+            data => serde_json::to_string(data).unwrap_or_default(),
+        }
+    }
+
+    /// 按渲染提示生成终端展示文本，没有提示时退回规范内容
+    pub fn render_plain(&self) -> String {
+        let hint = match (&self.render, self.success) {
+            (Some(hint), true) => hint,
+            _ => return self.to_model_content(),
+        };
+
+        let value = match hint.source() {
+            Some(field) => self.data.get(field).unwrap_or(&Value::Null),
+            None => &self.data,
+        };
+
+        match hint {
+            RenderHint::Text { .. } | RenderHint::Diff { .. } => value_text(value),
+            RenderHint::Code { language, .. } => format!("```{}\n{}\n```", language, value_text(value)),
+            RenderHint::Table { columns, .. } => render_table(columns, value),
+            RenderHint::FileList { .. } => render_file_list(value),
+        }
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn render_table(columns: &[String], rows: &Value) -> String {
+    let rows: Vec<Vec<String>> = rows
+        .as_array()
+        .map(|rows| {
+            rows.iter()
+                .map(|row| columns.iter().map(|c| value_text(row.get(c).unwrap_or(&Value::Null))).collect())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(column.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let format_row = |cells: &[String]| -> String {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join(" │ ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![
+        format_row(columns),
+        widths.iter().map(|w| "─".repeat(*w)).collect::<Vec<_>>().join("─┼─"),
+    ];
+    lines.extend(rows.iter().map(|row| format_row(row)));
+    lines.join("\n")
+}
+
+fn render_file_list(entries: &Value) -> String {
+    entries
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|entry| {
+                    let path = entry
+                        .get("path")
+                        .and_then(Value::as_str)
+                        .or_else(|| entry.as_str())
+                        .unwrap_or_default();
+                    if entry.get("is_dir").and_then(Value::as_bool).unwrap_or(false) {
+                        format!("📁 {}/", path.trim_end_matches('/'))
+                    } else {
+                        format!("📄 {}", path)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_table_rendering() {
+        let result = ToolResult::success(json!({
+            "rows": [{ "name": "a.rs", "lines": 10 }, { "name": "main.rs", "lines": 200 }]
+        }))
+        .with_render(RenderHint::Table {
+            columns: vec!["name".to_string(), "lines".to_string()],
+            source: Some("rows".to_string()),
+        });
+
+        let rendered = result.render_plain();
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[0], "name    │ lines");
+        assert_eq!(lines[2], "a.rs    │ 10");
+        assert_eq!(lines[3], "main.rs │ 200");
+
+        // 模型收到的仍是规范 JSON
+        assert!(result.to_model_content().starts_with("{\"rows\""));
+    }
+
+    #[test]
+    fn test_file_list_and_errors() {
+        let result = ToolResult::success(json!([{ "path": "src", "is_dir": true }, "Cargo.toml"]))
+            .with_render(RenderHint::FileList { source: None });
+        assert_eq!(result.render_plain(), "📁 src/\n📄 Cargo.toml");
+
+        let error = ToolResult::error("boom".to_string()).with_render(RenderHint::Diff { source: None });
+        assert_eq!(error.render_plain(), "Error: boom");
+    }
+
+    #[test]
+    fn test_hint_serialization() {
+        let hint: RenderHint = serde_json::from_value(json!({ "kind": "code", "language": "rust" })).unwrap();
+        assert_eq!(hint, RenderHint::Code { language: "rust".to_string(), source: None });
+    }
+}
@@ -0,0 +1,419 @@
+//! 声明式工具定义
+//!
+//! 由类型化的输入结构体生成 `ToolDefinition` 参数与 JSON Schema，
+//! 并统一完成参数反序列化与校验，减少内置工具和插件工具的样板代码。
+//!
+//! ```ignore
+//! tool_input! {
+//!     pub struct GreetInput {
+//!         /// Name of the person to greet
+//!         name: String,
+//!         /// Number of times to repeat the greeting
+//!         times: u32 = 1,
+//!         /// Optional suffix
+//!         suffix: Option<String>,
+//!     }
+//! }
+//!
+//! let definition = ToolDefinition::builder("greet")
+//!     .description("Greet someone")
+//!     .input::<GreetInput>()
+//!     .build();
+//! ```
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::{SecurityLevel, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::error::{ClaudeError, Result};
+
+/// 可作为工具参数的类型，对应 JSON Schema 中的类型名
+pub trait SchemaType {
+    /// JSON Schema 类型名
+    fn param_type() -> &'static str;
+
+    /// 是否为可选参数
+    fn is_optional() -> bool {
+        false
+    }
+}
+
+macro_rules! impl_schema_type {
+    ($name:literal => $($ty:ty),+) => {
+        $(impl SchemaType for $ty {
+            fn param_type() -> &'static str {
+                $name
+            }
+        })+
+    };
+}
+
+impl_schema_type!("string" => String, PathBuf);
+impl_schema_type!("boolean" => bool);
+impl_schema_type!("integer" => i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+impl_schema_type!("number" => f32, f64);
+impl_schema_type!("object" => Value, Map<String, Value>);
+
+impl<T: SchemaType> SchemaType for Option<T> {
+    fn param_type() -> &'static str {
+        T::param_type()
+    }
+
+    fn is_optional() -> bool {
+        true
+    }
+}
+
+impl<T> SchemaType for Vec<T> {
+    fn param_type() -> &'static str {
+        "array"
+    }
+}
+
+impl<V> SchemaType for HashMap<String, V> {
+    fn param_type() -> &'static str {
+        "object"
+    }
+}
+
+/// 类型化的工具输入
+///
+/// 通常由 [`tool_input!`](crate::tool_input) 宏生成
+pub trait ToolInput: Sized + Send {
+    /// 参数定义
+    fn parameters() -> Vec<ToolParameter>;
+
+    /// 从 JSON 参数解析输入，失败时返回校验错误
+    fn parse(parameters: Value) -> Result<Self>;
+}
+
+/// 由字段类型生成参数定义
+pub fn parameter<T: SchemaType>(name: &str, description: &str, default: Option<Value>) -> ToolParameter {
+    ToolParameter {
+        name: name.to_string(),
+        param_type: T::param_type().to_string(),
+        description: description.trim().to_string(),
+        required: default.is_none() && !T::is_optional(),
+        default,
+        constraints: None,
+    }
+}
+
+/// 解析单个字段：缺失时使用默认值，可选字段解析为 `None`
+pub fn parse_field<T: DeserializeOwned>(
+    parameters: &mut Map<String, Value>,
+    name: &str,
+    default: Option<Value>,
+) -> Result<T> {
+    match parameters.remove(name).filter(|v| !v.is_null()).or(default) {
+        Some(value) => serde_json::from_value(value).map_err(|e| ClaudeError::validation_error(name, e.to_string())),
+        None => serde_json::from_value(Value::Null)
+            .map_err(|_| ClaudeError::validation_error(name, "Required parameter missing")),
+    }
+}
+
+/// 将参数转换为对象，供生成的解析代码使用
+pub fn into_object(parameters: Value) -> Result<Map<String, Value>> {
+    match parameters {
+        Value::Object(map) => Ok(map),
+        Value::Null => Ok(Map::new()),
+        other => Err(ClaudeError::validation_error(
+            "parameters",
+            format!("Expected an object, got {}", other),
+        )),
+    }
+}
+
+/// 声明类型化的工具输入结构体，并生成 [`ToolInput`] 实现
+///
+/// 字段的文档注释作为参数描述，`= 默认值` 声明的字段为可选参数，
+/// `Option<T>` 字段缺失时为 `None`
+#[macro_export]
+macro_rules! tool_input {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                $field_vis:vis $field:ident : $ty:ty $(= $default:expr)?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone)]
+        $vis struct $name {
+            $(
+                $(#[doc = $doc])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::tools::schema::ToolInput for $name {
+            fn parameters() -> Vec<$crate::tools::ToolParameter> {
+                vec![$(
+                    $crate::tools::schema::parameter::<$ty>(
+                        stringify!($field),
+                        concat!("" $(, $doc)*),
+                        None::<serde_json::Value> $(.or(Some(serde_json::json!($default))))?,
+                    )
+                ),*]
+            }
+
+            fn parse(parameters: serde_json::Value) -> $crate::error::Result<Self> {
+                #[allow(unused_mut, unused_variables)]
+                let mut parameters = $crate::tools::schema::into_object(parameters)?;
+                Ok(Self {
+                    $(
+                        $field: $crate::tools::schema::parse_field::<$ty>(
+                            &mut parameters,
+                            stringify!($field),
+                            None::<serde_json::Value> $(.or(Some(serde_json::json!($default))))?,
+                        )?,
+                    )*
+                })
+            }
+        }
+    };
+}
+
+/// 由对象类型的 JSON Schema 生成参数列表，例如配置中的自定义工具或 MCP 服务器提供的工具
+///
+/// `type`、`description` 和 `default` 之外的关键字（enum、items 等）原样作为约束，
+/// 不是单个字符串的 `type`（例如 `["string", "null"]`）也保留在约束中
+pub fn parameters_from_schema(schema: &Value) -> Vec<ToolParameter> {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    properties
+        .iter()
+        .map(|(name, schema)| {
+            let mut constraints = schema.as_object().cloned().unwrap_or_default();
+            let param_type = match constraints.get("type") {
+                Some(Value::String(_)) => constraints.remove("type").and_then(|t| t.as_str().map(str::to_string)),
+                _ => None,
+            };
+            let description = constraints.remove("description").and_then(|d| d.as_str().map(str::to_string));
+            let default = constraints.remove("default");
+            ToolParameter {
+                name: name.clone(),
+                param_type: param_type.unwrap_or_else(|| "string".to_string()),
+                description: description.unwrap_or_default(),
+                required: required.contains(&name.as_str()),
+                default,
+                constraints: (!constraints.is_empty()).then_some(Value::Object(constraints)),
+            }
+        })
+        .collect()
+}
+
+/// 工具定义构建器
+#[derive(Debug, Clone)]
+pub struct ToolDefinitionBuilder {
+    definition: ToolDefinition,
+}
+
+impl ToolDefinition {
+    /// 创建工具定义构建器
+    pub fn builder(name: impl Into<String>) -> ToolDefinitionBuilder {
+        ToolDefinitionBuilder {
+            definition: ToolDefinition {
+                name: name.into(),
+                description: String::new(),
+                version: "1.0.0".to_string(),
+                parameters: Vec::new(),
+                category: "general".to_string(),
+                requires_confirmation: false,
+                security_level: SecurityLevel::Safe,
+            },
+        }
+    }
+
+    /// 生成 Claude API 使用的输入 JSON Schema
+    pub fn input_schema(&self) -> Value {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+
+        for param in &self.parameters {
+            let mut property = json!({
+                "type": param.param_type,
+                "description": param.description,
+            });
+            if let Some(default) = &param.default {
+                property["default"] = default.clone();
+            }
+            if let Some(Value::Object(constraints)) = &param.constraints {
+                for (key, value) in constraints {
+                    property[key] = value.clone();
+                }
+            }
+            properties.insert(param.name.clone(), property);
+
+            if param.required {
+                required.push(Value::String(param.name.clone()));
+            }
+        }
+
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+}
+
+impl ToolDefinitionBuilder {
+    /// 设置描述
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.definition.description = description.into();
+        self
+    }
+
+    /// 设置版本
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.definition.version = version.into();
+        self
+    }
+
+    /// 设置类别
+    pub fn category(mut self, category: impl Into<String>) -> Self {
+        self.definition.category = category.into();
+        self
+    }
+
+    /// 设置是否需要确认
+    pub fn requires_confirmation(mut self, requires_confirmation: bool) -> Self {
+        self.definition.requires_confirmation = requires_confirmation;
+        self
+    }
+
+    /// 设置安全级别
+    pub fn security_level(mut self, security_level: SecurityLevel) -> Self {
+        self.definition.security_level = security_level;
+        self
+    }
+
+    /// 添加单个参数
+    pub fn parameter(mut self, parameter: ToolParameter) -> Self {
+        self.definition.parameters.push(parameter);
+        self
+    }
+
+    /// 添加 JSON Schema 中声明的全部参数
+    pub fn input_schema(mut self, schema: &Value) -> Self {
+        self.definition.parameters.extend(parameters_from_schema(schema));
+        self
+    }
+
+    /// 添加类型化输入的全部参数
+    pub fn input<I: ToolInput>(mut self) -> Self {
+        self.definition.parameters.extend(I::parameters());
+        self
+    }
+
+    /// 构建工具定义
+    pub fn build(self) -> ToolDefinition {
+        self.definition
+    }
+}
+
+/// 使用类型化输入的工具
+///
+/// 实现该 trait 的类型自动实现 [`Tool`]，参数在执行前解析为 `Self::Input`
+#[async_trait]
+pub trait TypedTool: Send + Sync {
+    /// 输入类型
+    type Input: ToolInput;
+
+    /// 获取工具定义
+    fn definition(&self) -> ToolDefinition;
+
+    /// 使用解析后的输入执行工具
+    async fn run(&self, input: Self::Input, context: &ToolContext) -> Result<ToolResult>;
+}
+
+#[async_trait]
+impl<T: TypedTool> Tool for T {
+    fn definition(&self) -> ToolDefinition {
+        TypedTool::definition(self)
+    }
+
+    async fn execute(&self, parameters: Value, context: &ToolContext) -> Result<ToolResult> {
+        let input = T::Input::parse(parameters)?;
+        self.run(input, context).await
+    }
+
+    fn validate_parameters(&self, parameters: &Value) -> Result<()> {
+        T::Input::parse(parameters.clone()).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::tool_input! {
+        struct GreetInput {
+            /// Name of the person
+            /// to greet
+            name: String,
+            /// Number of repetitions
+            times: u32 = 1,
+            /// Optional suffix
+            suffix: Option<String>,
+        }
+    }
+
+    struct GreetTool;
+
+    #[async_trait]
+    impl TypedTool for GreetTool {
+        type Input = GreetInput;
+
+        fn definition(&self) -> ToolDefinition {
+            ToolDefinition::builder("greet")
+                .description("Greet someone")
+                .input::<GreetInput>()
+                .build()
+        }
+
+        async fn run(&self, input: GreetInput, _context: &ToolContext) -> Result<ToolResult> {
+            let greeting = format!("Hello, {}{}", input.name, input.suffix.unwrap_or_default());
+            Ok(ToolResult::success(json!(vec![greeting; input.times as usize])))
+        }
+    }
+
+    #[test]
+    fn test_generated_schema() {
+        let definition = Tool::definition(&GreetTool);
+        assert_eq!(definition.parameters.len(), 3);
+        assert_eq!(definition.parameters[0].description, "Name of the person to greet");
+
+        let schema = definition.input_schema();
+        assert_eq!(schema["properties"]["name"]["type"], "string");
+        assert_eq!(schema["properties"]["times"]["type"], "integer");
+        assert_eq!(schema["properties"]["times"]["default"], 1);
+        assert_eq!(schema["required"], json!(["name"]));
+    }
+
+    #[tokio::test]
+    async fn test_typed_execution() {
+        let context = ToolContext::new("test".to_string());
+
+        let result = GreetTool.execute(json!({ "name": "Rust", "times": 2, "suffix": "!" }), &context).await.unwrap();
+        assert_eq!(result.data, json!(["Hello, Rust!", "Hello, Rust!"]));
+
+        let result = GreetTool.execute(json!({ "name": "Rust" }), &context).await.unwrap();
+        assert_eq!(result.data, json!(["Hello, Rust"]));
+
+        assert!(GreetTool.validate_parameters(&json!({ "times": 2 })).is_err());
+        assert!(GreetTool.validate_parameters(&json!({ "name": "Rust", "times": "x" })).is_err());
+    }
+}
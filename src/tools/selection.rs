@@ -0,0 +1,220 @@
+//! 工具定义的选择
+//!
+//! 接入 MCP 服务器和插件后，工具定义可能占去大量上下文。这里估算每个工具定义的 Token 开销，
+//! 超出预算时只保留核心工具、强制包含的工具，再按与提示的相关程度补充其余工具
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+
+use crate::config::ToolSelectionConfig;
+use crate::tokens::estimate_tokens;
+
+/// 提示中直接提到工具名时的相关度
+const NAME_MENTION_SCORE: usize = 100;
+
+/// 不参与相关度计算的常见词
+const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "with", "this", "that", "from", "into", "use", "using", "file", "files", "tool", "tools",
+    "please", "can", "you", "all", "are", "not", "was", "its", "their", "then", "when", "what", "how",
+];
+
+/// 单个工具定义的开销
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolCost {
+    pub name: String,
+    pub tokens: u32,
+}
+
+/// 工具被选中或略去的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Inclusion {
+    /// 未超出预算，全部发送
+    All,
+    Core,
+    /// 配置或命令行强制包含
+    Forced,
+    /// 与提示相关
+    Relevant,
+    /// 与提示无关或超出预算
+    Omitted,
+}
+
+/// 选择结果
+#[derive(Debug, Clone, Default)]
+pub struct ToolSelection {
+    /// 要发送的 API 工具定义
+    pub schemas: Vec<Value>,
+    /// 每个工具的开销和去留，按开销从大到小排列
+    pub decisions: Vec<(ToolCost, Inclusion)>,
+    /// 所有工具定义的 Token 数
+    pub total_tokens: u32,
+    /// 选中工具定义的 Token 数
+    pub selected_tokens: u32,
+}
+
+impl ToolSelection {
+    pub fn omitted(&self) -> impl Iterator<Item = &str> {
+        self.decisions
+            .iter()
+            .filter(|(_, inclusion)| *inclusion == Inclusion::Omitted)
+            .map(|(cost, _)| cost.name.as_str())
+    }
+}
+
+fn schema_name(schema: &Value) -> &str {
+    schema["name"].as_str().unwrap_or_default()
+}
+
+/// 工具定义序列化后的 Token 数
+pub fn schema_tokens(schema: &Value) -> u32 {
+    estimate_tokens(&schema.to_string())
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() >= 3)
+        .map(str::to_lowercase)
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// 提示的分词结果
+struct PromptTerms {
+    text: String,
+    /// 保留下划线的词，用于匹配工具名
+    identifiers: HashSet<String>,
+    words: HashSet<String>,
+}
+
+impl PromptTerms {
+    fn new(prompt: &str) -> Self {
+        let text = prompt.to_lowercase();
+        let identifiers = text
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect();
+        let words = words(&text);
+        Self { text, identifiers, words }
+    }
+}
+
+/// 工具与提示的相关度：提到工具名（MCP 工具也可只写 `__` 之后的部分）时最高，
+/// 否则为提示与工具名、描述共有的词数
+fn relevance(schema: &Value, prompt: &PromptTerms) -> usize {
+    let name = schema_name(schema).to_lowercase();
+    let short_name = name.rsplit("__").next().unwrap_or(&name);
+    let mentioned = prompt.identifiers.contains(&name)
+        || prompt.identifiers.contains(short_name)
+        || (short_name.contains('_') && prompt.text.contains(&short_name.replace('_', " ")));
+    if mentioned {
+        return NAME_MENTION_SCORE;
+    }
+    let description = schema["description"].as_str().unwrap_or_default();
+    words(&format!("{} {}", name.replace('_', " "), description)).intersection(&prompt.words).count()
+}
+
+/// 按策略选择要发送的工具定义；`force` 为本次额外强制包含的工具
+pub fn select(schemas: &[Value], prompt: &str, config: &ToolSelectionConfig, force: &[String]) -> ToolSelection {
+    let costs: Vec<ToolCost> = schemas
+        .iter()
+        .map(|schema| ToolCost { name: schema_name(schema).to_string(), tokens: schema_tokens(schema) })
+        .collect();
+    let total_tokens = costs.iter().map(|cost| cost.tokens).sum();
+
+    let mut inclusions = vec![Inclusion::Omitted; schemas.len()];
+    if !config.enabled || total_tokens <= config.budget_tokens {
+        inclusions.fill(Inclusion::All);
+    } else {
+        let mut used = 0;
+        for (index, cost) in costs.iter().enumerate() {
+            if config.core.contains(&cost.name) {
+                inclusions[index] = Inclusion::Core;
+            } else if config.always_include.contains(&cost.name) || force.contains(&cost.name) {
+                inclusions[index] = Inclusion::Forced;
+            } else {
+                continue;
+            }
+            used += cost.tokens;
+        }
+
+        let prompt = PromptTerms::new(prompt);
+        let mut candidates: Vec<(usize, usize)> = schemas
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| inclusions[*index] == Inclusion::Omitted)
+            .map(|(index, schema)| (index, relevance(schema, &prompt)))
+            .filter(|(_, score)| *score > 0)
+            .collect();
+        candidates.sort_by(|a, b| b.1.cmp(&a.1).then(costs[a.0].tokens.cmp(&costs[b.0].tokens)));
+        for (index, _) in candidates {
+            if used + costs[index].tokens <= config.budget_tokens {
+                inclusions[index] = Inclusion::Relevant;
+                used += costs[index].tokens;
+            }
+        }
+    }
+
+    let selected: Vec<usize> = (0..schemas.len()).filter(|i| inclusions[*i] != Inclusion::Omitted).collect();
+    let mut decisions: Vec<(ToolCost, Inclusion)> = costs.into_iter().zip(inclusions).collect();
+    let selected_tokens = selected.iter().map(|i| decisions[*i].0.tokens).sum();
+    decisions.sort_by(|a, b| b.0.tokens.cmp(&a.0.tokens).then_with(|| a.0.name.cmp(&b.0.name)));
+    ToolSelection {
+        schemas: selected.into_iter().map(|i| schemas[i].clone()).collect(),
+        decisions,
+        total_tokens,
+        selected_tokens,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(name: &str, description: &str) -> Value {
+        serde_json::json!({
+            "name": name,
+            "description": description,
+            "input_schema": {"type": "object", "properties": {"query": {"type": "string", "description": "x".repeat(200)}}},
+        })
+    }
+
+    fn schemas() -> Vec<Value> {
+        vec![
+            schema("read", "Read a file"),
+            schema("github__create_issue", "Create an issue in a GitHub repository"),
+            schema("postgres__query", "Run a read-only SQL query against the database"),
+            schema("slack__post_message", "Post a message to a Slack channel"),
+        ]
+    }
+
+    #[test]
+    fn test_everything_fits_within_budget() {
+        let config = ToolSelectionConfig { budget_tokens: 100_000, ..Default::default() };
+        let selection = select(&schemas(), "hello", &config, &[]);
+        assert_eq!(selection.schemas.len(), 4);
+        assert_eq!(selection.selected_tokens, selection.total_tokens);
+        assert!(selection.decisions.iter().all(|(_, inclusion)| *inclusion == Inclusion::All));
+    }
+
+    #[test]
+    fn test_core_forced_and_relevant_tools() {
+        let per_tool = schema_tokens(&schemas()[1]);
+        let config = ToolSelectionConfig {
+            budget_tokens: per_tool * 3 + 20,
+            core: vec!["read".to_string()],
+            ..Default::default()
+        };
+
+        let selection = select(&schemas(), "Which SQL query finds slow rows in the database?", &config, &[]);
+        let names: Vec<&str> = selection.schemas.iter().map(schema_name).collect();
+        assert_eq!(names, vec!["read", "postgres__query"]);
+        assert_eq!(selection.omitted().collect::<Vec<_>>().len(), 2);
+
+        let forced = select(&schemas(), "use create_issue to file it", &config, &["slack__post_message".to_string()]);
+        let names: Vec<&str> = forced.schemas.iter().map(schema_name).collect();
+        assert_eq!(names, vec!["read", "github__create_issue", "slack__post_message"]);
+    }
+}
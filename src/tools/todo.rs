@@ -0,0 +1,157 @@
+//! 会话任务清单
+//!
+//! todo_write 工具维护的结构化任务列表。每次写入都替换整个列表，并保存在数据目录中以会话
+//! id 命名的文件里，恢复会话后仍可继续；终端界面定期读取该文件，在状态栏显示进度和当前任务
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::{ClaudeError, Result};
+
+/// 任务状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoStatus {
+    Pending,
+    InProgress,
+    Completed,
+}
+
+/// 一项任务
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TodoItem {
+    /// 任务描述，祈使句形式
+    pub content: String,
+    pub status: TodoStatus,
+    /// 进行中时显示的描述，例如 "Running tests"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_form: Option<String>,
+}
+
+impl TodoItem {
+    /// 状态栏中显示的描述
+    pub fn label(&self) -> &str {
+        match (&self.status, &self.active_form) {
+            (TodoStatus::InProgress, Some(active)) if !active.trim().is_empty() => active,
+            _ => &self.content,
+        }
+    }
+}
+
+/// 检查列表：任务描述不能为空，同时最多一项进行中
+pub fn validate(items: &[TodoItem]) -> Result<()> {
+    if let Some(index) = items.iter().position(|item| item.content.trim().is_empty()) {
+        return Err(ClaudeError::validation_error("todos", format!("Item {} has empty content", index + 1)));
+    }
+    let in_progress = items.iter().filter(|item| item.status == TodoStatus::InProgress).count();
+    if in_progress > 1 {
+        return Err(ClaudeError::validation_error(
+            "todos",
+            format!("Only one task can be in_progress at a time, got {}", in_progress),
+        ));
+    }
+    Ok(())
+}
+
+/// 状态栏摘要，例如 `2/5 · Running tests`；列表为空时返回 `None`
+pub fn summary(items: &[TodoItem]) -> Option<String> {
+    if items.is_empty() {
+        return None;
+    }
+    let completed = items.iter().filter(|item| item.status == TodoStatus::Completed).count();
+    let mut text = format!("{}/{}", completed, items.len());
+    if let Some(current) = items.iter().find(|item| item.status == TodoStatus::InProgress) {
+        text.push_str(&format!(" · {}", current.label()));
+    }
+    Some(text)
+}
+
+/// 一个会话的任务清单文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct TodoStore {
+    path: PathBuf,
+}
+
+impl TodoStore {
+    /// 所有会话清单所在的目录
+    pub fn root() -> PathBuf {
+        dirs::data_dir().unwrap_or_else(std::env::temp_dir).join("claude-code").join("todos")
+    }
+
+    pub fn for_session(session_id: &str) -> Self {
+        Self::in_dir(Self::root(), session_id)
+    }
+
+    pub fn in_dir(root: impl AsRef<Path>, session_id: &str) -> Self {
+        let name: String = session_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        Self { path: root.as_ref().join(format!("{}.json", name)) }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 读取清单，文件不存在时为空
+    pub fn load(&self) -> Result<Vec<TodoItem>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 校验并替换整个清单
+    pub fn save(&self, items: &[TodoItem]) -> Result<()> {
+        validate(items)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // 先写临时文件再改名，读取方不会看到写了一半的内容
+        let temp = self.path.with_extension("json.tmp");
+        std::fs::write(&temp, serde_json::to_vec_pretty(items)?)?;
+        std::fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+
+    /// 文件的修改时间，用于判断是否需要重新读取
+    pub fn modified(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(content: &str, status: TodoStatus) -> TodoItem {
+        TodoItem { content: content.to_string(), status, active_form: None }
+    }
+
+    #[test]
+    fn test_todo_store_round_trip_and_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TodoStore::in_dir(dir.path(), "session/1");
+        assert!(store.path().ends_with("session_1.json"));
+        assert!(store.load().unwrap().is_empty());
+
+        let mut items = vec![
+            item("Write parser", TodoStatus::Completed),
+            item("Run tests", TodoStatus::InProgress),
+            item("Update docs", TodoStatus::Pending),
+        ];
+        items[1].active_form = Some("Running tests".to_string());
+        store.save(&items).unwrap();
+        assert_eq!(store.load().unwrap(), items);
+        assert_eq!(summary(&items).as_deref(), Some("1/3 · Running tests"));
+        assert_eq!(summary(&[]), None);
+
+        items[2].status = TodoStatus::InProgress;
+        assert!(store.save(&items).is_err());
+        assert!(store.save(&[item(" ", TodoStatus::Pending)]).is_err());
+        assert_eq!(store.load().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,219 @@
+//! 文件选择
+//!
+//! 在桌面环境中调用系统自带的文件对话框（Linux 上的 zenity/kdialog、macOS 的 osascript、
+//! Windows 的 PowerShell），没有图形环境或对话框工具不可用时，退回终端内的模糊查找
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{ClaudeError, Result};
+use crate::fs::scan::{ParallelScanner, ScanOptions};
+
+/// 模糊查找最多收集的文件数
+const MAX_CANDIDATES: usize = 50_000;
+
+/// 当前会话是否能显示图形对话框
+pub fn has_desktop() -> bool {
+    // 远程登录时对话框会出现在另一台机器上（或根本无法显示）
+    if std::env::var_os("SSH_CONNECTION").is_some() || std::env::var_os("SSH_TTY").is_some() {
+        return false;
+    }
+    if cfg!(any(target_os = "macos", target_os = "windows")) {
+        return true;
+    }
+    std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+/// 打开系统文件对话框（阻塞直到用户关闭）。取消时返回 `Ok(None)`，没有可用的对话框工具时返回错误
+pub fn pick_native(title: &str, dir: &Path) -> Result<Option<PathBuf>> {
+    let mut candidates: Vec<Command> = Vec::new();
+    if cfg!(target_os = "macos") {
+        let script = format!(
+            "POSIX path of (choose file with prompt \"{}\" default location POSIX file \"{}\")",
+            title.replace('"', "'"),
+            dir.display()
+        );
+        let mut command = Command::new("osascript");
+        command.args(["-e", &script]);
+        candidates.push(command);
+    } else if cfg!(target_os = "windows") {
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms; $d = New-Object System.Windows.Forms.OpenFileDialog; \
+             $d.Title = '{}'; $d.InitialDirectory = '{}'; if ($d.ShowDialog() -eq 'OK') {{ $d.FileName }} else {{ exit 1 }}",
+            title.replace('\'', "''"),
+            dir.display()
+        );
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-STA", "-Command", &script]);
+        candidates.push(command);
+    } else {
+        let mut zenity = Command::new("zenity");
+        zenity.arg("--file-selection").arg(format!("--title={}", title)).arg(format!("--filename={}/", dir.display()));
+        candidates.push(zenity);
+        let mut kdialog = Command::new("kdialog");
+        kdialog.arg("--title").arg(title).arg("--getopenfilename").arg(dir);
+        candidates.push(kdialog);
+    }
+
+    for mut command in candidates {
+        let output = match command.output() {
+            Ok(output) => output,
+            // 工具没有安装，试下一个
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if !output.status.success() {
+            return Ok(None);
+        }
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        return Ok((!path.is_empty()).then(|| PathBuf::from(path)));
+    }
+    Err(ClaudeError::General("No native file dialog is available".to_string()))
+}
+
+/// 终端内的模糊文件查找
+#[derive(Debug, Clone)]
+pub struct FuzzyFinder {
+    root: PathBuf,
+    /// 相对 `root` 的候选路径
+    candidates: Vec<String>,
+    query: String,
+    /// 匹配的候选下标，按分数从高到低
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl FuzzyFinder {
+    /// 收集 `root` 下的文件（跳过隐藏目录和构建产物）
+    pub fn new(root: &Path) -> Result<Self> {
+        let scanner = ParallelScanner::new(ScanOptions::default())?;
+        let candidates = scanner
+            .discover(root, &|_| true, None)?
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .filter_map(|path| Some(path.strip_prefix(root).ok()?.to_string_lossy().into_owned()))
+            .collect();
+        Ok(Self::with_candidates(root, candidates))
+    }
+
+    pub fn with_candidates(root: &Path, candidates: Vec<String>) -> Self {
+        let mut finder = Self { root: root.to_path_buf(), candidates, query: String::new(), matches: Vec::new(), selected: 0 };
+        finder.refresh();
+        finder
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.refresh();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.refresh();
+    }
+
+    pub fn move_selection(&mut self, delta: isize) {
+        if !self.matches.is_empty() {
+            self.selected = self.selected.saturating_add_signed(delta).min(self.matches.len() - 1);
+        }
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    /// 匹配的相对路径，按分数排序
+    pub fn matches(&self) -> impl Iterator<Item = &str> {
+        self.matches.iter().map(|&index| self.candidates[index].as_str())
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// 当前选中的文件（绝对路径）
+    pub fn selected(&self) -> Option<PathBuf> {
+        let index = *self.matches.get(self.selected)?;
+        Some(self.root.join(&self.candidates[index]))
+    }
+
+    fn refresh(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(index, candidate)| Some((fuzzy_score(&self.query, candidate)?, index)))
+            .collect();
+        // 分数相同时短路径优先
+        scored.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| self.candidates[a.1].len().cmp(&self.candidates[b.1].len()))
+        });
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.selected = 0;
+    }
+}
+
+/// `query` 的字符按顺序（不区分大小写）出现在 `candidate` 中时给出分数：
+/// 连续命中、命中单词开头和文件名部分加分，空查询匹配所有候选
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let file_name_start = candidate.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut chars = candidate.char_indices();
+
+    for q in query.chars().filter(|c| !c.is_whitespace()) {
+        let q = q.to_ascii_lowercase();
+        let (index, _) = chars.by_ref().find(|(_, c)| c.to_ascii_lowercase() == q)?;
+        score += 1;
+        if previous.is_some_and(|p| candidate[p..index].chars().count() == 1) {
+            score += 5;
+        }
+        let boundary = index == 0
+            || candidate[..index].ends_with(['/', '\\', '_', '-', '.', ' '])
+            || (candidate[index..].starts_with(|c: char| c.is_uppercase())
+                && candidate[..index].ends_with(|c: char| c.is_lowercase()));
+        if boundary {
+            score += 3;
+        }
+        if index >= file_name_start {
+            score += 2;
+        }
+        previous = Some(index);
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_finder_ranks_file_names() {
+        let candidates = ["src/main.rs", "src/ui/terminal_app.rs", "docs/terminal.md", "Cargo.toml"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        let mut finder = FuzzyFinder::with_candidates(Path::new("/repo"), candidates);
+        assert_eq!(finder.match_count(), 4);
+
+        for c in "term".chars() {
+            finder.push(c);
+        }
+        assert_eq!(finder.matches().collect::<Vec<_>>(), vec!["docs/terminal.md", "src/ui/terminal_app.rs"]);
+        finder.move_selection(5);
+        assert_eq!(finder.selected(), Some(PathBuf::from("/repo/src/ui/terminal_app.rs")));
+
+        finder.push('x');
+        assert_eq!(finder.selected(), None);
+        finder.pop();
+        assert_eq!(finder.selected_index(), 0);
+
+        assert!(fuzzy_score("main", "src/main.rs").unwrap() > fuzzy_score("main", "my/admin_index.rs").unwrap());
+        assert_eq!(fuzzy_score("zz", "src/main.rs"), None);
+    }
+}
@@ -0,0 +1,146 @@
+//! 交互模式的行编辑
+//!
+//! 在终端中逐键读取一行输入，支持光标移动、历史记录和 Tab 补全；标准输入不是终端时退回按行读取
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+use std::io::{self, IsTerminal, Write};
+use unicode_width::UnicodeWidthStr;
+
+use crate::git::refs::Completion;
+
+/// 带历史记录的行编辑器
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    history: Vec<String>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 读取一行（已去掉首尾空白）；输入结束（空行上按 Ctrl-D 或 Ctrl-C）时返回 `None`。
+    /// `complete` 接收光标之前的内容，返回要替换的起始位置和候选
+    pub fn read_line(&mut self, prompt: &str, complete: impl Fn(&str) -> Completion) -> io::Result<Option<String>> {
+        if !io::stdin().is_terminal() {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+            let mut line = String::new();
+            let read = io::stdin().read_line(&mut line)?;
+            return Ok((read > 0).then(|| line.trim().to_string()));
+        }
+
+        terminal::enable_raw_mode()?;
+        let result = self.edit(prompt, &complete);
+        terminal::disable_raw_mode()?;
+        println!();
+
+        if let Ok(Some(line)) = &result {
+            if !line.is_empty() && self.history.last() != Some(line) {
+                self.history.push(line.clone());
+            }
+        }
+        result
+    }
+
+    fn edit(&self, prompt: &str, complete: &dyn Fn(&str) -> Completion) -> io::Result<Option<String>> {
+        let mut out = io::stdout();
+        let mut line = String::new();
+        let mut pos = 0;
+        let mut history_index = self.history.len();
+        redraw(&mut out, prompt, &line, pos)?;
+
+        loop {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            let control = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Enter => return Ok(Some(line.trim().to_string())),
+                KeyCode::Char('c') | KeyCode::Char('d') if control && line.is_empty() => return Ok(None),
+                KeyCode::Char('c') if control => {
+                    line.clear();
+                    pos = 0;
+                }
+                KeyCode::Char('a') if control => pos = 0,
+                KeyCode::Char('e') if control => pos = line.len(),
+                KeyCode::Char('u') if control => {
+                    line.replace_range(..pos, "");
+                    pos = 0;
+                }
+                KeyCode::Char(c) if !control => {
+                    let at_end = pos == line.len();
+                    line.insert(pos, c);
+                    pos += c.len_utf8();
+                    // 在末尾输入时直接输出，长行由终端自然换行
+                    if at_end {
+                        queue!(out, Print(c))?;
+                        out.flush()?;
+                        continue;
+                    }
+                }
+                KeyCode::Home => pos = 0,
+                KeyCode::End => pos = line.len(),
+                KeyCode::Left => pos -= line[..pos].chars().next_back().map_or(0, char::len_utf8),
+                KeyCode::Right => pos += line[pos..].chars().next().map_or(0, char::len_utf8),
+                KeyCode::Backspace => {
+                    if let Some(c) = line[..pos].chars().next_back() {
+                        pos -= c.len_utf8();
+                        line.remove(pos);
+                    }
+                }
+                KeyCode::Delete if pos < line.len() => {
+                    line.remove(pos);
+                }
+                KeyCode::Up | KeyCode::Down => {
+                    history_index = match key.code {
+                        KeyCode::Up => history_index.saturating_sub(1),
+                        _ => (history_index + 1).min(self.history.len()),
+                    };
+                    line = self.history.get(history_index).cloned().unwrap_or_default();
+                    pos = line.len();
+                }
+                KeyCode::Tab => {
+                    let completion = complete(&line[..pos]);
+                    let replacement = match completion.candidates.as_slice() {
+                        [] => continue,
+                        [only] => format!("{} ", only),
+                        candidates => {
+                            let prefix = completion.common_prefix();
+                            if prefix.len() <= pos - completion.start {
+                                queue!(out, Print("\r\n"), Print(candidates.join("  ")), Print("\r\n"))?;
+                            }
+                            prefix
+                        }
+                    };
+                    line.replace_range(completion.start..pos, &replacement);
+                    pos = completion.start + replacement.len();
+                }
+                _ => continue,
+            }
+            redraw(&mut out, prompt, &line, pos)?;
+        }
+    }
+}
+
+fn redraw(out: &mut io::Stdout, prompt: &str, line: &str, pos: usize) -> io::Result<()> {
+    let column = (prompt.width() + line[..pos].width()).min(u16::MAX as usize) as u16;
+    queue!(
+        out,
+        cursor::MoveToColumn(0),
+        terminal::Clear(ClearType::CurrentLine),
+        Print(prompt),
+        Print(line),
+        cursor::MoveToColumn(column)
+    )?;
+    out.flush()
+}
@@ -0,0 +1,123 @@
+//! 键盘宏
+//!
+//! 把一串提交过的输入（消息或斜杠命令）录制为命名宏，之后一键重放，适合“运行测试、查看 diff、提交”
+//! 这类重复流程。宏按用户保存在数据目录中，所有会话共享
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ClaudeError, Result};
+
+/// 快捷键录制时使用的宏名
+pub const QUICK_MACRO: &str = "q";
+
+/// 一个录制好的宏
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    /// 按顺序重放的输入
+    pub steps: Vec<String>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// 用户的宏集合
+#[derive(Debug, Default)]
+pub struct MacroStore {
+    path: PathBuf,
+    macros: BTreeMap<String, Macro>,
+}
+
+impl MacroStore {
+    /// 默认存储位置
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("claude-code")
+            .join("macros.json")
+    }
+
+    /// 读取宏文件，不存在时为空集合
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let macros = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, macros })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Macro)> {
+        self.macros.iter()
+    }
+
+    /// 保存宏（同名覆盖）并写回文件
+    pub fn insert(&mut self, name: &str, steps: Vec<String>) -> Result<()> {
+        validate_name(name)?;
+        if steps.is_empty() {
+            return Err(ClaudeError::validation_error("macro", "Nothing was recorded"));
+        }
+        self.macros.insert(name.to_string(), Macro { steps, recorded_at: Utc::now() });
+        self.save()
+    }
+
+    /// 删除宏，返回是否存在
+    pub fn remove(&mut self, name: &str) -> Result<bool> {
+        let removed = self.macros.remove(name).is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.macros)?)?;
+        Ok(())
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err(ClaudeError::validation_error(
+            "macro",
+            format!("Invalid macro name '{}': use letters, digits, '-' or '_'", name),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_store_persists() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("macros.json");
+
+        let mut store = MacroStore::load(&path).unwrap();
+        assert!(store.insert("bad name", vec!["/status".to_string()]).is_err());
+        assert!(store.insert("ship", Vec::new()).is_err());
+        store
+            .insert("ship", vec!["/git status".to_string(), "commit this".to_string()])
+            .unwrap();
+
+        let mut reloaded = MacroStore::load(&path).unwrap();
+        assert_eq!(reloaded.get("ship").unwrap().steps, vec!["/git status", "commit this"]);
+        assert!(reloaded.remove("ship").unwrap());
+        assert!(!reloaded.remove("ship").unwrap());
+        assert_eq!(MacroStore::load(&path).unwrap().iter().count(), 0);
+    }
+}
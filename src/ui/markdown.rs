@@ -0,0 +1,495 @@
+//! 终端 Markdown 渲染
+//!
+//! 把模型回复中的 Markdown 表格对齐为带框线的终端表格，
+//! 并把简单的 LaTeX 数学公式（`$...$`、`$$...$$`、`\(...\)`、`\[...\]`）转换为 Unicode 文本。
+//! 代码块和行内代码原样保留，无法转换的命令保持原文
+
+use unicode_width::UnicodeWidthStr;
+
+/// 渲染表格和数学公式，其余内容原样输出
+pub fn render_markdown(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut output = Vec::with_capacity(lines.len());
+    let mut fence: Option<&str> = None;
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        let trimmed = line.trim_start();
+
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            output.push(line.to_string());
+            index += 1;
+            continue;
+        }
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            fence = Some(marker);
+            output.push(line.to_string());
+            index += 1;
+            continue;
+        }
+
+        // 表格：表头行之后紧跟分隔行
+        let separator = lines.get(index + 1).filter(|_| line.contains('|')).and_then(|next| parse_separator(next));
+        if let Some(alignments) = separator {
+            let header = split_row(line);
+            if header.len() == alignments.len() {
+                let mut rows = vec![header];
+                index += 2;
+                while index < lines.len() && lines[index].contains('|') && !lines[index].trim().is_empty() {
+                    rows.push(split_row(lines[index]));
+                    index += 1;
+                }
+                output.extend(render_table(&rows, &alignments));
+                continue;
+            }
+        }
+
+        // 独立成行的公式块
+        if let Some(close) = ["$$", "\\["].into_iter().find(|open| trimmed.starts_with(open)) {
+            let close = if close == "$$" { "$$" } else { "\\]" };
+            let body_start = &trimmed[2..];
+            let (body, consumed) = match body_start.find(close) {
+                Some(end) if body_start[end + 2..].trim().is_empty() => (body_start[..end].to_string(), 1),
+                Some(_) => (String::new(), 0),
+                None => match lines[index + 1..].iter().position(|l| l.trim_end().ends_with(close)) {
+                    Some(offset) => {
+                        let mut parts = vec![body_start.to_string()];
+                        parts.extend(lines[index + 1..index + offset + 1].iter().map(|l| l.to_string()));
+                        let last = lines[index + offset + 1].trim_end();
+                        parts.push(last[..last.len() - 2].to_string());
+                        (parts.join(" "), offset + 2)
+                    }
+                    None => (String::new(), 0),
+                },
+            };
+            if consumed > 0 {
+                output.push(format!("    {}", latex_to_unicode(body.trim())));
+                index += consumed;
+                continue;
+            }
+        }
+
+        output.push(render_inline_math(line));
+        index += 1;
+    }
+
+    let mut rendered = output.join("\n");
+    if text.ends_with('\n') {
+        rendered.push('\n');
+    }
+    rendered
+}
+
+/// 列对齐方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// 解析 `|---|:---:|---:|` 形式的分隔行
+fn parse_separator(line: &str) -> Option<Vec<Alignment>> {
+    if !line.contains('|') {
+        return None;
+    }
+    let cells = split_row(line);
+    if cells.is_empty() {
+        return None;
+    }
+    cells
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Alignment::Center,
+                (false, true) => Alignment::Right,
+                _ => Alignment::Left,
+            })
+        })
+        .collect()
+}
+
+/// 按未转义的 `|` 拆分表格行，去掉两侧的边框
+fn split_row(line: &str) -> Vec<String> {
+    let trimmed = line.trim();
+    let trimmed = trimmed.strip_prefix('|').unwrap_or(trimmed);
+    let trimmed = trimmed.strip_suffix('|').filter(|rest| !rest.ends_with('\\')).unwrap_or(trimmed);
+
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = trimmed.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            _ => cell.push(c),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
+
+/// 用框线字符绘制表格，单元格中的公式同样转换
+fn render_table(rows: &[Vec<String>], alignments: &[Alignment]) -> Vec<String> {
+    let columns = alignments.len();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| (0..columns).map(|i| row.get(i).map(|cell| render_inline_math(cell)).unwrap_or_default()).collect())
+        .collect();
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| rows.iter().map(|row| row[i].width()).max().unwrap_or(0).max(1))
+        .collect();
+
+    let border = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|w| "─".repeat(w + 2)).collect();
+        format!("{}{}{}", left, segments.join(middle), right)
+    };
+    let row_line = |row: &[String]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .zip(alignments)
+            .map(|((cell, width), alignment)| format!(" {} ", pad(cell, *width, *alignment)))
+            .collect();
+        format!("│{}│", cells.join("│"))
+    };
+
+    let mut lines = vec![border("┌", "┬", "┐"), row_line(&rows[0]), border("├", "┼", "┤")];
+    lines.extend(rows[1..].iter().map(|row| row_line(row)));
+    lines.push(border("└", "┴", "┘"));
+    lines
+}
+
+fn pad(cell: &str, width: usize, alignment: Alignment) -> String {
+    let gap = width.saturating_sub(cell.width());
+    let (left, right) = match alignment {
+        Alignment::Left => (0, gap),
+        Alignment::Right => (gap, 0),
+        Alignment::Center => (gap / 2, gap - gap / 2),
+    };
+    format!("{}{}{}", " ".repeat(left), cell, " ".repeat(right))
+}
+
+/// 转换一行中的行内公式，跳过行内代码；金额之类的 `$5` 不视为公式
+fn render_inline_math(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut output = String::with_capacity(line.len());
+    let mut index = 0;
+
+    while index < chars.len() {
+        let c = chars[index];
+        if c == '`' {
+            let end = chars[index + 1..].iter().position(|&ch| ch == '`').map(|p| index + 1 + p);
+            let end = end.unwrap_or(chars.len() - 1);
+            output.extend(&chars[index..=end]);
+            index = end + 1;
+            continue;
+        }
+
+        let delimiters = match (c, chars.get(index + 1)) {
+            ('\\', Some('(')) => Some(("\\(", "\\)")),
+            ('$', Some(next)) if *next != '$' && !next.is_whitespace() => Some(("$", "$")),
+            _ => None,
+        };
+        if let Some((open, close)) = delimiters {
+            let start = index + open.len();
+            if let Some(end) = find_math_end(&chars, start, close) {
+                let body: String = chars[start..end].iter().collect();
+                output.push_str(&latex_to_unicode(&body));
+                index = end + close.len();
+                continue;
+            }
+        }
+
+        output.push(c);
+        index += 1;
+    }
+    output
+}
+
+fn find_math_end(chars: &[char], start: usize, close: &str) -> Option<usize> {
+    if close == "$" {
+        // 只看下一个 `$`：它前面是空白或后面紧跟数字时（如 `$5 and $10`）不构成公式
+        let end = (start..chars.len()).find(|&i| chars[i] == '$')?;
+        let valid = !chars[end - 1].is_whitespace() && !chars.get(end + 1).is_some_and(|c| c.is_ascii_digit());
+        return valid.then_some(end);
+    }
+    let close: Vec<char> = close.chars().collect();
+    (start..chars.len()).find(|&i| chars[i..].starts_with(&close))
+}
+
+/// 把简单的 LaTeX 公式转换为 Unicode 文本
+pub fn latex_to_unicode(source: &str) -> String {
+    let mut parser = MathParser { chars: source.chars().collect(), index: 0 };
+    parser.expression(false)
+}
+
+struct MathParser {
+    chars: Vec<char>,
+    index: usize,
+}
+
+impl MathParser {
+    /// 解析到末尾或（在分组内时）匹配的 `}`
+    fn expression(&mut self, in_group: bool) -> String {
+        let mut output = String::new();
+        while let Some(&c) = self.chars.get(self.index) {
+            match c {
+                '}' if in_group => {
+                    self.index += 1;
+                    break;
+                }
+                '{' => {
+                    self.index += 1;
+                    output.push_str(&self.expression(true));
+                }
+                '^' | '_' => {
+                    self.index += 1;
+                    let argument = self.argument();
+                    output.push_str(&script(&argument, c == '^'));
+                }
+                '\\' => {
+                    self.index += 1;
+                    output.push_str(&self.command());
+                }
+                _ => {
+                    self.index += 1;
+                    output.push(c);
+                }
+            }
+        }
+        output
+    }
+
+    /// `^`、`_` 和命令的参数：分组、命令或单个字符
+    fn argument(&mut self) -> String {
+        while self.chars.get(self.index).is_some_and(|c| *c == ' ') {
+            self.index += 1;
+        }
+        match self.chars.get(self.index).copied() {
+            Some('{') => {
+                self.index += 1;
+                self.expression(true)
+            }
+            Some('\\') => {
+                self.index += 1;
+                self.command()
+            }
+            Some(c) => {
+                self.index += 1;
+                c.to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    fn command(&mut self) -> String {
+        let start = self.index;
+        while self.chars.get(self.index).is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.index += 1;
+        }
+        if self.index == start {
+            // 单字符命令：\, \; \{ \} 等
+            let Some(&c) = self.chars.get(self.index) else {
+                return "\\".to_string();
+            };
+            self.index += 1;
+            return match c {
+                ',' | ';' | ':' | ' ' => " ".to_string(),
+                '!' => String::new(),
+                '\\' => "\n".to_string(),
+                other => other.to_string(),
+            };
+        }
+
+        let name: String = self.chars[start..self.index].iter().collect();
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                format!("{}/{}", parenthesize(&numerator), parenthesize(&denominator))
+            }
+            "sqrt" => {
+                let root = match self.chars.get(self.index) {
+                    Some('[') => {
+                        let end = self.chars[self.index..].iter().position(|c| *c == ']').map(|p| self.index + p);
+                        let end = end.unwrap_or(self.chars.len() - 1);
+                        let index: String = self.chars[self.index + 1..end].iter().collect();
+                        self.index = end + 1;
+                        match index.trim() {
+                            "3" => "∛",
+                            "4" => "∜",
+                            _ => "√",
+                        }
+                    }
+                    _ => "√",
+                };
+                let radicand = self.argument();
+                if radicand.chars().count() == 1 {
+                    format!("{}{}", root, radicand)
+                } else {
+                    format!("{}({})", root, radicand)
+                }
+            }
+            "text" | "mathrm" | "mathbf" | "mathit" | "mathsf" | "mathtt" | "operatorname" | "boldsymbol" => {
+                self.argument()
+            }
+            "mathbb" => {
+                let argument = self.argument();
+                argument.chars().map(double_struck).collect()
+            }
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "displaystyle" | "limits" => String::new(),
+            "quad" | "qquad" => "  ".to_string(),
+            _ => match symbol(&name) {
+                Some(symbol) => symbol.to_string(),
+                None if FUNCTIONS.contains(&name.as_str()) => name,
+                None => format!("\\{}", name),
+            },
+        }
+    }
+}
+
+/// 数学函数名，去掉反斜杠直接输出
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh", "log", "ln", "lg",
+    "exp", "max", "min", "sup", "inf", "lim", "det", "gcd", "deg", "dim", "ker", "arg", "mod", "bmod", "Pr",
+];
+
+fn symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α", "beta" => "β", "gamma" => "γ", "delta" => "δ", "epsilon" | "varepsilon" => "ε",
+        "zeta" => "ζ", "eta" => "η", "theta" | "vartheta" => "θ", "iota" => "ι", "kappa" => "κ",
+        "lambda" => "λ", "mu" => "μ", "nu" => "ν", "xi" => "ξ", "pi" => "π", "rho" => "ρ",
+        "sigma" => "σ", "tau" => "τ", "upsilon" => "υ", "phi" | "varphi" => "φ", "chi" => "χ",
+        "psi" => "ψ", "omega" => "ω",
+        "Gamma" => "Γ", "Delta" => "Δ", "Theta" => "Θ", "Lambda" => "Λ", "Xi" => "Ξ", "Pi" => "Π",
+        "Sigma" => "Σ", "Phi" => "Φ", "Psi" => "Ψ", "Omega" => "Ω",
+        "sum" => "∑", "prod" => "∏", "int" => "∫", "iint" => "∬", "oint" => "∮", "partial" => "∂",
+        "nabla" => "∇", "infty" => "∞", "pm" => "±", "mp" => "∓", "times" => "×", "cdot" => "·",
+        "div" => "÷", "ast" => "∗", "circ" => "∘", "bullet" => "•",
+        "leq" | "le" => "≤", "geq" | "ge" => "≥", "neq" | "ne" => "≠", "approx" => "≈", "equiv" => "≡",
+        "sim" => "∼", "simeq" => "≃", "propto" => "∝", "ll" => "≪", "gg" => "≫",
+        "in" => "∈", "notin" => "∉", "ni" => "∋", "subset" => "⊂", "subseteq" => "⊆", "supset" => "⊃",
+        "supseteq" => "⊇", "cup" => "∪", "cap" => "∩", "emptyset" | "varnothing" => "∅", "setminus" => "∖",
+        "forall" => "∀", "exists" => "∃", "neg" | "lnot" => "¬", "land" | "wedge" => "∧", "lor" | "vee" => "∨",
+        "oplus" => "⊕", "otimes" => "⊗",
+        "to" | "rightarrow" => "→", "leftarrow" | "gets" => "←", "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒", "Leftarrow" => "⇐", "Leftrightarrow" | "iff" => "⇔", "mapsto" => "↦",
+        "uparrow" => "↑", "downarrow" => "↓",
+        "ldots" | "dots" => "…", "cdots" => "⋯", "vdots" => "⋮", "ddots" => "⋱",
+        "lfloor" => "⌊", "rfloor" => "⌋", "lceil" => "⌈", "rceil" => "⌉", "langle" => "⟨", "rangle" => "⟩",
+        "mid" => "∣", "parallel" => "∥", "perp" => "⊥", "angle" => "∠", "degree" => "°", "prime" => "′",
+        "hbar" => "ℏ", "ell" => "ℓ", "Re" => "ℜ", "Im" => "ℑ", "aleph" => "ℵ",
+        "lbrace" => "{", "rbrace" => "}", "vert" => "|", "Vert" => "‖",
+        _ => return None,
+    })
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'N' => 'ℕ',
+        'Z' => 'ℤ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'C' => 'ℂ',
+        'P' => 'ℙ',
+        other => other,
+    }
+}
+
+/// 多字符的分子分母加括号，避免 `a+b/2` 这样的歧义
+fn parenthesize(text: &str) -> String {
+    if text.chars().count() <= 1 || text.chars().all(|c| c.is_alphanumeric()) {
+        text.to_string()
+    } else {
+        format!("({})", text)
+    }
+}
+
+/// 上标或下标：所有字符都有对应的 Unicode 字符时直接转换，否则写成 `^(…)`/`_(…)`
+fn script(text: &str, superscript: bool) -> String {
+    let map = if superscript { superscript_char } else { subscript_char };
+    match text.chars().map(map).collect::<Option<String>>() {
+        Some(converted) => converted,
+        None => {
+            let marker = if superscript { '^' } else { '_' };
+            if text.chars().count() == 1 {
+                format!("{}{}", marker, text)
+            } else {
+                format!("{}({})", marker, text)
+            }
+        }
+    }
+}
+
+fn superscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰', '1' => '¹', '2' => '²', '3' => '³', '4' => '⁴', '5' => '⁵', '6' => '⁶', '7' => '⁷',
+        '8' => '⁸', '9' => '⁹', '+' => '⁺', '-' | '−' => '⁻', '=' => '⁼', '(' => '⁽', ')' => '⁾',
+        'n' => 'ⁿ', 'i' => 'ⁱ', 'a' => 'ᵃ', 'b' => 'ᵇ', 'c' => 'ᶜ', 'd' => 'ᵈ', 'e' => 'ᵉ', 'f' => 'ᶠ',
+        'g' => 'ᵍ', 'h' => 'ʰ', 'j' => 'ʲ', 'k' => 'ᵏ', 'l' => 'ˡ', 'm' => 'ᵐ', 'o' => 'ᵒ', 'p' => 'ᵖ',
+        'r' => 'ʳ', 's' => 'ˢ', 't' => 'ᵗ', 'u' => 'ᵘ', 'v' => 'ᵛ', 'w' => 'ʷ', 'x' => 'ˣ', 'y' => 'ʸ',
+        'z' => 'ᶻ', 'T' => 'ᵀ', '′' => '′', '*' => '*',
+        _ => return None,
+    })
+}
+
+fn subscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀', '1' => '₁', '2' => '₂', '3' => '₃', '4' => '₄', '5' => '₅', '6' => '₆', '7' => '₇',
+        '8' => '₈', '9' => '₉', '+' => '₊', '-' | '−' => '₋', '=' => '₌', '(' => '₍', ')' => '₎',
+        'a' => 'ₐ', 'e' => 'ₑ', 'h' => 'ₕ', 'i' => 'ᵢ', 'j' => 'ⱼ', 'k' => 'ₖ', 'l' => 'ₗ', 'm' => 'ₘ',
+        'n' => 'ₙ', 'o' => 'ₒ', 'p' => 'ₚ', 'r' => 'ᵣ', 's' => 'ₛ', 't' => 'ₜ', 'u' => 'ᵤ', 'v' => 'ᵥ',
+        'x' => 'ₓ',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables_are_boxed_and_aligned() {
+        let input = "Results:\n\n| Name | 时间 | Score |\n|:-----|:---:|------:|\n| quick | 快 | 9 |\n| merge \\| sort | 慢 | 10 |\n\nDone.\n";
+        let expected = "Results:\n\n\
+┌──────────────┬──────┬───────┐\n\
+│ Name         │ 时间 │ Score │\n\
+├──────────────┼──────┼───────┤\n\
+│ quick        │  快  │     9 │\n\
+│ merge | sort │  慢  │    10 │\n\
+└──────────────┴──────┴───────┘\n\
+\nDone.\n";
+        assert_eq!(render_markdown(input), expected);
+
+        // 代码块中的内容不做处理
+        let fenced = "```\n| a | b |\n|---|---|\n$x^2$\n```";
+        assert_eq!(render_markdown(fenced), fenced);
+    }
+
+    #[test]
+    fn test_math_is_rendered_as_unicode() {
+        assert_eq!(latex_to_unicode(r"\sum_{i=1}^{n} i = \frac{n(n+1)}{2}"), "∑ᵢ₌₁ⁿ i = (n(n+1))/2");
+        assert_eq!(latex_to_unicode(r"O(n \log n) \leq \sqrt{x^2 + y^2}"), "O(n log n) ≤ √(x² + y²)");
+        assert_eq!(latex_to_unicode(r"x_{max} \in \mathbb{R}"), "xₘₐₓ ∈ ℝ");
+        assert_eq!(latex_to_unicode(r"e^{i\pi} + 1 = 0"), "e^(iπ) + 1 = 0");
+
+        assert_eq!(
+            render_markdown("Cost is $5 and $10, complexity $O(n^2)$ and `$HOME`."),
+            "Cost is $5 and $10, complexity O(n²) and `$HOME`."
+        );
+        assert_eq!(render_markdown("$$\n\\alpha + \\beta\n$$"), "    α + β");
+        assert_eq!(render_markdown("where \\(x \\to \\infty\\)"), "where x → ∞");
+    }
+}
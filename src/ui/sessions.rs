@@ -0,0 +1,115 @@
+//! 标签页
+//!
+//! TUI 中同时打开的多个会话按标签页排列。这里只维护顺序和当前选中项，会话内容由调用方决定
+
+/// 有序的标签页列表，始终至少有一个标签页
+#[derive(Debug, Clone)]
+pub struct TabList<T> {
+    tabs: Vec<T>,
+    active: usize,
+}
+
+impl<T> TabList<T> {
+    pub fn new(first: T) -> Self {
+        Self { tabs: vec![first], active: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn active(&self) -> &T {
+        &self.tabs[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut T {
+        &mut self.tabs[self.active]
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.tabs.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.tabs.get_mut(index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.tabs.iter()
+    }
+
+    pub fn position(&self, predicate: impl FnMut(&T) -> bool) -> Option<usize> {
+        self.tabs.iter().position(predicate)
+    }
+
+    /// 在当前标签页后面插入新标签页并选中它
+    pub fn insert_after_active(&mut self, tab: T) -> usize {
+        self.active += 1;
+        self.tabs.insert(self.active, tab);
+        self.active
+    }
+
+    /// 选中指定标签页，下标越界时返回 false
+    pub fn select(&mut self, index: usize) -> bool {
+        if index < self.tabs.len() {
+            self.active = index;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 循环切换到下一个（`forward`）或上一个标签页
+    pub fn cycle(&mut self, forward: bool) {
+        let len = self.tabs.len();
+        self.active = if forward { (self.active + 1) % len } else { (self.active + len - 1) % len };
+    }
+
+    /// 关闭当前标签页并选中它左边的一个；只剩一个时不关闭
+    pub fn close_active(&mut self) -> Option<T> {
+        if self.tabs.len() == 1 {
+            return None;
+        }
+        let closed = self.tabs.remove(self.active);
+        self.active = self.active.saturating_sub(1);
+        Some(closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tab_list_navigation() {
+        let mut tabs = TabList::new("a");
+        assert_eq!(tabs.close_active(), None);
+
+        tabs.insert_after_active("b");
+        tabs.insert_after_active("c");
+        tabs.select(0);
+        tabs.insert_after_active("d");
+        assert_eq!(tabs.iter().copied().collect::<Vec<_>>(), vec!["a", "d", "b", "c"]);
+        assert_eq!(*tabs.active(), "d");
+
+        tabs.cycle(false);
+        tabs.cycle(false);
+        assert_eq!(*tabs.active(), "c");
+        tabs.cycle(true);
+        assert_eq!(*tabs.active(), "a");
+        assert!(!tabs.select(4));
+
+        tabs.select(2);
+        assert_eq!(tabs.close_active(), Some("b"));
+        assert_eq!(*tabs.active(), "d");
+        assert_eq!(tabs.position(|t| *t == "c"), Some(2));
+    }
+}
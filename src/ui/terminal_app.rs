@@ -4,7 +4,7 @@
 
 use crate::error::Result;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -13,7 +13,7 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style},
     text::Line,
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap, Gauge},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Tabs, Wrap, Gauge},
     Frame, Terminal,
 };
 use std::io;
@@ -30,6 +30,28 @@ pub enum AppMode {
     Help,
     /// 退出确认
     ExitConfirm,
+    /// 终端内的文件查找
+    FilePicker,
+    /// 所有会话与后台任务的概览
+    Sessions,
+}
+
+/// 选中文件后的操作
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PickAction {
+    /// 随下一条消息发送
+    Attach,
+    /// 在对话中显示文件内容
+    Open,
+}
+
+impl PickAction {
+    fn title(self) -> &'static str {
+        match self {
+            PickAction::Attach => "Attach a file",
+            PickAction::Open => "Open a file",
+        }
+    }
 }
 
 /// 消息类型
@@ -43,13 +65,15 @@ pub enum MessageType {
     System,
     /// 错误消息
     Error,
+    /// 工具结果
+    Tool,
 }
 
 /// 聊天消息 - 重新设计以匹配原版Claude Code的消息格式
 #[derive(Debug, Clone)]
 pub struct ChatMessage {
-    /// 消息内容
-    pub content: String,
+    /// 消息内容，与对话历史共享同一份文本
+    pub content: crate::conversation::SharedText,
     /// 消息类型
     pub message_type: MessageType,
     /// 时间戳
@@ -58,6 +82,38 @@ pub struct ChatMessage {
     pub is_streaming: bool,
 }
 
+/// 一个标签页中的会话。当前标签页的内容保存在 `TerminalApp` 的字段中，切换标签页时互相交换
+#[derive(Default)]
+struct ChatSession {
+    id: u64,
+    title: String,
+    messages: Vec<ChatMessage>,
+    input: Input,
+    input_history: Vec<String>,
+    history_index: Option<usize>,
+    message_scroll: usize,
+    attachments: Vec<std::path::PathBuf>,
+    status_message: String,
+    /// 有未查看的新回复
+    unread: bool,
+}
+
+/// 后台回合发回主循环的进度
+enum TurnUpdate {
+    /// 流式事件（事件类型和负载），实时更新用量计数器
+    Event(String, serde_json::Value),
+    /// 回合结束
+    Done(Result<String>),
+}
+
+/// 后台进行中的对话回合
+struct RunningTurn {
+    started: Instant,
+    timer: crate::agent::TurnTimer,
+    ticker: crate::streaming::UsageTicker,
+    task: tokio::task::JoinHandle<()>,
+}
+
 /// 终端应用 - 重新设计以匹配原版Claude Code的体验
 pub struct TerminalApp {
     /// 当前模式
@@ -82,6 +138,48 @@ pub struct TerminalApp {
     input_history: Vec<String>,
     /// 历史索引
     history_index: Option<usize>,
+    /// 流式用量计数（tokens、成本、耗时、速率）
+    ticker: Option<crate::streaming::TickerSnapshot>,
+    /// 回合延迟历史
+    latency: crate::agent::LatencyStats,
+    /// 会话环境快照（启动时后台采集）
+    environment: Option<crate::conversation::EnvironmentSnapshot>,
+    /// 环境快照采集任务
+    environment_task: Option<tokio::task::JoinHandle<crate::conversation::EnvironmentSnapshot>>,
+    /// 配置热重载结果
+    config_updates: Option<tokio::sync::broadcast::Receiver<crate::config::reload::ReloadOutcome>>,
+    /// 本次会话的回复语言
+    language: Option<crate::conversation::ResponseLanguage>,
+    /// 工具命令的执行位置，显示在状态栏中
+    execution_target: Option<crate::process::devcontainer::SharedExecutionTarget>,
+    /// 本次会话的临时工作区
+    scratchpad: Option<crate::fs::scratch::Scratchpad>,
+    /// 本次会话的任务清单，以及上次读取时的修改时间和状态栏摘要
+    todos: Option<(crate::tools::todo::TodoStore, Option<std::time::SystemTime>, Option<String>)>,
+    /// 是否优先使用系统文件对话框
+    native_dialogs: bool,
+    /// 进行中的终端文件查找
+    picker: Option<(PickAction, crate::ui::file_picker::FuzzyFinder)>,
+    /// 随下一条消息发送的附件
+    attachments: Vec<std::path::PathBuf>,
+    /// 用户录制的键盘宏
+    macros: crate::ui::macros::MacroStore,
+    /// 正在录制的宏名及已录制的输入
+    recording: Option<(String, Vec<String>)>,
+    /// 最近录制或重放的宏，F3 重放它
+    last_macro: Option<String>,
+    /// 打开的会话标签页
+    tabs: crate::ui::sessions::TabList<ChatSession>,
+    next_session_id: u64,
+    /// 各会话进行中的回合
+    running: std::collections::HashMap<u64, RunningTurn>,
+    /// 后台回合把 (会话 id, 进度) 发回主循环
+    turn_tx: tokio::sync::mpsc::UnboundedSender<(u64, TurnUpdate)>,
+    turn_rx: tokio::sync::mpsc::UnboundedReceiver<(u64, TurnUpdate)>,
+    /// 会话使用的模型，用量计数器按它估算费用
+    model: String,
+    /// 会话概览中的光标
+    sessions_cursor: usize,
 }
 
 impl Default for TerminalApp {
@@ -93,6 +191,7 @@ impl Default for TerminalApp {
 impl TerminalApp {
     /// 创建新的终端应用 - 默认进入聊天模式，类似原版Claude Code
     pub fn new() -> Self {
+        let (turn_tx, turn_rx) = tokio::sync::mpsc::unbounded_channel();
         Self {
             mode: AppMode::Chat,  // 默认进入聊天模式
             should_quit: false,
@@ -105,11 +204,103 @@ impl TerminalApp {
             show_welcome: true,
             input_history: Vec::new(),
             history_index: None,
+            ticker: None,
+            latency: crate::agent::LatencyStats::new(),
+            environment: None,
+            environment_task: None,
+            config_updates: None,
+            language: None,
+            execution_target: None,
+            scratchpad: None,
+            todos: None,
+            native_dialogs: true,
+            picker: None,
+            attachments: Vec::new(),
+            macros: Self::load_macros(),
+            recording: None,
+            last_macro: None,
+            tabs: crate::ui::sessions::TabList::new(ChatSession { id: 1, title: "Session 1".to_string(), ..Default::default() }),
+            next_session_id: 2,
+            running: std::collections::HashMap::new(),
+            turn_tx,
+            turn_rx,
+            model: crate::config::ApiConfig::default().default_model,
+            sessions_cursor: 0,
+        }
+    }
+
+    fn load_macros() -> crate::ui::macros::MacroStore {
+        use crate::ui::macros::MacroStore;
+        let path = MacroStore::default_path();
+        MacroStore::load(&path).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable macro file {}: {}", path.display(), e);
+            MacroStore::default()
+        })
+    }
+
+    /// 设置会话使用的模型（通常来自配置）
+    pub fn set_model(&mut self, model: impl Into<String>) {
+        self.model = model.into();
+    }
+
+    /// 设置回复语言（通常来自配置）
+    pub fn set_language(&mut self, language: Option<crate::conversation::ResponseLanguage>) {
+        self.language = language;
+    }
+
+    /// 在状态栏中显示工具命令的执行位置
+    pub fn set_execution_target(&mut self, target: crate::process::devcontainer::SharedExecutionTarget) {
+        self.execution_target = Some(target);
+    }
+
+    /// 设置会话的临时工作区，供 /scratch 列出其中的文件
+    pub fn set_scratchpad(&mut self, scratchpad: crate::fs::scratch::Scratchpad) {
+        self.scratchpad = Some(scratchpad);
+    }
+
+    /// 在状态栏中显示会话任务清单的进度
+    pub fn set_todo_store(&mut self, store: crate::tools::todo::TodoStore) {
+        self.todos = Some((store, None, None));
+        self.refresh_todos();
+    }
+
+    /// 清单文件有变化时重新读取摘要
+    fn refresh_todos(&mut self) {
+        let Some((store, modified, summary)) = self.todos.as_mut() else {
+            return;
+        };
+        let current = store.modified();
+        if current != *modified {
+            *modified = current;
+            *summary = store.load().ok().and_then(|items| crate::tools::todo::summary(&items));
         }
     }
 
+    /// /attach 和 /open 是否在桌面环境中使用系统文件对话框
+    pub fn set_native_dialogs(&mut self, enabled: bool) {
+        self.native_dialogs = enabled;
+    }
+
+    /// 接收配置热重载结果，以系统消息显示变化
+    pub fn watch_config(&mut self, updates: tokio::sync::broadcast::Receiver<crate::config::reload::ReloadOutcome>) {
+        self.config_updates = Some(updates);
+    }
+
+    /// 更新状态栏中的流式用量计数
+    pub fn update_ticker(&mut self, snapshot: Option<crate::streaming::TickerSnapshot>) {
+        self.ticker = snapshot;
+    }
+
     /// 运行应用
     pub async fn run(&mut self) -> Result<()> {
+        // 后台采集会话环境快照
+        if self.environment.is_none() && self.environment_task.is_none() {
+            let working_dir = std::env::current_dir()?;
+            self.environment_task = Some(tokio::spawn(async move {
+                crate::conversation::EnvironmentSnapshot::capture(&working_dir).await
+            }));
+        }
+
         // 设置终端
         enable_raw_mode()?;
         let mut stdout = io::stdout();
@@ -191,6 +382,7 @@ impl TerminalApp {
                     }
                     _ => {
                         self.mode = AppMode::Chat;
+                        self.picker = None;
                     }
                 }
                 return Ok(());
@@ -198,14 +390,143 @@ impl TerminalApp {
             _ => {}
         }
 
+        if self.mode == AppMode::Chat && self.handle_tab_keys(key) {
+            return Ok(());
+        }
+
         match self.mode {
             AppMode::Chat => self.handle_chat_keys(key).await?,
             AppMode::Help => self.handle_help_keys(key).await?,
             AppMode::ExitConfirm => self.handle_exit_confirm_keys(key).await?,
+            AppMode::FilePicker => self.handle_picker_keys(key).await?,
+            AppMode::Sessions => self.handle_sessions_keys(key),
         }
         Ok(())
     }
 
+    /// 标签页快捷键：Ctrl+T 新建、Ctrl+W 关闭、Ctrl+←/→ 切换、Alt+1..9 跳转、F4 概览。
+    /// 返回按键是否已处理
+    fn handle_tab_keys(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('t') if ctrl => self.new_session(None),
+            KeyCode::Char('w') if ctrl => self.close_session(),
+            KeyCode::Right if ctrl => self.cycle_session(true),
+            KeyCode::Left if ctrl => self.cycle_session(false),
+            KeyCode::Char(c @ '1'..='9') if key.modifiers.contains(KeyModifiers::ALT) => {
+                self.switch_session(c as usize - '1' as usize);
+            }
+            KeyCode::F(4) => self.show_sessions(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// 会话概览中的按键：↑/↓ 选择，Enter 切换过去
+    fn handle_sessions_keys(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Up => self.sessions_cursor = self.sessions_cursor.saturating_sub(1),
+            KeyCode::Down => self.sessions_cursor = (self.sessions_cursor + 1).min(self.tabs.len() - 1),
+            KeyCode::Enter => {
+                self.mode = AppMode::Chat;
+                self.switch_session(self.sessions_cursor);
+            }
+            _ => {}
+        }
+    }
+
+    fn show_sessions(&mut self) {
+        self.sessions_cursor = self.tabs.active_index();
+        self.mode = AppMode::Sessions;
+    }
+
+    /// 交换 `TerminalApp` 字段与当前标签页中保存的会话内容
+    fn swap_active_session(&mut self) {
+        let tab = self.tabs.active_mut();
+        std::mem::swap(&mut self.messages, &mut tab.messages);
+        std::mem::swap(&mut self.input, &mut tab.input);
+        std::mem::swap(&mut self.input_history, &mut tab.input_history);
+        std::mem::swap(&mut self.history_index, &mut tab.history_index);
+        std::mem::swap(&mut self.message_scroll, &mut tab.message_scroll);
+        std::mem::swap(&mut self.attachments, &mut tab.attachments);
+        std::mem::swap(&mut self.status_message, &mut tab.status_message);
+    }
+
+    /// 切换标签页：`change` 修改 `tabs` 的选中项，前后交换会话内容并同步加载状态和用量计数
+    fn change_session(&mut self, change: impl FnOnce(&mut crate::ui::sessions::TabList<ChatSession>)) {
+        self.swap_active_session();
+        change(&mut self.tabs);
+        self.swap_active_session();
+
+        let tab = self.tabs.active_mut();
+        tab.unread = false;
+        let id = tab.id;
+        self.is_loading = self.running.contains_key(&id);
+        self.ticker = self.running.get(&id).map(|turn| turn.ticker.snapshot());
+    }
+
+    fn switch_session(&mut self, index: usize) {
+        if index != self.tabs.active_index() && index < self.tabs.len() {
+            self.change_session(|tabs| {
+                tabs.select(index);
+            });
+        }
+    }
+
+    fn cycle_session(&mut self, forward: bool) {
+        self.change_session(|tabs| tabs.cycle(forward));
+    }
+
+    /// 在当前标签页后面打开一个新会话
+    fn new_session(&mut self, title: Option<String>) {
+        let id = self.next_session_id;
+        self.next_session_id += 1;
+        let session = ChatSession {
+            id,
+            title: title.unwrap_or_else(|| format!("Session {}", id)),
+            status_message: "Ready to chat".to_string(),
+            ..Default::default()
+        };
+        self.change_session(|tabs| {
+            tabs.insert_after_active(session);
+        });
+    }
+
+    /// 关闭当前会话，取消它进行中的回合
+    fn close_session(&mut self) {
+        if self.tabs.len() == 1 {
+            self.status_message = "Cannot close the last session".to_string();
+            return;
+        }
+        let mut closed = None;
+        self.change_session(|tabs| closed = tabs.close_active());
+        if let Some(turn) = closed.and_then(|closed| self.running.remove(&closed.id)) {
+            turn.task.abort();
+        }
+    }
+
+    /// 处理 /tab 子命令
+    fn handle_tab_command(&mut self, args: &str) {
+        let args = args.trim();
+        let (sub, rest) = args.split_once(' ').map_or((args, ""), |(sub, rest)| (sub, rest.trim()));
+        match sub {
+            "new" => self.new_session((!rest.is_empty()).then(|| rest.to_string())),
+            "close" => self.close_session(),
+            "rename" if !rest.is_empty() => {
+                self.tabs.active_mut().title = rest.to_string();
+                self.status_message = format!("Renamed session to '{}'", rest);
+            }
+            "" | "list" => self.show_sessions(),
+            number => match number.parse::<usize>() {
+                Ok(n) if (1..=self.tabs.len()).contains(&n) => self.switch_session(n - 1),
+                _ => self.add_message(
+                    "Usage: /tab new [title] | close | rename <title> | <number> | list",
+                    MessageType::Error,
+                ),
+            },
+        }
+    }
+
     /// 处理聊天模式按键 - 重新设计以匹配原版Claude Code的交互
     async fn handle_chat_keys(&mut self, key: KeyEvent) -> Result<()> {
         match key.code {
@@ -215,11 +536,22 @@ impl TerminalApp {
                     // 添加到历史记录
                     self.input_history.push(message.clone());
                     self.history_index = None;
-
-                    self.send_message(message).await?;
                     self.input.reset();
+                    self.submit(message).await?;
                 }
             }
+            KeyCode::F(2) => {
+                // 开始/结束录制快捷宏
+                if self.recording.is_some() {
+                    self.stop_recording();
+                } else {
+                    self.start_recording(crate::ui::macros::QUICK_MACRO);
+                }
+            }
+            KeyCode::F(3) => {
+                let name = self.last_macro.clone().unwrap_or_else(|| crate::ui::macros::QUICK_MACRO.to_string());
+                self.play_macro(&name, 1).await?;
+            }
             KeyCode::Up if self.input.value().is_empty() => {
                 // 浏览输入历史
                 if !self.input_history.is_empty() {
@@ -315,8 +647,203 @@ impl TerminalApp {
         Ok(())
     }
 
+    /// 提交一条输入：斜杠命令或消息。录制宏时记录下来（宏管理命令本身除外）
+    async fn submit(&mut self, input: String) -> Result<()> {
+        if let Some((_, steps)) = self.recording.as_mut() {
+            if !is_macro_command(&input) {
+                steps.push(input.clone());
+            }
+        }
+        if input.trim_start().starts_with('/') {
+            self.execute_command(input).await
+        } else {
+            self.send_message(input).await
+        }
+    }
+
+    fn start_recording(&mut self, name: &str) {
+        if let Some((current, _)) = &self.recording {
+            self.add_message(format!("Already recording macro '{}'; /macro stop first", current), MessageType::Error);
+            return;
+        }
+        self.recording = Some((name.to_string(), Vec::new()));
+        self.add_message(
+            format!("⏺️ Recording macro '{}' — everything you submit is recorded until /macro stop (or F2)", name),
+            MessageType::System,
+        );
+    }
+
+    fn stop_recording(&mut self) {
+        let Some((name, steps)) = self.recording.take() else {
+            self.add_message("Not recording a macro", MessageType::Error);
+            return;
+        };
+        let count = steps.len();
+        match self.macros.insert(&name, steps) {
+            Ok(()) => {
+                self.add_message(format!("💾 Saved macro '{}' ({} steps)", name, count), MessageType::System);
+                self.last_macro = Some(name);
+            }
+            Err(e) => self.add_message(format!("Macro '{}' not saved: {}", name, e), MessageType::Error),
+        }
+    }
+
+    /// 按顺序重放宏的每一步，重放中出错时停止
+    async fn play_macro(&mut self, name: &str, times: usize) -> Result<()> {
+        if self.recording.is_some() {
+            self.add_message("Cannot play a macro while recording one", MessageType::Error);
+            return Ok(());
+        }
+        let Some(steps) = self.macros.get(name).map(|m| m.steps.clone()) else {
+            self.add_message(format!("No macro named '{}'; see /macro list", name), MessageType::Error);
+            return Ok(());
+        };
+        self.last_macro = Some(name.to_string());
+        self.add_message(format!("▶️ Playing macro '{}' ({} steps × {})", name, steps.len(), times), MessageType::System);
+        for _ in 0..times {
+            for step in &steps {
+                // 宏管理命令不会被录制，这里再挡一次以防手工编辑的宏文件递归重放
+                if is_macro_command(step) {
+                    continue;
+                }
+                if let Err(e) = Box::pin(self.submit(step.clone())).await {
+                    self.add_message(format!("Macro '{}' stopped at '{}': {}", name, step, e), MessageType::Error);
+                    return Ok(());
+                }
+                // 下一步可能依赖这一步的回复
+                self.wait_for_turn().await;
+            }
+        }
+        Ok(())
+    }
+
+    /// 处理 /macro 子命令
+    async fn handle_macro_command(&mut self, args: &str) -> Result<()> {
+        let mut parts = args.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("record"), name) => self.start_recording(name.unwrap_or(crate::ui::macros::QUICK_MACRO)),
+            (Some("stop"), _) => self.stop_recording(),
+            (Some("play"), Some(name)) => {
+                let times = parts.next().and_then(|n| n.parse().ok()).unwrap_or(1);
+                self.play_macro(name, times).await?;
+            }
+            (Some("delete"), Some(name)) => match self.macros.remove(name) {
+                Ok(true) => self.add_message(format!("🗑️ Deleted macro '{}'", name), MessageType::System),
+                Ok(false) => self.add_message(format!("No macro named '{}'", name), MessageType::Error),
+                Err(e) => self.add_message(format!("Failed to delete macro '{}': {}", name, e), MessageType::Error),
+            },
+            (None | Some("list"), _) => {
+                let lines: Vec<String> = self
+                    .macros
+                    .iter()
+                    .map(|(name, m)| format!("  {:<12} {} steps: {}", name, m.steps.len(), m.steps.join(" → ")))
+                    .collect();
+                if lines.is_empty() {
+                    self.add_message("No macros yet — /macro record <name> or press F2 to start", MessageType::System);
+                } else {
+                    self.add_message(format!("⌨️ Macros ({})\n{}", self.macros.path().display(), lines.join("\n")), MessageType::Tool);
+                }
+            }
+            _ => self.add_message(
+                "Usage: /macro record [name] | stop | play <name> [times] | list | delete <name>",
+                MessageType::Error,
+            ),
+        }
+        Ok(())
+    }
+
+    /// 处理文件查找按键
+    async fn handle_picker_keys(&mut self, key: KeyEvent) -> Result<()> {
+        let Some((action, finder)) = self.picker.as_mut() else {
+            self.mode = AppMode::Chat;
+            return Ok(());
+        };
+        match key.code {
+            KeyCode::Enter => {
+                let (action, selected) = (*action, finder.selected());
+                self.picker = None;
+                self.mode = AppMode::Chat;
+                if let Some(path) = selected {
+                    self.complete_pick(action, path);
+                }
+            }
+            KeyCode::Up => finder.move_selection(-1),
+            KeyCode::Down => finder.move_selection(1),
+            KeyCode::Backspace => finder.pop(),
+            KeyCode::Char(c) => finder.push(c),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// 选择文件：桌面环境中打开系统对话框，否则（或对话框不可用时）进入终端内的模糊查找
+    async fn pick_file(&mut self, action: PickAction) -> Result<()> {
+        use crate::ui::file_picker::{has_desktop, pick_native, FuzzyFinder};
+
+        let dir = std::env::current_dir()?;
+        if self.native_dialogs && has_desktop() {
+            let native_dir = dir.clone();
+            match tokio::task::spawn_blocking(move || pick_native(action.title(), &native_dir)).await {
+                Ok(Ok(Some(path))) => {
+                    self.complete_pick(action, path);
+                    return Ok(());
+                }
+                Ok(Ok(None)) => {
+                    self.status_message = "File selection cancelled".to_string();
+                    return Ok(());
+                }
+                Ok(Err(e)) => debug!("Falling back to the fuzzy finder: {}", e),
+                Err(e) => warn!("File dialog task failed: {}", e),
+            }
+        }
+
+        let finder = tokio::task::spawn_blocking(move || FuzzyFinder::new(&dir))
+            .await
+            .map_err(|e| crate::error::ClaudeError::General(format!("File search failed: {}", e)))??;
+        self.picker = Some((action, finder));
+        self.mode = AppMode::FilePicker;
+        Ok(())
+    }
+
+    /// 处理选中的文件
+    fn complete_pick(&mut self, action: PickAction, path: std::path::PathBuf) {
+        match action {
+            PickAction::Attach => {
+                self.add_message(format!("📎 Attached {} (sent with your next message)", path.display()), MessageType::System);
+                self.attachments.push(path);
+            }
+            PickAction::Open => match std::fs::read_to_string(&path) {
+                Ok(content) => {
+                    const PREVIEW_LINES: usize = 200;
+                    let total = content.lines().count();
+                    let mut preview: Vec<&str> = content.lines().take(PREVIEW_LINES).collect();
+                    let more = format!("… {} more lines", total.saturating_sub(PREVIEW_LINES));
+                    if total > PREVIEW_LINES {
+                        preview.push(&more);
+                    }
+                    self.add_message(format!("📄 {}\n{}", path.display(), preview.join("\n")), MessageType::Tool);
+                }
+                Err(e) => self.add_message(format!("Cannot open {}: {}", path.display(), e), MessageType::Error),
+            },
+        }
+    }
+
     /// 发送消息 - 重新设计以提供更好的用户体验
     async fn send_message(&mut self, message: String) -> Result<()> {
+        // 附件随消息一起发送
+        let message = if self.attachments.is_empty() {
+            message
+        } else {
+            let attachments: Vec<String> = self.attachments.drain(..).map(|path| format!("📎 {}", path.display())).collect();
+            format!("{}\n{}", message, attachments.join("\n"))
+        };
+
+        let session = self.tabs.active().id;
+        if self.running.contains_key(&session) {
+            self.add_message("A reply is still in progress here; wait for it or open another session (Ctrl+T)", MessageType::Error);
+            return Ok(());
+        }
+
         // 添加用户消息
         self.add_message(&message, MessageType::User);
 
@@ -324,18 +851,94 @@ impl TerminalApp {
         self.is_loading = true;
         self.status_message = "Claude is thinking...".to_string();
 
-        // 这里可以集成实际的AI API调用
-        let response = self.generate_ai_response(&message).await?;
-
-        self.add_message(&response, MessageType::Assistant);
-        self.is_loading = false;
-        self.status_message = "Ready for your next message".to_string();
+        // 回合在后台运行，等待回复时可以切换到其他会话
+        let mut timer = self.latency.start_turn();
+        let ticker = crate::streaming::UsageTicker::new(&self.model);
+        self.update_ticker(Some(ticker.snapshot()));
+        timer.mark_dispatched();
+        let turn_tx = self.turn_tx.clone();
+        let task = tokio::spawn(async move {
+            let events = turn_tx.clone();
+            let emit = move |event_type: &str, payload: serde_json::Value| {
+                let _ = events.send((session, TurnUpdate::Event(event_type.to_string(), payload)));
+            };
+            let _ = turn_tx.send((session, TurnUpdate::Done(Self::generate_ai_response(&message, emit).await)));
+        });
+        self.running.insert(session, RunningTurn { started: Instant::now(), timer, ticker, task });
 
         Ok(())
     }
 
-    /// 生成AI响应（模拟）
-    async fn generate_ai_response(&mut self, message: &str) -> Result<String> {
+    /// 等待当前会话进行中的回合结束，其他会话的回复照常送达
+    async fn wait_for_turn(&mut self) {
+        while self.running.contains_key(&self.tabs.active().id) {
+            match self.turn_rx.recv().await {
+                Some((session, update)) => self.apply_turn_update(session, update),
+                None => break,
+            }
+        }
+    }
+
+    fn apply_turn_update(&mut self, session: u64, update: TurnUpdate) {
+        match update {
+            TurnUpdate::Event(event_type, payload) => self.record_turn_event(session, &event_type, &payload),
+            TurnUpdate::Done(response) => self.finish_turn(session, response),
+        }
+    }
+
+    /// 流式事件到达时更新回合计时和用量计数器，当前会话的状态栏随之刷新
+    fn record_turn_event(&mut self, session: u64, event_type: &str, payload: &serde_json::Value) {
+        let Some(turn) = self.running.get_mut(&session) else {
+            return;
+        };
+        if event_type == "content_block_delta" {
+            turn.timer.mark_first_token();
+        }
+        turn.ticker.record_event(event_type, payload);
+        let snapshot = turn.ticker.snapshot();
+        if self.tabs.active().id == session {
+            self.update_ticker(Some(snapshot));
+        }
+    }
+
+    /// 把后台回合的回复放进所属的会话
+    fn finish_turn(&mut self, session: u64, response: Result<String>) {
+        // 会话已关闭
+        let Some(mut turn) = self.running.remove(&session) else {
+            return;
+        };
+        let Some(index) = self.tabs.position(|tab| tab.id == session) else {
+            return;
+        };
+        let (content, message_type) = match response {
+            Ok(response) => {
+                turn.timer.mark_stream_end();
+                self.latency.record(turn.timer.finish());
+                (response, MessageType::Assistant)
+            }
+            Err(e) => (format!("Turn failed: {}", e), MessageType::Error),
+        };
+
+        if index == self.tabs.active_index() {
+            self.add_message(content, message_type);
+            self.update_ticker(Some(turn.ticker.snapshot()));
+            self.is_loading = false;
+            self.status_message = "Ready for your next message".to_string();
+        } else if let Some(tab) = self.tabs.get_mut(index) {
+            tab.messages.push(ChatMessage {
+                content: content.into(),
+                message_type,
+                timestamp: chrono::Utc::now(),
+                is_streaming: false,
+            });
+            tab.message_scroll = tab.messages.len().saturating_sub(1);
+            tab.status_message = "Ready for your next message".to_string();
+            tab.unread = true;
+        }
+    }
+
+    /// 生成AI响应（模拟），文本按流式事件逐段交给 `emit`
+    async fn generate_ai_response(message: &str, emit: impl Fn(&str, serde_json::Value)) -> Result<String> {
         // 模拟处理时间
         tokio::time::sleep(Duration::from_millis(1000)).await;
         
@@ -353,14 +956,18 @@ impl TerminalApp {
                 "I understand your message. This is a demo response from the Claude Code Rust edition terminal UI!"
             }
         };
+        for word in response.split_inclusive(' ') {
+            emit("content_block_delta", serde_json::json!({ "delta": { "type": "text_delta", "text": word } }));
+            tokio::time::sleep(Duration::from_millis(30)).await;
+        }
         
         Ok(response.to_string())
     }
 
     /// 添加消息 - 统一的消息添加方法
-    fn add_message(&mut self, content: &str, message_type: MessageType) {
+    fn add_message(&mut self, content: impl Into<crate::conversation::SharedText>, message_type: MessageType) {
         self.messages.push(ChatMessage {
-            content: content.to_string(),
+            content: content.into(),
             message_type,
             timestamp: chrono::Utc::now(),
             is_streaming: false,
@@ -372,12 +979,31 @@ impl TerminalApp {
         }
     }
 
+    /// 获取会话环境快照，必要时等待后台采集完成
+    async fn environment_snapshot(&mut self) -> Option<&crate::conversation::EnvironmentSnapshot> {
+        if let Some(task) = self.environment_task.take() {
+            match task.await {
+                Ok(snapshot) => self.environment = Some(snapshot),
+                Err(e) => warn!("Environment snapshot capture failed: {}", e),
+            }
+        }
+        self.environment.as_ref()
+    }
+
+    /// 添加工具结果 - 按渲染提示展示结构化输出
+    pub fn add_tool_result(&mut self, tool_name: &str, result: &crate::tools::ToolResult) {
+        let content = format!("⚙ {}\n{}", tool_name, result.render_plain());
+        let message_type = if result.success { MessageType::Tool } else { MessageType::Error };
+        self.add_message(&content, message_type);
+    }
+
     /// 显示命令列表
     fn show_command_list(&mut self) {
         let command_list = "\
 Available commands:
 
   /add-dir            Add a new working directory
+  /attach [path]      Attach a file to your next message (opens a file picker without a path)
   /bug                Submit feedback about Claude Code
   /clear              Clear conversation history and free up context
   /compact            Clear conversation history but keep a summary in context. Optional: /compact
@@ -385,6 +1011,7 @@ Available commands:
   /config (theme)     Open config panel
   /cost               Show the total cost and duration of the current session
   /doctor             Diagnose and verify your Claude Code installation and settings
+  /env                Show the environment snapshot captured for this session
   /exit (quit)        Exit the REPL
   /export             Export the current conversation to a file or clipboard
   /help               Show help and available commands
@@ -392,18 +1019,24 @@ Available commands:
   /ide                Open IDE integration panel
   /init               Initialize Claude Code in a new directory
   /install-github-app Install GitHub app for enhanced integration
+  /lang [tag]         Show or set the response language (e.g. /lang zh-CN, /lang off)
   /login              Login to Claude Code services
   /logout             Logout from Claude Code services
+  /macro              Record and replay input macros (record, stop, play, list, delete)
   /mcp                Manage Model Context Protocol servers
   /memory             Manage conversation memory and context
   /migrate-installer  Migrate from old installer
   /model              Switch or configure AI models
+  /open [path]        Show a file in the conversation (opens a file picker without a path)
   /permissions        Manage file and directory permissions
   /pr-comments        Review and manage pull request comments
   /release-notes      Show release notes and updates
   /resume             Resume a previous conversation
   /review             Review code changes and provide feedback
+  /scratch            List files the agent created in the session scratchpad (scratch://)
+  /stats [turn]       Show latency stats for the session or the last turn
   /status             Show current session status
+  /tab                Manage sessions (new [title], close, rename <title>, <number>); /tabs shows all
   /upgrade            Upgrade Claude Code to the latest version
   /vim                Enable vim-style editing mode
 
@@ -497,6 +1130,57 @@ Press ESC to return to chat mode.";
                 self.show_command_list();
                 return Ok(());
             }
+            name if name == "macro" || name.starts_with("macro ") => {
+                self.handle_macro_command(&name["macro".len()..]).await?;
+                return Ok(());
+            }
+            name if name == "tab" || name.starts_with("tab ") => {
+                self.handle_tab_command(&name["tab".len()..]);
+                return Ok(());
+            }
+            "tabs" => {
+                self.show_sessions();
+                return Ok(());
+            }
+            "attach" => {
+                self.pick_file(PickAction::Attach).await?;
+                return Ok(());
+            }
+            "open" => {
+                self.pick_file(PickAction::Open).await?;
+                return Ok(());
+            }
+            name if name.starts_with("attach ") || name.starts_with("open ") => {
+                let (command, path) = cmd_name.split_once(' ').unwrap_or_default();
+                let action = if command.eq_ignore_ascii_case("attach") { PickAction::Attach } else { PickAction::Open };
+                self.complete_pick(action, std::path::PathBuf::from(path.trim()));
+                return Ok(());
+            }
+            "stats" => {
+                &format!(
+                    "{}\n\n{}",
+                    self.latency.summary().format_report(),
+                    crate::network::connection_stats().format_report()
+                )
+            }
+            "stats turn" => {
+                &match self.latency.last_turn() {
+                    Some(timing) => timing.format_report(),
+                    None => "No completed turns yet.".to_string(),
+                }
+            }
+            "env" => {
+                &match self.environment_snapshot().await {
+                    Some(snapshot) => snapshot.to_context_string(),
+                    None => "Environment snapshot is not available.".to_string(),
+                }
+            }
+            "scratch" => {
+                &match &self.scratchpad {
+                    Some(scratchpad) => scratchpad.describe(),
+                    None => "No scratchpad for this session.".to_string(),
+                }
+            }
             "status" => {
                 &format!("System Status:\n\n\
                 • Application: Claude Code - Rust Edition\n\
@@ -509,6 +1193,8 @@ Press ESC to return to chat mode.";
                     AppMode::Chat => "Chat",
                     AppMode::Help => "Help",
                     AppMode::ExitConfirm => "Exit Confirm",
+                    AppMode::FilePicker => "File Picker",
+                    AppMode::Sessions => "Sessions",
                 },
                 self.messages.len(),
                 self.input_history.len())
@@ -526,6 +1212,7 @@ Press ESC to return to chat mode.";
                 self.mode = AppMode::ExitConfirm;
                 return Ok(());
             }
+            name if name == "lang" || name.starts_with("lang ") => &self.lang_command(name["lang".len()..].trim()),
             _ => {
                 &format!("Unknown command: '{}'\n\n\
                 Type '/help' to see all available commands.\n\
@@ -539,8 +1226,57 @@ Press ESC to return to chat mode.";
         Ok(())
     }
 
+    /// 显示或切换回复语言
+    fn lang_command(&mut self, argument: &str) -> String {
+        use crate::conversation::language::known_languages;
+
+        match argument {
+            "" => {
+                let current = match &self.language {
+                    Some(language) => format!("Response language: {} ({})", language.native_name(), language),
+                    None => "Response language: not set (the model follows your language)".to_string(),
+                };
+                let available: Vec<String> = known_languages().map(|(tag, name)| format!("  {:<6} {}", tag, name)).collect();
+                format!("{}\n\nAvailable:\n{}", current, available.join("\n"))
+            }
+            "off" | "auto" | "none" => {
+                self.language = None;
+                "Response language cleared".to_string()
+            }
+            tag => match crate::conversation::ResponseLanguage::parse(tag) {
+                Ok(language) => {
+                    let message = format!("Responses will be in {} ({})", language.native_name(), language);
+                    self.language = Some(language);
+                    message
+                }
+                Err(e) => e.to_string(),
+            },
+        }
+    }
+
     /// 定时器回调
     fn on_tick(&mut self) {
+        while let Ok((session, update)) = self.turn_rx.try_recv() {
+            self.apply_turn_update(session, update);
+        }
+
+        let mut outcomes = Vec::new();
+        if let Some(updates) = self.config_updates.as_mut() {
+            while let Ok(outcome) = updates.try_recv() {
+                outcomes.push(outcome);
+            }
+        }
+        for outcome in outcomes {
+            if let Some(message) = outcome.system_message() {
+                self.add_message(message, MessageType::System);
+            }
+            if let Some(warning) = outcome.warning() {
+                self.add_message(warning, MessageType::Error);
+            }
+        }
+
+        self.refresh_todos();
+
         if self.is_loading {
             self.loading_progress += 0.1;
             if self.loading_progress > 1.0 {
@@ -555,28 +1291,37 @@ Press ESC to return to chat mode.";
             AppMode::Chat => self.render_chat(f),
             AppMode::Help => self.render_help(f),
             AppMode::ExitConfirm => self.render_exit_confirm(f),
+            AppMode::FilePicker => self.render_file_picker(f),
+            AppMode::Sessions => self.render_sessions(f),
         }
     }
 
     /// 渲染聊天界面 - 重新设计以匹配原版Claude Code的简洁风格
     fn render_chat(&mut self, f: &mut Frame) {
+        // 只有一个会话时不显示标签栏
+        let tab_bar = if self.tabs.len() > 1 { 1 } else { 0 };
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
+                Constraint::Length(tab_bar), // 标签栏
                 Constraint::Min(0),     // 消息区域
                 Constraint::Length(3),  // 输入框
                 Constraint::Length(1),  // 状态栏
             ])
             .split(f.size());
 
+        if tab_bar > 0 {
+            self.render_tab_bar(f, chunks[0]);
+        }
+
         // 消息区域
-        self.render_messages(f, chunks[0]);
+        self.render_messages(f, chunks[1]);
 
         // 输入框
-        self.render_input_box(f, chunks[1]);
+        self.render_input_box(f, chunks[2]);
 
         // 状态栏
-        self.render_status_bar(f, chunks[2]);
+        self.render_status_bar(f, chunks[3]);
 
         // 加载指示器
         if self.is_loading {
@@ -617,8 +1362,9 @@ Press ESC to return to chat mode.";
             return;
         }
 
-        // 渲染消息列表
-        let messages: Vec<ListItem> = self.messages
+        // 只为可见范围内的消息构建列表项，长会话不必每帧渲染全部历史
+        let visible = visible_range(&self.messages, self.message_scroll, area.height.saturating_sub(2) as usize);
+        let messages: Vec<ListItem> = self.messages[visible]
             .iter()
             .map(|msg| {
                 let timestamp = msg.timestamp.format("%H:%M");
@@ -627,13 +1373,38 @@ Press ESC to return to chat mode.";
                     MessageType::Assistant => ("Claude", Style::default().fg(Color::Green)),
                     MessageType::System => ("System", Style::default().fg(Color::Yellow)),
                     MessageType::Error => ("Error", Style::default().fg(Color::Red)),
+                    MessageType::Tool => ("Tool", Style::default().fg(Color::Magenta)),
+                };
+
+                // 工具结果逐行着色，差异行按增删高亮
+                if msg.message_type == MessageType::Tool {
+                    let mut lines = vec![Line::styled(format!("[{}] {}:", timestamp, prefix), style)];
+                    lines.extend(msg.content.lines().map(|line| {
+                        let line_style = if line.starts_with('+') && !line.starts_with("+++") {
+                            Style::default().fg(Color::Green)
+                        } else if line.starts_with('-') && !line.starts_with("---") {
+                            Style::default().fg(Color::Red)
+                        } else if line.starts_with("@@") {
+                            Style::default().fg(Color::Cyan)
+                        } else {
+                            Style::default().fg(Color::Gray)
+                        };
+                        Line::styled(line.to_string(), line_style)
+                    }));
+                    return ListItem::new(lines);
+                }
+
+                // 助手回复中的表格和公式渲染为终端文本
+                let body = match msg.message_type {
+                    MessageType::Assistant if !msg.is_streaming => crate::ui::markdown::render_markdown(&msg.content),
+                    _ => msg.content.to_string(),
                 };
 
                 // 格式化消息内容，支持多行
-                let content = if msg.content.contains('\n') {
-                    format!("[{}] {}:\n{}", timestamp, prefix, msg.content)
+                let content = if body.contains('\n') {
+                    format!("[{}] {}:\n{}", timestamp, prefix, body)
                 } else {
-                    format!("[{}] {}: {}", timestamp, prefix, msg.content)
+                    format!("[{}] {}: {}", timestamp, prefix, body)
                 };
 
                 ListItem::new(content).style(style)
@@ -703,6 +1474,11 @@ Press ESC to return to chat mode.";
             Line::from("  • Enter - Send message/Execute command"),
             Line::from("  • ESC - Go back/Cancel (press twice to exit)"),
             Line::from("  • ↑/↓ - Browse input history (when input is empty)"),
+            Line::from("  • F2 - Start/stop recording the quick macro"),
+            Line::from("  • F3 - Replay the last recorded or played macro"),
+            Line::from("  • Ctrl+T / Ctrl+W - Open / close a session tab"),
+            Line::from("  • Ctrl+←/→, Alt+1..9 - Switch session tabs"),
+            Line::from("  • F4 - Overview of sessions and background jobs"),
             Line::from("  • Ctrl+C - Force quit"),
             Line::from(""),
             Line::from("💡 Tips:"),
@@ -766,8 +1542,10 @@ Press ESC to return to chat mode.";
             ListItem::new("  /ide                Open IDE integration panel"),
             ListItem::new("  /init               Initialize Claude Code in a new directory"),
             ListItem::new("  /install-github-app Install GitHub app for enhanced integration"),
+            ListItem::new("  /lang [tag]         Show or set the response language (e.g. /lang zh-CN, /lang off)"),
             ListItem::new("  /login              Login to Claude Code services"),
             ListItem::new("  /logout             Logout from Claude Code services"),
+            ListItem::new("  /macro              Record and replay input macros"),
             ListItem::new("  /mcp                Manage Model Context Protocol servers"),
             ListItem::new("  /memory             Manage conversation memory and context"),
             ListItem::new("  /migrate-installer  Migrate from old installer"),
@@ -778,6 +1556,7 @@ Press ESC to return to chat mode.";
             ListItem::new("  /resume             Resume a previous conversation"),
             ListItem::new("  /review             Review code changes and provide feedback"),
             ListItem::new("  /status             Show current session status"),
+            ListItem::new("  /tab                Open, close, rename and switch session tabs"),
             ListItem::new("  /upgrade            Upgrade Claude Code to the latest version"),
             ListItem::new("  /vim                Enable vim-style editing mode"),
         ];
@@ -829,15 +1608,160 @@ Press ESC to return to chat mode.";
         f.render_widget(confirm_widget, popup_area);
     }
 
+    /// 标签页标题，带回合进行中和未读标记
+    fn tab_label(&self, index: usize, tab: &ChatSession) -> String {
+        let marker = if self.running.contains_key(&tab.id) {
+            " ⏳"
+        } else if tab.unread {
+            " ●"
+        } else {
+            ""
+        };
+        format!("{} {}{}", index + 1, tab.title, marker)
+    }
+
+    /// 渲染标签栏
+    fn render_tab_bar(&mut self, f: &mut Frame, area: Rect) {
+        let titles: Vec<String> = self.tabs.iter().enumerate().map(|(index, tab)| self.tab_label(index, tab)).collect();
+        let tabs = Tabs::new(titles)
+            .select(self.tabs.active_index())
+            .style(Style::default().fg(Color::DarkGray))
+            .highlight_style(Style::default().fg(Color::Black).bg(Color::Cyan));
+        f.render_widget(tabs, area);
+    }
+
+    /// 渲染会话概览：每个会话的状态和进行中的后台任务
+    fn render_sessions(&mut self, f: &mut Frame) {
+        self.render_chat(f);
+
+        let area = f.size();
+        let popup_area = Rect {
+            x: area.width / 8,
+            y: area.height / 8,
+            width: area.width * 3 / 4,
+            height: area.height * 3 / 4,
+        };
+        f.render_widget(Clear, popup_area);
+
+        let mut items: Vec<ListItem> = Vec::new();
+        for (index, tab) in self.tabs.iter().enumerate() {
+            let messages = if index == self.tabs.active_index() { self.messages.len() } else { tab.messages.len() };
+            let state = match self.running.get(&tab.id) {
+                Some(turn) => format!("⏳ turn running {:.1}s", turn.started.elapsed().as_secs_f64()),
+                None if tab.unread => "● new reply".to_string(),
+                None => "idle".to_string(),
+            };
+            let selected = index == self.sessions_cursor;
+            let line = format!("{} {:<3} {:<24} {:>4} messages   {}", if selected { ">" } else { " " }, index + 1, tab.title, messages, state);
+            let style = if selected { Style::default().fg(Color::Black).bg(Color::Cyan) } else { Style::default() };
+            items.push(ListItem::new(line).style(style));
+        }
+
+        let mut jobs = Vec::new();
+        if !self.running.is_empty() {
+            jobs.push(format!("  {} turn(s) running", self.running.len()));
+        }
+        if self.environment_task.as_ref().is_some_and(|task| !task.is_finished()) {
+            jobs.push("  capturing environment snapshot".to_string());
+        }
+        if let Some((name, steps)) = &self.recording {
+            jobs.push(format!("  recording macro '{}' ({} steps)", name, steps.len()));
+        }
+        let api = crate::network::dispatch::dispatcher().stats();
+        if api.in_flight > 0 || api.queued_interactive + api.queued_background > 0 || api.paused_for.is_some() {
+            let mut line = format!(
+                "  API: {} in flight, {} interactive / {} background queued",
+                api.in_flight, api.queued_interactive, api.queued_background
+            );
+            if let Some(paused) = api.paused_for {
+                line.push_str(&format!(", rate limited for {:.0}s", paused.as_secs_f64().ceil()));
+            }
+            jobs.push(line);
+        }
+        if jobs.is_empty() {
+            jobs.push("  none".to_string());
+        }
+        items.push(ListItem::new(""));
+        items.push(ListItem::new("Background jobs").style(Style::default().fg(Color::Yellow)));
+        items.extend(jobs.into_iter().map(ListItem::new));
+
+        let list = List::new(items).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Sessions (↑/↓ select, Enter switch, ESC back)")
+                .border_style(Style::default().fg(Color::Cyan)),
+        );
+        f.render_widget(list, popup_area);
+    }
+
+    /// 渲染文件查找弹窗
+    fn render_file_picker(&mut self, f: &mut Frame) {
+        self.render_chat(f);
+        let Some((action, finder)) = &self.picker else {
+            return;
+        };
+
+        let area = f.size();
+        let popup_area = Rect {
+            x: area.width / 8,
+            y: area.height / 8,
+            width: area.width * 3 / 4,
+            height: area.height * 3 / 4,
+        };
+        f.render_widget(Clear, popup_area);
+
+        // 让选中项保持在可见范围内
+        let visible = popup_area.height.saturating_sub(4) as usize;
+        let skip = finder.selected_index().saturating_sub(visible.saturating_sub(1));
+        let items: Vec<ListItem> = finder
+            .matches()
+            .enumerate()
+            .skip(skip)
+            .take(visible)
+            .map(|(index, path)| {
+                if index == finder.selected_index() {
+                    ListItem::new(format!("> {}", path)).style(Style::default().fg(Color::Black).bg(Color::Cyan))
+                } else {
+                    ListItem::new(format!("  {}", path))
+                }
+            })
+            .collect();
+
+        let mut lines = vec![ListItem::new(format!("🔎 {}", finder.query())).style(Style::default().fg(Color::Yellow))];
+        lines.extend(items);
+        let title = format!("{} — {} matches (↑/↓ select, Enter choose, ESC cancel)", action.title(), finder.match_count());
+        let list = List::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(Color::Cyan)),
+        );
+        f.render_widget(list, popup_area);
+    }
+
     /// 渲染状态栏 - 简化的状态栏设计
     fn render_status_bar(&mut self, f: &mut Frame, area: Rect) {
-        let status_text = if self.is_loading {
-            format!("⏳ {} | Messages: {} | ESC twice to exit",
+        let mut status_text = if self.is_loading {
+            format!("⏳ {} | Messages: {}",
                 self.status_message, self.messages.len())
         } else {
-            format!("✅ {} | Messages: {} | ESC twice to exit",
+            format!("✅ {} | Messages: {}",
                 self.status_message, self.messages.len())
         };
+        if let Some(ticker) = &self.ticker {
+            status_text.push_str(&format!(" | {}", ticker.format_status()));
+        }
+        if let Some(target) = self.execution_target.as_ref().and_then(|t| t.read().ok().map(|t| t.clone())) {
+            let icon = if target == crate::process::devcontainer::ExecutionTarget::Host { "🖥️" } else { "🐳" };
+            status_text.push_str(&format!(" | {} {}", icon, target.label()));
+        }
+        if let Some((_, _, Some(summary))) = &self.todos {
+            status_text.push_str(&format!(" | 📋 {}", summary));
+        }
+        if let Some((name, steps)) = &self.recording {
+            status_text.push_str(&format!(" | ⏺️ REC {} ({})", name, steps.len()));
+        }
+        status_text.push_str(" | ESC twice to exit");
 
         let status = Paragraph::new(status_text)
             .style(Style::default().fg(Color::DarkGray))
@@ -867,3 +1791,76 @@ Press ESC to return to chat mode.";
         f.render_widget(progress, popup_area);
     }
 }
+
+/// 是否为宏管理命令（不录制，也不在重放时执行）
+fn is_macro_command(input: &str) -> bool {
+    let input = input.trim_start();
+    input == "/macro" || input.starts_with("/macro ")
+}
+
+/// 消息渲染后占用的行数
+fn rendered_height(message: &ChatMessage) -> usize {
+    if message.message_type == MessageType::Tool || message.content.contains('\n') {
+        1 + message.content.lines().count()
+    } else {
+        1
+    }
+}
+
+/// 以 `last` 为最后一条、能放入 `height` 行的消息范围，至少包含最后一条
+fn visible_range(messages: &[ChatMessage], last: usize, height: usize) -> std::ops::Range<usize> {
+    if messages.is_empty() {
+        return 0..0;
+    }
+    let end = last.min(messages.len() - 1) + 1;
+    let mut start = end;
+    let mut used = 0;
+    while start > 0 {
+        let lines = rendered_height(&messages[start - 1]);
+        if start < end && used + lines > height {
+            break;
+        }
+        used += lines;
+        start -= 1;
+    }
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_range_renders_only_tail() {
+        let mut app = TerminalApp::new();
+        for i in 0..10_000 {
+            app.add_message(format!("message {}", i), MessageType::User);
+        }
+        app.add_message("line one\nline two", MessageType::Assistant);
+
+        assert_eq!(visible_range(&app.messages, app.message_scroll, 20), 9_983..10_001);
+        assert_eq!(visible_range(&app.messages, 5, 3), 3..6);
+        assert_eq!(visible_range(&app.messages, app.message_scroll, 0), 10_000..10_001);
+        assert_eq!(visible_range(&[], 0, 20), 0..0);
+    }
+
+    #[tokio::test]
+    async fn test_ticker_follows_streamed_deltas_with_session_model() {
+        let model = "claude-3-haiku-20240307";
+        let mut app = TerminalApp::new();
+        app.set_model(model);
+        app.send_message("hello".to_string()).await.unwrap();
+        let session = app.tabs.active().id;
+
+        // 每个增量到达时刷新状态栏
+        app.record_turn_event(session, "content_block_delta", &serde_json::json!({ "delta": { "text": "12345678" } }));
+        assert_eq!(app.ticker.as_ref().unwrap().output_tokens, 2);
+
+        app.wait_for_turn().await;
+        let reply = "Hello! I'm Claude, your AI assistant. How can I help you today?";
+        let snapshot = app.ticker.clone().unwrap();
+        assert_eq!(snapshot.output_tokens as usize, (8 + reply.chars().count()).div_ceil(4));
+        assert_eq!(snapshot.estimated_cost, Some(crate::cost::lookup_default_pricing(model).unwrap().cost(0, snapshot.output_tokens)));
+        assert_eq!(app.messages.last().unwrap().content.to_string(), reply);
+    }
+}
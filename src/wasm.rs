@@ -0,0 +1,42 @@
+//! Web UI 的 WASM 导出
+//!
+//! 让内置 Web UI 在浏览器中直接使用与后端相同的 Token 估算、上下文用量和 Markdown 渲染。构建方式：
+//! `cargo rustc --lib --target wasm32-unknown-unknown --no-default-features --features wasm --crate-type cdylib`，
+//! 再用 `wasm-bindgen --target web` 生成 JS 绑定
+
+use wasm_bindgen::prelude::*;
+
+use crate::tokens::{estimate_tokens, ContextMeter};
+
+/// 估算文本的 Token 数
+#[wasm_bindgen(js_name = estimateTokens)]
+pub fn wasm_estimate_tokens(text: &str) -> u32 {
+    estimate_tokens(text)
+}
+
+/// 上下文用量，返回 `{"used", "limit", "ratio", "level", "bar"}` 形式的 JSON
+#[wasm_bindgen(js_name = contextMeter)]
+pub fn wasm_context_meter(used: u32, limit: u32) -> String {
+    let meter = ContextMeter::new(used, limit);
+    serde_json::json!({
+        "used": meter.used,
+        "limit": meter.limit,
+        "ratio": meter.ratio(),
+        "level": meter.level(),
+        "bar": meter.bar(20),
+    })
+    .to_string()
+}
+
+/// 渲染 Markdown：表格绘制为框线表格，LaTeX 公式转换为 Unicode
+#[wasm_bindgen(js_name = renderMarkdown)]
+pub fn wasm_render_markdown(text: &str) -> String {
+    crate::ui::markdown::render_markdown(text)
+}
+
+/// 把对话 JSON（`claude export --format json` 的输出）转换为 Markdown
+#[wasm_bindgen(js_name = conversationToMarkdown)]
+pub fn wasm_conversation_to_markdown(json: &str) -> Result<String, JsError> {
+    let conversation: crate::conversation::Conversation = serde_json::from_str(json)?;
+    Ok(conversation.to_markdown(None))
+}
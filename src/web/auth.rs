@@ -0,0 +1,293 @@
+//! Web 认证
+//!
+//! 中间件按配置识别每个 API 请求的用户：API 令牌（`Authorization: Bearer` 或登录后设置的 Cookie），
+//! 或前置认证代理传来的用户名请求头。识别出的用户放入请求扩展，由各处理器检查权限档位和预算
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use tokio::sync::RwLock;
+
+use super::AppState;
+use crate::config::{WebAuthConfig, WebAuthMode};
+use crate::database::users::{PermissionProfile, UserStore};
+use crate::error::Result;
+
+/// 浏览器登录后保存令牌的 Cookie
+pub const TOKEN_COOKIE: &str = "claude_web_token";
+
+/// 不认证时所有请求使用的用户
+const LOCAL_USER: &str = "local";
+
+/// 已认证的请求用户
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthUser {
+    pub id: String,
+    pub name: String,
+    pub profile: PermissionProfile,
+}
+
+/// 用户表及其文件状态
+struct LoadedUsers {
+    store: UserStore,
+    /// 读取时文件的修改时间，用于发现 `claude web-users` 在服务运行期间做的修改
+    modified: Option<SystemTime>,
+}
+
+/// 认证器
+pub struct WebAuth {
+    config: WebAuthConfig,
+    users: RwLock<LoadedUsers>,
+    /// 校验过的令牌到（用户 ID，令牌哈希）的缓存，避免每个请求都计算 Argon2
+    verified: RwLock<HashMap<String, (String, String)>>,
+}
+
+impl WebAuth {
+    pub fn new(config: WebAuthConfig) -> Result<Self> {
+        let path = config.users_file.clone().unwrap_or_else(UserStore::default_path);
+        let users = LoadedUsers {
+            modified: modified_time(&path),
+            store: UserStore::load(path)?,
+        };
+        Ok(Self {
+            config,
+            users: RwLock::new(users),
+            verified: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub fn mode(&self) -> WebAuthMode {
+        self.config.mode
+    }
+
+    /// 识别请求的用户，失败时返回 401
+    pub async fn authenticate(&self, headers: &HeaderMap) -> std::result::Result<AuthUser, StatusCode> {
+        match self.config.mode {
+            WebAuthMode::None => Ok(AuthUser {
+                id: LOCAL_USER.to_string(),
+                name: LOCAL_USER.to_string(),
+                profile: PermissionProfile::Admin,
+            }),
+            WebAuthMode::Token => {
+                let token = request_token(headers).ok_or(StatusCode::UNAUTHORIZED)?;
+                self.verify_token(&token).await.ok_or(StatusCode::UNAUTHORIZED)
+            }
+            WebAuthMode::Proxy => {
+                let name = headers
+                    .get(self.config.proxy_header.as_str())
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .ok_or(StatusCode::UNAUTHORIZED)?;
+                self.provision(name).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+
+    /// 校验令牌
+    pub async fn verify_token(&self, token: &str) -> Option<AuthUser> {
+        self.refresh().await;
+        let cached = self.verified.read().await.get(token).cloned();
+        let users = self.users.read().await;
+        if let Some((id, hash)) = cached {
+            // 用户被删除或令牌被轮换后缓存失效
+            if let Some(user) = users.store.get(&id).filter(|user| user.token_hash.as_ref() == Some(&hash)) {
+                return Some(auth_user(user));
+            }
+        }
+        let user = users.store.verify_token(token)?;
+        let entry = (user.id.clone(), user.token_hash.clone().unwrap_or_default());
+        let user = auth_user(user);
+        drop(users);
+        self.verified.write().await.insert(token.to_string(), entry);
+        Some(user)
+    }
+
+    /// 用户剩余预算不足时返回 402
+    pub async fn check_budget(&self, user: &AuthUser) -> std::result::Result<(), StatusCode> {
+        let users = self.users.read().await;
+        match users.store.get(&user.id) {
+            Some(user) if user.over_budget() => Err(StatusCode::PAYMENT_REQUIRED),
+            _ => Ok(()),
+        }
+    }
+
+    /// 记入花费并保存；不认证时不记录
+    pub async fn charge(&self, user: &AuthUser, usd: f64) {
+        if self.config.mode == WebAuthMode::None || usd <= 0.0 {
+            return;
+        }
+        let mut users = self.users.write().await;
+        if let Some(record) = users.store.get(&user.id) {
+            if let Some(budget) = record.budget_usd {
+                for threshold in crate::network::webhooks::crossed_thresholds(record.spent_usd, record.spent_usd + usd, budget) {
+                    crate::network::webhooks::notify(crate::network::webhooks::WebhookEvent::BudgetThreshold {
+                        scope: format!("web user {}", user.name),
+                        threshold,
+                        spent_usd: record.spent_usd + usd,
+                        budget_usd: budget,
+                    });
+                }
+            }
+        }
+        let saved = users.store.record_spend(&user.id, usd).and_then(|_| users.store.save());
+        match saved {
+            Ok(()) => users.modified = modified_time(users.store.path()),
+            Err(e) => tracing::warn!("Failed to record spend for web user {}: {}", user.name, e),
+        }
+    }
+
+    /// 返回用户的（预算，已花费）
+    pub async fn budget(&self, user: &AuthUser) -> (Option<f64>, f64) {
+        let users = self.users.read().await;
+        users.store.get(&user.id).map(|user| (user.budget_usd, user.spent_usd)).unwrap_or((None, 0.0))
+    }
+
+    /// 代理模式下按名称找到或创建用户
+    async fn provision(&self, name: &str) -> Result<AuthUser> {
+        self.refresh().await;
+        if let Some(user) = self.users.read().await.store.find(name) {
+            return Ok(auth_user(user));
+        }
+        let mut users = self.users.write().await;
+        let user = auth_user(users.store.provision(name, self.config.default_profile, self.config.default_budget_usd)?);
+        users.store.save()?;
+        users.modified = modified_time(users.store.path());
+        tracing::info!("Provisioned web user {} ({})", user.name, user.profile);
+        Ok(user)
+    }
+
+    /// 用户文件在外部被修改时重新读取
+    async fn refresh(&self) {
+        let path = self.users.read().await.store.path().to_path_buf();
+        let modified = modified_time(&path);
+        if modified == self.users.read().await.modified {
+            return;
+        }
+        match UserStore::load(&path) {
+            Ok(store) => *self.users.write().await = LoadedUsers { store, modified },
+            Err(e) => tracing::warn!("Failed to reload web users: {}", e),
+        }
+    }
+}
+
+fn modified_time(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn auth_user(user: &crate::database::users::WebUser) -> AuthUser {
+    AuthUser {
+        id: user.id.clone(),
+        name: user.name.clone(),
+        profile: user.profile,
+    }
+}
+
+/// 请求中的令牌：优先 `Authorization: Bearer`，其次登录 Cookie
+fn request_token(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+    bearer.or_else(|| {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == TOKEN_COOKIE)
+            .map(|(_, token)| token.to_string())
+    })
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+/// 认证中间件，用于需要登录的 API 路由
+pub async fn require_user(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    match state.auth.authenticate(request.headers()).await {
+        Ok(user) => {
+            request.extensions_mut().insert(user);
+            next.run(request).await
+        }
+        Err(status) => error_response(status, "Authentication required"),
+    }
+}
+
+/// 登录请求
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub token: String,
+}
+
+/// 校验令牌并设置登录 Cookie
+pub async fn login_handler(State(state): State<AppState>, Json(request): Json<LoginRequest>) -> Response {
+    if state.auth.mode() != WebAuthMode::Token {
+        return error_response(StatusCode::BAD_REQUEST, "Token login is not enabled on this server");
+    }
+    let Some(user) = state.auth.verify_token(request.token.trim()).await else {
+        return error_response(StatusCode::UNAUTHORIZED, "Invalid token");
+    };
+    let cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Strict", TOKEN_COOKIE, request.token.trim());
+    (
+        [(header::SET_COOKIE, cookie)],
+        Json(serde_json::json!({ "name": user.name, "profile": user.profile })),
+    )
+        .into_response()
+}
+
+/// 清除登录 Cookie
+pub async fn logout_handler() -> Response {
+    let cookie = format!("{}=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0", TOKEN_COOKIE);
+    ([(header::SET_COOKIE, cookie)], Json(serde_json::json!({ "status": "logged_out" }))).into_response()
+}
+
+/// 用户数据文件的位置，用于启动时提示
+pub fn users_file(config: &WebAuthConfig) -> PathBuf {
+    config.users_file.clone().unwrap_or_else(UserStore::default_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_token_and_proxy_authentication() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let users_file = temp_dir.path().join("users.json");
+        let mut store = UserStore::load(&users_file).unwrap();
+        let (_, token) = store.add("alice", PermissionProfile::Standard, Some(0.01)).unwrap();
+        store.save().unwrap();
+
+        let config = WebAuthConfig { mode: WebAuthMode::Token, users_file: Some(users_file.clone()), ..Default::default() };
+        let auth = WebAuth::new(config).unwrap();
+        assert_eq!(auth.authenticate(&HeaderMap::new()).await, Err(StatusCode::UNAUTHORIZED));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, format!("theme=dark; {}={}", TOKEN_COOKIE, token).parse().unwrap());
+        let alice = auth.authenticate(&headers).await.unwrap();
+        assert_eq!(alice.name, "alice");
+        assert_eq!(auth.check_budget(&alice).await, Ok(()));
+        auth.charge(&alice, 0.02).await;
+        assert_eq!(auth.check_budget(&alice).await, Err(StatusCode::PAYMENT_REQUIRED));
+
+        let config = WebAuthConfig { mode: WebAuthMode::Proxy, users_file: Some(users_file.clone()), ..Default::default() };
+        let auth = WebAuth::new(config).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-email", "bob@example.com".parse().unwrap());
+        let bob = auth.authenticate(&headers).await.unwrap();
+        assert_eq!(bob.profile, PermissionProfile::Standard);
+        assert!(UserStore::load(&users_file).unwrap().find("bob@example.com").is_some());
+    }
+}
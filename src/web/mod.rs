@@ -3,8 +3,15 @@ use crate::config::ClaudeConfig;
 use crate::network::ClaudeApiClient;
 
 pub mod advanced;
+pub mod auth;
+pub mod sessions;
+pub mod shared;
+
+use auth::{AuthUser, WebAuth};
+use sessions::{SessionStore, SessionSummary, WebSession};
 use axum::{
-    extract::State,
+    extract::{Path, State},
+    Extension,
     http::StatusCode,
     response::{Html, Json, Sse, sse::Event},
     routing::{get, post},
@@ -64,6 +71,12 @@ pub struct AppState {
     pub active_connections: Arc<RwLock<u64>>,
     /// 请求统计
     pub request_stats: Arc<RwLock<RequestStats>>,
+    /// 用户认证
+    pub auth: Arc<WebAuth>,
+    /// 按用户隔离的会话
+    pub sessions: Arc<RwLock<SessionStore>>,
+    /// 多人共享的会话
+    pub shared: Arc<shared::SharedHub>,
 }
 
 /// 请求统计
@@ -83,11 +96,14 @@ pub struct ApiRequest {
     pub temperature: Option<f64>,
     pub max_tokens: Option<u32>,
     pub stream: Option<bool>,
+    /// 继续已有会话，不指定时新建
+    pub session_id: Option<String>,
 }
 
 /// API 响应
 #[derive(Debug, Serialize)]
 pub struct ApiResponse {
+    pub session_id: String,
     pub response: String,
     pub model: String,
     pub usage: Option<TokenUsage>,
@@ -127,6 +143,9 @@ impl WebServer {
 
         let app_state = AppState {
             claude_client,
+            auth: Arc::new(WebAuth::new(claude_config.web_auth.clone())?),
+            sessions: Arc::new(RwLock::new(SessionStore::default())),
+            shared: Arc::new(shared::SharedHub::default()),
             config: Arc::new(RwLock::new(claude_config)),
             active_connections: Arc::new(RwLock::new(0)),
             request_stats: Arc::new(RwLock::new(RequestStats::default())),
@@ -158,14 +177,29 @@ impl WebServer {
 
     /// 创建应用路由
     async fn create_app(&self) -> Result<Router> {
-        let mut app = Router::new()
-            // API 路由
+        // 需要识别用户的 API 路由
+        let api = Router::new()
             .route("/api/chat", post(chat_handler))
             .route("/api/chat/stream", post(chat_stream_handler))
-            .route("/api/status", get(status_handler))
             .route("/api/stats", get(stats_handler))
             .route("/api/config", get(get_config_handler))
             .route("/api/config", post(update_config_handler))
+            .route("/api/me", get(me_handler))
+            .route("/api/sessions", get(list_sessions_handler))
+            .route("/api/sessions/:id", get(get_session_handler))
+            .route("/api/shared", post(shared::create_handler))
+            .route("/api/shared/:id", get(shared::snapshot_handler))
+            .route("/api/shared/:id/events", get(shared::events_handler))
+            .route("/api/shared/:id/presence", post(shared::presence_handler))
+            .route("/api/shared/:id/control", post(shared::control_handler))
+            .route("/api/shared/:id/messages", post(shared::message_handler))
+            .route_layer(axum::middleware::from_fn_with_state(self.app_state.clone(), auth::require_user));
+
+        let mut app = Router::new()
+            .merge(api)
+            .route("/api/status", get(status_handler))
+            .route("/api/login", post(auth::login_handler))
+            .route("/api/logout", post(auth::logout_handler))
             
             // Web 界面路由
             .route("/", get(index_handler))
@@ -201,12 +235,22 @@ impl WebServer {
     }
 }
 
+/// 可以对话且预算未用完时通过
+async fn ensure_can_chat(state: &AppState, user: &AuthUser) -> std::result::Result<(), StatusCode> {
+    if !user.profile.can_chat() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    state.auth.check_budget(user).await
+}
+
 /// 聊天API处理器
 async fn chat_handler(
     State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
     Json(request): Json<ApiRequest>,
 ) -> std::result::Result<Json<ApiResponse>, StatusCode> {
     let start_time = std::time::Instant::now();
+    ensure_can_chat(&state, &user).await?;
 
     // 更新统计
     {
@@ -219,12 +263,13 @@ async fn chat_handler(
         model: request.model.unwrap_or_else(|| "claude-3-haiku-20240307".to_string()),
         messages: vec![crate::network::Message {
             role: "user".to_string(),
-            content: request.message,
+            content: request.message.into(),
         }],
         max_tokens: request.max_tokens.unwrap_or(4096),
         stream: Some(false),
         tools: None,
         temperature: request.temperature.map(|t| t as f32),
+        top_p: None,
         system: None,
     };
 
@@ -241,14 +286,27 @@ async fn chat_handler(
             / stats.successful_requests as f64;
     }
 
+    let response = format!("Echo: {}", claude_request.messages[0].content);
+    let usage = TokenUsage {
+        input_tokens: 10,
+        output_tokens: 20,
+        total_tokens: 30,
+    };
+    if let Some(pricing) = crate::cost::lookup_default_pricing(&claude_request.model) {
+        state.auth.charge(&user, pricing.cost(usage.input_tokens, usage.output_tokens)).await;
+    }
+    let session_id = state.sessions.write().await.record_turn(
+        &user.id,
+        request.session_id.as_deref(),
+        &claude_request.messages[0].content,
+        &response,
+    );
+
     let api_response = ApiResponse {
-        response: format!("Echo: {}", claude_request.messages[0].content),
+        session_id,
+        response,
         model: claude_request.model,
-        usage: Some(TokenUsage {
-            input_tokens: 10,
-            output_tokens: 20,
-            total_tokens: 30,
-        }),
+        usage: Some(usage),
         processing_time_ms: processing_time,
     };
 
@@ -258,18 +316,21 @@ async fn chat_handler(
 /// 流式聊天处理器
 async fn chat_stream_handler(
     State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
     Json(request): Json<ApiRequest>,
-) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+) -> std::result::Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>, StatusCode> {
+    ensure_can_chat(&state, &user).await?;
     let claude_request = crate::network::ClaudeRequest {
         model: request.model.unwrap_or_else(|| "claude-3-haiku-20240307".to_string()),
         messages: vec![crate::network::Message {
             role: "user".to_string(),
-            content: request.message,
+            content: request.message.into(),
         }],
         max_tokens: request.max_tokens.unwrap_or(4096),
         stream: Some(true),
         tools: None,
         temperature: request.temperature.map(|t| t as f32),
+        top_p: None,
         system: None,
     };
 
@@ -281,7 +342,7 @@ async fn chat_stream_handler(
         Ok(Event::default().data("[DONE]")),
     ]);
 
-    Sse::new(stream)
+    Ok(Sse::new(stream))
 }
 
 /// 状态处理器
@@ -300,10 +361,44 @@ async fn status_handler(State(state): State<AppState>) -> Json<serde_json::Value
     }))
 }
 
-/// 统计处理器
-async fn stats_handler(State(state): State<AppState>) -> Json<RequestStats> {
+/// 统计处理器，全局统计只对管理员开放
+async fn stats_handler(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+) -> std::result::Result<Json<RequestStats>, StatusCode> {
+    if !user.profile.can_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
     let stats = state.request_stats.read().await.clone();
-    Json(stats)
+    Ok(Json(stats))
+}
+
+/// 当前用户及其预算
+async fn me_handler(State(state): State<AppState>, Extension(user): Extension<AuthUser>) -> Json<serde_json::Value> {
+    let (budget_usd, spent_usd) = state.auth.budget(&user).await;
+    Json(serde_json::json!({
+        "name": user.name,
+        "profile": user.profile,
+        "budget_usd": budget_usd,
+        "spent_usd": spent_usd,
+    }))
+}
+
+/// 当前用户的会话列表
+async fn list_sessions_handler(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+) -> Json<Vec<SessionSummary>> {
+    Json(state.sessions.read().await.list(&user.id))
+}
+
+/// 当前用户的某个会话；其他用户的会话视为不存在
+async fn get_session_handler(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<WebSession>, StatusCode> {
+    state.sessions.read().await.get(&user.id, &id).cloned().map(Json).ok_or(StatusCode::NOT_FOUND)
 }
 
 /// 获取配置处理器
@@ -325,8 +420,12 @@ async fn get_config_handler(State(state): State<AppState>) -> Json<serde_json::V
 /// 更新配置处理器
 async fn update_config_handler(
     State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
     Json(update): Json<serde_json::Value>,
 ) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    if !user.profile.can_manage() {
+        return Err(StatusCode::FORBIDDEN);
+    }
     // 这里应该实现配置更新逻辑
     Ok(Json(serde_json::json!({
         "status": "success",
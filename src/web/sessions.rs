@@ -0,0 +1,78 @@
+//! Web 会话
+//!
+//! 每个用户的对话彼此隔离：会话按用户 ID 分组保存，用户只能列出和读取自己的会话
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// 会话中的一条消息
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionMessage {
+    pub role: String,
+    pub content: String,
+}
+
+/// 一个会话
+#[derive(Debug, Clone, Serialize)]
+pub struct WebSession {
+    pub id: String,
+    pub messages: Vec<SessionMessage>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 会话列表中的摘要
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub id: String,
+    pub messages: usize,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// 按用户分组的会话
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    sessions: HashMap<String, HashMap<String, WebSession>>,
+}
+
+impl SessionStore {
+    /// 追加一轮对话；会话不存在或属于其他用户时新建，返回会话 ID
+    pub fn record_turn(&mut self, user_id: &str, session_id: Option<&str>, user: &str, assistant: &str) -> String {
+        let sessions = self.sessions.entry(user_id.to_string()).or_default();
+        let id = session_id
+            .filter(|id| sessions.contains_key(*id))
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let session = sessions.entry(id.clone()).or_insert_with(|| WebSession {
+            id: id.clone(),
+            messages: Vec::new(),
+            updated_at: Utc::now(),
+        });
+        for (role, content) in [("user", user), ("assistant", assistant)] {
+            session.messages.push(SessionMessage { role: role.to_string(), content: content.to_string() });
+        }
+        session.updated_at = Utc::now();
+        id
+    }
+
+    /// 用户自己的会话，最近更新的在前
+    pub fn list(&self, user_id: &str) -> Vec<SessionSummary> {
+        let mut summaries: Vec<SessionSummary> = self
+            .sessions
+            .get(user_id)
+            .into_iter()
+            .flat_map(|sessions| sessions.values())
+            .map(|session| SessionSummary {
+                id: session.id.clone(),
+                messages: session.messages.len(),
+                updated_at: session.updated_at,
+            })
+            .collect();
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.updated_at));
+        summaries
+    }
+
+    pub fn get(&self, user_id: &str, session_id: &str) -> Option<&WebSession> {
+        self.sessions.get(user_id)?.get(session_id)
+    }
+}
@@ -0,0 +1,240 @@
+//! 共享会话的 Web 接口
+//!
+//! 参与者通过 SSE 订阅在场事件（连接即加入，断开即离开），通过 POST 上报输入状态和光标、
+//! 申请或移交控制权。只有驾驶者可以发消息，消息和回复会推送给会话中的所有人
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{sse::Event, Sse},
+    Extension, Json,
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use tokio::sync::{broadcast, Mutex};
+
+use super::auth::AuthUser;
+use super::{ApiRequest, ApiResponse, AppState};
+use crate::collaboration::presence::{ControlOutcome, PresenceEvent, SessionSnapshot, SharedSession};
+use crate::collaboration::Position;
+
+/// 每个会话事件通道的容量，落后太多的订阅者会丢失旧事件
+const EVENT_CAPACITY: usize = 256;
+
+struct SharedEntry {
+    session: SharedSession,
+    events: broadcast::Sender<PresenceEvent>,
+}
+
+impl SharedEntry {
+    fn publish(&self, events: impl IntoIterator<Item = PresenceEvent>) {
+        for event in events {
+            // 没有订阅者时发送失败，可以忽略
+            let _ = self.events.send(event);
+        }
+    }
+}
+
+/// 所有共享会话
+#[derive(Default)]
+pub struct SharedHub {
+    sessions: Mutex<HashMap<String, SharedEntry>>,
+}
+
+impl SharedHub {
+    async fn with_session<T>(
+        &self,
+        id: &str,
+        f: impl FnOnce(&mut SharedEntry) -> std::result::Result<T, StatusCode>,
+    ) -> std::result::Result<T, StatusCode> {
+        let mut sessions = self.sessions.lock().await;
+        f(sessions.get_mut(id).ok_or(StatusCode::NOT_FOUND)?)
+    }
+}
+
+/// 断开 SSE 连接时离开会话
+struct LeaveGuard {
+    hub: std::sync::Arc<SharedHub>,
+    session_id: String,
+    user_id: String,
+}
+
+impl Drop for LeaveGuard {
+    fn drop(&mut self) {
+        let hub = self.hub.clone();
+        let session_id = std::mem::take(&mut self.session_id);
+        let user_id = std::mem::take(&mut self.user_id);
+        tokio::spawn(async move {
+            let _ = hub
+                .with_session(&session_id, |entry| {
+                    let events = entry.session.leave(&user_id);
+                    entry.publish(events);
+                    Ok(())
+                })
+                .await;
+        });
+    }
+}
+
+/// 创建共享会话，返回会话 ID
+pub async fn create_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let (events, _) = broadcast::channel(EVENT_CAPACITY);
+    state
+        .shared
+        .sessions
+        .lock()
+        .await
+        .insert(id.clone(), SharedEntry { session: SharedSession::new(id.clone()), events });
+    Json(serde_json::json!({ "id": id }))
+}
+
+/// 会话的当前状态
+pub async fn snapshot_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> std::result::Result<Json<SessionSnapshot>, StatusCode> {
+    state.shared.with_session(&id, |entry| Ok(Json(entry.session.snapshot()))).await
+}
+
+/// 订阅会话事件；第一条是当前状态快照
+pub async fn events_handler(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+    Path(id): Path<String>,
+) -> std::result::Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>, StatusCode> {
+    let (receiver, snapshot) = state
+        .shared
+        .with_session(&id, |entry| {
+            let receiver = entry.events.subscribe();
+            let events = entry.session.join(&user.id, &user.name, chrono::Utc::now());
+            entry.publish(events);
+            Ok((receiver, entry.session.snapshot()))
+        })
+        .await?;
+    let guard = LeaveGuard { hub: state.shared.clone(), session_id: id, user_id: user.id };
+
+    let first = Event::default().event("snapshot").json_data(&snapshot).unwrap_or_default();
+    let updates = stream::unfold((receiver, guard), |(mut receiver, guard)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let event = Event::default().event("presence").json_data(&event).unwrap_or_default();
+                    return Some((Ok(event), (receiver, guard)));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(stream::once(async { Ok(first) }).chain(updates)))
+}
+
+/// 在场状态更新
+#[derive(Debug, Deserialize)]
+pub struct PresenceUpdate {
+    pub typing: Option<bool>,
+    pub cursor: Option<Position>,
+}
+
+/// 上报输入状态和光标位置
+pub async fn presence_handler(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+    Path(id): Path<String>,
+    Json(update): Json<PresenceUpdate>,
+) -> std::result::Result<StatusCode, StatusCode> {
+    let now = chrono::Utc::now();
+    state
+        .shared
+        .with_session(&id, |entry| {
+            let mut events = Vec::new();
+            if let Some(typing) = update.typing {
+                events.extend(entry.session.set_typing(&user.id, typing, now).map_err(|_| StatusCode::CONFLICT)?);
+            }
+            if let Some(cursor) = update.cursor {
+                events.push(entry.session.set_cursor(&user.id, cursor, now).map_err(|_| StatusCode::CONFLICT)?);
+            }
+            entry.publish(events);
+            Ok(StatusCode::NO_CONTENT)
+        })
+        .await
+}
+
+/// 控制权操作
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ControlRequest {
+    Request,
+    Release,
+    /// 交给指定参与者，不指定时交给最早的申请者
+    Grant { to: Option<String> },
+}
+
+/// 申请、放弃或移交控制权
+pub async fn control_handler(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+    Path(id): Path<String>,
+    Json(request): Json<ControlRequest>,
+) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+    if !user.profile.can_chat() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let now = chrono::Utc::now();
+    state
+        .shared
+        .with_session(&id, |entry| {
+            let (outcome, events): (Option<ControlOutcome>, Vec<PresenceEvent>) = match request {
+                ControlRequest::Request => {
+                    let (outcome, events) = entry.session.request_control(&user.id, now).map_err(|_| StatusCode::CONFLICT)?;
+                    (Some(outcome), events)
+                }
+                ControlRequest::Release => (None, vec![entry.session.release(&user.id).map_err(|_| StatusCode::CONFLICT)?]),
+                ControlRequest::Grant { to } => {
+                    (None, vec![entry.session.grant(&user.id, to.as_deref()).map_err(|_| StatusCode::CONFLICT)?])
+                }
+            };
+            entry.publish(events);
+            Ok(Json(serde_json::json!({ "outcome": outcome, "driver": entry.session.driver() })))
+        })
+        .await
+}
+
+/// 驾驶者发送消息；其他参与者得到 409
+pub async fn message_handler(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthUser>,
+    Path(id): Path<String>,
+    Json(mut request): Json<ApiRequest>,
+) -> std::result::Result<Json<ApiResponse>, StatusCode> {
+    let message = request.message.clone();
+    request.session_id = state
+        .shared
+        .with_session(&id, |entry| {
+            if !entry.session.is_driver(&user.id) {
+                return Err(StatusCode::CONFLICT);
+            }
+            entry.session.touch(&user.id, chrono::Utc::now());
+            entry.publish([PresenceEvent::Message { user_id: user.id.clone(), role: "user".to_string(), content: message }]);
+            Ok(entry.session.conversation_id.clone())
+        })
+        .await?;
+
+    let Json(response) = super::chat_handler(State(state.clone()), Extension(user.clone()), Json(request)).await?;
+    state
+        .shared
+        .with_session(&id, |entry| {
+            entry.session.conversation_id = Some(response.session_id.clone());
+            entry.publish([PresenceEvent::Message {
+                user_id: user.id.clone(),
+                role: "assistant".to_string(),
+                content: response.response.clone(),
+            }]);
+            Ok(())
+        })
+        .await?;
+    Ok(Json(response))
+}
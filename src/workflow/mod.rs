@@ -1,4 +1,5 @@
 use crate::error::{ClaudeError, Result};
+use crate::locks::{rank, RwLock as OrderedRwLock};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -540,7 +541,7 @@ pub trait WorkflowEventListener: Send + Sync {
 /// 工作流调度器
 pub struct WorkflowScheduler {
     /// 调度队列
-    schedule_queue: Arc<RwLock<Vec<ScheduledWorkflow>>>,
+    schedule_queue: Arc<OrderedRwLock<Vec<ScheduledWorkflow>>>,
     /// 调度器配置
     config: SchedulerConfig,
 }
@@ -754,7 +755,7 @@ impl WorkflowEventBus {
 impl WorkflowScheduler {
     pub fn new(config: SchedulerConfig) -> Self {
         Self {
-            schedule_queue: Arc::new(RwLock::new(Vec::new())),
+            schedule_queue: Arc::new(OrderedRwLock::new("workflow.schedule", rank::WORKFLOW_SCHEDULE, Vec::new())),
             config,
         }
     }